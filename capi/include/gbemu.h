@@ -0,0 +1,132 @@
+#ifndef GBEMU_H
+#define GBEMU_H
+
+/* Generated by cbindgen from capi/src/lib.rs, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define GBEMU_SCREEN_WIDTH 160
+
+#define GBEMU_SCREEN_HEIGHT 144
+
+#define GBEMU_OK 0
+
+#define GBEMU_ERROR -1
+
+// Button bits of [`gbemu_set_buttons`]
+#define GBEMU_BUTTON_RIGHT 1
+
+#define GBEMU_BUTTON_LEFT 2
+
+#define GBEMU_BUTTON_UP 4
+
+#define GBEMU_BUTTON_DOWN 8
+
+#define GBEMU_BUTTON_A 16
+
+#define GBEMU_BUTTON_B 32
+
+#define GBEMU_BUTTON_SELECT 64
+
+#define GBEMU_BUTTON_START 128
+
+// Emulated machine, opaque to C
+typedef struct GbemuMachine GbemuMachine;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Create a DMG without cartridge, null on failure. Free it with [`gbemu_destroy`].
+GbemuMachine *gbemu_create(void);
+
+// Free a machine, null is ignored.
+//
+// # Safety
+// `machine` must be null or come from [`gbemu_create`], it must not be used afterwards.
+void gbemu_destroy(GbemuMachine *machine);
+
+// Insert a cartridge from a rom image (raw or zipped) and reset the machine, which is left as it was
+// when the rom is rejected.
+//
+// # Safety
+// `machine` must be valid, `data` must point to `len` readable bytes.
+int32_t gbemu_load_rom(GbemuMachine *machine, const uint8_t *data, size_t len);
+
+// Turn the console off and on, the cartridge ram and clock are cleared unless kept by their battery.
+// A reset keeps both.
+//
+// # Safety
+// `machine` must be valid.
+int32_t gbemu_power_cycle(GbemuMachine *machine, bool keep_sram, bool keep_rtc);
+
+// Run until the next frame is complete, or until the machine stops on an error.
+//
+// # Safety
+// `machine` must be valid.
+int32_t gbemu_run_frame(GbemuMachine *machine);
+
+// Shade (0-3) of each pixel of the screen, [`GBEMU_SCREEN_WIDTH`] x [`GBEMU_SCREEN_HEIGHT`] bytes
+// row by row. The pointer is valid until the machine runs again or is destroyed. In CGB mode the
+// colors come from the CGB palettes, use [`gbemu_framebuffer_rgba`].
+//
+// # Safety
+// `machine` must be valid.
+const uint8_t *gbemu_framebuffer(const GbemuMachine *machine);
+
+// Copy the screen as RGBA bytes in the colors of the machine palette, or its own colors in CGB mode,
+// `out` holds [`GBEMU_SCREEN_WIDTH`] x [`GBEMU_SCREEN_HEIGHT`] x 4 bytes.
+//
+// # Safety
+// `machine` must be valid, `out` must point to `len` writable bytes.
+int32_t gbemu_framebuffer_rgba(GbemuMachine *machine, uint8_t *out, size_t len);
+
+// Set the held buttons, a combination of the `GBEMU_BUTTON_*` bits.
+//
+// # Safety
+// `machine` must be valid.
+void gbemu_set_buttons(GbemuMachine *machine, uint8_t pressed);
+
+// Write a snapshot of the machine to `out` when it holds `capacity` bytes or more, and return the
+// size of the snapshot either way, 0 when `machine` is null. Call it with a null `out` to get the size.
+//
+// # Safety
+// `machine` must be valid, `out` must be null or point to `capacity` writable bytes.
+size_t gbemu_save_state(const GbemuMachine *machine, uint8_t *out, size_t capacity);
+
+// Copy the 8 KiB of VRAM ($8000-$9FFF) to `out` like [`gbemu_save_state`], readable even when the PPU
+// locks it out of the CPU.
+//
+// # Safety
+// `machine` must be valid, `out` must be null or point to `capacity` writable bytes.
+size_t gbemu_vram(const GbemuMachine *machine, uint8_t *out, size_t capacity);
+
+// Copy the 160 bytes of OAM ($FE00-$FE9F) to `out` like [`gbemu_vram`].
+//
+// # Safety
+// `machine` must be valid, `out` must be null or point to `capacity` writable bytes.
+size_t gbemu_oam(const GbemuMachine *machine, uint8_t *out, size_t capacity);
+
+// Restore a snapshot written by [`gbemu_save_state`] with the same cartridge, the machine is left
+// untouched when it is rejected.
+//
+// # Safety
+// `machine` must be valid, `data` must point to `len` readable bytes.
+int32_t gbemu_load_state(GbemuMachine *machine, const uint8_t *data, size_t len);
+
+// Message of the last call that returned [`GBEMU_ERROR`], null when none failed. The string is owned by
+// the machine and valid until the next failure.
+//
+// # Safety
+// `machine` must be valid.
+const char *gbemu_last_error(const GbemuMachine *machine);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* GBEMU_H */
@@ -0,0 +1,371 @@
+//! C interface of gbemu-core, to embed the emulator in frontends written in other languages.
+//!
+//! The declarations are in `include/gbemu.h`, generated by cbindgen from this file with `cbindgen.toml`.
+//! Functions taking a `GbemuMachine` pointer expect one returned by [`gbemu_create`] and not destroyed yet,
+//! fallible ones return [`GBEMU_OK`] or [`GBEMU_ERROR`] and keep the message for [`gbemu_last_error`].
+//! A panic never unwinds into C: the call fails like any other error, the machine should then be
+//! reset or destroyed.
+
+use gbemu_core::video::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use gbemu_core::{Machine, PowerCycleOptions};
+use std::any::Any;
+use std::convert::Infallible;
+use std::ffi::{CString, c_char};
+use std::fmt::Display;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::{ptr, slice};
+
+pub const GBEMU_SCREEN_WIDTH: usize = SCREEN_WIDTH;
+pub const GBEMU_SCREEN_HEIGHT: usize = SCREEN_HEIGHT;
+
+pub const GBEMU_OK: i32 = 0;
+pub const GBEMU_ERROR: i32 = -1;
+
+/// Button bits of [`gbemu_set_buttons`]
+pub const GBEMU_BUTTON_RIGHT: u8 = 0x01;
+pub const GBEMU_BUTTON_LEFT: u8 = 0x02;
+pub const GBEMU_BUTTON_UP: u8 = 0x04;
+pub const GBEMU_BUTTON_DOWN: u8 = 0x08;
+pub const GBEMU_BUTTON_A: u8 = 0x10;
+pub const GBEMU_BUTTON_B: u8 = 0x20;
+pub const GBEMU_BUTTON_SELECT: u8 = 0x40;
+pub const GBEMU_BUTTON_START: u8 = 0x80;
+
+/// Emulated machine, opaque to C
+pub struct GbemuMachine {
+    machine: Machine,
+    /// Message of the last failed call
+    last_error: Option<CString>,
+}
+
+impl GbemuMachine {
+    fn status<E: Display>(&mut self, result: Result<(), E>) -> i32 {
+        match result {
+            Ok(()) => GBEMU_OK,
+            Err(e) => {
+                self.last_error = CString::new(e.to_string()).ok();
+                GBEMU_ERROR
+            }
+        }
+    }
+
+    /// Status of `call` on the machine, a panic is reported as an error
+    fn call<E: Display>(&mut self, call: impl FnOnce(&mut Machine) -> Result<(), E>) -> i32 {
+        let result = catch_unwind(AssertUnwindSafe(|| call(&mut self.machine).map_err(|e| e.to_string())))
+            .unwrap_or_else(|payload| Err(panic_message(payload)));
+        self.status(result)
+    }
+}
+
+/// Result of `call`, `fallback` when it panics
+fn guard<T>(fallback: T, call: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(call)).unwrap_or(fallback)
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    let message = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message,
+        (_, Some(message)) => message.as_str(),
+        _ => "unknown cause",
+    };
+    format!("panicked: {message}")
+}
+
+/// Bytes of a C buffer, empty when `data` is null
+///
+/// # Safety
+/// `data` must be null or point to `len` readable bytes.
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> &'a [u8] {
+    if data.is_null() {
+        &[]
+    } else {
+        unsafe { slice::from_raw_parts(data, len) }
+    }
+}
+
+/// Create a DMG without cartridge, null on failure. Free it with [`gbemu_destroy`].
+#[unsafe(no_mangle)]
+pub extern "C" fn gbemu_create() -> *mut GbemuMachine {
+    guard(ptr::null_mut(), || match Machine::builder().build() {
+        Ok(machine) => Box::into_raw(Box::new(GbemuMachine {
+            machine,
+            last_error: None,
+        })),
+        Err(_) => ptr::null_mut(),
+    })
+}
+
+/// Free a machine, null is ignored.
+///
+/// # Safety
+/// `machine` must be null or come from [`gbemu_create`], it must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gbemu_destroy(machine: *mut GbemuMachine) {
+    if !machine.is_null() {
+        guard((), || drop(unsafe { Box::from_raw(machine) }));
+    }
+}
+
+/// Insert a cartridge from a rom image (raw or zipped) and reset the machine, which is left as it was
+/// when the rom is rejected.
+///
+/// # Safety
+/// `machine` must be valid, `data` must point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gbemu_load_rom(machine: *mut GbemuMachine, data: *const u8, len: usize) -> i32 {
+    let Some(machine) = (unsafe { machine.as_mut() }) else {
+        return GBEMU_ERROR;
+    };
+    let rom = unsafe { bytes(data, len) }.to_vec();
+    machine.call(|machine| {
+        machine.load_cartridge_bytes(rom)?;
+        machine.reset();
+        Ok::<_, std::io::Error>(())
+    })
+}
+
+/// Turn the console off and on, the cartridge ram and clock are cleared unless kept by their battery.
+/// A reset keeps both.
+///
+/// # Safety
+/// `machine` must be valid.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gbemu_power_cycle(machine: *mut GbemuMachine, keep_sram: bool, keep_rtc: bool) -> i32 {
+    let Some(machine) = (unsafe { machine.as_mut() }) else {
+        return GBEMU_ERROR;
+    };
+    machine.call(|machine| {
+        machine.power_cycle(PowerCycleOptions { keep_sram, keep_rtc });
+        Ok::<_, Infallible>(())
+    })
+}
+
+/// Run until the next frame is complete, or until the machine stops on an error.
+///
+/// # Safety
+/// `machine` must be valid.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gbemu_run_frame(machine: *mut GbemuMachine) -> i32 {
+    let Some(machine) = (unsafe { machine.as_mut() }) else {
+        return GBEMU_ERROR;
+    };
+    machine.call(|machine| machine.step_frame().map(|_| ()))
+}
+
+/// Shade (0-3) of each pixel of the screen, [`GBEMU_SCREEN_WIDTH`] x [`GBEMU_SCREEN_HEIGHT`] bytes
+/// row by row. The pointer is valid until the machine runs again or is destroyed. In CGB mode the
+/// colors come from the CGB palettes, use [`gbemu_framebuffer_rgba`].
+///
+/// # Safety
+/// `machine` must be valid.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gbemu_framebuffer(machine: *const GbemuMachine) -> *const u8 {
+    match unsafe { machine.as_ref() } {
+        Some(machine) => guard(ptr::null(), || machine.machine.frame().as_ptr()),
+        None => ptr::null(),
+    }
+}
+
+/// Copy the screen as RGBA bytes in the colors of the machine palette, or its own colors in CGB mode,
+/// `out` holds [`GBEMU_SCREEN_WIDTH`] x [`GBEMU_SCREEN_HEIGHT`] x 4 bytes.
+///
+/// # Safety
+/// `machine` must be valid, `out` must point to `len` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gbemu_framebuffer_rgba(machine: *mut GbemuMachine, out: *mut u8, len: usize) -> i32 {
+    let Some(machine) = (unsafe { machine.as_mut() }) else {
+        return GBEMU_ERROR;
+    };
+    machine.call(|machine| {
+        let rgba = machine.frame_rgba();
+        let rgba = rgba.as_flattened();
+        if out.is_null() || len < rgba.len() {
+            return Err(format!("rgba buffer of {len} bytes, {} needed", rgba.len()));
+        }
+        unsafe { ptr::copy_nonoverlapping(rgba.as_ptr(), out, rgba.len()) };
+        Ok(())
+    })
+}
+
+/// Set the held buttons, a combination of the `GBEMU_BUTTON_*` bits.
+///
+/// # Safety
+/// `machine` must be valid.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gbemu_set_buttons(machine: *mut GbemuMachine, pressed: u8) {
+    if let Some(machine) = unsafe { machine.as_mut() } {
+        guard((), || machine.machine.set_pressed_buttons(pressed));
+    }
+}
+
+/// Write a snapshot of the machine to `out` when it holds `capacity` bytes or more, and return the
+/// size of the snapshot either way, 0 when `machine` is null. Call it with a null `out` to get the size.
+///
+/// # Safety
+/// `machine` must be valid, `out` must be null or point to `capacity` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gbemu_save_state(machine: *const GbemuMachine, out: *mut u8, capacity: usize) -> usize {
+    let Some(machine) = (unsafe { machine.as_ref() }) else {
+        return 0;
+    };
+    guard(0, || unsafe { write_out(&machine.machine.save_state(), out, capacity) })
+}
+
+/// Copy the 8 KiB of VRAM ($8000-$9FFF) to `out` like [`gbemu_save_state`], readable even when the PPU
+/// locks it out of the CPU.
+///
+/// # Safety
+/// `machine` must be valid, `out` must be null or point to `capacity` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gbemu_vram(machine: *const GbemuMachine, out: *mut u8, capacity: usize) -> usize {
+    match unsafe { machine.as_ref() } {
+        Some(machine) => guard(0, || unsafe { write_out(machine.machine.vram(), out, capacity) }),
+        None => 0,
+    }
+}
+
+/// Copy the 160 bytes of OAM ($FE00-$FE9F) to `out` like [`gbemu_vram`].
+///
+/// # Safety
+/// `machine` must be valid, `out` must be null or point to `capacity` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gbemu_oam(machine: *const GbemuMachine, out: *mut u8, capacity: usize) -> usize {
+    match unsafe { machine.as_ref() } {
+        Some(machine) => guard(0, || unsafe { write_out(machine.machine.oam(), out, capacity) }),
+        None => 0,
+    }
+}
+
+/// Copy `data` to `out` when it holds `capacity` bytes or more, returns the size of `data` either way.
+///
+/// # Safety
+/// `out` must be null or point to `capacity` writable bytes.
+unsafe fn write_out(data: &[u8], out: *mut u8, capacity: usize) -> usize {
+    if !out.is_null() && capacity >= data.len() {
+        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), out, data.len()) };
+    }
+    data.len()
+}
+
+/// Restore a snapshot written by [`gbemu_save_state`] with the same cartridge, the machine is left
+/// untouched when it is rejected.
+///
+/// # Safety
+/// `machine` must be valid, `data` must point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gbemu_load_state(machine: *mut GbemuMachine, data: *const u8, len: usize) -> i32 {
+    let Some(machine) = (unsafe { machine.as_mut() }) else {
+        return GBEMU_ERROR;
+    };
+    let data = unsafe { bytes(data, len) };
+    machine.call(|machine| machine.load_state(data))
+}
+
+/// Message of the last call that returned [`GBEMU_ERROR`], null when none failed. The string is owned by
+/// the machine and valid until the next failure.
+///
+/// # Safety
+/// `machine` must be valid.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gbemu_last_error(machine: *const GbemuMachine) -> *const c_char {
+    match unsafe { machine.as_ref() }.and_then(|machine| machine.last_error.as_ref()) {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_machine_lifecycle() {
+        let rom = gbemu_core::TestRom::new()
+            .code(&[0x3C, 0x18, 0xFD]) // INC A; JR -3
+            .build();
+        unsafe {
+            let machine = gbemu_create();
+            assert!(!machine.is_null());
+            assert_eq!(gbemu_load_rom(machine, rom.as_ptr(), rom.len()), GBEMU_OK);
+            assert_eq!(gbemu_power_cycle(machine, true, true), GBEMU_OK);
+            assert_eq!(gbemu_run_frame(machine), GBEMU_OK);
+            gbemu_set_buttons(machine, GBEMU_BUTTON_A | GBEMU_BUTTON_START);
+
+            let frame = slice::from_raw_parts(gbemu_framebuffer(machine), GBEMU_SCREEN_WIDTH * GBEMU_SCREEN_HEIGHT);
+            assert!(frame.iter().all(|shade| *shade < 4));
+            let mut rgba = vec![0; frame.len() * 4];
+            assert_eq!(gbemu_framebuffer_rgba(machine, rgba.as_mut_ptr(), rgba.len()), GBEMU_OK);
+            assert_eq!(rgba[3], 0xFF);
+
+            let size = gbemu_save_state(machine, ptr::null_mut(), 0);
+            let mut state = vec![0; size];
+            assert_eq!(gbemu_save_state(machine, state.as_mut_ptr(), state.len()), size);
+            assert_eq!(gbemu_run_frame(machine), GBEMU_OK);
+            assert_eq!(gbemu_load_state(machine, state.as_ptr(), state.len()), GBEMU_OK);
+            let mut reloaded = vec![0; size];
+            gbemu_save_state(machine, reloaded.as_mut_ptr(), reloaded.len());
+            assert_eq!(reloaded, state);
+
+            let mut oam = [0xFF; 0xA0];
+            assert_eq!(gbemu_oam(machine, oam.as_mut_ptr(), oam.len()), 0xA0);
+            assert_eq!(oam, [0; 0xA0]);
+            assert_eq!(gbemu_vram(machine, ptr::null_mut(), 0), 0x2000);
+
+            assert!(gbemu_last_error(machine).is_null());
+            assert_eq!(gbemu_load_state(machine, state.as_ptr(), 8), GBEMU_ERROR);
+            let error = CStr::from_ptr(gbemu_last_error(machine));
+            assert!(error.to_str().is_ok_and(|error| !error.is_empty()));
+
+            gbemu_destroy(machine);
+        }
+    }
+
+    #[test]
+    fn test_failures() {
+        unsafe {
+            let machine = gbemu_create();
+            // A rejected rom keeps the machine running the previous one
+            assert_eq!(gbemu_run_frame(machine), GBEMU_OK);
+            let state = vec![0; gbemu_save_state(machine, ptr::null_mut(), 0)];
+            gbemu_save_state(machine, state.as_ptr().cast_mut(), state.len());
+            assert_eq!(gbemu_load_rom(machine, [0u8; 16].as_ptr(), 16), GBEMU_ERROR);
+            let mut after = vec![0; state.len()];
+            gbemu_save_state(machine, after.as_mut_ptr(), after.len());
+            assert_eq!(after, state);
+
+            let status = (*machine).call(|_| -> Result<(), Infallible> { panic!("test panic") });
+            assert_eq!(status, GBEMU_ERROR);
+            assert_eq!(
+                CStr::from_ptr(gbemu_last_error(machine)).to_str(),
+                Ok("panicked: test panic")
+            );
+            gbemu_destroy(machine);
+        }
+    }
+
+    #[test]
+    fn test_null_machine() {
+        unsafe {
+            assert_eq!(gbemu_run_frame(ptr::null_mut()), GBEMU_ERROR);
+            assert_eq!(gbemu_power_cycle(ptr::null_mut(), true, true), GBEMU_ERROR);
+            assert!(gbemu_framebuffer(ptr::null()).is_null());
+            assert_eq!(gbemu_save_state(ptr::null(), ptr::null_mut(), 0), 0);
+            assert_eq!(gbemu_vram(ptr::null(), ptr::null_mut(), 0), 0);
+            gbemu_destroy(ptr::null_mut());
+        }
+    }
+
+    /// The header must be regenerated when a function is exported
+    #[test]
+    fn test_header_declares_exports() {
+        let header = include_str!("../include/gbemu.h");
+        let missing: Vec<_> = include_str!("lib.rs")
+            .lines()
+            .filter_map(|line| line.split(" extern \"C\" fn ").nth(1))
+            .filter_map(|declaration| declaration.split('(').next())
+            .filter(|name| !header.contains(&format!("{name}(")))
+            .collect();
+        assert!(missing.is_empty(), "missing from include/gbemu.h: {missing:?}");
+    }
+}
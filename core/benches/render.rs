@@ -0,0 +1,34 @@
+//! Frame rendering throughput, run with `cargo bench -p gbemu-core --features test-roms`.
+//! Add the `profiling` feature to get the time spent rendering each line.
+
+use gbemu_core::{Machine, TestRom};
+use std::hint::black_box;
+use std::time::Instant;
+
+const FRAMES: usize = 600;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let rom = TestRom::new()
+        .code(&[0x3E, 0x03]) // LD A,3
+        .code(&[0xE0, 0x43]) // LDH (SCX),A ; fine scroll
+        .code(&[0x18, 0xFE]) // JR -2
+        .build();
+    let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        black_box(machine.step_frame()?);
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{FRAMES} frames in {elapsed:.2?}, {:.2?} per frame",
+        elapsed / FRAMES as u32
+    );
+
+    #[cfg(feature = "profiling")]
+    println!(
+        "line render: {:.2?} on average",
+        machine.profile_report().line_render.average()
+    );
+    Ok(())
+}
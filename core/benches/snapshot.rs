@@ -0,0 +1,53 @@
+//! Snapshot stream encoding, run with `cargo bench -p gbemu-core --features test-roms --bench snapshot`.
+//! Prints the size of the keyframes and deltas of a game writing to the work ram and scrolling.
+
+use gbemu_core::state::{DEFAULT_KEYFRAME_INTERVAL, SnapshotStream};
+use gbemu_core::{Machine, TestRom};
+use std::time::{Duration, Instant};
+
+const FRAMES: usize = 600;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let rom = TestRom::new()
+        .code(&[0x21, 0x00, 0xC0]) // LD HL,$C000
+        .code(&[0x34]) // INC (HL)
+        .code(&[0x23]) // INC HL
+        .code(&[0xCB, 0x6C]) // BIT 5,H
+        .code(&[0x28, 0xFA]) // JR Z,-6 ; back to INC (HL) below $E000
+        .code(&[0xF0, 0x43]) // LDH A,(SCX)
+        .code(&[0x3C]) // INC A
+        .code(&[0xE0, 0x43]) // LDH (SCX),A
+        .code(&[0x18, 0xF0]) // JR -16 ; back to LD HL
+        .build();
+    let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+    let mut stream = SnapshotStream::new(DEFAULT_KEYFRAME_INTERVAL, FRAMES);
+
+    let (mut encoding, mut raw) = (Duration::ZERO, 0);
+    for _ in 0..FRAMES {
+        machine.step_frame()?;
+        let state = machine.save_state();
+        raw += state.len();
+        let start = Instant::now();
+        stream.push(&state);
+        encoding += start.elapsed();
+    }
+
+    let start = Instant::now();
+    for index in 0..stream.len() {
+        std::hint::black_box(stream.get(index));
+    }
+    let decoding = start.elapsed();
+
+    println!(
+        "{FRAMES} states: {} KB raw, {} KB encoded, {:.1} KB per frame",
+        raw / 1024,
+        stream.byte_size() / 1024,
+        stream.byte_size() as f64 / FRAMES as f64 / 1024.0
+    );
+    println!(
+        "push {:.2?}, get {:.2?} per state",
+        encoding / FRAMES as u32,
+        decoding / FRAMES as u32
+    );
+    Ok(())
+}
@@ -0,0 +1,49 @@
+//! Instruction stepping cost with a logger accepting everything, run with
+//! `cargo bench -p gbemu-core --features test-roms --bench step`, then again with `--features test-roms,trace`.
+//! Without the `trace` feature the jump traces are compiled out and the logger is never reached.
+
+use gbemu_core::{Machine, TestRom};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::hint::black_box;
+use std::time::Instant;
+
+const STEPS: u32 = 5_000_000;
+
+/// Formats every record and drops it, the cost of a frontend logging to a file
+struct NullLogger;
+
+impl Log for NullLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        black_box(record.args().to_string());
+    }
+
+    fn flush(&self) {}
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    log::set_logger(&NullLogger).map_err(|e| e.to_string())?;
+    log::set_max_level(LevelFilter::Trace);
+
+    let rom = TestRom::new()
+        .code(&[0x3C]) // INC A
+        .code(&[0x20, 0xFD]) // JR NZ,-3
+        .code(&[0xC3, 0x50, 0x01]) // JP $0150
+        .build();
+    let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+
+    let start = Instant::now();
+    for _ in 0..STEPS {
+        black_box(machine.step()?);
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{STEPS} steps in {elapsed:.2?}, {:.2?} per step (trace {})",
+        elapsed / STEPS,
+        if cfg!(feature = "trace") { "on" } else { "off" }
+    );
+    Ok(())
+}
@@ -0,0 +1,24 @@
+//! Compile time values of `build_info`: the git commit and the compiler version
+use std::process::Command;
+
+fn main() {
+    let git_hash = output("git", &["rev-parse", "--short=10", "HEAD"]);
+    let compiler = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc = output(&compiler, &["--version"]);
+    println!("cargo:rustc-env=GBEMU_GIT_HASH={git_hash}");
+    println!("cargo:rustc-env=GBEMU_RUSTC_VERSION={rustc}");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+}
+
+/// First line printed by the command, empty when it fails
+fn output(program: &str, args: &[&str]) -> String {
+    Command::new(program)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|stdout| stdout.lines().next().map(str::to_string))
+        .unwrap_or_default()
+}
@@ -0,0 +1,138 @@
+use crate::ppu::PpuModel;
+
+/// Named set of accuracy options, from the fastest to the most faithful emulation, see [`AccuracyProfile`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Accuracy {
+    /// Only what commercial games rely on
+    Fast,
+    /// Timer edge cases on top of [`Accuracy::Fast`], enough for nearly every game
+    #[default]
+    Balanced,
+    /// Every option, for test roms and demos
+    Accurate,
+}
+
+impl Accuracy {
+    pub const ALL: [Accuracy; 3] = [Accuracy::Fast, Accuracy::Balanced, Accuracy::Accurate];
+
+    pub fn profile(&self) -> AccuracyProfile {
+        match self {
+            Accuracy::Fast => AccuracyProfile {
+                vram_locking: false,
+                oam_bug: false,
+                timer_edge_cases: false,
+                dma_timing: false,
+                ppu_model: PpuModel::Scanline,
+            },
+            Accuracy::Balanced => AccuracyProfile {
+                timer_edge_cases: true,
+                ..Accuracy::Fast.profile()
+            },
+            Accuracy::Accurate => AccuracyProfile {
+                vram_locking: true,
+                oam_bug: true,
+                timer_edge_cases: true,
+                dma_timing: true,
+                ppu_model: PpuModel::Fifo,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Accuracy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Accuracy::Fast => write!(f, "Fast"),
+            Accuracy::Balanced => write!(f, "Balanced"),
+            Accuracy::Accurate => write!(f, "Accurate"),
+        }
+    }
+}
+
+impl std::str::FromStr for Accuracy {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Accuracy::ALL
+            .into_iter()
+            .find(|accuracy| accuracy.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("unknown accuracy: {s}, expected fast, balanced or accurate"),
+                )
+            })
+    }
+}
+
+/// Hardware behaviors that cost speed and that few games depend on, consulted by the subsystems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AccuracyProfile {
+    /// The CPU reads `$FF` from and cannot write to VRAM during pixel transfer, and OAM during OAM scan and
+    /// pixel transfer
+    pub vram_locking: bool,
+    /// DMG OAM corruption bug, see [`MachineBuilder::oam_bug`](crate::MachineBuilder::oam_bug)
+    pub oam_bug: bool,
+    /// TIMA increments on DIV and TAC writes, and the reload from TMA one M-cycle after an overflow
+    pub timer_edge_cases: bool,
+    /// OAM DMA takes 160 M-cycles during which the CPU cannot reach OAM, instead of an instant copy
+    pub dma_timing: bool,
+    /// Whole scanlines, or the pixel FIFO for the registers written in the middle of a line
+    pub ppu_model: PpuModel,
+}
+
+impl AccuracyProfile {
+    /// Preset with exactly these options, `None` for a custom profile
+    pub fn preset(&self) -> Option<Accuracy> {
+        Accuracy::ALL.into_iter().find(|accuracy| accuracy.profile() == *self)
+    }
+    /// This profile without the options compiled out of the build: VRAM locking, the OAM bug and the DMA
+    /// timing need the `accuracy-extras` feature
+    pub fn supported(self) -> Self {
+        if cfg!(feature = "accuracy-extras") {
+            return self;
+        }
+        AccuracyProfile {
+            vram_locking: false,
+            oam_bug: false,
+            dma_timing: false,
+            ..self
+        }
+    }
+}
+
+impl Default for AccuracyProfile {
+    fn default() -> Self {
+        Accuracy::default().profile()
+    }
+}
+
+impl From<Accuracy> for AccuracyProfile {
+    fn from(accuracy: Accuracy) -> Self {
+        accuracy.profile()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accuracy_presets() {
+        assert_eq!("accurate".parse::<Accuracy>().unwrap(), Accuracy::Accurate);
+        assert_eq!("FAST".parse::<Accuracy>().unwrap(), Accuracy::Fast);
+        assert!("exact".parse::<Accuracy>().is_err());
+
+        for accuracy in Accuracy::ALL {
+            assert_eq!(accuracy.to_string().parse::<Accuracy>().unwrap(), accuracy);
+            assert_eq!(accuracy.profile().preset(), Some(accuracy));
+        }
+        assert_eq!(AccuracyProfile::default().preset(), Some(Accuracy::Balanced));
+        let custom = AccuracyProfile {
+            oam_bug: true,
+            ..Accuracy::Fast.profile()
+        };
+        assert_eq!(custom.preset(), None);
+        assert_eq!(custom.supported().oam_bug, cfg!(feature = "accuracy-extras"));
+    }
+}
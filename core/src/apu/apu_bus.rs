@@ -0,0 +1,15 @@
+use crate::bus::{InterruptBus, define_u8_accessors};
+
+pub trait ApuBus: InterruptBus {
+    define_u8_accessors!(nr50, 0xFF24);
+    define_u8_accessors!(nr51, 0xFF25);
+
+    /// Byte `index` of the wave pattern ram ($FF30..$FF3F)
+    fn wave_ram(&self, index: u8) -> u8 {
+        self.read_byte(0xFF30 + (index as u16 & 0x0F))
+    }
+    /// Sound registers written by the CPU since the last call, in order
+    fn take_apu_writes(&mut self) -> Vec<(u16, u8)> {
+        vec![]
+    }
+}
@@ -0,0 +1,139 @@
+use crate::apu::SAMPLE_RATE;
+use bitflags::bitflags;
+use std::fs::File;
+use std::io::{BufWriter, Error, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+bitflags! {
+    /// Channels recorded to their own file by [`crate::Apu::start_capture`], next to the mixed output
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct AudioChannels: u8 {
+        const Square1 = 0b0001;
+        const Square2 = 0b0010;
+        const Wave = 0b0100;
+        const Noise = 0b1000;
+    }
+}
+
+/// 16-bit PCM wav file, the sizes in the header are written when the file is finished
+pub(crate) struct WavWriter {
+    writer: BufWriter<File>,
+    channels: u16,
+    data_size: u32,
+    finished: bool,
+}
+
+impl WavWriter {
+    pub(crate) fn create(path: &Path, channels: u16) -> Result<Self, Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        let block_align = channels * 2;
+
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?; // PCM
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        writer.write_all(&(SAMPLE_RATE * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            channels,
+            data_size: 0,
+            finished: false,
+        })
+    }
+
+    /// One sample per channel
+    pub(crate) fn write(&mut self, samples: &[i16]) -> Result<(), Error> {
+        debug_assert_eq!(samples.len(), self.channels as usize);
+        for sample in samples {
+            self.writer.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_size += samples.len() as u32 * 2;
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> Result<(), Error> {
+        self.write_sizes()
+    }
+
+    fn write_sizes(&mut self) -> Result<(), Error> {
+        self.finished = true;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(36 + self.data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(40))?;
+        self.writer.write_all(&self.data_size.to_le_bytes())?;
+        self.writer.flush()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.write_sizes();
+        }
+    }
+}
+
+/// Recording of the mixed output (stereo) and of the selected channels (mono)
+pub(crate) struct Capture {
+    mixed: WavWriter,
+    channels: [Option<WavWriter>; 4],
+    /// Samples left to record, unlimited when `None`
+    pub(crate) samples_left: Option<u64>,
+}
+
+impl Capture {
+    /// The mixed output is written to `path`, channel n to `<stem>_ch<n>.<extension>`
+    pub(crate) fn create(path: &Path, channels: AudioChannels) -> Result<Self, Error> {
+        let mut writers = [None, None, None, None];
+        for (index, writer) in writers.iter_mut().enumerate() {
+            if channels.bits() & (1 << index) != 0 {
+                *writer = Some(WavWriter::create(&channel_path(path, index + 1), 1)?);
+            }
+        }
+
+        Ok(Self {
+            mixed: WavWriter::create(path, 2)?,
+            channels: writers,
+            samples_left: None,
+        })
+    }
+
+    pub(crate) fn write(&mut self, mixed: [i16; 2], channels: [i16; 4]) -> Result<(), Error> {
+        self.mixed.write(&mixed)?;
+        for (writer, sample) in self.channels.iter_mut().zip(channels) {
+            if let Some(writer) = writer {
+                writer.write(&[sample])?;
+            }
+        }
+        if let Some(samples_left) = self.samples_left.as_mut() {
+            *samples_left = samples_left.saturating_sub(1);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_complete(&self) -> bool {
+        self.samples_left == Some(0)
+    }
+
+    pub(crate) fn finish(self) -> Result<(), Error> {
+        self.mixed.finish()?;
+        self.channels.into_iter().flatten().try_for_each(WavWriter::finish)
+    }
+}
+
+fn channel_path(path: &Path, channel: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut file_name = format!("{stem}_ch{channel}");
+    if let Some(extension) = path.extension() {
+        file_name = format!("{file_name}.{}", extension.to_string_lossy());
+    }
+    path.with_file_name(file_name)
+}
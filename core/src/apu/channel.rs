@@ -0,0 +1,465 @@
+use crate::apu::apu_bus::ApuBus;
+use crate::state::{Savable, StateReader, StateWriter};
+
+/// Waveforms of the square channels, one bit per step
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+/// Noise channel divisors selected by NR43 bits 0-2
+const NOISE_DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/// Length counter, the channel is disabled when it expires
+#[derive(Default)]
+struct Length {
+    counter: u16,
+    enabled: bool,
+}
+
+impl Length {
+    fn load(&mut self, max: u16, value: u8) {
+        self.counter = max - value as u16;
+    }
+
+    fn trigger(&mut self, max: u16) {
+        if self.counter == 0 {
+            self.counter = max;
+        }
+    }
+
+    /// Returns true when the counter expired
+    fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+        self.counter -= 1;
+        self.counter == 0
+    }
+}
+
+impl Savable for Length {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.counter);
+        writer.write_bool(self.enabled);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.counter = reader.read_u16()?;
+        self.enabled = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+/// Volume envelope, configured by NRx2
+#[derive(Default)]
+struct Envelope {
+    /// NRx2, applied on trigger
+    register: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    fn period(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    /// The DAC is off when the initial volume and the direction are 0
+    fn dac_enabled(&self) -> bool {
+        self.register & 0xF8 != 0
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = self.period();
+    }
+
+    fn clock(&mut self) {
+        if self.period() == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = self.period();
+            if self.register & 0x08 != 0 {
+                self.volume = (self.volume + 1).min(15);
+            } else {
+                self.volume = self.volume.saturating_sub(1);
+            }
+        }
+    }
+}
+
+impl Savable for Envelope {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.register);
+        writer.write_u8(self.volume);
+        writer.write_u8(self.timer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.register = reader.read_u8()?;
+        self.volume = reader.read_u8()?;
+        self.timer = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+/// Square channel (1 and 2), registers are numbered from NRx0
+#[derive(Default)]
+pub(crate) struct Square {
+    pub(crate) enabled: bool,
+    duty: u8,
+    position: u8,
+    pub(crate) frequency: u16,
+    timer: u32,
+    length: Length,
+    envelope: Envelope,
+}
+
+impl Square {
+    /// Returns true when the write triggered the channel
+    pub(crate) fn write(&mut self, register: u16, value: u8) -> bool {
+        match register {
+            1 => {
+                self.duty = value >> 6;
+                self.length.load(64, value & 0x3F);
+            }
+            2 => {
+                self.envelope.register = value;
+                self.enabled &= self.envelope.dac_enabled();
+            }
+            3 => self.frequency = self.frequency & 0x700 | value as u16,
+            4 => {
+                self.frequency = self.frequency & 0xFF | (value as u16 & 0x07) << 8;
+                self.length.enabled = value & 0x40 != 0;
+                if value & 0x80 != 0 {
+                    self.enabled = self.envelope.dac_enabled();
+                    self.length.trigger(64);
+                    self.envelope.trigger();
+                    self.timer = self.period();
+                    return true;
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+
+    pub(crate) fn tick(&mut self, mut cycles: u32) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) & 0x07;
+        }
+        self.timer -= cycles;
+    }
+
+    pub(crate) fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub(crate) fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Digital output, 0 to 15
+    pub(crate) fn output(&self) -> u8 {
+        let high = DUTY_PATTERNS[self.duty as usize] >> (7 - self.position) & 1 != 0;
+        if self.enabled && high { self.envelope.volume } else { 0 }
+    }
+}
+
+impl Savable for Square {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_u8(self.duty);
+        writer.write_u8(self.position);
+        writer.write_u16(self.frequency);
+        writer.write_u32(self.timer);
+        self.length.save_state(writer);
+        self.envelope.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.enabled = reader.read_bool()?;
+        self.duty = reader.read_u8()?;
+        self.position = reader.read_u8()?;
+        self.frequency = reader.read_u16()?;
+        self.timer = reader.read_u32()?;
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)
+    }
+}
+
+/// Frequency sweep of channel 1, configured by NR10
+#[derive(Default)]
+pub(crate) struct Sweep {
+    pub(crate) register: u8,
+    enabled: bool,
+    shadow: u16,
+    timer: u8,
+}
+
+impl Sweep {
+    fn period(&self) -> u8 {
+        self.register >> 4 & 0x07
+    }
+
+    fn shift(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    fn next_frequency(&self) -> u16 {
+        let delta = self.shadow >> self.shift();
+        if self.register & 0x08 != 0 {
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        }
+    }
+
+    fn reload_timer(&mut self) {
+        self.timer = match self.period() {
+            0 => 8,
+            period => period,
+        };
+    }
+
+    pub(crate) fn trigger(&mut self, square: &mut Square) {
+        self.shadow = square.frequency;
+        self.reload_timer();
+        self.enabled = self.period() != 0 || self.shift() != 0;
+        if self.shift() != 0 && self.next_frequency() > 0x7FF {
+            square.enabled = false;
+        }
+    }
+
+    pub(crate) fn clock(&mut self, square: &mut Square) {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return;
+        }
+        self.reload_timer();
+        if !self.enabled || self.period() == 0 {
+            return;
+        }
+
+        let frequency = self.next_frequency();
+        if frequency > 0x7FF {
+            square.enabled = false;
+        } else if self.shift() != 0 {
+            self.shadow = frequency;
+            square.frequency = frequency;
+            // The overflow check is done again with the new frequency
+            if self.next_frequency() > 0x7FF {
+                square.enabled = false;
+            }
+        }
+    }
+}
+
+impl Savable for Sweep {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.register);
+        writer.write_bool(self.enabled);
+        writer.write_u16(self.shadow);
+        writer.write_u8(self.timer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.register = reader.read_u8()?;
+        self.enabled = reader.read_bool()?;
+        self.shadow = reader.read_u16()?;
+        self.timer = reader.read_u8()?;
+        Ok(())
+    }
+}
+
+/// Wave channel (3), playing the 32 samples of the wave pattern ram
+#[derive(Default)]
+pub(crate) struct Wave {
+    pub(crate) enabled: bool,
+    dac: bool,
+    /// NR32 output level, 0 mutes the channel
+    level: u8,
+    frequency: u16,
+    timer: u32,
+    position: u8,
+    sample: u8,
+    length: Length,
+}
+
+impl Wave {
+    pub(crate) fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.dac = value & 0x80 != 0;
+                self.enabled &= self.dac;
+            }
+            1 => self.length.load(256, value),
+            2 => self.level = value >> 5 & 0x03,
+            3 => self.frequency = self.frequency & 0x700 | value as u16,
+            4 => {
+                self.frequency = self.frequency & 0xFF | (value as u16 & 0x07) << 8;
+                self.length.enabled = value & 0x40 != 0;
+                if value & 0x80 != 0 {
+                    self.enabled = self.dac;
+                    self.length.trigger(256);
+                    self.timer = self.period();
+                    self.position = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+
+    pub(crate) fn tick(&mut self, bus: &impl ApuBus, mut cycles: u32) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            self.position = (self.position + 1) & 0x1F;
+            let byte = bus.wave_ram(self.position / 2);
+            self.sample = if self.position & 1 == 0 { byte >> 4 } else { byte & 0x0F };
+        }
+        self.timer -= cycles;
+    }
+
+    pub(crate) fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// Digital output, 0 to 15
+    pub(crate) fn output(&self) -> u8 {
+        match self.level {
+            0 => 0,
+            _ if !self.enabled => 0,
+            level => self.sample >> (level - 1),
+        }
+    }
+}
+
+impl Savable for Wave {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_bool(self.dac);
+        writer.write_u8(self.level);
+        writer.write_u16(self.frequency);
+        writer.write_u32(self.timer);
+        writer.write_u8(self.position);
+        writer.write_u8(self.sample);
+        self.length.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.enabled = reader.read_bool()?;
+        self.dac = reader.read_bool()?;
+        self.level = reader.read_u8()?;
+        self.frequency = reader.read_u16()?;
+        self.timer = reader.read_u32()?;
+        self.position = reader.read_u8()?;
+        self.sample = reader.read_u8()?;
+        self.length.load_state(reader)
+    }
+}
+
+/// Noise channel (4), output of a 15 bit (or 7 bit) linear feedback shift register
+#[derive(Default)]
+pub(crate) struct Noise {
+    pub(crate) enabled: bool,
+    /// NR43 clock shift, width and divisor
+    polynomial: u8,
+    timer: u32,
+    lfsr: u16,
+    length: Length,
+    envelope: Envelope,
+}
+
+impl Noise {
+    pub(crate) fn write(&mut self, register: u16, value: u8) {
+        match register {
+            1 => self.length.load(64, value & 0x3F),
+            2 => {
+                self.envelope.register = value;
+                self.enabled &= self.envelope.dac_enabled();
+            }
+            3 => self.polynomial = value,
+            4 => {
+                self.length.enabled = value & 0x40 != 0;
+                if value & 0x80 != 0 {
+                    self.enabled = self.envelope.dac_enabled();
+                    self.length.trigger(64);
+                    self.envelope.trigger();
+                    self.timer = self.period();
+                    self.lfsr = 0x7FFF;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn period(&self) -> u32 {
+        NOISE_DIVISORS[(self.polynomial & 0x07) as usize] << (self.polynomial >> 4)
+    }
+
+    pub(crate) fn tick(&mut self, mut cycles: u32) {
+        while cycles >= self.timer {
+            cycles -= self.timer;
+            self.timer = self.period();
+            let bit = (self.lfsr ^ self.lfsr >> 1) & 1;
+            self.lfsr = self.lfsr >> 1 | bit << 14;
+            if self.polynomial & 0x08 != 0 {
+                self.lfsr = self.lfsr & !0x40 | bit << 6;
+            }
+        }
+        self.timer -= cycles;
+    }
+
+    pub(crate) fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub(crate) fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// Digital output, 0 to 15
+    pub(crate) fn output(&self) -> u8 {
+        if self.enabled && self.lfsr & 1 == 0 {
+            self.envelope.volume
+        } else {
+            0
+        }
+    }
+}
+
+impl Savable for Noise {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.enabled);
+        writer.write_u8(self.polynomial);
+        writer.write_u32(self.timer);
+        writer.write_u16(self.lfsr);
+        self.length.save_state(writer);
+        self.envelope.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.enabled = reader.read_bool()?;
+        self.polynomial = reader.read_u8()?;
+        self.timer = reader.read_u32()?;
+        self.lfsr = reader.read_u16()?;
+        self.length.load_state(reader)?;
+        self.envelope.load_state(reader)
+    }
+}
@@ -0,0 +1,503 @@
+pub(crate) mod apu_bus;
+mod capture;
+mod channel;
+mod output;
+mod resampler;
+mod sink;
+mod sound_log;
+
+use crate::apu::capture::Capture;
+use crate::apu::channel::{Noise, Square, Sweep, Wave};
+use crate::apu::sound_log::SoundLog;
+use crate::state::{Savable, StateReader, StateWriter};
+use apu_bus::ApuBus;
+pub use capture::AudioChannels;
+use log::{error, info};
+pub use output::{AudioOutput, AudioSettings};
+pub use resampler::{Resampler, ResamplerQuality};
+pub use sink::{AudioChunk, AudioSink, AudioStream, NullAudioSink, StreamSink, WavSink};
+use std::path::Path;
+
+/// Output sample rate of the APU, one sample every 64 cycles
+pub const SAMPLE_RATE: u32 = 65_536;
+const CYCLES_PER_SAMPLE: u32 = 64;
+/// The frame sequencer runs at 512 Hz
+const CYCLES_PER_SEQUENCER_STEP: u32 = 8192;
+
+/// Sound registers after the boot rom, $FF10..$FF26
+const POST_BOOT_REGISTERS: [u8; 0x17] = [
+    0x80, 0xBF, 0xF3, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF, 0xBF, 0xFF, 0xFF, 0x00, 0x00,
+    0xBF, 0x77, 0xF3, 0xF1,
+];
+
+/// Audio processing unit: two square channels (the first one with a frequency sweep), a wave channel
+/// and a noise channel, mixed to stereo by NR50/NR51.
+///
+/// The channels are driven by the register writes reported by the bus, the length counters, envelopes
+/// and sweep are clocked by the 512 Hz frame sequencer.
+#[derive(Default)]
+pub struct Apu {
+    powered: bool,
+    square1: Square,
+    sweep: Sweep,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+    sequencer_counter: u32,
+    sequencer_step: u8,
+    sample_counter: u32,
+    capture: Option<Capture>,
+    sound_log: Option<SoundLog>,
+    output: Option<AudioOutput>,
+    /// Mixed samples kept for the audio sinks of the machine
+    sink_samples: Option<Vec<[i16; 2]>>,
+}
+
+impl Apu {
+    pub fn reset(&mut self, bus: &mut impl ApuBus) {
+        self.power_off();
+        bus.take_apu_writes();
+        for (address, value) in (0xFF10..).zip(POST_BOOT_REGISTERS) {
+            bus.write_internal_byte(address, value);
+        }
+        self.powered = true;
+        self.sweep.register = 0x80;
+        // The boot sound has faded out, channel 1 is still reported as playing
+        self.square1.write(2, 0xF3);
+        self.square1.enabled = true;
+        self.sequencer_counter = 0;
+        self.sequencer_step = 0;
+        self.sample_counter = 0;
+    }
+
+    pub fn step(&mut self, bus: &mut impl ApuBus, cycles: u8) {
+        for (address, value) in bus.take_apu_writes() {
+            self.log_write(address, value);
+            self.write(bus, address, value);
+        }
+        if let Some(sound_log) = self.sound_log.as_mut() {
+            sound_log.advance(cycles);
+        }
+
+        let cycles = cycles as u32;
+        if self.powered {
+            self.square1.tick(cycles);
+            self.square2.tick(cycles);
+            self.wave.tick(bus, cycles);
+            self.noise.tick(cycles);
+
+            self.sequencer_counter += cycles;
+            if self.sequencer_counter >= CYCLES_PER_SEQUENCER_STEP {
+                self.sequencer_counter -= CYCLES_PER_SEQUENCER_STEP;
+                self.clock_sequencer();
+            }
+        }
+
+        self.sample_counter += cycles;
+        if self.sample_counter >= CYCLES_PER_SAMPLE {
+            self.sample_counter -= CYCLES_PER_SAMPLE;
+            self.output_sample(bus);
+        }
+
+        let status = [
+            self.square1.enabled,
+            self.square2.enabled,
+            self.wave.enabled,
+            self.noise.enabled,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0x70 | (self.powered as u8) << 7, |nr52, (index, enabled)| {
+            nr52 | (*enabled as u8) << index
+        });
+        bus.write_internal_byte(0xFF26, status);
+    }
+
+    fn log_write(&mut self, address: u16, value: u8) {
+        if let Some(sound_log) = self.sound_log.as_mut()
+            && let Err(e) = sound_log.write_register(address, value)
+        {
+            error!("Sound log failed: {e}");
+            self.sound_log = None;
+        }
+    }
+
+    fn write(&mut self, bus: &mut impl ApuBus, address: u16, value: u8) {
+        if address == 0xFF26 {
+            if value & 0x80 == 0 && self.powered {
+                self.power_off();
+                (0xFF10..0xFF26).for_each(|address| bus.write_internal_byte(address, 0x00));
+            } else if value & 0x80 != 0 && !self.powered {
+                self.powered = true;
+                self.sequencer_step = 0;
+            }
+            return;
+        }
+        if !self.powered {
+            return;
+        }
+
+        match address {
+            0xFF10 => self.sweep.register = value,
+            0xFF11..=0xFF14 => {
+                if self.square1.write(address - 0xFF10, value) {
+                    self.sweep.trigger(&mut self.square1);
+                }
+            }
+            0xFF16..=0xFF19 => {
+                self.square2.write(address - 0xFF15, value);
+            }
+            0xFF1A..=0xFF1E => self.wave.write(address - 0xFF1A, value),
+            0xFF20..=0xFF23 => self.noise.write(address - 0xFF1F, value),
+            _ => {}
+        }
+    }
+
+    /// Every channel is silenced, the wave ram is kept
+    fn power_off(&mut self) {
+        self.powered = false;
+        self.square1 = Square::default();
+        self.sweep = Sweep::default();
+        self.square2 = Square::default();
+        self.wave = Wave::default();
+        self.noise = Noise::default();
+    }
+
+    fn clock_sequencer(&mut self) {
+        if self.sequencer_step & 1 == 0 {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.sequencer_step == 2 || self.sequencer_step == 6 {
+            self.sweep.clock(&mut self.square1);
+        }
+        if self.sequencer_step == 7 {
+            self.square1.clock_envelope();
+            self.square2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+        self.sequencer_step = (self.sequencer_step + 1) & 0x07;
+    }
+
+    /// Output of each channel, centered on 0, from -15 to 15
+    fn channel_outputs(&self) -> [i16; 4] {
+        let analog = |enabled: bool, output: u8| if enabled { output as i16 * 2 - 15 } else { 0 };
+        [
+            analog(self.square1.enabled, self.square1.output()),
+            analog(self.square2.enabled, self.square2.output()),
+            analog(self.wave.enabled, self.wave.output()),
+            analog(self.noise.enabled, self.noise.output()),
+        ]
+    }
+
+    /// Left and right output, mixed as selected by NR51 and scaled by the NR50 volumes
+    fn mix(&self, bus: &impl ApuBus, channels: [i16; 4]) -> [i16; 2] {
+        let (nr50, nr51) = (bus.nr50(), bus.nr51());
+        let side = |enable_shift: u8, volume: u8| {
+            let sum: i16 = (0..4)
+                .filter(|index| nr51 >> (enable_shift + index) & 1 != 0)
+                .map(|index| channels[index as usize])
+                .sum();
+            sum * (volume as i16 + 1) * 64
+        };
+        [side(4, nr50 >> 4 & 0x07), side(0, nr50 & 0x07)]
+    }
+
+    fn output_sample(&mut self, bus: &impl ApuBus) {
+        if self.capture.is_none() && self.output.is_none() && self.sink_samples.is_none() {
+            return;
+        }
+
+        let channels = self.channel_outputs();
+        let mixed = if self.powered { self.mix(bus, channels) } else { [0, 0] };
+        if let Some(output) = self.output.as_mut() {
+            output.push(mixed);
+        }
+        if let Some(samples) = self.sink_samples.as_mut() {
+            samples.push(mixed);
+        }
+        let Some(capture) = self.capture.as_mut() else {
+            return;
+        };
+        let result = capture.write(mixed, channels.map(|output| output * 2048));
+        if let Err(e) = result {
+            error!("Audio capture failed: {e}");
+            self.capture = None;
+        } else if capture.is_complete() {
+            info!("Audio capture complete");
+            if let Err(e) = self.stop_capture() {
+                error!("Failed to finish audio capture: {e}");
+            }
+        }
+    }
+
+    /// Resample the output for the host audio device, `None` disables the output stream
+    pub fn set_audio_settings(&mut self, settings: Option<AudioSettings>) {
+        self.output = settings.map(AudioOutput::new);
+    }
+
+    pub fn audio_output(&self) -> Option<&AudioOutput> {
+        self.output.as_ref()
+    }
+
+    pub fn audio_output_mut(&mut self) -> Option<&mut AudioOutput> {
+        self.output.as_mut()
+    }
+
+    /// Fill `output` with the next samples at the host rate, see [`AudioOutput::read`]
+    pub fn read_audio(&mut self, output: &mut [[i16; 2]]) -> usize {
+        match self.output.as_mut() {
+            Some(audio_output) => audio_output.read(output),
+            None => {
+                output.fill([0, 0]);
+                0
+            }
+        }
+    }
+
+    /// Keep the mixed samples until [`Apu::drain_samples`], `false` drops the kept ones
+    pub(crate) fn keep_samples(&mut self, keep: bool) {
+        if keep != self.sink_samples.is_some() {
+            self.sink_samples = keep.then(Vec::new);
+        }
+    }
+
+    /// Move the samples kept since the last call to `samples`
+    pub(crate) fn drain_samples(&mut self, samples: &mut Vec<[i16; 2]>) {
+        if let Some(kept) = self.sink_samples.as_mut() {
+            samples.append(kept);
+        }
+    }
+
+    /// Record the output to wav files at [`SAMPLE_RATE`] until [`Apu::stop_capture`]: the stereo mix to `path`
+    /// and each of the selected `channels` to `<stem>_ch<n>.wav`.
+    pub fn start_capture(&mut self, path: impl AsRef<Path>, channels: AudioChannels) -> Result<(), std::io::Error> {
+        self.stop_capture()?;
+        self.capture = Some(Capture::create(path.as_ref(), channels)?);
+        Ok(())
+    }
+
+    /// Stop the capture after `samples` more samples
+    pub(crate) fn limit_capture(&mut self, samples: u64) {
+        if let Some(capture) = self.capture.as_mut() {
+            capture.samples_left = Some(samples);
+        }
+    }
+
+    /// Finish the wav files of the current capture, if any
+    pub fn stop_capture(&mut self) -> Result<(), std::io::Error> {
+        match self.capture.take() {
+            Some(capture) => capture.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_capturing(&self) -> bool {
+        self.capture.is_some()
+    }
+
+    /// Log the sound register writes with their timing to a VGM file until [`Apu::stop_sound_log`],
+    /// starting with the current content of the registers.
+    pub fn start_sound_log(&mut self, path: impl AsRef<Path>, bus: &impl ApuBus) -> Result<(), std::io::Error> {
+        self.stop_sound_log()?;
+        self.sound_log = Some(SoundLog::create(path.as_ref(), bus)?);
+        Ok(())
+    }
+
+    /// Finish the VGM file of the current sound log, if any
+    pub fn stop_sound_log(&mut self) -> Result<(), std::io::Error> {
+        match self.sound_log.take() {
+            Some(sound_log) => sound_log.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_sound_logging(&self) -> bool {
+        self.sound_log.is_some()
+    }
+}
+
+impl Savable for Apu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.powered);
+        self.square1.save_state(writer);
+        self.sweep.save_state(writer);
+        self.square2.save_state(writer);
+        self.wave.save_state(writer);
+        self.noise.save_state(writer);
+        writer.write_u32(self.sequencer_counter);
+        writer.write_u8(self.sequencer_step);
+        writer.write_u32(self.sample_counter);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.powered = reader.read_bool()?;
+        self.square1.load_state(reader)?;
+        self.sweep.load_state(reader)?;
+        self.square2.load_state(reader)?;
+        self.wave.load_state(reader)?;
+        self.noise.load_state(reader)?;
+        self.sequencer_counter = reader.read_u32()?;
+        self.sequencer_step = reader.read_u8()?;
+        self.sample_counter = reader.read_u32()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::MemorySystem;
+    use crate::tests::temp::TempDir;
+
+    fn powered_apu(bus: &mut MemorySystem) -> Apu {
+        let mut apu = Apu::default();
+        apu.reset(bus);
+        apu.step(bus, 4);
+        apu
+    }
+
+    fn run(apu: &mut Apu, bus: &mut MemorySystem, cycles: u32) {
+        for _ in 0..cycles / 4 {
+            apu.step(bus, 4);
+        }
+    }
+
+    #[test]
+    fn test_post_boot_registers() {
+        let mut bus = MemorySystem::default();
+        powered_apu(&mut bus);
+
+        assert_eq!(bus.read_byte(0xFF24), 0x77);
+        assert_eq!(bus.read_byte(0xFF25), 0xF3);
+        assert_eq!(bus.read_byte(0xFF26), 0xF1);
+    }
+
+    #[test]
+    fn test_trigger_and_length_expiry() {
+        let mut bus = MemorySystem::default();
+        let mut apu = powered_apu(&mut bus);
+
+        bus.write_byte(0xFF16, 0x3E); // Duty 0, length 64 - 62 = 2
+        bus.write_byte(0xFF17, 0xF0); // Volume 15
+        bus.write_byte(0xFF19, 0xC7); // Trigger with length enabled
+        apu.step(&mut bus, 4);
+        assert_eq!(bus.read_byte(0xFF26) & 0x02, 0x02);
+
+        // Length is clocked at 256 Hz
+        run(&mut apu, &mut bus, 4 * CYCLES_PER_SEQUENCER_STEP);
+        assert_eq!(bus.read_byte(0xFF26) & 0x02, 0x00);
+    }
+
+    #[test]
+    fn test_dac_off_disables_channel() {
+        let mut bus = MemorySystem::default();
+        let mut apu = powered_apu(&mut bus);
+
+        bus.write_byte(0xFF21, 0xF0);
+        bus.write_byte(0xFF23, 0x80);
+        apu.step(&mut bus, 4);
+        assert_eq!(bus.read_byte(0xFF26) & 0x08, 0x08);
+
+        bus.write_byte(0xFF21, 0x00);
+        apu.step(&mut bus, 4);
+        assert_eq!(bus.read_byte(0xFF26) & 0x08, 0x00);
+    }
+
+    #[test]
+    fn test_power_off_clears_registers() {
+        let mut bus = MemorySystem::default();
+        let mut apu = powered_apu(&mut bus);
+
+        bus.write_byte(0xFF26, 0x00);
+        apu.step(&mut bus, 4);
+
+        assert_eq!(bus.read_byte(0xFF26), 0x70);
+        assert_eq!(bus.read_byte(0xFF24), 0x00);
+        bus.write_byte(0xFF12, 0xF0);
+        bus.write_byte(0xFF14, 0x80);
+        apu.step(&mut bus, 4);
+        assert_eq!(bus.read_byte(0xFF26), 0x70);
+    }
+
+    #[test]
+    fn test_square_duty_output() {
+        let mut bus = MemorySystem::default();
+        let mut apu = powered_apu(&mut bus);
+
+        bus.write_byte(0xFF16, 0x80); // Duty 50%
+        bus.write_byte(0xFF17, 0xF0);
+        bus.write_byte(0xFF18, 0x00);
+        bus.write_byte(0xFF19, 0x87); // Frequency $700, 4 * 256 cycles per step
+        apu.step(&mut bus, 0);
+
+        let outputs: Vec<u8> = (0..8)
+            .map(|_| {
+                run(&mut apu, &mut bus, 1024);
+                apu.square2.output()
+            })
+            .collect();
+        // Half of the 8 steps are high, the trigger does not reset the duty position
+        assert_eq!(outputs.iter().filter(|&&output| output == 15).count(), 4);
+        assert_eq!(outputs.iter().filter(|&&output| output == 0).count(), 4);
+    }
+
+    #[test]
+    fn test_capture_writes_wav_files() -> Result<(), std::io::Error> {
+        let dir = TempDir::new("capture")?;
+        let path = dir.join("capture.wav");
+        let mut bus = MemorySystem::default();
+        let mut apu = powered_apu(&mut bus);
+
+        apu.start_capture(&path, AudioChannels::Square2 | AudioChannels::Noise)?;
+        apu.limit_capture(100);
+        run(&mut apu, &mut bus, 200 * CYCLES_PER_SAMPLE);
+        assert!(!apu.is_capturing());
+
+        let mixed = std::fs::read(&path)?;
+        assert_eq!(&mixed[0..4], b"RIFF");
+        assert_eq!(&mixed[8..16], b"WAVEfmt ");
+        assert_eq!(u16::from_le_bytes([mixed[22], mixed[23]]), 2);
+        assert_eq!(u32::from_le_bytes(mixed[24..28].try_into().unwrap()), SAMPLE_RATE);
+        assert_eq!(u32::from_le_bytes(mixed[40..44].try_into().unwrap()), 100 * 4);
+        assert_eq!(mixed.len(), 44 + 100 * 4);
+
+        assert_eq!(std::fs::read(dir.join("capture_ch2.wav"))?.len(), 44 + 100 * 2);
+        assert_eq!(std::fs::read(dir.join("capture_ch4.wav"))?.len(), 44 + 100 * 2);
+        assert!(!dir.join("capture_ch1.wav").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sound_log_vgm() -> Result<(), std::io::Error> {
+        let dir = TempDir::new("sound-log")?;
+        let path = dir.join("sound.vgm");
+        let mut bus = MemorySystem::default();
+        let mut apu = powered_apu(&mut bus);
+
+        apu.start_sound_log(&path, &bus)?;
+        run(&mut apu, &mut bus, 4_194_304 / 100);
+        bus.write_byte(0xFF12, 0xF0);
+        apu.step(&mut bus, 4);
+        apu.stop_sound_log()?;
+
+        let vgm = std::fs::read(&path)?;
+        assert_eq!(&vgm[0x00..0x04], b"Vgm ");
+        assert_eq!(
+            u32::from_le_bytes(vgm[0x04..0x08].try_into().unwrap()) as usize,
+            vgm.len() - 4
+        );
+        assert_eq!(u32::from_le_bytes(vgm[0x18..0x1C].try_into().unwrap()), 441);
+        assert_eq!(u32::from_le_bytes(vgm[0x80..0x84].try_into().unwrap()), 4_194_304);
+
+        // Initial registers (power first, 22 registers and 16 bytes of wave ram),
+        // then the write after 1/100 s and the final wait
+        let commands = &vgm[0x100..];
+        assert_eq!(commands[0..3], [0xB3, 0x16, 0xF1]);
+        let write = &commands[39 * 3..];
+        assert_eq!(write, [0x61, 0xB8, 0x01, 0xB3, 0x02, 0xF0, 0x70, 0x66]);
+        Ok(())
+    }
+}
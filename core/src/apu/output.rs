@@ -0,0 +1,166 @@
+use crate::apu::SAMPLE_RATE;
+use crate::apu::resampler::{Resampler, ResamplerQuality};
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+use std::time::Duration;
+
+/// Native samples resampled at once
+const CHUNK_SIZE: usize = 256;
+
+/// Format of the audio stream read by the frontend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioSettings {
+    /// Host device sample rate
+    pub sample_rate: u32,
+    pub quality: ResamplerQuality,
+    /// Audio buffered ahead of the device, older samples are dropped past it
+    pub latency: Duration,
+}
+
+impl AudioSettings {
+    /// Host sample rates of [`AudioOutput::new`], others are clamped to it. A rate of 0 would never
+    /// produce a sample.
+    pub const SAMPLE_RATE_RANGE: RangeInclusive<u32> = 8_000..=192_000;
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            sample_rate: 48_000,
+            quality: ResamplerQuality::default(),
+            latency: Duration::from_millis(60),
+        }
+    }
+}
+
+/// APU output converted to the host rate, buffered until the frontend reads it.
+pub struct AudioOutput {
+    settings: AudioSettings,
+    resampler: Resampler,
+    /// Native samples waiting for the resampler
+    pending: Vec<[i16; 2]>,
+    buffer: VecDeque<[i16; 2]>,
+    capacity: usize,
+    /// Samples dropped because the buffer was full
+    overruns: u64,
+    /// Samples missing when the frontend read, replaced by silence
+    underruns: u64,
+}
+
+impl AudioOutput {
+    pub fn new(settings: AudioSettings) -> Self {
+        let range = AudioSettings::SAMPLE_RATE_RANGE;
+        let settings = AudioSettings {
+            sample_rate: settings.sample_rate.clamp(*range.start(), *range.end()),
+            ..settings
+        };
+        let capacity = (settings.sample_rate as f64 * settings.latency.as_secs_f64()).ceil() as usize;
+        Self {
+            settings,
+            resampler: Resampler::new(SAMPLE_RATE, settings.sample_rate, settings.quality),
+            pending: Vec::with_capacity(CHUNK_SIZE),
+            buffer: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            overruns: 0,
+            underruns: 0,
+        }
+    }
+
+    pub fn settings(&self) -> AudioSettings {
+        self.settings
+    }
+
+    /// Emulation speed relative to a Game Boy, see [`Resampler::set_speed`]
+    pub fn set_speed(&mut self, speed: f64) {
+        self.resampler.set_speed(speed);
+    }
+
+    pub(crate) fn push(&mut self, sample: [i16; 2]) {
+        self.pending.push(sample);
+        if self.pending.len() < CHUNK_SIZE {
+            return;
+        }
+
+        let mut resampled = Vec::with_capacity(CHUNK_SIZE);
+        self.resampler.process(&self.pending, &mut resampled);
+        self.pending.clear();
+
+        for sample in resampled {
+            if self.buffer.len() == self.capacity {
+                self.buffer.pop_front();
+                self.overruns += 1;
+            }
+            self.buffer.push_back(sample);
+        }
+    }
+
+    /// Fill `output` with the oldest buffered samples, silence when the buffer runs dry.
+    /// Returns the number of samples coming from the buffer.
+    pub fn read(&mut self, output: &mut [[i16; 2]]) -> usize {
+        let available = self.buffer.len().min(output.len());
+        for (sample, buffered) in output.iter_mut().zip(self.buffer.drain(..available)) {
+            *sample = buffered;
+        }
+        output[available..].fill([0, 0]);
+        self.underruns += (output.len() - available) as u64;
+        available
+    }
+
+    /// Duration of the buffered audio
+    pub fn buffered(&self) -> Duration {
+        Duration::from_secs_f64(self.buffer.len() as f64 / self.settings.sample_rate as f64)
+    }
+
+    pub fn overruns(&self) -> u64 {
+        self.overruns
+    }
+
+    pub fn underruns(&self) -> u64 {
+        self.underruns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_is_bounded_by_latency() {
+        let settings = AudioSettings {
+            sample_rate: 32_768,
+            quality: ResamplerQuality::Nearest,
+            latency: Duration::from_millis(125),
+        };
+        let mut output = AudioOutput::new(settings);
+
+        // One second of audio, the buffer keeps 125 ms (4096 samples)
+        for _ in 0..SAMPLE_RATE {
+            output.push([100, 100]);
+        }
+        assert_eq!(output.buffered(), Duration::from_millis(125));
+        assert!(output.overruns() > 0);
+
+        let mut samples = vec![[1, 1]; 5000];
+        assert_eq!(output.read(&mut samples), 4096);
+        assert_eq!(samples[4095], [100, 100]);
+        assert_eq!(samples[4096], [0, 0]);
+        assert_eq!(output.underruns(), 5000 - 4096);
+    }
+
+    #[test]
+    fn test_sample_rate_is_clamped() {
+        for (sample_rate, expected) in [(0, 8_000), (1_000_000, 192_000)] {
+            let mut output = AudioOutput::new(AudioSettings {
+                sample_rate,
+                ..AudioSettings::default()
+            });
+            assert_eq!(output.settings().sample_rate, expected);
+
+            for _ in 0..SAMPLE_RATE / 10 {
+                output.push([100, 100]);
+            }
+            let mut samples = vec![[0, 0]; 64];
+            assert_eq!(output.read(&mut samples), 64);
+        }
+    }
+}
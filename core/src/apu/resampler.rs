@@ -0,0 +1,219 @@
+use std::f64::consts::PI;
+
+/// Input samples on each side of the interpolated position used by [`ResamplerQuality::Sinc`]
+const SINC_HALF_TAPS: usize = 8;
+/// Range of [`Resampler::set_speed`], a speed of 0 would never consume the input
+const SPEED_RANGE: std::ops::RangeInclusive<f64> = 0.1..=16.0;
+
+/// Interpolation used by the [`Resampler`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ResamplerQuality {
+    /// Closest input sample, aliasing but the cheapest
+    Nearest,
+    /// Straight line between the two closest input samples
+    #[default]
+    Linear,
+    /// Hann windowed sinc, low-pass filtered when downsampling
+    Sinc,
+}
+
+impl ResamplerQuality {
+    pub const ALL: [ResamplerQuality; 3] = [
+        ResamplerQuality::Nearest,
+        ResamplerQuality::Linear,
+        ResamplerQuality::Sinc,
+    ];
+}
+
+impl std::fmt::Display for ResamplerQuality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResamplerQuality::Nearest => write!(f, "Nearest"),
+            ResamplerQuality::Linear => write!(f, "Linear"),
+            ResamplerQuality::Sinc => write!(f, "Sinc"),
+        }
+    }
+}
+
+impl std::str::FromStr for ResamplerQuality {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ResamplerQuality::ALL
+            .into_iter()
+            .find(|quality| quality.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("unknown resampler quality: {s}"),
+                )
+            })
+    }
+}
+
+/// Stereo sample rate converter, e.g. from the APU [`SAMPLE_RATE`](crate::SAMPLE_RATE) to the host device rate.
+///
+/// The input is streamed in chunks of any size, an output sample is produced once the [`SINC_HALF_TAPS`]
+/// following input samples are received, whatever the quality, so switching quality does not shift the stream.
+pub struct Resampler {
+    quality: ResamplerQuality,
+    /// Input samples per output sample at normal speed
+    rate_step: f64,
+    /// Input samples per output sample
+    step: f64,
+    /// Low-pass cutoff of the sinc kernel, relative to the input rate
+    cutoff: f64,
+    /// Position of the next output sample in `history`
+    position: f64,
+    history: Vec<[f64; 2]>,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32, quality: ResamplerQuality) -> Self {
+        let rate_step = input_rate as f64 / output_rate as f64;
+        Self {
+            quality,
+            rate_step,
+            step: rate_step,
+            cutoff: (output_rate as f64 / input_rate as f64).min(1.0),
+            position: SINC_HALF_TAPS as f64,
+            history: vec![[0.0; 2]; SINC_HALF_TAPS],
+        }
+    }
+
+    pub fn quality(&self) -> ResamplerQuality {
+        self.quality
+    }
+
+    /// Consume the input `speed` times as fast, for an emulation running faster or slower than a Game Boy,
+    /// see [`FramePacer`](crate::FramePacer). The pitch follows: 1.0046 (60 Hz for 59.73 Hz) is 8 cents.
+    /// The speed is clamped to 0.1..=16, NaN is the normal speed.
+    pub fn set_speed(&mut self, speed: f64) {
+        let speed = match speed.is_nan() {
+            true => 1.0,
+            false => speed.clamp(*SPEED_RANGE.start(), *SPEED_RANGE.end()),
+        };
+        self.step = self.rate_step * speed;
+        self.cutoff = (1.0 / self.step).min(1.0);
+    }
+
+    /// Append the samples available once `input` is received to `output`
+    pub fn process(&mut self, input: &[[i16; 2]], output: &mut Vec<[i16; 2]>) {
+        self.history
+            .extend(input.iter().map(|&[left, right]| [left as f64, right as f64]));
+
+        while (self.position as usize) + SINC_HALF_TAPS < self.history.len() {
+            let sample = match self.quality {
+                ResamplerQuality::Nearest => self.history[self.position.round() as usize],
+                ResamplerQuality::Linear => self.linear(),
+                ResamplerQuality::Sinc => self.sinc(),
+            };
+            output.push(sample.map(|value| value.round().clamp(i16::MIN as f64, i16::MAX as f64) as i16));
+            self.position += self.step;
+        }
+
+        let consumed = (self.position as usize).saturating_sub(SINC_HALF_TAPS);
+        self.history.drain(..consumed);
+        self.position -= consumed as f64;
+    }
+
+    fn linear(&self) -> [f64; 2] {
+        let index = self.position as usize;
+        let fraction = self.position.fract();
+        let (a, b) = (self.history[index], self.history[index + 1]);
+        [0, 1].map(|side| a[side] + (b[side] - a[side]) * fraction)
+    }
+
+    fn sinc(&self) -> [f64; 2] {
+        let index = self.position as usize;
+        let mut sample = [0.0; 2];
+        for i in index + 1 - SINC_HALF_TAPS..=index + SINC_HALF_TAPS {
+            let distance = self.position - i as f64;
+            let window = 0.5 * (1.0 + (PI * distance / SINC_HALF_TAPS as f64).cos());
+            let x = PI * distance * self.cutoff;
+            let sinc = if x == 0.0 { 1.0 } else { x.sin() / x };
+            let weight = self.cutoff * sinc * window;
+            sample[0] += self.history[i][0] * weight;
+            sample[1] += self.history[i][1] * weight;
+        }
+        sample
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resample(quality: ResamplerQuality, input_rate: u32, output_rate: u32, input: &[[i16; 2]]) -> Vec<[i16; 2]> {
+        let mut resampler = Resampler::new(input_rate, output_rate, quality);
+        let mut output = vec![];
+        // Streamed in small chunks
+        for chunk in input.chunks(7) {
+            resampler.process(chunk, &mut output);
+        }
+        output
+    }
+
+    #[test]
+    fn test_output_count_follows_ratio() {
+        let input = vec![[1000, -1000]; 65_536];
+        for quality in ResamplerQuality::ALL {
+            let output = resample(quality, 65_536, 48_000, &input);
+            assert!((47_990..=48_000).contains(&output.len()), "{quality}: {}", output.len());
+        }
+    }
+
+    #[test]
+    fn test_constant_signal_is_kept() {
+        let input = vec![[1000, -1000]; 4096];
+        for quality in ResamplerQuality::ALL {
+            let output = resample(quality, 65_536, 44_100, &input);
+            // After the initial delay filled with silence
+            for sample in &output[100..] {
+                assert!((sample[0] - 1000).abs() <= 10, "{quality}: {sample:?}");
+                assert!((sample[1] + 1000).abs() <= 10, "{quality}: {sample:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_linear_upsampling_interpolates() {
+        let input: Vec<[i16; 2]> = (0..32).map(|i| [i * 100, 0]).collect();
+        let output = resample(ResamplerQuality::Linear, 1, 2, &input);
+
+        // Two output samples per input sample, the last 8 inputs are kept for the lookahead
+        assert_eq!(output.len(), 2 * (32 - SINC_HALF_TAPS));
+        let values: Vec<i16> = output[0..6].iter().map(|sample| sample[0]).collect();
+        assert_eq!(values, [0, 50, 100, 150, 200, 250]);
+    }
+
+    #[test]
+    fn test_speed() {
+        let input = vec![[1000, -1000]; 65_536];
+        let mut resampler = Resampler::new(65_536, 48_000, ResamplerQuality::Linear);
+        resampler.set_speed(60.0 / 59.7275);
+        let mut output = vec![];
+        resampler.process(&input, &mut output);
+
+        // 0.46% less output for the same input
+        assert!((47_770..=47_790).contains(&output.len()), "{}", output.len());
+    }
+
+    #[test]
+    fn test_invalid_speed() {
+        let input = vec![[1000, -1000]; 1024];
+        for speed in [0.0, -1.0, f64::NAN] {
+            let mut resampler = Resampler::new(1024, 1024, ResamplerQuality::Linear);
+            resampler.set_speed(speed);
+            let mut output = vec![];
+            resampler.process(&input, &mut output);
+            assert!(output.len() <= 10 * 1024, "{speed}: {}", output.len());
+        }
+    }
+
+    #[test]
+    fn test_parse_quality() {
+        assert_eq!("sinc".parse::<ResamplerQuality>().ok(), Some(ResamplerQuality::Sinc));
+        assert!("cubic".parse::<ResamplerQuality>().is_err());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::apu::SAMPLE_RATE;
+use crate::apu::capture::WavWriter;
+use crate::apu::output::{AudioOutput, AudioSettings};
+use std::io::Error;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Mixed samples of one frame handed to the [`AudioSink`]s, at [`SAMPLE_RATE`]
+pub struct AudioChunk<'a> {
+    /// Samples output before this chunk since the last reset
+    pub timestamp: u64,
+    /// Frame completed with this chunk, see [`Machine::frame_count`](crate::Machine::frame_count)
+    pub frame: u64,
+    pub samples: &'a [[i16; 2]],
+}
+
+impl AudioChunk<'_> {
+    /// Emulated time of the first sample
+    pub fn time(&self) -> Duration {
+        Duration::from_secs_f64(self.timestamp as f64 / SAMPLE_RATE as f64)
+    }
+}
+
+/// Receiver of the sound output by the machine, added with
+/// [`Machine::add_audio_sink`](crate::Machine::add_audio_sink).
+pub trait AudioSink: Send {
+    /// Called at the end of each frame with its samples, an error removes the sink
+    fn on_samples(&mut self, chunk: &AudioChunk) -> Result<(), Error>;
+
+    /// No more samples are wanted, the sink is finished and removed
+    fn is_done(&self) -> bool {
+        false
+    }
+
+    /// Complete the output, called once when the sink is removed
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Sink dropping every sample
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullAudioSink;
+
+impl AudioSink for NullAudioSink {
+    fn on_samples(&mut self, _chunk: &AudioChunk) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Sink writing the stereo mix to a 16-bit wav file at [`SAMPLE_RATE`]
+pub struct WavSink {
+    writer: Option<WavWriter>,
+}
+
+impl WavSink {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self {
+            writer: Some(WavWriter::create(path.as_ref(), 2)?),
+        })
+    }
+}
+
+impl AudioSink for WavSink {
+    fn on_samples(&mut self, chunk: &AudioChunk) -> Result<(), Error> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Ok(());
+        };
+        chunk.samples.iter().try_for_each(|sample| writer.write(sample))
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.writer.take().map_or(Ok(()), WavWriter::finish)
+    }
+}
+
+/// Sink resampling the sound for a host audio stream, e.g. the callback of a cpal output stream
+/// reads it through the [`AudioStream`] from its own thread.
+pub struct StreamSink {
+    output: Arc<Mutex<AudioOutput>>,
+}
+
+/// Reading end of a [`StreamSink`]
+#[derive(Clone)]
+pub struct AudioStream {
+    output: Arc<Mutex<AudioOutput>>,
+}
+
+impl StreamSink {
+    pub fn new(settings: AudioSettings) -> (Self, AudioStream) {
+        let output = Arc::new(Mutex::new(AudioOutput::new(settings)));
+        let stream = AudioStream {
+            output: Arc::clone(&output),
+        };
+        (Self { output }, stream)
+    }
+}
+
+impl AudioSink for StreamSink {
+    fn on_samples(&mut self, chunk: &AudioChunk) -> Result<(), Error> {
+        let mut output = self.output.lock().map_err(|_| Error::other("audio stream poisoned"))?;
+        chunk.samples.iter().for_each(|&sample| output.push(sample));
+        Ok(())
+    }
+
+    /// The stream was dropped
+    fn is_done(&self) -> bool {
+        Arc::strong_count(&self.output) == 1
+    }
+}
+
+impl AudioStream {
+    /// Fill `output` with the next samples at the host rate, see [`AudioOutput::read`]
+    pub fn read(&self, output: &mut [[i16; 2]]) -> usize {
+        match self.output.lock() {
+            Ok(mut audio_output) => audio_output.read(output),
+            Err(_) => {
+                output.fill([0, 0]);
+                0
+            }
+        }
+    }
+
+    /// Duration of the audio buffered ahead of the host
+    pub fn buffered(&self) -> Duration {
+        self.output.lock().map_or(Duration::ZERO, |output| output.buffered())
+    }
+
+    pub fn settings(&self) -> AudioSettings {
+        self.output
+            .lock()
+            .map_or(AudioSettings::default(), |output| output.settings())
+    }
+
+    /// Samples dropped and missing so far, see [`AudioOutput::overruns`] and [`AudioOutput::underruns`]
+    pub fn xruns(&self) -> (u64, u64) {
+        self.output
+            .lock()
+            .map_or((0, 0), |output| (output.overruns(), output.underruns()))
+    }
+
+    /// Emulation speed relative to a Game Boy, see [`AudioOutput::set_speed`]
+    pub fn set_speed(&self, speed: f64) {
+        if let Ok(mut output) = self.output.lock() {
+            output.set_speed(speed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apu::ResamplerQuality;
+
+    #[test]
+    fn test_stream_sink() -> Result<(), Error> {
+        let settings = AudioSettings {
+            sample_rate: SAMPLE_RATE / 2,
+            quality: ResamplerQuality::Nearest,
+            latency: Duration::from_millis(100),
+        };
+        let (mut sink, stream) = StreamSink::new(settings);
+        let samples = vec![[500, -500]; 4096];
+        sink.on_samples(&AudioChunk {
+            timestamp: 0,
+            frame: 1,
+            samples: &samples,
+        })?;
+
+        // Half the rate, minus the resampler lookahead
+        let mut output = vec![[0, 0]; 4096];
+        let read = stream.read(&mut output);
+        assert!((2000..=2048).contains(&read), "{read}");
+        assert_eq!(output[read - 1], [500, -500]);
+
+        assert_eq!(stream.settings(), settings);
+        assert_eq!(stream.xruns(), (0, 4096 - read as u64));
+
+        assert!(!sink.is_done());
+        drop(stream);
+        assert!(sink.is_done());
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+use crate::apu::apu_bus::ApuBus;
+use std::fs::File;
+use std::io::{BufWriter, Error, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Sample rate of the VGM wait commands
+const VGM_SAMPLE_RATE: u64 = 44_100;
+const VGM_HEADER_SIZE: u32 = 0x100;
+const CPU_CLOCK: u32 = 4_194_304;
+
+/// Sound register writes recorded as a VGM 1.61 stream (Game Boy DMG chip), playable by VGM players.
+///
+/// The registers are written at the start of the log so the replay starts from the current sound state.
+pub(crate) struct SoundLog {
+    writer: BufWriter<File>,
+    /// Cycles since the start of the log
+    cycles: u64,
+    /// Samples already waited in the stream
+    samples: u64,
+}
+
+impl SoundLog {
+    pub(crate) fn create(path: &Path, bus: &impl ApuBus) -> Result<Self, Error> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&[0; VGM_HEADER_SIZE as usize])?;
+
+        let mut log = Self {
+            writer,
+            cycles: 0,
+            samples: 0,
+        };
+        // Power first, the other registers are ignored while the APU is off
+        let registers = std::iter::once(0xFF26).chain(0xFF10..0xFF26).chain(0xFF30..=0xFF3F);
+        for address in registers {
+            log.write_register(address, bus.read_internal_byte(address))?;
+        }
+        Ok(log)
+    }
+
+    pub(crate) fn write_register(&mut self, address: u16, value: u8) -> Result<(), Error> {
+        self.write_wait()?;
+        self.writer.write_all(&[0xB3, (address - 0xFF10) as u8, value])
+    }
+
+    pub(crate) fn advance(&mut self, cycles: u8) {
+        self.cycles += cycles as u64;
+    }
+
+    /// Wait commands up to the current time
+    fn write_wait(&mut self) -> Result<(), Error> {
+        let target = self.cycles * VGM_SAMPLE_RATE / CPU_CLOCK as u64;
+        let mut wait = target - self.samples;
+        self.samples = target;
+
+        while wait > 0 {
+            let (command, samples): (&[u8], u64) = match wait {
+                735 => (&[0x62], 735),
+                882 => (&[0x63], 882),
+                1..=16 => (&[0x70 + (wait - 1) as u8], wait),
+                _ => {
+                    let samples = wait.min(u16::MAX as u64);
+                    self.writer.write_all(&[0x61])?;
+                    (&(samples as u16).to_le_bytes(), samples)
+                }
+            };
+            self.writer.write_all(command)?;
+            wait -= samples;
+        }
+        Ok(())
+    }
+
+    /// Write the end of the stream and the header
+    pub(crate) fn finish(mut self) -> Result<(), Error> {
+        self.write_wait()?;
+        self.writer.write_all(&[0x66])?;
+        let size = self.writer.stream_position()? as u32;
+
+        let mut header = [0u8; VGM_HEADER_SIZE as usize];
+        header[0x00..0x04].copy_from_slice(b"Vgm ");
+        header[0x04..0x08].copy_from_slice(&(size - 4).to_le_bytes());
+        header[0x08..0x0C].copy_from_slice(&0x161u32.to_le_bytes());
+        header[0x18..0x1C].copy_from_slice(&(self.samples as u32).to_le_bytes());
+        header[0x34..0x38].copy_from_slice(&(VGM_HEADER_SIZE - 0x34).to_le_bytes());
+        header[0x80..0x84].copy_from_slice(&CPU_CLOCK.to_le_bytes());
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&header)?;
+        self.writer.flush()
+    }
+}
@@ -0,0 +1,87 @@
+use std::fmt;
+
+/// Version and compile time options of the core, to paste in bug reports, see [`build_info`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// Version of the `gbemu-core` package
+    pub version: &'static str,
+    /// Short hash of the git commit, `None` when built outside a git checkout
+    pub git_hash: Option<&'static str>,
+    /// `rustc --version` of the compiler, `None` when it could not be run
+    pub rustc: Option<&'static str>,
+    /// `debug` or `release`
+    pub profile: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    /// Cargo features enabled on the core
+    pub features: Vec<&'static str>,
+}
+
+/// Build of the core, read from the package and the git checkout at compile time
+pub fn build_info() -> BuildInfo {
+    let features = [
+        ("profiling", cfg!(feature = "profiling")),
+        ("trace", cfg!(feature = "trace")),
+        ("serde-state", cfg!(feature = "serde-state")),
+        ("accuracy-extras", cfg!(feature = "accuracy-extras")),
+        ("test-bus", cfg!(feature = "test-bus")),
+        ("test-roms", cfg!(feature = "test-roms")),
+    ];
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: Some(env!("GBEMU_GIT_HASH")).filter(|hash| !hash.is_empty()),
+        rustc: Some(env!("GBEMU_RUSTC_VERSION")).filter(|rustc| !rustc.is_empty()),
+        profile: if cfg!(debug_assertions) { "debug" } else { "release" },
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        features: features
+            .into_iter()
+            .filter_map(|(name, enabled)| enabled.then_some(name))
+            .collect(),
+    }
+}
+
+/// One line: `gbemu-core 0.1.0 (1a2b3c4d5e, release, linux x86_64, rustc 1.90.0) features: trace`
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "gbemu-core {} (", self.version)?;
+        if let Some(git_hash) = self.git_hash {
+            write!(f, "{git_hash}, ")?;
+        }
+        write!(f, "{}, {} {}", self.profile, self.os, self.arch)?;
+        if let Some(rustc) = self.rustc {
+            write!(f, ", {rustc}")?;
+        }
+        match self.features.is_empty() {
+            true => write!(f, ") features: none"),
+            false => write!(f, ") features: {}", self.features.join(", ")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_info() {
+        let info = build_info();
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.features.contains(&"profiling"), cfg!(feature = "profiling"));
+
+        let line = BuildInfo {
+            version: "0.1.0",
+            git_hash: Some("1a2b3c4d5e"),
+            rustc: None,
+            profile: "release",
+            os: "linux",
+            arch: "x86_64",
+            features: vec!["profiling", "trace"],
+        }
+        .to_string();
+        assert_eq!(
+            line,
+            "gbemu-core 0.1.0 (1a2b3c4d5e, release, linux x86_64) features: profiling, trace"
+        );
+    }
+}
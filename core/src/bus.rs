@@ -1,5 +1,14 @@
+use crate::accuracy::AccuracyProfile;
 use crate::cpu::CpuBus;
-use crate::ppu::PpuBus;
+#[cfg(feature = "profiling")]
+use crate::debug::profile::AccessCounters;
+use crate::debug::protection::MemoryProtection;
+use crate::interceptor::Interceptors;
+use crate::model::Model;
+use crate::ppu::{ChangedTiles, PpuBus, TILE_COUNT};
+use crate::ram_init::RamInit;
+use crate::rng::Rng;
+use crate::state::{Savable, StateReader, StateWriter};
 use bitflags::bitflags;
 use log::{debug, error};
 use std::default::Default;
@@ -8,6 +17,7 @@ use std::io::Read;
 use std::path::Path;
 
 bitflags! {
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
     pub struct Interrupt: u8 {
         const VBLANK = 0b0000_0001;  // Bit 0
         const LCD_STAT = 0b0000_0010; // Bit 1
@@ -17,6 +27,51 @@ bitflags! {
     }
 }
 
+impl Interrupt {
+    /// Interrupt sources, highest priority first
+    pub const SOURCES: [Interrupt; 5] = [
+        Interrupt::VBLANK,
+        Interrupt::LCD_STAT,
+        Interrupt::TIMER,
+        Interrupt::SERIAL,
+        Interrupt::JOYPAD,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Interrupt::VBLANK => "VBLANK",
+            Interrupt::LCD_STAT => "STAT",
+            Interrupt::TIMER => "TIMER",
+            Interrupt::SERIAL => "SERIAL",
+            Interrupt::JOYPAD => "JOYPAD",
+            _ => "MULTIPLE",
+        }
+    }
+}
+
+/// Interrupt enable (IE) and interrupt flag (IF) registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-state", derive(serde::Serialize, serde::Deserialize))]
+pub struct InterruptState {
+    pub enable: u8,
+    pub flag: u8,
+}
+
+impl InterruptState {
+    pub fn is_enabled(&self, interrupt: Interrupt) -> bool {
+        self.enable & interrupt.bits() == interrupt.bits()
+    }
+
+    pub fn is_requested(&self, interrupt: Interrupt) -> bool {
+        self.flag & interrupt.bits() == interrupt.bits()
+    }
+
+    /// `(source, enabled, requested)` of each source, highest priority first
+    pub fn sources(&self) -> [(Interrupt, bool, bool); 5] {
+        Interrupt::SOURCES.map(|source| (source, self.is_enabled(source), self.is_requested(source)))
+    }
+}
+
 macro_rules! define_flags_accessors {
     ($name:ident, $addr:expr, $type:ty) => {
         fn $name(&self) -> $type {
@@ -79,35 +134,274 @@ macro_rules! define_palette_accessors {
         }
     };
 }
+use crate::apu::apu_bus::ApuBus;
 use crate::cartridge::Cartridge;
 use crate::joypad::joypad_bus::JoypadBus;
+use crate::serial::serial_bus::SerialBus;
 use crate::timer::timer_bus::TimerBus;
 pub(crate) use define_palette_accessors;
 
+/// Bits of the IO registers ($FF00..$FF7F) always reading 1 on DMG: unused and write-only bits,
+/// unmapped registers read $FF.
+#[rustfmt::skip]
+const IO_READ_MASKS: [u8; 0x80] = [
+    // P1    SB    SC    --    DIV   TIMA  TMA   TAC   --    --    --    --    --    --    --    IF
+    0xC0, 0x00, 0x7E, 0xFF, 0x00, 0x00, 0x00, 0xF8, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xE0,
+    // NR10  NR11  NR12  NR13  NR14  --    NR21  NR22  NR23  NR24  NR30  NR31  NR32  NR33  NR34  --
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, 0xFF, 0x3F, 0x00, 0xFF, 0xBF, 0x7F, 0xFF, 0x9F, 0xFF, 0xBF, 0xFF,
+    // NR41  NR42  NR43  NR44  NR50  NR51  NR52  --    --    --    --    --    --    --    --    --
+    0xFF, 0x00, 0x00, 0xBF, 0x00, 0x00, 0x70, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    // Wave pattern ram
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    // LCDC  STAT  SCY   SCX   LY    LYC   DMA   BGP   OBP0  OBP1  WY    WX    --    --    --    --
+    0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF,
+    // $FF50 boot rom disable and the CGB registers
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+/// SC on CGB, the clock speed bit can be read back
+const CGB_SC_READ_MASK: u8 = 0x7C;
+
 pub struct MemorySystem {
     boot_rom: [u8; 0x100],
     boot_rom_enabled: bool,
     boot_rom_loaded: bool,
 
     vram: [u8; 0x2_000],
+    /// CGB VRAM bank 1, selected by VBK: tile data and the attributes of the tile maps
+    vram1: [u8; 0x2_000],
+    /// Tiles written with new data since the last [`MemorySystem::take_changed_tiles`]
+    changed_tiles: ChangedTiles,
     wram0: [u8; 0x1_000],
     wram1: [u8; 0x1_000],
+    /// CGB WRAM banks 2-7 at $D000, selected by SVBK
+    wram_banks: [u8; 0x6_000],
+    /// CGB color palettes written through BCPD and OCPD, 8 palettes of 4 RGB555 colors each
+    bg_palettes: [u8; 0x40],
+    obj_palettes: [u8; 0x40],
+    /// CGB registers and memory banks enabled, a CGB running a CGB cartridge
+    cgb_mode: bool,
     oam: [u8; 0x100],
     io_regs: [u8; 0x80],
     hram: [u8; 0xFF],
     interrupts: u8,
     cartridge: Cartridge,
+    div_written: bool,
+    tima_written: bool,
+    sc_written: bool,
+    /// Sound register writes not yet seen by the APU
+    apu_writes: Vec<(u16, u8)>,
+    ram_init: RamInit,
+    protection: MemoryProtection,
+    interceptors: Interceptors,
+    accuracy: AccuracyProfile,
+    model: Model,
+    /// Running OAM DMA with its source and the bytes already copied, see [`AccuracyProfile::dma_timing`]
+    dma: Option<(u16, u8)>,
+    /// Cycles since the last byte copied by the DMA
+    dma_clock: u8,
+    #[cfg(feature = "profiling")]
+    access_counters: AccessCounters,
 }
 
 impl MemorySystem {
+    /// Reset, the work ram and high ram keep their content until [`MemorySystem::init_ram`]
     pub fn reset(&mut self) {
         // Clear VRAM
         self.vram.fill(0);
+        self.changed_tiles = ChangedTiles::all();
+        self.vram1.fill(0);
+        // White, as left by the CGB boot rom
+        self.bg_palettes.fill(0xFF);
+        self.obj_palettes.fill(0xFF);
+        // Normal speed, VRAM bank 0 and WRAM bank 1 mapped: KEY1, VBK, BCPS, OCPS and SVBK
+        for index in [0x4D, 0x4F, 0x68, 0x6A, 0x70] {
+            self.io_regs[index] = 0;
+        }
         self.boot_rom_enabled = self.boot_rom_loaded;
+        self.dma = None;
+        self.dma_clock = 0;
+    }
+    /// Power on content of the work ram and high ram, a random ram init draws from `rng`
+    pub(crate) fn init_ram(&mut self, rng: &mut Rng) {
+        self.ram_init.fill(&mut self.wram0, rng);
+        self.ram_init.fill(&mut self.wram1, rng);
+        self.ram_init.fill(&mut self.hram, rng);
+        if self.cgb_mode {
+            self.ram_init.fill(&mut self.wram_banks, rng);
+        }
+    }
+    /// Unmap the boot rom, as the write to $FF50 at its end does
+    pub(crate) fn disable_boot_rom(&mut self) {
+        self.boot_rom_enabled = false;
+    }
+    pub(crate) fn ram_init(&self) -> RamInit {
+        self.ram_init
+    }
+    /// Pattern applied to the work ram and high ram on the next power on
+    pub(crate) fn set_ram_init(&mut self, ram_init: RamInit) {
+        self.ram_init = ram_init;
+    }
+    pub(crate) fn set_model(&mut self, model: Model) {
+        self.model = model;
+    }
+    pub(crate) fn set_cgb_mode(&mut self, cgb_mode: bool) {
+        self.cgb_mode = cgb_mode;
+    }
+    pub(crate) fn is_cgb_mode(&self) -> bool {
+        self.cgb_mode
+    }
+    /// KEY1 bit 7, the CPU, timer, serial and OAM DMA run twice as fast
+    pub(crate) fn is_double_speed(&self) -> bool {
+        self.cgb_mode && self.io_regs[0x4D] & 0x80 != 0
+    }
+    /// Switch the speed if KEY1 bit 0 requested it, as a `STOP` does
+    pub(crate) fn take_speed_switch(&mut self) -> bool {
+        let key1 = self.io_regs[0x4D];
+        if !self.cgb_mode || key1 & 0x01 == 0 {
+            return false;
+        }
+        self.io_regs[0x4D] = (key1 ^ 0x80) & 0x80;
+        true
+    }
+    /// Only the VRAM locking and DMA timing options apply to the bus
+    pub(crate) fn set_accuracy(&mut self, accuracy: AccuracyProfile) {
+        self.accuracy = accuracy;
+        if !self.dma_timing() {
+            self.finish_dma();
+        }
+    }
+    fn dma_timing(&self) -> bool {
+        cfg!(feature = "accuracy-extras") && self.accuracy.dma_timing
+    }
+    /// Copy one byte every M-cycle of a running OAM DMA
+    pub(crate) fn step_dma(&mut self, cycles: u8) {
+        let Some((source, copied)) = self.dma else {
+            return;
+        };
+        let cycles = self.dma_clock + cycles;
+        let end = (copied + cycles / 4).min(0xA0);
+        self.copy_dma(source, copied..end);
+        self.dma_clock = cycles % 4;
+        self.dma = (end < 0xA0).then_some((source, end));
+    }
+    /// Copy what is left of a running OAM DMA at once
+    fn finish_dma(&mut self) {
+        if let Some((source, copied)) = self.dma.take() {
+            self.copy_dma(source, copied..0xA0);
+        }
+    }
+    /// VRAM from $8000, whatever the locking
+    pub fn vram(&self) -> &[u8] {
+        &self.vram
+    }
+    /// CGB VRAM bank 1 from $8000, whatever VBK
+    pub fn vram_bank1(&self) -> &[u8] {
+        &self.vram1
+    }
+    /// Tiles whose data changed since the last call, every tile after a reset or a state load
+    pub(crate) fn take_changed_tiles(&mut self) -> ChangedTiles {
+        std::mem::take(&mut self.changed_tiles)
+    }
+    /// VRAM bank selected by VBK, always bank 0 on DMG
+    fn vram_bank1_selected(&self) -> bool {
+        self.cgb_mode && self.io_regs[0x4F] & 0x01 != 0
+    }
+    /// WRAM bank mapped at $D000 by SVBK, bank 0 selects bank 1
+    fn wram_bank(&self) -> &[u8] {
+        match self.cgb_mode {
+            true => match (self.io_regs[0x70] & 0x07) as usize {
+                0 | 1 => &self.wram1,
+                bank => &self.wram_banks[(bank - 2) * 0x1000..(bank - 1) * 0x1000],
+            },
+            false => &self.wram1,
+        }
+    }
+    fn wram_bank_mut(&mut self) -> &mut [u8] {
+        match self.cgb_mode {
+            true => match (self.io_regs[0x70] & 0x07) as usize {
+                0 | 1 => &mut self.wram1,
+                bank => &mut self.wram_banks[(bank - 2) * 0x1000..(bank - 1) * 0x1000],
+            },
+            false => &mut self.wram1,
+        }
+    }
+    /// BCPD or OCPD write, at the index of BCPS or OCPS which is incremented when its bit 7 is set
+    fn write_palette(&mut self, address: u16, byte: u8) {
+        let index_register = address as usize - 0xFF01;
+        let index = self.io_regs[index_register];
+        let palettes = match address {
+            0xFF69 => &mut self.bg_palettes,
+            _ => &mut self.obj_palettes,
+        };
+        palettes[(index & 0x3F) as usize] = byte;
+        if index & 0x80 != 0 {
+            self.io_regs[index_register] = 0x80 | ((index & 0x3F) + 1) & 0x3F;
+        }
+    }
+    fn write_vram(&mut self, offset: usize, byte: u8) {
+        let (vram, first_tile) = match self.vram_bank1_selected() {
+            true => (&mut self.vram1, TILE_COUNT),
+            false => (&mut self.vram, 0),
+        };
+        // Tile data ends at $97FF, the tile maps follow
+        if offset < 0x1800 && vram[offset] != byte {
+            self.changed_tiles.insert(first_tile + offset as u16 / 16);
+        }
+        vram[offset] = byte;
+    }
+    /// OAM from $FE00, whatever the locking or a running DMA
+    pub fn oam(&self) -> &[u8] {
+        &self.oam[..0xA0]
+    }
+    fn copy_dma(&mut self, source: u16, offsets: std::ops::Range<u8>) {
+        for offset in offsets {
+            self.oam[offset as usize] = self.read_byte(source + offset as u16);
+        }
+    }
+    /// The CPU cannot reach this address: VRAM during pixel transfer and OAM during OAM scan, pixel transfer
+    /// or a DMA
+    fn cpu_blocked(&self, address: u16) -> bool {
+        let locked =
+            || cfg!(feature = "accuracy-extras") && self.accuracy.vram_locking && self.io_regs[0x40] & 0x80 != 0;
+        let mode = self.io_regs[0x41] & 0x03;
+        match address {
+            0x8000..=0x9FFF => locked() && mode == 3,
+            0xFE00..=0xFE9F => self.dma.is_some() || (locked() && mode >= 2),
+            _ => false,
+        }
+    }
+    pub(crate) fn protection(&self) -> &MemoryProtection {
+        &self.protection
+    }
+    pub(crate) fn protection_mut(&mut self) -> &mut MemoryProtection {
+        &mut self.protection
+    }
+    /// Write the frozen values back, bypassing the protection
+    pub(crate) fn restore_frozen(&mut self) {
+        let frozen: Vec<(u16, u8)> = self.protection.frozen().collect();
+        for (address, value) in frozen {
+            self.write_internal_byte(address, value);
+        }
+    }
+    pub(crate) fn interceptors_mut(&mut self) -> &mut Interceptors {
+        &mut self.interceptors
     }
     pub(crate) fn cartridge(&self) -> &Cartridge {
         &self.cartridge
     }
+    pub(crate) fn cartridge_mut(&mut self) -> &mut Cartridge {
+        &mut self.cartridge
+    }
+    #[cfg(feature = "profiling")]
+    pub(crate) fn access_counters(&self) -> &AccessCounters {
+        &self.access_counters
+    }
+    #[cfg(feature = "profiling")]
+    pub(crate) fn reset_access_counters(&mut self) {
+        self.access_counters = AccessCounters::default();
+    }
 }
 
 impl Default for MemorySystem {
@@ -116,14 +410,33 @@ impl Default for MemorySystem {
             boot_rom_enabled: false,
             boot_rom_loaded: false,
             boot_rom: [0; 0x100],
-            vram: [0; 0x2_000],  // $8000..$9FFF
+            vram: [0; 0x2_000], // $8000..$9FFF
+            vram1: [0; 0x2_000],
             wram0: [0; 0x1_000], // $C000..$CFFF
             wram1: [0; 0x1_000], // $D000..$DFFF
-            oam: [0; 0x100],     // $FE00..$FE9F
-            io_regs: [0; 0x80],  // $FF00..$FF7F
-            hram: [0; 0xFF],     // $FF80..$FFFE
-            interrupts: 0u8,     // $FFFF
+            wram_banks: [0; 0x6_000],
+            bg_palettes: [0xFF; 0x40],
+            obj_palettes: [0xFF; 0x40],
+            cgb_mode: false,
+            oam: [0; 0x100],    // $FE00..$FE9F
+            io_regs: [0; 0x80], // $FF00..$FF7F
+            hram: [0; 0xFF],    // $FF80..$FFFE
+            interrupts: 0u8,    // $FFFF
+            changed_tiles: ChangedTiles::all(),
             cartridge: Cartridge::empty(),
+            div_written: false,
+            tima_written: false,
+            sc_written: false,
+            apu_writes: Vec::new(),
+            ram_init: RamInit::default(),
+            protection: MemoryProtection::default(),
+            interceptors: Interceptors::default(),
+            accuracy: AccuracyProfile::default(),
+            model: Model::default(),
+            dma: None,
+            dma_clock: 0,
+            #[cfg(feature = "profiling")]
+            access_counters: AccessCounters::default(),
         }
     }
 }
@@ -139,11 +452,26 @@ impl MemorySystem {
         Ok(())
     }
 
+    pub(crate) fn set_boot_rom(&mut self, boot_rom: [u8; 0x100]) {
+        self.boot_rom = boot_rom;
+        self.boot_rom_enabled = true;
+        self.boot_rom_loaded = true;
+    }
+
     pub fn load_cartridge<P: AsRef<Path>>(&mut self, path: P) -> Result<(), std::io::Error> {
         self.cartridge = Cartridge::load_from_path(path)?;
         Ok(())
     }
 
+    pub fn load_cartridge_bytes(&mut self, bytes: &[u8]) -> Result<(), std::io::Error> {
+        self.cartridge = Cartridge::load_from_bytes(bytes)?;
+        Ok(())
+    }
+
+    pub(crate) fn set_cartridge(&mut self, cartridge: Cartridge) {
+        self.cartridge = cartridge;
+    }
+
     pub fn read_byte(&self, address: u16) -> u8 {
         if self.boot_rom_enabled && address < 0x100 {
             unsafe { *self.boot_rom.get_unchecked(address as usize) }
@@ -151,34 +479,85 @@ impl MemorySystem {
             match address {
                 0x0000..=0x3FFF => self.cartridge.read_byte(address), // ROM BANK 00
                 0x4000..=0x7FFF => self.cartridge.read_byte(address), // ROM BANK 01-NN
+                0x8000..=0x9FFF if self.vram_bank1_selected() => self.vram1[address as usize - 0x8000], // VRAM 1
                 0x8000..=0x9FFF => self.vram[address as usize - 0x8000], // VRAM
                 0xA000..=0xBFFF => self.cartridge.read_byte(address), // External RAM
                 0xC000..=0xCFFF => self.wram0[address as usize - 0xC000], // WRAM 0
-                0xD000..=0xDFFF => self.wram1[address as usize - 0xD000], // WRAM 1
+                0xD000..=0xDFFF => self.wram_bank()[address as usize - 0xD000], // WRAM 1-7
                 0xE000..=0xEFFF => self.wram0[address as usize - 0xE000], // ECHO -> WRAM 0
-                0xF000..=0xFDFF => self.wram1[address as usize - 0xF000], // ECHO -> WRAM 1
+                0xF000..=0xFDFF => self.wram_bank()[address as usize - 0xF000], // ECHO -> WRAM 1-7
                 0xFE00..=0xFE9F => self.oam[address as usize - 0xFE00], // OAM
                 0xFEA0..=0xFEFF => 0xFF,                              // Not usable
-                0xFF00..=0xFF7F => self.io_regs[address as usize - 0xFF00], // IO regs
+                0xFF69 if self.cgb_mode => self.bg_palettes[(self.io_regs[0x68] & 0x3F) as usize], // BCPD
+                0xFF6B if self.cgb_mode => self.obj_palettes[(self.io_regs[0x6A] & 0x3F) as usize], // OCPD
+                0xFF00..=0xFF7F => {
+                    // IO regs
+                    let index = address as usize - 0xFF00;
+                    let mask = match (index, self.model) {
+                        (0x02, Model::Cgb) => CGB_SC_READ_MASK,
+                        // KEY1, VBK, BCPS, OCPS and SVBK
+                        (0x4D, _) if self.cgb_mode => 0x7E,
+                        (0x4F, _) if self.cgb_mode => 0xFE,
+                        (0x68 | 0x6A, _) if self.cgb_mode => 0x40,
+                        (0x70, _) if self.cgb_mode => 0xF8,
+                        _ => IO_READ_MASKS[index],
+                    };
+                    self.io_regs[index] | mask
+                }
                 0xFF80..=0xFFFE => self.hram[address as usize - 0xFF80], // HRAM
-                0xFFFF => self.interrupts,                            // Interrupts
+                0xFFFF => self.interrupts,                               // Interrupts
             }
         }
     }
 
+    /// Byte as last written, without the read masks of the IO registers
+    pub fn read_internal_byte(&self, address: u16) -> u8 {
+        match address {
+            0xFF00..=0xFF7F => self.io_regs[address as usize - 0xFF00],
+            _ => self.read_byte(address),
+        }
+    }
+
     pub fn write_byte(&mut self, address: u16, byte: u8) {
+        if self.protection.blocks_write(address, byte) {
+            return;
+        }
+
         if address == 0xFF04 {
             // TIMER DIV -> write = reset
             self.write_internal_byte(address, 0x00);
+            self.div_written = true;
             return;
         }
 
+        if address == 0xFF05 {
+            self.tima_written = true;
+        }
+
+        if address == 0xFF02 {
+            self.sc_written = true;
+        }
+
+        if (0xFF10..=0xFF3F).contains(&address) {
+            self.apu_writes.push((address, byte));
+        }
+
+        if self.cgb_mode {
+            match address {
+                0xFF69 | 0xFF6B => return self.write_palette(address, byte),
+                // Only the switch request is writable, the current speed is changed by STOP
+                0xFF4D => return self.write_internal_byte(address, self.io_regs[0x4D] & 0x80 | byte & 0x01),
+                _ => {}
+            }
+        }
+
         if address == 0xFF46 {
-            // DMA transfer
-            let src_addr = (byte as u16) << 8;
-            for i in 0..0xA0 {
-                let data = self.read_byte(src_addr + i);
-                self.write_internal_byte(0xFE00 + i, data);
+            // DMA transfer, the register reads back the source
+            self.write_internal_byte(address, byte);
+            self.dma = Some(((byte as u16) << 8, 0));
+            self.dma_clock = 0;
+            if !self.dma_timing() {
+                self.finish_dma();
             }
 
             return;
@@ -201,12 +580,12 @@ impl MemorySystem {
         match address {
             0x0000..=0x3FFF => self.cartridge.write_byte(address, byte), // ROM BANK 00
             0x4000..=0x7FFF => self.cartridge.write_byte(address, byte), // ROM BANK 01-NN
-            0x8000..=0x9FFF => self.vram[address as usize - 0x8000] = byte, // VRAM
+            0x8000..=0x9FFF => self.write_vram(address as usize - 0x8000, byte), // VRAM
             0xA000..=0xBFFF => self.cartridge.write_byte(address, byte), // External RAM
             0xC000..=0xCFFF => self.wram0[address as usize - 0xC000] = byte, // WRAM 0
-            0xD000..=0xDFFF => self.wram1[address as usize - 0xD000] = byte, // WRAM 1
+            0xD000..=0xDFFF => self.wram_bank_mut()[address as usize - 0xD000] = byte, // WRAM 1-7
             0xE000..=0xEFFF => self.wram0[address as usize - 0xE000] = byte, // ECHO -> WRAM 0
-            0xF000..=0xFDFF => self.wram1[address as usize - 0xF000] = byte, // ECHO -> WRAM 1
+            0xF000..=0xFDFF => self.wram_bank_mut()[address as usize - 0xF000] = byte, // ECHO -> WRAM 1-7
             0xFE00..=0xFE9F => self.oam[address as usize - 0xFE00] = byte, // OAM
             0xFEA0..=0xFEFF => {}                                        // Not usable
             0xFF00..=0xFF7F => self.io_regs[address as usize - 0xFF00] = byte, // IO regs
@@ -216,56 +595,173 @@ impl MemorySystem {
     }
 
     pub fn read_word(&self, address: u16) -> u16 {
-        (self.read_byte(address) as u16)  // LSB first
-            | (self.read_byte(address + 1) as u16) << 8 // MSB second
+        read_word_with(address, |address| self.read_byte(address))
     }
 
     pub fn write_word(&mut self, address: u16, word: u16) {
-        self.write_byte(address, word as u8); // LSB first
-        self.write_byte(address + 1, (word >> 8) as u8); // MSB second
+        write_word_with(address, word, |address, byte| self.write_byte(address, byte))
     }
 }
 
+/// Little endian word at `address`, the MSB at `address + 1` wrapping from $FFFF to $0000
+#[inline(always)]
+pub(crate) fn read_word_with(address: u16, read: impl Fn(u16) -> u8) -> u16 {
+    u16::from_le_bytes([read(address), read(address.wrapping_add(1))])
+}
+
+/// LSB first then MSB, at the same addresses as [`read_word_with`]
+#[inline(always)]
+pub(crate) fn write_word_with(address: u16, word: u16, mut write: impl FnMut(u16, u8)) {
+    let [low, high] = word.to_le_bytes();
+    write(address, low);
+    write(address.wrapping_add(1), high);
+}
+
 pub trait BusIO {
     fn read_byte(&self, address: u16) -> u8;
+    /// Value as written, e.g. the write-only bits of the IO registers
+    fn read_internal_byte(&self, address: u16) -> u8;
     fn write_byte(&mut self, address: u16, byte: u8);
     fn write_internal_byte(&mut self, address: u16, byte: u8);
-    fn read_word(&self, address: u16) -> u16;
-    fn write_word(&mut self, address: u16, word: u16);
+
+    /// Word at `address`, the MSB read from $0000 at $FFFF
+    fn read_word(&self, address: u16) -> u16 {
+        read_word_with(address, |address| self.read_byte(address))
+    }
+
+    /// Word at `address`, the MSB written to $0000 at $FFFF
+    fn write_word(&mut self, address: u16, word: u16) {
+        write_word_with(address, word, |address, byte| self.write_byte(address, byte))
+    }
 }
 
 pub trait InterruptBus: BusIO {
     define_flags_accessors!(interrupt_flag, 0xFF0F, Interrupt);
     define_flags_accessors!(interrupt_enable, 0xFFFF, Interrupt);
 }
+// Accesses through the trait are counted by the profiler, see the VRAM and OAM locking and go through the
+// interceptors, direct debugger accesses do not
 impl BusIO for MemorySystem {
     fn read_byte(&self, address: u16) -> u8 {
-        self.read_byte(address)
+        #[cfg(feature = "profiling")]
+        self.access_counters.read(address);
+        let value = match self.cpu_blocked(address) {
+            true => 0xFF,
+            false => self.read_byte(address),
+        };
+        self.interceptors.read(address, value)
     }
 
     fn write_byte(&mut self, address: u16, byte: u8) {
-        self.write_byte(address, byte)
+        #[cfg(feature = "profiling")]
+        self.access_counters.write(address);
+        if !self.cpu_blocked(address) {
+            self.write_byte(address, byte)
+        }
+        self.interceptors.write(address, byte);
+    }
+
+    fn read_internal_byte(&self, address: u16) -> u8 {
+        self.read_internal_byte(address)
     }
 
     fn write_internal_byte(&mut self, address: u16, byte: u8) {
         self.write_internal_byte(address, byte)
     }
+}
 
-    fn read_word(&self, address: u16) -> u16 {
-        self.read_word(address)
+impl CpuBus for MemorySystem {}
+// The PPU is never locked out of its own memory
+impl PpuBus for MemorySystem {
+    fn read_oam(&self, address: u16) -> u8 {
+        self.oam[address as usize]
     }
-
-    fn write_word(&mut self, address: u16, word: u16) {
-        self.write_word(address, word)
+    fn read_vram(&self, address: u16) -> u8 {
+        self.vram[address as usize]
+    }
+    fn read_vram_bank1(&self, address: u16) -> u8 {
+        self.vram1[address as usize]
+    }
+    fn bg_palette_color(&self, palette: u8, color_id: u8) -> u16 {
+        palette_color(&self.bg_palettes, palette, color_id)
+    }
+    fn obj_palette_color(&self, palette: u8, color_id: u8) -> u16 {
+        palette_color(&self.obj_palettes, palette, color_id)
     }
 }
 
-impl CpuBus for MemorySystem {}
-impl PpuBus for MemorySystem {}
-impl TimerBus for MemorySystem {}
+/// RGB555 color of a CGB palette, little endian
+fn palette_color(palettes: &[u8; 0x40], palette: u8, color_id: u8) -> u16 {
+    let index = (palette as usize & 0x07) * 8 + color_id as usize * 2;
+    u16::from_le_bytes([palettes[index], palettes[index + 1]]) & 0x7FFF
+}
+impl TimerBus for MemorySystem {
+    fn take_div_write(&mut self) -> bool {
+        std::mem::take(&mut self.div_written)
+    }
+    fn take_tima_write(&mut self) -> bool {
+        std::mem::take(&mut self.tima_written)
+    }
+}
+impl SerialBus for MemorySystem {
+    fn take_sc_write(&mut self) -> bool {
+        std::mem::take(&mut self.sc_written)
+    }
+}
+impl ApuBus for MemorySystem {
+    fn take_apu_writes(&mut self) -> Vec<(u16, u8)> {
+        std::mem::take(&mut self.apu_writes)
+    }
+}
 impl InterruptBus for MemorySystem {}
 impl JoypadBus for MemorySystem {}
 
+// The boot rom content is part of the machine configuration, only its mapping is saved
+impl Savable for MemorySystem {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.boot_rom_enabled);
+        writer.write_bytes(&self.vram);
+        writer.write_bytes(&self.wram0);
+        writer.write_bytes(&self.wram1);
+        writer.write_bytes(&self.oam);
+        writer.write_bytes(&self.io_regs);
+        writer.write_bytes(&self.hram);
+        writer.write_u8(self.interrupts);
+        let (dma_source, dma_copied) = self.dma.unwrap_or((0, 0xA0));
+        writer.write_u16(dma_source);
+        writer.write_u8(dma_copied);
+        writer.write_u8(self.dma_clock);
+        self.cartridge.save_state(writer);
+        writer.write_bytes(&self.vram1);
+        writer.write_bytes(&self.wram_banks);
+        writer.write_bytes(&self.bg_palettes);
+        writer.write_bytes(&self.obj_palettes);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.boot_rom_enabled = reader.read_bool()? && self.boot_rom_loaded;
+        reader.read_bytes(&mut self.vram)?;
+        self.changed_tiles = ChangedTiles::all();
+        reader.read_bytes(&mut self.wram0)?;
+        reader.read_bytes(&mut self.wram1)?;
+        reader.read_bytes(&mut self.oam)?;
+        reader.read_bytes(&mut self.io_regs)?;
+        reader.read_bytes(&mut self.hram)?;
+        self.interrupts = reader.read_u8()?;
+        let (dma_source, dma_copied) = (reader.read_u16()?, reader.read_u8()?);
+        self.dma = (dma_copied < 0xA0).then_some((dma_source, dma_copied));
+        self.dma_clock = reader.read_u8()?;
+        self.cartridge.load_state(reader)?;
+        if reader.version() >= 2 {
+            reader.read_bytes(&mut self.vram1)?;
+            reader.read_bytes(&mut self.wram_banks)?;
+            reader.read_bytes(&mut self.bg_palettes)?;
+            reader.read_bytes(&mut self.obj_palettes)?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -322,6 +818,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_word_wraps_at_highest_address() {
+        let mut memory = MemorySystem::default();
+        memory.write_byte(0xFFFF, 0x1F);
+        let msb = memory.read_byte(0x0000) as u16;
+        assert_eq!(memory.read_word(0xFFFF), msb << 8 | 0x1F);
+        assert_eq!(BusIO::read_word(&memory, 0xFFFF), msb << 8 | 0x1F);
+
+        // The MSB goes to the cartridge at $0000, IE gets the LSB
+        memory.write_word(0xFFFF, 0x0A05);
+        assert_eq!(memory.read_byte(0xFFFF), 0x05);
+        BusIO::write_word(&mut memory, 0xFFFF, 0x0A06);
+        assert_eq!(memory.read_byte(0xFFFF), 0x06);
+    }
+
     #[test]
     fn test_dma_transfer() {
         let mut memory = MemorySystem::default();
@@ -339,6 +850,52 @@ mod tests {
         assert_eq!(memory.read_oam(3), 0);
     }
 
+    #[test]
+    #[cfg(feature = "accuracy-extras")]
+    fn test_timed_dma_transfer() {
+        let mut memory = MemorySystem::default();
+        memory.set_accuracy(crate::Accuracy::Accurate.profile());
+        for i in 0..0xA0 {
+            memory.write_byte(0xC000 + i, i as u8 + 1);
+        }
+
+        BusIO::write_byte(&mut memory, 0xFF46, 0xC0);
+        memory.step_dma(8);
+        assert_eq!((memory.read_oam(0), memory.read_oam(1), memory.read_oam(2)), (1, 2, 0));
+        // The CPU cannot reach OAM until the transfer ends, 160 M-cycles later
+        assert_eq!(BusIO::read_byte(&memory, 0xFE00), 0xFF);
+        for _ in 2..0xA0 {
+            memory.step_dma(4);
+        }
+        assert_eq!(BusIO::read_byte(&memory, 0xFE00), 1);
+        assert_eq!(BusIO::read_byte(&memory, 0xFE9F), 0xA0);
+    }
+
+    #[test]
+    #[cfg(feature = "accuracy-extras")]
+    fn test_vram_locking() {
+        let mut memory = MemorySystem::default();
+        memory.set_accuracy(crate::Accuracy::Accurate.profile());
+        memory.write_byte(0x8000, 0x42);
+        memory.write_byte(0xFF40, 0x80);
+
+        // Pixel transfer
+        memory.write_internal_byte(0xFF41, 0x03);
+        assert_eq!(BusIO::read_byte(&memory, 0x8000), 0xFF);
+        BusIO::write_byte(&mut memory, 0x8000, 0x00);
+        assert_eq!(memory.read_vram(0), 0x42);
+        assert_eq!(memory.vram()[0], 0x42);
+
+        // OAM scan, only OAM is locked
+        memory.write_internal_byte(0xFF41, 0x02);
+        assert_eq!(BusIO::read_byte(&memory, 0x8000), 0x42);
+        assert_eq!(BusIO::read_byte(&memory, 0xFE00), 0xFF);
+        assert_eq!(memory.oam().len(), 0xA0);
+
+        memory.set_accuracy(crate::Accuracy::Balanced.profile());
+        assert_eq!(BusIO::read_byte(&memory, 0xFE00), 0x00);
+    }
+
     #[test]
     fn test_time_div_reset() {
         let mut timer = Timer::default();
@@ -386,4 +943,135 @@ mod tests {
             assert_eq!(bus.read_byte(0xC000 + i), byte);
         }
     }
+
+    #[test]
+    fn test_memory_regions() {
+        let rom = crate::TestRom::new().code(&[0x18, 0xFE]).build(); // JR -2
+        let mut bus = MemorySystem::default();
+        bus.set_cartridge(Cartridge::load_from_bytes(&rom).unwrap());
+
+        // Rom writes reach the mapper registers, the rom is left as is
+        for address in [0x0150, 0x2000, 0x7FFF] {
+            bus.write_byte(address, 0x00);
+        }
+        assert_eq!((bus.read_byte(0x0150), bus.read_byte(0x0151)), (0x18, 0xFE));
+        // No cartridge ram
+        bus.write_byte(0xA000, 0x42);
+        assert_eq!(bus.read_byte(0xA000), 0xFF);
+
+        // The echo ends on $FDFF, mirror of $DDFF
+        bus.write_byte(0xFDFF, 0x42);
+        assert_eq!(bus.read_byte(0xDDFF), 0x42);
+        // OAM ends on $FE9F, then the unusable area ignores writes
+        bus.write_byte(0xFE9F, 0x42);
+        bus.write_byte(0xFEA0, 0x42);
+        assert_eq!(bus.read_byte(0xFE9F), 0x42);
+        assert_eq!((bus.read_byte(0xFEA0), bus.read_byte(0xFEFF)), (0xFF, 0xFF));
+    }
+
+    #[test]
+    fn test_io_read_masks() {
+        let mut bus = MemorySystem::default();
+        for address in [0xFF03, 0xFF07, 0xFF08, 0xFF0E, 0xFF0F, 0xFF41, 0xFF4C, 0xFF7F] {
+            bus.write_byte(address, 0x00);
+        }
+
+        // Unmapped registers
+        assert_eq!(bus.read_byte(0xFF03), 0xFF);
+        assert_eq!(bus.read_byte(0xFF08), 0xFF);
+        assert_eq!(bus.read_byte(0xFF0E), 0xFF);
+        assert_eq!(bus.read_byte(0xFF4C), 0xFF);
+        assert_eq!(bus.read_byte(0xFF7F), 0xFF);
+        // Unused bits
+        assert_eq!(bus.read_byte(0xFF07), 0xF8); // TAC
+        assert_eq!(bus.read_byte(0xFF0F), 0xE0); // IF
+        assert_eq!(bus.read_byte(0xFF41), 0x80); // STAT
+
+        // Write-only bits keep the written value
+        bus.write_byte(0xFF13, 0x42); // NR13
+        assert_eq!(bus.read_byte(0xFF13), 0xFF);
+        assert_eq!(bus.read_internal_byte(0xFF13), 0x42);
+    }
+
+    fn cgb_bus() -> MemorySystem {
+        let mut bus = MemorySystem::default();
+        bus.set_model(Model::Cgb);
+        bus.set_cgb_mode(true);
+        bus
+    }
+
+    #[test]
+    fn test_cgb_banks() {
+        let mut bus = cgb_bus();
+        bus.write_byte(0x8000, 0x10);
+        bus.write_byte(0xFF4F, 0x01); // VBK
+        assert_eq!(bus.read_byte(0xFF4F), 0xFF);
+        assert_eq!(bus.read_byte(0x8000), 0x00);
+        bus.write_byte(0x8000, 0x11);
+        assert_eq!((bus.vram()[0], bus.vram_bank1()[0]), (0x10, 0x11));
+        bus.take_changed_tiles();
+        bus.write_byte(0x8010, 0x12); // tile 1 of bank 1
+        assert_eq!(bus.take_changed_tiles().iter().collect::<Vec<_>>(), [TILE_COUNT + 1]);
+
+        // SVBK 0 and 1 both map bank 1, echoed at $F000
+        for bank in 0..8u8 {
+            bus.write_byte(0xFF70, bank);
+            bus.write_byte(0xD000, 0x20 + bank);
+        }
+        bus.write_byte(0xFF70, 0x00);
+        assert_eq!(bus.read_byte(0xFF70), 0xF8);
+        assert_eq!(bus.read_byte(0xD000), 0x21);
+        for bank in 2..8u8 {
+            bus.write_byte(0xFF70, bank);
+            assert_eq!(bus.read_byte(0xD000), 0x20 + bank);
+            assert_eq!(bus.read_byte(0xF000), 0x20 + bank);
+        }
+
+        // Ignored without the CGB mode
+        bus.set_cgb_mode(false);
+        assert_eq!(bus.read_byte(0xD000), 0x21);
+        bus.write_byte(0xFF4F, 0x01);
+        assert_eq!(bus.read_byte(0x8000), 0x10);
+    }
+
+    #[test]
+    fn test_cgb_palettes() {
+        let mut bus = cgb_bus();
+        assert_eq!(bus.bg_palette_color(0, 0), 0x7FFF);
+
+        // BCPS with auto increment from color 1 of palette 7
+        bus.write_byte(0xFF68, 0x80 | 0x3E);
+        for byte in [0x1F, 0x00, 0xE0, 0x03] {
+            bus.write_byte(0xFF69, byte);
+        }
+        assert_eq!(bus.read_byte(0xFF68), 0xC2);
+        assert_eq!(bus.bg_palette_color(7, 3), 0x001F);
+        assert_eq!(bus.bg_palette_color(0, 0), 0x03E0);
+
+        // Without auto increment, the index stays
+        bus.write_byte(0xFF6A, 0x02);
+        bus.write_byte(0xFF6B, 0x00);
+        bus.write_byte(0xFF6B, 0x7C);
+        assert_eq!(bus.read_byte(0xFF6A), 0x42);
+        assert_eq!(bus.read_byte(0xFF6B), 0x7C);
+        // Bit 15 of a color is not used
+        assert_eq!(bus.obj_palette_color(0, 1), 0x7F7C);
+    }
+
+    #[test]
+    fn test_speed_switch() {
+        let mut bus = cgb_bus();
+        assert!(!bus.take_speed_switch());
+
+        bus.write_byte(0xFF4D, 0xFF);
+        assert_eq!(bus.read_byte(0xFF4D), 0x7F);
+        assert!(!bus.is_double_speed());
+        assert!(bus.take_speed_switch());
+        assert!(bus.is_double_speed());
+        assert_eq!(bus.read_byte(0xFF4D), 0xFE);
+
+        bus.write_byte(0xFF4D, 0x01);
+        assert!(bus.take_speed_switch());
+        assert_eq!(bus.read_byte(0xFF4D), 0x7E);
+    }
 }
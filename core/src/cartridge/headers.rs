@@ -1,11 +1,53 @@
-use std::ops::RangeInclusive;
+use std::ops::{Range, RangeInclusive};
 
 pub(crate) enum Headers {}
 
 impl Headers {
+    /// Entry point, logo, title and the other fields up to the checksums
+    pub const HEADER: Range<usize> = 0x0100..0x0150;
     pub const ROM_TITLE: RangeInclusive<usize> = 0x0134..=0x0143;
 
+    /// Bit 7 set by the games using the CGB features, it is also the last byte of the title
+    pub const CGB_FLAG: usize = 0x0143;
     pub const TYPE: usize = 0x0147;
     pub const ROM_SIZE: usize = 0x0148;
     pub const RAM_SIZE: usize = 0x0149;
+
+    pub const HEADER_END: usize = 0x0150;
+
+    /// Hardware named by the cartridge type byte ($0147)
+    pub fn cartridge_type_name(cartridge_type: u8) -> Option<&'static str> {
+        let name = match cartridge_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC3",
+            0xFF => "HuC1+RAM+BATTERY",
+            _ => return None,
+        };
+        Some(name)
+    }
 }
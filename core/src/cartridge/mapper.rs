@@ -3,6 +3,9 @@ use crate::cartridge::*;
 pub(crate) enum Mapper {
     RomOnly(RomOnly),
     Mbc1(Mbc1),
+    Mbc3(Mbc3),
+    Mbc5(Mbc5),
+    Custom(Box<dyn MapperTrait + Send>),
 }
 
 impl MapperTrait for Mapper {
@@ -10,17 +13,136 @@ impl MapperTrait for Mapper {
         match self {
             Mapper::RomOnly(m) => m.read(rom, ram, address),
             Mapper::Mbc1(m) => m.read(rom, ram, address),
+            Mapper::Mbc3(m) => m.read(rom, ram, address),
+            Mapper::Mbc5(m) => m.read(rom, ram, address),
+            Mapper::Custom(m) => m.read(rom, ram, address),
         }
     }
     fn write(&mut self, rom: &[u8], ram: Option<&mut [u8]>, address: u16, byte: u8) {
         match self {
             Mapper::RomOnly(m) => m.write(rom, ram, address, byte),
             Mapper::Mbc1(m) => m.write(rom, ram, address, byte),
+            Mapper::Mbc3(m) => m.write(rom, ram, address, byte),
+            Mapper::Mbc5(m) => m.write(rom, ram, address, byte),
+            Mapper::Custom(m) => m.write(rom, ram, address, byte),
+        }
+    }
+    fn state(&self) -> MapperState {
+        match self {
+            Mapper::RomOnly(m) => m.state(),
+            Mapper::Mbc1(m) => m.state(),
+            Mapper::Mbc3(m) => m.state(),
+            Mapper::Mbc5(m) => m.state(),
+            Mapper::Custom(m) => m.state(),
+        }
+    }
+}
+
+impl Mapper {
+    /// Banking registers back to their power on values, custom mappers are opaque and keep theirs
+    pub(crate) fn reset(&mut self) {
+        match self {
+            Mapper::Mbc1(m) => m.reset(),
+            Mapper::Mbc3(m) => m.reset(),
+            Mapper::Mbc5(m) => m.reset(),
+            Mapper::RomOnly(_) | Mapper::Custom(_) => {}
+        }
+    }
+
+    /// Writes at $A000..$BFFF reach the ram or the clock, custom mappers are opaque and assumed to store them
+    pub(crate) fn ram_writable(&self) -> bool {
+        match self {
+            Mapper::Custom(_) => true,
+            mapper => mapper.state().ram_enabled,
+        }
+    }
+
+    pub(crate) fn rtc(&self) -> Option<&Rtc> {
+        match self {
+            Mapper::Mbc3(m) => m.rtc.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        match self {
+            Mapper::Mbc3(m) => m.rtc.as_mut(),
+            _ => None,
+        }
+    }
+}
+
+// Custom mappers are opaque, they restart from their current state
+impl Savable for Mapper {
+    fn save_state(&self, writer: &mut StateWriter) {
+        match self {
+            Mapper::RomOnly(_) => writer.write_u8(0),
+            Mapper::Mbc1(m) => {
+                writer.write_u8(1);
+                m.save_state(writer);
+            }
+            Mapper::Mbc3(m) => {
+                writer.write_u8(2);
+                m.save_state(writer);
+            }
+            Mapper::Mbc5(m) => {
+                writer.write_u8(3);
+                m.save_state(writer);
+            }
+            Mapper::Custom(_) => writer.write_u8(0xFF),
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        match (self, reader.read_u8()?) {
+            (Mapper::RomOnly(_), 0) => Ok(()),
+            (Mapper::Mbc1(m), 1) => m.load_state(reader),
+            (Mapper::Mbc3(m), 2) => m.load_state(reader),
+            (Mapper::Mbc5(m), 3) => m.load_state(reader),
+            (Mapper::Custom(_), 0xFF) => Ok(()),
+            _ => Err(invalid_data("mapper mismatch")),
         }
     }
 }
 
-pub(crate) trait MapperTrait {
+/// Memory bank controller of a cartridge, handles accesses to $0000..$7FFF and $A000..$BFFF.
+pub trait MapperTrait {
     fn read(&self, rom: &[u8], ram: Option<&[u8]>, address: u16) -> u8;
     fn write(&mut self, rom: &[u8], ram: Option<&mut [u8]>, address: u16, byte: u8);
+
+    /// Banks currently mapped, shown by debuggers. The bank counts are filled by the cartridge.
+    fn state(&self) -> MapperState {
+        MapperState::default()
+    }
+}
+
+/// Banking registers of a mapper, see [`Machine::mapper_state`](crate::Machine::mapper_state)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-state", derive(serde::Serialize, serde::Deserialize))]
+pub struct MapperState {
+    pub rom_bank_count: usize,
+    pub ram_bank_count: usize,
+    /// Rom bank mapped at $0000..$3FFF
+    pub rom_bank_low: usize,
+    /// Rom bank mapped at $4000..$7FFF
+    pub rom_bank_high: usize,
+    /// Ram bank mapped at $A000..$BFFF
+    pub ram_bank: usize,
+    pub ram_enabled: bool,
+    /// Banking mode register, e.g. $6000 on MBC1 (1 = advanced banking)
+    pub mode: u8,
+}
+
+impl Default for MapperState {
+    fn default() -> Self {
+        Self {
+            rom_bank_count: 0,
+            ram_bank_count: 0,
+            rom_bank_low: 0,
+            rom_bank_high: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+            mode: 0,
+        }
+    }
 }
@@ -1,5 +1,7 @@
-use super::mapper::MapperTrait;
+use super::mapper::{Mapper, MapperState, MapperTrait};
+use super::registry::MapperRegistry;
 use crate::cartridge::{RAM_BANK_SIZE, ROM_BANK_SIZE};
+use crate::state::{Savable, StateReader, StateWriter};
 
 #[derive(Default)]
 pub struct Mbc1 {
@@ -21,6 +23,16 @@ impl Mbc1 {
         }
     }
 
+    pub(crate) fn reset(&mut self) {
+        *self = Self::new(self.rom_bank_count, self.ram_bank_count);
+    }
+
+    pub(crate) fn register(registry: &mut MapperRegistry) {
+        registry.register_builtin("MBC1", &[0x01, 0x02, 0x03], |config| {
+            Mapper::Mbc1(Mbc1::new(config.rom_bank_count, config.ram_bank_count))
+        });
+    }
+
     #[inline(always)]
     fn current_rom_bank_0000(&self) -> usize {
         if self.mode_ram_banking {
@@ -101,12 +113,10 @@ impl Mbc1 {
     fn write_handler_set_ram_bank(&mut self, _: Option<&mut [u8]>, _: u16, byte: u8) {
         let bits = (byte & 0b0000_00011) as usize;
 
-        // the 2-bit register is always written
+        // the 2-bit register always latches, whatever the banking mode and even without RAM,
+        // the mode only decides where it applies
         self.rom_bank = (self.rom_bank & 0b0001_1111) | (bits << 5);
-
-        if self.mode_ram_banking {
-            self.ram_bank = bits;
-        }
+        self.ram_bank = bits;
     }
 
     /// write $6000..$7FFF: set bank mode (1bits)
@@ -184,6 +194,40 @@ impl MapperTrait for Mbc1 {
     fn write(&mut self, _rom: &[u8], ram: Option<&mut [u8]>, address: u16, byte: u8) {
         WRITE_HANDLERS[address as usize >> 12](self, ram, address, byte);
     }
+
+    fn state(&self) -> MapperState {
+        let ram_bank = if self.mode_ram_banking && self.ram_bank_count > 0 {
+            self.ram_bank % self.ram_bank_count
+        } else {
+            0
+        };
+
+        MapperState {
+            rom_bank_low: self.current_rom_bank_0000() % self.rom_bank_count,
+            rom_bank_high: self.current_rom_bank_4000(),
+            ram_bank,
+            ram_enabled: self.ram_enabled,
+            mode: self.mode_ram_banking as u8,
+            ..MapperState::default()
+        }
+    }
+}
+
+impl Savable for Mbc1 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.rom_bank as u16);
+        writer.write_u8(self.ram_bank as u8);
+        writer.write_bool(self.mode_ram_banking);
+        writer.write_bool(self.ram_enabled);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.rom_bank = reader.read_u16()? as usize;
+        self.ram_bank = reader.read_u8()? as usize;
+        self.mode_ram_banking = reader.read_bool()?;
+        self.ram_enabled = reader.read_bool()?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -339,6 +383,23 @@ mod tests {
         assert_eq!(mbc.read(&rom, ram.as_deref(), ADDR_RAM + 0x100), 0xAA);
     }
 
+    #[test]
+    fn state_reports_mapped_banks() {
+        let (mut mbc, rom, mut ram) = init(128, 4);
+        assert_eq!(mbc.state().rom_bank_high, 1);
+
+        mbc.write(&rom, ram.as_deref_mut(), W_RAM_ENABLE, 0x0A);
+        mbc.write(&rom, ram.as_deref_mut(), W_BANKING_MODE, 1);
+        mbc.write(&rom, ram.as_deref_mut(), W_RAM_N_OR_HIGH2, 2);
+        mbc.write(&rom, ram.as_deref_mut(), W_ROM_N, 5);
+
+        let state = mbc.state();
+        assert_eq!((state.rom_bank_low, state.rom_bank_high), (0x40, 0x45));
+        assert_eq!(state.ram_bank, 2);
+        assert!(state.ram_enabled);
+        assert_eq!(state.mode, 1);
+    }
+
     #[test]
     fn odd_sizes_behaviour() {
         let (mut mbc, rom, mut ram) = init(7, 3);
@@ -366,4 +427,144 @@ mod tests {
         mbc.write(&rom, ram.as_deref_mut(), W_RAM_N_OR_HIGH2, 3);
         assert_eq!(mbc.read(&rom, ram.as_deref(), ADDR_RAM), 0); // % 2 => bank(0..2)
     }
+
+    // The 2-bit register written in mode 0 selects the RAM bank once mode 1 is entered
+    #[test]
+    fn ram_bank_latched_in_mode0() {
+        let (mut mbc, rom, mut ram) = init(4, 4);
+        mbc.write(&rom, ram.as_deref_mut(), W_RAM_ENABLE, 0x0A);
+
+        mbc.write(&rom, ram.as_deref_mut(), W_RAM_N_OR_HIGH2, 2);
+        assert_eq!(mbc.read(&rom, ram.as_deref(), ADDR_RAM), 0);
+        mbc.write(&rom, ram.as_deref_mut(), W_BANKING_MODE, 1);
+        assert_eq!(mbc.read(&rom, ram.as_deref(), ADDR_RAM), 2);
+        assert_eq!(mbc.state().ram_bank, 2);
+    }
+
+    // Without RAM the register still latches and drives the ROM high bits
+    #[test]
+    fn bank2_latched_without_ram() {
+        let (mut mbc, rom, _) = init(128, 0);
+
+        mbc.write(&rom, None, W_RAM_N_OR_HIGH2, 1);
+        mbc.write(&rom, None, W_BANKING_MODE, 1);
+        assert_eq!(mbc.read(&rom, None, R_BANK_0), 0x20);
+        assert_eq!(mbc.read(&rom, None, ADDR_RAM), 0xFF);
+        assert_eq!(mbc.state().ram_bank, 0);
+    }
+
+    // Up to 256 KiB the bank number is masked to the rom size: the high bits are ignored and
+    // $10 selects bank 0 at $4000 on a 256 KiB rom, the zero check comes before the masking
+    #[test]
+    fn small_rom_aliasing() {
+        let (mut mbc, rom, _) = init(16, 0);
+
+        mbc.write(&rom, None, W_ROM_N, 0x10);
+        assert_eq!(mbc.read(&rom, None, R_BANK_N), 0);
+        mbc.write(&rom, None, W_ROM_N, 0x13);
+        assert_eq!(mbc.read(&rom, None, R_BANK_N), 3);
+
+        mbc.write(&rom, None, W_BANKING_MODE, 1);
+        mbc.write(&rom, None, W_RAM_N_OR_HIGH2, 3);
+        assert_eq!(mbc.read(&rom, None, R_BANK_0), 0);
+        assert_eq!(mbc.read(&rom, None, R_BANK_N), 3);
+        let state = mbc.state();
+        assert_eq!((state.rom_bank_low, state.rom_bank_high), (0, 3));
+
+        let (mut mbc, rom, _) = init(4, 0);
+        mbc.write(&rom, None, W_ROM_N, 0x1E);
+        assert_eq!(mbc.read(&rom, None, R_BANK_N), 2);
+        mbc.write(&rom, None, W_ROM_N, 0x04);
+        assert_eq!(mbc.read(&rom, None, R_BANK_N), 0);
+    }
+
+    /// MBC1 as documented by Pan Docs
+    #[derive(Default)]
+    struct Reference {
+        ram_enable: bool,
+        bank1: usize,
+        bank2: usize,
+        mode: bool,
+    }
+
+    impl Reference {
+        fn write(&mut self, address: u16, byte: u8) {
+            match address {
+                0x0000..=0x1FFF => self.ram_enable = byte & 0x0F == 0x0A,
+                0x2000..=0x3FFF => self.bank1 = (byte & 0x1F) as usize,
+                0x4000..=0x5FFF => self.bank2 = (byte & 0x03) as usize,
+                0x6000..=0x7FFF => self.mode = byte & 0x01 != 0,
+                _ => {}
+            }
+        }
+
+        fn rom_bank(&self, address: u16, rom_banks: usize) -> usize {
+            let bank = match address {
+                0x0000..=0x3FFF if self.mode => self.bank2 << 5,
+                0x0000..=0x3FFF => 0,
+                _ => self.bank2 << 5 | self.bank1.max(1),
+            };
+            bank % rom_banks
+        }
+
+        fn ram_bank(&self, ram_banks: usize) -> Option<usize> {
+            let bank = if self.mode { self.bank2 } else { 0 };
+            (self.ram_enable && ram_banks > 0).then(|| bank % ram_banks)
+        }
+    }
+
+    #[test]
+    fn matches_reference_on_random_writes() {
+        let mut seed = 0x4D42_4331;
+        let mut random = || crate::rng::splitmix64(&mut seed);
+
+        for rom_banks in [2, 4, 8, 16, 32, 64, 128] {
+            for ram_banks in [0, 1, 4] {
+                let (mut mbc, rom, mut ram) = init(rom_banks, ram_banks);
+                let mut reference = Reference::default();
+                let mut reference_ram = ram.clone();
+
+                for step in 0..5_000 {
+                    let value = random();
+                    let byte = value as u8;
+                    // Mostly register writes, some RAM writes
+                    let address = if value >> 60 == 0 {
+                        0xA000 | (value >> 8) as u16 & 0x1FFF
+                    } else {
+                        (value >> 8) as u16 & 0x7FFF
+                    };
+
+                    mbc.write(&rom, ram.as_deref_mut(), address, byte);
+                    reference.write(address, byte);
+                    if address >= 0xA000
+                        && let (Some(bank), Some(ram)) = (reference.ram_bank(ram_banks), reference_ram.as_mut())
+                    {
+                        ram[bank * RAM_BANK_SIZE + (address as usize & 0x1FFF)] = byte;
+                    }
+
+                    let context = format!("{rom_banks} rom banks, {ram_banks} ram banks, step {step}");
+                    for address in [R_BANK_0, R_BANK_N] {
+                        let expected = reference.rom_bank(address, rom_banks) as u8;
+                        assert_eq!(mbc.read(&rom, ram.as_deref(), address), expected, "{context}");
+                    }
+                    let ram_address = 0xA000 | (value >> 24) as u16 & 0x1FFF;
+                    let expected = match (reference.ram_bank(ram_banks), reference_ram.as_deref()) {
+                        (Some(bank), Some(ram)) => ram[bank * RAM_BANK_SIZE + (ram_address as usize & 0x1FFF)],
+                        _ => 0xFF,
+                    };
+                    assert_eq!(mbc.read(&rom, ram.as_deref(), ram_address), expected, "{context}");
+
+                    let state = mbc.state();
+                    assert_eq!(state.rom_bank_low, reference.rom_bank(R_BANK_0, rom_banks), "{context}");
+                    assert_eq!(
+                        state.rom_bank_high,
+                        reference.rom_bank(R_BANK_N, rom_banks),
+                        "{context}"
+                    );
+                    assert_eq!(state.ram_enabled, reference.ram_enable, "{context}");
+                }
+                assert_eq!(ram, reference_ram);
+            }
+        }
+    }
 }
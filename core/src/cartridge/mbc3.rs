@@ -0,0 +1,206 @@
+use super::mapper::{Mapper, MapperState, MapperTrait};
+use super::registry::MapperRegistry;
+use super::rtc::{Rtc, SystemClock};
+use crate::cartridge::{RAM_BANK_SIZE, ROM_BANK_SIZE};
+use crate::state::{Savable, StateReader, StateWriter, invalid_data};
+
+pub struct Mbc3 {
+    rom_bank: usize,
+    /// $00..$03 selects a ram bank, $08..$0C a rtc register
+    ram_bank: u8,
+    ram_enabled: bool,
+    latch_armed: bool,
+    rom_bank_count: usize,
+    ram_bank_count: usize,
+    pub(crate) rtc: Option<Rtc>,
+}
+
+impl Mbc3 {
+    pub(crate) fn new(rom_bank_count: usize, ram_bank_count: usize, rtc: Option<Rtc>) -> Self {
+        Self {
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+            latch_armed: false,
+            rom_bank_count,
+            ram_bank_count,
+            rtc,
+        }
+    }
+
+    /// Banking registers back to their power on values, the clock is not touched
+    pub(crate) fn reset(&mut self) {
+        self.rom_bank = 1;
+        self.ram_bank = 0;
+        self.ram_enabled = false;
+        self.latch_armed = false;
+    }
+
+    pub(crate) fn register(registry: &mut MapperRegistry) {
+        registry.register_builtin("MBC3", &[0x0F, 0x10, 0x11, 0x12, 0x13], |config| {
+            let rtc = matches!(config.cartridge_type, 0x0F | 0x10).then(|| Rtc::new(Box::new(SystemClock)));
+            Mapper::Mbc3(Mbc3::new(config.rom_bank_count, config.ram_bank_count, rtc))
+        });
+    }
+
+    fn rtc_selected(&self) -> bool {
+        self.rtc.is_some() && matches!(self.ram_bank, 0x08..=0x0C)
+    }
+
+    fn ram_index(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram_bank_count == 0 || self.ram_bank > 0x03 {
+            return None;
+        }
+        let bank = self.ram_bank as usize % self.ram_bank_count;
+        Some(bank * RAM_BANK_SIZE + (address as usize & (RAM_BANK_SIZE - 1)))
+    }
+}
+
+impl MapperTrait for Mbc3 {
+    fn read(&self, rom: &[u8], ram: Option<&[u8]>, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => rom[address as usize],
+            0x4000..=0x7FFF => {
+                let bank = self.rom_bank % self.rom_bank_count;
+                rom[bank * ROM_BANK_SIZE + (address as usize - ROM_BANK_SIZE)]
+            }
+            0xA000..=0xBFFF if self.ram_enabled && self.rtc_selected() => {
+                self.rtc.as_ref().map_or(0xFF, |rtc| rtc.read(self.ram_bank))
+            }
+            0xA000..=0xBFFF => match (ram, self.ram_index(address)) {
+                (Some(ram), Some(index)) => ram[index],
+                _ => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, _rom: &[u8], ram: Option<&mut [u8]>, address: u16, byte: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = byte & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = ((byte & 0x7F) as usize).max(1),
+            0x4000..=0x5FFF => self.ram_bank = byte & 0x0F,
+            // Writing $00 then $01 latches the clock registers
+            0x6000..=0x7FFF => {
+                if self.latch_armed
+                    && byte == 0x01
+                    && let Some(rtc) = self.rtc.as_mut()
+                {
+                    rtc.latch();
+                }
+                self.latch_armed = byte == 0x00;
+            }
+            0xA000..=0xBFFF if self.ram_enabled && self.rtc_selected() => {
+                if let Some(rtc) = self.rtc.as_mut() {
+                    rtc.write(self.ram_bank, byte);
+                }
+            }
+            0xA000..=0xBFFF => {
+                if let (Some(ram), Some(index)) = (ram, self.ram_index(address)) {
+                    ram[index] = byte;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn state(&self) -> MapperState {
+        MapperState {
+            rom_bank_high: self.rom_bank % self.rom_bank_count,
+            ram_bank: self.ram_bank as usize,
+            ram_enabled: self.ram_enabled,
+            ..MapperState::default()
+        }
+    }
+}
+
+impl Savable for Mbc3 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.rom_bank as u8);
+        writer.write_u8(self.ram_bank);
+        writer.write_bool(self.ram_enabled);
+        writer.write_bool(self.latch_armed);
+        writer.write_bool(self.rtc.is_some());
+        if let Some(rtc) = &self.rtc {
+            rtc.save_state(writer);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.rom_bank = reader.read_u8()? as usize;
+        self.ram_bank = reader.read_u8()?;
+        self.ram_enabled = reader.read_bool()?;
+        self.latch_armed = reader.read_bool()?;
+        match (reader.read_bool()?, self.rtc.as_mut()) {
+            (true, Some(rtc)) => rtc.load_state(reader),
+            (false, None) => Ok(()),
+            _ => Err(invalid_data("rtc mismatch")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::rtc::FixedClock;
+
+    fn init(rom_banks: usize, ram_banks: usize, clock: Option<&FixedClock>) -> (Mbc3, Vec<u8>, Vec<u8>) {
+        let rom = (0..rom_banks)
+            .flat_map(|i| std::iter::repeat_n(i as u8, ROM_BANK_SIZE))
+            .collect();
+        let ram = (0..ram_banks)
+            .flat_map(|i| std::iter::repeat_n(i as u8, RAM_BANK_SIZE))
+            .collect();
+        let rtc = clock.map(|clock| Rtc::new(Box::new(clock.clone())));
+        (Mbc3::new(rom_banks, ram_banks, rtc), rom, ram)
+    }
+
+    #[test]
+    fn rom_and_ram_banking() {
+        let (mut mbc, rom, mut ram) = init(128, 4, None);
+
+        mbc.write(&rom, None, 0x2000, 0x00); // 0 maps to 1
+        assert_eq!(mbc.read(&rom, None, 0x4000), 1);
+        mbc.write(&rom, None, 0x2000, 0x45);
+        assert_eq!(mbc.read(&rom, None, 0x4000), 0x45);
+        assert_eq!(mbc.read(&rom, None, 0x0000), 0);
+
+        assert_eq!(mbc.read(&rom, Some(&ram), 0xA000), 0xFF);
+        mbc.write(&rom, Some(&mut ram), 0x0000, 0x0A);
+        mbc.write(&rom, Some(&mut ram), 0x4000, 0x02);
+        assert_eq!(mbc.read(&rom, Some(&ram), 0xA000), 2);
+        mbc.write(&rom, Some(&mut ram), 0xA010, 0x99);
+        assert_eq!(ram[2 * RAM_BANK_SIZE + 0x10], 0x99);
+
+        // No clock on this cartridge type
+        mbc.write(&rom, Some(&mut ram), 0x4000, 0x08);
+        assert_eq!(mbc.read(&rom, Some(&ram), 0xA000), 0xFF);
+    }
+
+    #[test]
+    fn rtc_registers_are_latched() {
+        let clock = FixedClock::new(0);
+        let (mut mbc, rom, _) = init(4, 0, Some(&clock));
+        let latch = |mbc: &mut Mbc3| {
+            mbc.write(&rom, None, 0x6000, 0x00);
+            mbc.write(&rom, None, 0x6000, 0x01);
+        };
+
+        mbc.write(&rom, None, 0x0000, 0x0A);
+        mbc.write(&rom, None, 0x4000, 0x08); // seconds
+        clock.advance(42);
+        assert_eq!(mbc.read(&rom, None, 0xA000), 0);
+
+        latch(&mut mbc);
+        assert_eq!(mbc.read(&rom, None, 0xA000), 42);
+
+        // A single write does not latch
+        clock.advance(1);
+        mbc.write(&rom, None, 0x6000, 0x01);
+        assert_eq!(mbc.read(&rom, None, 0xA000), 42);
+
+        mbc.write(&rom, None, 0xA000, 10);
+        latch(&mut mbc);
+        assert_eq!(mbc.read(&rom, None, 0xA000), 10);
+    }
+}
@@ -0,0 +1,215 @@
+use super::mapper::{Mapper, MapperState, MapperTrait};
+use super::registry::MapperRegistry;
+use crate::cartridge::{RAM_BANK_SIZE, ROM_BANK_SIZE};
+use crate::state::{Savable, StateReader, StateWriter};
+
+pub struct Mbc5 {
+    /// 9 bits, bank 0 can be mapped at $4000..$7FFF
+    rom_bank: usize,
+    ram_bank: u8,
+    ram_enabled: bool,
+    /// Bit 3 of the ram bank register drives the motor instead of selecting a bank
+    rumble: bool,
+    rom_bank_count: usize,
+    ram_bank_count: usize,
+}
+
+impl Mbc5 {
+    pub(crate) fn new(rom_bank_count: usize, ram_bank_count: usize, rumble: bool) -> Self {
+        Self {
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+            rumble,
+            rom_bank_count,
+            ram_bank_count,
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        *self = Self::new(self.rom_bank_count, self.ram_bank_count, self.rumble);
+    }
+
+    pub(crate) fn register(registry: &mut MapperRegistry) {
+        registry.register_builtin("MBC5", &[0x19, 0x1A, 0x1B, 0x1C, 0x1D, 0x1E], |config| {
+            let rumble = matches!(config.cartridge_type, 0x1C..=0x1E);
+            Mapper::Mbc5(Mbc5::new(config.rom_bank_count, config.ram_bank_count, rumble))
+        });
+    }
+
+    /// Bit 3 of the RAM bank drives the motor of the rumble carts
+    fn ram_bank_mask(&self) -> u8 {
+        match self.rumble {
+            true => 0x07,
+            false => 0x0F,
+        }
+    }
+
+    fn ram_index(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram_bank_count == 0 {
+            return None;
+        }
+        let bank = self.ram_bank as usize % self.ram_bank_count;
+        Some(bank * RAM_BANK_SIZE + (address as usize & (RAM_BANK_SIZE - 1)))
+    }
+}
+
+impl MapperTrait for Mbc5 {
+    fn read(&self, rom: &[u8], ram: Option<&[u8]>, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => rom[address as usize],
+            0x4000..=0x7FFF => {
+                let bank = self.rom_bank % self.rom_bank_count;
+                rom[bank * ROM_BANK_SIZE + (address as usize - ROM_BANK_SIZE)]
+            }
+            0xA000..=0xBFFF => match (ram, self.ram_index(address)) {
+                (Some(ram), Some(index)) => ram[index],
+                _ => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, _rom: &[u8], ram: Option<&mut [u8]>, address: u16, byte: u8) {
+        match address {
+            // Unlike MBC1 and MBC3, the whole byte is compared
+            0x0000..=0x1FFF => self.ram_enabled = byte == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = self.rom_bank & 0x100 | byte as usize,
+            0x3000..=0x3FFF => self.rom_bank = self.rom_bank & 0xFF | (byte as usize & 0x01) << 8,
+            0x4000..=0x5FFF => self.ram_bank = byte & self.ram_bank_mask(),
+            0xA000..=0xBFFF => {
+                if let (Some(ram), Some(index)) = (ram, self.ram_index(address)) {
+                    ram[index] = byte;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn state(&self) -> MapperState {
+        MapperState {
+            rom_bank_high: self.rom_bank % self.rom_bank_count,
+            ram_bank: self.ram_bank as usize,
+            ram_enabled: self.ram_enabled,
+            ..MapperState::default()
+        }
+    }
+}
+
+impl Savable for Mbc5 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.rom_bank as u16);
+        writer.write_u8(self.ram_bank);
+        writer.write_bool(self.ram_enabled);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.rom_bank = reader.read_u16()? as usize & 0x1FF;
+        self.ram_bank = reader.read_u8()? & self.ram_bank_mask();
+        self.ram_enabled = reader.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn init(rom_banks: usize, ram_banks: usize, rumble: bool) -> (Mbc5, Vec<u8>, Vec<u8>) {
+        // Each bank starts with its number, low byte then high byte
+        let rom = (0..rom_banks)
+            .flat_map(|i| {
+                let mut bank = vec![0u8; ROM_BANK_SIZE];
+                bank[..2].copy_from_slice(&(i as u16).to_le_bytes());
+                bank
+            })
+            .collect();
+        let ram = (0..ram_banks)
+            .flat_map(|i| std::iter::repeat_n(i as u8, RAM_BANK_SIZE))
+            .collect();
+        (Mbc5::new(rom_banks, ram_banks, rumble), rom, ram)
+    }
+
+    fn mapped_rom_bank(mbc: &Mbc5, rom: &[u8]) -> u16 {
+        u16::from_le_bytes([mbc.read(rom, None, 0x4000), mbc.read(rom, None, 0x4001)])
+    }
+
+    #[test]
+    fn rom_bank_has_nine_bits() {
+        let (mut mbc, rom, _) = init(512, 0, false);
+        assert_eq!(mapped_rom_bank(&mbc, &rom), 1);
+
+        mbc.write(&rom, None, 0x2000, 0x45);
+        assert_eq!(mapped_rom_bank(&mbc, &rom), 0x45);
+        mbc.write(&rom, None, 0x3000, 0x01);
+        assert_eq!(mapped_rom_bank(&mbc, &rom), 0x145);
+        // The low byte keeps the ninth bit
+        mbc.write(&rom, None, 0x2FFF, 0xFF);
+        assert_eq!(mapped_rom_bank(&mbc, &rom), 0x1FF);
+        mbc.write(&rom, None, 0x3FFF, 0xFE);
+        assert_eq!(mapped_rom_bank(&mbc, &rom), 0xFF);
+
+        // Bank 0 is not remapped to 1
+        mbc.write(&rom, None, 0x2000, 0x00);
+        assert_eq!(mapped_rom_bank(&mbc, &rom), 0);
+        assert_eq!(mapped_rom_bank(&mbc, &rom), u16::from_le_bytes([rom[0], rom[1]]));
+
+        // Wraps on smaller roms
+        let (mut mbc, rom, _) = init(64, 0, false);
+        mbc.write(&rom, None, 0x2000, 0x42);
+        assert_eq!(mapped_rom_bank(&mbc, &rom), 0x02);
+    }
+
+    #[test]
+    fn sixteen_ram_banks() {
+        let (mut mbc, rom, mut ram) = init(4, 16, false);
+
+        assert_eq!(mbc.read(&rom, Some(&ram), 0xA000), 0xFF);
+        // Only $0A enables, not any value with $A in the low nibble
+        mbc.write(&rom, Some(&mut ram), 0x0000, 0x1A);
+        assert_eq!(mbc.read(&rom, Some(&ram), 0xA000), 0xFF);
+        mbc.write(&rom, Some(&mut ram), 0x0000, 0x0A);
+
+        mbc.write(&rom, Some(&mut ram), 0x4000, 0x0F);
+        assert_eq!(mbc.read(&rom, Some(&ram), 0xA000), 15);
+        mbc.write(&rom, Some(&mut ram), 0xB123, 0x99);
+        assert_eq!(ram[15 * RAM_BANK_SIZE + 0x1123], 0x99);
+
+        mbc.write(&rom, Some(&mut ram), 0x0000, 0x00);
+        assert_eq!(mbc.read(&rom, Some(&ram), 0xB123), 0xFF);
+    }
+
+    #[test]
+    fn rumble_bit_does_not_select_a_bank() {
+        let (mut mbc, rom, mut ram) = init(4, 16, true);
+        mbc.write(&rom, Some(&mut ram), 0x0000, 0x0A);
+
+        mbc.write(&rom, Some(&mut ram), 0x4000, 0x0B); // motor on, bank 3
+        assert_eq!(mbc.read(&rom, Some(&ram), 0xA000), 3);
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let (mut mbc, rom, _) = init(512, 4, false);
+        mbc.write(&rom, None, 0x2000, 0x23);
+        mbc.write(&rom, None, 0x3000, 0x01);
+        mbc.write(&rom, None, 0x4000, 0x02);
+        let mut writer = StateWriter::default();
+        mbc.save_state(&mut writer);
+
+        let mut restored = Mbc5::new(512, 4, false);
+        let data = writer.into_inner();
+        restored.load_state(&mut StateReader::new(&data)).unwrap();
+        assert_eq!(restored.state(), mbc.state());
+        assert_eq!(mapped_rom_bank(&restored, &rom), 0x123);
+
+        // The motor bit is not a bank on rumble carts
+        let mut rumble = Mbc5::new(512, 16, true);
+        let data = [0x01, 0x00, 0x0B, 0x01];
+        rumble.load_state(&mut StateReader::new(&data)).unwrap();
+        assert_eq!(rumble.state().ram_bank, 3);
+
+        mbc.reset();
+        assert_eq!(mapped_rom_bank(&mbc, &rom), 1);
+    }
+}
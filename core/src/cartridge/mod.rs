@@ -1,16 +1,30 @@
 mod headers;
 mod mapper;
 mod mbc1;
+mod mbc3;
+mod mbc5;
+mod patch;
+mod registry;
 mod rom_only;
+mod rtc;
 
-use crate::cartridge::mapper::{Mapper, MapperTrait};
+use crate::cartridge::mapper::Mapper;
+pub use crate::cartridge::mapper::{MapperState, MapperTrait};
 use crate::cartridge::mbc1::Mbc1;
+use crate::cartridge::mbc3::Mbc3;
+use crate::cartridge::mbc5::Mbc5;
+pub use crate::cartridge::patch::apply_patch;
+pub use crate::cartridge::registry::{MapperConfig, MapperRegistry};
 use crate::cartridge::rom_only::RomOnly;
+use crate::cartridge::rtc::Rtc;
+pub use crate::cartridge::rtc::{Clock, FixedClock, OffsetClock, SystemClock};
+use crate::state::{Savable, StateReader, StateWriter, invalid_data};
+use crate::video::crc32;
 use headers::Headers;
 use log::debug;
 use std::ffi::OsStr;
 use std::fs::File;
-use std::io::{Error, Read};
+use std::io::{Cursor, Error, ErrorKind, Read, Seek};
 use std::path::Path;
 
 pub struct Cartridge {
@@ -18,22 +32,54 @@ pub struct Cartridge {
     rom: Vec<u8>,
     ram: Option<Vec<u8>>,
     mapper: Mapper,
+    battery: bool,
+    ram_dirty: bool, // external ram written since last check
 }
 
 pub const ROM_BANK_SIZE: usize = 0x4000;
 pub const RAM_BANK_SIZE: usize = 0x2000;
+/// Largest rom a header can declare, 512 banks. Larger images are rejected before they are read in full, so a
+/// zip bomb or an endless file cannot exhaust the memory.
+pub const MAX_ROM_SIZE: usize = 512 * ROM_BANK_SIZE;
 
 impl Cartridge {
     pub fn load_from_path<P: AsRef<Path>>(path: P) -> Result<Cartridge, Error> {
+        Self::from_rom(Self::read_path(path)?, &MapperRegistry::default())
+    }
+
+    /// Load a cartridge from an in-memory rom image, zip archives are detected by their signature.
+    pub fn load_from_bytes(bytes: &[u8]) -> Result<Cartridge, Error> {
+        Self::from_rom(Self::read_bytes(bytes)?, &MapperRegistry::default())
+    }
+
+    pub(crate) fn read_path<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, Error> {
         let mut file = File::open(&path)?;
         let ext = path.as_ref().extension().and_then(OsStr::to_str);
 
         let (rom, _) = match ext {
             Some("gb") => Self::read_file(&mut file)?,
             Some("zip") => Self::read_zip(file)?,
-            _ => panic!("unsupported file type"),
+            _ => return Err(Error::new(ErrorKind::Unsupported, "unsupported file type")),
         };
 
+        Ok(rom)
+    }
+
+    pub(crate) fn read_bytes(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+        const ZIP_SIGNATURE: &[u8] = b"PK\x03\x04";
+
+        if bytes.starts_with(ZIP_SIGNATURE) {
+            Ok(Self::read_zip(Cursor::new(bytes))?.0)
+        } else {
+            Self::read_rom(bytes)
+        }
+    }
+
+    pub(crate) fn from_rom(rom: Vec<u8>, registry: &MapperRegistry) -> Result<Cartridge, Error> {
+        if rom.len() < Headers::HEADER_END {
+            return Err(Error::other("rom too small to contain a header"));
+        }
+
         let title = &rom[Headers::ROM_TITLE];
         let title = String::from_utf8_lossy(title).trim_end_matches('\0').to_string();
         let (ram_banks, ram_size): (usize, usize) = match rom[Headers::RAM_SIZE] {
@@ -57,11 +103,29 @@ impl Cartridge {
             t => return Err(Error::other(format!("unsupported rom size ${:02x}", t))),
         };
 
-        let mapper = match rom[Headers::TYPE] {
-            0x00 => Mapper::RomOnly(RomOnly),
-            0x01..=0x03 => Mapper::Mbc1(Mbc1::new(rom_banks, ram_banks)), // MBC1
-            t => return Err(Error::other(format!("unsupported cartridge type ${:02x}", t))),
+        let config = MapperConfig {
+            cartridge_type: rom[Headers::TYPE],
+            rom_bank_count: rom_banks,
+            ram_bank_count: ram_banks,
         };
+        let Some(mapper) = registry.create(&config) else {
+            let t = config.cartridge_type;
+            let header = format!(
+                "title \"{title}\", type ${t:02x}, {} KiB ROM, {} KiB RAM",
+                rom_size / 1024,
+                ram_size / 1024
+            );
+            let message = match Headers::cartridge_type_name(t) {
+                Some(name) => format!("{name} not yet supported ({header})"),
+                None => format!("unknown cartridge type ({header})"),
+            };
+            return Err(Error::new(ErrorKind::Unsupported, message));
+        };
+
+        let battery = matches!(
+            config.cartridge_type,
+            0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFF
+        );
 
         let ram = if ram_size > 0 { Some(vec![0u8; ram_size]) } else { None };
         let rom_raw = rom;
@@ -74,6 +138,8 @@ impl Cartridge {
             rom,
             ram,
             mapper,
+            battery,
+            ram_dirty: false,
         })
     }
 
@@ -83,51 +149,267 @@ impl Cartridge {
             rom: vec![0xFF; 0x4000],
             mapper: Mapper::RomOnly(RomOnly {}),
             ram: None,
+            battery: false,
+            ram_dirty: false,
         }
     }
 
+    pub(crate) fn rom(&self) -> &[u8] {
+        &self.rom
+    }
     pub fn title(&self) -> &str {
         &self.title
     }
+    /// CRC32 of the cartridge header, identifies a game better than its title
+    pub fn header_hash(&self) -> u32 {
+        crc32(&[self.rom.get(Headers::HEADER).unwrap_or_default()])
+    }
+    /// Hardware named by the cartridge type of the header, e.g. `MBC1+RAM+BATTERY`
+    pub fn type_name(&self) -> &'static str {
+        self.rom
+            .get(Headers::TYPE)
+            .and_then(|cartridge_type| Headers::cartridge_type_name(*cartridge_type))
+            .unwrap_or("UNKNOWN")
+    }
+
+    /// The header flags a game using the CGB features ($80, or $C0 for CGB only), run in CGB mode by a CGB.
+    /// The erased header of the empty cartridge ($FF) is a DMG one.
+    pub fn supports_cgb(&self) -> bool {
+        self.rom
+            .get(Headers::CGB_FLAG)
+            .is_some_and(|flag| matches!(flag, 0x80 | 0xC0))
+    }
+
+    /// External ram or clock is kept by a battery when the console is off
+    pub fn has_battery(&self) -> bool {
+        self.battery && (self.ram.is_some() || self.has_rtc())
+    }
+
+    /// The cartridge has a real time clock (MBC3+TIMER)
+    pub fn has_rtc(&self) -> bool {
+        self.mapper.rtc().is_some()
+    }
+
+    /// Replace the time source of the real time clock, if any
+    pub fn set_rtc_clock(&mut self, clock: Box<dyn Clock>) {
+        if let Some(rtc) = self.mapper.rtc_mut() {
+            rtc.set_clock(clock);
+        }
+    }
+
+    pub fn ram(&self) -> Option<&[u8]> {
+        self.ram.as_deref()
+    }
+
+    /// Banks currently mapped by the mapper
+    pub fn mapper_state(&self) -> MapperState {
+        MapperState {
+            rom_bank_count: self.rom.len() / ROM_BANK_SIZE,
+            ram_bank_count: self.ram.as_ref().map_or(0, |ram| ram.len().div_ceil(RAM_BANK_SIZE)),
+            ..self.mapper.state()
+        }
+    }
+
+    /// Replace the external ram content, typically from a battery save file.
+    pub fn load_ram(&mut self, data: &[u8]) -> Result<(), Error> {
+        match self.ram.as_mut() {
+            Some(ram) if ram.len() == data.len() => {
+                ram.copy_from_slice(data);
+                Ok(())
+            }
+            _ => Err(Error::new(ErrorKind::InvalidData, "cartridge ram size mismatch")),
+        }
+    }
+
+    /// Content of the battery save file: the external ram followed by the BGB rtc footer
+    pub(crate) fn battery_data(&self) -> Vec<u8> {
+        let mut data = self.ram.clone().unwrap_or_default();
+        if let Some(rtc) = self.mapper.rtc() {
+            data.extend(rtc.footer());
+        }
+        data
+    }
+
+    /// Restore a battery save file, a save without rtc footer leaves the clock untouched.
+    pub(crate) fn load_battery_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        let ram_size = self.ram.as_ref().map_or(0, Vec::len);
+        if data.len() < ram_size {
+            return Err(Error::new(ErrorKind::InvalidData, "cartridge ram size mismatch"));
+        }
+        let (ram, footer) = data.split_at(ram_size);
+
+        if self.ram.is_some() {
+            self.load_ram(ram)?;
+        }
+        match self.mapper.rtc_mut() {
+            Some(rtc) if !footer.is_empty() => rtc.load_footer(footer),
+            None if !footer.is_empty() => Err(Error::new(ErrorKind::InvalidData, "cartridge ram size mismatch")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Console power cycle: the mapper registers are reset, the external ram is zeroed and the clock
+    /// stopped at day 0 unless kept, as they would be by the cartridge battery.
+    pub(crate) fn power_cycle(&mut self, keep_ram: bool, keep_rtc: bool) {
+        self.mapper.reset();
+        if !keep_ram && let Some(ram) = self.ram.as_mut() {
+            ram.fill(0);
+        }
+        if !keep_rtc && let Some(rtc) = self.mapper.rtc_mut() {
+            rtc.clear();
+        }
+    }
+
+    /// Return true once after the external ram has been written
+    pub(crate) fn take_ram_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.ram_dirty)
+    }
 
     fn read_file(file: &mut File) -> Result<(Vec<u8>, usize), Error> {
-        let mut rom = vec![];
-        let rom_size = file.read_to_end(&mut rom)?;
+        let rom = Self::read_rom(file)?;
+        let rom_size = rom.len();
 
         Ok((rom, rom_size))
     }
 
-    fn read_zip(file: File) -> Result<(Vec<u8>, usize), Error> {
+    fn read_zip<R: Read + Seek>(file: R) -> Result<(Vec<u8>, usize), Error> {
         debug!("Unzipping rom...");
         let mut archive = zip::ZipArchive::new(file)?;
 
         let filename = archive
             .file_names()
             .find(|name| name.to_lowercase().ends_with(".gb"))
-            .expect("any roms found in the archive!")
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "no .gb rom in the archive"))?
             .to_string();
         debug!(" > file extract: {}", filename);
 
-        let mut file = archive.by_name(&filename)?;
-        let mut rom = vec![];
-        let rom_size = file.read_to_end(&mut rom)?;
+        let file = archive.by_name(&filename)?;
+        if file.size() > MAX_ROM_SIZE as u64 {
+            return Err(rom_too_large());
+        }
+        // The declared size is not trusted, the reading stops past the limit
+        let rom = Self::read_rom(file)?;
+        let rom_size = rom.len();
         Ok((rom, rom_size))
     }
 
+    /// Read a whole rom image of at most [`MAX_ROM_SIZE`] bytes
+    fn read_rom(reader: impl Read) -> Result<Vec<u8>, Error> {
+        let mut rom = vec![];
+        reader.take(MAX_ROM_SIZE as u64 + 1).read_to_end(&mut rom)?;
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(rom_too_large());
+        }
+        Ok(rom)
+    }
+
     pub fn read_byte(&self, address: u16) -> u8 {
         self.mapper.read(&self.rom, self.ram.as_deref(), address)
     }
 
     pub(crate) fn write_byte(&mut self, address: u16, byte: u8) {
+        self.ram_dirty |=
+            matches!(address, 0xA000..=0xBFFF) && (self.ram.is_some() || self.has_rtc()) && self.mapper.ram_writable();
         self.mapper.write(&self.rom, self.ram.as_deref_mut(), address, byte);
     }
 }
 
+fn rom_too_large() -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        "rom larger than the 8 MiB of the largest cartridge",
+    )
+}
+
+// The rom is not saved, the state can only be restored on the same cartridge
+impl Savable for Cartridge {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_vec(self.title.as_bytes());
+        writer.write_vec(self.ram.as_deref().unwrap_or_default());
+        self.mapper.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        if reader.read_vec()? != self.title.as_bytes() {
+            return Err(invalid_data("cartridge mismatch"));
+        }
+
+        let ram = reader.read_vec()?;
+        match self.ram.as_mut() {
+            Some(current) if current.len() == ram.len() => current.copy_from_slice(&ram),
+            None if ram.is_empty() => {}
+            _ => return Err(invalid_data("cartridge ram size mismatch")),
+        }
+
+        self.mapper.load_state(reader)
+    }
+}
+
 #[cfg(test)]
-#[cfg(feature = "use-test-roms")]
 mod tests {
     use super::*;
 
+    /// Zip archive of one stored (uncompressed) file
+    fn stored_zip(name: &str, data: &[u8]) -> Vec<u8> {
+        let crc = crc32(&[data]).to_le_bytes();
+        let size = (data.len() as u32).to_le_bytes();
+        let name_len = (name.len() as u16).to_le_bytes();
+        let entry = |zip: &mut Vec<u8>| {
+            zip.extend_from_slice(&crc);
+            zip.extend_from_slice(&size); // compressed
+            zip.extend_from_slice(&size);
+            zip.extend_from_slice(&name_len);
+        };
+
+        // Local header, method 0 (stored)
+        let mut zip = b"PK\x03\x04\x14\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        entry(&mut zip);
+        zip.extend_from_slice(&[0; 2]); // extra field
+        zip.extend_from_slice(name.as_bytes());
+        zip.extend_from_slice(data);
+
+        // Central directory
+        let directory = zip.len() as u32;
+        zip.extend_from_slice(b"PK\x01\x02\x14\x00\x14\x00\x00\x00\x00\x00\x00\x00\x00\x00");
+        entry(&mut zip);
+        zip.extend_from_slice(&[0; 12]); // extra, comment, disk, attributes
+        zip.extend_from_slice(&[0; 4]); // offset of the local header
+        zip.extend_from_slice(name.as_bytes());
+        let directory_size = zip.len() as u32 - directory;
+
+        // End of central directory
+        zip.extend_from_slice(b"PK\x05\x06\x00\x00\x00\x00\x01\x00\x01\x00");
+        zip.extend_from_slice(&directory_size.to_le_bytes());
+        zip.extend_from_slice(&directory.to_le_bytes());
+        zip.extend_from_slice(&[0; 2]); // comment
+        zip
+    }
+
+    #[test]
+    fn test_read_zip_bytes() -> Result<(), Error> {
+        let mut rom = vec![0; 0x8000];
+        rom[Headers::ROM_TITLE][..4].copy_from_slice(b"ZIPD");
+        let cartridge = Cartridge::load_from_bytes(&stored_zip("game.gb", &rom))?;
+        assert_eq!(cartridge.title(), "ZIPD");
+
+        let error = Cartridge::load_from_bytes(&stored_zip("readme.txt", b"hello"))
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        Ok(())
+    }
+
+    /// Images past the largest cartridge are rejected, raw or zipped
+    #[test]
+    fn test_rom_size_limit() {
+        let rom = vec![0; MAX_ROM_SIZE + 1];
+        for bytes in [stored_zip("big.gb", &rom), rom] {
+            let error = Cartridge::load_from_bytes(&bytes).err().unwrap();
+            assert_eq!(error.to_string(), rom_too_large().to_string());
+        }
+    }
+
+    #[cfg(feature = "use-test-roms")]
     #[test]
     fn test_read_gb() -> Result<(), Error> {
         let cartridge = Cartridge::load_from_path("../doctor/roms/demos/cncd-at.zip")?;
@@ -135,6 +417,44 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_ram_dirty_only_when_stored() -> Result<(), Error> {
+        let mut rom = vec![0; 0x8000];
+        rom[Headers::TYPE] = 0x03;
+        rom[Headers::RAM_SIZE] = 0x02;
+        let mut cartridge = Cartridge::from_rom(rom, &MapperRegistry::default())?;
+
+        cartridge.write_byte(0xA000, 0x42);
+        assert!(!cartridge.take_ram_dirty());
+        cartridge.write_byte(0x0000, 0x0A); // enable ram
+        cartridge.write_byte(0xA000, 0x42);
+        assert!(cartridge.take_ram_dirty());
+        assert!(!cartridge.take_ram_dirty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_mapper_is_named() {
+        let mut rom = vec![0; 0x8000];
+        rom[Headers::ROM_TITLE][..4].copy_from_slice(b"GAME");
+        rom[Headers::TYPE] = 0x22;
+        rom[Headers::RAM_SIZE] = 0x02;
+
+        let error = Cartridge::from_rom(rom.clone(), &MapperRegistry::default())
+            .err()
+            .unwrap();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+        assert_eq!(
+            error.to_string(),
+            "MBC7+SENSOR+RUMBLE+RAM+BATTERY not yet supported (title \"GAME\", type $22, 32 KiB ROM, 8 KiB RAM)"
+        );
+
+        rom[Headers::TYPE] = 0x40;
+        let error = Cartridge::from_rom(rom, &MapperRegistry::default()).err().unwrap();
+        assert!(error.to_string().starts_with("unknown cartridge type"));
+    }
+
+    #[cfg(feature = "use-test-roms")]
     #[test]
     fn test_read_zip() -> Result<(), Error> {
         let cartridge = Cartridge::load_from_path("../doctor/roms/demos/alttoo.gb")?;
@@ -0,0 +1,307 @@
+use crate::cartridge::MAX_ROM_SIZE;
+use crate::video::crc32;
+use std::io::{Error, ErrorKind};
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+/// Source, target and patch CRC32 at the end of a BPS file
+const BPS_FOOTER: usize = 12;
+
+/// Apply an IPS or BPS patch to `rom`, the format is detected from the patch header.
+/// BPS patches are checked against the CRC32 of the source rom, the patched rom and the patch itself.
+pub fn apply_patch(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, Error> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, &patch[IPS_MAGIC.len()..])
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else {
+        Err(invalid_patch("unknown format, expected IPS or BPS"))
+    }
+}
+
+fn invalid_patch(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("invalid patch: {message}"))
+}
+
+/// Bounds checked reads of a patch
+struct PatchReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], Error> {
+        let bytes = self
+            .data
+            .get(self.position..)
+            .and_then(|rest| rest.get(..count))
+            .ok_or_else(|| invalid_patch("truncated"))?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    /// Big endian value of `count` bytes, as used by IPS
+    fn be(&mut self, count: usize) -> Result<usize, Error> {
+        Ok(self
+            .bytes(count)?
+            .iter()
+            .fold(0, |value, byte| value << 8 | *byte as usize))
+    }
+
+    /// BPS variable length number
+    fn number(&mut self) -> Result<usize, Error> {
+        let (mut value, mut shift) = (0usize, 1usize);
+        loop {
+            let byte = self.bytes(1)?[0];
+            value = (byte as usize & 0x7F)
+                .checked_mul(shift)
+                .and_then(|add| value.checked_add(add))
+                .ok_or_else(|| invalid_patch("number overflow"))?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift
+                .checked_mul(0x80)
+                .ok_or_else(|| invalid_patch("number overflow"))?;
+            value = value
+                .checked_add(shift)
+                .ok_or_else(|| invalid_patch("number overflow"))?;
+        }
+    }
+
+    /// BPS relative offset, the low bit is the sign
+    fn offset(&mut self, base: usize) -> Result<usize, Error> {
+        let number = self.number()?;
+        let offset = if number & 1 != 0 {
+            base.checked_sub(number >> 1)
+        } else {
+            base.checked_add(number >> 1)
+        };
+        offset.ok_or_else(|| invalid_patch("copy offset out of range"))
+    }
+}
+
+/// Records of a 24-bit offset and a 16-bit length, a zero length is a run of one byte.
+/// A 24-bit size after the `EOF` marker truncates the rom.
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut output = rom.to_vec();
+    let mut reader = PatchReader {
+        data: patch,
+        position: 0,
+    };
+
+    loop {
+        let offset = reader.bytes(3)?;
+        if offset == IPS_EOF {
+            if reader.data.len() - reader.position == 3 {
+                let size = reader.be(3)?;
+                output.truncate(size);
+            }
+            return Ok(output);
+        }
+        let offset = offset.iter().fold(0, |value, byte| value << 8 | *byte as usize);
+
+        let size = reader.be(2)?;
+        let run;
+        let data = match size {
+            0 => {
+                let size = reader.be(2)?;
+                run = vec![reader.bytes(1)?[0]; size];
+                &run
+            }
+            _ => reader.bytes(size)?,
+        };
+        if offset + data.len() > MAX_ROM_SIZE {
+            return Err(invalid_patch("output larger than the largest cartridge"));
+        }
+        if output.len() < offset + data.len() {
+            output.resize(offset + data.len(), 0);
+        }
+        output[offset..offset + data.len()].copy_from_slice(data);
+    }
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, Error> {
+    if patch.len() < BPS_MAGIC.len() + BPS_FOOTER {
+        return Err(invalid_patch("truncated"));
+    }
+    let (body, footer) = patch.split_at(patch.len() - BPS_FOOTER);
+    let crc = |index: usize| u32::from_le_bytes(footer[index * 4..index * 4 + 4].try_into().unwrap());
+    if crc32(&[body, &footer[..8]]) != crc(2) {
+        return Err(invalid_patch("corrupted, checksum mismatch"));
+    }
+    if crc32(&[rom]) != crc(0) {
+        return Err(invalid_patch("made for another rom, source checksum mismatch"));
+    }
+
+    let mut reader = PatchReader {
+        data: body,
+        position: BPS_MAGIC.len(),
+    };
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    reader.bytes(metadata_size)?;
+    if source_size != rom.len() {
+        return Err(invalid_patch("made for another rom, source size mismatch"));
+    }
+    if target_size > MAX_ROM_SIZE {
+        return Err(invalid_patch("target larger than the largest cartridge"));
+    }
+
+    let mut output = Vec::with_capacity(target_size);
+    let (mut source_offset, mut target_offset) = (0, 0);
+    while reader.position < body.len() {
+        let action = reader.number()?;
+        let length = (action >> 2) + 1;
+        if length > target_size - output.len() {
+            return Err(invalid_patch("output larger than the target size"));
+        }
+        match action & 3 {
+            // Source read
+            0 => {
+                let start = output.len();
+                let data = rom
+                    .get(start..)
+                    .and_then(|rest| rest.get(..length))
+                    .ok_or_else(|| invalid_patch("source read out of range"))?;
+                output.extend_from_slice(data);
+            }
+            // Target read
+            1 => output.extend_from_slice(reader.bytes(length)?),
+            // Source copy
+            2 => {
+                source_offset = reader.offset(source_offset)?;
+                let data = rom
+                    .get(source_offset..)
+                    .and_then(|rest| rest.get(..length))
+                    .ok_or_else(|| invalid_patch("source copy out of range"))?;
+                output.extend_from_slice(data);
+                source_offset += data.len();
+            }
+            // Target copy, byte by byte as the copy may overlap its own output
+            _ => {
+                target_offset = reader.offset(target_offset)?;
+                for _ in 0..length {
+                    let byte = *output
+                        .get(target_offset)
+                        .ok_or_else(|| invalid_patch("target copy out of range"))?;
+                    output.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if output.len() != target_size || crc32(&[&output]) != crc(1) {
+        return Err(invalid_patch("target checksum mismatch"));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bps_number(mut value: usize, output: &mut Vec<u8>) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                output.push(byte | 0x80);
+                return;
+            }
+            output.push(byte);
+            value -= 1;
+        }
+    }
+
+    fn bps_patch(source: &[u8], target: &[u8], actions: &[(usize, usize, Option<usize>)], data: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_MAGIC.to_vec();
+        bps_number(source.len(), &mut patch);
+        bps_number(target.len(), &mut patch);
+        bps_number(0, &mut patch);
+        for &(kind, length, offset) in actions {
+            bps_number((length - 1) << 2 | kind, &mut patch);
+            if let Some(offset) = offset {
+                bps_number(offset, &mut patch);
+            }
+            if kind == 1 {
+                patch.extend_from_slice(&data[..length]);
+            }
+        }
+        patch.extend_from_slice(&crc32(&[source]).to_le_bytes());
+        patch.extend_from_slice(&crc32(&[target]).to_le_bytes());
+        let crc = crc32(&[&patch]);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_ips() -> Result<(), Error> {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]); // 2 bytes at 1
+        patch.extend_from_slice(&[0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x03, 0xCC]); // run of 3 at 6
+        patch.extend_from_slice(b"EOF");
+
+        let rom = apply_patch(&[0; 4], &patch)?;
+        assert_eq!(rom, [0x00, 0xAA, 0xBB, 0x00, 0x00, 0x00, 0xCC, 0xCC, 0xCC]);
+
+        patch.extend_from_slice(&[0x00, 0x00, 0x02]);
+        assert_eq!(apply_patch(&[0; 4], &patch)?, [0x00, 0xAA]);
+
+        let truncated = &patch[..patch.len() - 10];
+        assert_eq!(
+            apply_patch(&[0; 4], truncated).map_err(|e| e.kind()),
+            Err(ErrorKind::InvalidData)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_bps() -> Result<(), Error> {
+        let source = b"GAMEBOY ROM";
+        let target = b"GAME ROM ROM ROM!";
+        // "GAME", " ROM" copied from 7, then repeated twice, then "!"
+        let actions = [(0, 4, None), (2, 4, Some(7 << 1)), (3, 8, Some(4 << 1)), (1, 1, None)];
+        let patch = bps_patch(source, target, &actions, b"!");
+        assert_eq!(apply_patch(source, &patch)?, target);
+
+        let other = apply_patch(b"OTHER ROM!!", &patch).map_err(|e| e.to_string());
+        assert!(other.is_err_and(|e| e.contains("another rom")));
+
+        let mut corrupted = patch.clone();
+        corrupted[6] ^= 1;
+        let corrupted = apply_patch(source, &corrupted).map_err(|e| e.to_string());
+        assert!(corrupted.is_err_and(|e| e.contains("corrupted")));
+        Ok(())
+    }
+
+    /// Sizes declared by a patch cannot make the rom grow past the largest cartridge
+    #[test]
+    fn test_output_size_limit() {
+        let mut ips = b"PATCH".to_vec();
+        ips.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0x00, 0x02, 0xAA, 0xBB]); // 2 bytes at 16 MiB
+        ips.extend_from_slice(b"EOF");
+        let ips = apply_patch(&[0; 4], &ips).map_err(|e| e.to_string());
+        assert!(ips.is_err_and(|e| e.contains("larger than the largest cartridge")));
+
+        let source = b"GAMEBOY ROM";
+        let mut bps = BPS_MAGIC.to_vec();
+        bps_number(source.len(), &mut bps);
+        bps_number(1 << 40, &mut bps);
+        bps_number(0, &mut bps);
+        bps.extend_from_slice(&crc32(&[source]).to_le_bytes());
+        bps.extend_from_slice(&[0; 4]);
+        let crc = crc32(&[&bps]);
+        bps.extend_from_slice(&crc.to_le_bytes());
+        let bps = apply_patch(source, &bps).map_err(|e| e.to_string());
+        assert!(bps.is_err_and(|e| e.contains("larger than the largest cartridge")));
+    }
+
+    #[test]
+    fn test_unknown_format() {
+        assert!(apply_patch(&[0; 4], b"UPS1").is_err());
+    }
+}
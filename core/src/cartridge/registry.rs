@@ -0,0 +1,154 @@
+use crate::cartridge::mapper::{Mapper, MapperTrait};
+use crate::cartridge::mbc1::Mbc1;
+use crate::cartridge::mbc3::Mbc3;
+use crate::cartridge::mbc5::Mbc5;
+use crate::cartridge::rom_only::RomOnly;
+
+/// Cartridge characteristics given to a mapper factory, decoded from the rom header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapperConfig {
+    /// Cartridge type byte ($0147)
+    pub cartridge_type: u8,
+    pub rom_bank_count: usize,
+    pub ram_bank_count: usize,
+}
+
+type CustomFactory = Box<dyn Fn(&MapperConfig) -> Box<dyn MapperTrait + Send> + Send + Sync>;
+
+enum Factory {
+    Builtin(fn(&MapperConfig) -> Mapper),
+    Custom(CustomFactory),
+}
+
+struct Entry {
+    name: &'static str,
+    cartridge_types: Vec<u8>,
+    factory: Factory,
+}
+
+/// Associate cartridge type bytes to the mapper handling them.
+///
+/// The default registry knows every mapper supported by the emulator. Custom mappers can be
+/// registered on top of it, a later registration takes precedence for the same cartridge type.
+pub struct MapperRegistry {
+    entries: Vec<Entry>,
+}
+
+impl Default for MapperRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        RomOnly::register(&mut registry);
+        Mbc1::register(&mut registry);
+        Mbc3::register(&mut registry);
+        Mbc5::register(&mut registry);
+        registry
+    }
+}
+
+impl MapperRegistry {
+    /// Registry without any mapper
+    pub fn empty() -> Self {
+        Self { entries: vec![] }
+    }
+
+    /// Register a custom mapper for the given cartridge types.
+    pub fn register<F>(&mut self, name: &'static str, cartridge_types: &[u8], factory: F)
+    where
+        F: Fn(&MapperConfig) -> Box<dyn MapperTrait + Send> + Send + Sync + 'static,
+    {
+        self.entries.push(Entry {
+            name,
+            cartridge_types: cartridge_types.to_vec(),
+            factory: Factory::Custom(Box::new(factory)),
+        });
+    }
+
+    pub(crate) fn register_builtin(
+        &mut self,
+        name: &'static str,
+        cartridge_types: &[u8],
+        factory: fn(&MapperConfig) -> Mapper,
+    ) {
+        self.entries.push(Entry {
+            name,
+            cartridge_types: cartridge_types.to_vec(),
+            factory: Factory::Builtin(factory),
+        });
+    }
+
+    /// Name of the mapper handling the cartridge type, if any.
+    pub fn name(&self, cartridge_type: u8) -> Option<&'static str> {
+        self.find(cartridge_type).map(|entry| entry.name)
+    }
+
+    pub fn supports(&self, cartridge_type: u8) -> bool {
+        self.find(cartridge_type).is_some()
+    }
+
+    pub(crate) fn create(&self, config: &MapperConfig) -> Option<Mapper> {
+        let entry = self.find(config.cartridge_type)?;
+        let mapper = match &entry.factory {
+            Factory::Builtin(factory) => factory(config),
+            Factory::Custom(factory) => Mapper::Custom(factory(config)),
+        };
+        Some(mapper)
+    }
+
+    fn find(&self, cartridge_type: u8) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|entry| entry.cartridge_types.contains(&cartridge_type))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct FixedValue(u8);
+    impl MapperTrait for FixedValue {
+        fn read(&self, _: &[u8], _: Option<&[u8]>, _: u16) -> u8 {
+            self.0
+        }
+        fn write(&mut self, _: &[u8], _: Option<&mut [u8]>, _: u16, byte: u8) {
+            self.0 = byte;
+        }
+    }
+
+    fn config(cartridge_type: u8) -> MapperConfig {
+        MapperConfig {
+            cartridge_type,
+            rom_bank_count: 2,
+            ram_bank_count: 0,
+        }
+    }
+
+    #[test]
+    fn test_builtin_mappers() {
+        let registry = MapperRegistry::default();
+
+        assert_eq!(registry.name(0x00), Some("ROM ONLY"));
+        assert_eq!(registry.name(0x01), Some("MBC1"));
+        assert_eq!(registry.name(0x03), Some("MBC1"));
+        assert_eq!(registry.name(0x10), Some("MBC3"));
+        assert_eq!(registry.name(0x1E), Some("MBC5"));
+        assert!(!registry.supports(0xFC));
+        assert!(registry.create(&config(0xFC)).is_none());
+    }
+
+    #[test]
+    fn test_custom_mapper() {
+        let mut registry = MapperRegistry::default();
+        registry.register("FIXED", &[0xFC, 0x00], |_| Box::new(FixedValue(0x42)));
+
+        assert_eq!(registry.name(0xFC), Some("FIXED"));
+        // later registration takes precedence
+        assert_eq!(registry.name(0x00), Some("FIXED"));
+
+        let mut mapper = registry.create(&config(0xFC)).unwrap();
+        assert_eq!(mapper.read(&[], None, 0x4000), 0x42);
+        mapper.write(&[], None, 0x2000, 0x13);
+        assert_eq!(mapper.read(&[], None, 0x4000), 0x13);
+    }
+}
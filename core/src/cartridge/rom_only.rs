@@ -1,6 +1,14 @@
-use super::mapper::MapperTrait;
+use super::mapper::{Mapper, MapperTrait};
+use super::registry::MapperRegistry;
 
 pub struct RomOnly;
+
+impl RomOnly {
+    pub(crate) fn register(registry: &mut MapperRegistry) {
+        registry.register_builtin("ROM ONLY", &[0x00], |_| Mapper::RomOnly(RomOnly));
+    }
+}
+
 impl MapperTrait for RomOnly {
     fn read(&self, rom: &[u8], _: Option<&[u8]>, address: u16) -> u8 {
         match address {
@@ -0,0 +1,305 @@
+use crate::state::{Savable, StateReader, StateWriter, invalid_data};
+use std::io::Error;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Time source of the cartridge real time clock, in seconds since the UNIX epoch
+pub trait Clock: Send {
+    fn now(&self) -> u64;
+}
+
+/// Host UTC time
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+    }
+}
+
+/// Manually driven time, clones share the same time (tests, TAS)
+#[derive(Debug, Default, Clone)]
+pub struct FixedClock(Arc<AtomicU64>);
+
+impl FixedClock {
+    pub fn new(now: u64) -> Self {
+        Self(Arc::new(AtomicU64::new(now)))
+    }
+
+    pub fn set(&self, now: u64) {
+        self.0.store(now, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, seconds: u64) {
+        self.0.fetch_add(seconds, Ordering::Relaxed);
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Another clock shifted by a number of seconds, to move the in-game time.
+/// Clones share the same offset, so it can be changed while the clock is in use.
+#[derive(Debug, Default, Clone)]
+pub struct OffsetClock<C> {
+    clock: C,
+    offset: Arc<AtomicI64>,
+}
+
+impl<C: Clock> OffsetClock<C> {
+    pub fn new(clock: C, offset: i64) -> Self {
+        Self {
+            clock,
+            offset: Arc::new(AtomicI64::new(offset)),
+        }
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset.load(Ordering::Relaxed)
+    }
+
+    pub fn set_offset(&self, offset: i64) {
+        self.offset.store(offset, Ordering::Relaxed);
+    }
+}
+
+impl<C: Clock> Clock for OffsetClock<C> {
+    fn now(&self) -> u64 {
+        self.clock.now().saturating_add_signed(self.offset())
+    }
+}
+
+/// Number of RTC registers, selected with $08..$0C
+const REGISTER_COUNT: usize = 5;
+/// Writable bits of the seconds, minutes, hours, days low and days high registers
+const REGISTER_MASKS: [u8; REGISTER_COUNT] = [0x3F, 0x3F, 0x1F, 0xFF, 0xC1];
+
+const DAYS_HIGH: usize = 4;
+const DAY_BIT8: u8 = 0b0000_0001;
+const HALT: u8 = 0b0100_0000;
+const DAY_CARRY: u8 = 0b1000_0000;
+
+/// Size of the RTC footer appended to the battery save, as written by BGB
+pub(crate) const FOOTER_SIZE: usize = 48;
+/// Older footer with a 32-bit timestamp
+const FOOTER_SIZE_32: usize = 44;
+
+/// MBC3 real time clock.
+///
+/// The registers are brought up to date from the host clock when accessed, `timestamp` being
+/// the host time of the last update. The game reads a copy latched by writing 0 then 1 to $6000.
+pub(crate) struct Rtc {
+    clock: Box<dyn Clock>,
+    registers: [u8; REGISTER_COUNT],
+    latched: [u8; REGISTER_COUNT],
+    timestamp: u64,
+}
+
+impl Rtc {
+    pub fn new(clock: Box<dyn Clock>) -> Self {
+        Self {
+            timestamp: clock.now(),
+            clock,
+            registers: [0; REGISTER_COUNT],
+            latched: [0; REGISTER_COUNT],
+        }
+    }
+
+    /// Replace the time source, the clock keeps running from its current time
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.update();
+        self.timestamp = clock.now();
+        self.clock = clock;
+    }
+
+    /// Stop at day 0, 00:00:00, as a clock whose battery was removed
+    pub fn clear(&mut self) {
+        self.registers = [0; REGISTER_COUNT];
+        self.latched = [0; REGISTER_COUNT];
+        self.timestamp = self.clock.now();
+    }
+
+    fn update(&mut self) {
+        (self.registers, self.timestamp) = self.current();
+    }
+
+    /// Registers at the current host time
+    fn current(&self) -> ([u8; REGISTER_COUNT], u64) {
+        let now = self.clock.now();
+        if self.registers[DAYS_HIGH] & HALT != 0 || now <= self.timestamp {
+            return (self.registers, now);
+        }
+        (advance(self.registers, now - self.timestamp), now)
+    }
+
+    pub fn latch(&mut self) {
+        self.update();
+        self.latched = self.registers;
+    }
+
+    /// `register` is the $08..$0C bank number
+    pub fn read(&self, register: u8) -> u8 {
+        self.latched[(register - 0x08) as usize]
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        self.update();
+        let index = (register - 0x08) as usize;
+        self.registers[index] = value & REGISTER_MASKS[index];
+    }
+
+    /// Registers, latched registers (as 32-bit values) then the 64-bit timestamp, little endian
+    pub fn footer(&self) -> Vec<u8> {
+        let (registers, timestamp) = self.current();
+        let mut footer: Vec<u8> = registers
+            .iter()
+            .chain(&self.latched)
+            .flat_map(|value| (*value as u32).to_le_bytes())
+            .collect();
+        footer.extend_from_slice(&timestamp.to_le_bytes());
+        footer
+    }
+
+    pub fn load_footer(&mut self, footer: &[u8]) -> Result<(), Error> {
+        let timestamp = match footer.len() {
+            FOOTER_SIZE => u64::from_le_bytes(footer[40..48].try_into().unwrap()),
+            FOOTER_SIZE_32 => u32::from_le_bytes(footer[40..44].try_into().unwrap()) as u64,
+            _ => return Err(invalid_data("invalid rtc footer size")),
+        };
+
+        let mut values = footer[..40]
+            .chunks(4)
+            .map(|value| u32::from_le_bytes(value.try_into().unwrap()) as u8);
+        for registers in [&mut self.registers, &mut self.latched] {
+            for (register, mask) in registers.iter_mut().zip(REGISTER_MASKS) {
+                *register = values.next().unwrap_or_default() & mask;
+            }
+        }
+        self.timestamp = timestamp;
+        Ok(())
+    }
+}
+
+/// Registers after `seconds` elapsed, days overflowing 511 set the carry bit
+fn advance(registers: [u8; REGISTER_COUNT], seconds: u64) -> [u8; REGISTER_COUNT] {
+    let [s, m, h, days_low, days_high] = registers;
+
+    let seconds_total = s as u64 + seconds;
+    let minutes_total = m as u64 + seconds_total / 60;
+    let hours_total = h as u64 + minutes_total / 60;
+    let days_total = (((days_high & DAY_BIT8) as u64) << 8 | days_low as u64) + hours_total / 24;
+
+    let mut days_high = days_high & !DAY_BIT8;
+    if days_total > 0x1FF {
+        days_high |= DAY_CARRY;
+    }
+    let days = days_total & 0x1FF;
+    days_high |= (days >> 8) as u8;
+
+    [
+        (seconds_total % 60) as u8,
+        (minutes_total % 60) as u8,
+        (hours_total % 24) as u8,
+        days as u8,
+        days_high,
+    ]
+}
+
+impl Savable for Rtc {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.registers);
+        writer.write_bytes(&self.latched);
+        writer.write_u64(self.timestamp);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error> {
+        reader.read_bytes(&mut self.registers)?;
+        reader.read_bytes(&mut self.latched)?;
+        self.timestamp = reader.read_u64()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rtc(clock: &FixedClock) -> Rtc {
+        Rtc::new(Box::new(clock.clone()))
+    }
+
+    #[test]
+    fn test_time_flows_with_the_clock() {
+        let clock = FixedClock::new(1_000);
+        let mut rtc = rtc(&clock);
+
+        clock.advance(2 * 86400 + 3 * 3600 + 4 * 60 + 5);
+        rtc.latch();
+        assert_eq!([0x08, 0x09, 0x0A, 0x0B, 0x0C].map(|r| rtc.read(r)), [5, 4, 3, 2, 0]);
+
+        // The latched copy does not change until the next latch
+        clock.advance(10);
+        assert_eq!(rtc.read(0x08), 5);
+    }
+
+    #[test]
+    fn test_halt_and_day_carry() {
+        let clock = FixedClock::new(0);
+        let mut rtc = rtc(&clock);
+
+        rtc.write(0x0B, 0xFF);
+        rtc.write(0x0C, DAY_BIT8 | HALT);
+        clock.advance(86400);
+        rtc.latch();
+        assert_eq!(rtc.read(0x0B), 0xFF);
+
+        rtc.write(0x0C, DAY_BIT8);
+        clock.advance(86400);
+        rtc.latch();
+        assert_eq!(rtc.read(0x0B), 0x00);
+        assert_eq!(rtc.read(0x0C), DAY_CARRY);
+    }
+
+    #[test]
+    fn test_footer_round_trip() -> Result<(), Error> {
+        let clock = FixedClock::new(500);
+        let mut rtc = rtc(&clock);
+        rtc.write(0x09, 30);
+        rtc.latch();
+
+        let footer = rtc.footer();
+        assert_eq!(footer.len(), FOOTER_SIZE);
+        assert_eq!(&footer[40..], &500u64.to_le_bytes());
+
+        // Time elapsed while the emulator was closed is applied on load
+        clock.advance(60);
+        let mut restored = Rtc::new(Box::new(clock.clone()));
+        restored.load_footer(&footer)?;
+        assert_eq!(restored.read(0x09), 30);
+        restored.latch();
+        assert_eq!(restored.read(0x09), 31);
+
+        assert!(restored.load_footer(&footer[..20]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_offset_clock_moves_the_time() {
+        let clock = OffsetClock::new(FixedClock::new(100), 0);
+        let mut rtc = Rtc::new(Box::new(clock.clone()));
+
+        clock.set_offset(3600);
+        rtc.latch();
+        assert_eq!(rtc.read(0x0A), 1);
+
+        // Going back in time does not rewind the clock
+        clock.set_offset(0);
+        rtc.latch();
+        assert_eq!(rtc.read(0x0A), 1);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::cpu::{Cpu, Flags};
+use std::fmt;
+
+/// Registers and interrupt master enable of a [`Cpu`], see [`Cpu::state`] and [`Cpu::set_state`].
+///
+/// `{}` prints one line in the Gameboy Doctor register order, `{:#}` one register pair per line with the
+/// flags as letters.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde-state", derive(serde::Serialize, serde::Deserialize))]
+pub struct CpuState {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub ime: bool,
+    pub halted: bool,
+}
+
+impl CpuState {
+    /// Set flags as letters, `-` for a cleared one: `Z-HC`
+    pub fn flags(&self) -> String {
+        [(Flags::Z, 'Z'), (Flags::N, 'N'), (Flags::H, 'H'), (Flags::C, 'C')]
+            .iter()
+            .map(|&(flag, name)| if self.f & flag.bits() != 0 { name } else { '-' })
+            .collect()
+    }
+}
+
+impl Cpu {
+    pub fn state(&self) -> CpuState {
+        CpuState {
+            a: self.a(),
+            f: self.f(),
+            b: self.b(),
+            c: self.c(),
+            d: self.d(),
+            e: self.e(),
+            h: self.h(),
+            l: self.l(),
+            sp: self.sp,
+            pc: self.pc,
+            ime: self.ime,
+            halted: self.halted,
+        }
+    }
+
+    /// Load the registers, a pending `EI` is cancelled
+    pub fn set_state(&mut self, state: &CpuState) {
+        self.set_a(state.a);
+        self.set_f(state.f);
+        self.set_b(state.b);
+        self.set_c(state.c);
+        self.set_d(state.d);
+        self.set_e(state.e);
+        self.set_h(state.h);
+        self.set_l(state.l);
+        self.sp = state.sp;
+        self.pc = state.pc;
+        self.ime = state.ime;
+        self.ime_scheduled = false;
+        self.halted = state.halted;
+    }
+}
+
+impl fmt::Display for CpuState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            writeln!(f, "AF {:02X}{:02X}  {}", self.a, self.f, self.flags())?;
+            writeln!(f, "BC {:02X}{:02X}", self.b, self.c)?;
+            writeln!(f, "DE {:02X}{:02X}", self.d, self.e)?;
+            writeln!(f, "HL {:02X}{:02X}", self.h, self.l)?;
+            writeln!(f, "SP {:04X}", self.sp)?;
+            write!(f, "PC {:04X}  IME {}", self.pc, self.ime as u8)?;
+            if self.halted {
+                write!(f, "  HALT")?;
+            }
+            return Ok(());
+        }
+        write!(
+            f,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X}",
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l, self.sp, self.pc
+        )
+    }
+}
+
+/// Same formats as [`CpuState`]
+impl fmt::Display for Cpu {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.state(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_round_trip() {
+        let state = CpuState {
+            a: 0x12,
+            f: 0xB0,
+            b: 0x34,
+            c: 0x56,
+            d: 0x78,
+            e: 0x9A,
+            h: 0xBC,
+            l: 0xDE,
+            sp: 0xCFF0,
+            pc: 0x0150,
+            ime: true,
+            halted: true,
+        };
+        let mut cpu = Cpu::default();
+        cpu.set_state(&state);
+
+        assert_eq!(cpu.state(), state);
+        assert_eq!(cpu.hl(), 0xBCDE);
+    }
+
+    #[test]
+    fn test_display() {
+        let cpu = Cpu::default();
+
+        assert_eq!(
+            cpu.to_string(),
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100"
+        );
+        assert_eq!(
+            format!("{cpu:#}"),
+            "AF 01B0  Z-HC\nBC 0013\nDE 00D8\nHL 014D\nSP FFFE\nPC 0100  IME 0"
+        );
+    }
+}
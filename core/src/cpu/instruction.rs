@@ -3,7 +3,8 @@ use crate::cpu::addressing_mode::{CC, Op};
 use crate::cpu::instruction::Operation::*;
 use crate::cpu::{Cpu, CpuBus, Flags};
 use crate::z;
-use log::{error, trace};
+#[cfg(feature = "trace")]
+use log::trace;
 
 macro_rules! read_u16_le {
     ($data:expr) => {
@@ -43,8 +44,7 @@ macro_rules! read_operand_value_u8 {
             z!("(BC)") => $bus.read_byte($cpu.bc()),
             z!("(nn)") => $bus.read_byte(read_u16_le!($data)),
             _ => {
-                error!("op_read_u8: Unsupported operand: `{}`", $op);
-                unreachable!("Unsupported operand")
+                unreachable!("op_read_u8: Unsupported operand: `{}`", $op)
             }
         }
     };
@@ -60,8 +60,7 @@ macro_rules! read_operand_value_u16 {
             z!("SP") => $cpu.sp(),
             z!("SP+e") => $cpu.sp().wrapping_add_signed($data[0] as i16),
             _ => {
-                error!("op_read_u16: Unsupported operand: `{}`", $op);
-                unreachable!("Unsupported operand")
+                unreachable!("op_read_u16: Unsupported operand: `{}`", $op)
             }
         }
     };
@@ -91,8 +90,7 @@ macro_rules! write_to_operand_u8 {
                 $cpu.set_hl($cpu.hl().wrapping_sub(1));
             }
             _ => {
-                error!("op_write_u8: Unsupported operand: `{}`", $op);
-                unreachable!("Unsupported operand")
+                unreachable!("op_write_u8: Unsupported operand: `{}`", $op)
             }
         }
     };
@@ -107,8 +105,7 @@ macro_rules! write_to_operand_u16 {
             z!("SP") => $cpu.set_sp($value),
             z!("(nn)") => $bus.write_word(read_u16_le!($data), $value),
             _ => {
-                error!("op_write_u16: Unsupported operand: `{}`", $op);
-                unreachable!("Unsupported operand")
+                unreachable!("op_write_u16: Unsupported operand: `{}`", $op)
             }
         }
     };
@@ -216,6 +213,7 @@ impl Instruction {
 
             JP(op) => {
                 let address = read_operand_value_u16!(cpu, bus, data, op);
+                #[cfg(feature = "trace")]
                 trace!("jump to ${:04x}", address);
                 cpu.pc = address;
 
@@ -225,6 +223,7 @@ impl Instruction {
                 handle_cc_not_taken!(self, cpu, cc);
 
                 let address = read_operand_value_u16!(cpu, bus, data, op);
+                #[cfg(feature = "trace")]
                 trace!("jump to ${:04x}", address);
                 cpu.pc = address;
 
@@ -232,6 +231,7 @@ impl Instruction {
             }
             JR(op) => {
                 let offset = read_operand_value_u8!(cpu, bus, data, op) as i8; // e
+                #[cfg(feature = "trace")]
                 trace!("jump to ${:04x} {}", cpu.pc(), offset);
                 cpu.set_pc(cpu.pc().wrapping_add_signed(offset as i16));
 
@@ -241,6 +241,7 @@ impl Instruction {
                 handle_cc_not_taken!(self, cpu, cc);
 
                 let offset = read_operand_value_u8!(cpu, bus, data, op) as i8; // e
+                #[cfg(feature = "trace")]
                 trace!("jump to ${:04x} {}", cpu.pc(), offset);
                 cpu.set_pc(cpu.pc().wrapping_add_signed(offset as i16));
 
@@ -431,6 +432,7 @@ impl Instruction {
                         let value = read_operand_value_u16!(cpu, bus, data, op);
                         let result = value.wrapping_sub(1);
                         write_to_operand_u16!(cpu, bus, data, op, result);
+                        cpu.idu_value = Some(value);
                     }
                 );
 
@@ -452,6 +454,7 @@ impl Instruction {
                         let value = read_operand_value_u16!(cpu, bus, data, op);
                         let result = value.wrapping_add(1);
                         write_to_operand_u16!(cpu, bus, data, op, result);
+                        cpu.idu_value = Some(value);
                     }
                 );
 
@@ -592,6 +595,7 @@ impl Instruction {
 
                 // set pc to the address of the rst
                 cpu.set_pc(v as u16);
+                cpu.vector_call = Some(v as u16);
 
                 self.cycles
             }
@@ -607,12 +611,18 @@ impl Instruction {
                 self.cycles
             }
             HALT => {
-                cpu.set_halted(true);
+                // HALT bug: with IME clear and an interrupt pending the CPU does not halt, and the next
+                // byte is read twice
+                if !cpu.ime && !(bus.interrupt_flag() & bus.interrupt_enable()).is_empty() {
+                    cpu.halt_bug = true;
+                } else {
+                    cpu.set_halted(true);
+                }
                 self.cycles
             }
             STOP => {
                 cpu.set_stopped(true);
-                bus.write_word(0xFF04, 0x00); // reset TIMER DIV
+                bus.write_byte(0xFF04, 0x00); // reset TIMER DIV
                 self.cycles
             }
 
@@ -0,0 +1,135 @@
+//! High RAM addressing forms, `LDH (n),A` ($E0), `LDH (C),A` ($E2), `LDH A,(n)` ($F0) and `LDH A,(C)` ($F2):
+//! address `$FF00 | n` or `$FF00 | C`, cycles, and registers other than A and PC left alone, flags included.
+use crate::bus::BusIO;
+use crate::cpu::{Cpu, CpuState};
+use crate::tests::bus::TestBus;
+
+/// Code is run from WRAM, away from the $FFxx addresses
+const CODE: u16 = 0xC000;
+/// Flag combinations kept by every form
+const FLAGS: [u8; 4] = [0x00, 0xF0, 0xA0, 0x50];
+
+/// Execute `code` with A and C set, the cycles returned and the state before and after
+fn run(code: &[u8], a: u8, c: u8, f: u8, bus: &mut TestBus) -> (u8, CpuState, CpuState) {
+    bus.memory[CODE as usize..CODE as usize + code.len()].copy_from_slice(code);
+    let mut cpu = Cpu::default();
+    cpu.set_state(&CpuState {
+        a,
+        f,
+        b: 0x12,
+        c,
+        d: 0x34,
+        e: 0x56,
+        h: 0x78,
+        l: 0x9A,
+        sp: 0xDFF0,
+        pc: CODE,
+        ..CpuState::default()
+    });
+    let before = cpu.state();
+    let cycles = cpu.fetch_instruction(bus).unwrap();
+    (cycles, before, cpu.state())
+}
+
+#[test]
+fn test_ldh_n_a() {
+    for (n, address) in [(0x00, 0xFF00), (0x44, 0xFF44), (0x80, 0xFF80), (0xFF, 0xFFFF)] {
+        for f in FLAGS {
+            let mut bus = TestBus::default();
+            let (cycles, before, after) = run(&[0xE0, n], 0x42, 0x00, f, &mut bus);
+
+            assert_eq!(cycles, 12);
+            assert_eq!(bus.memory[address as usize], 0x42, "LDH (${n:02X}),A");
+            assert_eq!(after, CpuState { pc: CODE + 2, ..before });
+        }
+    }
+}
+
+#[test]
+fn test_ldh_c_a() {
+    for (c, address) in [(0x00, 0xFF00), (0x0F, 0xFF0F), (0x80, 0xFF80), (0xFF, 0xFFFF)] {
+        for f in FLAGS {
+            let mut bus = TestBus::default();
+            let (cycles, before, after) = run(&[0xE2], 0x42, c, f, &mut bus);
+
+            assert_eq!(cycles, 8);
+            assert_eq!(bus.memory[address as usize], 0x42, "LDH (C),A with C=${c:02X}");
+            // C is an address, not a pointer register: it is not incremented
+            assert_eq!(after, CpuState { pc: CODE + 1, ..before });
+        }
+    }
+}
+
+#[test]
+fn test_ldh_a_n() {
+    for (n, address) in [(0x00, 0xFF00), (0x44, 0xFF44), (0x80, 0xFF80), (0xFF, 0xFFFF)] {
+        for f in FLAGS {
+            let mut bus = TestBus::default();
+            bus.memory[address as usize] = 0x42;
+            let (cycles, before, after) = run(&[0xF0, n], 0x00, 0x00, f, &mut bus);
+
+            assert_eq!(cycles, 12);
+            let expected = CpuState {
+                a: 0x42,
+                pc: CODE + 2,
+                ..before
+            };
+            assert_eq!(after, expected, "LDH A,(${n:02X})");
+        }
+    }
+}
+
+#[test]
+fn test_ldh_a_c() {
+    for (c, address) in [(0x00, 0xFF00), (0x0F, 0xFF0F), (0x80, 0xFF80), (0xFF, 0xFFFF)] {
+        for f in FLAGS {
+            let mut bus = TestBus::default();
+            bus.memory[address as usize] = 0x42;
+            let (cycles, before, after) = run(&[0xF2], 0x00, c, f, &mut bus);
+
+            assert_eq!(cycles, 8);
+            let expected = CpuState {
+                a: 0x42,
+                pc: CODE + 1,
+                ..before
+            };
+            assert_eq!(after, expected, "LDH A,(C) with C=${c:02X}");
+        }
+    }
+}
+
+/// The operand is read from the $FFxx page, never from the page of the instruction or of an absolute address
+#[test]
+fn test_not_ld_nn() {
+    let mut bus = TestBus::default();
+    bus.memory[0x0044] = 0x11;
+    bus.memory[0xC044] = 0x22;
+    bus.memory[0xFF44] = 0x33;
+    let (_, _, after) = run(&[0xF0, 0x44], 0x00, 0x00, 0x00, &mut bus);
+    assert_eq!(after.a, 0x33);
+
+    // LD A,($FF44) reaches the same byte with one more operand byte and cycle
+    let (cycles, _, after) = run(&[0xFA, 0x44, 0xFF], 0x00, 0x00, 0x00, &mut bus);
+    assert_eq!((cycles, after.a, after.pc), (16, 0x33, CODE + 3));
+    assert_eq!(bus.read_byte(0xFF44), 0x33);
+}
+
+/// Every case of the sm83 vectors of the four opcodes, the cycle count included
+#[cfg(feature = "sm83-vectors")]
+#[test]
+fn test_vectors() {
+    use crate::cpu::sm83_test::{final_state_matches, initial_state, open_archive, read_cases};
+
+    let mut archive = open_archive("main.zip");
+    for opcode in [0xE0, 0xE2, 0xF0, 0xF2] {
+        for case in read_cases(&mut archive, &format!("{opcode:02x}.json")) {
+            let name = case["name"].as_str().unwrap_or_default();
+            let (mut cpu, mut bus) = initial_state(&case);
+            let cycles = cpu.fetch_instruction(&mut bus).unwrap();
+
+            let m_cycles = case["cycles"].as_array().map_or(0, Vec::len);
+            assert_eq!(cycles as usize, m_cycles * 4, "{name}: cycles");
+            assert!(final_state_matches(&case, &cpu, &bus), "{name}: final state");
+        }
+    }
+}
@@ -1,20 +1,27 @@
-mod addressing_mode;
+pub(crate) mod addressing_mode;
 mod cpu_bus;
+mod cpu_state;
 
 use crate::bus::Interrupt;
 use crate::cpu::addressing_mode::CC;
 pub use crate::cpu::cpu_bus::CpuBus;
+pub use crate::cpu::cpu_state::CpuState;
 use crate::cpu::register::Register16;
+use crate::state::{Savable, StateReader, StateWriter};
 use bitflags::bitflags;
 
-mod decoder;
-mod instruction;
+pub(crate) mod decoder;
+pub(crate) mod instruction;
 
 #[cfg(test)]
 mod decoder_test;
 mod display;
 mod instruction_test;
+#[cfg(test)]
+mod ldh_test;
 mod register;
+#[cfg(all(test, feature = "sm83-vectors"))]
+mod sm83_test;
 
 use crate::{cpu_decode, cpu_decode_cb};
 
@@ -36,9 +43,16 @@ pub struct Cpu {
     sp: u16,
     pc: u16,
     halted: bool,
+    /// The next opcode is read without incrementing PC, see the HALT instruction
+    halt_bug: bool,
     stopped: bool,
     ime: bool,
     ime_scheduled: bool,
+    /// Vector called by the last step: an interrupt dispatch or a `RST`
+    pub(crate) vector_call: Option<u16>,
+    /// Value of the register incremented or decremented by the last step (`INC rr`, `DEC rr`),
+    /// which triggers the OAM corruption bug when in $FE00-$FEFF
+    pub(crate) idu_value: Option<u16>,
 }
 
 impl Default for Cpu {
@@ -51,15 +65,20 @@ impl Default for Cpu {
             sp: 0xFFFE,
             pc: 0x0100,
             halted: false,
+            halt_bug: false,
             stopped: false,
             ime: false,
             ime_scheduled: false,
+            vector_call: None,
+            idu_value: None,
         }
     }
 }
 
 impl Cpu {
     pub fn step(&mut self, bus: &mut impl CpuBus) -> Result<u8, String> {
+        self.vector_call = None;
+        self.idu_value = None;
         let interrupt_cycles = self.handle_interrupt(bus);
         if interrupt_cycles > 0 {
             return Ok(interrupt_cycles);
@@ -78,7 +97,10 @@ impl Cpu {
     }
 
     pub fn fetch_instruction(&mut self, bus: &mut impl CpuBus) -> Result<u8, String> {
-        let opcode = self.pc_read_byte(bus);
+        let opcode = match std::mem::take(&mut self.halt_bug) {
+            true => bus.read_byte(self.pc),
+            false => self.pc_read_byte(bus),
+        };
 
         let instruction = cpu_decode!(opcode);
         let instruction = match instruction {
@@ -131,6 +153,7 @@ impl Cpu {
     }
 
     fn handle_interrupt(&mut self, bus: &mut impl CpuBus) -> u8 {
+        let mut wake_cycles = 0;
         if self.halted {
             let if_val = bus.interrupt_flag();
             let ie_val = bus.interrupt_enable();
@@ -141,6 +164,8 @@ impl Cpu {
                 if !self.ime {
                     return 0; // no IME, do not handle interrupt
                 }
+                // Leaving HALT takes one more M-cycle before the dispatch
+                wake_cycles = 4;
             } else {
                 return 0; // no interruptions, stay halted
             }
@@ -184,9 +209,10 @@ impl Cpu {
         // Set PC to interrupt address
         self.sp_push_word(bus, self.pc);
         self.pc = interrupt_vector;
+        self.vector_call = Some(interrupt_vector);
 
         // Processing an interrupt takes 20 cycles
-        20
+        20 + wake_cycles
     }
 
     // Registers accessors 8 bits
@@ -327,6 +353,37 @@ impl Cpu {
     }
 }
 
+impl Savable for Cpu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.af.value());
+        writer.write_u16(self.bc.value());
+        writer.write_u16(self.de.value());
+        writer.write_u16(self.hl.value());
+        writer.write_u16(self.sp);
+        writer.write_u16(self.pc);
+        writer.write_bool(self.halted);
+        writer.write_bool(self.stopped);
+        writer.write_bool(self.ime);
+        writer.write_bool(self.ime_scheduled);
+        writer.write_bool(self.halt_bug);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.af.set_value(reader.read_u16()?);
+        self.bc.set_value(reader.read_u16()?);
+        self.de.set_value(reader.read_u16()?);
+        self.hl.set_value(reader.read_u16()?);
+        self.sp = reader.read_u16()?;
+        self.pc = reader.read_u16()?;
+        self.halted = reader.read_bool()?;
+        self.stopped = reader.read_bool()?;
+        self.ime = reader.read_bool()?;
+        self.ime_scheduled = reader.read_bool()?;
+        self.halt_bug = reader.version() >= 2 && reader.read_bool()?;
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -408,6 +465,21 @@ mod tests {
         assert_eq!(actual_value, initial_value, "Stack value should be read");
     }
 
+    #[test]
+    fn test_stack_wraps_around_address_space() {
+        let mut cpu = Cpu::default();
+        let mut bus = TestBus::default();
+
+        // SP = $0001: the LSB lands at $FFFF (IE) and the MSB at $0000
+        cpu.set_sp(0x0001);
+        cpu.sp_push_word(&mut bus, 0xABCD);
+        assert_eq!(cpu.sp(), 0xFFFF);
+        assert_eq!((bus.read_byte(0xFFFF), bus.read_byte(0x0000)), (0xCD, 0xAB));
+
+        assert_eq!(cpu.sp_pop_word(&mut bus), 0xABCD);
+        assert_eq!(cpu.sp(), 0x0001);
+    }
+
     #[test]
     fn test_interrupt_handling_ime_disabled() {
         let mut cpu = Cpu::default();
@@ -436,4 +508,65 @@ mod tests {
             "Interrupt flag should remain set"
         );
     }
+
+    #[test]
+    fn test_halt_bug() {
+        let mut cpu = Cpu::default();
+        let mut bus = TestBus::default();
+        bus.set_interrupt_enable(Interrupt::TIMER);
+        bus.set_interrupt_flag(Interrupt::TIMER);
+        // HALT; INC A; INC B
+        bus.memory[0x0100..0x0103].copy_from_slice(&[0x76, 0x3C, 0x04]);
+        cpu.set_pc(0x0100);
+        cpu.set_a(0);
+        cpu.set_b(0);
+
+        cpu.step(&mut bus).unwrap();
+        assert!(!cpu.halt(), "IME clear with an interrupt pending, HALT does not halt");
+        assert_eq!(cpu.pc(), 0x0101);
+        // INC A is read twice
+        for _ in 0..3 {
+            cpu.step(&mut bus).unwrap();
+        }
+        assert_eq!((cpu.a(), cpu.b(), cpu.pc()), (2, 1, 0x0103));
+    }
+
+    #[test]
+    fn test_interrupt_from_halt_timing() {
+        let mut cpu = Cpu::default();
+        let mut bus = TestBus::default();
+        cpu.set_ime(true);
+        cpu.set_halted(true);
+        bus.set_interrupt_enable(Interrupt::VBLANK);
+
+        assert_eq!(cpu.step(&mut bus).unwrap(), 4);
+        assert!(cpu.halt());
+        bus.set_interrupt_flag(Interrupt::VBLANK);
+        // One M-cycle to wake up, then the 5 of the dispatch
+        assert_eq!(cpu.step(&mut bus).unwrap(), 24);
+        assert_eq!(cpu.pc(), 0x0040);
+        assert!(!cpu.halt());
+    }
+
+    #[test]
+    fn test_polling_ly() {
+        let mut cpu = Cpu::default();
+        let mut bus = TestBus::default();
+        let mut ly = 0u8;
+        bus.on_read(0xFF44..=0xFF44, move |_, _| {
+            ly = (ly + 1) % 154;
+            ly
+        });
+        // LDH A,(LY); CP $90; JR NZ,-6
+        bus.memory[0x0100..0x0106].copy_from_slice(&[0xF0, 0x44, 0xFE, 0x90, 0x20, 0xFA]);
+        cpu.set_pc(0x0100);
+
+        let mut steps = 0;
+        while cpu.pc() != 0x0106 {
+            cpu.step(&mut bus).unwrap();
+            steps += 1;
+        }
+        assert_eq!(cpu.a(), 0x90);
+        assert_eq!(steps, 3 * 0x90);
+    }
 }
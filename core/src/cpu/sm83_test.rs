@@ -0,0 +1,130 @@
+//! Single step tests of the SingleStepTests/sm83 vectors bundled in `fixtures/sm83`, a subset of the cases
+//! of each opcode made by `doctor/make-sm83-fixtures.sh`.
+use crate::bus::{BusIO, InterruptBus};
+use crate::cpu::Cpu;
+use crate::tests::bus::TestBus;
+use serde_json::Value;
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use zip::ZipArchive;
+
+/// Cases run per opcode, the first ones of the vector file
+const CASES_PER_OPCODE: usize = 50;
+/// Opcodes missing from the main table
+const ILLEGAL_OPCODES: [u8; 11] = [0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD];
+
+#[test]
+fn test_main_table_vectors() {
+    let names = (0..=0xFF)
+        .filter(|opcode| *opcode != 0xCB && !ILLEGAL_OPCODES.contains(opcode))
+        .map(|opcode: u8| format!("{opcode:02x}.json"));
+    run_table("main.zip", names);
+}
+
+#[test]
+fn test_cb_table_vectors() {
+    run_table("cb.zip", (0..=0xFF).map(|opcode: u8| format!("cb {opcode:02x}.json")));
+}
+
+/// Run the vectors of each file of the archive, every one of `names` must be present
+fn run_table(archive: &str, names: impl Iterator<Item = String>) {
+    let mut archive = open_archive(archive);
+
+    let mut failures = vec![];
+    for name in names {
+        let cases = read_cases(&mut archive, &name);
+        failures.extend(cases.iter().take(CASES_PER_OPCODE).filter_map(run_case));
+    }
+    assert!(
+        failures.is_empty(),
+        "{} failed: {}",
+        failures.len(),
+        failures.join(", ")
+    );
+}
+
+pub(super) fn open_archive(archive: &str) -> ZipArchive<File> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("fixtures/sm83")
+        .join(archive);
+    let file =
+        File::open(&path).unwrap_or_else(|e| panic!("{}: {e}, see doctor/make-sm83-fixtures.sh", path.display()));
+    ZipArchive::new(file).unwrap()
+}
+
+/// Cases of the vector file `name`, there is at least one
+pub(super) fn read_cases(archive: &mut ZipArchive<File>, name: &str) -> Vec<Value> {
+    let mut json = String::new();
+    archive
+        .by_name(name)
+        .unwrap_or_else(|e| panic!("{name}: {e}"))
+        .read_to_string(&mut json)
+        .unwrap();
+    let cases: Vec<Value> = serde_json::from_str(&json).unwrap();
+    assert!(!cases.is_empty(), "{name}: no case");
+    cases
+}
+
+/// Execute one instruction from the initial state, the name of the case when the final state differs
+fn run_case(case: &Value) -> Option<String> {
+    let (mut cpu, mut bus) = initial_state(case);
+    let failed = cpu.fetch_instruction(&mut bus).is_err() || !final_state_matches(case, &cpu, &bus);
+    failed.then(|| case["name"].as_str().unwrap_or_default().to_string())
+}
+
+/// Cpu and memory of the `initial` state of the case
+pub(super) fn initial_state(case: &Value) -> (Cpu, TestBus) {
+    let mut cpu = Cpu::default();
+    let mut bus = TestBus::default();
+    bus.set_interrupt_flag_u8(0x00);
+
+    let initial = &case["initial"];
+    cpu.set_pc(field(initial, "pc"));
+    cpu.set_sp(field(initial, "sp"));
+    cpu.set_a(field(initial, "a") as u8);
+    cpu.set_b(field(initial, "b") as u8);
+    cpu.set_c(field(initial, "c") as u8);
+    cpu.set_d(field(initial, "d") as u8);
+    cpu.set_e(field(initial, "e") as u8);
+    cpu.set_f(field(initial, "f") as u8);
+    cpu.set_h(field(initial, "h") as u8);
+    cpu.set_l(field(initial, "l") as u8);
+    cpu.set_ime(field(initial, "ime") == 1);
+    for (address, value) in ram(initial) {
+        bus.write_internal_byte(address, value);
+    }
+    (cpu, bus)
+}
+
+/// Registers and memory match the `final` state of the case
+pub(super) fn final_state_matches(case: &Value, cpu: &Cpu, bus: &TestBus) -> bool {
+    let expected = &case["final"];
+    let registers = [
+        (cpu.pc(), field(expected, "pc")),
+        (cpu.sp(), field(expected, "sp")),
+        (cpu.a() as u16, field(expected, "a")),
+        (cpu.b() as u16, field(expected, "b")),
+        (cpu.c() as u16, field(expected, "c")),
+        (cpu.d() as u16, field(expected, "d")),
+        (cpu.e() as u16, field(expected, "e")),
+        (cpu.f() as u16, field(expected, "f")),
+        (cpu.h() as u16, field(expected, "h")),
+        (cpu.l() as u16, field(expected, "l")),
+        (cpu.ime() as u16, field(expected, "ime")),
+    ];
+    registers.iter().all(|(actual, expected)| actual == expected)
+        && ram(expected).all(|(address, value)| bus.read_byte(address) == value)
+}
+
+fn field(state: &Value, name: &str) -> u16 {
+    state[name].as_u64().unwrap_or_else(|| panic!("missing {name}")) as u16
+}
+
+/// `[address, value]` pairs of the state
+fn ram(state: &Value) -> impl Iterator<Item = (u16, u8)> + '_ {
+    state["ram"].as_array().into_iter().flatten().map(|pair| {
+        let (address, value) = (pair[0].as_u64().unwrap(), pair[1].as_u64().unwrap());
+        (address as u16, value as u8)
+    })
+}
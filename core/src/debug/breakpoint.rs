@@ -1,8 +1,28 @@
+use crate::bus::Interrupt;
 use std::collections::HashSet;
 
+/// Vector of the first interrupt, the next ones follow every 8 bytes like the `RST` vectors
+const INTERRUPT_VECTORS: u16 = 0x0040;
+
+/// Execution budget, counted down from the moment it is set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Budget {
+    Instructions(u64),
+    Cycles(u64),
+}
+
 #[derive(Default)]
 pub struct BreakpointManager {
     breakpoints: HashSet<u16>,
+    budget: Option<Budget>,
+    budget_reached: bool,
+    /// Dispatched interrupts that break
+    interrupt_breaks: Interrupt,
+    /// `RST` vectors that break, one bit per vector ($00 is bit 0, $38 bit 7)
+    rst_breaks: u8,
+    vector_hit: bool,
+    outside_code_break: bool,
+    outside_code_hit: bool,
 }
 
 impl BreakpointManager {
@@ -22,7 +42,178 @@ impl BreakpointManager {
         self.breakpoints.len()
     }
 
+    /// Break once `count` more instructions have been executed.
+    pub fn break_after_instructions(&mut self, count: u64) {
+        self.budget = Some(Budget::Instructions(count));
+        self.budget_reached = false;
+    }
+
+    /// Break once at least `cycles` more cycles have elapsed.
+    pub fn break_after_cycles(&mut self, cycles: u64) {
+        self.budget = Some(Budget::Cycles(cycles));
+        self.budget_reached = false;
+    }
+
+    /// Instructions or cycles left before the budget break
+    pub fn remaining_budget(&self) -> Option<u64> {
+        match self.budget {
+            Some(Budget::Instructions(count) | Budget::Cycles(count)) => Some(count),
+            None => None,
+        }
+    }
+
+    pub fn clear_budget(&mut self) {
+        self.budget = None;
+        self.budget_reached = false;
+    }
+
+    /// Break when one of `interrupts` is dispatched, [`Interrupt::all`] for any, empty to disable.
+    pub fn break_on_interrupts(&mut self, interrupts: Interrupt) {
+        self.interrupt_breaks = interrupts;
+    }
+
+    pub fn interrupt_breaks(&self) -> Interrupt {
+        self.interrupt_breaks
+    }
+
+    /// Break when `RST vector` is executed, e.g. `RST $38` reached by running into $FF bytes.
+    pub fn break_on_rst(&mut self, vector: u8, enabled: bool) {
+        let bit = 1 << (vector / 8);
+        if enabled {
+            self.rst_breaks |= bit;
+        } else {
+            self.rst_breaks &= !bit;
+        }
+    }
+
+    pub fn has_rst_break(&self, vector: u8) -> bool {
+        self.rst_breaks & (1 << (vector / 8)) != 0
+    }
+
+    /// Break when PC leaves the ROM, work RAM and high RAM, e.g. runs into VRAM or OAM after a bad jump.
+    pub fn break_on_outside_code(&mut self, enabled: bool) {
+        self.outside_code_break = enabled;
+    }
+
+    pub fn has_outside_code_break(&self) -> bool {
+        self.outside_code_break
+    }
+
     pub fn clear(&mut self) {
         self.breakpoints.clear();
+        self.clear_budget();
+        self.interrupt_breaks = Interrupt::empty();
+        self.rst_breaks = 0;
+        self.vector_hit = false;
+        self.outside_code_break = false;
+        self.outside_code_hit = false;
+    }
+
+    /// Account for an executed instruction
+    pub(crate) fn consume(&mut self, cycles: u8) {
+        let remaining = match &mut self.budget {
+            Some(Budget::Instructions(count)) => {
+                *count = count.saturating_sub(1);
+                *count
+            }
+            Some(Budget::Cycles(count)) => {
+                *count = count.saturating_sub(cycles as u64);
+                *count
+            }
+            None => return,
+        };
+
+        if remaining == 0 {
+            self.budget = None;
+            self.budget_reached = true;
+        }
+    }
+
+    /// The budget ran out since the last call
+    pub(crate) fn take_budget_reached(&mut self) -> bool {
+        std::mem::take(&mut self.budget_reached)
+    }
+
+    /// Account for a call to an interrupt or `RST` vector
+    pub(crate) fn vector_called(&mut self, address: u16) {
+        self.vector_hit |= match address {
+            0x0000..=0x0038 if address & 7 == 0 => self.has_rst_break(address as u8),
+            0x0040..=0x0060 if address & 7 == 0 => {
+                let interrupt = Interrupt::from_bits_truncate(1 << ((address - INTERRUPT_VECTORS) / 8));
+                self.interrupt_breaks.contains(interrupt)
+            }
+            _ => false,
+        };
+    }
+
+    /// A vector break was hit since the last call
+    pub(crate) fn take_vector_hit(&mut self) -> bool {
+        std::mem::take(&mut self.vector_hit)
+    }
+
+    /// Account for PC entering memory that does not hold code, see [`is_code_address`]
+    pub(crate) fn left_code(&mut self) {
+        self.outside_code_hit |= self.outside_code_break;
+    }
+
+    /// An outside code break was hit since the last call
+    pub(crate) fn take_outside_code_hit(&mut self) -> bool {
+        std::mem::take(&mut self.outside_code_hit)
+    }
+}
+
+/// ROM, work RAM or high RAM, where games run code. Execution elsewhere, e.g. VRAM, OAM, IO registers or the
+/// echo RAM, is usually the symptom of a crash.
+pub(crate) fn is_code_address(address: u16) -> bool {
+    matches!(address, 0x0000..=0x7FFF | 0xC000..=0xDFFF | 0xFF80..=0xFFFE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_instruction_budget() {
+        let mut manager = BreakpointManager::default();
+        manager.break_after_instructions(2);
+
+        manager.consume(4);
+        assert!(!manager.take_budget_reached());
+        assert_eq!(manager.remaining_budget(), Some(1));
+        manager.consume(12);
+        assert!(manager.take_budget_reached());
+        assert!(!manager.take_budget_reached());
+        assert_eq!(manager.remaining_budget(), None);
+    }
+
+    #[test]
+    fn test_vector_breaks() {
+        let mut manager = BreakpointManager::default();
+        manager.break_on_interrupts(Interrupt::TIMER);
+        manager.break_on_rst(0x38, true);
+
+        manager.vector_called(0x0040); // VBlank
+        manager.vector_called(0x0008); // RST $08
+        assert!(!manager.take_vector_hit());
+        manager.vector_called(0x0050); // Timer
+        assert!(manager.take_vector_hit());
+        manager.vector_called(0x0038);
+        assert!(manager.take_vector_hit());
+        assert!(!manager.take_vector_hit());
+
+        manager.break_on_rst(0x38, false);
+        manager.vector_called(0x0038);
+        assert!(!manager.take_vector_hit());
+    }
+
+    #[test]
+    fn test_cycle_budget() {
+        let mut manager = BreakpointManager::default();
+        manager.break_after_cycles(10);
+
+        manager.consume(8);
+        assert!(!manager.take_budget_reached());
+        manager.consume(8);
+        assert!(manager.take_budget_reached());
     }
 }
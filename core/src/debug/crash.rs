@@ -0,0 +1,158 @@
+use crate::debug::disassembler::{DisassembledLine, disassemble, disassemble_range};
+use crate::debug::trace::TraceEntry;
+use crate::machine::Machine;
+use crate::state::file_stem;
+use std::fmt;
+use std::fs;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DISASSEMBLY_HISTORY: usize = 8;
+const DISASSEMBLY_AHEAD: usize = 8;
+const MEMORY_DUMP_SIZE: u16 = 0x40;
+
+/// Post-mortem report of an emulation error: registers, execution trace, disassembly and memory
+/// around PC and SP.
+#[derive(Debug, Clone)]
+pub struct CrashReport {
+    pub error: String,
+    pub game: String,
+    pub timestamp: SystemTime,
+    /// Registers when the error was raised
+    pub registers: TraceEntry,
+    /// Address of the failing instruction
+    pub crash_pc: u16,
+    /// Last executed instructions, oldest first, empty without [`Machine::set_instruction_trace`]
+    pub trace: Vec<TraceEntry>,
+    pub disassembly: Vec<DisassembledLine>,
+    pub memory_pc: MemoryDump,
+    pub memory_sp: MemoryDump,
+}
+
+#[derive(Debug, Clone)]
+pub struct MemoryDump {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+}
+
+impl MemoryDump {
+    fn around(machine: &Machine, address: u16) -> Self {
+        let start = (address & 0xFFF0).wrapping_sub(MEMORY_DUMP_SIZE / 2);
+        Self {
+            address: start,
+            bytes: (0..MEMORY_DUMP_SIZE)
+                .map(|i| machine.peek(start.wrapping_add(i)))
+                .collect(),
+        }
+    }
+}
+
+impl CrashReport {
+    pub(crate) fn new(machine: &Machine, error: &str) -> Self {
+        let registers = TraceEntry::capture(machine.cpu());
+        let trace: Vec<TraceEntry> = machine.trace().copied().collect();
+        let crash_pc = trace.last().map_or(registers.pc, |entry| entry.pc);
+
+        let read = |address| machine.peek(address);
+        let history = trace.iter().rev().skip(1).take(DISASSEMBLY_HISTORY).rev();
+        let mut disassembly: Vec<_> = history.map(|entry| disassemble(entry.pc, read)).collect();
+        disassembly.extend(disassemble_range(crash_pc, DISASSEMBLY_AHEAD, read));
+
+        Self {
+            error: error.to_string(),
+            game: machine.cartridge().title().to_string(),
+            timestamp: SystemTime::now(),
+            registers,
+            crash_pc,
+            trace,
+            disassembly,
+            memory_pc: MemoryDump::around(machine, crash_pc),
+            memory_sp: MemoryDump::around(machine, registers.sp),
+        }
+    }
+
+    /// Short description, for dialogs
+    pub fn summary(&self) -> String {
+        format!("{} at ${:04X}\n{}", self.error, self.crash_pc, self.registers)
+    }
+
+    /// Write the report as `<directory>/crash-<game>-<timestamp>.txt`
+    pub fn write(&self, directory: impl AsRef<Path>) -> Result<PathBuf, Error> {
+        let seconds = self.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let path = directory
+            .as_ref()
+            .join(format!("crash-{}-{seconds}.txt", file_stem(&self.game)));
+
+        fs::create_dir_all(directory)?;
+        fs::write(&path, self.to_string())?;
+        Ok(path)
+    }
+}
+
+impl fmt::Display for CrashReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Emulation error: {}", self.error)?;
+        writeln!(f, "Game: {}", self.game)?;
+        writeln!(f, "\n== Registers ==\n{}", self.registers)?;
+
+        writeln!(f, "\n== Disassembly ==")?;
+        for line in &self.disassembly {
+            let marker = if line.address == self.crash_pc { "=>" } else { "  " };
+            let bytes: Vec<String> = line.bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+            writeln!(
+                f,
+                "{marker} ${:04X}: {:<9} {}",
+                line.address,
+                bytes.join(" "),
+                line.text
+            )?;
+        }
+
+        for (name, dump) in [("PC", &self.memory_pc), ("SP", &self.memory_sp)] {
+            writeln!(f, "\n== Memory around {name} ==")?;
+            for (row, bytes) in dump.bytes.chunks(16).enumerate() {
+                let bytes: Vec<String> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+                writeln!(
+                    f,
+                    "${:04X}: {}",
+                    dump.address.wrapping_add(row as u16 * 16),
+                    bytes.join(" ")
+                )?;
+            }
+        }
+
+        writeln!(f, "\n== Trace (last {} instructions) ==", self.trace.len())?;
+        for entry in &self.trace {
+            writeln!(f, "{entry}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_on_unknown_opcode() -> Result<(), Box<dyn std::error::Error>> {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x0100] = 0x00; // NOP
+        rom[0x0101] = 0xD3; // unknown opcode
+        let mut machine = Machine::builder()
+            .cartridge_bytes(rom)
+            .instruction_trace(true)
+            .build()?;
+
+        machine.step()?;
+        let error = machine.step().expect_err("0xD3 is not a valid opcode");
+        let report = machine.crash_report(&error.to_string());
+
+        assert_eq!(report.crash_pc, 0x0101);
+        assert_eq!(report.trace.len(), 2);
+        let crash_line = report.disassembly.iter().find(|line| line.address == 0x0101);
+        assert_eq!(crash_line.map(|line| line.text.as_str()), Some("DB $D3"));
+        assert!(report.to_string().contains("=> $0101: D3"));
+        Ok(())
+    }
+}
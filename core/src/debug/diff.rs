@@ -0,0 +1,283 @@
+use crate::debug::trace::TraceEntry;
+use crate::machine::Machine;
+use std::fmt;
+
+/// Changed bytes closer than this are reported as one range
+const MERGE_GAP: usize = 8;
+
+/// Compared memory areas, the ROM, echo RAM and unusable area are left out
+const REGIONS: [(&str, u16, u16); 5] = [
+    ("VRAM", 0x8000, 0x9FFF),
+    ("SRAM", 0xA000, 0xBFFF),
+    ("WRAM", 0xC000, 0xDFFF),
+    ("OAM", 0xFE00, 0xFE9F),
+    ("HRAM", 0xFF80, 0xFFFE),
+];
+
+const IO_NAMES: [(u16, &str); 45] = [
+    (0xFF00, "JOYP"),
+    (0xFF01, "SB"),
+    (0xFF02, "SC"),
+    (0xFF04, "DIV"),
+    (0xFF05, "TIMA"),
+    (0xFF06, "TMA"),
+    (0xFF07, "TAC"),
+    (0xFF0F, "IF"),
+    (0xFF10, "NR10"),
+    (0xFF11, "NR11"),
+    (0xFF12, "NR12"),
+    (0xFF13, "NR13"),
+    (0xFF14, "NR14"),
+    (0xFF16, "NR21"),
+    (0xFF17, "NR22"),
+    (0xFF18, "NR23"),
+    (0xFF19, "NR24"),
+    (0xFF1A, "NR30"),
+    (0xFF1B, "NR31"),
+    (0xFF1C, "NR32"),
+    (0xFF1D, "NR33"),
+    (0xFF1E, "NR34"),
+    (0xFF20, "NR41"),
+    (0xFF21, "NR42"),
+    (0xFF22, "NR43"),
+    (0xFF23, "NR44"),
+    (0xFF24, "NR50"),
+    (0xFF25, "NR51"),
+    (0xFF26, "NR52"),
+    (0xFF40, "LCDC"),
+    (0xFF41, "STAT"),
+    (0xFF42, "SCY"),
+    (0xFF43, "SCX"),
+    (0xFF44, "LY"),
+    (0xFF45, "LYC"),
+    (0xFF46, "DMA"),
+    (0xFF47, "BGP"),
+    (0xFF48, "OBP0"),
+    (0xFF49, "OBP1"),
+    (0xFF4A, "WY"),
+    (0xFF4B, "WX"),
+    (0xFF4D, "KEY1"),
+    (0xFF4F, "VBK"),
+    (0xFF70, "SVBK"),
+    (0xFFFF, "IE"),
+];
+
+/// Registers and memory of a machine at one moment, see [`Machine::diff_since`]
+#[derive(Debug, Clone)]
+pub struct MachineSnapshot {
+    registers: TraceEntry,
+    cycles: u64,
+    frame_count: u64,
+    /// Whole address space as seen by the CPU
+    memory: Box<[u8]>,
+}
+
+impl MachineSnapshot {
+    pub(crate) fn capture(machine: &Machine) -> Self {
+        Self {
+            registers: TraceEntry::capture(machine.cpu()),
+            cycles: machine.cycles(),
+            frame_count: machine.frame_count(),
+            memory: (0..=0xFFFF).map(|address| machine.peek(address)).collect(),
+        }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterChange {
+    pub name: &'static str,
+    pub before: u16,
+    pub after: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoChange {
+    pub address: u16,
+    /// Register name, `None` for wave RAM and unnamed registers
+    pub name: Option<&'static str>,
+    pub before: u8,
+    pub after: u8,
+}
+
+/// Addresses `start..=end` of a memory region holding `changed` differing bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangedRange {
+    pub region: &'static str,
+    pub start: u16,
+    pub end: u16,
+    pub changed: usize,
+}
+
+/// What changed between a [`MachineSnapshot`] and a later state: registers, IO registers one by one and
+/// memory summarized as ranges
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    pub cycles: u64,
+    pub frames: u64,
+    pub registers: Vec<RegisterChange>,
+    pub io: Vec<IoChange>,
+    pub memory: Vec<ChangedRange>,
+}
+
+impl StateDiff {
+    pub(crate) fn new(before: &MachineSnapshot, after: &MachineSnapshot) -> Self {
+        let (old, new) = (before.registers, after.registers);
+        let registers = [
+            ("PC", old.pc, new.pc),
+            ("SP", old.sp, new.sp),
+            ("AF", old.af, new.af),
+            ("BC", old.bc, new.bc),
+            ("DE", old.de, new.de),
+            ("HL", old.hl, new.hl),
+        ]
+        .into_iter()
+        .filter(|(_, before, after)| before != after)
+        .map(|(name, before, after)| RegisterChange { name, before, after })
+        .collect();
+
+        let io = (0xFF00..0xFF80)
+            .chain([0xFFFF])
+            .filter(|address| before.memory[*address] != after.memory[*address])
+            .map(|address| IoChange {
+                address: address as u16,
+                name: IO_NAMES
+                    .iter()
+                    .find(|(io, _)| *io as usize == address)
+                    .map(|(_, name)| *name),
+                before: before.memory[address],
+                after: after.memory[address],
+            })
+            .collect();
+
+        let memory = REGIONS
+            .iter()
+            .flat_map(|&(region, start, end)| {
+                let range = start as usize..=end as usize;
+                changed_ranges(&before.memory[range.clone()], &after.memory[range])
+                    .into_iter()
+                    .map(move |(first, last, changed)| ChangedRange {
+                        region,
+                        start: start + first as u16,
+                        end: start + last as u16,
+                        changed,
+                    })
+            })
+            .collect();
+
+        Self {
+            cycles: after.cycles.saturating_sub(before.cycles),
+            frames: after.frame_count.saturating_sub(before.frame_count),
+            registers,
+            io,
+            memory,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.io.is_empty() && self.memory.is_empty()
+    }
+}
+
+/// Offsets `(first, last, changed bytes)` of the differing bytes, merged when at most [`MERGE_GAP`] apart
+fn changed_ranges(before: &[u8], after: &[u8]) -> Vec<(usize, usize, usize)> {
+    let mut ranges: Vec<(usize, usize, usize)> = vec![];
+    for offset in (0..before.len()).filter(|offset| before[*offset] != after[*offset]) {
+        match ranges.last_mut() {
+            Some((_, last, changed)) if offset - *last <= MERGE_GAP => {
+                *last = offset;
+                *changed += 1;
+            }
+            _ => ranges.push((offset, offset, 1)),
+        }
+    }
+    ranges
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} cycles, {} frames later", self.cycles, self.frames)?;
+        if self.is_empty() {
+            return writeln!(f, "No change");
+        }
+
+        if !self.registers.is_empty() {
+            writeln!(f, "\n== Registers ==")?;
+            for change in &self.registers {
+                writeln!(f, "{}: ${:04X} -> ${:04X}", change.name, change.before, change.after)?;
+            }
+        }
+        if !self.io.is_empty() {
+            writeln!(f, "\n== IO ==")?;
+            for change in &self.io {
+                let name = change.name.unwrap_or_default();
+                writeln!(
+                    f,
+                    "${:04X} {name:<4}: ${:02X} -> ${:02X}",
+                    change.address, change.before, change.after
+                )?;
+            }
+        }
+        if !self.memory.is_empty() {
+            writeln!(f, "\n== Memory ==")?;
+            for range in &self.memory {
+                writeln!(
+                    f,
+                    "{:<4} ${:04X}-${:04X}: {} bytes changed",
+                    range.region, range.start, range.end, range.changed
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_ranges_are_merged() {
+        let before = [0u8; 64];
+        let mut after = before;
+        for offset in [3, 5, 11, 40] {
+            after[offset] = 1;
+        }
+        assert_eq!(changed_ranges(&before, &after), [(3, 11, 3), (40, 40, 1)]);
+        assert!(changed_ranges(&before, &before).is_empty());
+    }
+
+    #[test]
+    fn test_diff_since_snapshot() -> Result<(), Box<dyn std::error::Error>> {
+        let rom = crate::TestRom::new()
+            .code(&[0x3E, 0x42]) // LD A,$42
+            .code(&[0xEA, 0x10, 0xC0]) // LD ($C010),A
+            .code(&[0xE0, 0x47]) // LDH ($47),A
+            .code(&[0x18, 0xFE]) // JR -2
+            .build();
+        let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+        let snapshot = machine.snapshot();
+        assert!(machine.diff_since(&snapshot).is_empty());
+
+        // NOP and JP $0150 of the entry point, then the three instructions
+        for _ in 0..5 {
+            machine.step()?;
+        }
+        let diff = machine.diff_since(&snapshot);
+        assert!(diff.cycles > 0);
+        assert!(diff.registers.iter().any(|change| change.name == "PC"));
+        assert_eq!(
+            diff.registers.iter().find(|change| change.name == "AF").unwrap().after >> 8,
+            0x42
+        );
+        let bgp = diff.io.iter().find(|change| change.address == 0xFF47).unwrap();
+        assert_eq!((bgp.name, bgp.after), (Some("BGP"), 0x42));
+        let wram: Vec<_> = diff.memory.iter().filter(|range| range.region == "WRAM").collect();
+        assert_eq!((wram[0].start, wram[0].changed), (0xC010, 1));
+        assert!(diff.to_string().contains("WRAM $C010-$C010: 1 bytes changed"));
+        Ok(())
+    }
+}
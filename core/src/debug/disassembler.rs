@@ -0,0 +1,255 @@
+use crate::cpu::Cpu;
+use crate::cpu::addressing_mode::{CC, Op, Reg};
+use crate::cpu::instruction::Operation::*;
+use crate::{cpu_decode, cpu_decode_cb, z};
+
+/// Instruction decoded at an address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisassembledLine {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    /// Mnemonic with the operands resolved, `DB $xx` for unknown opcodes
+    pub text: String,
+}
+
+/// Decode the instruction at `address`, reading memory through `read`.
+pub fn disassemble(address: u16, read: impl Fn(u16) -> u8) -> DisassembledLine {
+    let opcode = read(address);
+    let instruction = if opcode == 0xCB {
+        cpu_decode_cb!(read(address.wrapping_add(1)))
+    } else {
+        cpu_decode!(opcode)
+    };
+
+    let Some(instruction) = instruction else {
+        return DisassembledLine {
+            address,
+            bytes: vec![opcode],
+            text: format!("DB ${opcode:02X}"),
+        };
+    };
+
+    let bytes: Vec<u8> = (0..instruction.size as u16)
+        .map(|offset| read(address.wrapping_add(offset)))
+        .collect();
+    let mut text = instruction.operation.to_string();
+
+    // Operands are displayed as `n`, `nn` and `e`, replace them with the values
+    if opcode != 0xCB {
+        match bytes[1..] {
+            [low, high] => text = text.replace("nn", &format!("${:04X}", u16::from_le_bytes([low, high]))),
+            [value] if text.starts_with("JR") => {
+                let target = address.wrapping_add(2).wrapping_add_signed(value as i8 as i16);
+                text = text.replace('e', &format!("${target:04X}"));
+            }
+            [value] if text.contains('e') => {
+                let offset = value as i8;
+                text = text
+                    .replace("+e", &format!("{offset:+}"))
+                    .replace('e', &offset.to_string());
+            }
+            [value] => text = text.replace('n', &format!("${value:02X}")),
+            _ => {}
+        }
+    }
+
+    DisassembledLine { address, bytes, text }
+}
+
+/// Operation of `opcode` (`$CBxx` when prefixed) with its operands unresolved, e.g. `LD A,n`
+pub fn mnemonic(opcode: u16) -> Option<String> {
+    let instruction = match opcode {
+        0xCB00..=0xCBFF => cpu_decode_cb!(opcode as u8),
+        _ => cpu_decode!(opcode as u8),
+    };
+    instruction
+        .as_ref()
+        .map(|instruction| instruction.operation.to_string())
+}
+
+/// Decode `count` instructions starting at `address`
+pub fn disassemble_range(address: u16, count: usize, read: impl Fn(u16) -> u8) -> Vec<DisassembledLine> {
+    let mut lines = Vec::with_capacity(count);
+    let mut address = address;
+    for _ in 0..count {
+        let line = disassemble(address, &read);
+        address = address.wrapping_add(line.bytes.len() as u16);
+        lines.push(line);
+    }
+    lines
+}
+
+/// Operand values of the instruction at PC, resolved from the registers and `read` before its execution:
+/// `HL=$C123 -> $5F` for `LD A,(HL)`, `$FF44 <- $90` for `LDH ($44),A`, `taken -> $0210` or `not taken`
+/// for `JR NZ,$0210`. `None` when the text of [`disassemble`] already shows every operand.
+pub fn annotate_operands(cpu: &Cpu, read: impl Fn(u16) -> u8) -> Option<String> {
+    let pc = cpu.pc();
+    let opcode = read(pc);
+    let instruction = if opcode == 0xCB {
+        cpu_decode_cb!(read(pc.wrapping_add(1)))
+    } else {
+        cpu_decode!(opcode)
+    }
+    .as_ref()?;
+    let data = [read(pc.wrapping_add(1)), read(pc.wrapping_add(2))];
+    let stack = u16::from_le_bytes([read(cpu.sp()), read(cpu.sp().wrapping_add(1))]);
+
+    let branch = |condition: CC, target: u16| match cpu.check_condition(condition) {
+        true => format!("taken -> ${target:04X}"),
+        false => "not taken".to_string(),
+    };
+    let read_operand =
+        |op: Op| memory_operand(cpu, op, data).map(|(operand, address)| format!("{operand} -> ${:02X}", read(address)));
+
+    match instruction.operation {
+        JPcc(condition, _) | CALLcc(condition, _) => Some(branch(condition, u16::from_le_bytes(data))),
+        JRcc(condition, _) => {
+            let next = pc.wrapping_add(instruction.size as u16);
+            Some(branch(condition, next.wrapping_add_signed(data[0] as i8 as i16)))
+        }
+        RETcc(condition) => Some(branch(condition, stack)),
+        RET | RETI | POP(_) => Some(format!("-> ${stack:04X}")),
+        JP(z!("HL")) => Some(format!("-> ${:04X}", cpu.hl())),
+        LD(destination, source) | LDH(destination, source) => match memory_operand(cpu, destination, data) {
+            Some((operand, _)) => {
+                let value = match source {
+                    z!("SP") => format!("${:04X}", cpu.sp()),
+                    z!("n") => format!("${:02X}", data[0]),
+                    Op::Register(register) => format!("${:02X}", byte_register(cpu, register)?),
+                    _ => return None,
+                };
+                Some(format!("{operand} <- {value}"))
+            }
+            None => read_operand(source),
+        },
+        ADD(_, op) | ADC(_, op) | SBC(_, op) | AND(op) | CP(op) | OR(op) | SUB(op) | XOR(op) => read_operand(op),
+        INC(op) | DEC(op) | RL(op) | RLC(op) | RR(op) | RRC(op) | SLA(op) | SRA(op) | SRL(op) | SWAP(op) => {
+            read_operand(op)
+        }
+        BIT(_, op) | RES(_, op) | SET(_, op) => read_operand(op),
+        _ => None,
+    }
+}
+
+/// Memory operand shown as its pointer register (`HL=$C123`) or its address (`$FF44`), and its address
+fn memory_operand(cpu: &Cpu, op: Op, data: [u8; 2]) -> Option<(String, u16)> {
+    let address = match op {
+        z!("(C)") => 0xFF00 | cpu.c() as u16,
+        z!("(n)") => 0xFF00 | data[0] as u16,
+        z!("(nn)") => u16::from_le_bytes(data),
+        Op::RegisterIndirect(register)
+        | Op::RegisterIndirectPostIncrement(register)
+        | Op::RegisterIndirectPostDecrement(register) => {
+            let address = match register {
+                Reg::BC => cpu.bc(),
+                Reg::DE => cpu.de(),
+                Reg::HL => cpu.hl(),
+                Reg::SP => cpu.sp(),
+                _ => return None,
+            };
+            return Some((format!("{register:?}=${address:04X}"), address));
+        }
+        _ => return None,
+    };
+    Some((format!("${address:04X}"), address))
+}
+
+fn byte_register(cpu: &Cpu, register: Reg) -> Option<u8> {
+    match register {
+        Reg::A => Some(cpu.a()),
+        Reg::B => Some(cpu.b()),
+        Reg::C => Some(cpu.c()),
+        Reg::D => Some(cpu.d()),
+        Reg::E => Some(cpu.e()),
+        Reg::H => Some(cpu.h()),
+        Reg::L => Some(cpu.l()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CpuState;
+
+    fn memory(bytes: &[u8]) -> impl Fn(u16) -> u8 + '_ {
+        |address| bytes.get(address as usize).copied().unwrap_or(0)
+    }
+
+    #[test]
+    fn test_disassemble_operands() {
+        let rom = [0x3E, 0x12, 0xC3, 0x50, 0x01, 0x18, 0xFE, 0xCB, 0x7C, 0xD3];
+        let lines = disassemble_range(0, 5, memory(&rom));
+
+        let text: Vec<_> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(text, ["LD A,$12", "JP $0150", "JR $0005", "BIT 7,H", "DB $D3"]);
+        assert_eq!(lines[1].bytes, [0xC3, 0x50, 0x01]);
+        assert_eq!(lines[4].address, 0x0009);
+    }
+
+    #[test]
+    fn test_annotate_operands() {
+        let mut memory = [0u8; 0x10000];
+        memory[0xC123] = 0x5F;
+        memory[0xFF44] = 0x90;
+        memory[0xDFF0..0xDFF2].copy_from_slice(&[0x34, 0x12]);
+        let mut cpu = Cpu::default();
+        cpu.set_state(&CpuState {
+            a: 0x42,
+            f: 0x80, // Z
+            c: 0x44,
+            h: 0xC1,
+            l: 0x23,
+            sp: 0xDFF0,
+            pc: 0x0200,
+            ..CpuState::default()
+        });
+
+        let annotations: Vec<_> = [
+            &[0x7E][..],         // LD A,(HL)
+            &[0x77],             // LD (HL),A
+            &[0xF2],             // LDH A,(C)
+            &[0xE0, 0x44],       // LDH ($44),A
+            &[0x36, 0x07],       // LD (HL),$07
+            &[0xCB, 0x46],       // BIT 0,(HL)
+            &[0x20, 0x0E],       // JR NZ,$0210
+            &[0x28, 0x0E],       // JR Z,$0210
+            &[0xC8],             // RET Z
+            &[0xE9],             // JP HL
+            &[0x47],             // LD B,A
+            &[0xC3, 0x50, 0x01], // JP $0150
+        ]
+        .into_iter()
+        .map(|code| {
+            let read = |address: u16| match address.checked_sub(0x0200) {
+                Some(offset) if (offset as usize) < code.len() => code[offset as usize],
+                _ => memory[address as usize],
+            };
+            annotate_operands(&cpu, read)
+        })
+        .collect();
+
+        let expected = [
+            Some("HL=$C123 -> $5F"),
+            Some("HL=$C123 <- $42"),
+            Some("$FF44 -> $90"),
+            Some("$FF44 <- $42"),
+            Some("HL=$C123 <- $07"),
+            Some("HL=$C123 -> $5F"),
+            Some("not taken"),
+            Some("taken -> $0210"),
+            Some("taken -> $1234"),
+            Some("-> $C123"),
+            None,
+            None,
+        ];
+        assert_eq!(annotations, expected.map(|annotation| annotation.map(String::from)));
+    }
+
+    #[test]
+    fn test_mnemonic() {
+        assert_eq!(mnemonic(0x3E).as_deref(), Some("LD A,n"));
+        assert_eq!(mnemonic(0xCB7C).as_deref(), Some("BIT 7,H"));
+        assert_eq!(mnemonic(0xD3), None);
+    }
+}
@@ -0,0 +1,348 @@
+use crate::Machine;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+
+/// Condition on the machine state, e.g. `PC == $4000 && A != 0` or `[HL] >= 0x80`.
+///
+/// Operands are registers (`A`..`L`, `AF`, `BC`, `DE`, `HL`, `SP`, `PC`), flags (`ZF`, `NF`,
+/// `HF`, `CF`), numbers (`0x1F`, `$1F` or `31`) and memory bytes (`[address]`). Operators are,
+/// from the lowest precedence: `||`, `&&`, comparisons, `|`, `&`, `+ -`, and unary `!`.
+///
+/// The expression is parsed once, `&&` and `||` stop at the first decisive operand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+    Flag(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Number(u32),
+    Operand(Operand),
+    Memory(Box<Node>),
+    Not(Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let mut parser = Parser { input: source };
+        let root = parser.binary(0)?;
+        parser.skip_whitespace();
+        if !parser.input.is_empty() {
+            return Err(invalid_input(format!("unexpected `{}`", parser.input)));
+        }
+
+        Ok(Self {
+            source: source.trim().to_string(),
+            root,
+        })
+    }
+
+    /// Value of the expression, comparisons and logical operators give 0 or 1
+    pub fn evaluate(&self, machine: &Machine) -> u32 {
+        self.root.evaluate(machine)
+    }
+
+    pub fn is_true(&self, machine: &Machine) -> bool {
+        self.evaluate(machine) != 0
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Node {
+    fn evaluate(&self, machine: &Machine) -> u32 {
+        match self {
+            Node::Number(value) => *value,
+            Node::Operand(operand) => operand.evaluate(machine),
+            Node::Memory(address) => machine.peek(address.evaluate(machine) as u16) as u32,
+            Node::Not(node) => (node.evaluate(machine) == 0) as u32,
+            Node::Binary(BinaryOp::Or, a, b) => (a.evaluate(machine) != 0 || b.evaluate(machine) != 0) as u32,
+            Node::Binary(BinaryOp::And, a, b) => (a.evaluate(machine) != 0 && b.evaluate(machine) != 0) as u32,
+            Node::Binary(op, a, b) => {
+                let (a, b) = (a.evaluate(machine), b.evaluate(machine));
+                match op {
+                    BinaryOp::Eq => (a == b) as u32,
+                    BinaryOp::Ne => (a != b) as u32,
+                    BinaryOp::Lt => (a < b) as u32,
+                    BinaryOp::Le => (a <= b) as u32,
+                    BinaryOp::Gt => (a > b) as u32,
+                    BinaryOp::Ge => (a >= b) as u32,
+                    BinaryOp::BitOr => a | b,
+                    BinaryOp::BitAnd => a & b,
+                    BinaryOp::Add => a.wrapping_add(b),
+                    BinaryOp::Sub => a.wrapping_sub(b),
+                    BinaryOp::Or | BinaryOp::And => unreachable!(),
+                }
+            }
+        }
+    }
+}
+
+impl Operand {
+    fn from_name(name: &str) -> Option<Self> {
+        let operand = match name.to_ascii_uppercase().as_str() {
+            "A" => Operand::A,
+            "F" => Operand::F,
+            "B" => Operand::B,
+            "C" => Operand::C,
+            "D" => Operand::D,
+            "E" => Operand::E,
+            "H" => Operand::H,
+            "L" => Operand::L,
+            "AF" => Operand::AF,
+            "BC" => Operand::BC,
+            "DE" => Operand::DE,
+            "HL" => Operand::HL,
+            "SP" => Operand::SP,
+            "PC" => Operand::PC,
+            "ZF" => Operand::Flag(0x80),
+            "NF" => Operand::Flag(0x40),
+            "HF" => Operand::Flag(0x20),
+            "CF" => Operand::Flag(0x10),
+            _ => return None,
+        };
+        Some(operand)
+    }
+
+    fn evaluate(self, machine: &Machine) -> u32 {
+        let cpu = machine.cpu();
+        let value = match self {
+            Operand::A => cpu.a() as u16,
+            Operand::F => cpu.f() as u16,
+            Operand::B => cpu.b() as u16,
+            Operand::C => cpu.c() as u16,
+            Operand::D => cpu.d() as u16,
+            Operand::E => cpu.e() as u16,
+            Operand::H => cpu.h() as u16,
+            Operand::L => cpu.l() as u16,
+            Operand::AF => cpu.af(),
+            Operand::BC => cpu.bc(),
+            Operand::DE => cpu.de(),
+            Operand::HL => cpu.hl(),
+            Operand::SP => cpu.sp(),
+            Operand::PC => cpu.pc(),
+            Operand::Flag(mask) => (cpu.f() & mask != 0) as u16,
+        };
+        value as u32
+    }
+}
+
+/// Binary operators by precedence level, lowest first
+const LEVELS: &[&[(&str, BinaryOp)]] = &[
+    &[("||", BinaryOp::Or)],
+    &[("&&", BinaryOp::And)],
+    &[
+        ("==", BinaryOp::Eq),
+        ("!=", BinaryOp::Ne),
+        ("<=", BinaryOp::Le),
+        (">=", BinaryOp::Ge),
+        ("<", BinaryOp::Lt),
+        (">", BinaryOp::Gt),
+    ],
+    &[("|", BinaryOp::BitOr)],
+    &[("&", BinaryOp::BitAnd)],
+    &[("+", BinaryOp::Add), ("-", BinaryOp::Sub)],
+];
+
+struct Parser<'a> {
+    input: &'a str,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        self.input = self.input.trim_start();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        match self.input.strip_prefix(token) {
+            Some(rest) => {
+                self.input = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), Error> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(invalid_input(format!("expected `{token}`")))
+        }
+    }
+
+    /// Left associative operators of `LEVELS[level]` and above
+    fn binary(&mut self, level: usize) -> Result<Node, Error> {
+        let Some(operators) = LEVELS.get(level) else {
+            return self.unary();
+        };
+
+        let mut node = self.binary(level + 1)?;
+        'operators: loop {
+            self.skip_whitespace();
+            for (token, op) in operators.iter() {
+                // `|` and `&` must not take the first character of `||` and `&&`
+                let matched = self.input.starts_with(token)
+                    && !(matches!(*token, "|" | "&") && self.input[1..].starts_with(token));
+                if matched {
+                    self.input = &self.input[token.len()..];
+                    node = Node::Binary(*op, Box::new(node), Box::new(self.binary(level + 1)?));
+                    continue 'operators;
+                }
+            }
+            return Ok(node);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, Error> {
+        if self.eat("!") {
+            return Ok(Node::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let node = self.binary(0)?;
+            self.expect(")")?;
+            return Ok(node);
+        }
+        if self.eat("[") {
+            let node = self.binary(0)?;
+            self.expect("]")?;
+            return Ok(Node::Memory(Box::new(node)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Node, Error> {
+        self.skip_whitespace();
+        let end = self
+            .input
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '$' || c == '_'))
+            .unwrap_or(self.input.len());
+        let (word, rest) = self.input.split_at(end);
+        if word.is_empty() {
+            return Err(invalid_input(match self.input.chars().next() {
+                Some(c) => format!("unexpected `{c}`"),
+                None => "unexpected end of expression".to_string(),
+            }));
+        }
+
+        let node = if let Some(operand) = Operand::from_name(word) {
+            Node::Operand(operand)
+        } else {
+            Node::Number(parse_number(word).ok_or_else(|| invalid_input(format!("unknown operand `{word}`")))?)
+        };
+        self.input = rest;
+        Ok(node)
+    }
+}
+
+fn parse_number(word: &str) -> Option<u32> {
+    let word = word.replace('_', "");
+    if let Some(hex) = word.strip_prefix('$').or_else(|| word.strip_prefix("0x")) {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        word.parse().ok()
+    }
+}
+
+fn invalid_input(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestRom;
+
+    fn machine() -> Machine {
+        let rom = TestRom::new()
+            .code(&[0x3E, 0x12]) // LD A,$12
+            .code(&[0x21, 0x00, 0xC0]) // LD HL,$C000
+            .code(&[0x36, 0x99]) // LD (HL),$99
+            .code(&[0x37]) // SCF
+            .code(&[0xC3, 0x00, 0x40]) // JP $4000
+            .org(0x4000)
+            .code(&[0x18, 0xFE]) // JR -2
+            .build();
+        let mut machine = Machine::builder().cartridge_bytes(rom).build().unwrap();
+        for _ in 0..8 {
+            machine.step().unwrap();
+        }
+        machine
+    }
+
+    fn evaluate(source: &str) -> u32 {
+        Expression::parse(source).unwrap().evaluate(&machine())
+    }
+
+    #[test]
+    fn test_operands() {
+        assert_eq!(evaluate("PC"), 0x4000);
+        assert_eq!(evaluate("a"), 0x12);
+        assert_eq!(evaluate("$1F + 0x01 + 2"), 0x22);
+        assert_eq!(evaluate("[HL]"), 0x99);
+        assert_eq!(evaluate("[$C000 - 0 ]"), 0x99);
+        assert_eq!(evaluate("ZF"), 1);
+        assert_eq!(evaluate("CF"), 1);
+        assert_eq!(evaluate("NF"), 0);
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(evaluate("PC == 0x4000 && A != 0"), 1);
+        assert_eq!(evaluate("PC == 0x4000 && A == 0"), 0);
+        assert_eq!(evaluate("A == 0 || !NF"), 1);
+        assert_eq!(evaluate("A & $F0 | 1"), 0x11);
+        assert_eq!(evaluate("(A & $F0) >= $10"), 1);
+        assert_eq!(evaluate("1 + 1 == 2"), 1);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for source in ["", "PC ==", "XY == 1", "(A == 1", "[HL", "A == 1 )", "A = 1"] {
+            let error = Expression::parse(source).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput, "{source}");
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::machine::Machine;
+use crate::video::{SCREEN_WIDTH, encode_png};
+use std::fs;
+use std::io::Error;
+use std::path::PathBuf;
+
+/// Write the next frames as numbered PNG files, with a `frames.json` sidecar describing them.
+pub(crate) struct FrameDump {
+    directory: PathBuf,
+    remaining: usize,
+    /// LY when the previous frame ended
+    ly_start: u8,
+    frames: Vec<String>,
+}
+
+impl FrameDump {
+    pub fn new(directory: PathBuf, count: usize, machine: &Machine) -> Result<Self, Error> {
+        fs::create_dir_all(&directory)?;
+        Ok(Self {
+            directory,
+            remaining: count,
+            ly_start: machine.peek(0xFF44),
+            frames: vec![],
+        })
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Write the completed frame of the machine
+    pub fn record(&mut self, machine: &Machine) -> Result<(), Error> {
+        let index = self.frames.len();
+        let frame = machine.frame_count();
+        let ly_end = machine.peek(0xFF44);
+        let file = format!("frame_{index:04}_{frame:06}.png");
+
+        let rgb = machine.frame_rgb();
+        fs::write(self.directory.join(&file), encode_png(&rgb, SCREEN_WIDTH))?;
+
+        self.frames.push(format!(
+            r#"{{"index": {index}, "frame": {frame}, "file": "{file}", "ly_start": {}, "ly_end": {ly_end}}}"#,
+            self.ly_start
+        ));
+        fs::write(
+            self.directory.join("frames.json"),
+            format!("[\n  {}\n]\n", self.frames.join(",\n  ")),
+        )?;
+
+        self.ly_start = ly_end;
+        self.remaining -= 1;
+        Ok(())
+    }
+}
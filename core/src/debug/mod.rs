@@ -1 +1,10 @@
 pub mod breakpoint;
+pub mod crash;
+pub mod diff;
+pub mod disassembler;
+pub mod expression;
+pub(crate) mod frame_dump;
+#[cfg(feature = "profiling")]
+pub mod profile;
+pub mod protection;
+pub mod trace;
@@ -0,0 +1,239 @@
+use std::cell::Cell;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Memory regions of the access counters, with their last address
+const REGIONS: [(&str, u16); 11] = [
+    ("ROM0", 0x3FFF),
+    ("ROMX", 0x7FFF),
+    ("VRAM", 0x9FFF),
+    ("SRAM", 0xBFFF),
+    ("WRAM", 0xDFFF),
+    ("ECHO", 0xFDFF),
+    ("OAM", 0xFE9F),
+    ("UNUSABLE", 0xFEFF),
+    ("IO", 0xFF7F),
+    ("HRAM", 0xFFFE),
+    ("IE", 0xFFFF),
+];
+
+fn region(address: u16) -> usize {
+    REGIONS
+        .iter()
+        .position(|(_, end)| address <= *end)
+        .unwrap_or(REGIONS.len() - 1)
+}
+
+/// Bus reads and writes by memory region
+#[derive(Default)]
+pub(crate) struct AccessCounters {
+    reads: [Cell<u64>; REGIONS.len()],
+    writes: [u64; REGIONS.len()],
+}
+
+impl AccessCounters {
+    pub(crate) fn read(&self, address: u16) {
+        let counter = &self.reads[region(address)];
+        counter.set(counter.get() + 1);
+    }
+
+    pub(crate) fn write(&mut self, address: u16) {
+        self.writes[region(address)] += 1;
+    }
+}
+
+/// Executed opcodes and time spent in the CPU
+pub(crate) struct CpuCounters {
+    /// Unprefixed opcodes, then the CB prefixed ones
+    opcodes: Vec<u64>,
+    /// T-cycles spent in each opcode, same order as `opcodes`
+    cycles: Vec<u64>,
+    pub(crate) step: Timing,
+}
+
+impl Default for CpuCounters {
+    fn default() -> Self {
+        Self {
+            opcodes: vec![0; 0x200],
+            cycles: vec![0; 0x200],
+            step: Timing::default(),
+        }
+    }
+}
+
+impl CpuCounters {
+    /// `opcode` is `$CBxx` for prefixed instructions, `cycles` the T-cycles it took
+    pub(crate) fn record_opcode(&mut self, opcode: u16, cycles: u8) {
+        let index = match opcode {
+            0xCB00..=0xCBFF => 0x100 | (opcode & 0xFF),
+            _ => opcode & 0xFF,
+        } as usize;
+        self.opcodes[index] += 1;
+        self.cycles[index] += cycles as u64;
+    }
+}
+
+/// Number of runs and total duration of a measured section
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    pub count: u64,
+    pub total: Duration,
+}
+
+impl Timing {
+    pub(crate) fn record(&mut self, start: Instant) {
+        self.count += 1;
+        self.total += start.elapsed();
+    }
+
+    pub fn average(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => Duration::from_nanos((self.total.as_nanos() / count as u128) as u64),
+        }
+    }
+}
+
+/// Executions of an opcode and the T-cycles they took
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeStats {
+    /// `$CBxx` when prefixed
+    pub opcode: u16,
+    pub count: u64,
+    pub cycles: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionAccesses {
+    pub region: &'static str,
+    pub reads: u64,
+    pub writes: u64,
+}
+
+/// Counters collected since power on or [`Machine::reset_profile`](crate::Machine::reset_profile) with
+/// the `profiling` feature, see [`Machine::profile_report`](crate::Machine::profile_report).
+#[derive(Debug, Clone)]
+pub struct ProfileReport {
+    /// Executed opcodes, most frequent first
+    pub opcodes: Vec<OpcodeStats>,
+    pub accesses: Vec<RegionAccesses>,
+    pub cpu_step: Timing,
+    pub line_render: Timing,
+}
+
+impl ProfileReport {
+    pub(crate) fn new(cpu: &CpuCounters, accesses: &AccessCounters, line_render: Timing) -> Self {
+        let mut opcodes: Vec<OpcodeStats> = cpu
+            .opcodes
+            .iter()
+            .zip(&cpu.cycles)
+            .enumerate()
+            .filter(|(_, (count, _))| **count > 0)
+            .map(|(index, (&count, &cycles))| OpcodeStats {
+                opcode: match index {
+                    0x100.. => 0xCB00 | (index as u16 & 0xFF),
+                    _ => index as u16,
+                },
+                count,
+                cycles,
+            })
+            .collect();
+        opcodes.sort_by_key(|stats| std::cmp::Reverse(stats.count));
+
+        let accesses = REGIONS
+            .iter()
+            .enumerate()
+            .map(|(index, (region, _))| RegionAccesses {
+                region,
+                reads: accesses.reads[index].get(),
+                writes: accesses.writes[index],
+            })
+            .collect();
+
+        Self {
+            opcodes,
+            accesses,
+            cpu_step: cpu.step,
+            line_render,
+        }
+    }
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let timing = |f: &mut fmt::Formatter<'_>, name: &str, timing: &Timing| {
+            writeln!(
+                f,
+                "{name:<12} {:>12} runs {:>10.3?} total {:>8.1?} avg",
+                timing.count,
+                timing.total,
+                timing.average()
+            )
+        };
+        writeln!(f, "TIMINGS")?;
+        timing(f, "cpu step", &self.cpu_step)?;
+        timing(f, "line render", &self.line_render)?;
+
+        writeln!(f, "\nBUS ACCESSES")?;
+        for access in &self.accesses {
+            writeln!(
+                f,
+                "{:<12} {:>12} reads {:>12} writes",
+                access.region, access.reads, access.writes
+            )?;
+        }
+
+        let total: u64 = self.opcodes.iter().map(|stats| stats.count).sum();
+        writeln!(f, "\nOPCODES ({total} instructions)")?;
+        for stats in self.opcodes.iter().take(32) {
+            let share = stats.count as f64 * 100.0 / total as f64;
+            let opcode = if stats.opcode > 0xFF {
+                format!("{:04X}", stats.opcode)
+            } else {
+                format!("{:02X}", stats.opcode)
+            };
+            writeln!(
+                f,
+                "{opcode:<12} {:>12} {share:>6.2}% {:>14} cycles",
+                stats.count, stats.cycles
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_counts() {
+        let mut cpu = CpuCounters::default();
+        let mut accesses = AccessCounters::default();
+        for (opcode, cycles) in [(0x00, 4), (0xCB37, 8), (0x00, 4), (0xC3, 16)] {
+            cpu.record_opcode(opcode, cycles);
+        }
+        accesses.read(0x0150);
+        accesses.read(0xFF44);
+        accesses.write(0xC000);
+
+        let report = ProfileReport::new(&cpu, &accesses, Timing::default());
+        assert_eq!(
+            report.opcodes[0],
+            OpcodeStats {
+                opcode: 0x00,
+                count: 2,
+                cycles: 8
+            }
+        );
+        assert!(
+            report
+                .opcodes
+                .iter()
+                .any(|stats| stats.opcode == 0xCB37 && stats.cycles == 8)
+        );
+        let io = report.accesses.iter().find(|access| access.region == "IO");
+        assert_eq!(io.map(|access| access.reads), Some(1));
+        assert_eq!(report.accesses[4].writes, 1);
+    }
+}
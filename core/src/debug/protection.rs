@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+
+/// Write refused by a read-only region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteViolation {
+    pub address: u16,
+    pub value: u8,
+}
+
+/// Debugging guards applied to the CPU writes.
+///
+/// A frozen address ignores the writes and gets its value back every frame, e.g. for infinite lives.
+/// A read-only region ignores the writes too and reports them, the machine pauses on the next violation.
+#[derive(Default)]
+pub struct MemoryProtection {
+    frozen: BTreeMap<u16, u8>,
+    read_only: Vec<RangeInclusive<u16>>,
+    violation: Option<WriteViolation>,
+}
+
+impl MemoryProtection {
+    /// Keep `value` at `address`
+    pub fn freeze(&mut self, address: u16, value: u8) {
+        self.frozen.insert(address, value);
+    }
+
+    pub fn unfreeze(&mut self, address: u16) {
+        self.frozen.remove(&address);
+    }
+
+    pub fn frozen(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.frozen.iter().map(|(&address, &value)| (address, value))
+    }
+
+    pub fn is_frozen(&self, address: u16) -> bool {
+        self.frozen.contains_key(&address)
+    }
+
+    pub fn protect(&mut self, region: RangeInclusive<u16>) {
+        if !self.read_only.contains(&region) {
+            self.read_only.push(region);
+        }
+    }
+
+    pub fn unprotect(&mut self, region: &RangeInclusive<u16>) {
+        self.read_only.retain(|protected| protected != region);
+    }
+
+    pub fn read_only_regions(&self) -> &[RangeInclusive<u16>] {
+        &self.read_only
+    }
+
+    pub fn is_read_only(&self, address: u16) -> bool {
+        self.read_only.iter().any(|region| region.contains(&address))
+    }
+
+    pub fn clear(&mut self) {
+        self.frozen.clear();
+        self.read_only.clear();
+        self.violation = None;
+    }
+
+    /// The write must be dropped, a read-only region records the violation.
+    #[inline(always)]
+    pub(crate) fn blocks_write(&mut self, address: u16, value: u8) -> bool {
+        if self.frozen.is_empty() && self.read_only.is_empty() {
+            return false;
+        }
+        if self.is_read_only(address) {
+            self.violation = Some(WriteViolation { address, value });
+            return true;
+        }
+        self.is_frozen(address)
+    }
+
+    /// Last violation since the previous call
+    pub(crate) fn take_violation(&mut self) -> Option<WriteViolation> {
+        self.violation.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocked_writes() {
+        let mut protection = MemoryProtection::default();
+        assert!(!protection.blocks_write(0xC000, 1));
+
+        protection.freeze(0xC000, 3);
+        protection.protect(0xD000..=0xD0FF);
+        assert!(protection.blocks_write(0xC000, 1));
+        assert!(!protection.blocks_write(0xC001, 1));
+        assert_eq!(protection.take_violation(), None);
+
+        assert!(protection.blocks_write(0xD010, 7));
+        assert_eq!(
+            protection.take_violation(),
+            Some(WriteViolation {
+                address: 0xD010,
+                value: 7
+            })
+        );
+        assert_eq!(protection.take_violation(), None);
+
+        protection.unprotect(&(0xD000..=0xD0FF));
+        protection.unfreeze(0xC000);
+        assert!(!protection.blocks_write(0xD010, 7));
+        assert!(!protection.blocks_write(0xC000, 1));
+    }
+}
@@ -0,0 +1,321 @@
+use crate::bus::BusIO;
+use crate::cpu::Cpu;
+use crate::debug::disassembler::{annotate_operands, disassemble};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{Error, ErrorKind, Write};
+
+/// Number of instructions kept by the [`Trace`]
+pub const TRACE_LENGTH: usize = 1000;
+
+/// Registers before the execution of an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub sp: u16,
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+}
+
+impl TraceEntry {
+    pub(crate) fn capture(cpu: &Cpu) -> Self {
+        Self {
+            pc: cpu.pc(),
+            sp: cpu.sp(),
+            af: cpu.af(),
+            bc: cpu.bc(),
+            de: cpu.de(),
+            hl: cpu.hl(),
+        }
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "PC:{:04X} SP:{:04X} AF:{:04X} BC:{:04X} DE:{:04X} HL:{:04X}",
+            self.pc, self.sp, self.af, self.bc, self.de, self.hl
+        )
+    }
+}
+
+/// Last executed instructions, oldest first, recorded once enabled
+#[derive(Default)]
+pub(crate) struct Trace {
+    entries: VecDeque<TraceEntry>,
+    enabled: bool,
+}
+
+impl Trace {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.entries.clear();
+        }
+    }
+
+    pub fn push(&mut self, entry: TraceEntry) {
+        if self.entries.len() == TRACE_LENGTH {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Line format of the [`TraceLogger`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// Gameboy Doctor: registers and the 4 bytes at PC
+    #[default]
+    GbDoctor,
+    /// binjgb `-t` trace: registers, flags as letters and the elapsed cycles
+    Binjgb,
+    /// [`TraceEntry`] registers and the elapsed cycles
+    Custom,
+}
+
+impl TraceFormat {
+    pub const ALL: [TraceFormat; 3] = [TraceFormat::GbDoctor, TraceFormat::Binjgb, TraceFormat::Custom];
+}
+
+impl fmt::Display for TraceFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceFormat::GbDoctor => write!(f, "gbdoctor"),
+            TraceFormat::Binjgb => write!(f, "binjgb"),
+            TraceFormat::Custom => write!(f, "custom"),
+        }
+    }
+}
+
+impl std::str::FromStr for TraceFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TraceFormat::ALL
+            .into_iter()
+            .find(|format| format.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("unknown trace format: {s}")))
+    }
+}
+
+/// What the [`TraceLogger`] appends to the registers of a line
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TraceVerbosity {
+    /// The line of the [`TraceFormat`] alone, as compared by the reference logs
+    #[default]
+    Registers,
+    /// ` ; LD A,(HL)`, the instruction about to be executed
+    Disassembly,
+    /// ` ; LD A,(HL) ; HL=$C123 -> $5F`, with the operand values, see
+    /// [`annotate_operands`](crate::annotate_operands)
+    Annotated,
+}
+
+impl TraceVerbosity {
+    pub const ALL: [TraceVerbosity; 3] = [
+        TraceVerbosity::Registers,
+        TraceVerbosity::Disassembly,
+        TraceVerbosity::Annotated,
+    ];
+}
+
+impl fmt::Display for TraceVerbosity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceVerbosity::Registers => write!(f, "registers"),
+            TraceVerbosity::Disassembly => write!(f, "disassembly"),
+            TraceVerbosity::Annotated => write!(f, "annotated"),
+        }
+    }
+}
+
+impl std::str::FromStr for TraceVerbosity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TraceVerbosity::ALL
+            .into_iter()
+            .find(|verbosity| verbosity.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("unknown trace verbosity: {s}")))
+    }
+}
+
+/// Write one line per instruction, before its execution, in a [`TraceFormat`]
+pub struct TraceLogger<W: Write> {
+    writer: W,
+    format: TraceFormat,
+    verbosity: TraceVerbosity,
+    /// Cycles elapsed since the start of the log
+    cycles: u64,
+}
+
+impl<W: Write> TraceLogger<W> {
+    pub fn new(writer: W, format: TraceFormat) -> Self {
+        Self {
+            writer,
+            format,
+            verbosity: TraceVerbosity::default(),
+            cycles: 0,
+        }
+    }
+
+    pub fn format(&self) -> TraceFormat {
+        self.format
+    }
+
+    pub fn verbosity(&self) -> TraceVerbosity {
+        self.verbosity
+    }
+
+    pub fn set_verbosity(&mut self, verbosity: TraceVerbosity) {
+        self.verbosity = verbosity;
+    }
+
+    /// Count the cycles of the last executed instruction
+    pub fn advance(&mut self, cycles: u8) {
+        self.cycles += cycles as u64;
+    }
+
+    pub fn log(&mut self, cpu: &Cpu, bus: &impl BusIO) -> Result<(), Error> {
+        writeln!(self.writer, "{}", self.line(cpu, bus))
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.writer.flush()
+    }
+
+    fn line(&self, cpu: &Cpu, bus: &impl BusIO) -> String {
+        let mut line = self.registers(cpu, bus);
+        if self.verbosity >= TraceVerbosity::Disassembly {
+            let read = |address| bus.read_byte(address);
+            line.push_str(" ; ");
+            line.push_str(&disassemble(cpu.pc(), read).text);
+            if self.verbosity == TraceVerbosity::Annotated
+                && let Some(annotation) = annotate_operands(cpu, read)
+            {
+                line.push_str(" ; ");
+                line.push_str(&annotation);
+            }
+        }
+        line
+    }
+
+    fn registers(&self, cpu: &Cpu, bus: &impl BusIO) -> String {
+        match self.format {
+            TraceFormat::GbDoctor => {
+                let pc = cpu.pc();
+                let pcmem = [0, 1, 2, 3].map(|offset| bus.read_byte(pc.wrapping_add(offset)));
+                format!(
+                    "{cpu} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+                    pcmem[0], pcmem[1], pcmem[2], pcmem[3]
+                )
+            }
+            TraceFormat::Binjgb => format!(
+                "A:{:02x} F:{} BC:{:04x} DE:{:04x} HL:{:04x} SP:{:04x} PC:{:04x} (cy: {})",
+                cpu.a(),
+                cpu.state().flags(),
+                cpu.bc(),
+                cpu.de(),
+                cpu.hl(),
+                cpu.sp(),
+                cpu.pc(),
+                self.cycles
+            ),
+            TraceFormat::Custom => format!("{} CY:{}", TraceEntry::capture(cpu), self.cycles),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::bus::TestBus;
+
+    #[test]
+    fn test_trace_keeps_last_entries() {
+        let mut trace = Trace::default();
+        let mut entry = TraceEntry::capture(&Cpu::default());
+        for pc in 0..(TRACE_LENGTH as u16 + 5) {
+            entry.pc = pc;
+            trace.push(entry);
+        }
+
+        assert_eq!(trace.entries().count(), TRACE_LENGTH);
+        assert_eq!(trace.entries().next().map(|e| e.pc), Some(5));
+        assert_eq!(trace.entries().last().map(|e| e.pc), Some(TRACE_LENGTH as u16 + 4));
+    }
+
+    #[test]
+    fn test_trace_logger_formats() {
+        let mut cpu = Cpu::default();
+        cpu.reset();
+        let mut bus = TestBus::default();
+        bus.memory[0x100..0x104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]);
+
+        let lines: Vec<String> = TraceFormat::ALL
+            .into_iter()
+            .map(|format| {
+                let mut logger = TraceLogger::new(vec![], format);
+                logger.advance(4);
+                logger.log(&cpu, &bus).unwrap();
+                String::from_utf8(logger.writer).unwrap()
+            })
+            .collect();
+
+        assert_eq!(
+            lines[0],
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,50,01\n"
+        );
+        assert_eq!(
+            lines[1],
+            "A:01 F:Z-HC BC:0013 DE:00d8 HL:014d SP:fffe PC:0100 (cy: 4)\n"
+        );
+        assert_eq!(lines[2], "PC:0100 SP:FFFE AF:01B0 BC:0013 DE:00D8 HL:014D CY:4\n");
+        assert_eq!("BinJGB".parse::<TraceFormat>().ok(), Some(TraceFormat::Binjgb));
+    }
+
+    #[test]
+    fn test_trace_logger_verbosity() {
+        let mut cpu = Cpu::default();
+        cpu.reset();
+        cpu.set_hl(0xC123);
+        let mut bus = TestBus::default();
+        bus.memory[0x100] = 0x7E; // LD A,(HL)
+        bus.memory[0xC123] = 0x5F;
+
+        let lines: Vec<String> = TraceVerbosity::ALL
+            .into_iter()
+            .map(|verbosity| {
+                let mut logger = TraceLogger::new(vec![], TraceFormat::Custom);
+                logger.set_verbosity(verbosity);
+                logger.log(&cpu, &bus).unwrap();
+                String::from_utf8(logger.writer).unwrap()
+            })
+            .collect();
+
+        let registers = "PC:0100 SP:FFFE AF:01B0 BC:0013 DE:00D8 HL:C123 CY:0";
+        assert_eq!(lines[0], format!("{registers}\n"));
+        assert_eq!(lines[1], format!("{registers} ; LD A,(HL)\n"));
+        assert_eq!(lines[2], format!("{registers} ; LD A,(HL) ; HL=$C123 -> $5F\n"));
+        assert_eq!(
+            "Annotated".parse::<TraceVerbosity>().ok(),
+            Some(TraceVerbosity::Annotated)
+        );
+    }
+}
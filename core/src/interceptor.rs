@@ -0,0 +1,144 @@
+//! Address ranges handled by the embedding application, e.g. a fake link device, a debug console at a magic
+//! address or a bridge between a homebrew and the host.
+//!
+//! Interceptors see the accesses going through the bus after it routed them: a read gets the value of the bus
+//! and may replace it, a write has already been stored (or dropped by the VRAM/OAM locking and the memory
+//! protection). Those are the CPU accesses, and the IO registers read and updated by the PPU, timer, serial
+//! and joypad. The debugger accesses ([`Machine::peek`](crate::Machine::peek),
+//! [`Machine::poke`](crate::Machine::poke)) and the OAM DMA are not intercepted.
+//!
+//! Several interceptors may cover an address, they are called in registration order and each read gets the
+//! value returned by the previous one. Without interceptor an access costs a single branch, with some every
+//! access walks the registered ranges: keep them few and narrow.
+
+use std::ops::RangeInclusive;
+
+pub trait BusInterceptor: Send {
+    /// Value read at `address`, `value` is the one of the bus or of the previous interceptor
+    fn read(&self, address: u16, value: u8) -> u8 {
+        let _ = address;
+        value
+    }
+
+    /// `byte` written at `address`
+    fn write(&mut self, address: u16, byte: u8) {
+        let _ = (address, byte);
+    }
+}
+
+/// Handle of a registered interceptor, see [`Machine::remove_bus_interceptor`](crate::Machine::remove_bus_interceptor)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct InterceptorId(u32);
+
+#[derive(Default)]
+pub(crate) struct Interceptors {
+    entries: Vec<(InterceptorId, RangeInclusive<u16>, Box<dyn BusInterceptor>)>,
+    next_id: u32,
+}
+
+impl Interceptors {
+    pub(crate) fn add(&mut self, range: RangeInclusive<u16>, interceptor: Box<dyn BusInterceptor>) -> InterceptorId {
+        let id = InterceptorId(self.next_id);
+        self.next_id += 1;
+        self.entries.push((id, range, interceptor));
+        id
+    }
+
+    pub(crate) fn remove(&mut self, id: InterceptorId) -> Option<Box<dyn BusInterceptor>> {
+        let index = self.entries.iter().position(|(entry, ..)| *entry == id)?;
+        Some(self.entries.remove(index).2)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    #[inline(always)]
+    pub(crate) fn read(&self, address: u16, value: u8) -> u8 {
+        if self.entries.is_empty() {
+            return value;
+        }
+        self.entries
+            .iter()
+            .filter(|(_, range, _)| range.contains(&address))
+            .fold(value, |value, (_, _, interceptor)| interceptor.read(address, value))
+    }
+
+    #[inline(always)]
+    pub(crate) fn write(&mut self, address: u16, byte: u8) {
+        for (_, range, interceptor) in &mut self.entries {
+            if range.contains(&address) {
+                interceptor.write(address, byte);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Machine, TestRom};
+    use std::error::Error;
+    use std::sync::mpsc::{Sender, channel};
+
+    /// Debug console printing the bytes written to its port, reads as ready
+    struct Console(Sender<u8>);
+
+    impl BusInterceptor for Console {
+        fn read(&self, _: u16, _: u8) -> u8 {
+            0x01
+        }
+
+        fn write(&mut self, _: u16, byte: u8) {
+            self.0.send(byte).unwrap();
+        }
+    }
+
+    struct Xor(u8);
+
+    impl BusInterceptor for Xor {
+        fn read(&self, _: u16, value: u8) -> u8 {
+            value ^ self.0
+        }
+    }
+
+    #[test]
+    fn test_read_order() {
+        let mut interceptors = Interceptors::default();
+        assert_eq!(interceptors.read(0xC000, 0x10), 0x10);
+
+        let (sender, _receiver) = channel();
+        interceptors.add(0xC000..=0xC0FF, Box::new(Console(sender)));
+        let xor = interceptors.add(0xC000..=0xC000, Box::new(Xor(0x80)));
+        assert_eq!(interceptors.read(0xC000, 0x10), 0x81);
+        assert_eq!(interceptors.read(0xC001, 0x10), 0x01);
+        assert_eq!(interceptors.read(0xD000, 0x10), 0x10);
+
+        assert!(interceptors.remove(xor).is_some());
+        assert!(interceptors.remove(xor).is_none());
+        assert_eq!(interceptors.read(0xC000, 0x10), 0x01);
+    }
+
+    #[test]
+    fn test_cpu_accesses() -> Result<(), Box<dyn Error>> {
+        let rom = TestRom::new()
+            .code(&[0x3E, b'H']) // LD A,'H'
+            .code(&[0xEA, 0xA0, 0xFE]) // LD ($FEA0),A
+            .code(&[0x3E, b'i']) // LD A,'i'
+            .code(&[0xEA, 0xA0, 0xFE]) // LD ($FEA0),A
+            .code(&[0xFA, 0xA0, 0xFE]) // LD A,($FEA0)
+            .code(&[0xEA, 0x00, 0xC0]) // LD ($C000),A
+            .code(&[0x18, 0xFE]) // JR -2
+            .build();
+        let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+        let (sender, receiver) = channel();
+        machine.add_bus_interceptor(0xFEA0..=0xFEA0, Console(sender));
+        machine.step_frame()?;
+
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), b"Hi");
+        assert_eq!(machine.peek(0xC000), 0x01);
+        // Not intercepted
+        assert_eq!(machine.peek(0xFEA0), 0xFF);
+        Ok(())
+    }
+}
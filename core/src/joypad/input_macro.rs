@@ -0,0 +1,97 @@
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+
+/// Short sequence of pressed buttons, one entry per frame with the layout of
+/// [`Machine::pressed_buttons`](crate::Machine::pressed_buttons), replayed by
+/// [`Machine::play_macro`](crate::Machine::play_macro).
+///
+/// Written as run-length encoded hex masks, e.g. `10*3 00*2 01` is A for 3 frames, nothing for
+/// 2 frames and Right for 1 frame.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InputMacro {
+    frames: Vec<u8>,
+}
+
+impl InputMacro {
+    /// Macro of the recorded frames, without the idle frames before the first and after the last press
+    pub fn new(frames: &[u8]) -> Self {
+        let start = frames.iter().position(|&pressed| pressed != 0).unwrap_or(frames.len());
+        let end = frames
+            .iter()
+            .rposition(|&pressed| pressed != 0)
+            .map_or(start, |end| end + 1);
+        Self {
+            frames: frames[start..end].to_vec(),
+        }
+    }
+
+    pub fn frames(&self) -> &[u8] {
+        &self.frames
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+impl fmt::Display for InputMacro {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut frames = self.frames.iter().peekable();
+        let mut first = true;
+        while let Some(&pressed) = frames.next() {
+            let mut count = 1;
+            while frames.next_if_eq(&&pressed).is_some() {
+                count += 1;
+            }
+
+            if !first {
+                write!(f, " ")?;
+            }
+            first = false;
+            write!(f, "{pressed:02X}")?;
+            if count > 1 {
+                write!(f, "*{count}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for InputMacro {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |run: &str| Error::new(ErrorKind::InvalidInput, format!("invalid macro frames: {run}"));
+
+        let mut frames = vec![];
+        for run in s.split_whitespace() {
+            let (pressed, count) = run.split_once('*').unwrap_or((run, "1"));
+            let pressed = u8::from_str_radix(pressed, 16).map_err(|_| invalid(run))?;
+            let count: usize = count.parse().map_err(|_| invalid(run))?;
+            frames.extend(std::iter::repeat_n(pressed, count));
+        }
+        Ok(Self { frames })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() -> Result<(), Error> {
+        let input_macro = InputMacro::new(&[0, 0, 0x10, 0x10, 0x10, 0, 0, 0x01, 0, 0]);
+        assert_eq!(input_macro.frames(), [0x10, 0x10, 0x10, 0, 0, 0x01]);
+        assert_eq!(input_macro.to_string(), "10*3 00*2 01");
+        assert_eq!("10*3 00*2 01".parse::<InputMacro>()?, input_macro);
+
+        assert!("10*x".parse::<InputMacro>().is_err());
+        assert!(InputMacro::new(&[0, 0]).is_empty());
+        Ok(())
+    }
+}
@@ -24,3 +24,11 @@ bitflags! {
 pub(crate) trait JoypadBus: InterruptBus {
     define_flags_accessors!(p1joyp, 0xFF00, P1JOYP);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::bus::TestBus;
+
+    impl JoypadBus for TestBus {}
+}
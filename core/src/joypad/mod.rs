@@ -1,13 +1,28 @@
+mod input_macro;
 pub(crate) mod joypad_bus;
+mod sgb;
+mod turbo;
+
+pub use input_macro::InputMacro;
+pub use turbo::Turbo;
 
 use crate::bus::Interrupt;
 use crate::joypad::joypad_bus::{JoypadBus, P1JOYP};
+use crate::joypad::sgb::SgbStub;
+use crate::state::{Savable, StateReader, StateWriter};
 
 #[derive(Default)]
 pub struct Joypad {
     buttons: P1JOYP,
     d_pad: P1JOYP,
     prev: P1JOYP,
+    /// Buttons held by a playing macro on top of the host ones, same layout as [`Joypad::pressed`]
+    overlay: u8,
+    turbo: Turbo,
+    /// Held turbo buttons released during the current frame, masked from the host ones
+    turbo_released: u8,
+    /// Answers the Super Game Boy detection when set, see [`Joypad::set_sgb_stub`]
+    sgb: Option<SgbStub>,
 }
 
 impl Joypad {
@@ -19,20 +34,34 @@ impl Joypad {
         self.buttons |= P1JOYP::all();
         self.d_pad |= P1JOYP::all();
         self.prev = joyp;
+        if let Some(sgb) = &mut self.sgb {
+            *sgb = SgbStub::default();
+        }
     }
 
+    /// Refresh the low nibble from the selected groups: with both groups selected (select bits low)
+    /// a line reads low when a button of either group is pressed, with none selected it reads $F.
+    /// The unused bits 6-7 read high.
     pub fn update(&mut self, bus: &mut impl JoypadBus) {
-        let mut joyp = bus.p1joyp();
-
-        joyp |= P1JOYP::from_bits_truncate(0b0000_1111);
-        if !joyp.contains(P1JOYP::SELECT_DPAD) {
-            joyp &= self.d_pad;
+        let select = bus.p1joyp() & (P1JOYP::SELECT_DPAD | P1JOYP::SELECT_BUTTONS);
+        let mut lines = 0b0000_1111;
+        if let Some(sgb) = &mut self.sgb {
+            sgb.select(self.prev.bits() & 0x30, select.bits());
+            if select == P1JOYP::SELECT_DPAD | P1JOYP::SELECT_BUTTONS {
+                lines = sgb.joypad_id();
+            }
         }
-        if !joyp.contains(P1JOYP::SELECT_BUTTONS) {
-            joyp &= self.buttons;
+        let released = !self.pressed();
+        if !select.contains(P1JOYP::SELECT_DPAD) {
+            lines &= released;
         }
+        if !select.contains(P1JOYP::SELECT_BUTTONS) {
+            lines &= released >> 4;
+        }
+        let joyp = P1JOYP::from_bits_retain(0b1100_0000 | select.bits() | lines);
 
-        if joyp.bits() & 0x0F != self.prev.bits() & 0x0F {
+        // The interrupt is requested when a line goes from high to low
+        if self.prev.bits() & !joyp.bits() & 0x0F != 0 {
             bus.set_interrupt_flag(Interrupt::JOYPAD);
         }
 
@@ -49,6 +78,41 @@ impl Joypad {
             }
         };
     }
+    /// Pressed buttons, see [`Machine::pressed_buttons`](crate::Machine::pressed_buttons)
+    pub fn pressed(&self) -> u8 {
+        self.held() & !self.turbo_released | self.overlay
+    }
+    /// Buttons held by the host, before the turbo
+    fn held(&self) -> u8 {
+        (!self.d_pad.bits() & 0x0F) | (!self.buttons.bits() & 0x0F) << 4
+    }
+    pub fn turbo(&self) -> &Turbo {
+        &self.turbo
+    }
+    pub fn turbo_mut(&mut self) -> &mut Turbo {
+        &mut self.turbo
+    }
+    /// Start a frame of the turbo, see [`Turbo`]
+    pub(crate) fn advance_turbo(&mut self) {
+        self.turbo_released = self.turbo.advance(self.held());
+    }
+    /// Replace the pressed buttons, see [`Joypad::pressed`]
+    pub fn set_pressed(&mut self, pressed: u8) {
+        self.d_pad = P1JOYP::from_bits_retain(!pressed & 0x0F);
+        self.buttons = P1JOYP::from_bits_retain(!pressed >> 4);
+    }
+    /// Buttons pressed whatever the host does, released with 0
+    pub(crate) fn set_overlay(&mut self, pressed: u8) {
+        self.overlay = pressed;
+    }
+    /// Answer the multiplayer request games send to detect a Super Game Boy, see
+    /// [`Machine::set_sgb_stub`](crate::Machine::set_sgb_stub)
+    pub fn set_sgb_stub(&mut self, enabled: bool) {
+        self.sgb = enabled.then(SgbStub::default);
+    }
+    pub fn sgb_stub(&self) -> bool {
+        self.sgb.is_some()
+    }
     pub fn button_released(&mut self, button: Button) {
         match &button {
             Button::Up | Button::Down | Button::Left | Button::Right => {
@@ -61,7 +125,19 @@ impl Joypad {
     }
 }
 
-#[derive(Eq, Hash, PartialEq, Clone, Debug)]
+// Pressed buttons belong to the host and are not part of the state
+impl Savable for Joypad {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.prev.bits());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.prev = P1JOYP::from_bits_retain(reader.read_u8()?);
+        Ok(())
+    }
+}
+
+#[derive(Eq, Hash, PartialEq, Clone, Copy, Debug)]
 pub enum Button {
     Up,
     Down,
@@ -72,6 +148,45 @@ pub enum Button {
     Select,
     Start,
 }
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    /// Bit of the button in [`Machine::pressed_buttons`](crate::Machine::pressed_buttons)
+    pub fn bit(&self) -> usize {
+        Button::ALL.iter().position(|button| button == self).unwrap_or(0)
+    }
+}
+
+/// Buttons of the frame for an input display, see [`Machine::input_state`](crate::Machine::input_state)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-state", derive(serde::Serialize, serde::Deserialize))]
+pub struct InputState {
+    /// Layout of [`Machine::pressed_buttons`](crate::Machine::pressed_buttons)
+    pub pressed: u8,
+    /// Buttons with a turbo rate, same layout
+    pub turbo: u8,
+}
+
+impl InputState {
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.pressed & 1 << button.bit() != 0
+    }
+
+    pub fn has_turbo(&self, button: Button) -> bool {
+        self.turbo & 1 << button.bit() != 0
+    }
+}
+
 impl From<Button> for P1JOYP {
     fn from(button: Button) -> Self {
         match button {
@@ -86,3 +201,93 @@ impl From<Button> for P1JOYP {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::InterruptBus;
+    use crate::tests::bus::TestBus;
+
+    fn read_p1(joypad: &mut Joypad, bus: &mut TestBus, select: u8) -> u8 {
+        bus.memory[0xFF00] = select;
+        joypad.update(bus);
+        bus.memory[0xFF00]
+    }
+
+    fn setup() -> (Joypad, TestBus) {
+        let mut joypad = Joypad::default();
+        let mut bus = TestBus::default();
+        joypad.reset(&mut bus);
+        joypad.button_pressed(Button::Right);
+        joypad.button_pressed(Button::Start);
+        (joypad, bus)
+    }
+
+    #[test]
+    fn test_single_group_selected() {
+        let (mut joypad, mut bus) = setup();
+
+        assert_eq!(read_p1(&mut joypad, &mut bus, 0x20), 0xEE); // d-pad: RIGHT
+        assert_eq!(read_p1(&mut joypad, &mut bus, 0x10), 0xD7); // buttons: START
+    }
+
+    #[test]
+    fn test_both_groups_selected() {
+        let (mut joypad, mut bus) = setup();
+
+        // Lines of both groups are combined
+        assert_eq!(read_p1(&mut joypad, &mut bus, 0x00), 0xC6);
+        joypad.button_released(Button::Start);
+        assert_eq!(read_p1(&mut joypad, &mut bus, 0x00), 0xCE);
+    }
+
+    #[test]
+    fn test_no_group_selected() {
+        let (mut joypad, mut bus) = setup();
+
+        assert_eq!(read_p1(&mut joypad, &mut bus, 0x30), 0xFF);
+        // Written low bits are ignored
+        assert_eq!(read_p1(&mut joypad, &mut bus, 0x35), 0xFF);
+    }
+
+    #[test]
+    fn test_sgb_stub_joypad_id() {
+        let (mut joypad, mut bus) = setup();
+        joypad.set_sgb_stub(true);
+
+        assert_eq!(read_p1(&mut joypad, &mut bus, 0x30), 0xFF);
+        // Without a multiplayer request the first player stays selected after a poll
+        read_p1(&mut joypad, &mut bus, 0x20);
+        read_p1(&mut joypad, &mut bus, 0x10);
+        assert_eq!(read_p1(&mut joypad, &mut bus, 0x30), 0xFF);
+        assert!(joypad.sgb_stub());
+    }
+
+    #[test]
+    fn test_turbo_releases_held_button() {
+        let (mut joypad, mut bus) = setup();
+        joypad.turbo_mut().set_rate(Button::Start, 30);
+
+        joypad.advance_turbo();
+        assert_eq!(read_p1(&mut joypad, &mut bus, 0x10), 0xD7);
+        joypad.advance_turbo();
+        assert_eq!(read_p1(&mut joypad, &mut bus, 0x10), 0xDF);
+        assert_eq!(joypad.pressed(), 0x01);
+    }
+
+    #[test]
+    fn test_interrupt_on_falling_edge() {
+        let (mut joypad, mut bus) = setup();
+        read_p1(&mut joypad, &mut bus, 0x30);
+        bus.clear_interrupt_flag(Interrupt::JOYPAD);
+
+        // Selecting the d-pad pulls RIGHT low
+        read_p1(&mut joypad, &mut bus, 0x20);
+        assert!(bus.interrupt_flag().contains(Interrupt::JOYPAD));
+        bus.clear_interrupt_flag(Interrupt::JOYPAD);
+
+        // Deselecting releases it, no interrupt
+        read_p1(&mut joypad, &mut bus, 0x30);
+        assert!(!bus.interrupt_flag().contains(Interrupt::JOYPAD));
+    }
+}
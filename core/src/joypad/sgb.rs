@@ -0,0 +1,149 @@
+/// `MLT_REQ`, the command games send to detect a Super Game Boy
+const MLT_REQ: u8 = 0x11;
+/// Bits of a packet, the stop bit follows
+const PACKET_BITS: usize = 128;
+
+/// Super Game Boy answer to the multiplayer request, for the games that wait for it on a DMG.
+///
+/// Packets are sent through P1: a reset pulse (both select bits low) then 128 bits, a `0` pulls P14 low and a
+/// `1` pulls P15 low, each followed by both lines high, and a `0` stop bit. `MLT_REQ` sets the number of
+/// players, every other command is ignored. With both select bits high P1 reads the id of the current player,
+/// `$F` for the first one, and the next player is selected each time P15 goes back high.
+#[derive(Debug, Clone)]
+pub(crate) struct SgbStub {
+    packet: [u8; 16],
+    /// Bits received of the current packet, `None` outside a transfer
+    bit: Option<usize>,
+    /// Packets left of a multi packets command, skipped
+    skipped: u8,
+    players: u8,
+    player: u8,
+}
+
+impl Default for SgbStub {
+    fn default() -> Self {
+        Self {
+            packet: [0; 16],
+            bit: None,
+            skipped: 0,
+            players: 1,
+            player: 0,
+        }
+    }
+}
+
+impl SgbStub {
+    /// Follow a change of the select bits (`P1` bits 4-5)
+    pub(crate) fn select(&mut self, previous: u8, select: u8) {
+        if previous == select {
+            return;
+        }
+        match (select, self.bit) {
+            (0x00, _) => {
+                self.packet = [0; 16];
+                self.bit = Some(0);
+            }
+            (0x10 | 0x20, Some(PACKET_BITS)) => {
+                self.bit = None;
+                if select == 0x20 {
+                    self.receive();
+                }
+            }
+            (0x10 | 0x20, Some(bit)) if previous == 0x30 => {
+                if select == 0x10 {
+                    self.packet[bit / 8] |= 1 << (bit % 8);
+                }
+                self.bit = Some(bit + 1);
+            }
+            (0x30, None) if previous == 0x10 => self.player = (self.player + 1) % self.players,
+            _ => {}
+        }
+    }
+
+    /// P1 low nibble read with both select bits high
+    pub(crate) fn joypad_id(&self) -> u8 {
+        0x0F - self.player
+    }
+
+    fn receive(&mut self) {
+        if self.skipped > 0 {
+            self.skipped -= 1;
+            return;
+        }
+        let (command, length) = (self.packet[0] >> 3, self.packet[0] & 0x07);
+        self.skipped = length.saturating_sub(1);
+        if command == MLT_REQ {
+            self.players = match self.packet[1] & 0x03 {
+                0 => 1,
+                1 => 2,
+                _ => 4,
+            };
+            self.player = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(sgb: &mut SgbStub, packet: [u8; 16]) {
+        let pulse = |sgb: &mut SgbStub, select: u8| {
+            sgb.select(0x30, select);
+            sgb.select(select, 0x30);
+        };
+        pulse(sgb, 0x00);
+        for bit in 0..PACKET_BITS {
+            let one = (packet[bit / 8] >> (bit % 8)) & 1 == 1;
+            pulse(sgb, if one { 0x10 } else { 0x20 });
+        }
+        pulse(sgb, 0x20);
+    }
+
+    fn poll(sgb: &mut SgbStub) -> u8 {
+        sgb.select(0x30, 0x20);
+        sgb.select(0x20, 0x10);
+        sgb.select(0x10, 0x30);
+        sgb.joypad_id()
+    }
+
+    fn mlt_req(players: u8) -> [u8; 16] {
+        let mut packet = [0; 16];
+        packet[0] = MLT_REQ << 3 | 1;
+        packet[1] = players;
+        packet
+    }
+
+    #[test]
+    fn test_single_player_by_default() {
+        let mut sgb = SgbStub::default();
+
+        assert_eq!(sgb.joypad_id(), 0x0F);
+        assert_eq!(poll(&mut sgb), 0x0F);
+    }
+
+    #[test]
+    fn test_mlt_req() {
+        let mut sgb = SgbStub::default();
+
+        send(&mut sgb, mlt_req(1));
+        assert_eq!(sgb.joypad_id(), 0x0F);
+        assert_eq!(poll(&mut sgb), 0x0E);
+        assert_eq!(poll(&mut sgb), 0x0F);
+
+        send(&mut sgb, mlt_req(0));
+        assert_eq!(poll(&mut sgb), 0x0F);
+    }
+
+    #[test]
+    fn test_multi_packets_command_skipped() {
+        let mut sgb = SgbStub::default();
+        let mut attr_blk = [0; 16];
+        attr_blk[0] = 0x04 << 3 | 2;
+
+        send(&mut sgb, attr_blk);
+        // Second packet of ATTR_BLK, its first byte is not a command
+        send(&mut sgb, mlt_req(1));
+        assert_eq!(poll(&mut sgb), 0x0F);
+    }
+}
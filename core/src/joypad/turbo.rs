@@ -0,0 +1,74 @@
+use crate::joypad::Button;
+
+/// Frames per second the turbo rates are counted in
+const FRAME_RATE: u32 = 60;
+
+/// Auto fire of held buttons: a button with a rate is pressed and released `rate` times per second while
+/// it is held.
+///
+/// The buttons are in the layout of [`Machine::pressed_buttons`](crate::Machine::pressed_buttons), the
+/// fastest rate is 30 presses per second, one frame pressed and one released.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Turbo {
+    /// Presses per second of each button, 0 without turbo
+    rates: [u8; 8],
+    /// Frames each button has been held
+    held_frames: [u32; 8],
+}
+
+impl Turbo {
+    pub fn rate(&self, button: Button) -> u8 {
+        self.rates[button.bit()]
+    }
+
+    pub fn set_rate(&mut self, button: Button, rate: u8) {
+        self.rates[button.bit()] = rate;
+    }
+
+    /// Buttons with a rate
+    pub fn buttons(&self) -> u8 {
+        self.rates
+            .iter()
+            .enumerate()
+            .filter(|(_, rate)| **rate > 0)
+            .fold(0, |buttons, (bit, _)| buttons | 1 << bit)
+    }
+
+    /// Count a frame with the `held` buttons, returns the turbo buttons released during the frame
+    pub(crate) fn advance(&mut self, held: u8) -> u8 {
+        let mut released = 0;
+        for (bit, (rate, frames)) in self.rates.iter().zip(&mut self.held_frames).enumerate() {
+            if *rate == 0 || held & 1 << bit == 0 {
+                *frames = 0;
+                continue;
+            }
+            let half_period = (FRAME_RATE / (2 * *rate as u32)).max(1);
+            if (*frames / half_period) % 2 == 1 {
+                released |= 1 << bit;
+            }
+            *frames += 1;
+        }
+        released
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_turbo_rate() {
+        let mut turbo = Turbo::default();
+        turbo.set_rate(Button::A, 10);
+        turbo.set_rate(Button::B, 30);
+        assert_eq!(turbo.buttons(), 0x30);
+
+        // 10 per second: 3 frames pressed, 3 released. The first frame is always a press.
+        let a: Vec<_> = (0..8).map(|_| turbo.advance(0x11) & 0x10 != 0).collect();
+        assert_eq!(a, [false, false, false, true, true, true, false, false]);
+        // Releasing the button restarts the period, a button without rate is never released
+        assert_eq!(turbo.advance(0x01), 0);
+        let b: Vec<_> = (0..4).map(|_| turbo.advance(0x20)).collect();
+        assert_eq!(b, [0, 0x20, 0, 0x20]);
+    }
+}
@@ -1,18 +1,64 @@
+pub(crate) mod accuracy;
+mod apu;
+mod build_info;
 pub(crate) mod bus;
 pub(crate) mod cartridge;
 pub(crate) mod cpu;
 pub(crate) mod debug;
+mod interceptor;
 pub(crate) mod joypad;
 pub(crate) mod machine;
+mod metrics;
+pub(crate) mod model;
 pub(crate) mod ppu;
+pub(crate) mod ram_init;
+pub(crate) mod rng;
+mod serial;
+pub mod state;
 mod tests;
 mod timer;
+pub mod video;
 
+pub use accuracy::{Accuracy, AccuracyProfile};
+pub use apu::{
+    Apu, AudioChannels, AudioChunk, AudioOutput, AudioSettings, AudioSink, AudioStream, NullAudioSink, Resampler,
+    ResamplerQuality, SAMPLE_RATE, StreamSink, WavSink,
+};
+pub use build_info::{BuildInfo, build_info};
 pub use bus::*;
-pub use cpu::{Cpu, CpuBus, Flags as CpuFlags};
-pub use joypad::Button as JoypadButton;
-pub use machine::Machine;
+pub use cartridge::{
+    Clock, FixedClock, MAX_ROM_SIZE, MapperConfig, MapperRegistry, MapperState, MapperTrait, OffsetClock, SystemClock,
+    apply_patch,
+};
+pub use cpu::{Cpu, CpuBus, CpuState, Flags as CpuFlags};
+pub use debug::crash::{CrashReport, MemoryDump};
+pub use debug::diff::{ChangedRange, IoChange, MachineSnapshot, RegisterChange, StateDiff};
+pub use debug::disassembler::{DisassembledLine, annotate_operands, disassemble, disassemble_range, mnemonic};
+pub use debug::expression::Expression;
+#[cfg(feature = "profiling")]
+pub use debug::profile::{OpcodeStats, ProfileReport, RegionAccesses, Timing as ProfileTiming};
+pub use debug::protection::{MemoryProtection, WriteViolation};
+pub use debug::trace::{TRACE_LENGTH, TraceEntry, TraceFormat, TraceLogger, TraceVerbosity};
+pub use interceptor::{BusInterceptor, InterceptorId};
+pub use joypad::{Button as JoypadButton, InputMacro, InputState, Turbo};
+pub use machine::{
+    CPU_CLOCK_HZ, CYCLES_PER_FRAME, DEFAULT_SRAM_FLUSH_DELAY, DETERMINISTIC_RTC_TIME, EmulationStatus, FRAME_RATE,
+    FramePacer, FrameResult, InterestingAddress, LinkCable, MAX_STATE_SIZE, Machine, MachineBuilder, MachineEvent,
+    PacingMode, PowerCycleOptions, RomPreview, rom_preview,
+};
+pub use metrics::{MetricsRecorder, MetricsReporter};
+pub use model::Model;
+pub use ppu::{ChangedLines, ChangedTiles, ColorPalette, Layers, Palette, PpuMode, PpuModel, PpuSnapshot, TILE_COUNT};
+pub use ram_init::RamInit;
+pub use rng::Rng;
+pub use serial::{Serial, SerialDevice};
 pub use timer::Timer;
 
 #[cfg(any(test, feature = "test-bus"))]
 pub use crate::tests::bus::TestBus;
+#[cfg(any(test, feature = "test-roms"))]
+pub use crate::tests::regress::RegressCase;
+#[cfg(any(test, feature = "test-roms"))]
+pub use crate::tests::rom::TestRom;
+#[cfg(any(test, feature = "test-roms"))]
+pub use crate::tests::verdict::TestVerdict;
@@ -0,0 +1,44 @@
+use crate::machine::Machine;
+use crate::ppu::{LcdControl, PpuBus};
+
+/// Named address worth jumping to from a memory view, see [`Machine::interesting_addresses`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterestingAddress {
+    pub name: &'static str,
+    pub address: u16,
+}
+
+impl InterestingAddress {
+    const fn new(name: &'static str, address: u16) -> Self {
+        Self { name, address }
+    }
+}
+
+impl Machine {
+    /// Registers pointing into memory and the areas the PPU currently fetches from, e.g. `PC`,
+    /// `SP` or `BG MAP`, in a stable order
+    pub fn interesting_addresses(&self) -> Vec<InterestingAddress> {
+        let cpu = self.cpu();
+        let lcdc = self.bus.lcdc();
+        let tile_map = |flag| if lcdc.contains(flag) { 0x9C00 } else { 0x9800 };
+
+        vec![
+            InterestingAddress::new("PC", cpu.pc()),
+            InterestingAddress::new("SP", cpu.sp()),
+            InterestingAddress::new("HL", cpu.hl()),
+            InterestingAddress::new("BC", cpu.bc()),
+            InterestingAddress::new("DE", cpu.de()),
+            InterestingAddress::new(
+                "TILES",
+                if lcdc.contains(LcdControl::TILEDATA_AREA) {
+                    0x8000
+                } else {
+                    0x8800
+                },
+            ),
+            InterestingAddress::new("BG MAP", tile_map(LcdControl::TILEMAP_AREA)),
+            InterestingAddress::new("WIN MAP", tile_map(LcdControl::WINDOW_TILE_MAP)),
+            InterestingAddress::new("DMA", (self.bus.read_internal_byte(0xFF46) as u16) << 8),
+        ]
+    }
+}
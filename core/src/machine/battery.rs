@@ -0,0 +1,215 @@
+use crate::cartridge::Cartridge;
+use crate::state::file_stem;
+use log::{info, warn};
+use std::fs;
+use std::io::{Error, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// Frames without external ram writes before the battery save is written
+pub const DEFAULT_SRAM_FLUSH_DELAY: u32 = 60;
+
+/// Persist the battery backed ram of a cartridge to `<directory>/<game>.sav`.
+///
+/// Writes are debounced: the file is written once the game stopped writing to the ram
+/// for `flush_delay` frames, and on [`BatterySave::flush`].
+pub(crate) struct BatterySave {
+    path: PathBuf,
+    flush_delay: u32,
+    idle_frames: u32,
+    pending: bool,
+}
+
+impl BatterySave {
+    /// `None` when the cartridge has no battery
+    pub fn new(directory: &Path, cartridge: &Cartridge, flush_delay: u32) -> Option<Self> {
+        if !cartridge.has_battery() {
+            return None;
+        }
+
+        Some(Self {
+            path: directory.join(format!("{}.sav", file_stem(cartridge.title()))),
+            flush_delay,
+            idle_frames: 0,
+            pending: false,
+        })
+    }
+
+    /// Restore the ram from the save file, a missing file is not an error.
+    pub fn load(&self, cartridge: &mut Cartridge) -> Result<(), Error> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        info!("Loading battery save: {:?}", self.path);
+        cartridge
+            .load_battery_data(&data)
+            .inspect_err(|e| warn!("Ignoring {:?}: {e}", self.path))
+    }
+
+    /// Called once per frame with the ram writes of the frame, write the file when the ram stays
+    /// untouched long enough.
+    pub fn frame(&mut self, ram_written: bool, cartridge: &mut Cartridge) -> Result<(), Error> {
+        if ram_written {
+            self.pending = true;
+            self.idle_frames = 0;
+        } else if self.pending {
+            self.idle_frames += 1;
+            if self.idle_frames >= self.flush_delay {
+                self.flush(cartridge)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write pending changes, through a temporary file renamed over the previous save
+    /// so an interrupted write never leaves a truncated file.
+    pub fn flush(&mut self, cartridge: &mut Cartridge) -> Result<(), Error> {
+        self.pending |= cartridge.take_ram_dirty();
+        if !self.pending {
+            return Ok(());
+        }
+        let data = cartridge.battery_data();
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+
+        let temp_path = self.path.with_extension("sav.tmp");
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &self.path)?;
+
+        self.pending = false;
+        self.idle_frames = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::temp::TempDir;
+    use crate::{FixedClock, Machine};
+    use std::error::Error;
+    use std::fs;
+
+    /// MBC1+RAM+BATTERY with 8KiB of ram, looping on NOPs
+    fn battery_rom() -> Vec<u8> {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x0134..0x0138].copy_from_slice(b"SAVE");
+        rom[0x0147] = 0x03;
+        rom[0x0149] = 0x02;
+        rom[0x0102..0x0104].copy_from_slice(&[0x18, 0xFC]);
+        rom
+    }
+
+    fn write_sram(machine: &mut Machine, byte: u8) {
+        machine.bus.write_byte(0x0000, 0x0A); // enable ram
+        machine.bus.write_byte(0xA000, byte);
+    }
+
+    #[test]
+    fn test_flush_after_idle_frames() -> Result<(), Box<dyn Error>> {
+        let dir = TempDir::new("battery-debounce")?;
+        let path = dir.join("SAVE.sav");
+        let mut machine = Machine::builder()
+            .cartridge_bytes(battery_rom())
+            .battery_save_dir(dir.path())
+            .sram_flush_delay(3)
+            .build()?;
+
+        write_sram(&mut machine, 0x42);
+        machine.step_frame()?; // write detected
+        machine.step_frame()?;
+        machine.step_frame()?;
+        assert!(!path.exists());
+
+        machine.step_frame()?;
+        assert_eq!(fs::read(&path)?[0], 0x42);
+        assert!(!dir.join("SAVE.sav.tmp").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_flush_on_drop_and_reload() -> Result<(), Box<dyn Error>> {
+        let dir = TempDir::new("battery-reload")?;
+        let builder = || {
+            Machine::builder()
+                .cartridge_bytes(battery_rom())
+                .battery_save_dir(dir.path())
+        };
+
+        let mut machine = builder().build()?;
+        write_sram(&mut machine, 0x24);
+        drop(machine);
+
+        let mut machine = builder().build()?;
+        machine.bus.write_byte(0x0000, 0x0A);
+        assert_eq!(machine.bus.read_byte(0xA000), 0x24);
+        Ok(())
+    }
+
+    /// The save file name comes from the rom header, an untrusted rom must not reach it
+    #[test]
+    fn test_sandbox_leaves_the_save_alone() -> Result<(), Box<dyn Error>> {
+        let dir = TempDir::new("battery-sandbox")?;
+        let path = dir.join("SAVE.sav");
+        fs::write(&path, [0x42; 0x2000])?;
+
+        let mut machine = Machine::builder()
+            .cartridge_bytes(battery_rom())
+            .battery_save_dir(dir.path())
+            .sandbox(true)
+            .build()?;
+        assert!(machine.is_sandboxed());
+        machine.bus.write_byte(0x0000, 0x0A);
+        assert_eq!(machine.bus.read_byte(0xA000), 0x00);
+
+        write_sram(&mut machine, 0x24);
+        machine.flush_sram()?;
+        drop(machine);
+        assert_eq!(fs::read(&path)?, [0x42; 0x2000]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rtc_footer() -> Result<(), Box<dyn Error>> {
+        let dir = TempDir::new("battery-rtc")?;
+        let mut rom = battery_rom();
+        rom[0x0147] = 0x10; // MBC3+TIMER+RAM+BATTERY
+        let clock = FixedClock::new(1_000);
+        let builder = || {
+            Machine::builder()
+                .cartridge_bytes(rom.clone())
+                .battery_save_dir(dir.path())
+                .rtc_clock(clock.clone())
+        };
+
+        let mut machine = builder().build()?;
+        write_sram(&mut machine, 0x11);
+        machine.bus.write_byte(0x4000, 0x09); // minutes
+        machine.bus.write_byte(0xA000, 5);
+        drop(machine);
+
+        let data = fs::read(dir.join("SAVE.sav"))?;
+        assert_eq!(data.len(), 0x2000 + 48);
+        assert_eq!(data[0], 0x11);
+        assert_eq!(data[0x2000 + 4], 5);
+
+        clock.advance(120);
+        let mut machine = builder().build()?;
+        machine.bus.write_byte(0x0000, 0x0A);
+        machine.bus.write_byte(0x6000, 0x00);
+        machine.bus.write_byte(0x6000, 0x01);
+        machine.bus.write_byte(0x4000, 0x09);
+        assert_eq!(machine.bus.read_byte(0xA000), 7);
+        Ok(())
+    }
+}
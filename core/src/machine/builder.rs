@@ -0,0 +1,456 @@
+use crate::accuracy::AccuracyProfile;
+use crate::cartridge::{Cartridge, Clock, FixedClock, MapperRegistry, apply_patch};
+use crate::machine::{DEFAULT_SRAM_FLUSH_DELAY, DETERMINISTIC_RTC_TIME, Machine, PowerCycleOptions};
+use crate::ppu::ColorPalette;
+use crate::rng::host_seed;
+use crate::{Model, RamInit};
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+
+enum Source {
+    Path(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+impl Source {
+    fn read(self) -> Result<Vec<u8>, Error> {
+        match self {
+            Source::Path(path) => std::fs::read(path),
+            Source::Bytes(bytes) => Ok(bytes),
+        }
+    }
+}
+
+/// Configure and create a ready to run [`Machine`].
+///
+/// ```no_run
+/// use gbemu_core::{Machine, Model};
+///
+/// let machine = Machine::builder()
+///     .model(Model::Dmg)
+///     .boot_rom_path("roms/dmg.bin")
+///     .cartridge_path("roms/tetris.gb")
+///     .breakpoint(0x0100)
+///     .build()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub struct MachineBuilder {
+    model: Model,
+    boot_rom: Option<Source>,
+    cartridge: Option<Source>,
+    patches: Vec<Source>,
+    breakpoints: Vec<u16>,
+    color_palette: ColorPalette,
+    ram_init: RamInit,
+    mapper_registry: MapperRegistry,
+    battery_dir: Option<PathBuf>,
+    sram_flush_delay: u32,
+    rtc_clock: Option<Box<dyn Clock>>,
+    deterministic: bool,
+    sandbox: bool,
+    fast_boot: bool,
+    instruction_trace: bool,
+    seed: Option<u64>,
+    accuracy: AccuracyProfile,
+    /// Overrides the option of the accuracy profile
+    oam_bug: Option<bool>,
+    sgb_stub: bool,
+}
+
+impl Default for MachineBuilder {
+    fn default() -> Self {
+        Self {
+            model: Model::default(),
+            boot_rom: None,
+            cartridge: None,
+            patches: vec![],
+            breakpoints: vec![],
+            color_palette: ColorPalette::default(),
+            ram_init: RamInit::default(),
+            mapper_registry: MapperRegistry::default(),
+            battery_dir: None,
+            sram_flush_delay: DEFAULT_SRAM_FLUSH_DELAY,
+            rtc_clock: None,
+            deterministic: false,
+            sandbox: false,
+            fast_boot: false,
+            instruction_trace: false,
+            seed: None,
+            accuracy: AccuracyProfile::default(),
+            oam_bug: None,
+            sgb_stub: false,
+        }
+    }
+}
+
+impl MachineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn model(mut self, model: Model) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Start execution from the boot rom stored at `path`.
+    pub fn boot_rom_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.boot_rom = Some(Source::Path(path.into()));
+        self
+    }
+
+    /// Start execution from the given boot rom image.
+    pub fn boot_rom_bytes(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.boot_rom = Some(Source::Bytes(bytes.into()));
+        self
+    }
+
+    /// Insert the cartridge stored at `path`, `.gb` and `.zip` files are supported.
+    pub fn cartridge_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cartridge = Some(Source::Path(path.into()));
+        self
+    }
+
+    /// Insert a cartridge from a rom image (raw or zipped).
+    pub fn cartridge_bytes(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.cartridge = Some(Source::Bytes(bytes.into()));
+        self
+    }
+
+    /// Apply the IPS or BPS patch stored at `path` to the cartridge rom, patches are applied in the
+    /// order they are added.
+    pub fn patch_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.patches.push(Source::Path(path.into()));
+        self
+    }
+
+    /// Apply an IPS or BPS patch to the cartridge rom, see [`MachineBuilder::patch_path`].
+    pub fn patch_bytes(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.patches.push(Source::Bytes(bytes.into()));
+        self
+    }
+
+    /// Mappers available to the cartridge, to support custom cartridge types.
+    pub fn mapper_registry(mut self, mapper_registry: MapperRegistry) -> Self {
+        self.mapper_registry = mapper_registry;
+        self
+    }
+
+    /// Directory of the battery saves (`<game>.sav`), battery backed ram is not persisted without it.
+    pub fn battery_save_dir(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.battery_dir = Some(directory.into());
+        self
+    }
+
+    /// Frames without external ram writes before the battery save is written
+    pub fn sram_flush_delay(mut self, frames: u32) -> Self {
+        self.sram_flush_delay = frames;
+        self
+    }
+
+    /// Time source of the cartridge real time clock, the host time by default.
+    pub fn rtc_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.rtc_clock = Some(Box::new(clock));
+        self
+    }
+
+    /// Pin every default taken from the host so two runs of the same inputs give the same states,
+    /// e.g. for movies or netplay: the real time clock is frozen at [`DETERMINISTIC_RTC_TIME`] unless
+    /// set with [`MachineBuilder::rtc_clock`], the seed is 0 unless set with [`MachineBuilder::seed`], and
+    /// battery saves are neither read nor written.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Harden the machine for untrusted roms, e.g. on a service running user provided games: the battery saves
+    /// are neither read nor written, so the content of a rom never leads to a host file access. Whatever the
+    /// mode, the rom images and patches are capped at [`MAX_ROM_SIZE`](crate::MAX_ROM_SIZE), the
+    /// cartridge ram is sized by the header and the states at [`MAX_STATE_SIZE`](crate::MAX_STATE_SIZE).
+    pub fn sandbox(mut self, sandbox: bool) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Keep the registers of the last [`TRACE_LENGTH`](crate::TRACE_LENGTH) instructions for
+    /// [`Machine::trace`] and the [`CrashReport`](crate::CrashReport), at the cost of a copy per instruction
+    pub fn instruction_trace(mut self, enabled: bool) -> Self {
+        self.instruction_trace = enabled;
+        self
+    }
+
+    /// Start from the exact state left by the boot rom on each reset, see [`Machine::skip_boot`].
+    /// A boot rom set with [`MachineBuilder::boot_rom_path`] is not run.
+    pub fn fast_boot(mut self, fast_boot: bool) -> Self {
+        self.fast_boot = fast_boot;
+        self
+    }
+
+    /// Emulate the DMG OAM corruption bug: `INC rr`/`DEC rr` of a value in $FE00-$FEFF during the
+    /// OAM scan garbles a sprite row. Off by default, some test roms and demos rely on it.
+    /// Takes precedence over the accuracy profile.
+    pub fn oam_bug(mut self, enabled: bool) -> Self {
+        self.oam_bug = Some(enabled);
+        self
+    }
+
+    /// Answer the Super Game Boy detection of the games that wait for it, see [`Machine::set_sgb_stub`].
+    pub fn sgb_stub(mut self, enabled: bool) -> Self {
+        self.sgb_stub = enabled;
+        self
+    }
+
+    /// Hardware behaviors to emulate, an [`Accuracy`](crate::Accuracy) preset or a custom profile, balanced by
+    /// default.
+    pub fn accuracy(mut self, accuracy: impl Into<AccuracyProfile>) -> Self {
+        self.accuracy = accuracy.into();
+        self
+    }
+
+    pub fn breakpoint(mut self, address: u16) -> Self {
+        self.breakpoints.push(address);
+        self
+    }
+
+    pub fn breakpoints(mut self, addresses: impl IntoIterator<Item = u16>) -> Self {
+        self.breakpoints.extend(addresses);
+        self
+    }
+
+    pub fn color_palette(mut self, color_palette: ColorPalette) -> Self {
+        self.color_palette = color_palette;
+        self
+    }
+
+    /// Seed of the random features such as [`RamInit::Random`], kept in the save states and sessions so a
+    /// replay gives the same bytes. Taken from the host clock by default.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Content of the work ram and high ram at power on, [`RamInit::DmgStripes`] by default.
+    pub fn ram_init(mut self, ram_init: RamInit) -> Self {
+        self.ram_init = ram_init;
+        self
+    }
+
+    pub fn build(self) -> Result<Machine, Error> {
+        if self.model == Model::Cgb && self.boot_rom.is_some() {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "the CGB boot rom is not supported yet",
+            ));
+        }
+
+        let mut machine = Machine::default();
+        machine.model = self.model;
+        machine.ppu.set_model(self.model);
+        machine.bus.set_model(self.model);
+        machine.serial.set_model(self.model);
+        machine.color_palette = self.color_palette;
+        machine.battery_dir = self.battery_dir;
+        machine.sram_flush_delay = self.sram_flush_delay;
+        machine.deterministic = self.deterministic;
+        machine.sandbox = self.sandbox;
+        machine.fast_boot = self.fast_boot;
+        machine.set_instruction_trace(self.instruction_trace);
+        machine.seed = self.seed.unwrap_or_else(|| match self.deterministic {
+            true => 0,
+            false => host_seed(),
+        });
+        machine.set_accuracy(AccuracyProfile {
+            oam_bug: self.oam_bug.unwrap_or(self.accuracy.oam_bug),
+            ..self.accuracy
+        });
+        machine.bus.set_ram_init(self.ram_init);
+        machine.set_sgb_stub(self.sgb_stub);
+
+        if let Some(source) = self.boot_rom {
+            let bytes = source.read()?;
+            let boot_rom = bytes.try_into().map_err(|bytes: Vec<u8>| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "boot rom size mismatch: expected {} bytes, got {}",
+                        self.model.boot_rom_size(),
+                        bytes.len()
+                    ),
+                )
+            })?;
+            machine.bus.set_boot_rom(boot_rom);
+            machine.start_addr = Some(0x0000);
+        }
+
+        if self.cartridge.is_none() && !self.patches.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "patch without a cartridge"));
+        }
+        if let Some(source) = self.cartridge {
+            let mut rom = match source {
+                Source::Path(path) => Cartridge::read_path(path)?,
+                Source::Bytes(bytes) => Cartridge::read_bytes(&bytes)?,
+            };
+            for patch in self.patches {
+                rom = apply_patch(&rom, &patch.read()?)?;
+            }
+            let mut cartridge = Cartridge::from_rom(rom, &self.mapper_registry)?;
+            let pinned_clock = || Box::new(FixedClock::new(DETERMINISTIC_RTC_TIME)) as Box<dyn Clock>;
+            if let Some(clock) = self.rtc_clock.or_else(|| self.deterministic.then(pinned_clock)) {
+                cartridge.set_rtc_clock(clock);
+            }
+            machine.bus.set_cartridge(cartridge);
+            machine.attach_battery();
+        }
+
+        for address in self.breakpoints {
+            machine.breakpoint_manager.add_breakpoint(address);
+        }
+
+        machine.power_cycle(PowerCycleOptions::default());
+
+        Ok(machine)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom_only() -> Vec<u8> {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x0134..0x0138].copy_from_slice(b"TEST");
+        rom
+    }
+
+    #[test]
+    fn test_build_default() -> Result<(), Error> {
+        let machine = MachineBuilder::new().build()?;
+
+        assert_eq!(machine.model(), Model::Dmg);
+        assert_eq!(machine.cpu().pc(), 0x0100);
+        assert_eq!(machine.color_palette(), &ColorPalette::DMG_GREEN);
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_with_boot_rom() -> Result<(), Error> {
+        let machine = MachineBuilder::new().boot_rom_bytes(vec![0x31; 0x100]).build()?;
+
+        assert_eq!(machine.cpu().pc(), 0x0000);
+        assert_eq!(machine.bus().read_byte(0x0000), 0x31);
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_rejects_invalid_boot_rom() {
+        let result = MachineBuilder::new().boot_rom_bytes(vec![0; 0x80]).build();
+
+        assert_eq!(result.err().map(|e| e.kind()), Some(ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn test_build_cgb() -> Result<(), Error> {
+        let machine = MachineBuilder::new().model(Model::Cgb).build()?;
+        assert_eq!(machine.model(), Model::Cgb);
+        // No cartridge flagged for the CGB
+        assert!(!machine.is_cgb_mode());
+
+        let result = MachineBuilder::new()
+            .model(Model::Cgb)
+            .boot_rom_bytes(vec![0; 0x900])
+            .build();
+        assert_eq!(result.err().map(|e| e.kind()), Some(ErrorKind::Unsupported));
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_with_ram_init() -> Result<(), Error> {
+        let machine = MachineBuilder::new().ram_init(RamInit::Ones).build()?;
+
+        assert_eq!(machine.ram_init(), RamInit::Ones);
+        assert_eq!(machine.peek(0xC123), 0xFF);
+        assert_eq!(machine.peek(0xFF90), 0xFF);
+        Ok(())
+    }
+
+    #[test]
+    fn test_seeded_random_ram() -> Result<(), Error> {
+        let wram = |machine: &Machine| {
+            (0xC000..0xE000)
+                .map(|address| machine.peek(address))
+                .collect::<Vec<u8>>()
+        };
+        let build = |seed| MachineBuilder::new().ram_init(RamInit::Random).seed(seed).build();
+
+        let mut machine = build(42)?;
+        let first = wram(&machine);
+        assert_eq!(wram(&build(42)?), first);
+        assert_ne!(wram(&build(43)?), first);
+
+        // A reset keeps the content, each power cycle draws the same content again
+        machine.poke(0xC000, !first[0]);
+        machine.reset();
+        assert_eq!(machine.peek(0xC000), !first[0]);
+        machine.power_cycle(PowerCycleOptions::default());
+        assert_eq!(wram(&machine), first);
+
+        let state = machine.save_state();
+        let mut other = build(1)?;
+        other.load_state(&state)?;
+        assert_eq!(other.seed(), 42);
+        Ok(())
+    }
+
+    #[test]
+    fn test_deterministic_runs_give_the_same_state() -> Result<(), Box<dyn std::error::Error>> {
+        let run = || -> Result<u64, Box<dyn std::error::Error>> {
+            let rom = crate::TestRom::new()
+                .code(&[0xFA, 0x00, 0xC0]) // LD A,($C000)
+                .code(&[0x3C]) // INC A
+                .code(&[0xEA, 0x00, 0xC0]) // LD ($C000),A
+                .code(&[0x18, 0xF7]) // JR -9
+                .build();
+            let mut machine = MachineBuilder::new().cartridge_bytes(rom).deterministic(true).build()?;
+            for _ in 0..10 {
+                machine.step_frame()?;
+            }
+            Ok(machine.state_hash())
+        };
+
+        assert_eq!(run()?, run()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_with_cartridge_and_breakpoints() -> Result<(), Error> {
+        let machine = MachineBuilder::new()
+            .cartridge_bytes(rom_only())
+            .breakpoints([0x0100, 0x0150])
+            .build()?;
+
+        assert_eq!(machine.cartridge().title(), "TEST");
+        assert!(machine.breakpoint_manager().has_breakpoint(0x0100));
+        assert!(machine.breakpoint_manager().has_breakpoint(0x0150));
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_with_patch() -> Result<(), Error> {
+        // IPS record writing the title at $0134
+        let patch = |title: &[u8; 4]| [b"PATCH".as_slice(), &[0x00, 0x01, 0x34, 0x00, 0x04], title, b"EOF"].concat();
+        let mut machine = MachineBuilder::new()
+            .cartridge_bytes(rom_only())
+            .patch_bytes(patch(b"HACK"))
+            .build()?;
+        assert_eq!(machine.cartridge().title(), "HACK");
+
+        machine.apply_patch(&patch(b"MODS"))?;
+        assert_eq!(machine.cartridge().title(), "MODS");
+        assert!(machine.apply_patch(b"NOT A PATCH").is_err());
+        assert_eq!(machine.cartridge().title(), "MODS");
+
+        let result = MachineBuilder::new().patch_bytes(patch(b"HACK")).build();
+        assert_eq!(result.err().map(|e| e.kind()), Some(ErrorKind::InvalidInput));
+        Ok(())
+    }
+}
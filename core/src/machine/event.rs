@@ -0,0 +1,23 @@
+use crate::bus::Interrupt;
+
+/// Events kept until [`Machine::drain_events`](crate::Machine::drain_events), the oldest are dropped past it
+pub(crate) const EVENT_QUEUE_CAPACITY: usize = 1024;
+
+/// Notification queued by the machine for the frontend, see [`Machine::drain_events`](crate::Machine::drain_events)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MachineEvent {
+    /// Execution paused on a breakpoint, a watch or a break condition, with the PC
+    BreakpointHit { address: u16 },
+    /// [`Machine::frame`](crate::Machine::frame) is complete
+    FrameCompleted { frame: u64 },
+    /// Byte sent by a serial transfer
+    SerialByte(u8),
+    /// The external ram was written during the last frame
+    SaveRamModified,
+    /// The CPU jumped to the vector of this interrupt
+    InterruptDispatched(Interrupt),
+    /// PC left the ROM, work RAM and high RAM, going from the instruction at `from` to `to`
+    ExecutionOutsideCode { from: u16, to: u16 },
+    /// An instruction failed and stopped the machine
+    EmulationError(String),
+}
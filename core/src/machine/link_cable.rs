@@ -0,0 +1,178 @@
+use crate::machine::{FrameResult, Machine};
+use crate::serial::SerialDevice;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+/// Bits exchanged while one end runs, the other end is not running
+#[derive(Default)]
+struct Wire {
+    /// Bits the other end has to send, empty when it does not wait for the clock
+    incoming: VecDeque<bool>,
+    /// Bits clocked out by this end, delivered to the other end after the instruction
+    clocked: Vec<bool>,
+}
+
+/// End of the cable plugged in a [`Machine`]
+struct CableEnd(Arc<Mutex<Wire>>);
+
+impl SerialDevice for CableEnd {
+    fn exchange_bit(&mut self, outgoing: bool) -> bool {
+        let mut wire = self.0.lock().unwrap();
+        wire.clocked.push(outgoing);
+        wire.incoming.pop_front().unwrap_or(true)
+    }
+}
+
+/// Two machines linked by their serial port, e.g. for trading or two-player games.
+///
+/// The machines run in lockstep, one instruction at a time, the one behind in cycles first. A bit clocked by
+/// the internal clock of one machine is exchanged with the transfer waiting for the external clock on the
+/// other, which raises its SERIAL interrupt after the eighth bit like the real cable.
+///
+/// ```no_run
+/// use gbemu_core::{LinkCable, Machine};
+///
+/// let first = Machine::builder().cartridge_path("roms/tetris.gb").build()?;
+/// let second = Machine::builder().cartridge_path("roms/tetris.gb").build()?;
+/// let mut cable = LinkCable::new(first, second);
+/// loop {
+///     cable.step_frame()?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct LinkCable {
+    machines: [Machine; 2],
+    wires: [Arc<Mutex<Wire>>; 2],
+    /// Cycles run by each machine since the cable was plugged
+    cycles: [u64; 2],
+}
+
+impl LinkCable {
+    /// Plug the cable, replacing the serial devices of both machines
+    pub fn new(mut first: Machine, mut second: Machine) -> Self {
+        let wires = [Arc::default(), Arc::default()];
+        first.connect_serial(CableEnd(Arc::clone(&wires[0])));
+        second.connect_serial(CableEnd(Arc::clone(&wires[1])));
+        Self {
+            machines: [first, second],
+            wires,
+            cycles: [0; 2],
+        }
+    }
+
+    pub fn machines(&self) -> &[Machine; 2] {
+        &self.machines
+    }
+
+    pub fn machines_mut(&mut self) -> &mut [Machine; 2] {
+        &mut self.machines
+    }
+
+    /// Unplug the cable
+    pub fn into_machines(mut self) -> [Machine; 2] {
+        for machine in &mut self.machines {
+            machine.disconnect_serial();
+        }
+        self.machines
+    }
+
+    /// Run both machines until each completed a frame, or until a breakpoint. A stopped machine is left
+    /// behind, the other one still runs its frame.
+    pub fn step_frame(&mut self) -> Result<[FrameResult; 2], Box<dyn Error>> {
+        let mut results = [FrameResult::default(); 2];
+        let mut stopped = [false; 2];
+        while (0..2).any(|index| !results[index].frame_completed && !stopped[index]) {
+            let index = match stopped {
+                [true, _] => 1,
+                [_, true] => 0,
+                _ => (self.cycles[1] < self.cycles[0]) as usize,
+            };
+            let result = self.step(index)?;
+            results[index].cycles += result.cycles;
+            results[index].frame_completed |= result.frame_completed;
+            stopped[index] = result.cycles == 0;
+            if result.hit_breakpoint {
+                results[index].hit_breakpoint = true;
+                break;
+            }
+        }
+        Ok(results)
+    }
+
+    /// Run one instruction of a machine, then deliver the bits it clocked to the other one
+    fn step(&mut self, index: usize) -> Result<FrameResult, Box<dyn Error>> {
+        let [first, second] = &mut self.machines;
+        let (machine, partner) = match index {
+            0 => (first, second),
+            _ => (second, first),
+        };
+        {
+            let mut wire = self.wires[index].lock().unwrap();
+            wire.incoming.clear();
+            wire.incoming.extend(partner.serial.waiting_bits(&mut partner.bus));
+        }
+
+        let result = machine.run_cycles(1)?;
+        self.cycles[index] += result.cycles as u64;
+
+        let clocked = std::mem::take(&mut self.wires[index].lock().unwrap().clocked);
+        for bit in clocked {
+            partner.serial_external_clock(bit);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestRom;
+
+    /// Puts `sb` in SB and starts a transfer with the clock of `sc`
+    fn machine(sb: u8, sc: u8) -> Result<Machine, std::io::Error> {
+        let rom = TestRom::new()
+            .code(&[0x3E, sb]) // LD A,sb
+            .code(&[0xE0, 0x01]) // LDH ($01),A
+            .code(&[0x3E, sc]) // LD A,sc
+            .code(&[0xE0, 0x02]) // LDH ($02),A
+            .code(&[0x18, 0xFE]) // JR -2
+            .build();
+        Machine::builder().cartridge_bytes(rom).build()
+    }
+
+    #[test]
+    fn test_exchange() -> Result<(), Box<dyn Error>> {
+        let mut cable = LinkCable::new(machine(0x42, 0x81)?, machine(0x99, 0x80)?);
+        let results = cable.step_frame()?;
+        assert!(results.iter().all(|result| result.frame_completed));
+
+        let [first, second] = cable.machines_mut();
+        assert_eq!(first.peek(0xFF01), 0x99);
+        assert_eq!(second.peek(0xFF01), 0x42);
+        for machine in [first, second] {
+            assert!(!machine.serial().is_transferring());
+            assert_eq!(machine.peek(0xFF0F) & 0x08, 0x08, "SERIAL interrupt requested");
+        }
+        // Lockstep, apart by less than an instruction
+        assert!(cable.machines[0].cycles().abs_diff(cable.machines[1].cycles()) <= 24);
+
+        let [mut first, _] = cable.into_machines();
+        assert!(first.disconnect_serial().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_partner_waiting() -> Result<(), Box<dyn Error>> {
+        // Both on the internal clock, neither listens to the other
+        let mut cable = LinkCable::new(machine(0x42, 0x81)?, machine(0x99, 0x81)?);
+        cable.step_frame()?;
+
+        let [first, second] = cable.machines_mut();
+        assert_eq!(first.peek(0xFF01), 0xFF);
+        assert_eq!(second.peek(0xFF01), 0xFF);
+        assert_eq!(first.take_serial_output(), [0x42]);
+        assert_eq!(second.take_serial_output(), [0x99]);
+        Ok(())
+    }
+}
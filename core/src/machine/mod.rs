@@ -0,0 +1,2095 @@
+mod address;
+mod battery;
+mod builder;
+mod event;
+mod link_cable;
+mod pacer;
+mod preview;
+
+pub use address::InterestingAddress;
+pub use battery::DEFAULT_SRAM_FLUSH_DELAY;
+pub use builder::MachineBuilder;
+pub use event::MachineEvent;
+pub use link_cable::LinkCable;
+pub use pacer::{FRAME_RATE, FramePacer, PacingMode};
+pub use preview::{RomPreview, rom_preview};
+
+use crate::accuracy::AccuracyProfile;
+use crate::apu::{Apu, AudioChunk, AudioSink};
+use crate::bus::{Interrupt, InterruptBus, InterruptState, MemorySystem};
+use crate::cartridge::{Cartridge, Clock, FixedClock, MapperState};
+use crate::cpu::{Cpu, Flags as CpuFlags};
+use crate::debug::breakpoint::{BreakpointManager, is_code_address};
+use crate::debug::crash::CrashReport;
+use crate::debug::diff::{MachineSnapshot, StateDiff};
+use crate::debug::expression::Expression;
+use crate::debug::frame_dump::FrameDump;
+#[cfg(feature = "profiling")]
+use crate::debug::profile::{CpuCounters, ProfileReport};
+use crate::debug::protection::MemoryProtection;
+use crate::debug::trace::{Trace, TraceEntry};
+use crate::interceptor::{BusInterceptor, InterceptorId};
+use crate::joypad;
+use crate::joypad::{InputMacro, InputState, Joypad, Turbo};
+use crate::machine::battery::BatterySave;
+use crate::machine::event::EVENT_QUEUE_CAPACITY;
+use crate::ppu::{ChangedLines, ChangedTiles, ColorPalette, Layers, Palette, Ppu, PpuBus, PpuSnapshot};
+use crate::rng::Rng;
+use crate::serial::{Serial, SerialDevice};
+use crate::state::{Savable, Section, Session, StateReader, StateSections, StateWriter, invalid_data};
+use crate::timer::{DMG_POST_BOOT_COUNTER, Timer};
+use crate::video::{FrameRef, VideoSink, crc32, rgb555_to_rgb, to_rgb};
+use crate::{AudioChannels, Model, RamInit, SAMPLE_RATE};
+use log::{error, info};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Number of cycles of a full frame (154 lines of 456 cycles)
+pub const CYCLES_PER_FRAME: usize = 70224;
+
+/// Frequency of the CPU clock, in cycles per second
+pub const CPU_CLOCK_HZ: u64 = 4_194_304;
+
+/// Unix time of the real time clock in deterministic mode, see [`MachineBuilder::deterministic`]
+pub const DETERMINISTIC_RTC_TIME: u64 = 0;
+
+/// Largest state accepted by [`Machine::load_state`], the states of the cartridges with the most ram are
+/// under 300 KiB
+pub const MAX_STATE_SIZE: usize = 1024 * 1024;
+
+const STATE_MAGIC: &[u8; 4] = b"GBSS";
+/// Version 7 puts each component in its own [`Section`], version 6 states are still read
+const STATE_VERSION: u8 = 7;
+const LEGACY_STATE_VERSION: u8 = 6;
+
+const CPU_SECTION: Section = Section::new(b"CPU ", 2);
+const BUS_SECTION: Section = Section::new(b"BUS ", 2);
+const PPU_SECTION: Section = Section::new(b"PPU ", 3);
+const TIMER_SECTION: Section = Section::new(b"TIMR", 1);
+const SERIAL_SECTION: Section = Section::new(b"SERL", 1);
+const APU_SECTION: Section = Section::new(b"APU ", 1);
+const JOYPAD_SECTION: Section = Section::new(b"JOYP", 1);
+/// Frame cycles, seed and random generator
+const MACHINE_SECTION: Section = Section::new(b"MACH", 1);
+
+/// Outcome of [`Machine::step_frame`] and [`Machine::run_cycles`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameResult {
+    /// Cycles executed during the call
+    pub cycles: usize,
+    /// Execution stopped on a breakpoint
+    pub hit_breakpoint: bool,
+    /// A frame reached VBlank, the frame buffer is complete
+    pub frame_completed: bool,
+}
+
+/// Execution state of a [`Machine`]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum EmulationStatus {
+    #[default]
+    Running,
+    /// Paused by [`Machine::pause`] or by a breakpoint, stepping still executes instructions
+    Paused,
+    /// An instruction failed with this error, nothing is executed until a reset or a state load
+    Stopped(String),
+}
+
+/// What survives [`Machine::power_cycle`], the cartridge battery keeps both by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerCycleOptions {
+    /// External ram of the cartridge, zeroed otherwise
+    pub keep_sram: bool,
+    /// Real time clock of the cartridge, stopped at day 0 otherwise
+    pub keep_rtc: bool,
+}
+
+impl Default for PowerCycleOptions {
+    fn default() -> Self {
+        Self {
+            keep_sram: true,
+            keep_rtc: true,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Machine {
+    cpu: Cpu,
+    bus: MemorySystem,
+    ppu: Ppu,
+    timer: Timer,
+    serial: Serial,
+    apu: Apu,
+    joypad: Joypad,
+    start_addr: Option<u16>,
+    breakpoint_manager: BreakpointManager,
+    break_condition: Option<Expression>,
+    trace: Trace,
+    /// PC of the last executed instruction
+    instruction_pc: u16,
+    #[cfg(feature = "profiling")]
+    cpu_counters: CpuCounters,
+    model: Model,
+    color_palette: ColorPalette,
+    status: EmulationStatus,
+    frame_cycles: usize,
+    frame_count: u64,
+    /// Cycles executed since the last reset
+    cycles: u64,
+    frame_dump: Option<FrameDump>,
+    /// Tiles written during the last completed frame
+    changed_tiles: ChangedTiles,
+    battery_dir: Option<PathBuf>,
+    sram_flush_delay: u32,
+    battery: Option<BatterySave>,
+    deterministic: bool,
+    /// Untrusted roms, see [`MachineBuilder::sandbox`]
+    sandbox: bool,
+    /// Call [`Machine::skip_boot`] on each reset
+    fast_boot: bool,
+    session: Option<Session>,
+    accuracy: AccuracyProfile,
+    /// Seed of `rng` on each power cycle, see [`MachineBuilder::seed`]
+    seed: u64,
+    rng: Rng,
+    /// Pressed buttons of the frames since the macro recording started
+    macro_recording: Option<Vec<u8>>,
+    /// Playing macro with its next frame
+    macro_playback: Option<(InputMacro, usize)>,
+    video_sinks: Vec<Box<dyn VideoSink>>,
+    audio_sinks: Vec<Box<dyn AudioSink>>,
+    /// Samples of the current frame for the audio sinks
+    audio_chunk: Vec<[i16; 2]>,
+    /// Samples handed to the audio sinks since the last reset
+    audio_timestamp: u64,
+    events: VecDeque<MachineEvent>,
+}
+
+impl Machine {
+    pub fn builder() -> MachineBuilder {
+        MachineBuilder::new()
+    }
+
+    pub fn use_boot_rom(&mut self) -> Result<(), std::io::Error> {
+        self.start_addr = Some(0x0000);
+        self.bus.load_boot_rom()
+    }
+    pub fn load_cartridge<P: AsRef<Path>>(&mut self, path: P) -> Result<(), std::io::Error> {
+        info!("Loading cartridge: {:?}", path.as_ref());
+        if let Err(e) = self.flush_sram() {
+            error!("Failed to write battery save: {e}");
+        }
+        self.bus.load_cartridge(path)?;
+        self.cartridge_inserted();
+        Ok(())
+    }
+
+    /// Insert a cartridge from a rom image (raw or zipped), e.g. read from stdin.
+    pub fn load_cartridge_bytes(&mut self, bytes: Vec<u8>) -> Result<(), std::io::Error> {
+        info!("Loading cartridge from {} bytes", bytes.len());
+        if let Err(e) = self.flush_sram() {
+            error!("Failed to write battery save: {e}");
+        }
+        self.bus.load_cartridge_bytes(&bytes)?;
+        self.cartridge_inserted();
+        Ok(())
+    }
+
+    /// Replace the cartridge by its rom patched with an IPS or BPS `patch`, see [`apply_patch`](crate::apply_patch).
+    /// The cartridge is left untouched when the patch fails.
+    pub fn apply_patch(&mut self, patch: &[u8]) -> Result<(), std::io::Error> {
+        let rom = crate::apply_patch(self.bus.cartridge().rom(), patch)?;
+        self.load_cartridge_bytes(rom)
+    }
+
+    /// Write the battery backed ram to the save directory if it changed.
+    pub fn flush_sram(&mut self) -> Result<(), std::io::Error> {
+        match self.battery.as_mut() {
+            Some(battery) => battery.flush(self.bus.cartridge_mut()),
+            None => Ok(()),
+        }
+    }
+
+    fn cartridge_inserted(&mut self) {
+        if self.deterministic {
+            self.set_rtc_clock(FixedClock::new(DETERMINISTIC_RTC_TIME));
+        }
+        self.attach_battery();
+    }
+
+    fn attach_battery(&mut self) {
+        if self.deterministic || self.sandbox {
+            // The save file would make runs differ, or its name comes from an untrusted header
+            self.battery = None;
+            return;
+        }
+        let Some(directory) = &self.battery_dir else { return };
+
+        self.battery = BatterySave::new(directory, self.bus.cartridge(), self.sram_flush_delay);
+        if let Some(battery) = &self.battery
+            && let Err(e) = battery.load(self.bus.cartridge_mut())
+        {
+            error!("Failed to load battery save: {e}");
+        }
+    }
+
+    pub fn frame(&self) -> &[u8] {
+        &self.ppu.frame_buffer
+    }
+    /// RGB555 colors of the last frame in CGB mode, [`Machine::frame`] then holds the color ids
+    pub fn frame_rgb555(&self) -> Option<&[u16]> {
+        self.is_cgb_mode().then_some(&self.ppu.rgb_frame_buffer[..])
+    }
+    /// RGB color of each pixel of the last frame, the CGB colors in CGB mode and the shades in the
+    /// color palette otherwise
+    pub fn frame_rgb(&self) -> Vec<[u8; 3]> {
+        match self.frame_rgb555() {
+            Some(colors) => rgb555_to_rgb(colors).collect(),
+            None => to_rgb(self.frame(), &self.color_palette).collect(),
+        }
+    }
+    /// Opaque RGBA version of [`Machine::frame_rgb`], use `as_flattened` to get the bytes
+    pub fn frame_rgba(&self) -> Vec<[u8; 4]> {
+        self.frame_rgb().into_iter().map(|[r, g, b]| [r, g, b, 0xFF]).collect()
+    }
+    /// A CGB running a cartridge flagged for it, with the VRAM and WRAM banks and the color palettes
+    pub fn is_cgb_mode(&self) -> bool {
+        self.bus.is_cgb_mode()
+    }
+    /// CGB double speed, switched by the game with KEY1 and `STOP`
+    pub fn double_speed(&self) -> bool {
+        self.bus.is_double_speed()
+    }
+    /// Frames completed by [`Machine::step_frame`] since the last reset
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+    /// Cycles executed since the last reset, at the normal speed: an instruction counts for half its cycles
+    /// in double speed
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+    /// Time elapsed on the console since the last reset, derived from [`Machine::cycles`]
+    pub fn emulated_time(&self) -> Duration {
+        Duration::from_secs(self.cycles / CPU_CLOCK_HZ)
+            + Duration::from_nanos(self.cycles % CPU_CLOCK_HZ * 1_000_000_000 / CPU_CLOCK_HZ)
+    }
+    /// Take the events queued since the last call, oldest first
+    pub fn drain_events(&mut self) -> std::collections::vec_deque::Drain<'_, MachineEvent> {
+        self.events.drain(..)
+    }
+    fn push_event(&mut self, event: MachineEvent) {
+        if self.events.len() == EVENT_QUEUE_CAPACITY {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+    /// Hand each completed frame to `sink`, until it is done or fails
+    pub fn add_video_sink(&mut self, sink: impl VideoSink + 'static) {
+        self.video_sinks.push(Box::new(sink));
+    }
+    /// Frames dropped by the video sinks still attached, e.g. a [`ChannelSink`](crate::video::ChannelSink)
+    /// with a slow receiver
+    pub fn dropped_frames(&self) -> u64 {
+        self.video_sinks.iter().map(|sink| sink.dropped_frames()).sum()
+    }
+    /// Finish and remove the video sinks
+    pub fn clear_video_sinks(&mut self) {
+        for mut sink in self.video_sinks.drain(..) {
+            finish_video_sink(sink.as_mut());
+        }
+    }
+    /// Hand the sound of each completed frame to `sink`, until it is done or fails.
+    /// Video and audio sinks are fed together at the end of the frame, so their outputs stay in sync.
+    pub fn add_audio_sink(&mut self, sink: impl AudioSink + 'static) {
+        self.audio_sinks.push(Box::new(sink));
+        self.apu.keep_samples(true);
+    }
+    /// Finish and remove the audio sinks
+    pub fn clear_audio_sinks(&mut self) {
+        for mut sink in self.audio_sinks.drain(..) {
+            finish_audio_sink(sink.as_mut());
+        }
+        self.apu.keep_samples(false);
+    }
+    /// Write the next `count` completed frames as numbered PNG files into `directory`, with a
+    /// `frames.json` sidecar giving the frame number and LY progression of each file.
+    pub fn dump_frames(&mut self, count: usize, directory: impl Into<PathBuf>) -> Result<(), std::io::Error> {
+        self.frame_dump = Some(FrameDump::new(directory.into(), count, self)?).filter(|dump| dump.remaining() > 0);
+        Ok(())
+    }
+    /// Frames left to write by the dump started with [`Machine::dump_frames`]
+    pub fn frames_left_to_dump(&self) -> usize {
+        self.frame_dump.as_ref().map_or(0, FrameDump::remaining)
+    }
+    pub fn interrupt_state(&self) -> InterruptState {
+        InterruptState {
+            enable: self.bus.interrupt_enable().bits(),
+            flag: self.bus.interrupt_flag().bits(),
+        }
+    }
+    /// Set the interrupt flag (IF) bits, e.g. to force a VBlank interrupt while debugging
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.bus.set_interrupt_flag(interrupt);
+    }
+    /// Clear the interrupt flag (IF) bits, pending interrupts are not serviced
+    pub fn clear_interrupt(&mut self, interrupt: Interrupt) {
+        self.bus.clear_interrupt_flag(interrupt);
+    }
+    /// Lines of [`Machine::frame`] changed since the last call, to redraw only what changed.
+    /// Every line is reported after a reset, a state load or a palette change.
+    pub fn take_changed_lines(&mut self) -> ChangedLines {
+        self.ppu.take_changed_lines()
+    }
+    /// Tiles of the tile data ($8000-$97FF) of both VRAM banks written with new bytes during the last completed
+    /// frame, for a VRAM viewer to highlight animations and corruptions. Every tile is reported for the frame of a reset or a
+    /// state load.
+    pub fn changed_tiles(&self) -> ChangedTiles {
+        self.changed_tiles
+    }
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+    pub fn bus(&self) -> &MemorySystem {
+        &self.bus
+    }
+    /// VRAM ($8000-$9FFF) for tile viewers and tools, unlike [`Machine::peek`] it stays readable while the
+    /// PPU locks it out of the CPU
+    pub fn vram(&self) -> &[u8] {
+        self.bus.vram()
+    }
+    /// OAM ($FE00-$FE9F), 40 sprites of 4 bytes, readable like [`Machine::vram`]
+    pub fn oam(&self) -> &[u8] {
+        self.bus.oam()
+    }
+    pub fn ppu_state(&self) -> PpuSnapshot {
+        PpuSnapshot::read(&self.bus)
+    }
+    /// Read memory as seen by the CPU, without side effects.
+    pub fn peek(&self, address: u16) -> u8 {
+        self.bus.read_byte(address)
+    }
+    /// Write memory without IO side effects (DIV reset, OAM DMA, boot rom unmapping).
+    /// Cartridge addresses are still handled by the mapper.
+    pub fn poke(&mut self, address: u16, value: u8) {
+        self.bus.write_internal_byte(address, value);
+    }
+    /// Write memory like the CPU does, IO registers react to the write.
+    pub fn cpu_write(&mut self, address: u16, value: u8) {
+        self.bus.write_byte(address, value);
+    }
+    /// Background palette (BGP)
+    pub fn bgp(&self) -> Palette {
+        PpuBus::bgp(&self.bus).into()
+    }
+    pub fn set_bgp(&mut self, palette: Palette) {
+        PpuBus::set_bgp(&mut self.bus, palette.into());
+    }
+    /// Sprite palette 0 (OBP0)
+    pub fn obp0(&self) -> Palette {
+        PpuBus::obp0(&self.bus).into()
+    }
+    pub fn set_obp0(&mut self, palette: Palette) {
+        PpuBus::set_obp0(&mut self.bus, palette.into());
+    }
+    /// Sprite palette 1 (OBP1)
+    pub fn obp1(&self) -> Palette {
+        PpuBus::obp1(&self.bus).into()
+    }
+    pub fn set_obp1(&mut self, palette: Palette) {
+        PpuBus::set_obp1(&mut self.bus, palette.into());
+    }
+    /// Frozen addresses and read-only regions, applied to the CPU writes
+    pub fn memory_protection(&self) -> &MemoryProtection {
+        self.bus.protection()
+    }
+    pub fn memory_protection_mut(&mut self) -> &mut MemoryProtection {
+        self.bus.protection_mut()
+    }
+    /// Hand the accesses to `range` to `interceptor`, after the bus routed them.
+    /// See [`BusInterceptor`] for the ordering and the cost.
+    pub fn add_bus_interceptor(
+        &mut self,
+        range: RangeInclusive<u16>,
+        interceptor: impl BusInterceptor + 'static,
+    ) -> InterceptorId {
+        self.bus.interceptors_mut().add(range, Box::new(interceptor))
+    }
+    pub fn remove_bus_interceptor(&mut self, id: InterceptorId) -> Option<Box<dyn BusInterceptor>> {
+        self.bus.interceptors_mut().remove(id)
+    }
+    pub fn clear_bus_interceptors(&mut self) {
+        self.bus.interceptors_mut().clear();
+    }
+    pub fn cartridge(&self) -> &Cartridge {
+        self.bus.cartridge()
+    }
+    /// Rom and ram banks currently mapped by the cartridge
+    pub fn mapper_state(&self) -> MapperState {
+        self.bus.cartridge().mapper_state()
+    }
+
+    /// Time source of the cartridge real time clock, the host time by default.
+    /// A newly loaded cartridge starts again on the host time.
+    pub fn set_rtc_clock(&mut self, clock: impl Clock + 'static) {
+        self.bus.cartridge_mut().set_rtc_clock(Box::new(clock));
+    }
+    pub fn model(&self) -> Model {
+        self.model
+    }
+    /// Power on content of the ram
+    pub fn ram_init(&self) -> RamInit {
+        self.bus.ram_init()
+    }
+    /// Seed of the random features, see [`MachineBuilder::seed`]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    /// Used from the next power cycle on
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+    /// Emulation of the DMG OAM corruption bug, see [`MachineBuilder::oam_bug`]
+    pub fn oam_bug(&self) -> bool {
+        self.accuracy.oam_bug
+    }
+    pub fn set_oam_bug(&mut self, enabled: bool) {
+        self.set_accuracy(AccuracyProfile {
+            oam_bug: enabled,
+            ..self.accuracy
+        });
+    }
+    /// Hardware behaviors emulated, see [`MachineBuilder::accuracy`]
+    pub fn accuracy(&self) -> AccuracyProfile {
+        self.accuracy
+    }
+    pub fn set_accuracy(&mut self, accuracy: impl Into<AccuracyProfile>) {
+        self.accuracy = accuracy.into().supported();
+        self.bus.set_accuracy(self.accuracy);
+        self.timer.set_ignore_edge_cases(!self.accuracy.timer_edge_cases);
+        self.ppu.set_ppu_model(self.accuracy.ppu_model);
+    }
+    pub fn color_palette(&self) -> &ColorPalette {
+        &self.color_palette
+    }
+    pub fn set_color_palette(&mut self, color_palette: ColorPalette) {
+        self.color_palette = color_palette;
+        self.ppu.invalidate_lines();
+    }
+    /// Skip the drawing of the lines when disabled, the frame buffer keeps the last drawn frame but the
+    /// timing is unchanged. Speeds up frames nobody looks at.
+    pub fn set_rendering(&mut self, enabled: bool) {
+        self.ppu.set_skip_rendering(!enabled);
+        if enabled {
+            self.ppu.invalidate_lines();
+        }
+    }
+
+    pub fn hidden_layers(&self) -> Layers {
+        self.ppu.hidden_layers()
+    }
+
+    /// Leave layers out of the picture to isolate rendering issues, e.g. only the sprites for a sprite sheet.
+    /// Debug only, the machine runs the same.
+    pub fn set_hidden_layers(&mut self, layers: Layers) {
+        self.ppu.set_hidden_layers(layers);
+        self.ppu.invalidate_lines();
+    }
+
+    pub fn breakpoint_manager(&self) -> &BreakpointManager {
+        &self.breakpoint_manager
+    }
+
+    pub fn breakpoint_manager_mut(&mut self) -> &mut BreakpointManager {
+        &mut self.breakpoint_manager
+    }
+
+    /// Registers before each of the last [`TRACE_LENGTH`](crate::TRACE_LENGTH) executed instructions,
+    /// oldest first, empty unless [`Machine::set_instruction_trace`] is on
+    pub fn trace(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.trace.entries()
+    }
+    /// Keep the registers of the last instructions for [`Machine::trace`] and the crash reports, see
+    /// [`MachineBuilder::instruction_trace`]
+    pub fn instruction_trace(&self) -> bool {
+        self.trace.is_enabled()
+    }
+    /// Turning the trace off drops its entries
+    pub fn set_instruction_trace(&mut self, enabled: bool) {
+        self.trace.set_enabled(enabled);
+    }
+
+    /// Bytes sent on the serial port since the last call, e.g. the results printed by test roms
+    pub fn take_serial_output(&mut self) -> Vec<u8> {
+        self.serial.take_output()
+    }
+
+    /// Clock pulse from a link partner, see [`Serial::external_clock`]
+    pub fn serial_external_clock(&mut self, incoming: bool) -> Option<bool> {
+        self.serial.external_clock(&mut self.bus, incoming)
+    }
+
+    /// Plug a link partner clocked by this console, returns the one it replaces
+    pub fn connect_serial(&mut self, device: impl SerialDevice + 'static) -> Option<Box<dyn SerialDevice>> {
+        self.serial.connect(Box::new(device))
+    }
+
+    /// Unplug the link partner, the bits received with the internal clock are 1 again
+    pub fn disconnect_serial(&mut self) -> Option<Box<dyn SerialDevice>> {
+        self.serial.disconnect()
+    }
+
+    pub fn serial(&self) -> &Serial {
+        &self.serial
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    /// Record `duration` of emulated audio, see [`Apu::start_capture`]
+    pub fn capture_audio(
+        &mut self,
+        path: impl AsRef<Path>,
+        channels: AudioChannels,
+        duration: Duration,
+    ) -> Result<(), std::io::Error> {
+        self.apu.start_capture(path, channels)?;
+        self.apu
+            .limit_capture((duration.as_secs_f64() * SAMPLE_RATE as f64) as u64);
+        Ok(())
+    }
+
+    /// Log the sound register writes to a VGM file, see [`Apu::start_sound_log`]
+    pub fn start_sound_log(&mut self, path: impl AsRef<Path>) -> Result<(), std::io::Error> {
+        self.apu.start_sound_log(path, &self.bus)
+    }
+
+    /// Opcode, bus access and timing counters since power on
+    #[cfg(feature = "profiling")]
+    pub fn profile_report(&self) -> ProfileReport {
+        ProfileReport::new(&self.cpu_counters, self.bus.access_counters(), self.ppu.line_render)
+    }
+    /// Clear the counters of [`Machine::profile_report`], to measure from now on
+    #[cfg(feature = "profiling")]
+    pub fn reset_profile(&mut self) {
+        self.cpu_counters = CpuCounters::default();
+        self.bus.reset_access_counters();
+        self.ppu.line_render = Default::default();
+    }
+
+    /// Opcode at PC, `$CBxx` for prefixed instructions
+    #[cfg(feature = "profiling")]
+    fn current_opcode(&self) -> u16 {
+        let pc = self.cpu.pc();
+        match self.bus.read_byte(pc) {
+            0xCB => 0xCB00 | self.bus.read_byte(pc.wrapping_add(1)) as u16,
+            opcode => opcode as u16,
+        }
+    }
+
+    /// Post-mortem report of the error returned by [`Machine::step`]
+    pub fn crash_report(&self, error: &str) -> CrashReport {
+        CrashReport::new(self, error)
+    }
+
+    /// Registers and memory now, to find out later what changed with [`Machine::diff_since`]
+    pub fn snapshot(&self) -> MachineSnapshot {
+        MachineSnapshot::capture(self)
+    }
+
+    /// Registers, IO registers and memory ranges that changed since `snapshot`
+    pub fn diff_since(&self, snapshot: &MachineSnapshot) -> StateDiff {
+        StateDiff::new(snapshot, &self.snapshot())
+    }
+
+    pub fn status(&self) -> &EmulationStatus {
+        &self.status
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.status == EmulationStatus::Running
+    }
+
+    /// Ask the frontend to stop running frames, a stopped machine stays stopped
+    pub fn pause(&mut self) {
+        if self.status == EmulationStatus::Running {
+            self.status = EmulationStatus::Paused;
+        }
+    }
+
+    /// Resume a paused machine, a stopped machine stays stopped
+    pub fn resume(&mut self) {
+        if self.status == EmulationStatus::Paused {
+            self.status = EmulationStatus::Running;
+        }
+    }
+
+    /// Leave the stopped state after a reset or a state load, the machine is then paused
+    fn recover(&mut self) {
+        if matches!(self.status, EmulationStatus::Stopped(_)) {
+            self.status = EmulationStatus::Paused;
+        }
+    }
+
+    /// Break the next time `expression` is true after an instruction, e.g. `PC == $4000 && A != 0`.
+    /// The condition is checked by [`Machine::step_frame`] and removed once reached.
+    pub fn run_until_expr(&mut self, expression: &str) -> Result<(), std::io::Error> {
+        self.break_condition = Some(Expression::parse(expression)?);
+        Ok(())
+    }
+
+    pub fn break_condition(&self) -> Option<&Expression> {
+        self.break_condition.as_ref()
+    }
+
+    pub fn clear_break_condition(&mut self) {
+        self.break_condition = None;
+    }
+
+    /// Run the machine until the end of the current frame (VBlank), a breakpoint, the condition set
+    /// by [`Machine::run_until_expr`] or the end of the instruction/cycle budget set on the
+    /// [`BreakpointManager`].
+    ///
+    /// Cycles spent in the current frame are kept between calls, so a frame interrupted by a
+    /// breakpoint is completed by the next call. When the LCD is off, the frame ends after
+    /// [`CYCLES_PER_FRAME`] cycles.
+    ///
+    /// A breakpoint pauses the machine. An error stops it: it is returned once, then nothing is
+    /// executed until a reset or a state load, see [`Machine::status`].
+    pub fn step_frame(&mut self) -> Result<FrameResult, Box<dyn Error>> {
+        self.run(None)
+    }
+
+    /// Run the machine for `cycles` cycles across frames, a breakpoint stops it early like
+    /// [`Machine::step_frame`]. The last instruction may go past, [`FrameResult::cycles`] is the count executed.
+    pub fn run_cycles(&mut self, cycles: usize) -> Result<FrameResult, Box<dyn Error>> {
+        self.run(Some(cycles))
+    }
+
+    /// Until the end of the frame, or until `budget` cycles are executed
+    fn run(&mut self, budget: Option<usize>) -> Result<FrameResult, Box<dyn Error>> {
+        let mut result = FrameResult::default();
+        if matches!(self.status, EmulationStatus::Stopped(_)) {
+            return Ok(result);
+        }
+
+        loop {
+            match budget {
+                Some(budget) if result.cycles >= budget => break,
+                None if result.frame_completed => break,
+                _ => {}
+            }
+            if self.frame_cycles == 0 {
+                self.advance_inputs();
+                if let Some(mut session) = self.session.take() {
+                    session.record_frame(self);
+                    self.session = Some(session);
+                }
+            }
+
+            // Frames and budgets are in cycles of the normal speed, the PPU keeps its pace in double speed
+            let double_speed = self.bus.is_double_speed();
+            let cycles = self.step()? as usize >> double_speed as usize;
+            result.cycles += cycles;
+            self.frame_cycles += cycles;
+
+            if self.ppu.take_frame_ready() || self.frame_cycles >= CYCLES_PER_FRAME {
+                self.frame_cycles = 0;
+                self.frame_count += 1;
+                result.frame_completed = true;
+                self.bus.restore_frozen();
+                self.changed_tiles = self.bus.take_changed_tiles();
+                self.dump_frame();
+                self.feed_video_sinks();
+                self.feed_audio_sinks();
+                self.push_event(MachineEvent::FrameCompleted {
+                    frame: self.frame_count,
+                });
+
+                let ram_written = self.bus.cartridge_mut().take_ram_dirty();
+                if ram_written {
+                    self.push_event(MachineEvent::SaveRamModified);
+                }
+                if let Some(battery) = self.battery.as_mut()
+                    && let Err(e) = battery.frame(ram_written, self.bus.cartridge_mut())
+                {
+                    error!("Failed to write battery save: {e}");
+                }
+            }
+
+            if let Some(violation) = self.bus.protection_mut().take_violation() {
+                info!(
+                    "Write of ${:02X} to read-only ${:04X} at PC ${:04X}",
+                    violation.value, violation.address, self.instruction_pc
+                );
+                result.hit_breakpoint = true;
+                break;
+            }
+            if self.breakpoint_manager.take_budget_reached()
+                || self.breakpoint_manager.take_vector_hit()
+                || self.breakpoint_manager.take_outside_code_hit()
+                || self.breakpoint_manager.has_breakpoint(self.cpu.pc())
+            {
+                result.hit_breakpoint = true;
+                break;
+            }
+            if self
+                .break_condition
+                .as_ref()
+                .is_some_and(|condition| condition.is_true(self))
+            {
+                self.break_condition = None;
+                result.hit_breakpoint = true;
+                break;
+            }
+        }
+
+        if result.hit_breakpoint {
+            self.pause();
+            self.push_event(MachineEvent::BreakpointHit { address: self.cpu.pc() });
+        }
+        Ok(result)
+    }
+
+    /// Apply the turbo and the playing macro, and record the buttons of the frame about to start
+    fn advance_inputs(&mut self) {
+        self.joypad.advance_turbo();
+        if let Some((input_macro, next)) = self.macro_playback.as_mut() {
+            match input_macro.frames().get(*next) {
+                Some(&pressed) => {
+                    self.joypad.set_overlay(pressed);
+                    *next += 1;
+                }
+                None => self.stop_macro(),
+            }
+        }
+        if let Some(frames) = self.macro_recording.as_mut() {
+            frames.push(self.joypad.pressed());
+        }
+    }
+
+    fn feed_video_sinks(&mut self) {
+        let frame = FrameRef {
+            number: self.frame_count,
+            shades: &self.ppu.frame_buffer,
+            colors: self.bus.is_cgb_mode().then_some(&self.ppu.rgb_frame_buffer[..]),
+            palette: &self.color_palette,
+        };
+        let mut sinks = std::mem::take(&mut self.video_sinks);
+        sinks.retain_mut(|sink| match sink.on_frame(&frame) {
+            Ok(()) if !sink.is_done() => true,
+            Ok(()) => {
+                finish_video_sink(sink.as_mut());
+                false
+            }
+            Err(e) => {
+                error!("Video sink removed: {e}");
+                false
+            }
+        });
+        self.video_sinks = sinks;
+    }
+
+    fn feed_audio_sinks(&mut self) {
+        if self.audio_sinks.is_empty() {
+            return;
+        }
+        self.apu.drain_samples(&mut self.audio_chunk);
+        let chunk = AudioChunk {
+            timestamp: self.audio_timestamp,
+            frame: self.frame_count,
+            samples: &self.audio_chunk,
+        };
+        let mut sinks = std::mem::take(&mut self.audio_sinks);
+        sinks.retain_mut(|sink| match sink.on_samples(&chunk) {
+            Ok(()) if !sink.is_done() => true,
+            Ok(()) => {
+                finish_audio_sink(sink.as_mut());
+                false
+            }
+            Err(e) => {
+                error!("Audio sink removed: {e}");
+                false
+            }
+        });
+        self.audio_sinks = sinks;
+        self.audio_timestamp += self.audio_chunk.len() as u64;
+        self.audio_chunk.clear();
+        if self.audio_sinks.is_empty() {
+            self.apu.keep_samples(false);
+        }
+    }
+
+    fn dump_frame(&mut self) {
+        let Some(mut dump) = self.frame_dump.take() else { return };
+
+        match dump.record(self) {
+            Ok(()) if dump.remaining() > 0 => self.frame_dump = Some(dump),
+            Ok(()) => info!("Frame dump completed"),
+            Err(e) => error!("Failed to dump frame: {e}"),
+        }
+    }
+
+    /// Execute one instruction, a stopped machine executes nothing and returns 0 cycles.
+    /// In double speed the PPU and APU run half the cycles returned.
+    pub fn step(&mut self) -> Result<u8, Box<dyn Error>> {
+        if matches!(self.status, EmulationStatus::Stopped(_)) {
+            return Ok(0);
+        }
+        let double_speed = self.bus.is_double_speed();
+        if self.trace.is_enabled() {
+            self.trace.push(TraceEntry::capture(&self.cpu));
+        }
+        let pc = self.cpu.pc();
+        self.instruction_pc = pc;
+        #[cfg(feature = "profiling")]
+        let (start, opcode) = (std::time::Instant::now(), self.current_opcode());
+        let cycles = self.cpu.step(&mut self.bus).inspect_err(|e| {
+            self.status = EmulationStatus::Stopped(e.clone());
+            self.push_event(MachineEvent::EmulationError(e.clone()));
+        })?;
+        #[cfg(feature = "profiling")]
+        {
+            self.cpu_counters.step.record(start);
+            self.cpu_counters.record_opcode(opcode, cycles);
+        }
+        if cfg!(feature = "accuracy-extras")
+            && self.accuracy.oam_bug
+            && self
+                .cpu
+                .idu_value
+                .is_some_and(|value| (0xFE00..=0xFEFF).contains(&value))
+        {
+            self.ppu.corrupt_oam(&mut self.bus);
+        }
+        // STOP switches the speed requested with KEY1 and resumes right away
+        if self.cpu.stop() && self.bus.take_speed_switch() {
+            self.cpu.set_stopped(false);
+        }
+        let dots = cycles >> double_speed as u8;
+        self.cycles += dots as u64;
+        self.bus.step_dma(cycles);
+        self.ppu.update(&mut self.bus, dots as u32);
+        if !self.cpu.stop() {
+            self.timer.step(&mut self.bus, cycles);
+            self.serial.step(&mut self.bus, cycles);
+            self.apu.step(&mut self.bus, dots);
+        }
+        self.joypad.update(&mut self.bus);
+        self.breakpoint_manager.consume(cycles);
+        if let Some(address) = self.cpu.vector_call {
+            self.breakpoint_manager.vector_called(address);
+            // RST vectors are below the interrupt ones
+            if let Some(&interrupt) = address
+                .checked_sub(0x40)
+                .and_then(|offset| Interrupt::SOURCES.get(offset as usize / 8))
+            {
+                self.push_event(MachineEvent::InterruptDispatched(interrupt));
+            }
+        }
+        if let Some(byte) = self.serial.take_sent() {
+            self.push_event(MachineEvent::SerialByte(byte));
+        }
+        // Once per excursion, not for each instruction executed outside
+        if is_code_address(pc) && !is_code_address(self.cpu.pc()) {
+            self.breakpoint_manager.left_code();
+            self.push_event(MachineEvent::ExecutionOutsideCode {
+                from: pc,
+                to: self.cpu.pc(),
+            });
+        }
+
+        Ok(cycles)
+    }
+
+    /// The DMG has no reset button, this is a short power cycle keeping the cartridge ram and clock. The
+    /// work ram and high ram keep their content too, they only get the [`RamInit`] pattern at power on.
+    pub fn reset(&mut self) {
+        self.restart(PowerCycleOptions::default(), false);
+    }
+
+    /// Turn the console off and on, in this order:
+    /// 1. the machine counters, trace and macro are cleared and a recorded session is marked discontinuous
+    /// 2. the battery save is written, then the cartridge mapper is reset and its ram and clock are cleared
+    ///    unless kept
+    /// 3. the random generator is seeded again, then the bus (ram, boot rom mapping, DMA), CPU, timer, serial,
+    ///    APU, PPU and joypad are reset, the work ram and high ram get the [`RamInit`] pattern
+    /// 4. the interrupt registers get their power on values
+    /// 5. the boot rom is skipped with a fast boot
+    ///
+    /// A CGB model runs in CGB mode when the cartridge header flags a CGB game, see [`Machine::is_cgb_mode`].
+    pub fn power_cycle(&mut self, options: PowerCycleOptions) {
+        self.restart(options, true);
+    }
+
+    /// Power cycle, or reset keeping the work ram and high ram without `power_on`
+    fn restart(&mut self, options: PowerCycleOptions, power_on: bool) {
+        info!("{} ({options:?})", if power_on { "Power cycle" } else { "Reset" });
+        self.recover();
+        self.frame_cycles = 0;
+        self.frame_count = 0;
+        self.cycles = 0;
+        self.audio_timestamp = 0;
+        self.trace.clear();
+        self.stop_macro();
+        if let Some(session) = self.session.as_mut() {
+            session.mark_discontinuity();
+        }
+        if !(options.keep_sram && options.keep_rtc)
+            && let Err(e) = self.flush_sram()
+        {
+            error!("Failed to write battery save: {e}");
+        }
+        self.bus
+            .cartridge_mut()
+            .power_cycle(options.keep_sram, options.keep_rtc);
+        self.rng = Rng::new(self.seed);
+        let cgb_mode = self.model == Model::Cgb && self.bus.cartridge().supports_cgb();
+        self.bus.set_cgb_mode(cgb_mode);
+        self.ppu.set_cgb_mode(cgb_mode);
+        self.bus.reset();
+        if power_on {
+            self.bus.init_ram(&mut self.rng);
+        }
+        self.cpu.reset();
+        if cgb_mode {
+            self.set_cgb_post_boot_registers();
+        }
+        if let Some(addr) = self.start_addr {
+            self.cpu.set_pc(addr);
+        }
+        self.timer.reset(&mut self.bus);
+        self.serial.reset(&mut self.bus);
+        self.apu.reset(&mut self.bus);
+        self.ppu.reset(&mut self.bus);
+        self.joypad.reset(&mut self.bus);
+
+        self.bus.set_interrupt_enable_u8(0x00);
+        self.bus.set_interrupt_flag_u8(0xE1);
+
+        if self.fast_boot {
+            self.skip_boot();
+        }
+    }
+
+    /// Jump to the cartridge entry point with the registers and IO left by the DMG boot rom, as listed by
+    /// the Pan Docs power up sequence, without running a boot rom. F depends on the header checksum,
+    /// DIV is partway through its count and the PPU partway through line 153.
+    pub fn skip_boot(&mut self) {
+        self.bus.disable_boot_rom();
+
+        self.cpu.reset();
+        if self.bus.is_cgb_mode() {
+            self.set_cgb_post_boot_registers();
+        } else {
+            let flags = match self.bus.read_byte(0x014D) {
+                0x00 => CpuFlags::Z,
+                _ => CpuFlags::Z | CpuFlags::H | CpuFlags::C,
+            };
+            self.cpu.set_f(flags.bits());
+        }
+
+        self.timer.set_counter(&mut self.bus, DMG_POST_BOOT_COUNTER);
+        self.ppu.skip_boot(&mut self.bus);
+        self.bus.write_internal_byte(0xFF00, 0xCF);
+        self.bus.set_interrupt_enable_u8(0x00);
+        self.bus.set_interrupt_flag_u8(0xE1);
+    }
+    /// Registers left by the CGB boot rom for a CGB game, A = $11 tells the game it runs on a CGB
+    fn set_cgb_post_boot_registers(&mut self) {
+        self.cpu.set_af(0x1180);
+        self.cpu.set_bc(0x0000);
+        self.cpu.set_de(0xFF56);
+        self.cpu.set_hl(0x000D);
+    }
+    /// Reset with [`Machine::skip_boot`], see [`MachineBuilder::fast_boot`]
+    pub fn fast_boot(&self) -> bool {
+        self.fast_boot
+    }
+
+    /// Snapshot of the whole machine, the cartridge rom and boot rom are not included.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut writer = StateWriter::default();
+        writer.write_bytes(STATE_MAGIC);
+        writer.write_u8(STATE_VERSION);
+
+        writer.write_section(CPU_SECTION, |writer| self.cpu.save_state(writer));
+        writer.write_section(BUS_SECTION, |writer| self.bus.save_state(writer));
+        writer.write_section(PPU_SECTION, |writer| self.ppu.save_state(writer));
+        writer.write_section(TIMER_SECTION, |writer| self.timer.save_state(writer));
+        writer.write_section(SERIAL_SECTION, |writer| self.serial.save_state(writer));
+        writer.write_section(APU_SECTION, |writer| self.apu.save_state(writer));
+        writer.write_section(JOYPAD_SECTION, |writer| self.joypad.save_state(writer));
+        writer.write_section(MACHINE_SECTION, |writer| {
+            writer.write_u32(self.frame_cycles as u32);
+            writer.write_u64(self.seed);
+            self.rng.save_state(writer);
+        });
+
+        writer.into_inner()
+    }
+
+    /// CRC-32 of the shade ids of the frame, followed in CGB mode by its RGB555 colors. Unlike
+    /// [`Machine::state_hash`] the same on every build.
+    pub fn frame_hash(&self) -> u32 {
+        match self.frame_rgb555() {
+            Some(colors) => {
+                let colors: Vec<u8> = colors.iter().flat_map(|color| color.to_le_bytes()).collect();
+                crc32(&[self.frame(), &colors])
+            }
+            None => crc32(&[self.frame()]),
+        }
+    }
+
+    /// Hash of [`Machine::save_state`], equal for two machines in the same state
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = std::hash::DefaultHasher::new();
+        std::hash::Hasher::write(&mut hasher, &self.save_state());
+        std::hash::Hasher::finish(&hasher)
+    }
+
+    /// Pinned host defaults, see [`MachineBuilder::deterministic`]
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// Hardened for untrusted roms, see [`MachineBuilder::sandbox`]
+    pub fn is_sandboxed(&self) -> bool {
+        self.sandbox
+    }
+
+    /// Restore a snapshot created by [`Machine::save_state`] with the same cartridge.
+    /// The machine is left untouched when the state is rejected, unless its own state can not be
+    /// restored either, which the error then reports.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), std::io::Error> {
+        let backup = self.save_state();
+
+        if let Err(e) = self.read_state(data) {
+            return match self.read_state(&backup) {
+                Ok(()) => Err(e),
+                Err(restore) => Err(std::io::Error::new(
+                    restore.kind(),
+                    format!("{e}, and the previous state could not be restored: {restore}"),
+                )),
+            };
+        }
+        self.trace.clear();
+        self.recover();
+        if let Some(session) = self.session.as_mut() {
+            session.mark_discontinuity();
+        }
+        Ok(())
+    }
+
+    fn read_state(&mut self, data: &[u8]) -> Result<(), std::io::Error> {
+        if data.len() > MAX_STATE_SIZE {
+            return Err(invalid_data("larger than any machine state"));
+        }
+        let mut reader = StateReader::new(data);
+
+        let mut magic = [0u8; 4];
+        reader.read_bytes(&mut magic)?;
+        if &magic != STATE_MAGIC {
+            return Err(invalid_data("bad magic"));
+        }
+        let mut sections = match reader.read_u8()? {
+            STATE_VERSION => StateSections::read(&mut reader)?,
+            LEGACY_STATE_VERSION => StateSections::Legacy(reader),
+            _ => return Err(invalid_data("unsupported version")),
+        };
+
+        sections.load(CPU_SECTION, |reader| self.cpu.load_state(reader))?;
+        sections.load(BUS_SECTION, |reader| self.bus.load_state(reader))?;
+        sections.load(PPU_SECTION, |reader| self.ppu.load_state(reader))?;
+        sections.load(TIMER_SECTION, |reader| self.timer.load_state(reader))?;
+        sections.load(SERIAL_SECTION, |reader| self.serial.load_state(reader))?;
+        sections.load(APU_SECTION, |reader| self.apu.load_state(reader))?;
+        sections.load(JOYPAD_SECTION, |reader| self.joypad.load_state(reader))?;
+        sections.load(MACHINE_SECTION, |reader| {
+            self.frame_cycles = reader.read_u32()? as usize;
+            self.seed = reader.read_u64()?;
+            self.rng.load_state(reader)
+        })?;
+
+        sections.finish()
+    }
+
+    /// Record the next frames into a [`Session`], with a snapshot every `snapshot_interval` frames
+    pub fn start_session_recording(&mut self, snapshot_interval: u32) {
+        info!("Session recording started");
+        self.session = Some(Session::new(self.bus.cartridge().title(), self.seed, snapshot_interval));
+    }
+
+    /// Record a new branch of `session` from `frame` (clamped to its end): the machine is put in the state of
+    /// `frame`, replayed from the nearest snapshot, and the recorded frames from there are dropped.
+    pub fn resume_session_recording(&mut self, mut session: Session, frame: u32) -> Result<(), std::io::Error> {
+        let frame = frame.min(session.frame_count());
+        self.session = None;
+        session.rebuild(self, frame)?;
+        session.branch(frame);
+        info!("Session recording resumed at frame {frame}");
+        self.session = Some(session);
+        Ok(())
+    }
+
+    pub fn stop_session_recording(&mut self) -> Option<Session> {
+        self.session.take()
+    }
+
+    pub fn is_recording_session(&self) -> bool {
+        self.session.is_some()
+    }
+
+    /// Record the buttons pressed on each of the next frames into an [`InputMacro`]
+    pub fn start_macro_recording(&mut self) {
+        self.macro_recording = Some(vec![]);
+    }
+
+    pub fn stop_macro_recording(&mut self) -> Option<InputMacro> {
+        self.macro_recording.take().map(|frames| InputMacro::new(&frames))
+    }
+
+    pub fn is_recording_macro(&self) -> bool {
+        self.macro_recording.is_some()
+    }
+
+    /// Hold the buttons of the macro from the next frame on, one entry per frame, on top of the
+    /// buttons pressed by the host
+    pub fn play_macro(&mut self, input_macro: InputMacro) {
+        self.macro_playback = Some((input_macro, 0));
+    }
+
+    pub fn is_playing_macro(&self) -> bool {
+        self.macro_playback.is_some()
+    }
+
+    /// Release the buttons held by the playing macro
+    pub fn stop_macro(&mut self) {
+        self.macro_playback = None;
+        self.joypad.set_overlay(0);
+    }
+
+    /// Pressed buttons, d-pad (Right, Left, Up, Down) in bits 0-3 and A, B, Select, Start in bits 4-7
+    pub fn pressed_buttons(&self) -> u8 {
+        self.joypad.pressed()
+    }
+
+    pub fn set_pressed_buttons(&mut self, pressed: u8) {
+        self.joypad.set_pressed(pressed);
+    }
+
+    /// Buttons of the current frame with the turbo ones, for an input display
+    pub fn input_state(&self) -> InputState {
+        InputState {
+            pressed: self.joypad.pressed(),
+            turbo: self.joypad.turbo().buttons(),
+        }
+    }
+
+    pub fn turbo(&self) -> &Turbo {
+        self.joypad.turbo()
+    }
+
+    /// Answer the `MLT_REQ` packets some games send through P1 to detect a Super Game Boy and wait on: P1 reads
+    /// the id of the requested player with both select bits high, one player until a request. Other commands
+    /// are ignored, there is no SGB border nor palette.
+    pub fn set_sgb_stub(&mut self, enabled: bool) {
+        self.joypad.set_sgb_stub(enabled);
+    }
+    pub fn sgb_stub(&self) -> bool {
+        self.joypad.sgb_stub()
+    }
+
+    /// Auto fire rates of the held buttons, applied from the next frame
+    pub fn turbo_mut(&mut self) -> &mut Turbo {
+        self.joypad.turbo_mut()
+    }
+
+    pub fn button_pressed(&mut self, button: joypad::Button) {
+        self.joypad.button_pressed(button);
+    }
+
+    pub fn button_released(&mut self, button: joypad::Button) {
+        self.joypad.button_released(button);
+    }
+
+    pub fn button_changed(&mut self, button: joypad::Button, pressed: bool) {
+        if pressed {
+            self.button_pressed(button);
+        } else {
+            self.button_released(button);
+        }
+    }
+}
+
+impl Drop for Machine {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush_sram() {
+            error!("Failed to write battery save: {e}");
+        }
+        if let Err(e) = self.apu.stop_capture() {
+            error!("Failed to finish audio capture: {e}");
+        }
+        if let Err(e) = self.apu.stop_sound_log() {
+            error!("Failed to finish sound log: {e}");
+        }
+        self.clear_video_sinks();
+        self.clear_audio_sinks();
+    }
+}
+
+fn finish_audio_sink(sink: &mut dyn AudioSink) {
+    if let Err(e) = sink.finish() {
+        error!("Failed to finish audio sink: {e}");
+    }
+}
+
+fn finish_video_sink(sink: &mut dyn VideoSink) {
+    if let Err(e) = sink.finish() {
+        error!("Failed to finish video sink: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp::TempDir;
+
+    /// Rom looping on NOPs: $0100 NOP, $0101 NOP, $0102 JR -4
+    fn nop_loop_rom() -> Vec<u8> {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x0102..0x0104].copy_from_slice(&[0x18, 0xFC]);
+        rom
+    }
+
+    #[test]
+    fn test_save_and_load_state() -> Result<(), Box<dyn Error>> {
+        let mut machine = Machine::builder().cartridge_bytes(nop_loop_rom()).build()?;
+        machine.step_frame()?;
+        let state = machine.save_state();
+        let (pc, ly) = (machine.cpu().pc(), machine.bus().read_byte(0xFF44));
+
+        machine.step_frame()?;
+        machine.step()?;
+        assert_ne!(machine.save_state(), state);
+
+        machine.load_state(&state)?;
+        assert_eq!(machine.cpu().pc(), pc);
+        assert_eq!(machine.bus().read_byte(0xFF44), ly);
+        assert_eq!(machine.save_state(), state);
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_restores_banking_and_sram() -> Result<(), Box<dyn Error>> {
+        let rom = crate::TestRom::new()
+            .cartridge_type(0x03) // MBC1+RAM+BATTERY
+            .rom_banks(4)
+            .ram_size(0x02)
+            .code(&[0x3E, 0x0A, 0xEA, 0x00, 0x00]) // LD A,$0A ; LD ($0000),A ; enable ram
+            .code(&[0x3E, 0x03, 0xEA, 0x00, 0x20]) // LD A,$03 ; LD ($2000),A ; rom bank 3
+            .code(&[0x3E, 0x5A, 0xEA, 0x00, 0xA0]) // LD A,$5A ; LD ($A000),A
+            .code(&[0x18, 0xFE]) // JR -2
+            .org(3 * 0x4000)
+            .code(&[0xB3])
+            .build();
+        let mut machine = Machine::builder().cartridge_bytes(rom.clone()).build()?;
+        machine.step_frame()?;
+        let state = machine.save_state();
+
+        // Power on state: bank 1 mapped, ram disabled
+        let mut restored = Machine::builder().cartridge_bytes(rom).build()?;
+        assert_ne!(restored.peek(0x4000), 0xB3);
+        restored.load_state(&state)?;
+        assert_eq!(restored.peek(0x4000), 0xB3);
+        assert_eq!(restored.peek(0xA000), 0x5A);
+        assert_eq!(restored.cpu().state(), machine.cpu().state());
+        assert_eq!(restored.state_hash(), machine.state_hash());
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_interrupt() -> Result<(), Box<dyn Error>> {
+        let mut machine = Machine::builder().cartridge_bytes(nop_loop_rom()).build()?;
+        machine.clear_interrupt(Interrupt::all());
+        machine.poke(0xFFFF, Interrupt::VBLANK.bits());
+
+        machine.request_interrupt(Interrupt::VBLANK | Interrupt::TIMER);
+        let state = machine.interrupt_state();
+        assert_eq!(state.sources()[0], (Interrupt::VBLANK, true, true));
+        assert_eq!(state.sources()[2], (Interrupt::TIMER, false, true));
+        assert!(!state.is_requested(Interrupt::SERIAL));
+
+        machine.clear_interrupt(Interrupt::TIMER);
+        assert!(!machine.interrupt_state().is_requested(Interrupt::TIMER));
+        Ok(())
+    }
+
+    #[test]
+    fn test_changed_lines() -> Result<(), Box<dyn Error>> {
+        let mut machine = Machine::builder().cartridge_bytes(nop_loop_rom()).build()?;
+        machine.step_frame()?;
+        machine.step_frame()?;
+        assert_eq!(machine.take_changed_lines(), ChangedLines::all());
+
+        // Blank vram, the frame stays the same
+        machine.step_frame()?;
+        assert!(machine.take_changed_lines().is_empty());
+
+        machine.set_color_palette(ColorPalette::GRAYSCALE);
+        assert_eq!(machine.take_changed_lines(), ChangedLines::all());
+        Ok(())
+    }
+
+    #[test]
+    fn test_changed_tiles() -> Result<(), Box<dyn Error>> {
+        let rom = crate::TestRom::new().code(&[0x18, 0xFE]).build(); // JR -2
+        let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+        machine.step_frame()?;
+        assert_eq!(machine.changed_tiles(), ChangedTiles::all());
+        machine.step_frame()?;
+        assert!(machine.changed_tiles().is_empty());
+
+        machine.poke(0x8010, 0x42); // tile 1
+        machine.poke(0x9800, 0x01); // tile map
+        machine.step_frame()?;
+        assert_eq!(machine.changed_tiles().iter().collect::<Vec<_>>(), [1]);
+
+        // Same bytes again
+        machine.poke(0x8010, 0x42);
+        machine.step_frame()?;
+        assert!(machine.changed_tiles().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_cartridge_bytes() -> Result<(), Box<dyn Error>> {
+        let mut rom = nop_loop_rom();
+        rom[0x0134..0x0138].copy_from_slice(b"PIPE");
+        let mut machine = Machine::default();
+
+        machine.load_cartridge_bytes(rom)?;
+        assert_eq!(machine.cartridge().title(), "PIPE");
+        assert!(machine.load_cartridge_bytes(vec![0; 0x10]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_until_expr() -> Result<(), Box<dyn Error>> {
+        let rom = crate::TestRom::new()
+            .code(&[0xAF]) // XOR A
+            .code(&[0x3C]) // INC A
+            .code(&[0x18, 0xFD]) // JR -3
+            .build();
+        let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+
+        assert!(machine.run_until_expr("A = 5").is_err());
+        machine.run_until_expr("A == 5 && PC == $0152")?;
+        assert!(machine.step_frame()?.hit_breakpoint);
+        assert_eq!((machine.cpu().a(), machine.cpu().pc()), (5, 0x0152));
+        assert!(machine.break_condition().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_emulated_time() -> Result<(), Box<dyn Error>> {
+        let mut machine = Machine::builder().cartridge_bytes(nop_loop_rom()).build()?;
+        let mut cycles = 0;
+        for _ in 0..60 {
+            cycles += machine.step_frame()?.cycles as u64;
+        }
+        assert_eq!(machine.cycles(), cycles);
+        // About 60 frames per second
+        let time = machine.emulated_time();
+        assert_eq!(time.as_nanos(), cycles as u128 * 1_000_000_000 / CPU_CLOCK_HZ as u128);
+        assert!(
+            time > Duration::from_secs(1) && time < Duration::from_millis(1010),
+            "{time:?}"
+        );
+
+        machine.reset();
+        assert_eq!(machine.emulated_time(), Duration::ZERO);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dump_frames() -> Result<(), Box<dyn Error>> {
+        let dir = TempDir::new("frames")?;
+        let mut machine = Machine::builder().cartridge_bytes(nop_loop_rom()).build()?;
+
+        machine.dump_frames(2, dir.path())?;
+        for _ in 0..3 {
+            machine.step_frame()?;
+        }
+        assert_eq!(machine.frame_count(), 3);
+        assert_eq!(machine.frames_left_to_dump(), 0);
+
+        assert!(std::fs::read(dir.join("frame_0000_000001.png"))?.starts_with(b"\x89PNG"));
+        assert!(dir.join("frame_0001_000002.png").exists());
+        assert!(!dir.join("frame_0002_000003.png").exists());
+        let json = std::fs::read_to_string(dir.join("frames.json"))?;
+        assert!(json.contains(r#""index": 1, "frame": 2, "file": "frame_0001_000002.png""#));
+        Ok(())
+    }
+
+    /// States of [`nop_loop_rom`] after one frame, written by the releases of version 6 (the components one
+    /// after the other) and of version 7 with the first version of each section
+    const STATE_FIXTURES: [&[u8]; 2] = [
+        include_bytes!("../../fixtures/state/nop_loop_v6.bin"),
+        include_bytes!("../../fixtures/state/nop_loop_v7.bin"),
+    ];
+
+    #[test]
+    fn test_load_previous_versions() -> Result<(), Box<dyn Error>> {
+        for fixture in STATE_FIXTURES {
+            let mut machine = Machine::builder().cartridge_bytes(nop_loop_rom()).build()?;
+            machine.load_state(fixture)?;
+
+            // The same frame run by this release, with the random seed of the fixture
+            let mut expected = Machine::builder()
+                .cartridge_bytes(nop_loop_rom())
+                .seed(machine.seed())
+                .build()?;
+            expected.step_frame()?;
+            assert_eq!(machine.save_state(), expected.save_state());
+
+            let mut trailing = fixture.to_vec();
+            trailing.push(0);
+            assert!(machine.load_state(&trailing).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_invalid_state_keeps_machine() -> Result<(), Box<dyn Error>> {
+        let mut machine = Machine::builder().cartridge_bytes(nop_loop_rom()).build()?;
+        machine.step_frame()?;
+        let state = machine.save_state();
+
+        assert!(machine.load_state(&state[..state.len() / 2]).is_err());
+        assert!(machine.load_state(b"nope").is_err());
+        assert_eq!(machine.save_state(), state);
+
+        let mut other_rom = nop_loop_rom();
+        other_rom[0x0134] = b'X';
+        let mut other = Machine::builder().cartridge_bytes(other_rom).build()?;
+        assert!(other.load_state(&state).is_err());
+        Ok(())
+    }
+
+    /// Truncated or oversized states are rejected without touching the machine
+    #[test]
+    fn test_state_size_limit() -> Result<(), Box<dyn Error>> {
+        // MBC3+RAM+BATTERY with the largest ram
+        let rom = crate::TestRom::new()
+            .cartridge_type(0x13)
+            .ram_size(0x04)
+            .code(&[0x18, 0xFE])
+            .build();
+        let mut machine = Machine::builder().cartridge_bytes(rom).sandbox(true).build()?;
+        let state = machine.save_state();
+        assert!(state.len() < MAX_STATE_SIZE / 2, "{} bytes", state.len());
+
+        let oversized = [state.as_slice(), &vec![0; MAX_STATE_SIZE]].concat();
+        assert!(machine.load_state(&oversized).is_err());
+        for len in (0..state.len()).step_by(997) {
+            assert!(machine.load_state(&state[..len]).is_err(), "{len} bytes");
+        }
+        assert_eq!(machine.save_state(), state);
+        Ok(())
+    }
+
+    #[test]
+    fn test_peek_poke() -> Result<(), Box<dyn Error>> {
+        let mut machine = Machine::builder().cartridge_bytes(nop_loop_rom()).build()?;
+
+        machine.poke(0xC000, 0x42);
+        assert_eq!(machine.peek(0xC000), 0x42);
+
+        // raw write keeps DIV, CPU write resets it
+        machine.poke(0xFF04, 0x12);
+        assert_eq!(machine.peek(0xFF04), 0x12);
+        machine.cpu_write(0xFF04, 0x12);
+        assert_eq!(machine.peek(0xFF04), 0x00);
+        Ok(())
+    }
+
+    #[test]
+    fn test_step_frame_stops_at_vblank() -> Result<(), Box<dyn Error>> {
+        let mut machine = Machine::builder().cartridge_bytes(nop_loop_rom()).build()?;
+
+        let result = machine.step_frame()?;
+
+        assert!(result.frame_completed);
+        assert!(!result.hit_breakpoint);
+        assert_eq!(machine.bus().read_byte(0xFF44), 144); // LY
+        Ok(())
+    }
+
+    #[test]
+    fn test_step_frame_resumes_after_breakpoint() -> Result<(), Box<dyn Error>> {
+        let mut reference = Machine::builder().cartridge_bytes(nop_loop_rom()).build()?;
+        let expected = reference.step_frame()?;
+
+        let mut machine = Machine::builder()
+            .cartridge_bytes(nop_loop_rom())
+            .breakpoint(0x0102)
+            .build()?;
+
+        let first = machine.step_frame()?;
+        assert!(first.hit_breakpoint);
+        assert!(!first.frame_completed);
+        assert_eq!(machine.status(), &EmulationStatus::Paused);
+
+        machine.breakpoint_manager_mut().clear();
+        let second = machine.step_frame()?;
+        assert!(second.frame_completed);
+        assert_eq!(first.cycles + second.cycles, expected.cycles);
+        Ok(())
+    }
+
+    #[test]
+    fn test_step_frame_stops_outside_code() -> Result<(), Box<dyn Error>> {
+        let rom = crate::TestRom::new()
+            .code(&[0xC3, 0x00, 0x80]) // JP $8000, into VRAM full of NOPs
+            .build();
+        let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+        // Entry point, JP $8000 then NOPs: one event for the jump, not for each instruction outside
+        for _ in 0..100 {
+            machine.step()?;
+        }
+        let events: Vec<_> = machine.drain_events().collect();
+        assert_eq!(
+            events,
+            [MachineEvent::ExecutionOutsideCode {
+                from: 0x0150,
+                to: 0x8000
+            }]
+        );
+
+        let rom = crate::TestRom::new().code(&[0xC3, 0x00, 0x80]).build();
+        let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+        machine.breakpoint_manager_mut().break_on_outside_code(true);
+        assert!(machine.step_frame()?.hit_breakpoint);
+        assert_eq!(machine.cpu().pc(), 0x8000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_step_frame_stops_on_vector_call() -> Result<(), Box<dyn Error>> {
+        let rom = crate::TestRom::new()
+            .code(&[0x3E, 0x01, 0xE0, 0xFF]) // LD A,$01; LDH (IE),A
+            .code(&[0xFB]) // EI
+            .code(&[0x18, 0xFE]) // JR -2
+            .build();
+        let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+        machine.breakpoint_manager_mut().break_on_interrupts(Interrupt::VBLANK);
+
+        assert!(machine.step_frame()?.hit_breakpoint);
+        assert_eq!(machine.cpu().pc(), 0x0040);
+
+        let rom = crate::TestRom::new().code(&[0xFF]).build(); // RST $38
+        let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+        machine.breakpoint_manager_mut().break_on_rst(0x38, true);
+
+        assert!(machine.step_frame()?.hit_breakpoint);
+        assert_eq!(machine.cpu().pc(), 0x0038);
+        Ok(())
+    }
+
+    #[test]
+    fn test_palette_helpers() -> Result<(), Box<dyn Error>> {
+        let mut machine = Machine::builder().build()?;
+        assert_eq!(machine.bgp(), Palette::from(0xFC));
+
+        machine.set_bgp(Palette([3, 2, 1, 0]));
+        machine.set_obp1(Palette::IDENTITY);
+        assert_eq!(machine.peek(0xFF47), 0x1B);
+        assert_eq!(machine.peek(0xFF49), 0xE4);
+        assert_eq!(machine.obp1(), Palette::IDENTITY);
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_protection() -> Result<(), Box<dyn Error>> {
+        let rom = crate::TestRom::new()
+            .code(&[0x3E, 0x09]) // LD A,$09
+            .code(&[0xEA, 0x00, 0xC0]) // LD ($C000),A
+            .code(&[0xEA, 0x00, 0xD0]) // LD ($D000),A
+            .code(&[0x18, 0xFE]) // JR -2
+            .build();
+        let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+        machine.memory_protection_mut().freeze(0xC000, 0x03);
+        machine.memory_protection_mut().protect(0xD000..=0xD0FF);
+
+        let result = machine.step_frame()?;
+        assert!(result.hit_breakpoint);
+        assert_eq!(machine.cpu().pc(), 0x0158);
+        assert_ne!(machine.peek(0xD000), 0x09);
+
+        // Restored on the next frame
+        machine.resume();
+        machine.poke(0xC000, 0x00);
+        assert!(machine.step_frame()?.frame_completed);
+        assert_eq!(machine.peek(0xC000), 0x03);
+        Ok(())
+    }
+
+    #[test]
+    fn test_power_cycle() -> Result<(), Box<dyn Error>> {
+        let rom = crate::TestRom::new()
+            .cartridge_type(0x03) // MBC1+RAM+BATTERY
+            .rom_banks(4)
+            .ram_size(0x02)
+            .build();
+        let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+        machine.poke(0x0000, 0x0A); // Enable ram
+        machine.poke(0x2000, 0x02); // Rom bank 2
+        machine.poke(0xA000, 0x42);
+
+        machine.reset();
+        assert_eq!(machine.mapper_state().rom_bank_high, 1);
+        assert!(!machine.mapper_state().ram_enabled);
+        assert_eq!(machine.cartridge().ram().map(|ram| ram[0]), Some(0x42));
+
+        machine.power_cycle(PowerCycleOptions {
+            keep_sram: false,
+            ..PowerCycleOptions::default()
+        });
+        assert_eq!(machine.cartridge().ram().map(|ram| ram[0]), Some(0x00));
+        assert_eq!(machine.cpu().pc(), 0x0100);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "accuracy-extras")]
+    fn test_oam_bug() -> Result<(), Box<dyn Error>> {
+        let oam_after_frame = |oam_bug: bool, hl: u16| -> Result<Vec<u8>, Box<dyn Error>> {
+            let [low, high] = hl.to_le_bytes();
+            let rom = crate::TestRom::new()
+                .code(&[0x21, low, high]) // LD HL,nn
+                .code(&[0x23]) // INC HL
+                .code(&[0x2B]) // DEC HL
+                .code(&[0x18, 0xFC]) // JR -4
+                .build();
+            let mut machine = Machine::builder().cartridge_bytes(rom).oam_bug(oam_bug).build()?;
+            for i in 0..0xA0 {
+                machine.poke(0xFE00 + i, i as u8);
+            }
+            machine.step_frame()?;
+            Ok((0xFE00..0xFEA0).map(|address| machine.peek(address)).collect())
+        };
+        let untouched: Vec<u8> = (0..0xA0).collect();
+
+        assert_ne!(oam_after_frame(true, 0xFE40)?, untouched);
+        assert_eq!(oam_after_frame(false, 0xFE40)?, untouched);
+        assert_eq!(oam_after_frame(true, 0xC000)?, untouched);
+        Ok(())
+    }
+
+    #[test]
+    fn test_input_macro_replay() -> Result<(), Box<dyn Error>> {
+        let mut machine = Machine::builder().cartridge_bytes(nop_loop_rom()).build()?;
+
+        machine.start_macro_recording();
+        machine.step_frame()?;
+        machine.button_pressed(joypad::Button::A);
+        machine.step_frame()?;
+        machine.step_frame()?;
+        machine.button_released(joypad::Button::A);
+        machine.button_pressed(joypad::Button::Right);
+        machine.step_frame()?;
+        machine.button_released(joypad::Button::Right);
+        machine.step_frame()?;
+        let input_macro = machine.stop_macro_recording().expect("recording");
+        assert_eq!(input_macro.frames(), [0x10, 0x10, 0x01]);
+
+        // Held on top of the host buttons, one entry per frame
+        machine.button_pressed(joypad::Button::B);
+        machine.play_macro(input_macro);
+        let mut pressed = vec![];
+        while machine.is_playing_macro() {
+            machine.step_frame()?;
+            pressed.push(machine.pressed_buttons());
+        }
+        assert_eq!(pressed, [0x30, 0x30, 0x21, 0x20]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_turbo_is_recorded() -> Result<(), Box<dyn Error>> {
+        let mut machine = Machine::builder().cartridge_bytes(nop_loop_rom()).build()?;
+        machine.turbo_mut().set_rate(joypad::Button::A, 30);
+        machine.button_pressed(joypad::Button::A);
+        machine.button_pressed(joypad::Button::Up);
+
+        machine.start_macro_recording();
+        let mut states = vec![];
+        for _ in 0..4 {
+            machine.step_frame()?;
+            states.push(machine.input_state());
+        }
+        let input_macro = machine.stop_macro_recording().expect("recording");
+        assert_eq!(input_macro.frames(), [0x14, 0x04, 0x14, 0x04]);
+        assert!(states.iter().all(|state| state.has_turbo(joypad::Button::A)));
+        assert!(states[0].is_pressed(joypad::Button::A) && !states[1].is_pressed(joypad::Button::A));
+        Ok(())
+    }
+
+    #[test]
+    fn test_video_sinks() -> Result<(), Box<dyn Error>> {
+        use crate::video::ChannelSink;
+
+        /// Done after two frames, counts its finish calls
+        struct TwoFrames(u64, std::sync::Arc<std::sync::atomic::AtomicU32>);
+        impl VideoSink for TwoFrames {
+            fn on_frame(&mut self, _frame: &FrameRef) -> Result<(), std::io::Error> {
+                self.0 += 1;
+                Ok(())
+            }
+            fn is_done(&self) -> bool {
+                self.0 == 2
+            }
+            fn finish(&mut self) -> Result<(), std::io::Error> {
+                self.1.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                Ok(())
+            }
+        }
+
+        let mut machine = Machine::builder().cartridge_bytes(nop_loop_rom()).build()?;
+        let (sink, receiver) = ChannelSink::new(8);
+        let finished = std::sync::Arc::default();
+        machine.add_video_sink(sink);
+        machine.add_video_sink(TwoFrames(0, std::sync::Arc::clone(&finished)));
+
+        for _ in 0..3 {
+            machine.step_frame()?;
+        }
+        let frames: Vec<_> = receiver.try_iter().collect();
+        assert_eq!(frames.iter().map(|frame| frame.number).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(frames[2].shades, machine.frame());
+        assert_eq!(finished.load(std::sync::atomic::Ordering::Relaxed), 1);
+        assert_eq!(machine.video_sinks.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_audio_sinks() -> Result<(), Box<dyn Error>> {
+        use crate::apu::WavSink;
+        use std::sync::{Arc, Mutex};
+
+        /// Keeps the timestamp, frame and length of each chunk
+        struct Chunks(Arc<Mutex<Vec<(u64, u64, usize)>>>);
+        impl AudioSink for Chunks {
+            fn on_samples(&mut self, chunk: &AudioChunk) -> Result<(), std::io::Error> {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((chunk.timestamp, chunk.frame, chunk.samples.len()));
+                Ok(())
+            }
+        }
+
+        let dir = TempDir::new("audio-sink")?;
+        let path = dir.join("audio.wav");
+        let mut machine = Machine::builder().cartridge_bytes(nop_loop_rom()).build()?;
+        let chunks = Arc::default();
+        machine.add_audio_sink(Chunks(Arc::clone(&chunks)));
+        machine.add_audio_sink(WavSink::create(&path)?);
+
+        for _ in 0..3 {
+            machine.step_frame()?;
+        }
+        let chunks = chunks.lock().unwrap().clone();
+        assert_eq!(chunks.iter().map(|chunk| chunk.1).collect::<Vec<_>>(), [1, 2, 3]);
+        for pair in chunks.windows(2) {
+            assert_eq!(pair[1].0, pair[0].0 + pair[0].2 as u64);
+        }
+        // One sample every 64 cycles, the first frame starts after the boot rom
+        for chunk in &chunks[1..] {
+            assert!(chunk.2.abs_diff(CYCLES_PER_FRAME / 64) <= 1, "{chunk:?}");
+        }
+        let samples: usize = chunks.iter().map(|chunk| chunk.2).sum();
+
+        machine.clear_audio_sinks();
+        assert_eq!(std::fs::metadata(&path)?.len(), 44 + 4 * samples as u64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_step_frame_stops_after_cycle_budget() -> Result<(), Box<dyn Error>> {
+        let mut machine = Machine::builder().cartridge_bytes(nop_loop_rom()).build()?;
+        machine.breakpoint_manager_mut().break_after_cycles(1000);
+
+        let result = machine.step_frame()?;
+        assert!(result.hit_breakpoint);
+        assert!((1000..1024).contains(&result.cycles));
+
+        let result = machine.step_frame()?;
+        assert!(result.frame_completed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_skip_boot() -> Result<(), Box<dyn Error>> {
+        let rom = crate::TestRom::new().code(&[0x18, 0xFE]).build(); // JR -2
+        let mut machine = Machine::builder()
+            .boot_rom_bytes(vec![0x00; 0x100])
+            .cartridge_bytes(rom.clone())
+            .fast_boot(true)
+            .build()?;
+
+        let cpu = machine.cpu();
+        assert_eq!(
+            (cpu.af(), cpu.bc(), cpu.de(), cpu.hl()),
+            (0x01B0, 0x0013, 0x00D8, 0x014D)
+        );
+        assert_eq!((cpu.sp(), cpu.pc()), (0xFFFE, 0x0100));
+        assert_eq!(machine.bus().read_byte(0x0100), 0x00, "boot rom still mapped");
+
+        // Pan Docs, as read by mooneye boot_hwio
+        #[rustfmt::skip]
+        let io = [
+            (0xFF00, 0xCF), (0xFF01, 0x00), (0xFF02, 0x7E), (0xFF04, 0xAB), (0xFF05, 0x00), (0xFF06, 0x00),
+            (0xFF07, 0xF8), (0xFF0F, 0xE1), (0xFF10, 0x80), (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF13, 0xFF),
+            (0xFF14, 0xBF), (0xFF16, 0x3F), (0xFF17, 0x00), (0xFF18, 0xFF), (0xFF19, 0xBF), (0xFF1A, 0x7F),
+            (0xFF1B, 0xFF), (0xFF1C, 0x9F), (0xFF1D, 0xFF), (0xFF1E, 0xBF), (0xFF20, 0xFF), (0xFF21, 0x00),
+            (0xFF22, 0x00), (0xFF23, 0xBF), (0xFF24, 0x77), (0xFF25, 0xF3), (0xFF26, 0xF1), (0xFF40, 0x91),
+            (0xFF41, 0x85), (0xFF42, 0x00), (0xFF43, 0x00), (0xFF44, 0x00), (0xFF45, 0x00), (0xFF46, 0xFF),
+            (0xFF47, 0xFC), (0xFF4A, 0x00), (0xFF4B, 0x00), (0xFFFF, 0x00),
+        ];
+        for (address, value) in io {
+            assert_eq!(machine.bus().read_byte(address), value, "${address:04X}");
+        }
+
+        // DIV ticks 52 cycles later: NOP, JP, then JR every 12 cycles
+        let mut cycles = 0;
+        for _ in 0..4 {
+            cycles += machine.step()? as u32;
+        }
+        assert_eq!((cycles, machine.bus().read_byte(0xFF04)), (44, 0xAB));
+        cycles += machine.step()? as u32;
+        assert_eq!(machine.bus().read_byte(0xFF04), 0xAC);
+
+        // VBlank ends halfway through line 153
+        while machine.bus().read_byte(0xFF41) & 0x03 == 0x01 {
+            cycles += machine.step()? as u32;
+        }
+        assert!((228..240).contains(&cycles), "{cycles}");
+
+        // Flags follow the header checksum, also after a reset
+        let mut rom = rom;
+        rom[0x014D] = 0x00;
+        machine.load_cartridge_bytes(rom)?;
+        machine.reset();
+        assert_eq!(machine.cpu().f(), 0x80);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cgb_mode() -> Result<(), Box<dyn Error>> {
+        let rom = crate::TestRom::new()
+            .cgb_flag(0x80)
+            .code(&[0x3E, 0x80, 0xE0, 0x68]) // BCPS: color 0 of palette 0, auto increment
+            .code(&[0x3E, 0x1F, 0xE0, 0x69]) // BCPD: red
+            .code(&[0xAF, 0xE0, 0x69])
+            .code(&[0x3E, 0x01, 0xE0, 0x4D]) // KEY1: switch to double speed
+            .code(&[0x10, 0x00]) // STOP
+            .code(&[0x18, 0xFE]) // JR -2
+            .build();
+
+        let dmg = Machine::builder().cartridge_bytes(rom.clone()).build()?;
+        assert!(!dmg.is_cgb_mode());
+        assert_eq!(dmg.cpu().a(), 0x01);
+
+        let mut machine = Machine::builder().model(Model::Cgb).cartridge_bytes(rom).build()?;
+        assert!(machine.is_cgb_mode());
+        let cpu = machine.cpu();
+        assert_eq!(
+            (cpu.af(), cpu.bc(), cpu.de(), cpu.hl()),
+            (0x1180, 0x0000, 0xFF56, 0x000D)
+        );
+
+        machine.step_frame()?;
+        assert!(machine.double_speed());
+        // The frames keep their length, with twice the instructions
+        let result = machine.step_frame()?;
+        assert!(result.cycles.abs_diff(CYCLES_PER_FRAME) <= 12, "{}", result.cycles);
+        assert!(
+            machine
+                .frame_rgb555()
+                .is_some_and(|frame| frame.iter().all(|&color| color == 0x001F))
+        );
+        assert!(machine.frame_rgba().iter().all(|&pixel| pixel == [0xFF, 0, 0, 0xFF]));
+        assert!(dmg.frame_rgb555().is_none());
+
+        // The colors of the last frame are part of the state
+        let state = machine.save_state();
+        machine.reset();
+        machine.load_state(&state)?;
+        assert!(machine.frame_rgba().iter().all(|&pixel| pixel == [0xFF, 0, 0, 0xFF]));
+        Ok(())
+    }
+
+    /// Mooneye roms downloaded by `doctor/setup.sh`, they pass with the Fibonacci numbers in B-L at `LD B,B`
+    #[test]
+    #[cfg(feature = "use-test-roms")]
+    fn test_mooneye_boot() -> Result<(), Box<dyn Error>> {
+        for name in ["boot_regs-dmgABC", "boot_hwio-dmgABCmgb", "boot_div-dmgABCmgb"] {
+            let path = format!("../doctor/roms/mooneye-test-suite/acceptance/{name}.gb");
+            assert_eq!(
+                crate::TestVerdict::run(path, 600)?,
+                crate::TestVerdict::Passed,
+                "{name}"
+            );
+        }
+        Ok(())
+    }
+
+    /// Smoke test of the CPU, PPU, timer and interrupts together: Tetris reaches its title screen the same way
+    /// as recorded in `doctor/regress.txt`. The rom is not distributed, it goes to `doctor/roms/tetris.gb`.
+    #[test]
+    #[cfg(feature = "use-test-roms")]
+    fn test_tetris_title_screen() -> Result<(), Box<dyn Error>> {
+        let cases = crate::RegressCase::load("../doctor/regress.txt")?;
+        let case = cases
+            .iter()
+            .find(|case| case.rom.ends_with("tetris.gb"))
+            .ok_or("no tetris case in doctor/regress.txt")?;
+        assert!(case.frames >= 600);
+        assert!(
+            case.is_recorded(),
+            "record the expected values with `doctor/run-regress.sh --record`"
+        );
+
+        assert_eq!(case.run("../doctor")?, *case);
+        Ok(())
+    }
+
+    /// Counts the VBlank interrupts in $FF80, drawn as the first row of tile 0, and the timer interrupts in $FF81
+    fn smoke_rom() -> Vec<u8> {
+        crate::TestRom::new()
+            .title("SMOKE")
+            .code(&[0xF3]) // DI
+            .code(&[0x3E, 0x04, 0xE0, 0x07]) // LD A,$04; LDH (TAC),A: timer at 4096 Hz
+            .code(&[0x3E, 0x05, 0xE0, 0xFF]) // LD A,$05; LDH (IE),A: VBlank and timer
+            .code(&[0xAF, 0xE0, 0x0F]) // XOR A; LDH (IF),A
+            .code(&[0xFB]) // EI
+            .code(&[0x76, 0x18, 0xFD]) // HALT; JR -3
+            .org(0x0040)
+            .code(&[0xF0, 0x80, 0x3C, 0xE0, 0x80]) // LDH A,($80); INC A; LDH ($80),A
+            .code(&[0xEA, 0x00, 0x80, 0xD9]) // LD ($8000),A; RETI
+            .org(0x0050)
+            .code(&[0xF0, 0x81, 0x3C, 0xE0, 0x81, 0xD9]) // LDH A,($81); INC A; LDH ($81),A; RETI
+            .build()
+    }
+
+    /// Smoke test of the CPU, PPU, timer and interrupts together with a distributed rom, [`smoke_rom`]
+    /// committed in `core/fixtures/regress`, as recorded in `doctor/regress.txt`
+    #[test]
+    fn test_regress_smoke() -> Result<(), Box<dyn Error>> {
+        assert_eq!(std::fs::read("fixtures/regress/smoke.gb")?, smoke_rom());
+        let cases = crate::RegressCase::load("../doctor/regress.txt")?;
+        let case = cases
+            .iter()
+            .find(|case| case.rom.ends_with("smoke.gb"))
+            .ok_or("no smoke case in doctor/regress.txt")?;
+        assert!(case.is_recorded());
+
+        assert_eq!(case.run("../doctor")?, *case);
+        Ok(())
+    }
+
+    #[test]
+    fn test_interesting_addresses() -> Result<(), Box<dyn Error>> {
+        let rom = crate::TestRom::new()
+            .code(&[0x21, 0x23, 0xC1]) // LD HL,$C123
+            .code(&[0x3E, 0xC0, 0xE0, 0x46]) // LD A,$C0; LDH (DMA),A
+            .code(&[0x3E, 0x89, 0xE0, 0x40]) // LD A,$89; LDH (LCDC),A
+            .code(&[0x18, 0xFE]) // JR -2
+            .build();
+        let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+        machine.step_frame()?;
+
+        let addresses = machine.interesting_addresses();
+        let find = |name| {
+            addresses
+                .iter()
+                .find(|address| address.name == name)
+                .map(|address| address.address)
+        };
+        assert_eq!(find("PC"), Some(machine.cpu().pc()));
+        assert_eq!(find("SP"), Some(machine.cpu().sp()));
+        assert_eq!(find("HL"), Some(0xC123));
+        assert_eq!(find("TILES"), Some(0x8800));
+        assert_eq!(find("BG MAP"), Some(0x9C00));
+        assert_eq!(find("WIN MAP"), Some(0x9800));
+        assert_eq!(find("DMA"), Some(0xC000));
+        Ok(())
+    }
+
+    #[test]
+    fn test_events() -> Result<(), Box<dyn Error>> {
+        // The serial interrupt handler slides to the entry point, which sends the byte again
+        let rom = crate::TestRom::new()
+            .code(&[0x3E, 0x08, 0xE0, 0xFF]) // LD A,$08; LDH (IE),A
+            .code(&[0xFB]) // EI
+            .code(&[0x3E, b'O', 0xE0, 0x01]) // LD A,'O'; LDH ($01),A
+            .code(&[0x3E, 0x81, 0xE0, 0x02]) // LD A,$81; LDH ($02),A
+            .code(&[0x18, 0xFE]) // JR -2
+            .build();
+        let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+
+        machine.step_frame()?;
+        let events: Vec<_> = machine.drain_events().collect();
+        let serial = events.iter().position(|event| *event == MachineEvent::SerialByte(b'O'));
+        let interrupt = events
+            .iter()
+            .position(|event| *event == MachineEvent::InterruptDispatched(Interrupt::SERIAL));
+        assert!(serial.is_some() && serial < interrupt, "{events:?}");
+        assert_eq!(events.last(), Some(&MachineEvent::FrameCompleted { frame: 1 }));
+        assert_eq!(machine.drain_events().count(), 0);
+
+        machine.breakpoint_manager_mut().add_breakpoint(0x015D);
+        machine.step_frame()?;
+        assert_eq!(
+            machine.drain_events().next_back(),
+            Some(MachineEvent::BreakpointHit { address: 0x015D })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_stops_machine() -> Result<(), Box<dyn Error>> {
+        let rom = crate::TestRom::new().code(&[0xD3]).build(); // invalid opcode
+        let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+
+        assert!(machine.step_frame().is_err());
+        assert!(matches!(machine.status(), EmulationStatus::Stopped(_)));
+        assert!(matches!(
+            machine.drain_events().next_back(),
+            Some(MachineEvent::EmulationError(_))
+        ));
+        let pc = machine.cpu().pc();
+
+        // The error is reported once, then nothing runs
+        assert_eq!(machine.step_frame()?, FrameResult::default());
+        assert_eq!(machine.step()?, 0);
+        assert_eq!(machine.cpu().pc(), pc);
+        machine.resume();
+        assert!(!machine.is_running());
+
+        machine.reset();
+        assert_eq!(machine.status(), &EmulationStatus::Paused);
+        machine.resume();
+        assert!(machine.is_running());
+        Ok(())
+    }
+}
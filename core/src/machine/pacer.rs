@@ -0,0 +1,162 @@
+use crate::machine::{CPU_CLOCK_HZ, CYCLES_PER_FRAME, FrameResult, Machine};
+use std::error::Error;
+
+/// Frames per second of a Game Boy, 59.73
+pub const FRAME_RATE: f64 = CPU_CLOCK_HZ as f64 / CYCLES_PER_FRAME as f64;
+
+/// Largest speed change of [`PacingMode::Vsync`], 2% covers 59.73 Hz games on 59.94 Hz and 60 Hz displays
+const MAX_VSYNC_SPEED_CHANGE: f64 = 0.02;
+
+/// How [`FramePacer`] fits the 59.73 Hz Game Boy to the refresh rate of the host display
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum PacingMode {
+    /// Run the cycles of a refresh at the Game Boy speed, a frame is shown twice now and then
+    /// (every 3.7 s at 60 Hz)
+    #[default]
+    Exact,
+    /// Run one frame per refresh, the game goes slightly faster (0.46% at 60 Hz) and every frame is shown once.
+    /// [`FramePacer::speed`] is handed to the audio resampler so the sound does not drift, see
+    /// [`AudioOutput::set_speed`](crate::AudioOutput::set_speed). Falls back to [`PacingMode::Exact`] when
+    /// the refresh rate is more than 2% away from the Game Boy.
+    Vsync,
+}
+
+/// Emulation driven by the refresh (vsync) of the host display, so long sessions neither drift nor stutter.
+///
+/// [`PacingMode::Exact`] runs [`CPU_CLOCK_HZ`] / refresh rate cycles per refresh. The fraction of a cycle and
+/// the cycles of the last instruction past the target are carried to the next refresh.
+///
+/// ```no_run
+/// use gbemu_core::{FramePacer, Machine, PacingMode};
+///
+/// let mut machine = Machine::builder().cartridge_path("roms/tetris.gb").build()?;
+/// let mut pacer = FramePacer::new(60.0, PacingMode::Vsync);
+/// if let Some(output) = machine.apu_mut().audio_output_mut() {
+///     output.set_speed(pacer.speed());
+/// }
+/// loop {
+///     // Wait for the vsync of the host display
+///     pacer.refresh(&mut machine)?;
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct FramePacer {
+    mode: PacingMode,
+    refresh_rate: f64,
+    cycles_per_refresh: f64,
+    /// Cycles owed to the next refresh, negative when the last one went past
+    carry: f64,
+}
+
+impl FramePacer {
+    /// Pacing for a display refreshed `refresh_rate` times per second, the Game Boy rate when it is not a
+    /// positive number
+    pub fn new(refresh_rate: f64, mode: PacingMode) -> Self {
+        let refresh_rate = match refresh_rate.is_finite() && refresh_rate > 0.0 {
+            true => refresh_rate,
+            false => FRAME_RATE,
+        };
+        let mode = match mode {
+            PacingMode::Vsync if (refresh_rate / FRAME_RATE - 1.0).abs() > MAX_VSYNC_SPEED_CHANGE => PacingMode::Exact,
+            mode => mode,
+        };
+        Self {
+            mode,
+            refresh_rate,
+            cycles_per_refresh: CPU_CLOCK_HZ as f64 / refresh_rate,
+            carry: 0.0,
+        }
+    }
+
+    /// Mode in use, [`PacingMode::Exact`] when the refresh rate is too far for [`PacingMode::Vsync`]
+    pub fn mode(&self) -> PacingMode {
+        self.mode
+    }
+
+    pub fn refresh_rate(&self) -> f64 {
+        self.refresh_rate
+    }
+
+    /// Emulation speed relative to a Game Boy, 1.0 in [`PacingMode::Exact`]
+    pub fn speed(&self) -> f64 {
+        match self.mode {
+            PacingMode::Exact => 1.0,
+            PacingMode::Vsync => self.refresh_rate / FRAME_RATE,
+        }
+    }
+
+    /// Run the emulation of one refresh. A breakpoint drops the cycles left, they are not caught up later.
+    pub fn refresh(&mut self, machine: &mut Machine) -> Result<FrameResult, Box<dyn Error>> {
+        if self.mode == PacingMode::Vsync {
+            return machine.step_frame();
+        }
+        let target = self.cycles_per_refresh + self.carry;
+        let result = machine.run_cycles(target.max(0.0) as usize)?;
+        self.carry = match result.hit_breakpoint {
+            true => 0.0,
+            false => target - result.cycles as f64,
+        };
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestRom;
+
+    fn machine() -> Result<Machine, std::io::Error> {
+        let rom = TestRom::new().code(&[0x18, 0xFE]).build(); // JR -2
+        Machine::builder().cartridge_bytes(rom).build()
+    }
+
+    #[test]
+    fn test_exact_does_not_drift() -> Result<(), Box<dyn Error>> {
+        let mut machine = machine()?;
+        let mut pacer = FramePacer::new(60.0, PacingMode::Exact);
+
+        // One minute of a 60 Hz display
+        let mut frames = 0;
+        for _ in 0..3600 {
+            frames += pacer.refresh(&mut machine)?.frame_completed as u32;
+        }
+        assert_eq!(pacer.speed(), 1.0);
+        let cycles = machine.cycles();
+        assert!(cycles.abs_diff(60 * CPU_CLOCK_HZ) < 24, "{cycles}");
+        // 3584 frames in 60 s, the other refreshes show the previous frame again
+        assert!((3583..=3585).contains(&frames), "{frames}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_vsync() -> Result<(), Box<dyn Error>> {
+        let mut machine = machine()?;
+        let mut pacer = FramePacer::new(60.0, PacingMode::Vsync);
+
+        for _ in 0..60 {
+            assert!(pacer.refresh(&mut machine)?.frame_completed);
+        }
+        assert_eq!(machine.frame_count(), 60);
+        assert!((pacer.speed() - 1.00456).abs() < 0.00001, "{}", pacer.speed());
+        Ok(())
+    }
+
+    #[test]
+    fn test_vsync_fallback() {
+        assert_eq!(FramePacer::new(59.94, PacingMode::Vsync).mode(), PacingMode::Vsync);
+        assert_eq!(FramePacer::new(144.0, PacingMode::Vsync).mode(), PacingMode::Exact);
+        assert_eq!(FramePacer::new(50.0, PacingMode::Vsync).speed(), 1.0);
+    }
+
+    #[test]
+    fn test_invalid_refresh_rate() -> Result<(), Box<dyn Error>> {
+        let mut machine = machine()?;
+        for refresh_rate in [0.0, -60.0, f64::NAN, f64::INFINITY] {
+            let mut pacer = FramePacer::new(refresh_rate, PacingMode::Exact);
+            assert_eq!(pacer.refresh_rate(), FRAME_RATE);
+            pacer.refresh(&mut machine)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use crate::machine::Machine;
+use crate::video::{SCREEN_HEIGHT, SCREEN_WIDTH, downscale};
+use std::error::Error;
+use std::path::Path;
+
+/// Screen of a rom after a few seconds, with the title of its header, see [`rom_preview`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomPreview {
+    /// Title of the header, the file name when it is blank
+    pub title: String,
+    /// Cartridge type of the header, e.g. `MBC1+RAM+BATTERY`
+    pub mapper: &'static str,
+    /// CRC32 of the cartridge header, identifies the game
+    pub header_hash: u32,
+    pub width: usize,
+    pub height: usize,
+    /// One shade id per pixel, like [`Machine::frame`]
+    pub pixels: Vec<u8>,
+}
+
+/// Boot a rom without boot rom nor battery save, run it for `frames` frames and keep the last screen
+/// downscaled by `factor`. Only the last frame is drawn, the others just run the emulation.
+pub fn rom_preview(path: impl AsRef<Path>, frames: usize, factor: usize) -> Result<RomPreview, Box<dyn Error>> {
+    let path = path.as_ref();
+    let mut machine = Machine::builder()
+        .cartridge_path(path)
+        .deterministic(true)
+        .fast_boot(true)
+        .build()?;
+
+    machine.set_rendering(false);
+    for _ in 1..frames {
+        machine.step_frame()?;
+    }
+    machine.set_rendering(true);
+    machine.step_frame()?;
+
+    let title = match machine.cartridge().title().trim() {
+        "" => path.file_stem().unwrap_or_default().to_string_lossy().into_owned(),
+        title => title.to_string(),
+    };
+    let factor = factor.max(1);
+    Ok(RomPreview {
+        title,
+        mapper: machine.cartridge().type_name(),
+        header_hash: machine.cartridge().header_hash(),
+        width: SCREEN_WIDTH.div_ceil(factor),
+        height: SCREEN_HEIGHT.div_ceil(factor),
+        pixels: downscale(machine.frame(), SCREEN_WIDTH, factor),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp::TempDir;
+
+    #[test]
+    fn test_rom_preview() -> Result<(), Box<dyn Error>> {
+        let rom = crate::TestRom::new()
+            .title("PREVIEW")
+            .code(&[
+                0xAF, // XOR A
+                0xE0, 0x40, // LDH ($40),A: LCD off
+                0x3D, // DEC A: $FF
+                0x21, 0x00, 0x80, // LD HL,$8000
+                0x06, 0x10, // LD B,16
+                0x22, // LD (HL+),A: tile 0 of color 3
+                0x05, // DEC B
+                0x20, 0xFC, // JR NZ,-4
+                0x3E, 0x91, // LD A,$91
+                0xE0, 0x40, // LDH ($40),A: LCD on
+                0x18, 0xFE, // JR -2
+            ])
+            .build();
+        let dir = TempDir::new("preview")?;
+        let path = dir.join("preview.gb");
+        std::fs::write(&path, &rom)?;
+        let preview = rom_preview(&path, 3, 4)?;
+        assert_eq!(preview.title, "PREVIEW");
+        assert_eq!(preview.mapper, "ROM ONLY");
+        assert_eq!(preview.header_hash, crate::video::crc32(&[&rom[0x0100..0x0150]]));
+        assert_eq!((preview.width, preview.height), (40, 36));
+        assert_eq!(preview.pixels.len(), 40 * 36);
+        assert!(preview.pixels.iter().all(|shade| *shade == 3));
+        Ok(())
+    }
+}
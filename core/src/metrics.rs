@@ -0,0 +1,198 @@
+//! Counters and gauges of a machine running headless (bots, netplay servers), handed to a recorder of the
+//! embedding application which exports them, e.g. to a Prometheus registry. The core does not serve them.
+
+use crate::Machine;
+use std::time::{Duration, Instant};
+
+/// Receiver of the metrics reported by a [`MetricsReporter`]:
+///
+/// | name                         | kind    |                                                  |
+/// |------------------------------|---------|--------------------------------------------------|
+/// | `gbemu_frames_total`         | counter | frames completed                                 |
+/// | `gbemu_cycles_total`         | counter | CPU cycles executed                              |
+/// | `gbemu_dropped_frames_total` | counter | frames dropped by the video sinks                |
+/// | `gbemu_fps`                  | gauge   | frames per second of host time since last report |
+/// | `gbemu_cycles_per_second`    | gauge   | cycles per second of host time since last report |
+/// | `gbemu_rewind_buffer_bytes`  | gauge   | see [`MetricsReporter::set_rewind_buffer_bytes`] |
+pub trait MetricsRecorder {
+    /// Total since the reporter was created, it never decreases
+    fn counter(&mut self, name: &'static str, total: u64);
+
+    fn gauge(&mut self, name: &'static str, value: f64);
+}
+
+/// Recorder calling a closure with the name and value of each metric, counters converted to `f64`
+impl<F: FnMut(&'static str, f64)> MetricsRecorder for F {
+    fn counter(&mut self, name: &'static str, total: u64) {
+        self(name, total as f64)
+    }
+
+    fn gauge(&mut self, name: &'static str, value: f64) {
+        self(name, value)
+    }
+}
+
+/// Report the metrics of a machine to a [`MetricsRecorder`] at most once per interval of host time.
+///
+/// ```no_run
+/// use gbemu_core::{Machine, MetricsReporter};
+/// use std::time::Duration;
+///
+/// let mut machine = Machine::builder().cartridge_path("roms/tetris.gb").build()?;
+/// let print = |name: &'static str, value: f64| println!("{name} {value}");
+/// let mut reporter = MetricsReporter::new(print, Duration::from_secs(5));
+/// loop {
+///     machine.step_frame()?;
+///     reporter.update(&machine);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct MetricsReporter<R: MetricsRecorder> {
+    recorder: R,
+    interval: Duration,
+    last_report: Instant,
+    /// Frame count and cycles of the machine at the last update, they go back to 0 on a reset
+    last_machine: (u64, u64),
+    frames: u64,
+    cycles: u64,
+    /// Frames and cycles at the last report, for the rates
+    reported: (u64, u64),
+    rewind_buffer_bytes: usize,
+}
+
+impl<R: MetricsRecorder> MetricsReporter<R> {
+    pub fn new(recorder: R, interval: Duration) -> Self {
+        Self {
+            recorder,
+            interval,
+            last_report: Instant::now(),
+            last_machine: (0, 0),
+            frames: 0,
+            cycles: 0,
+            reported: (0, 0),
+            rewind_buffer_bytes: 0,
+        }
+    }
+
+    /// Size of the rewind buffer kept by the application, e.g.
+    /// [`SnapshotStream::byte_size`](crate::state::SnapshotStream::byte_size)
+    pub fn set_rewind_buffer_bytes(&mut self, bytes: usize) {
+        self.rewind_buffer_bytes = bytes;
+    }
+
+    /// Account for what `machine` ran since the last call, and report once the interval has elapsed.
+    /// Call it after each [`Machine::step_frame`], returns whether the metrics were reported.
+    pub fn update(&mut self, machine: &Machine) -> bool {
+        let (frame_count, cycles) = (machine.frame_count(), machine.cycles());
+        let (last_frame_count, last_cycles) = self.last_machine;
+        // Counted from 0 again after a reset
+        self.frames += frame_count.checked_sub(last_frame_count).unwrap_or(frame_count);
+        self.cycles += cycles.checked_sub(last_cycles).unwrap_or(cycles);
+        self.last_machine = (frame_count, cycles);
+
+        let elapsed = self.last_report.elapsed();
+        if elapsed < self.interval {
+            return false;
+        }
+        self.report(machine, elapsed);
+        true
+    }
+
+    fn report(&mut self, machine: &Machine, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+        let (reported_frames, reported_cycles) = self.reported;
+        self.recorder.counter("gbemu_frames_total", self.frames);
+        self.recorder.counter("gbemu_cycles_total", self.cycles);
+        self.recorder
+            .counter("gbemu_dropped_frames_total", machine.dropped_frames());
+        self.recorder
+            .gauge("gbemu_fps", (self.frames - reported_frames) as f64 / seconds);
+        let cycles_per_second = (self.cycles - reported_cycles) as f64 / seconds;
+        self.recorder.gauge("gbemu_cycles_per_second", cycles_per_second);
+        self.recorder
+            .gauge("gbemu_rewind_buffer_bytes", self.rewind_buffer_bytes as f64);
+
+        self.reported = (self.frames, self.cycles);
+        self.last_report = Instant::now();
+    }
+
+    pub fn recorder(&self) -> &R {
+        &self.recorder
+    }
+
+    pub fn into_recorder(self) -> R {
+        self.recorder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestRom;
+    use crate::video::ChannelSink;
+    use std::collections::HashMap;
+    use std::error::Error;
+
+    #[derive(Default)]
+    struct Registry {
+        counters: HashMap<&'static str, u64>,
+        gauges: HashMap<&'static str, f64>,
+    }
+
+    impl MetricsRecorder for Registry {
+        fn counter(&mut self, name: &'static str, total: u64) {
+            self.counters.insert(name, total);
+        }
+
+        fn gauge(&mut self, name: &'static str, value: f64) {
+            self.gauges.insert(name, value);
+        }
+    }
+
+    fn machine() -> Result<Machine, std::io::Error> {
+        let rom = TestRom::new().code(&[0x18, 0xFE]).build(); // JR -2
+        Machine::builder().cartridge_bytes(rom).build()
+    }
+
+    #[test]
+    fn test_report() -> Result<(), Box<dyn Error>> {
+        let mut machine = machine()?;
+        let (sink, _receiver) = ChannelSink::new(1);
+        machine.add_video_sink(sink);
+        let mut reporter = MetricsReporter::new(Registry::default(), Duration::ZERO);
+        reporter.set_rewind_buffer_bytes(1024);
+
+        for _ in 0..3 {
+            machine.step_frame()?;
+        }
+        assert!(reporter.update(&machine));
+        let cycles = machine.cycles();
+        machine.reset();
+        machine.step_frame()?;
+        assert!(reporter.update(&machine));
+
+        let registry = reporter.into_recorder();
+        assert_eq!(registry.counters["gbemu_frames_total"], 4);
+        assert_eq!(registry.counters["gbemu_cycles_total"], cycles + machine.cycles());
+        // The channel only holds the first frame
+        assert_eq!(registry.counters["gbemu_dropped_frames_total"], 3);
+        assert!(registry.gauges["gbemu_fps"] > 0.0);
+        assert_eq!(registry.gauges["gbemu_rewind_buffer_bytes"], 1024.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_once_per_interval() -> Result<(), Box<dyn Error>> {
+        let mut machine = machine()?;
+        let mut names = vec![];
+        {
+            let record = |name: &'static str, _: f64| names.push(name);
+            let mut reporter = MetricsReporter::new(record, Duration::from_secs(3600));
+
+            machine.step_frame()?;
+            assert!(!reporter.update(&machine));
+        }
+        assert!(names.is_empty());
+        Ok(())
+    }
+}
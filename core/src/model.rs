@@ -0,0 +1,19 @@
+/// Hardware revision emulated by a [`Machine`](crate::Machine).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Model {
+    /// Original Game Boy (DMG-01)
+    #[default]
+    Dmg,
+    /// Game Boy Color (CGB-001)
+    Cgb,
+}
+
+impl Model {
+    /// Size in bytes of the boot ROM mapped by this model at power on.
+    pub fn boot_rom_size(&self) -> usize {
+        match self {
+            Model::Dmg => 0x100,
+            Model::Cgb => 0x900,
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use crate::ppu::LCD_HEIGHT;
+
+/// Bitmap of the screen lines whose pixels changed, see [`Machine::take_changed_lines`](crate::Machine::take_changed_lines).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ChangedLines([u64; 3]);
+
+impl ChangedLines {
+    /// Every line of the screen
+    pub fn all() -> Self {
+        let mut lines = Self::default();
+        (0..LCD_HEIGHT).for_each(|line| lines.insert(line));
+        lines
+    }
+
+    pub fn insert(&mut self, line: u8) {
+        self.0[line as usize / 64] |= 1 << (line % 64);
+    }
+
+    pub fn contains(&self, line: u8) -> bool {
+        line < LCD_HEIGHT && self.0[line as usize / 64] & (1 << (line % 64)) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|bits| *bits == 0)
+    }
+
+    /// Changed lines, top to bottom
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..LCD_HEIGHT).filter(|line| self.contains(*line))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_lines() {
+        let mut lines = ChangedLines::default();
+        assert!(lines.is_empty());
+
+        lines.insert(0);
+        lines.insert(70);
+        lines.insert(143);
+        assert_eq!(lines.iter().collect::<Vec<_>>(), [0, 70, 143]);
+        assert!(!lines.contains(1));
+        assert_eq!(ChangedLines::all().iter().count(), LCD_HEIGHT as usize);
+    }
+}
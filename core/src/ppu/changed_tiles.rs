@@ -0,0 +1,50 @@
+/// Tiles of the tile data ($8000-$97FF) of a VRAM bank, 16 bytes each
+pub const TILE_COUNT: u16 = 384;
+
+/// Bitmap of the tiles whose data changed, see [`Machine::changed_tiles`](crate::Machine::changed_tiles).
+/// The tiles of the CGB VRAM bank 1 follow those of bank 0, from [`TILE_COUNT`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ChangedTiles([u64; 12]);
+
+impl ChangedTiles {
+    /// Every tile of the tile data of both banks
+    pub fn all() -> Self {
+        Self([u64::MAX; 12])
+    }
+
+    pub fn insert(&mut self, tile: u16) {
+        self.0[tile as usize / 64] |= 1 << (tile % 64);
+    }
+
+    pub fn contains(&self, tile: u16) -> bool {
+        tile < 2 * TILE_COUNT && self.0[tile as usize / 64] & (1 << (tile % 64)) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|bits| *bits == 0)
+    }
+
+    /// Changed tiles, in tile data order, bank 0 first
+    pub fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..2 * TILE_COUNT).filter(|tile| self.contains(*tile))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_tiles() {
+        let mut tiles = ChangedTiles::default();
+        assert!(tiles.is_empty());
+
+        tiles.insert(0);
+        tiles.insert(130);
+        tiles.insert(383);
+        tiles.insert(767);
+        assert_eq!(tiles.iter().collect::<Vec<_>>(), [0, 130, 383, 767]);
+        assert!(!tiles.contains(1));
+        assert_eq!(ChangedTiles::all().iter().count(), 2 * TILE_COUNT as usize);
+    }
+}
@@ -0,0 +1,314 @@
+//! Pixel transfer drawn one dot at a time: the background fetcher fills a FIFO 8 pixels at a time, the sprite
+//! fetches stall it, and a pixel leaves it for the LCD on every dot. A register written during the pixel
+//! transfer shows from the next pixel (palettes, LCDC) or from the next tile fetched (scroll, tile maps, tile
+//! data), as on hardware.
+
+use crate::Model;
+use crate::ppu::{
+    ATTRIBUTE_PALETTE, ATTRIBUTE_PRIORITY, LCD_WIDTH, Layers, LcdControl, Ppu, PpuBus, sprite_penalty, tile, tile_row,
+};
+
+/// How the PPU draws the picture, see [`AccuracyProfile::ppu_model`](crate::AccuracyProfile::ppu_model)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PpuModel {
+    /// The whole line at the end of its pixel transfer, whose length is computed ahead. A register written
+    /// during the pixel transfer applies to the whole line.
+    #[default]
+    Scanline,
+    /// Pixel by pixel with a background and a sprite fetcher, for the demos and test roms writing the
+    /// registers in the middle of a line. Slower.
+    Fifo,
+}
+
+/// Dots of the first fetch of a line, whose pixels are thrown away
+const DUMMY_FETCH_DOTS: u8 = 6;
+/// Dots of a tile fetch: tile number, then the low and high bytes of its row
+const FETCH_DOTS: u8 = 6;
+
+/// Sprite pixel waiting for its position, color 0 is no pixel
+#[derive(Debug, Default, Clone, Copy)]
+struct SpritePixel {
+    color_id: u8,
+    /// OBP0 or OBP1 on DMG, one of the 8 CGB palettes
+    palette: u8,
+    behind_background: bool,
+    oam_index: u8,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Fetcher {
+    /// Dots left before the first fetch
+    delay: u8,
+    /// Dots spent on the current tile, its row is pushed from [`FETCH_DOTS`] once the FIFO is empty
+    step: u8,
+    /// Tiles fetched since the start of the line, or of the window
+    column: u8,
+    window: bool,
+}
+
+/// Pixel transfer of the line being drawn with [`PpuModel::Fifo`]
+#[derive(Debug, Default, Clone)]
+pub(crate) struct PixelFifo {
+    /// The sprites of the line are selected and the fetcher started
+    active: bool,
+    /// Dots of the pixel transfer run so far
+    dots: u64,
+    /// Next pixel of the LCD
+    x: u8,
+    /// Pixels thrown away before the next one reaches the LCD: SCX % 8 at the start of the line, 7 - WX for
+    /// a window starting left of the screen
+    discard: u8,
+    fetcher: Fetcher,
+    /// Background or window row being shifted out, its last `bg_count` pixels are left
+    bg_row: [u8; 8],
+    bg_attributes: u8,
+    bg_count: u8,
+    /// Sprite pixels of the next 8 positions of the LCD
+    sprite_pixels: [SpritePixel; 8],
+    /// Indices of the sprites of the line in fetch order, by X then OAM index
+    sprite_order: [u8; 10],
+    sprite_count: u8,
+    next_sprite: u8,
+    /// Dots left in the fetch of `fetched_sprite`, its pixels are mixed in at the end
+    stall: u64,
+    fetched_sprite: u8,
+    fine_scroll: i16,
+    /// Background tile the sprite fetches already waited for
+    waited_tile: Option<i16>,
+    /// A pixel of the line changed
+    changed: bool,
+}
+
+impl Ppu {
+    /// Run the pixel transfer of `line` up to the current dot, true once its last pixel reached the LCD.
+    /// The length of the pixel transfer is then known.
+    pub(super) fn run_fifo(&mut self, bus: &impl PpuBus, line: u8) -> bool {
+        if !self.fifo.active {
+            self.start_fifo(bus, line);
+        }
+        while self.fifo.dots < self.mode_clock {
+            self.fifo.dots += 1;
+            self.fifo_dot(bus, line);
+            if self.fifo.x == LCD_WIDTH {
+                self.pixel_transfer_cycles = self.fifo.dots;
+                self.fifo.active = false;
+                if self.fifo.changed {
+                    self.changed_lines.insert(line);
+                }
+                // A window disabled or moved off screen keeps its row for the next lines that show it
+                if self.fifo.fetcher.window {
+                    self.window_line = self.window_line.wrapping_add(1);
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    fn start_fifo(&mut self, bus: &impl PpuBus, line: u8) {
+        self.latch_wy(bus, line);
+        self.update_visibles_sprites(bus, line, bus.lcdc().contains(LcdControl::OBJ_SIZE));
+
+        let sprites = &self.sprites_visibles_on_current_line;
+        let fifo = &mut self.fifo;
+        let order = &mut fifo.sprite_order[..sprites.len()];
+        for (index, slot) in order.iter_mut().enumerate() {
+            *slot = index as u8;
+        }
+        order.sort_unstable_by_key(|&index| {
+            let sprite = &sprites[index as usize];
+            (sprite.x(), sprite.oam_index())
+        });
+        fifo.sprite_count = sprites.len() as u8;
+        fifo.fine_scroll = (bus.scx() % 8) as i16;
+        fifo.discard = bus.scx() % 8;
+        fifo.fetcher.delay = DUMMY_FETCH_DOTS;
+        fifo.active = true;
+    }
+
+    /// One dot: the fetcher steps, then either the window restarts it, a sprite fetch stalls the line, or a
+    /// pixel leaves the FIFO
+    fn fifo_dot(&mut self, bus: &impl PpuBus, line: u8) {
+        if self.fifo.stall == 0 {
+            self.fetcher_dot(bus, line);
+            if self.fifo.bg_count == 0 {
+                return;
+            }
+            if self.window_reached(bus) {
+                // The background pixels left are dropped, the fetcher starts over on the window
+                self.fifo.bg_count = 0;
+                self.fifo.fetcher = Fetcher {
+                    window: true,
+                    ..Fetcher::default()
+                };
+                self.fifo.discard = 7u8.saturating_sub(bus.wx());
+                self.fetcher_dot(bus, line);
+                return;
+            }
+            self.start_sprite_fetch(bus);
+        }
+        if self.fifo.stall > 0 {
+            self.fifo.stall -= 1;
+            if self.fifo.stall == 0 {
+                self.merge_sprite(bus, line);
+            }
+            return;
+        }
+        self.shift_pixel(bus, line);
+    }
+
+    fn fetcher_dot(&mut self, bus: &impl PpuBus, line: u8) {
+        let fetcher = &mut self.fifo.fetcher;
+        if fetcher.delay > 0 {
+            fetcher.delay -= 1;
+            return;
+        }
+        if fetcher.step < FETCH_DOTS {
+            fetcher.step += 1;
+            return;
+        }
+        if self.fifo.bg_count > 0 {
+            return;
+        }
+
+        let (row, attributes) = self.fetch_tile(bus, line);
+        let fifo = &mut self.fifo;
+        fifo.bg_row = row;
+        fifo.bg_attributes = attributes;
+        fifo.bg_count = 8;
+        fifo.fetcher.step = 0;
+        fifo.fetcher.column = fifo.fetcher.column.wrapping_add(1);
+    }
+
+    /// Row of the next background or window tile, with the registers of this dot
+    fn fetch_tile(&self, bus: &impl PpuBus, line: u8) -> ([u8; 8], u8) {
+        let lcdc = bus.lcdc();
+        let column = self.fifo.fetcher.column;
+        let (tilemap_area, map_x, map_y) = match self.fifo.fetcher.window {
+            true => (LcdControl::WINDOW_TILE_MAP, column, self.window_line),
+            false if self.hidden_layers.contains(Layers::BACKGROUND) => return ([0; 8], 0),
+            false => (
+                LcdControl::TILEMAP_AREA,
+                (bus.scx() / 8).wrapping_add(column),
+                line.wrapping_add(bus.scy()),
+            ),
+        };
+        let tilemap = if lcdc.contains(tilemap_area) {
+            0x1C00 // at $9C00
+        } else {
+            0x1800 // at $9800
+        };
+        let map_address = tilemap + (map_y as u16 / 8) * 32 + (map_x & 31) as u16;
+        tile_row(bus, self.cgb_mode, map_address, map_y)
+    }
+
+    /// The next pixel is the first one of the window, once the fine scroll is discarded
+    fn window_reached(&self, bus: &impl PpuBus) -> bool {
+        !self.fifo.fetcher.window
+            && self.fifo.discard == 0
+            && !self.hidden_layers.contains(Layers::WINDOW)
+            && self.window_start(bus) == Some(self.fifo.x as usize)
+    }
+
+    /// Stall the line on the next sprite reaching the next pixel, a sprite left of the screen at the first pixel
+    fn start_sprite_fetch(&mut self, bus: &impl PpuBus) {
+        let fifo = &mut self.fifo;
+        if fifo.next_sprite == fifo.sprite_count || !bus.lcdc().contains(LcdControl::OBJ_ENABLE) {
+            return;
+        }
+        let index = fifo.sprite_order[fifo.next_sprite as usize];
+        let x = self.sprites_visibles_on_current_line[index as usize].x();
+        if x > fifo.x as i16 {
+            return;
+        }
+        fifo.next_sprite += 1;
+        fifo.fetched_sprite = index;
+        fifo.stall = sprite_penalty(x, fifo.fine_scroll, &mut fifo.waited_tile);
+    }
+
+    /// Mix the row of the fetched sprite into the sprite pixels. On DMG the pixels of the sprites fetched first,
+    /// lower X then lower OAM index, are kept. On CGB the lower OAM index wins.
+    fn merge_sprite(&mut self, bus: &impl PpuBus, line: u8) {
+        if self.hidden_layers.contains(Layers::SPRITES) {
+            return;
+        }
+        let sprite = &self.sprites_visibles_on_current_line[self.fifo.fetched_sprite as usize];
+        let address = sprite.get_tile_address(line, bus.lcdc().contains(LcdControl::OBJ_SIZE));
+        let mut row = match self.cgb_mode && sprite.is_in_bank1() {
+            true => tile::decode_row(bus.read_vram_bank1(address), bus.read_vram_bank1(address + 1)),
+            false => tile::decode_row(bus.read_vram(address), bus.read_vram(address + 1)),
+        };
+        if sprite.has_x_flip() {
+            row.reverse();
+        }
+        let pixel = SpritePixel {
+            color_id: 0,
+            palette: match self.cgb_mode {
+                true => sprite.cgb_palette(),
+                false => sprite.palette() as u8,
+            },
+            behind_background: sprite.is_behind_background(),
+            oam_index: sprite.oam_index(),
+        };
+
+        for (px, color_id) in row.into_iter().enumerate() {
+            // Left of the screen
+            let Ok(slot) = usize::try_from(sprite.x() + px as i16 - self.fifo.x as i16) else {
+                continue;
+            };
+            let waiting = &mut self.fifo.sprite_pixels[slot];
+            let replace = waiting.color_id == 0 || (self.model == Model::Cgb && pixel.oam_index < waiting.oam_index);
+            if color_id != 0 && replace {
+                *waiting = SpritePixel { color_id, ..pixel };
+            }
+        }
+    }
+
+    /// Send the next pixel to the LCD, the background and sprite pixels mixed with the registers of this dot
+    fn shift_pixel(&mut self, bus: &impl PpuBus, line: u8) {
+        let fifo = &mut self.fifo;
+        let bg_color_id = fifo.bg_row[8 - fifo.bg_count as usize];
+        let attributes = fifo.bg_attributes;
+        fifo.bg_count -= 1;
+        if fifo.discard > 0 {
+            fifo.discard -= 1;
+            return;
+        }
+        let sprite = fifo.sprite_pixels[0];
+        fifo.sprite_pixels.rotate_left(1);
+        fifo.sprite_pixels[7] = SpritePixel::default();
+        let x = fifo.x;
+        fifo.x += 1;
+        if self.skip_rendering {
+            return;
+        }
+
+        let lcdc = bus.lcdc();
+        // In CGB mode LCDC bit 0 only takes the priority away from the background and window
+        let bg_priority = lcdc.contains(LcdControl::BG_WINDOW_ENABLE);
+        let behind = sprite.behind_background || attributes & ATTRIBUTE_PRIORITY != 0;
+        let sprite_shown = sprite.color_id != 0
+            && lcdc.contains(LcdControl::OBJ_ENABLE)
+            && !(behind && bg_color_id != 0 && bg_priority);
+
+        let index = line as usize * LCD_WIDTH as usize + x as usize;
+        let rgb = self.rgb_frame_buffer[index];
+        let (pixel, rgb) = match (self.cgb_mode, sprite_shown) {
+            (true, true) => (sprite.color_id, bus.obj_palette_color(sprite.palette, sprite.color_id)),
+            (true, false) => (
+                bg_color_id,
+                bus.bg_palette_color(attributes & ATTRIBUTE_PALETTE, bg_color_id),
+            ),
+            (false, true) if sprite.palette == 1 => (bus.obp1_color(sprite.color_id), rgb),
+            (false, true) => (bus.obp0_color(sprite.color_id), rgb),
+            (false, false) if bg_priority => (bus.bgp_color(bg_color_id), rgb),
+            // LCDC bit 0 blanks the background and window of the DMG
+            (false, false) => (0, rgb),
+        };
+        if self.frame_buffer[index] != pixel || self.rgb_frame_buffer[index] != rgb {
+            self.fifo.changed = true;
+            self.frame_buffer[index] = pixel;
+            self.rgb_frame_buffer[index] = rgb;
+        }
+    }
+}
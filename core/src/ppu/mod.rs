@@ -1,40 +1,169 @@
+use crate::Model;
 use crate::bus::Interrupt;
+#[cfg(feature = "profiling")]
+use crate::debug::profile::Timing;
+pub use crate::ppu::changed_lines::ChangedLines;
+pub use crate::ppu::changed_tiles::{ChangedTiles, TILE_COUNT};
+use crate::ppu::fifo::PixelFifo;
+pub use crate::ppu::fifo::PpuModel;
 use crate::ppu::mode::Mode;
+pub use crate::ppu::mode::Mode as PpuMode;
+pub use crate::ppu::palette::{ColorPalette, Palette};
 pub(crate) use crate::ppu::ppu_bus::PpuBus;
 pub(crate) use crate::ppu::ppu_bus::{LcdControl, LcdStatus};
+pub use crate::ppu::snapshot::PpuSnapshot;
 use crate::ppu::sprite::Sprite;
+use crate::state::{Savable, StateReader, StateWriter, invalid_data};
+use bitflags::bitflags;
+use std::ops::Range;
 
+mod changed_lines;
+mod changed_tiles;
+mod fifo;
 mod mode;
+mod palette;
 mod ppu_bus;
+mod snapshot;
 mod sprite;
+mod tile;
 
 const LCD_WIDTH: u8 = 160;
 const LCD_HEIGHT: u8 = 144;
+const LINES_PER_FRAME: u8 = 154;
+
+const CYCLES_PER_LINE: u64 = 456;
+const OAM_SCAN_CYCLES: u64 = 80;
+/// Pixel transfer without fine scroll, window or sprites, up to 289 cycles with them
+const PIXEL_TRANSFER_CYCLES: u64 = 172;
+/// Pixel transfer stalled while the window fetch restarts
+const WINDOW_PENALTY_CYCLES: u64 = 6;
+/// Pixel transfer stalled while a sprite is fetched, plus up to 5 for the background fetch it waits for
+const SPRITE_PENALTY_CYCLES: u64 = 6;
+/// LY reads 153 only at the start of the last line, then 0 until the end of VBlank
+const LINE_153_LY_CYCLES: u64 = 4;
+/// Dot of line 153 when the DMG boot rom jumps to $0100. Pan Docs only gives STAT $85 and LY $00,
+/// i.e. past the LY reset of line 153, the middle of the line is used.
+const POST_BOOT_LINE_153_CYCLES: u64 = CYCLES_PER_LINE / 2;
+const WHITE_RGB555: u16 = 0x7FFF;
+
+/// Tile map attributes of the CGB mode, in VRAM bank 1
+const ATTRIBUTE_PALETTE: u8 = 0b0000_0111;
+const ATTRIBUTE_BANK: u8 = 0b0000_1000;
+const ATTRIBUTE_X_FLIP: u8 = 0b0010_0000;
+const ATTRIBUTE_Y_FLIP: u8 = 0b0100_0000;
+const ATTRIBUTE_PRIORITY: u8 = 0b1000_0000;
+
+bitflags! {
+    /// Layers of the picture, hidden for debugging with [`Machine::set_hidden_layers`](crate::Machine::set_hidden_layers)
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    pub struct Layers: u8 {
+        const BACKGROUND = 0b001;
+        const WINDOW     = 0b010;
+        const SPRITES    = 0b100;
+    }
+}
 
 pub(crate) struct Ppu {
     // Internal status
     mode_clock: u64, // Cycle counter for current mode
     sprites_visibles_on_current_line: Vec<Sprite>,
+    frame_ready: bool, // VBlank reached since last check
+    stat_line: bool,   // STAT interrupt line state, interrupt is requested on rising edge
+    /// Length of the pixel transfer of the current line, the HBlank gets the rest of the line
+    pixel_transfer_cycles: u64,
+    changed_lines: ChangedLines,
+    model: Model,
+    /// Colors from the CGB palettes and tile attributes from VRAM bank 1, a CGB running a CGB cartridge
+    cgb_mode: bool,
+    ppu_model: PpuModel,
+    /// Line drawn pixel by pixel with [`PpuModel::Fifo`]
+    fifo: PixelFifo,
+    /// Keep the frame buffer as is, see [`Machine::set_rendering`](crate::Machine::set_rendering)
+    skip_rendering: bool,
+    /// Not drawn, a hidden background or window is color 0 and shows the layer below
+    hidden_layers: Layers,
+    /// Row of the window drawn on the next line, only advanced by the lines that show the window
+    window_line: u8,
+    /// LY matched WY during the frame, the window can show from then on
+    wy_triggered: bool,
+    #[cfg(feature = "profiling")]
+    pub(crate) line_render: Timing,
+
+    /// Background and window color ids of the line being rendered, before the palette
+    bg_color_ids: [u8; LCD_WIDTH as usize],
+    /// CGB tile attributes of the background and window pixels of the line being rendered
+    bg_attributes: [u8; LCD_WIDTH as usize],
 
     // buffer
     pub frame_buffer: [u8; LCD_WIDTH as usize * LCD_HEIGHT as usize],
+    /// RGB555 colors of the CGB mode, the frame buffer then holds the color ids
+    pub rgb_frame_buffer: [u16; LCD_WIDTH as usize * LCD_HEIGHT as usize],
 }
 
 impl Default for Ppu {
     fn default() -> Self {
         Self {
             mode_clock: 0,
+            frame_ready: false,
+            stat_line: false,
+            pixel_transfer_cycles: PIXEL_TRANSFER_CYCLES,
+            changed_lines: ChangedLines::all(),
+            model: Model::default(),
+            cgb_mode: false,
+            ppu_model: PpuModel::default(),
+            fifo: PixelFifo::default(),
+            skip_rendering: false,
+            hidden_layers: Layers::empty(),
+            window_line: 0,
+            wy_triggered: false,
+            #[cfg(feature = "profiling")]
+            line_render: Timing::default(),
+            bg_color_ids: [0; LCD_WIDTH as usize],
+            bg_attributes: [0; LCD_WIDTH as usize],
             frame_buffer: [0; LCD_WIDTH as usize * LCD_HEIGHT as usize],
+            rgb_frame_buffer: [WHITE_RGB555; LCD_WIDTH as usize * LCD_HEIGHT as usize],
             sprites_visibles_on_current_line: Vec::with_capacity(10),
         }
     }
 }
 
 impl Ppu {
+    /// The model decides the priority between overlapping sprites
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+    }
+
+    pub fn set_cgb_mode(&mut self, cgb_mode: bool) {
+        self.cgb_mode = cgb_mode;
+    }
+
+    pub fn set_ppu_model(&mut self, ppu_model: PpuModel) {
+        self.ppu_model = ppu_model;
+    }
+
+    pub fn set_skip_rendering(&mut self, skip: bool) {
+        self.skip_rendering = skip;
+    }
+
+    pub fn hidden_layers(&self) -> Layers {
+        self.hidden_layers
+    }
+
+    pub fn set_hidden_layers(&mut self, layers: Layers) {
+        self.hidden_layers = layers;
+    }
+
     pub fn reset(&mut self, bus: &mut impl PpuBus) {
-        bus.write_mode(Mode::HBlank);
         self.mode_clock = 0;
+        self.frame_ready = false;
+        self.stat_line = false;
+        self.pixel_transfer_cycles = PIXEL_TRANSFER_CYCLES;
+        self.fifo = PixelFifo::default();
+        self.window_line = 0;
+        self.wy_triggered = false;
         self.frame_buffer.fill(33);
+        self.rgb_frame_buffer.fill(WHITE_RGB555);
+        self.changed_lines = ChangedLines::all();
 
         // ly and lyc can update LCDC
         bus.set_ly(0);
@@ -42,6 +171,7 @@ impl Ppu {
 
         bus.set_lcdc_u8(0x91);
         bus.set_stat_u8(0x80);
+        bus.write_mode(Mode::OAMScan);
         bus.set_scy(0);
         bus.set_scx(0);
         bus.set_dma_u8(0xFF);
@@ -56,107 +186,315 @@ impl Ppu {
         }
     }
 
+    /// Place the PPU where the DMG boot rom leaves it: LCD on, in the VBlank of line 153 with LY reading 0
+    pub(crate) fn skip_boot(&mut self, bus: &mut impl PpuBus) {
+        bus.set_lcdc_u8(0x91);
+        bus.set_ly(0);
+        bus.write_mode(Mode::VBlank);
+        self.mode_clock = POST_BOOT_LINE_153_CYCLES;
+        self.fifo = PixelFifo::default();
+        self.frame_ready = false;
+        self.stat_line = false;
+        self.window_line = 0;
+        self.wy_triggered = false;
+    }
+
     pub fn update(&mut self, bus: &mut impl PpuBus, cycles: u32) {
         if !bus.lcdc().contains(LcdControl::ENABLE) {
             return;
         }
 
         self.mode_clock += cycles as u64;
-        const CYCLES_PER_LINE: u64 = 456;
 
-        if self.mode_clock < CYCLES_PER_LINE {
+        loop {
+            let ly = bus.ly();
+            match bus.read_mode() {
+                Mode::OAMScan if self.mode_clock >= OAM_SCAN_CYCLES => {
+                    self.mode_clock -= OAM_SCAN_CYCLES;
+                    self.pixel_transfer_cycles = self.pixel_transfer_length(bus, ly);
+                    self.fifo = PixelFifo::default();
+                    bus.write_mode(Mode::PixelTransfer);
+                }
+                // The line ends when its last pixel is pushed, HBlank takes the rest
+                Mode::PixelTransfer if self.ppu_model == PpuModel::Fifo => {
+                    if !self.run_fifo(bus, ly) {
+                        break;
+                    }
+                    self.mode_clock -= self.pixel_transfer_cycles;
+                    bus.write_mode(Mode::HBlank);
+                }
+                Mode::PixelTransfer if self.mode_clock >= self.pixel_transfer_cycles => {
+                    self.mode_clock -= self.pixel_transfer_cycles;
+                    #[cfg(feature = "profiling")]
+                    let start = std::time::Instant::now();
+                    self.scanline(bus, ly);
+                    #[cfg(feature = "profiling")]
+                    self.line_render.record(start);
+                    bus.write_mode(Mode::HBlank);
+                }
+                Mode::HBlank if self.mode_clock >= self.hblank_cycles() => {
+                    self.mode_clock -= self.hblank_cycles();
+                    bus.set_ly(ly + 1);
+                    if ly + 1 == LCD_HEIGHT {
+                        bus.write_mode(Mode::VBlank);
+                        bus.update_interrupt_flag(Interrupt::VBLANK, true);
+                        self.frame_ready = true;
+                    } else {
+                        bus.write_mode(Mode::OAMScan);
+                    }
+                }
+                // The LY=LYC comparison, and an LYC=0 interrupt, happen here and not on line 0
+                Mode::VBlank if ly == LINES_PER_FRAME - 1 && self.mode_clock >= LINE_153_LY_CYCLES => {
+                    bus.set_ly(0);
+                }
+                Mode::VBlank if self.mode_clock >= CYCLES_PER_LINE => {
+                    self.mode_clock -= CYCLES_PER_LINE;
+                    // LY is already 0 at the end of line 153
+                    if ly == 0 {
+                        bus.write_mode(Mode::OAMScan);
+                    } else {
+                        bus.set_ly(ly + 1);
+                    }
+                }
+                _ => break,
+            }
+
+            self.update_stat_interrupt(bus);
+        }
+    }
+
+    /// Pixel transfer of `line`: the pixels discarded by the fine scroll (SCX % 8), the window fetch and the
+    /// sprite fetches, as measured in Pan Docs. A sprite over a background tile not yet waited for by another
+    /// sprite also waits for the end of that tile fetch, 5 cycles when it starts on the first pixel of the tile.
+    fn pixel_transfer_length(&mut self, bus: &impl PpuBus, line: u8) -> u64 {
+        let mut cycles = PIXEL_TRANSFER_CYCLES + (bus.scx() % 8) as u64;
+
+        let lcdc = bus.lcdc();
+        let wy_triggered = (self.wy_triggered && line != 0) || line == bus.wy();
+        if lcdc.contains(LcdControl::BG_WINDOW_ENABLE | LcdControl::WINDOW_ENABLE)
+            && wy_triggered
+            && bus.wx() < LCD_WIDTH + 7
+        {
+            cycles += WINDOW_PENALTY_CYCLES;
+        }
+
+        if !lcdc.contains(LcdControl::OBJ_ENABLE) {
+            return cycles;
+        }
+        self.update_visibles_sprites(bus, line, lcdc.contains(LcdControl::OBJ_SIZE));
+        let mut xs: Vec<i16> = self
+            .sprites_visibles_on_current_line
+            .iter()
+            .filter(|sprite| sprite.is_fetched())
+            .map(Sprite::x)
+            .collect();
+        xs.sort_unstable();
+
+        let fine_scroll = (bus.scx() % 8) as i16;
+        let mut waited_tile = None;
+        for x in xs {
+            cycles += sprite_penalty(x, fine_scroll, &mut waited_tile);
+        }
+        cycles
+    }
+
+    fn hblank_cycles(&self) -> u64 {
+        CYCLES_PER_LINE - OAM_SCAN_CYCLES - self.pixel_transfer_cycles
+    }
+
+    /// DMG OAM corruption bug: a 16-bit increment or decrement of a value in $FE00-$FEFF during
+    /// the OAM scan garbles the OAM row read by the PPU, from the row before it. Called before the
+    /// instruction cycles are run, the register is changed on its second M-cycle.
+    pub(crate) fn corrupt_oam(&mut self, bus: &mut impl PpuBus) {
+        if !bus.lcdc().contains(LcdControl::ENABLE) || bus.read_mode() != Mode::OAMScan {
             return;
         }
+        // 20 rows of 8 bytes, one read per M-cycle, the first row is never corrupted
+        let row = (self.mode_clock + 4) / 4;
+        if !(1..20).contains(&row) {
+            return;
+        }
+
+        let row = row as u16 * 8;
+        let word = |offset| u16::from_le_bytes([bus.read_oam(offset), bus.read_oam(offset + 1)]);
+        let (a, b, c) = (word(row), word(row - 8), word(row - 4));
 
-        self.mode_clock -= CYCLES_PER_LINE;
+        let [low, high] = (((a ^ c) & (b ^ c)) ^ c).to_le_bytes();
+        bus.write_internal_byte(0xFE00 + row, low);
+        bus.write_internal_byte(0xFE00 + row + 1, high);
+        for offset in 2..8 {
+            let value = bus.read_oam(row - 8 + offset);
+            bus.write_internal_byte(0xFE00 + row + offset, value);
+        }
+    }
 
-        let current_ly = bus.ly();
-        let new_ly = current_ly.wrapping_add(1) % 154;
-        bus.set_ly(new_ly);
+    /// The STAT interrupt is requested on the rising edge of the OR of all enabled sources,
+    /// a source becoming active while another one holds the line high is "blocked".
+    fn update_stat_interrupt(&mut self, bus: &mut impl PpuBus) {
+        let stat = bus.stat();
+        let mode_source = match bus.read_mode() {
+            Mode::HBlank => LcdStatus::HBLANK_INTERRUPT,
+            Mode::VBlank => LcdStatus::VBLANK_INTERRUPT,
+            Mode::OAMScan => LcdStatus::OAM_INTERRUPT,
+            Mode::PixelTransfer => LcdStatus::empty(),
+        };
+        let lyc_source = stat.contains(LcdStatus::LYC_EQUAL | LcdStatus::LYC_INTERRUPT);
+        let stat_line = stat.intersects(mode_source) || lyc_source;
 
-        if new_ly == bus.lyc() {
-            bus.update_stat(LcdStatus::LYC_EQUAL, true);
-            if bus.stat().contains(LcdStatus::LYC_INTERRUPT) {
-                bus.update_interrupt_flag(Interrupt::LCD_STAT, true);
-            }
-        } else {
-            bus.update_stat(LcdStatus::LYC_EQUAL, false);
+        if stat_line && !self.stat_line {
+            bus.update_interrupt_flag(Interrupt::LCD_STAT, true);
         }
+        self.stat_line = stat_line;
+    }
 
-        if new_ly < LCD_HEIGHT {
-            self.render_line(bus, new_ly);
-            bus.write_mode(Mode::HBlank);
-        } else if new_ly == LCD_HEIGHT {
-            bus.write_mode(Mode::VBlank);
-            bus.update_interrupt_flag(Interrupt::VBLANK, true);
-        } else {
-            bus.write_mode(Mode::VBlank);
+    /// Return true once after the PPU entered VBlank
+    pub fn take_frame_ready(&mut self) -> bool {
+        std::mem::take(&mut self.frame_ready)
+    }
+
+    /// Lines whose pixels changed since the last call
+    pub fn take_changed_lines(&mut self) -> ChangedLines {
+        std::mem::take(&mut self.changed_lines)
+    }
+
+    /// Every line is reported as changed on the next call to [`Ppu::take_changed_lines`]
+    pub fn invalidate_lines(&mut self) {
+        self.changed_lines = ChangedLines::all();
+    }
+
+    /// Draw `line` at the end of its pixel transfer and advance the window line counter
+    fn scanline(&mut self, bus: &impl PpuBus, line: u8) {
+        self.latch_wy(bus, line);
+
+        if !self.skip_rendering {
+            self.render_line(bus, line);
+        }
+        // A window disabled or moved off screen keeps its row for the next lines that show it
+        if self.window_start(bus).is_some() {
+            self.window_line = self.window_line.wrapping_add(1);
         }
     }
 
+    /// Restart the window on the first line of a frame, then remember LY matching WY
+    fn latch_wy(&mut self, bus: &impl PpuBus, line: u8) {
+        if line == 0 {
+            self.window_line = 0;
+            self.wy_triggered = false;
+        }
+        self.wy_triggered |= line == bus.wy();
+    }
+
+    /// First pixel of the window on the current line, `None` when the line does not show it.
+    /// In CGB mode LCDC bit 0 does not hide the window.
+    fn window_start(&self, bus: &impl PpuBus) -> Option<usize> {
+        let lcdc = bus.lcdc();
+        let wx = bus.wx();
+        (lcdc.contains(LcdControl::WINDOW_ENABLE)
+            && (self.cgb_mode || lcdc.contains(LcdControl::BG_WINDOW_ENABLE))
+            && self.wy_triggered
+            && wx < LCD_WIDTH + 7)
+            .then(|| wx.saturating_sub(7) as usize)
+    }
+
     fn render_line(&mut self, bus: &impl PpuBus, line: u8) {
-        if line >= 144 {
+        if line >= LCD_HEIGHT {
             return;
         }
 
-        if bus.lcdc().contains(LcdControl::BG_WINDOW_ENABLE) {
+        let range = line as usize * LCD_WIDTH as usize..(line as usize + 1) * LCD_WIDTH as usize;
+        let previous: [u8; LCD_WIDTH as usize] = self.frame_buffer[range.clone()].try_into().unwrap();
+        let previous_rgb: [u16; LCD_WIDTH as usize] = self.rgb_frame_buffer[range.clone()].try_into().unwrap();
+
+        // In CGB mode LCDC bit 0 only takes the priority away from the background and window
+        if self.cgb_mode || bus.lcdc().contains(LcdControl::BG_WINDOW_ENABLE) {
             self.render_background_line(bus, line);
+        } else {
+            self.bg_color_ids.fill(0);
         }
 
-        if bus.lcdc().contains(LcdControl::OBJ_ENABLE) {
+        if bus.lcdc().contains(LcdControl::OBJ_ENABLE) && !self.hidden_layers.contains(Layers::SPRITES) {
             let double_height = bus.lcdc().contains(LcdControl::OBJ_SIZE);
             self.update_visibles_sprites(bus, line, double_height);
             self.render_sprites_line(bus, line, double_height);
         }
+
+        if self.frame_buffer[range.clone()] != previous || self.rgb_frame_buffer[range] != previous_rgb {
+            self.changed_lines.insert(line);
+        }
     }
 
+    /// The background, then the window from WX - 7 to the end of the line once LY reached WY.
     fn render_background_line(&mut self, bus: &impl PpuBus, line: u8) {
-        let tilemap = if bus.lcdc().contains(LcdControl::TILEMAP_AREA) {
+        let lcdc = bus.lcdc();
+        let wx = bus.wx();
+        let window_start = self
+            .window_start(bus)
+            .filter(|_| !self.hidden_layers.contains(Layers::WINDOW));
+
+        let bg_tilemap = if lcdc.contains(LcdControl::TILEMAP_AREA) {
             0x1C00 // at $9C00
         } else {
             0x1800 // at $9800
         };
+        let bg_end = window_start.unwrap_or(LCD_WIDTH as usize);
+        if self.hidden_layers.contains(Layers::BACKGROUND) {
+            self.bg_color_ids[..bg_end].fill(0);
+            self.bg_attributes[..bg_end].fill(0);
+        } else {
+            self.render_tiles(bus, bg_tilemap, line.wrapping_add(bus.scy()), bus.scx(), 0..bg_end);
+        }
 
-        let y = line as u16;
-        let scroll_y = bus.scy() as u16;
-        let scroll_x = bus.scx() as u16;
-
-        // Draw background
-        for x in 0..LCD_WIDTH as u16 {
-            let bg_y = (y + scroll_y) % 256;
-            let bg_x = (x + scroll_x) % 256;
-
-            let tile_y = bg_y / 8;
-            let tile_x = bg_x / 8;
-
-            let py = bg_y % 8;
-            let px = bg_x % 8;
-
-            let tile_addr = tilemap + tile_x + tile_y * 32;
-            let tile_value = bus.read_vram(tile_addr) as u16;
-
-            let tile_data_addr = if bus.lcdc().contains(LcdControl::TILEDATA_AREA) {
-                tile_value * 16
-            } else if tile_value < 128 {
-                0x1000 + tile_value * 16
+        if let Some(start) = window_start {
+            let window_tilemap = if lcdc.contains(LcdControl::WINDOW_TILE_MAP) {
+                0x1C00
             } else {
-                0x0800 + (tile_value - 128) * 16
+                0x1800
             };
+            // With WX < 7 the first window pixels are left of the screen
+            let window_x = 7u8.saturating_sub(wx);
+            self.render_tiles(
+                bus,
+                window_tilemap,
+                self.window_line,
+                window_x,
+                start..LCD_WIDTH as usize,
+            );
+        }
 
-            let line_addr = tile_data_addr + py * 2;
+        let start = line as usize * LCD_WIDTH as usize;
+        let pixels = &mut self.frame_buffer[start..start + LCD_WIDTH as usize];
+        if self.cgb_mode {
+            pixels.copy_from_slice(&self.bg_color_ids);
+            let colors = &mut self.rgb_frame_buffer[start..start + LCD_WIDTH as usize];
+            for ((color, color_id), attributes) in colors.iter_mut().zip(&self.bg_color_ids).zip(&self.bg_attributes) {
+                *color = bus.bg_palette_color(attributes & ATTRIBUTE_PALETTE, *color_id);
+            }
+            return;
+        }
+        let palette = [0, 1, 2, 3].map(|color_id| bus.bgp_color(color_id));
+        for (pixel, color_id) in pixels.iter_mut().zip(&self.bg_color_ids) {
+            *pixel = palette[*color_id as usize];
+        }
+    }
 
-            //  pixel value
-            let low_byte = bus.read_vram(line_addr);
-            let high_byte = bus.read_vram(line_addr + 1);
-            let bit_pos = 7 - px;
+    /// Fill `range` of the line color ids from the tilemap row at `map_y`, starting at `map_x`.
+    /// Each tile is fetched and decoded once, the first one being cut by the fine scroll (`map_x` % 8).
+    /// In CGB mode the attributes of the tile select its bank and flip it.
+    fn render_tiles(&mut self, bus: &impl PpuBus, tilemap: u16, map_y: u8, mut map_x: u8, range: Range<usize>) {
+        let tilemap_row = tilemap + (map_y as u16 / 8) * 32;
 
-            // apply palette
-            let color_low = (low_byte >> bit_pos) & 0x01;
-            let color_high = (high_byte >> bit_pos) & 0x01;
-            let color_id = (color_high << 1) | color_low;
-            let color = bus.bgp_color(color_id);
+        let color_ids = &mut self.bg_color_ids[range.clone()];
+        let tile_attributes = &mut self.bg_attributes[range];
+        let mut x = 0;
+        while x < color_ids.len() {
+            let (row, attributes) = tile_row(bus, self.cgb_mode, tilemap_row + map_x as u16 / 8, map_y);
 
-            self.frame_buffer[(y * LCD_WIDTH as u16 + x) as usize] = color;
+            let first = (map_x % 8) as usize;
+            let count = (8 - first).min(color_ids.len() - x);
+            color_ids[x..x + count].copy_from_slice(&row[first..first + count]);
+            tile_attributes[x..x + count].fill(attributes);
+            x += count;
+            map_x = map_x.wrapping_add(count as u8);
         }
     }
 
@@ -171,7 +509,7 @@ impl Ppu {
                 bus.read_oam(sprite_idx + 2),
                 bus.read_oam(sprite_idx + 3),
             ];
-            let sprite = Sprite::from(bytes);
+            let sprite = Sprite::new((sprite_idx / 4) as u8, bytes);
 
             if sprite.is_visible_at_line(line, double_height) {
                 self.sprites_visibles_on_current_line.push(sprite);
@@ -182,14 +520,18 @@ impl Ppu {
             }
         }
 
-        // Sort by X coordinate in descending order
-        self.sprites_visibles_on_current_line
-            .sort_by_key(|s| std::cmp::Reverse(s.x()));
+        sort_by_priority(&mut self.sprites_visibles_on_current_line, self.model);
     }
 
+    /// Sprites from the highest priority down, the first opaque pixel at a position wins it. Behind the background,
+    /// that pixel leaves the background colors 1-3 showing and still hides the lower priority sprites.
+    /// In CGB mode the priority bit of a background tile also puts it over the sprites, unless LCDC bit 0 is clear.
     fn render_sprites_line(&mut self, bus: &impl PpuBus, line: u8, double_height: bool) {
-        for sprite in &self.sprites_visibles_on_current_line {
+        let bg_priority = bus.lcdc().contains(LcdControl::BG_WINDOW_ENABLE);
+        let mut taken = [false; LCD_WIDTH as usize];
+        for sprite in self.sprites_visibles_on_current_line.iter().rev() {
             let tile_addr = sprite.get_tile_address(line, double_height);
+            let bank1 = self.cgb_mode && sprite.is_in_bank1();
 
             // draw 8 pixels of the sprite
             for px in 0..8 {
@@ -200,8 +542,10 @@ impl Ppu {
                 }
 
                 //  pixel value
-                let low_byte = bus.read_vram(tile_addr);
-                let high_byte = bus.read_vram(tile_addr + 1);
+                let (low_byte, high_byte) = match bank1 {
+                    true => (bus.read_vram_bank1(tile_addr), bus.read_vram_bank1(tile_addr + 1)),
+                    false => (bus.read_vram(tile_addr), bus.read_vram(tile_addr + 1)),
+                };
                 let bit_pos = if sprite.has_x_flip() { px } else { 7 - px };
 
                 // apply palette
@@ -210,11 +554,22 @@ impl Ppu {
                 let color_id = (color_high << 1) | color_low;
 
                 // color_id == 0 means transparent for sprites
-                if color_id == 0 {
+                if color_id == 0 || taken[x] {
                     continue;
                 }
+                taken[x] = true;
 
-                // todo handle priority sprite/background => sprite.priority()
+                let behind = sprite.is_behind_background() || self.bg_attributes[x] & ATTRIBUTE_PRIORITY != 0;
+                if behind && self.bg_color_ids[x] != 0 && bg_priority {
+                    continue;
+                }
+
+                let index = line as usize * LCD_WIDTH as usize + x;
+                if self.cgb_mode {
+                    self.frame_buffer[index] = color_id;
+                    self.rgb_frame_buffer[index] = bus.obj_palette_color(sprite.cgb_palette(), color_id);
+                    continue;
+                }
 
                 // retrieve the color from the palette
                 let color = if sprite.palette() {
@@ -223,7 +578,7 @@ impl Ppu {
                     bus.obp0_color(color_id)
                 };
 
-                self.frame_buffer[line as usize * LCD_WIDTH as usize + x] = color;
+                self.frame_buffer[index] = color;
             }
         }
     }
@@ -261,3 +616,701 @@ impl Ppu {
         }
     }
 }
+
+impl Savable for Ppu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u64(self.mode_clock);
+        writer.write_bool(self.frame_ready);
+        writer.write_bool(self.stat_line);
+        writer.write_u8(self.window_line);
+        writer.write_bool(self.wy_triggered);
+        writer.write_bytes(&self.frame_buffer);
+        writer.write_u16(self.pixel_transfer_cycles as u16);
+        self.rgb_frame_buffer.iter().for_each(|color| writer.write_u16(*color));
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.mode_clock = reader.read_u64()?;
+        self.frame_ready = reader.read_bool()?;
+        self.stat_line = reader.read_bool()?;
+        self.window_line = reader.read_u8()?;
+        self.wy_triggered = reader.read_bool()?;
+        reader.read_bytes(&mut self.frame_buffer)?;
+        self.pixel_transfer_cycles = match reader.version() >= 2 {
+            true => reader.read_u16()? as u64,
+            false => PIXEL_TRANSFER_CYCLES,
+        };
+        // The HBlank takes the rest of the line
+        if !(PIXEL_TRANSFER_CYCLES..=CYCLES_PER_LINE - OAM_SCAN_CYCLES).contains(&self.pixel_transfer_cycles) {
+            return Err(invalid_data("pixel transfer out of range"));
+        }
+        // The CGB colors of the older states come back with the next frame, the DMG ones are not used
+        match reader.version() >= 3 {
+            true => {
+                for color in self.rgb_frame_buffer.iter_mut() {
+                    *color = reader.read_u16()?;
+                }
+            }
+            false => self.rgb_frame_buffer.fill(WHITE_RGB555),
+        }
+        // A line in progress is drawn again from its first pixel
+        self.fifo = PixelFifo::default();
+        self.changed_lines = ChangedLines::all();
+        Ok(())
+    }
+}
+
+/// Color ids of the row `map_y` % 8 of the background or window tile at `map_address`, with its CGB attributes
+/// (0 on DMG) which may flip it and take it from VRAM bank 1
+fn tile_row(bus: &impl PpuBus, cgb_mode: bool, map_address: u16, map_y: u8) -> ([u8; 8], u8) {
+    let attributes = match cgb_mode {
+        true => bus.read_vram_bank1(map_address),
+        false => 0,
+    };
+    let py = match attributes & ATTRIBUTE_Y_FLIP {
+        0 => map_y as u16 % 8,
+        _ => 7 - map_y as u16 % 8,
+    };
+    let tile_value = bus.read_vram(map_address) as u16;
+    let tile_data_addr = if bus.lcdc().contains(LcdControl::TILEDATA_AREA) {
+        tile_value * 16
+    } else if tile_value < 128 {
+        0x1000 + tile_value * 16
+    } else {
+        0x0800 + (tile_value - 128) * 16
+    };
+    let line_addr = tile_data_addr + py * 2;
+    let mut row = match attributes & ATTRIBUTE_BANK {
+        0 => tile::decode_row(bus.read_vram(line_addr), bus.read_vram(line_addr + 1)),
+        _ => tile::decode_row(bus.read_vram_bank1(line_addr), bus.read_vram_bank1(line_addr + 1)),
+    };
+    if attributes & ATTRIBUTE_X_FLIP != 0 {
+        row.reverse();
+    }
+    (row, attributes)
+}
+
+/// Cycles the fetch of a sprite at `x` stalls the pixel transfer, `waited_tile` being the background tile the
+/// previous sprites of the line already waited for
+fn sprite_penalty(x: i16, fine_scroll: i16, waited_tile: &mut Option<i16>) -> u64 {
+    // A sprite at OAM X 0 always waits for a whole tile fetch
+    if x == -8 {
+        return SPRITE_PENALTY_CYCLES + 5;
+    }
+    let tile = (x + fine_scroll).div_euclid(8);
+    if *waited_tile == Some(tile) {
+        return SPRITE_PENALTY_CYCLES;
+    }
+    *waited_tile = Some(tile);
+    SPRITE_PENALTY_CYCLES + 5u64.saturating_sub((x + fine_scroll).rem_euclid(8) as u64)
+}
+
+/// Order the sprites of a line from the lowest to the highest priority, the last drawn being on top.
+/// DMG: the smallest X wins, then the smallest OAM index. CGB: the smallest OAM index wins.
+fn sort_by_priority(sprites: &mut [Sprite], model: Model) {
+    match model {
+        Model::Dmg => sprites.sort_by_key(|s| std::cmp::Reverse((s.x(), s.oam_index()))),
+        Model::Cgb => sprites.sort_by_key(|s| std::cmp::Reverse(s.oam_index())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::InterruptBus;
+    use crate::tests::bus::TestBus;
+
+    fn setup(stat: LcdStatus) -> (Ppu, TestBus) {
+        let mut ppu = Ppu::default();
+        let mut bus = TestBus::default();
+        ppu.reset(&mut bus);
+        bus.set_stat(stat);
+
+        (ppu, bus)
+    }
+
+    /// Run the PPU for `lines` scanlines and count the STAT interrupt requests
+    fn count_stat_interrupts(ppu: &mut Ppu, bus: &mut TestBus, lines: u32) -> u32 {
+        let mut count = 0;
+        for _ in 0..lines * CYCLES_PER_LINE as u32 / 4 {
+            ppu.update(bus, 4);
+            if bus.interrupt_flag().contains(Interrupt::LCD_STAT) {
+                bus.clear_interrupt_flag(Interrupt::LCD_STAT);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn test_background_fine_scroll() {
+        let (mut ppu, mut bus) = setup(LcdStatus::empty());
+        bus.set_bgp(0xE4); // Identity palette
+        for row in 0..8 {
+            bus.memory[0x8010 + row * 2] = 0x3C;
+            bus.memory[0x8011 + row * 2] = 0x7E;
+        }
+        // Tiles 0 and 1 alternate, tile 31 is tile 1 too
+        for tile_x in 0..32 {
+            bus.memory[0x9800 + tile_x] = (tile_x % 2) as u8;
+        }
+
+        bus.set_scx(3);
+        ppu.render_line(&bus, 0);
+        assert_eq!(ppu.frame_buffer[..13], [0, 0, 0, 0, 0, 0, 2, 3, 3, 3, 3, 2, 0]);
+
+        // Wrapping around the tilemap, the line starts in the middle of tile 31
+        bus.set_scx(252);
+        ppu.render_line(&bus, 0);
+        assert_eq!(ppu.frame_buffer[..8], [3, 3, 2, 0, 0, 0, 0, 0]);
+        assert_eq!(ppu.frame_buffer[159], 3);
+    }
+
+    /// VRAM, tilemaps and OAM written directly on a [`TestBus`], rendered one line at a time
+    struct Fixture {
+        ppu: Ppu,
+        bus: TestBus,
+    }
+
+    impl Fixture {
+        /// Identity palettes, tile data at $8000, background map at $9800 and window map at $9C00
+        fn new(lcdc: LcdControl) -> Self {
+            let (ppu, mut bus) = setup(LcdStatus::empty());
+            bus.set_lcdc(lcdc | LcdControl::ENABLE | LcdControl::TILEDATA_AREA | LcdControl::WINDOW_TILE_MAP);
+            bus.set_bgp(0xE4);
+            bus.set_obp0(0xE4);
+            bus.set_obp1(0xE4);
+            Self { ppu, bus }
+        }
+
+        /// Tile whose 8 rows are `rows`, given as color ids
+        fn tile(&mut self, index: u8, rows: [[u8; 8]; 8]) -> &mut Self {
+            for (y, row) in rows.iter().enumerate() {
+                let address = 0x8000 + index as usize * 16 + y * 2;
+                for (x, color_id) in row.iter().enumerate() {
+                    self.bus.memory[address] |= (color_id & 1) << (7 - x);
+                    self.bus.memory[address + 1] |= (color_id >> 1) << (7 - x);
+                }
+            }
+            self
+        }
+
+        fn solid_tile(&mut self, index: u8, color_id: u8) -> &mut Self {
+            self.tile(index, [[color_id; 8]; 8])
+        }
+
+        /// Background map from its first tile, the next tiles are 0
+        fn background(&mut self, tiles: &[u8]) -> &mut Self {
+            self.bus.memory[0x9800..0x9800 + tiles.len()].copy_from_slice(tiles);
+            self
+        }
+
+        fn window(&mut self, tiles: &[u8], wx: u8, wy: u8) -> &mut Self {
+            self.bus.memory[0x9C00..0x9C00 + tiles.len()].copy_from_slice(tiles);
+            self.bus.set_wx(wx);
+            self.bus.set_wy(wy);
+            self
+        }
+
+        /// Sprite at the screen position (`x`, `y`)
+        fn sprite(&mut self, index: usize, x: u8, y: u8, tile: u8, attributes: u8) -> &mut Self {
+            let address = 0xFE00 + index * 4;
+            self.bus.memory[address..address + 4].copy_from_slice(&[y + 16, x + 8, tile, attributes]);
+            self
+        }
+
+        fn render(&mut self, line: u8) -> [u8; LCD_WIDTH as usize] {
+            self.ppu.scanline(&self.bus, line);
+            let start = line as usize * LCD_WIDTH as usize;
+            self.ppu.frame_buffer[start..start + LCD_WIDTH as usize]
+                .try_into()
+                .unwrap()
+        }
+    }
+
+    /// Full line from (color, length) runs
+    fn runs(runs: &[(u8, usize)]) -> [u8; LCD_WIDTH as usize] {
+        let line: Vec<u8> = runs
+            .iter()
+            .flat_map(|&(color, length)| std::iter::repeat_n(color, length))
+            .collect();
+        line.try_into().expect("runs must cover 160 pixels")
+    }
+
+    #[test]
+    fn test_window_starts_mid_line_over_fine_scroll() {
+        let mut fixture = Fixture::new(LcdControl::BG_WINDOW_ENABLE | LcdControl::WINDOW_ENABLE);
+        let background: Vec<u8> = (0..32).map(|x| 1 + (x & 1)).collect();
+        fixture
+            .solid_tile(1, 1)
+            .solid_tile(2, 2)
+            .solid_tile(3, 3)
+            .background(&background)
+            .window(&[3; 32], 84 + 7, 0);
+        fixture.bus.set_scx(3);
+
+        // The first background tile is cut by the fine scroll, the last one by the window
+        let mut expected = vec![(1, 5)];
+        expected.extend((1..10).map(|tile| (1 + (tile & 1), 8)));
+        expected.extend([(1, 7), (3, 76)]);
+        assert_eq!(fixture.render(0), runs(&expected));
+    }
+
+    #[test]
+    fn test_window_left_of_screen() {
+        let mut fixture = Fixture::new(LcdControl::BG_WINDOW_ENABLE | LcdControl::WINDOW_ENABLE);
+        let pattern = [0, 1, 2, 3, 3, 2, 1, 0];
+        fixture.tile(1, [pattern; 8]).window(&[1; 32], 3, 0);
+
+        // WX = 3 hides the first 4 window pixels
+        let expected: Vec<u8> = (0..160).map(|x| pattern[(x + 4) % 8]).collect();
+        assert_eq!(fixture.render(0).as_slice(), expected);
+    }
+
+    #[test]
+    fn test_window_rows_start_at_wy() {
+        let mut fixture = Fixture::new(LcdControl::BG_WINDOW_ENABLE | LcdControl::WINDOW_ENABLE);
+        let mut rows = [[1; 8]; 8];
+        rows[0] = [3; 8];
+        fixture
+            .solid_tile(2, 2)
+            .tile(1, rows)
+            .background(&[2; 32 * 32])
+            .window(&[1; 32], 87, 10);
+
+        assert_eq!(fixture.render(9), runs(&[(2, 160)]));
+        assert_eq!(fixture.render(10), runs(&[(2, 80), (3, 80)]));
+        assert_eq!(fixture.render(11), runs(&[(2, 80), (1, 80)]));
+    }
+
+    /// Window tile whose row `y` is color 1 + y % 3, over a color 0 background
+    fn window_rows_fixture() -> Fixture {
+        let mut fixture = Fixture::new(LcdControl::BG_WINDOW_ENABLE | LcdControl::WINDOW_ENABLE);
+        let rows = std::array::from_fn(|y| [1 + y as u8 % 3; 8]);
+        fixture.tile(1, rows).window(&[1; 32], 7, 0);
+        fixture
+    }
+
+    #[test]
+    fn test_window_line_counter_skips_disabled_lines() {
+        let mut fixture = window_rows_fixture();
+        assert_eq!(fixture.render(0), runs(&[(1, 160)]));
+
+        // Disabled then enabled again mid-frame, the window resumes at its next row and not at LY - WY
+        fixture.bus.clear_lcdc(LcdControl::WINDOW_ENABLE);
+        assert_eq!(fixture.render(1), runs(&[(0, 160)]));
+        assert_eq!(fixture.render(2), runs(&[(0, 160)]));
+        fixture.bus.set_lcdc(LcdControl::WINDOW_ENABLE);
+        assert_eq!(fixture.render(3), runs(&[(2, 160)]));
+        assert_eq!(fixture.render(4), runs(&[(3, 160)]));
+    }
+
+    #[test]
+    fn test_window_line_counter_skips_off_screen_wx() {
+        let mut fixture = window_rows_fixture();
+        assert_eq!(fixture.render(0), runs(&[(1, 160)]));
+
+        // WX >= 167 hides the window without advancing its row
+        fixture.bus.set_wx(LCD_WIDTH + 7);
+        assert_eq!(fixture.render(1), runs(&[(0, 160)]));
+        fixture.bus.set_wx(87);
+        assert_eq!(fixture.render(2), runs(&[(0, 80), (2, 80)]));
+    }
+
+    #[test]
+    fn test_wy_is_latched_for_the_frame() {
+        let mut fixture = window_rows_fixture();
+        assert_eq!(fixture.render(0), runs(&[(1, 160)]));
+
+        // Once LY reached WY, moving WY below LY keeps the window
+        fixture.bus.set_wy(100);
+        assert_eq!(fixture.render(1), runs(&[(2, 160)]));
+
+        // A new frame waits for LY = WY again, a WY already passed is never reached
+        fixture.bus.set_wy(1);
+        assert_eq!(fixture.render(0), runs(&[(0, 160)]));
+        fixture.bus.set_wy(0);
+        assert_eq!(fixture.render(1), runs(&[(0, 160)]));
+        fixture.bus.set_wy(2);
+        assert_eq!(fixture.render(2), runs(&[(1, 160)]));
+    }
+
+    #[test]
+    fn test_sprites_overlapping_window() {
+        let mut fixture =
+            Fixture::new(LcdControl::BG_WINDOW_ENABLE | LcdControl::WINDOW_ENABLE | LcdControl::OBJ_ENABLE);
+        fixture
+            .solid_tile(1, 1)
+            .solid_tile(2, 2)
+            .solid_tile(3, 3)
+            .window(&[3; 32], 84 + 7, 0)
+            // Behind the background, visible over its color 0
+            .sprite(0, 20, 0, 1, 0x80)
+            // Across the window edge, on top of the next one (smaller X)
+            .sprite(1, 78, 0, 2, 0)
+            .sprite(2, 82, 0, 1, 0)
+            // Behind the window
+            .sprite(3, 88, 0, 1, 0x80);
+
+        assert_eq!(
+            fixture.render(0),
+            runs(&[(0, 20), (1, 8), (0, 50), (2, 8), (1, 4), (3, 70)])
+        );
+    }
+
+    #[test]
+    fn test_sprite_behind_background_masks_lower_sprites() {
+        let mut fixture = Fixture::new(LcdControl::BG_WINDOW_ENABLE | LcdControl::OBJ_ENABLE);
+        fixture
+            .solid_tile(1, 1)
+            .solid_tile(2, 2)
+            .solid_tile(3, 3)
+            .background(&[0, 0, 3, 3])
+            // Smaller X: above the next one, behind the background
+            .sprite(0, 12, 0, 1, 0x80)
+            .sprite(1, 14, 0, 2, 0);
+
+        // Over color 0 the first sprite shows, over color 3 the background shows and the second sprite is masked
+        // up to the end of the first one
+        assert_eq!(
+            fixture.render(0),
+            runs(&[(0, 12), (1, 4), (3, 4), (2, 2), (3, 10), (0, 128)])
+        );
+    }
+
+    #[test]
+    fn test_hidden_layers() {
+        let mut fixture =
+            Fixture::new(LcdControl::BG_WINDOW_ENABLE | LcdControl::WINDOW_ENABLE | LcdControl::OBJ_ENABLE);
+        fixture
+            .solid_tile(1, 1)
+            .solid_tile(2, 2)
+            .solid_tile(3, 3)
+            .background(&[2; 32])
+            .window(&[3; 32], 80 + 7, 0)
+            .sprite(0, 8, 0, 1, 0)
+            // Behind the background, shown once it is hidden
+            .sprite(1, 40, 0, 1, 0x80);
+        assert_eq!(fixture.render(0), runs(&[(2, 8), (1, 8), (2, 64), (3, 80)]));
+
+        fixture.ppu.set_hidden_layers(Layers::WINDOW);
+        assert_eq!(fixture.render(0), runs(&[(2, 8), (1, 8), (2, 144)]));
+
+        fixture.ppu.set_hidden_layers(Layers::BACKGROUND);
+        assert_eq!(
+            fixture.render(0),
+            runs(&[(0, 8), (1, 8), (0, 24), (1, 8), (0, 32), (3, 80)])
+        );
+
+        fixture.ppu.set_hidden_layers(Layers::SPRITES);
+        assert_eq!(fixture.render(0), runs(&[(2, 80), (3, 80)]));
+
+        fixture.ppu.set_hidden_layers(Layers::all());
+        assert_eq!(fixture.render(0), runs(&[(0, 160)]));
+    }
+
+    #[test]
+    fn test_modes_timing() {
+        let (mut ppu, mut bus) = setup(LcdStatus::empty());
+
+        let mut timeline = vec![];
+        for _ in 0..CYCLES_PER_LINE / 4 {
+            timeline.push(bus.read_mode() as u8);
+            ppu.update(&mut bus, 4);
+        }
+
+        assert_eq!(timeline.iter().filter(|&&m| m == 2).count(), 80 / 4);
+        assert_eq!(timeline.iter().filter(|&&m| m == 3).count(), 172 / 4);
+        assert_eq!(timeline.iter().filter(|&&m| m == 0).count(), 204 / 4);
+        assert_eq!(bus.ly(), 1);
+    }
+
+    #[test]
+    fn test_hblank_interrupt_once_per_line() {
+        let (mut ppu, mut bus) = setup(LcdStatus::HBLANK_INTERRUPT);
+
+        assert_eq!(count_stat_interrupts(&mut ppu, &mut bus, LINES_PER_FRAME as u32), 144);
+    }
+
+    #[test]
+    fn test_oam_interrupt_once_per_line() {
+        let (mut ppu, mut bus) = setup(LcdStatus::OAM_INTERRUPT);
+
+        // lines 1..=143 and line 0 of the next frame
+        assert_eq!(count_stat_interrupts(&mut ppu, &mut bus, LINES_PER_FRAME as u32), 144);
+    }
+
+    #[test]
+    fn test_oam_interrupt_blocked_by_hblank() {
+        let (mut ppu, mut bus) = setup(LcdStatus::HBLANK_INTERRUPT | LcdStatus::OAM_INTERRUPT);
+
+        // HBlank keeps the line high when entering OAM scan, only the first OAM scan after VBlank fires
+        assert_eq!(
+            count_stat_interrupts(&mut ppu, &mut bus, LINES_PER_FRAME as u32),
+            144 + 1
+        );
+    }
+
+    #[test]
+    fn test_hblank_interrupt_blocked_by_lyc() {
+        let (mut ppu, mut bus) = setup(LcdStatus::HBLANK_INTERRUPT | LcdStatus::LYC_INTERRUPT);
+        bus.set_lyc(1);
+
+        // line 0 HBlank raises the line, LY=LYC on line 1 keeps it high
+        assert_eq!(count_stat_interrupts(&mut ppu, &mut bus, 2), 1);
+        assert_eq!(count_stat_interrupts(&mut ppu, &mut bus, 1), 1);
+    }
+
+    #[test]
+    fn test_line_153_reads_0() {
+        let (mut ppu, mut bus) = setup(LcdStatus::LYC_INTERRUPT);
+        ppu.update(&mut bus, 153 * CYCLES_PER_LINE as u32);
+        assert_eq!((bus.ly(), bus.read_mode()), (153, Mode::VBlank));
+        bus.clear_interrupt_flag(Interrupt::LCD_STAT);
+
+        // LY=0 and the LYC=0 interrupt after the first M-cycle of line 153
+        ppu.update(&mut bus, 4);
+        assert_eq!((bus.ly(), bus.read_mode()), (0, Mode::VBlank));
+        assert!(bus.interrupt_flag().contains(Interrupt::LCD_STAT));
+        assert!(bus.stat().contains(LcdStatus::LYC_EQUAL));
+
+        // No second interrupt when line 0 starts
+        bus.clear_interrupt_flag(Interrupt::LCD_STAT);
+        ppu.update(&mut bus, CYCLES_PER_LINE as u32 - 8);
+        assert_eq!(bus.read_mode(), Mode::VBlank);
+        ppu.update(&mut bus, 4);
+        assert_eq!((bus.ly(), bus.read_mode()), (0, Mode::OAMScan));
+        assert!(!bus.interrupt_flag().contains(Interrupt::LCD_STAT));
+    }
+
+    #[test]
+    fn test_frame_length() {
+        let (mut ppu, mut bus) = setup(LcdStatus::empty());
+        ppu.update(&mut bus, LINES_PER_FRAME as u32 * CYCLES_PER_LINE as u32 - 4);
+        assert_eq!((bus.ly(), bus.read_mode()), (0, Mode::VBlank));
+        ppu.update(&mut bus, 4);
+        assert_eq!((bus.ly(), bus.read_mode()), (0, Mode::OAMScan));
+    }
+
+    /// Cycles of the pixel transfer of line 0, checking the HBlank ends the line on time
+    fn measure_pixel_transfer(fixture: &mut Fixture) -> u64 {
+        let (ppu, bus) = (&mut fixture.ppu, &mut fixture.bus);
+        ppu.update(bus, OAM_SCAN_CYCLES as u32);
+        let mut cycles = 0;
+        while bus.read_mode() == Mode::PixelTransfer {
+            ppu.update(bus, 1);
+            cycles += 1;
+        }
+        assert_eq!(bus.read_mode(), Mode::HBlank);
+        ppu.update(bus, (CYCLES_PER_LINE - OAM_SCAN_CYCLES - cycles - 1) as u32);
+        assert_eq!((bus.ly(), bus.read_mode()), (0, Mode::HBlank));
+        ppu.update(bus, 1);
+        assert_eq!((bus.ly(), bus.read_mode()), (1, Mode::OAMScan));
+        cycles
+    }
+
+    #[test]
+    fn test_pixel_transfer_length() {
+        let mut fixture = Fixture::new(LcdControl::BG_WINDOW_ENABLE);
+        assert_eq!(measure_pixel_transfer(&mut fixture), 172);
+
+        let mut fixture = Fixture::new(LcdControl::BG_WINDOW_ENABLE);
+        fixture.bus.set_scx(11);
+        assert_eq!(measure_pixel_transfer(&mut fixture), 172 + 3);
+
+        let mut fixture = Fixture::new(LcdControl::BG_WINDOW_ENABLE | LcdControl::WINDOW_ENABLE);
+        fixture.bus.set_wx(7);
+        assert_eq!(measure_pixel_transfer(&mut fixture), 172 + 6);
+
+        // Sprites are only fetched when shown
+        let mut fixture = Fixture::new(LcdControl::BG_WINDOW_ENABLE);
+        fixture.sprite(0, 0, 0, 0, 0);
+        assert_eq!(measure_pixel_transfer(&mut fixture), 172);
+    }
+
+    #[test]
+    fn test_sprite_penalty() {
+        let length = |sprites: &[u8], scx: u8| {
+            let mut fixture = Fixture::new(LcdControl::BG_WINDOW_ENABLE | LcdControl::OBJ_ENABLE);
+            fixture.bus.set_scx(scx);
+            for (index, &x) in sprites.iter().enumerate() {
+                fixture.bus.memory[0xFE00 + index * 4..0xFE02 + index * 4].copy_from_slice(&[16, x]);
+            }
+            measure_pixel_transfer(&mut fixture) - 172 - (scx % 8) as u64
+        };
+
+        // First pixel of a tile: 6 for the fetch and 5 waiting for the background fetch
+        assert_eq!(length(&[8], 0), 11);
+        assert_eq!(length(&[13], 0), 6);
+        assert_eq!(length(&[10], 0), 6 + 3);
+        // The second sprite over the same tile does not wait again
+        assert_eq!(length(&[8, 10], 0), 11 + 6);
+        assert_eq!(length(&[8, 16], 0), 11 + 11);
+        // The fine scroll moves the tile boundaries
+        assert_eq!(length(&[13], 3), 6 + 5);
+        // OAM X 0 always waits for a whole fetch
+        assert_eq!(length(&[0], 3), 11);
+        assert_eq!(length(&[8; 10], 0), 11 + 9 * 6);
+    }
+
+    /// Pixel transfer length and line 0 drawn by the scanline renderer and the pixel FIFO
+    fn draw_with_both_models(setup: impl Fn(&mut Fixture)) -> [(u64, [u8; LCD_WIDTH as usize]); 2] {
+        [PpuModel::Scanline, PpuModel::Fifo].map(|model| {
+            let lcdc = LcdControl::BG_WINDOW_ENABLE | LcdControl::WINDOW_ENABLE | LcdControl::OBJ_ENABLE;
+            let mut fixture = Fixture::new(lcdc);
+            fixture.ppu.set_ppu_model(model);
+            fixture
+                .tile(1, [[0, 1, 2, 3, 3, 2, 1, 0]; 8])
+                .solid_tile(2, 2)
+                .tile(3, [[3, 3, 0, 0, 1, 1, 0, 0]; 8])
+                .tile(4, [[1, 0, 2, 0, 3, 3, 0, 1]; 8])
+                .background(&[1, 2].repeat(16))
+                .window(&[3; 32], 200, 0);
+            setup(&mut fixture);
+            let cycles = measure_pixel_transfer(&mut fixture);
+            (
+                cycles,
+                fixture.ppu.frame_buffer[..LCD_WIDTH as usize].try_into().unwrap(),
+            )
+        })
+    }
+
+    /// Name and setup of a line drawn by both models
+    type Case = (&'static str, fn(&mut Fixture));
+
+    #[test]
+    fn test_fifo_matches_scanline() {
+        let cases: [Case; 9] = [
+            ("plain", |_| {}),
+            ("fine scroll", |fixture| fixture.bus.set_scx(11)),
+            ("window at 0", |fixture| fixture.bus.set_wx(7)),
+            ("window mid-line", |fixture| {
+                fixture.bus.set_scx(3);
+                fixture.bus.set_wx(91);
+            }),
+            ("sprite on a tile boundary", |fixture| {
+                fixture.sprite(0, 8, 0, 4, 0);
+            }),
+            ("overlapping sprites", |fixture| {
+                fixture.sprite(0, 10, 0, 4, 0x20).sprite(1, 8, 0, 3, 0x10);
+            }),
+            ("sprite over the fine scroll", |fixture| {
+                fixture.bus.set_scx(3);
+                fixture.sprite(0, 5, 0, 4, 0);
+            }),
+            ("sprites behind the background", |fixture| {
+                fixture.sprite(0, 20, 0, 4, 0x80).sprite(1, 150, 0, 3, 0x80);
+            }),
+            ("sprites left of the screen and over the window", |fixture| {
+                fixture.bus.memory[0xFE00..0xFE04].copy_from_slice(&[16, 3, 4, 0]);
+                fixture.bus.set_wx(50);
+                fixture.sprite(1, 40, 0, 3, 0).sprite(2, 43, 0, 4, 0);
+            }),
+        ];
+        for (name, setup) in cases {
+            let [scanline, fifo] = draw_with_both_models(setup);
+            assert_eq!(scanline, fifo, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_fifo_mid_line_palette() {
+        let mut fixture = Fixture::new(LcdControl::BG_WINDOW_ENABLE);
+        fixture.ppu.set_ppu_model(PpuModel::Fifo);
+        fixture.solid_tile(0, 1);
+        let (ppu, bus) = (&mut fixture.ppu, &mut fixture.bus);
+        ppu.take_changed_lines();
+
+        // The first pixel reaches the LCD on the 13th dot of the pixel transfer
+        ppu.update(bus, OAM_SCAN_CYCLES as u32 + 12 + 80);
+        bus.set_bgp(0xE8); // Color 1 is now shade 2
+        ppu.update(bus, 100);
+        assert_eq!(bus.read_mode(), Mode::HBlank);
+        assert_eq!(ppu.frame_buffer[..LCD_WIDTH as usize], runs(&[(1, 80), (2, 80)]));
+        assert!(ppu.take_changed_lines().contains(0));
+    }
+
+    fn draw_order(model: Model, sprites: &[(u8, u8)]) -> Vec<u8> {
+        let mut sprites: Vec<Sprite> = sprites
+            .iter()
+            .enumerate()
+            .map(|(index, &(x, tile))| Sprite::new(index as u8, [16, x, tile, 0]))
+            .collect();
+        sort_by_priority(&mut sprites, model);
+        sprites.iter().map(Sprite::oam_index).collect()
+    }
+
+    #[test]
+    fn test_oam_corruption_pattern() {
+        let (mut ppu, mut bus) = setup(LcdStatus::empty());
+        bus.memory[0xFE00..0xFE10].copy_from_slice(&[
+            0x0F, 0xF0, 0x3C, 0xC3, 0x55, 0xAA, 0x77, 0x88, // row 0
+            0x00, 0xFF, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, // row 1
+        ]);
+
+        // Second M-cycle of the scan reads row 1
+        ppu.corrupt_oam(&mut bus);
+        assert_eq!(
+            bus.memory[0xFE08..0xFE10],
+            [0x05, 0xFA, 0x3C, 0xC3, 0x55, 0xAA, 0x77, 0x88]
+        );
+        assert_eq!(
+            bus.memory[0xFE00..0xFE08],
+            [0x0F, 0xF0, 0x3C, 0xC3, 0x55, 0xAA, 0x77, 0x88]
+        );
+    }
+
+    #[test]
+    fn test_oam_corruption_outside_scan() {
+        let (mut ppu, mut bus) = setup(LcdStatus::empty());
+        for (i, byte) in bus.memory[0xFE00..0xFEA0].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let oam = bus.memory[0xFE00..0xFEA0].to_vec();
+
+        // Last M-cycle of the scan, past the rows
+        ppu.update(&mut bus, 76);
+        ppu.corrupt_oam(&mut bus);
+        // Pixel transfer
+        ppu.update(&mut bus, 8);
+        ppu.corrupt_oam(&mut bus);
+        assert_eq!(bus.memory[0xFE00..0xFEA0], oam);
+    }
+
+    #[test]
+    fn test_sprite_priority_dmg() {
+        // Smallest X on top, the OAM index breaks ties
+        assert_eq!(draw_order(Model::Dmg, &[(20, 0), (10, 1), (30, 2)]), [2, 0, 1]);
+        assert_eq!(draw_order(Model::Dmg, &[(10, 0), (10, 1), (10, 2)]), [2, 1, 0]);
+        assert_eq!(draw_order(Model::Dmg, &[(10, 0), (5, 1), (10, 2)]), [2, 0, 1]);
+    }
+
+    #[test]
+    fn test_sprite_priority_cgb() {
+        // The OAM index alone decides
+        assert_eq!(draw_order(Model::Cgb, &[(20, 0), (10, 1), (30, 2)]), [2, 1, 0]);
+        assert_eq!(draw_order(Model::Cgb, &[(10, 0), (10, 1), (10, 2)]), [2, 1, 0]);
+    }
+
+    #[test]
+    fn test_load_state_pixel_transfer_range() {
+        let ppu = Ppu::default();
+        let mut writer = StateWriter::default();
+        ppu.save_state(&mut writer);
+        let mut data = writer.into_inner();
+
+        let offset = data.len() - 2 - 2 * ppu.rgb_frame_buffer.len();
+        for (cycles, valid) in [
+            (171u16, false),
+            (172, true),
+            (376, true),
+            (377, false),
+            (u16::MAX, false),
+        ] {
+            data[offset..offset + 2].copy_from_slice(&cycles.to_le_bytes());
+            let mut restored = Ppu::default();
+            let result = restored.load_state(&mut StateReader::with_version(&data, 3));
+            assert_eq!(result.is_ok(), valid, "{cycles}");
+        }
+    }
+}
@@ -1,5 +1,7 @@
-#[derive(Debug, Clone, Copy)]
-pub(crate) enum Mode {
+/// PPU mode, as reported in STAT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-state", derive(serde::Serialize, serde::Deserialize))]
+pub enum Mode {
     HBlank = 0,        // 87-204 cycles
     VBlank = 1,        // 4560 cycles ( 10 lines x 456 cycles)
     OAMScan = 2,       // 80 cycles
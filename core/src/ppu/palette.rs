@@ -0,0 +1,73 @@
+/// RGB colors used by frontends to display the 4 DMG shades.
+///
+/// The frame buffer only contains shade ids (`0` = lightest, `3` = darkest),
+/// the palette maps them to real colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColorPalette {
+    colors: [[u8; 3]; 4],
+}
+
+impl ColorPalette {
+    /// Greenish colors of the original DMG screen
+    pub const DMG_GREEN: Self = Self::new([[155, 188, 15], [139, 172, 15], [48, 98, 48], [15, 56, 15]]);
+    /// Neutral gray levels, as seen on the Game Boy Pocket
+    pub const GRAYSCALE: Self = Self::new([[255, 255, 255], [170, 170, 170], [85, 85, 85], [0, 0, 0]]);
+
+    pub const fn new(colors: [[u8; 3]; 4]) -> Self {
+        Self { colors }
+    }
+
+    /// RGB color for the shade id, out of range ids use the darkest shade.
+    pub fn color(&self, shade: u8) -> [u8; 3] {
+        self.colors[(shade as usize).min(3)]
+    }
+
+    pub fn colors(&self) -> &[[u8; 3]; 4] {
+        &self.colors
+    }
+}
+
+impl Default for ColorPalette {
+    fn default() -> Self {
+        Self::DMG_GREEN
+    }
+}
+
+/// DMG palette register (BGP, OBP0, OBP1) decoded: the shade of each color id.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Palette(pub [u8; 4]);
+
+impl Palette {
+    /// Color id `n` is shade `n`, BGP = $E4
+    pub const IDENTITY: Self = Self([0, 1, 2, 3]);
+
+    pub fn shade(&self, color_id: u8) -> u8 {
+        self.0[color_id as usize & 0x03]
+    }
+}
+
+impl From<u8> for Palette {
+    fn from(register: u8) -> Self {
+        Self([0, 1, 2, 3].map(|color_id| register >> (color_id * 2) & 0x03))
+    }
+}
+
+impl From<Palette> for u8 {
+    fn from(palette: Palette) -> Self {
+        palette.0.iter().enumerate().fold(0, |register, (color_id, shade)| {
+            register | (shade & 0x03) << (color_id * 2)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palette_register() {
+        assert_eq!(Palette::from(0xE4), Palette::IDENTITY);
+        assert_eq!(Palette::from(0x1B), Palette([3, 2, 1, 0]));
+        assert_eq!(u8::from(Palette([0, 0, 3, 1])), 0x70);
+    }
+}
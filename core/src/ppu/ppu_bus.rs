@@ -107,6 +107,21 @@ pub trait PpuBus: InterruptBus {
     fn read_vram(&self, address: u16) -> u8 {
         self.read_byte(0x8000 + address)
     }
+    /// CGB VRAM bank 1, whatever VBK
+    fn read_vram_bank1(&self, address: u16) -> u8 {
+        let _ = address;
+        0
+    }
+    /// RGB555 color of a CGB background palette
+    fn bg_palette_color(&self, palette: u8, color_id: u8) -> u16 {
+        let _ = (palette, color_id);
+        0x7FFF
+    }
+    /// RGB555 color of a CGB sprite palette
+    fn obj_palette_color(&self, palette: u8, color_id: u8) -> u16 {
+        let _ = (palette, color_id);
+        0x7FFF
+    }
     fn read_mode(&self) -> Mode {
         match self.stat().bits() & 0x03 {
             0 => Mode::HBlank,
@@ -0,0 +1,88 @@
+use crate::ppu::PpuBus;
+use crate::ppu::mode::Mode;
+
+/// Copy of the PPU registers, decoded for debuggers and frontends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-state", derive(serde::Serialize, serde::Deserialize))]
+pub struct PpuSnapshot {
+    /// Raw LCDC register ($FF40)
+    pub lcdc: u8,
+    /// Raw STAT register ($FF41)
+    pub stat: u8,
+    pub mode: Mode,
+    pub ly: u8,
+    pub lyc: u8,
+    pub scx: u8,
+    pub scy: u8,
+    pub wx: u8,
+    pub wy: u8,
+    /// Raw palette registers ($FF47..$FF49)
+    pub bgp: u8,
+    pub obp0: u8,
+    pub obp1: u8,
+}
+
+impl PpuSnapshot {
+    pub(crate) fn read(bus: &impl PpuBus) -> Self {
+        Self {
+            lcdc: bus.lcdc().bits(),
+            stat: bus.stat().bits(),
+            mode: bus.read_mode(),
+            ly: bus.ly(),
+            lyc: bus.lyc(),
+            scx: bus.scx(),
+            scy: bus.scy(),
+            wx: bus.wx(),
+            wy: bus.wy(),
+            bgp: bus.bgp(),
+            obp0: bus.obp0(),
+            obp1: bus.obp1(),
+        }
+    }
+
+    pub fn lcd_enabled(&self) -> bool {
+        self.lcdc & 0x80 != 0
+    }
+
+    /// Shade of each color id of the background palette
+    pub fn bg_palette(&self) -> [u8; 4] {
+        decode_palette(self.bgp)
+    }
+    pub fn obj_palette0(&self) -> [u8; 4] {
+        decode_palette(self.obp0)
+    }
+    pub fn obj_palette1(&self) -> [u8; 4] {
+        decode_palette(self.obp1)
+    }
+}
+
+fn decode_palette(value: u8) -> [u8; 4] {
+    [0, 1, 2, 3].map(|color_id| value >> (color_id * 2) & 0x03)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::bus::TestBus;
+
+    #[test]
+    fn test_snapshot() {
+        let mut bus = TestBus::default();
+        bus.set_lcdc_u8(0x91);
+        bus.write_mode(Mode::VBlank);
+        bus.set_ly(144);
+        bus.set_scx(0x12);
+        bus.set_wy(0x34);
+        bus.set_bgp(0b11_10_01_00);
+        bus.set_obp0(0b00_01_10_11);
+
+        let snapshot = PpuSnapshot::read(&bus);
+        assert!(snapshot.lcd_enabled());
+        assert_eq!(snapshot.mode, Mode::VBlank);
+        assert_eq!(snapshot.ly, 144);
+        assert_eq!(snapshot.scx, 0x12);
+        assert_eq!(snapshot.wy, 0x34);
+        assert_eq!(snapshot.bg_palette(), [0, 1, 2, 3]);
+        assert_eq!(snapshot.obj_palette0(), [3, 2, 1, 0]);
+    }
+}
@@ -3,6 +3,7 @@ use bitflags::bitflags;
 
 #[derive(Debug)]
 pub struct Sprite {
+    oam_index: u8,
     x: i16,
     y: i16,
     tile_index: u8,
@@ -15,17 +16,16 @@ bitflags! {
         const PRIORITY = 0b1000_0000;
         const Y_FLIP = 0b0100_0000;
         const X_FLIP = 0b0010_0000;
-        const DMG_PALETTE = 0b0001_0000; // [CGB Mode Only]
-        // const BANK = 0b0000_1000; // [CGB Mode Only]
-        // const CGB_PALETTE_2 = 0b0010_0100; // [CGB Mode Only]
-        // const CGB_PALETTE_1 = 0b0010_0010; // [CGB Mode Only]
-        // const CGB_PALETTE_0 = 0b0010_0001; // [CGB Mode Only]
+        const DMG_PALETTE = 0b0001_0000; // [Non CGB Mode Only]
+        const BANK = 0b0000_1000; // [CGB Mode Only]
+        const CGB_PALETTE = 0b0000_0111; // [CGB Mode Only]
     }
 }
 
 impl Sprite {
-    pub fn from(bytes: [u8; 4]) -> Self {
+    pub fn new(oam_index: u8, bytes: [u8; 4]) -> Self {
         Self {
+            oam_index,
             x: (bytes[1] as i16) - 8,
             y: (bytes[0] as i16) - 16,
             tile_index: bytes[2],
@@ -37,21 +37,44 @@ impl Sprite {
         self.x
     }
 
+    /// Position of the sprite in the OAM (0..40)
+    pub fn oam_index(&self) -> u8 {
+        self.oam_index
+    }
+
     pub fn has_x_flip(&self) -> bool {
         self.attributes.contains(Attributes::X_FLIP)
     }
     pub fn has_y_flip(&self) -> bool {
         self.attributes.contains(Attributes::Y_FLIP)
     }
+    /// The background and window colors 1-3 are drawn over the sprite
+    pub fn is_behind_background(&self) -> bool {
+        self.attributes.contains(Attributes::PRIORITY)
+    }
     pub fn palette(&self) -> bool {
         self.attributes.contains(Attributes::DMG_PALETTE)
     }
+    /// Tile data in VRAM bank 1, CGB mode only
+    pub fn is_in_bank1(&self) -> bool {
+        self.attributes.contains(Attributes::BANK)
+    }
+    /// One of the 8 CGB sprite palettes
+    pub fn cgb_palette(&self) -> u8 {
+        self.attributes.bits() & Attributes::CGB_PALETTE.bits()
+    }
+    /// Selected by the OAM scan, which only looks at Y: a sprite out of the screen horizontally still takes
+    /// one of the 10 places of the line
     pub fn is_visible_at_line(&self, line: u8, double_height: bool) -> bool {
         let line = line as i16;
         let height = if double_height { 16 } else { 8 };
-        let width: i16 = 8; // always 8 pixels wide for sprites
 
-        (line >= self.y && line < self.y + height) && (self.x < LCD_WIDTH as i16 && self.x + width > 0)
+        line >= self.y && line < self.y + height
+    }
+
+    /// Left of the right edge of the screen, the pixel transfer reaches it
+    pub fn is_fetched(&self) -> bool {
+        self.x < LCD_WIDTH as i16
     }
 
     /// Calculates the address for the current line of a sprite tile.
@@ -0,0 +1,36 @@
+/// Bits of a byte spread to the lowest bit of each byte of a u64, the most significant bit
+/// (leftmost pixel) going to the first byte in little endian order
+const SPREAD: [u64; 256] = spread_table();
+
+const fn spread_table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut bit = 0;
+        while bit < 8 {
+            table[byte] |= ((byte as u64 >> (7 - bit)) & 1) << (bit * 8);
+            bit += 1;
+        }
+        byte += 1;
+    }
+    table
+}
+
+/// Color ids of the 8 pixels of a tile row, leftmost first, decoded at once from the two bit planes
+#[inline]
+pub(crate) fn decode_row(low: u8, high: u8) -> [u8; 8] {
+    (SPREAD[low as usize] | SPREAD[high as usize] << 1).to_le_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_row() {
+        // Pan Docs example: $3C $7E -> 0 2 3 3 3 3 2 0
+        assert_eq!(decode_row(0x3C, 0x7E), [0, 2, 3, 3, 3, 3, 2, 0]);
+        assert_eq!(decode_row(0xFF, 0x00), [1; 8]);
+        assert_eq!(decode_row(0x80, 0x01), [1, 0, 0, 0, 0, 0, 0, 2]);
+    }
+}
@@ -0,0 +1,64 @@
+use crate::rng::Rng;
+
+/// Content of the work ram and high ram at power on.
+///
+/// Some games and test roms read uninitialized ram, a few demos use it to detect emulators.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RamInit {
+    /// All bytes cleared
+    Zero,
+    /// All bytes set to `$FF`
+    Ones,
+    /// Hardware like pattern of a DMG: runs of 8 bytes alternating `$00` and `$FF`, the phase being
+    /// inverted every 128 bytes
+    #[default]
+    DmgStripes,
+    /// Pseudo random bytes drawn from the machine seed, see [`MachineBuilder::seed`](crate::MachineBuilder::seed)
+    Random,
+}
+
+impl RamInit {
+    /// Fill `ram`, random bytes are drawn from `rng`
+    pub(crate) fn fill(&self, ram: &mut [u8], rng: &mut Rng) {
+        match *self {
+            RamInit::Zero => ram.fill(0x00),
+            RamInit::Ones => ram.fill(0xFF),
+            RamInit::DmgStripes => {
+                for (index, byte) in ram.iter_mut().enumerate() {
+                    let stripe = (index >> 3) & 1 != 0;
+                    let inverted = (index >> 7) & 1 != 0;
+                    *byte = if stripe != inverted { 0xFF } else { 0x00 };
+                }
+            }
+            RamInit::Random => ram.fill_with(|| rng.next_u8()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dmg_stripes() {
+        let mut ram = [0x55; 0x100];
+        RamInit::DmgStripes.fill(&mut ram, &mut Rng::default());
+
+        assert_eq!(ram[0..8], [0x00; 8]);
+        assert_eq!(ram[8..16], [0xFF; 8]);
+        assert_eq!(ram[0x80..0x88], [0xFF; 8]);
+        assert_eq!(ram[0x88..0x90], [0x00; 8]);
+    }
+
+    #[test]
+    fn test_random_is_deterministic() {
+        let mut first = [0; 0x100];
+        let mut second = [0; 0x100];
+        RamInit::Random.fill(&mut first, &mut Rng::new(42));
+        RamInit::Random.fill(&mut second, &mut Rng::new(42));
+        assert_eq!(first, second);
+
+        RamInit::Random.fill(&mut second, &mut Rng::new(43));
+        assert_ne!(first, second);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::state::{Savable, StateReader, StateWriter};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seeded pseudo random generator of the stochastic features, e.g. [`RamInit::Random`](crate::RamInit::Random).
+///
+/// SplitMix64 on integers only, the same seed gives the same numbers on every platform.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        splitmix64(&mut self.state)
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+}
+
+impl Savable for Rng {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u64(self.state);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.state = reader.read_u64()?;
+        Ok(())
+    }
+}
+
+/// Seed taken from the host clock, differs on each run
+pub(crate) fn host_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64)
+}
+
+/// Step of [`Rng`], also used to drive randomized tests
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rng_is_portable() {
+        // Reference values of SplitMix64 seeded with 0
+        let mut rng = Rng::new(0);
+        assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+
+        let mut writer = StateWriter::default();
+        rng.save_state(&mut writer);
+        let expected = rng.next_u64();
+        let state = writer.into_inner();
+        let mut restored = Rng::default();
+        restored.load_state(&mut StateReader::new(&state)).unwrap();
+        assert_eq!(restored.next_u64(), expected);
+    }
+}
@@ -0,0 +1,346 @@
+pub(crate) mod serial_bus;
+
+use crate::bus::Interrupt;
+use crate::model::Model;
+use crate::serial::serial_bus::SC;
+use crate::state::{Savable, StateReader, StateWriter, invalid_data};
+use serial_bus::SerialBus;
+
+/// Cycles per bit with the internal clock (8192 Hz)
+const CYCLES_PER_BIT: u16 = 512;
+/// Cycles per bit with the fast internal clock of the CGB (262144 Hz)
+const FAST_CYCLES_PER_BIT: u16 = 16;
+
+/// Serial port, shifting SB out one bit at a time, most significant bit first.
+///
+/// With the internal clock a transfer takes 8 * 512 cycles, each bit is exchanged with the connected
+/// [`SerialDevice`], the bits received are 1 when none is connected. With the external clock the transfer
+/// stalls until the partner clocks each bit with [`Serial::external_clock`]. On CGB, SC bit 1 selects a
+/// 32 times faster internal clock.
+#[derive(Default)]
+pub struct Serial {
+    model: Model,
+    /// Link partner of the internal clock transfers, not part of the save states
+    device: Option<Box<dyn SerialDevice>>,
+    /// Bits left to shift, 0 when no transfer is in progress
+    bits_left: u8,
+    /// Cycles before the next bit with the internal clock
+    counter: u16,
+    /// SB at the start of the transfer
+    outgoing: u8,
+    /// Bytes sent since the last [`Serial::take_output`]
+    output: Vec<u8>,
+    /// Byte of the last transfer, until [`Serial::take_sent`]
+    sent: Option<u8>,
+}
+
+/// Link partner clocked by this console, e.g. a printer or the other end of a
+/// [`LinkCable`](crate::LinkCable)
+pub trait SerialDevice: Send {
+    /// Bit shifted out with the internal clock, returns the bit shifted in
+    fn exchange_bit(&mut self, outgoing: bool) -> bool;
+}
+
+impl Serial {
+    pub(crate) fn set_model(&mut self, model: Model) {
+        self.model = model;
+    }
+
+    /// A transfer started and not yet complete, waiting for the link partner with the external clock
+    pub fn is_transferring(&self) -> bool {
+        self.bits_left > 0
+    }
+
+    /// Bits still to be sent when a transfer waits for the external clock, most significant first
+    pub(crate) fn waiting_bits<B: SerialBus>(&mut self, bus: &mut B) -> impl Iterator<Item = bool> + use<B> {
+        if bus.take_sc_write() {
+            self.start(bus);
+        }
+        let bits = match bus.sc().contains(SC::ClockSelect) {
+            true => 0,
+            false => self.bits_left,
+        };
+        let sb = bus.sb();
+        (0..bits).map(move |bit| sb << bit & 0x80 != 0)
+    }
+
+    pub(crate) fn connect(&mut self, device: Box<dyn SerialDevice>) -> Option<Box<dyn SerialDevice>> {
+        self.device.replace(device)
+    }
+
+    pub(crate) fn disconnect(&mut self) -> Option<Box<dyn SerialDevice>> {
+        self.device.take()
+    }
+
+    pub fn reset(&mut self, bus: &mut impl SerialBus) {
+        bus.set_sb(0x00);
+        bus.write_internal_byte(0xFF02, 0x7E);
+        bus.take_sc_write();
+        self.bits_left = 0;
+        self.counter = 0;
+        self.output.clear();
+        self.sent = None;
+    }
+
+    pub fn step(&mut self, bus: &mut impl SerialBus, cycles: u8) {
+        if bus.take_sc_write() {
+            self.start(bus);
+        }
+        if self.bits_left == 0 || !bus.sc().contains(SC::ClockSelect) {
+            return;
+        }
+
+        for _ in 0..cycles {
+            self.counter -= 1;
+            if self.counter == 0 {
+                self.counter = self.cycles_per_bit(bus.sc());
+                let incoming = match self.device.as_mut() {
+                    Some(device) => device.exchange_bit(bus.sb() & 0x80 != 0),
+                    None => true,
+                };
+                self.shift(bus, incoming);
+                if self.bits_left == 0 {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// SC write, setting the transfer enable bit starts a transfer and clearing it aborts it
+    fn start(&mut self, bus: &mut impl SerialBus) {
+        if bus.sc().contains(SC::TransferEnable) {
+            self.bits_left = 8;
+            self.counter = self.cycles_per_bit(bus.sc());
+            self.outgoing = bus.sb();
+        } else {
+            self.bits_left = 0;
+        }
+    }
+
+    /// The clock speed bit only exists on CGB, it always reads 1 on DMG
+    fn cycles_per_bit(&self, sc: SC) -> u16 {
+        match self.model == Model::Cgb && sc.contains(SC::ClockSpeed) {
+            true => FAST_CYCLES_PER_BIT,
+            false => CYCLES_PER_BIT,
+        }
+    }
+
+    /// Clock pulse from the link partner, returns the bit sent when a transfer waits for the external clock.
+    pub fn external_clock(&mut self, bus: &mut impl SerialBus, incoming: bool) -> Option<bool> {
+        if bus.take_sc_write() {
+            self.start(bus);
+        }
+        if self.bits_left == 0 || bus.sc().contains(SC::ClockSelect) {
+            return None;
+        }
+        Some(self.shift(bus, incoming))
+    }
+
+    fn shift(&mut self, bus: &mut impl SerialBus, incoming: bool) -> bool {
+        let sb = bus.sb();
+        bus.set_sb(sb << 1 | incoming as u8);
+        self.bits_left -= 1;
+
+        if self.bits_left == 0 {
+            bus.end_transfer();
+            bus.set_interrupt_flag(Interrupt::SERIAL);
+            self.output.push(self.outgoing);
+            self.sent = Some(self.outgoing);
+        }
+        sb & 0x80 != 0
+    }
+
+    /// Byte of the transfer completed since the last call
+    pub(crate) fn take_sent(&mut self) -> Option<u8> {
+        self.sent.take()
+    }
+
+    /// Bytes sent since the last call, e.g. the text printed by test roms
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+}
+
+impl Savable for Serial {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.bits_left);
+        writer.write_u16(self.counter);
+        writer.write_u8(self.outgoing);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.bits_left = reader.read_u8()?;
+        self.counter = reader.read_u16()?;
+        self.outgoing = reader.read_u8()?;
+        // A transfer in progress always has a bit on its way
+        if self.bits_left > 8 || (self.bits_left > 0 && !(1..=CYCLES_PER_BIT).contains(&self.counter)) {
+            return Err(invalid_data("invalid serial transfer"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{InterruptBus, MemorySystem};
+
+    fn start_transfer(bus: &mut MemorySystem, sb: u8, sc: u8) -> Serial {
+        let mut serial = Serial::default();
+        serial.reset(bus);
+        bus.write_byte(0xFF01, sb);
+        bus.write_byte(0xFF02, sc);
+        serial
+    }
+
+    #[test]
+    fn test_internal_clock_transfer() {
+        let mut bus = MemorySystem::default();
+        let mut serial = start_transfer(&mut bus, 0x41, 0x81);
+
+        serial.step(&mut bus, 0);
+        for _ in 0..(CYCLES_PER_BIT / 4) {
+            serial.step(&mut bus, 4);
+        }
+        // One bit shifted, a 1 was received
+        assert_eq!(bus.read_byte(0xFF01), 0x83);
+
+        for _ in 0..(7 * CYCLES_PER_BIT / 4 - 1) {
+            serial.step(&mut bus, 4);
+        }
+        assert!(bus.sc().contains(SC::TransferEnable));
+        assert!(!bus.interrupt_flag().contains(Interrupt::SERIAL));
+
+        serial.step(&mut bus, 4);
+        assert_eq!(bus.read_byte(0xFF01), 0xFF);
+        assert!(!bus.sc().contains(SC::TransferEnable));
+        assert!(bus.interrupt_flag().contains(Interrupt::SERIAL));
+        assert_eq!(serial.take_output(), vec![0x41]);
+    }
+
+    /// Sends back the complement of the bits received
+    struct Inverter;
+
+    impl SerialDevice for Inverter {
+        fn exchange_bit(&mut self, outgoing: bool) -> bool {
+            !outgoing
+        }
+    }
+
+    #[test]
+    fn test_device() {
+        let mut bus = MemorySystem::default();
+        let mut serial = start_transfer(&mut bus, 0x41, 0x81);
+        assert!(serial.connect(Box::new(Inverter)).is_none());
+
+        serial.step(&mut bus, 0);
+        for _ in 0..(8 * CYCLES_PER_BIT / 4) {
+            serial.step(&mut bus, 4);
+        }
+        assert_eq!(bus.read_byte(0xFF01), 0xBE);
+        assert!(bus.interrupt_flag().contains(Interrupt::SERIAL));
+        assert!(serial.disconnect().is_some());
+    }
+
+    #[test]
+    fn test_cgb_fast_clock() {
+        for (model, sc, cycles) in [
+            (Model::Cgb, 0x83, 8 * FAST_CYCLES_PER_BIT),
+            (Model::Cgb, 0x81, 8 * CYCLES_PER_BIT),
+            (Model::Dmg, 0x83, 8 * CYCLES_PER_BIT),
+        ] {
+            let mut bus = MemorySystem::default();
+            bus.set_model(model);
+            let mut serial = start_transfer(&mut bus, 0x41, sc);
+            serial.set_model(model);
+
+            serial.step(&mut bus, 0);
+            for _ in 0..(cycles / 4 - 1) {
+                serial.step(&mut bus, 4);
+            }
+            assert!(serial.is_transferring(), "{model:?} SC={sc:02X}");
+            serial.step(&mut bus, 4);
+            assert!(!serial.is_transferring(), "{model:?} SC={sc:02X}");
+            assert_eq!(serial.take_output(), vec![0x41]);
+        }
+    }
+
+    #[test]
+    fn test_clock_speed_bit_reads_back_on_cgb() {
+        let mut bus = MemorySystem::default();
+        bus.write_byte(0xFF02, 0x81);
+        assert_eq!(bus.read_byte(0xFF02), 0xFF);
+        bus.set_model(Model::Cgb);
+        assert_eq!(bus.read_byte(0xFF02), 0xFD);
+    }
+
+    #[test]
+    fn test_external_clock_stalls() {
+        let mut bus = MemorySystem::default();
+        let mut serial = start_transfer(&mut bus, 0xA5, 0x80);
+
+        for _ in 0..(16 * CYCLES_PER_BIT / 4) {
+            serial.step(&mut bus, 4);
+        }
+        assert_eq!(bus.read_byte(0xFF01), 0xA5);
+        assert!(bus.sc().contains(SC::TransferEnable));
+
+        let sent: Vec<_> = (0..8).filter_map(|_| serial.external_clock(&mut bus, false)).collect();
+        assert_eq!(sent, [true, false, true, false, false, true, false, true]);
+        assert_eq!(bus.read_byte(0xFF01), 0x00);
+        assert!(bus.interrupt_flag().contains(Interrupt::SERIAL));
+        assert_eq!(serial.external_clock(&mut bus, false), None);
+    }
+
+    #[test]
+    fn test_clearing_transfer_enable_aborts() {
+        let mut bus = MemorySystem::default();
+        let mut serial = start_transfer(&mut bus, 0x00, 0x81);
+        serial.step(&mut bus, 4);
+
+        bus.write_byte(0xFF02, 0x01);
+        for _ in 0..(8 * CYCLES_PER_BIT / 4) {
+            serial.step(&mut bus, 4);
+        }
+        assert_eq!(bus.read_byte(0xFF01), 0x00);
+        assert!(!bus.interrupt_flag().contains(Interrupt::SERIAL));
+        assert!(serial.take_output().is_empty());
+    }
+
+    #[test]
+    fn test_load_state_rejects_expired_bit() {
+        let mut serial = Serial::default();
+        for (bits_left, counter, valid) in [
+            (0, 0, true),
+            (8, 1, true),
+            (8, 0, false),
+            (8, 513, false),
+            (9, 1, false),
+        ] {
+            let mut writer = StateWriter::default();
+            writer.write_u8(bits_left);
+            writer.write_u16(counter);
+            writer.write_u8(0);
+            let data = writer.into_inner();
+            let result = serial.load_state(&mut StateReader::new(&data));
+            assert_eq!(result.is_ok(), valid, "{bits_left} {counter}");
+        }
+    }
+
+    #[test]
+    fn test_machine_serial_output() -> Result<(), Box<dyn std::error::Error>> {
+        let rom = crate::TestRom::new()
+            .code(&[0x3E, b'O']) // LD A,'O'
+            .code(&[0xE0, 0x01]) // LDH ($01),A
+            .code(&[0x3E, 0x81]) // LD A,$81
+            .code(&[0xE0, 0x02]) // LDH ($02),A ; start, internal clock
+            .code(&[0x18, 0xFE]) // JR -2
+            .build();
+        let mut machine = crate::Machine::builder().cartridge_bytes(rom).build()?;
+
+        machine.step_frame()?;
+        assert_eq!(machine.take_serial_output(), b"O");
+        assert!(machine.take_serial_output().is_empty());
+        Ok(())
+    }
+}
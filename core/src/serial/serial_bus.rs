@@ -0,0 +1,37 @@
+use crate::bus::InterruptBus;
+use bitflags::bitflags;
+
+bitflags! {
+    /// Serial transfer control
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct SC: u8 {
+        /// Transfer requested or in progress
+        const TransferEnable = 0b1000_0000;
+        /// CGB only: internal clock at 262144 Hz instead of 8192 Hz
+        const ClockSpeed = 0b0000_0010;
+        /// Shift clock generated by this console (internal) instead of the link partner (external)
+        const ClockSelect = 0b0000_0001;
+    }
+}
+
+pub trait SerialBus: InterruptBus {
+    fn sb(&self) -> u8 {
+        self.read_byte(0xFF01)
+    }
+    fn set_sb(&mut self, byte: u8) {
+        self.write_internal_byte(0xFF01, byte);
+    }
+    fn sc(&self) -> SC {
+        SC::from_bits_truncate(self.read_byte(0xFF02))
+    }
+    /// Clear the transfer enable bit, not seen as a CPU write
+    fn end_transfer(&mut self) {
+        let sc = self.read_byte(0xFF02) & !SC::TransferEnable.bits();
+        self.write_internal_byte(0xFF02, sc);
+    }
+
+    /// SC was written by the CPU since the last call
+    fn take_sc_write(&mut self) -> bool {
+        false
+    }
+}
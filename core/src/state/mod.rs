@@ -0,0 +1,285 @@
+mod session;
+mod slots;
+mod stream;
+
+pub use session::{DEFAULT_SNAPSHOT_INTERVAL, Session};
+pub use slots::{SLOT_COUNT, SaveSlots, SlotInfo, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
+pub use stream::{DEFAULT_KEYFRAME_INTERVAL, SnapshotStream};
+
+use std::io::{Error, ErrorKind};
+
+/// Component which can be written to and restored from a save state.
+pub(crate) trait Savable {
+    fn save_state(&self, writer: &mut StateWriter);
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Error>;
+}
+
+#[derive(Default)]
+pub(crate) struct StateWriter {
+    buffer: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buffer
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buffer.push(value);
+    }
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+    pub fn write_u16(&mut self, value: u16) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+    pub fn write_u32(&mut self, value: u32) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+    pub fn write_u64(&mut self, value: u64) {
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+    }
+    /// Fixed size block, the reader must know its length
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+    /// Variable size block, prefixed by its length
+    pub fn write_vec(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.write_bytes(bytes);
+    }
+    /// Block of a component, prefixed by the tag and version of the section, see [`Section`]
+    pub fn write_section(&mut self, section: Section, write: impl FnOnce(&mut StateWriter)) {
+        let mut content = StateWriter::default();
+        write(&mut content);
+        self.write_bytes(&section.tag);
+        self.write_u8(section.version);
+        self.write_vec(&content.buffer);
+    }
+}
+
+/// Tagged and versioned block of a save state holding one component.
+///
+/// Bump the version when the component writes a new field, and read the field only from sections of that
+/// version on ([`StateReader::version`]) so the states of the previous releases keep loading with a default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Section {
+    pub tag: [u8; 4],
+    pub version: u8,
+}
+
+impl Section {
+    pub const fn new(tag: &[u8; 4], version: u8) -> Self {
+        Self { tag: *tag, version }
+    }
+
+    fn name(&self) -> String {
+        String::from_utf8_lossy(&self.tag).trim().to_string()
+    }
+}
+
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    /// Layout version of the section being read
+    version: u8,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self::with_version(data, 1)
+    }
+
+    pub fn with_version(data: &'a [u8], version: u8) -> Self {
+        Self {
+            data,
+            position: 0,
+            version,
+        }
+    }
+
+    /// Version of the section being read, 1 for the states written before the sections
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self.position + len;
+        if end > self.data.len() {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "truncated save state"));
+        }
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+    pub fn read_bool(&mut self) -> Result<bool, Error> {
+        Ok(self.read_u8()? != 0)
+    }
+    pub fn read_u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+    pub fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    pub fn read_u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+    pub fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        buffer.copy_from_slice(self.take(buffer.len())?);
+        Ok(())
+    }
+    pub fn read_vec(&mut self) -> Result<Vec<u8>, Error> {
+        let len = self.read_u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+}
+
+/// Components of a save state, either in their own [`Section`] or one after the other as written before the
+/// sections, in the layout of the first version of each section.
+pub(crate) enum StateSections<'a> {
+    Tagged(Vec<([u8; 4], StateReader<'a>)>),
+    Legacy(StateReader<'a>),
+}
+
+impl<'a> StateSections<'a> {
+    /// Split the rest of `reader` into sections, those of a newer release are ignored
+    pub fn read(reader: &mut StateReader<'a>) -> Result<Self, Error> {
+        let mut sections = vec![];
+        while !reader.is_empty() {
+            let mut tag = [0; 4];
+            reader.read_bytes(&mut tag)?;
+            let version = reader.read_u8()?;
+            let len = reader.read_u32()? as usize;
+            sections.push((tag, StateReader::with_version(reader.take(len)?, version)));
+        }
+        Ok(Self::Tagged(sections))
+    }
+
+    /// Read the component of `section` with `read`, which must consume the whole section
+    pub fn load<T>(
+        &mut self,
+        section: Section,
+        read: impl FnOnce(&mut StateReader<'a>) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let reader = match self {
+            Self::Legacy(reader) => return read(reader),
+            Self::Tagged(sections) => sections
+                .iter_mut()
+                .find(|(tag, _)| *tag == section.tag)
+                .map(|(_, reader)| reader)
+                .ok_or_else(|| invalid_data(&format!("missing {} section", section.name())))?,
+        };
+        if reader.version() > section.version {
+            return Err(invalid_data(&format!(
+                "{} section from a newer release",
+                section.name()
+            )));
+        }
+        let value = read(reader)?;
+        if !reader.is_empty() {
+            return Err(invalid_data(&format!("trailing data in {} section", section.name())));
+        }
+        Ok(value)
+    }
+
+    /// Fails on the bytes left after the components of a legacy state
+    pub fn finish(self) -> Result<(), Error> {
+        match self {
+            Self::Legacy(reader) if !reader.is_empty() => Err(invalid_data("trailing data")),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// File name usable on any platform for a game title
+pub(crate) fn file_stem(title: &str) -> String {
+    let stem: String = title
+        .trim()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    if stem.is_empty() { "untitled".into() } else { stem }
+}
+
+pub(crate) fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("invalid save state: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() -> Result<(), Error> {
+        let mut writer = StateWriter::default();
+        writer.write_u8(0x12);
+        writer.write_bool(true);
+        writer.write_u16(0x3456);
+        writer.write_u32(0x789A_BCDE);
+        writer.write_u64(u64::MAX);
+        writer.write_bytes(&[1, 2]);
+        writer.write_vec(&[3, 4, 5]);
+        let data = writer.into_inner();
+
+        let mut reader = StateReader::new(&data);
+        assert_eq!(reader.read_u8()?, 0x12);
+        assert!(reader.read_bool()?);
+        assert_eq!(reader.read_u16()?, 0x3456);
+        assert_eq!(reader.read_u32()?, 0x789A_BCDE);
+        assert_eq!(reader.read_u64()?, u64::MAX);
+        let mut bytes = [0; 2];
+        reader.read_bytes(&mut bytes)?;
+        assert_eq!(bytes, [1, 2]);
+        assert_eq!(reader.read_vec()?, vec![3, 4, 5]);
+        assert!(reader.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sections() -> Result<(), Error> {
+        const OLD: Section = Section::new(b"OLD ", 1);
+        const NEW: Section = Section::new(b"NEW ", 2);
+        let mut writer = StateWriter::default();
+        writer.write_section(Section::new(b"NEW ", 1), |writer| writer.write_u8(0x12));
+        writer.write_section(Section::new(b"NEXT", 1), |writer| writer.write_u16(0x3456));
+        writer.write_section(OLD, |writer| writer.write_u8(0x78));
+        let data = writer.into_inner();
+
+        let mut sections = StateSections::read(&mut StateReader::new(&data))?;
+        // A field added in version 2 gets its default from a version 1 section
+        let new = sections.load(NEW, |reader| {
+            let value = reader.read_u8()?;
+            let added = match reader.version() >= 2 {
+                true => reader.read_u8()?,
+                false => 0xFF,
+            };
+            Ok((value, added))
+        })?;
+        assert_eq!(new, (0x12, 0xFF));
+        assert_eq!(sections.load(OLD, |reader| reader.read_u8())?, 0x78);
+
+        let missing = sections.load(Section::new(b"GONE", 1), |reader| reader.read_u8());
+        assert_eq!(missing.map_err(|e| e.kind()), Err(ErrorKind::InvalidData));
+        let trailing = sections.load(Section::new(b"NEXT", 1), |reader| reader.read_u8());
+        assert_eq!(trailing.map_err(|e| e.kind()), Err(ErrorKind::InvalidData));
+        let newer = sections.load(Section::new(b"NEXT", 0), |reader| reader.read_u16());
+        assert_eq!(newer.map_err(|e| e.kind()), Err(ErrorKind::InvalidData));
+        sections.finish()
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut reader = StateReader::new(&[0x01]);
+
+        assert_eq!(reader.read_u16().map_err(|e| e.kind()), Err(ErrorKind::UnexpectedEof));
+    }
+}
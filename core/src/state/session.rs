@@ -0,0 +1,326 @@
+use crate::Machine;
+use crate::state::{StateReader, StateWriter, invalid_data};
+use std::fs;
+use std::io::Error;
+use std::path::Path;
+
+/// Frames between two snapshots of a recorded session
+pub const DEFAULT_SNAPSHOT_INTERVAL: u32 = 60;
+
+const FILE_MAGIC: &[u8; 4] = b"GBSN";
+const FILE_VERSION: u8 = 2;
+
+/// Recording of a play session: the pressed buttons of every frame and a machine snapshot every
+/// `snapshot_interval` frames, so any frame is rebuilt by replaying from the nearest snapshot.
+///
+/// Frame `n` is the state at the start of the `n`th recorded frame, its frame buffer shows the
+/// previous frame.
+pub struct Session {
+    title: String,
+    /// Seed of the recorded machine, see [`Machine::seed`]
+    seed: u64,
+    snapshot_interval: u32,
+    /// Pressed buttons of each frame, see [`Machine::pressed_buttons`]
+    inputs: Vec<u8>,
+    /// Machine states with their frame, in frame order
+    snapshots: Vec<(u32, Vec<u8>)>,
+    /// The machine state jumped (reset, state load), the next frame cannot be replayed
+    discontinuity: bool,
+}
+
+impl Session {
+    pub(crate) fn new(title: &str, seed: u64, snapshot_interval: u32) -> Self {
+        Self {
+            title: title.to_string(),
+            seed,
+            snapshot_interval: snapshot_interval.max(1),
+            inputs: vec![],
+            snapshots: vec![],
+            discontinuity: true,
+        }
+    }
+
+    /// Game title of the recorded cartridge
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.inputs.len() as u32
+    }
+
+    /// Pressed buttons of each frame, see [`Machine::pressed_buttons`]
+    pub fn inputs(&self) -> &[u8] {
+        &self.inputs
+    }
+
+    /// Last frame with a snapshot of the current inputs. An edit drops the snapshots after the edited frame,
+    /// a seek past the greenzone replays from its end and takes the missing snapshots on the way.
+    pub fn greenzone(&self) -> u32 {
+        self.snapshots.last().map_or(0, |(frame, _)| *frame)
+    }
+
+    /// Replace the buttons pressed on `frame`
+    pub fn set_input(&mut self, frame: u32, pressed: u8) {
+        if let Some(input) = self.inputs.get_mut(frame as usize)
+            && *input != pressed
+        {
+            *input = pressed;
+            self.invalidate(frame);
+        }
+    }
+
+    /// Insert a frame pressing `pressed` before `frame` (after the last one past the end), the next frames
+    /// happen one frame later
+    pub fn insert_frame(&mut self, frame: u32, pressed: u8) {
+        let frame = frame.min(self.frame_count());
+        self.inputs.insert(frame as usize, pressed);
+        self.invalidate(frame);
+    }
+
+    /// Remove `frame`, the next frames happen one frame earlier
+    pub fn remove_frame(&mut self, frame: u32) {
+        if frame < self.frame_count() {
+            self.inputs.remove(frame as usize);
+            self.invalidate(frame);
+        }
+    }
+
+    /// Drop `frame` and the next ones, the recording goes on from the state of `frame`
+    pub(crate) fn branch(&mut self, frame: u32) {
+        self.inputs.truncate(frame as usize);
+        self.invalidate(frame);
+        self.discontinuity = false;
+    }
+
+    /// Drop the snapshots after `frame`, the state at the start of `frame` does not depend on its inputs
+    fn invalidate(&mut self, frame: u32) {
+        let frame_count = self.frame_count();
+        self.snapshots
+            .retain(|(start, _)| *start == 0 || (*start <= frame && *start < frame_count));
+    }
+
+    /// Account for a frame about to start
+    pub(crate) fn record_frame(&mut self, machine: &Machine) {
+        let frame = self.frame_count();
+        let last_snapshot = self.snapshots.last().map_or(0, |(frame, _)| *frame);
+        if self.discontinuity || frame - last_snapshot >= self.snapshot_interval {
+            self.snapshots.push((frame, machine.save_state()));
+            self.discontinuity = false;
+        }
+        self.inputs.push(machine.pressed_buttons());
+    }
+
+    pub(crate) fn mark_discontinuity(&mut self) {
+        self.discontinuity = true;
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let mut writer = StateWriter::default();
+        writer.write_bytes(FILE_MAGIC);
+        writer.write_u8(FILE_VERSION);
+        writer.write_vec(self.title.as_bytes());
+        writer.write_u64(self.seed);
+        writer.write_u32(self.snapshot_interval);
+        writer.write_vec(&self.inputs);
+        writer.write_u32(self.snapshots.len() as u32);
+        for (frame, state) in &self.snapshots {
+            writer.write_u32(*frame);
+            writer.write_vec(state);
+        }
+        fs::write(path, writer.into_inner())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let data = fs::read(path)?;
+        let mut reader = StateReader::new(&data);
+
+        let mut magic = [0u8; 4];
+        reader.read_bytes(&mut magic)?;
+        if &magic != FILE_MAGIC {
+            return Err(invalid_data("bad session magic"));
+        }
+        if reader.read_u8()? != FILE_VERSION {
+            return Err(invalid_data("unsupported session version"));
+        }
+
+        let title = String::from_utf8_lossy(&reader.read_vec()?).into_owned();
+        let seed = reader.read_u64()?;
+        let snapshot_interval = reader.read_u32()?.max(1);
+        let inputs = reader.read_vec()?;
+        let mut snapshots: Vec<(u32, Vec<u8>)> = vec![];
+        for _ in 0..reader.read_u32()? {
+            let frame = reader.read_u32()?;
+            // A session emptied by the editor keeps its first snapshot
+            let past_end = frame > 0 && frame as usize >= inputs.len();
+            if past_end || snapshots.last().is_some_and(|(last, _)| *last >= frame) {
+                return Err(invalid_data("session snapshot out of order"));
+            }
+            snapshots.push((frame, reader.read_vec()?));
+        }
+        if snapshots.first().is_some_and(|(frame, _)| *frame != 0) || (snapshots.is_empty() && !inputs.is_empty()) {
+            return Err(invalid_data("session without a first snapshot"));
+        }
+
+        Ok(Self {
+            title,
+            seed,
+            snapshot_interval,
+            inputs,
+            snapshots,
+            discontinuity: true,
+        })
+    }
+
+    /// Put `machine` in the state of `frame` (clamped to the last one), replayed from the nearest
+    /// previous snapshot. The machine must run the recorded cartridge.
+    pub fn seek(&mut self, machine: &mut Machine, frame: u32) -> Result<(), Error> {
+        self.rebuild(machine, frame.min(self.frame_count().saturating_sub(1)))
+    }
+
+    /// [`Session::seek`] up to the state after the last frame
+    pub(crate) fn rebuild(&mut self, machine: &mut Machine, frame: u32) -> Result<(), Error> {
+        let index = self.snapshots.partition_point(|(start, _)| *start <= frame);
+        let Some((start, state)) = index.checked_sub(1).map(|index| &self.snapshots[index]) else {
+            return Err(invalid_data("empty session"));
+        };
+        let start = *start;
+        machine.load_state(state)?;
+
+        // The recorded inputs went through the turbo already
+        let turbo = std::mem::take(machine.turbo_mut());
+        let result = self.replay(machine, start, index, frame);
+        *machine.turbo_mut() = turbo;
+        result
+    }
+
+    /// Run the frames from `start` to `frame` with their recorded inputs. The snapshots missing on the way
+    /// are inserted at `index`, none lies between `start` and `frame`.
+    fn replay(&mut self, machine: &mut Machine, start: u32, mut index: usize, frame: u32) -> Result<(), Error> {
+        let mut last_snapshot = start;
+        for current in start..frame {
+            if current - last_snapshot >= self.snapshot_interval {
+                self.snapshots.insert(index, (current, machine.save_state()));
+                index += 1;
+                last_snapshot = current;
+            }
+            machine.set_pressed_buttons(self.inputs[current as usize]);
+            // Breakpoints stop the frame early, run until its end
+            while !machine
+                .step_frame()
+                .map_err(|e| Error::other(e.to_string()))?
+                .frame_completed
+            {}
+        }
+        if let Some(&input) = self.inputs.get(frame as usize) {
+            machine.set_pressed_buttons(input);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::JoypadButton;
+    use crate::tests::temp::TempDir;
+
+    #[test]
+    fn test_seek_replays_inputs() -> Result<(), Box<dyn std::error::Error>> {
+        let rom = crate::TestRom::new()
+            .code(&[0x3E, 0x20, 0xE0, 0x00]) // LD A,$20; LDH (P1),A
+            .code(&[0xF0, 0x00]) // LDH A,(P1)
+            .code(&[0xE0, 0x80]) // LDH ($80),A
+            .code(&[0x18, 0xF6]) // JR -10
+            .build();
+        let mut machine = Machine::builder().cartridge_bytes(rom).seed(7).build()?;
+        machine.start_session_recording(4);
+
+        let mut hashes = vec![];
+        for frame in 0..10 {
+            if frame == 6 {
+                machine.button_pressed(JoypadButton::Right);
+            }
+            hashes.push(machine.state_hash());
+            machine.step_frame()?;
+        }
+        let session = machine.stop_session_recording().expect("recording");
+        assert_eq!(session.frame_count(), 10);
+        assert_eq!(session.snapshots.len(), 3);
+
+        let dir = TempDir::new("session")?;
+        let path = dir.join("session.gbsn");
+        session.save(&path)?;
+        let mut session = Session::load(&path)?;
+        assert_eq!(session.seed(), 7);
+
+        machine.button_released(JoypadButton::Right);
+        for frame in [9, 2, 7, 5] {
+            session.seek(&mut machine, frame)?;
+            assert_eq!(machine.state_hash(), hashes[frame as usize], "frame {frame}");
+            machine.step_frame()?;
+            assert_eq!(machine.peek(0xFF80), if frame >= 6 { 0xEE } else { 0xEF });
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_edit_and_branch() -> Result<(), Box<dyn std::error::Error>> {
+        let rom = crate::TestRom::new()
+            .code(&[0x3E, 0x20, 0xE0, 0x00]) // LD A,$20; LDH (P1),A
+            .code(&[0xF0, 0x00]) // LDH A,(P1)
+            .code(&[0xE0, 0x80]) // LDH ($80),A
+            .code(&[0x18, 0xF6]) // JR -10
+            .build();
+        let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+        machine.start_session_recording(4);
+        for _ in 0..10 {
+            machine.step_frame()?;
+        }
+        let mut session = machine.stop_session_recording().expect("recording");
+        assert_eq!(session.greenzone(), 8);
+
+        let right = 1 << JoypadButton::Right.bit();
+        session.set_input(5, right);
+        assert_eq!(session.greenzone(), 4);
+        session.insert_frame(2, right);
+        assert_eq!((session.frame_count(), session.greenzone()), (11, 0));
+        session.remove_frame(2);
+
+        // The edited frames are replayed, the snapshots come back on the way
+        for frame in 0..10 {
+            session.seek(&mut machine, frame)?;
+            machine.step_frame()?;
+            let expected = if frame == 5 { 0xEE } else { 0xEF };
+            assert_eq!(machine.peek(0xFF80), expected, "frame {frame}");
+        }
+        assert_eq!(session.greenzone(), 8);
+
+        machine.resume_session_recording(session, 6)?;
+        machine.button_pressed(JoypadButton::A);
+        machine.step_frame()?;
+        machine.step_frame()?;
+        let session = machine.stop_session_recording().expect("recording");
+        let a = 1 << JoypadButton::A.bit();
+        assert_eq!(session.inputs()[4..], [0, right, a, a]);
+        assert_eq!(session.greenzone(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_discontinuity_takes_snapshot() {
+        let mut machine = Machine::builder().build().unwrap();
+        machine.start_session_recording(60);
+        machine.step_frame().unwrap();
+        machine.reset();
+        machine.step_frame().unwrap();
+
+        let session = machine.stop_session_recording().unwrap();
+        let frames: Vec<u32> = session.snapshots.iter().map(|(frame, _)| *frame).collect();
+        assert_eq!(frames, [0, 1]);
+    }
+}
@@ -0,0 +1,166 @@
+use crate::Machine;
+use crate::state::{StateReader, StateWriter, file_stem, invalid_data};
+use crate::video::{SCREEN_WIDTH, downscale};
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Number of save state slots per game
+pub const SLOT_COUNT: usize = 10;
+
+/// Thumbnails are the screen downscaled by 2, one shade id per pixel
+pub const THUMBNAIL_WIDTH: usize = 80;
+pub const THUMBNAIL_HEIGHT: usize = 72;
+
+const FILE_MAGIC: &[u8; 4] = b"GBSF";
+const FILE_VERSION: u8 = 1;
+
+/// Header of a saved slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotInfo {
+    pub slot: usize,
+    pub timestamp: SystemTime,
+    /// `THUMBNAIL_WIDTH` x `THUMBNAIL_HEIGHT` shade ids
+    pub thumbnail: Vec<u8>,
+}
+
+/// Save state slots of a game, stored as `<directory>/<game>.ss<slot>`.
+///
+/// Each file starts with a header (timestamp and thumbnail) followed by the machine state.
+pub struct SaveSlots {
+    directory: PathBuf,
+    game: String,
+}
+
+impl SaveSlots {
+    pub fn new(directory: impl Into<PathBuf>, game: &str) -> Self {
+        Self {
+            directory: directory.into(),
+            game: file_stem(game),
+        }
+    }
+
+    /// Slots of the cartridge inserted in the machine
+    pub fn for_machine(directory: impl Into<PathBuf>, machine: &Machine) -> Self {
+        Self::new(directory, machine.cartridge().title())
+    }
+
+    pub fn path(&self, slot: usize) -> PathBuf {
+        self.directory.join(format!("{}.ss{slot}", self.game))
+    }
+
+    pub fn save(&self, slot: usize, machine: &Machine) -> Result<(), Error> {
+        check_slot(slot)?;
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        let mut writer = StateWriter::default();
+        writer.write_bytes(FILE_MAGIC);
+        writer.write_u8(FILE_VERSION);
+        writer.write_u64(timestamp.as_secs());
+        writer.write_bytes(&thumbnail(machine.frame()));
+        writer.write_vec(&machine.save_state());
+
+        fs::create_dir_all(&self.directory)?;
+        fs::write(self.path(slot), writer.into_inner())
+    }
+
+    pub fn load(&self, slot: usize, machine: &mut Machine) -> Result<(), Error> {
+        check_slot(slot)?;
+
+        let data = fs::read(self.path(slot))?;
+        let mut reader = StateReader::new(&data);
+        read_header(slot, &mut reader)?;
+
+        machine.load_state(&reader.read_vec()?)
+    }
+
+    /// Header of the slot, `None` when the slot is empty
+    pub fn info(&self, slot: usize) -> Result<Option<SlotInfo>, Error> {
+        check_slot(slot)?;
+
+        let data = match fs::read(self.path(slot)) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        read_header(slot, &mut StateReader::new(&data)).map(Some)
+    }
+
+    /// Headers of all slots, unreadable slots are reported as empty
+    pub fn list(&self) -> Vec<Option<SlotInfo>> {
+        (0..SLOT_COUNT).map(|slot| self.info(slot).ok().flatten()).collect()
+    }
+}
+
+fn check_slot(slot: usize) -> Result<(), Error> {
+    if slot >= SLOT_COUNT {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("invalid slot {slot}")));
+    }
+    Ok(())
+}
+
+fn read_header(slot: usize, reader: &mut StateReader) -> Result<SlotInfo, Error> {
+    let mut magic = [0u8; 4];
+    reader.read_bytes(&mut magic)?;
+    if &magic != FILE_MAGIC {
+        return Err(invalid_data("bad slot file magic"));
+    }
+    if reader.read_u8()? != FILE_VERSION {
+        return Err(invalid_data("unsupported slot file version"));
+    }
+
+    let timestamp = UNIX_EPOCH + Duration::from_secs(reader.read_u64()?);
+    let mut thumbnail = vec![0u8; THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT];
+    reader.read_bytes(&mut thumbnail)?;
+
+    Ok(SlotInfo {
+        slot,
+        timestamp,
+        thumbnail,
+    })
+}
+
+fn thumbnail(frame: &[u8]) -> Vec<u8> {
+    downscale(frame, SCREEN_WIDTH, SCREEN_WIDTH / THUMBNAIL_WIDTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp::TempDir;
+
+    #[test]
+    fn test_game_name_sanitized() {
+        let slots = SaveSlots::new("saves", "POKEMON RED");
+        assert_eq!(slots.path(3), PathBuf::from("saves/POKEMON_RED.ss3"));
+
+        let slots = SaveSlots::new("saves", "");
+        assert_eq!(slots.path(0), PathBuf::from("saves/untitled.ss0"));
+    }
+
+    #[test]
+    fn test_save_and_load_slot() -> Result<(), Error> {
+        let dir = TempDir::new("slots")?;
+        let mut machine = Machine::builder().cartridge_bytes(vec![0u8; 0x8000]).build()?;
+        let slots = SaveSlots::for_machine(dir.path(), &machine);
+
+        assert_eq!(slots.info(2)?, None);
+        slots.save(2, &machine)?;
+        let state = machine.save_state();
+
+        let info = slots.info(2)?.expect("slot 2 is saved");
+        assert_eq!(info.thumbnail.len(), THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT);
+        assert!(info.timestamp > UNIX_EPOCH);
+        assert_eq!(slots.list().iter().filter(|s| s.is_some()).count(), 1);
+
+        machine.step().map_err(|e| Error::other(e.to_string()))?;
+        slots.load(2, &mut machine)?;
+        assert_eq!(machine.save_state(), state);
+
+        assert!(slots.save(SLOT_COUNT, &machine).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::VecDeque;
+
+/// Frames between two keyframes by default, a state is rebuilt from at most this many deltas
+pub const DEFAULT_KEYFRAME_INTERVAL: usize = 30;
+
+struct Snapshot {
+    /// Encoded from zeros instead of from the previous state
+    keyframe: bool,
+    /// Run length encoded XOR with the previous state
+    data: Vec<u8>,
+}
+
+/// In memory history of machine states, e.g. [`Machine::save_state`](crate::Machine::save_state) of each
+/// frame for a rewind.
+///
+/// Every `keyframe_interval` states, and whenever the state size changes, a keyframe is stored, the states
+/// in between are stored as their XOR with the previous one. Both are run length encoded: the memory of a
+/// frame barely differs from the previous one so a delta is mostly zeros. The oldest states are dropped
+/// past `capacity`.
+pub struct SnapshotStream {
+    keyframe_interval: usize,
+    capacity: usize,
+    snapshots: VecDeque<Snapshot>,
+    /// Decoded last state, the reference of the next delta
+    last: Vec<u8>,
+    /// States since the last keyframe
+    since_keyframe: usize,
+}
+
+impl SnapshotStream {
+    pub fn new(keyframe_interval: usize, capacity: usize) -> Self {
+        Self {
+            keyframe_interval: keyframe_interval.max(1),
+            capacity: capacity.max(1),
+            snapshots: VecDeque::new(),
+            last: vec![],
+            since_keyframe: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Encoded size of the stored states in bytes
+    pub fn byte_size(&self) -> usize {
+        self.snapshots.iter().map(|snapshot| snapshot.data.len()).sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.last.clear();
+    }
+
+    /// Append a state, the oldest one is dropped when the stream is full
+    pub fn push(&mut self, state: &[u8]) {
+        let keyframe = self.snapshots.is_empty()
+            || self.since_keyframe + 1 >= self.keyframe_interval
+            || state.len() != self.last.len();
+        let data = match keyframe {
+            true => encode(state, &[]),
+            false => encode(state, &self.last),
+        };
+        self.snapshots.push_back(Snapshot { keyframe, data });
+        self.since_keyframe = if keyframe { 0 } else { self.since_keyframe + 1 };
+        self.last.clear();
+        self.last.extend_from_slice(state);
+
+        if self.snapshots.len() > self.capacity {
+            self.drop_oldest();
+        }
+    }
+
+    /// State `index`, 0 being the oldest one kept
+    pub fn get(&self, index: usize) -> Option<Vec<u8>> {
+        if index >= self.snapshots.len() {
+            return None;
+        }
+        match index == self.snapshots.len() - 1 {
+            true => Some(self.last.clone()),
+            false => Some(self.decode(index)),
+        }
+    }
+
+    /// Remove and return the newest state, stepping back in time
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        self.snapshots.pop_back()?;
+        let state = std::mem::take(&mut self.last);
+        if let Some(last) = self.len().checked_sub(1) {
+            self.last = self.decode(last);
+            self.since_keyframe = self
+                .snapshots
+                .iter()
+                .rev()
+                .take_while(|snapshot| !snapshot.keyframe)
+                .count();
+        }
+        Some(state)
+    }
+
+    /// Replay the deltas from the keyframe before `index`
+    fn decode(&self, index: usize) -> Vec<u8> {
+        let start = (0..=index)
+            .rev()
+            .find(|index| self.snapshots[*index].keyframe)
+            .unwrap_or(0);
+        let mut state = vec![];
+        for snapshot in self.snapshots.range(start..=index) {
+            decode(&snapshot.data, &mut state, snapshot.keyframe);
+        }
+        state
+    }
+
+    /// The next state becomes a keyframe when the oldest one goes
+    fn drop_oldest(&mut self) {
+        let Some(oldest) = self.snapshots.pop_front() else {
+            return;
+        };
+        if let Some(next) = self.snapshots.front_mut()
+            && !next.keyframe
+        {
+            let mut state = vec![];
+            decode(&oldest.data, &mut state, true);
+            decode(&next.data, &mut state, false);
+            *next = Snapshot {
+                keyframe: true,
+                data: encode(&state, &[]),
+            };
+        }
+    }
+}
+
+/// XOR of `state` and `reference` (zeros when empty) as `<zero run> <literal count> <literals>` groups,
+/// the counts in LEB128
+fn encode(state: &[u8], reference: &[u8]) -> Vec<u8> {
+    let xor = |index: usize| state[index] ^ reference.get(index).copied().unwrap_or(0);
+    let mut data = vec![];
+    let mut index = 0;
+    while index < state.len() {
+        let zeros = (index..state.len()).take_while(|index| xor(*index) == 0).count();
+        index += zeros;
+        // A literal run ends at two zeros in a row, a single zero is cheaper inside the run
+        let mut end = index;
+        while end < state.len() && (xor(end) != 0 || (end + 1 < state.len() && xor(end + 1) != 0)) {
+            end += 1;
+        }
+        write_varint(&mut data, zeros);
+        write_varint(&mut data, end - index);
+        data.extend((index..end).map(xor));
+        index = end;
+    }
+    data
+}
+
+/// Apply encoded `data` to `state`, replacing it for a keyframe
+fn decode(data: &[u8], state: &mut Vec<u8>, keyframe: bool) {
+    if keyframe {
+        state.clear();
+    }
+    let (mut position, mut index) = (0, 0);
+    while position < data.len() {
+        let zeros = read_varint(data, &mut position);
+        let literals = read_varint(data, &mut position);
+        index += zeros;
+        let end = index + literals;
+        if state.len() < end {
+            state.resize(end, 0);
+        }
+        for (byte, xor) in state[index..end].iter_mut().zip(&data[position..position + literals]) {
+            *byte ^= xor;
+        }
+        position += literals;
+        index = end;
+    }
+    if keyframe {
+        state.resize(index, 0);
+    }
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        data.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    data.push(value as u8);
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    for shift in (0..).step_by(7) {
+        let byte = data[*position];
+        *position += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Machine;
+    use crate::rng::splitmix64;
+
+    #[test]
+    fn test_codec_round_trip() {
+        let reference = [1, 2, 3, 4, 5, 6, 7, 8];
+        for state in [vec![], vec![0; 300], vec![1, 2, 0, 4, 5, 0, 0, 8], reference.to_vec()] {
+            let mut decoded = vec![];
+            decode(&encode(&state, &[]), &mut decoded, true);
+            assert_eq!(decoded, state);
+            if state.len() == reference.len() {
+                let mut decoded = reference.to_vec();
+                decode(&encode(&state, &reference), &mut decoded, false);
+                assert_eq!(decoded, state);
+            }
+        }
+        // A delta of identical states is a single group
+        assert_eq!(encode(&reference, &reference), [8, 0]);
+    }
+
+    /// Random states with sparse changes and size changes, every state kept must come back unchanged
+    #[test]
+    fn test_fuzz_round_trip() {
+        let mut seed = 0x534E_4150;
+        let mut random = || splitmix64(&mut seed) as usize;
+
+        for _ in 0..20 {
+            let capacity = 1 + random() % 40;
+            let mut stream = SnapshotStream::new(1 + random() % 10, capacity);
+            let mut states: Vec<Vec<u8>> = vec![];
+            let mut state = vec![0u8; random() % 2000];
+            for _ in 0..100 {
+                match random() % 10 {
+                    0 => state.resize(random() % 2000, random() as u8),
+                    1 => state.iter_mut().for_each(|byte| *byte = random() as u8),
+                    _ => {
+                        for _ in 0..random() % 20 {
+                            if !state.is_empty() {
+                                let index = random() % state.len();
+                                state[index] = random() as u8;
+                            }
+                        }
+                    }
+                }
+                stream.push(&state);
+                states.push(state.clone());
+                if random() % 8 == 0 {
+                    assert_eq!(stream.pop().as_ref(), states.pop().as_ref());
+                }
+            }
+
+            let kept = &states[states.len() - stream.len()..];
+            assert!(stream.len() <= capacity);
+            for (index, state) in kept.iter().enumerate() {
+                assert_eq!(stream.get(index).as_ref(), Some(state), "state {index}");
+            }
+            assert_eq!(stream.get(kept.len()), None);
+        }
+    }
+
+    #[test]
+    fn test_frame_delta_size() -> Result<(), Box<dyn std::error::Error>> {
+        let rom = crate::TestRom::new()
+            .code(&[0x21, 0x00, 0xC0]) // LD HL,$C000
+            .code(&[0x34]) // INC (HL)
+            .code(&[0x23]) // INC HL
+            .code(&[0x18, 0xFC]) // JR -4
+            .build();
+        let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+        let mut stream = SnapshotStream::new(DEFAULT_KEYFRAME_INTERVAL, 10);
+        for _ in 0..3 {
+            machine.step_frame()?;
+            stream.push(&machine.save_state());
+        }
+
+        let delta = stream.snapshots[2].data.len();
+        assert!(delta < 10 * 1024, "{delta} bytes");
+        assert!(delta < stream.snapshots[0].data.len());
+        assert_eq!(stream.pop(), Some(machine.save_state()));
+        Ok(())
+    }
+}
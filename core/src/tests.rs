@@ -2,14 +2,52 @@
 pub(crate) mod bus {
     use crate::CpuBus;
     use crate::bus::{BusIO, InterruptBus};
+    use std::cell::RefCell;
+    use std::ops::RangeInclusive;
 
+    type ReadHook = Box<dyn FnMut(&[u8; 0x10000], u16) -> u8>;
+    type WriteHook = Box<dyn FnMut(&mut [u8; 0x10000], u16, u8)>;
+
+    /// Flat 64 KiB memory, with optional hooks simulating the side effects of the IO registers.
+    ///
+    /// The hooks only see the accesses of the CPU ([`BusIO::read_byte`], [`BusIO::write_byte`] and the
+    /// word accesses), the internal accesses of the other components go to the memory.
     pub struct TestBus {
         pub memory: [u8; 0x10000],
+        read_hooks: Vec<(RangeInclusive<u16>, RefCell<ReadHook>)>,
+        write_hooks: Vec<(RangeInclusive<u16>, WriteHook)>,
     }
 
     impl Default for TestBus {
         fn default() -> Self {
-            Self { memory: [0; 0x10000] }
+            Self {
+                memory: [0; 0x10000],
+                read_hooks: vec![],
+                write_hooks: vec![],
+            }
+        }
+    }
+
+    impl TestBus {
+        /// Reads in `range` return the value of `hook`, called with the memory and the address.
+        /// The last hook added wins when ranges overlap.
+        pub fn on_read(
+            &mut self,
+            range: RangeInclusive<u16>,
+            hook: impl FnMut(&[u8; 0x10000], u16) -> u8 + 'static,
+        ) -> &mut Self {
+            self.read_hooks.push((range, RefCell::new(Box::new(hook))));
+            self
+        }
+
+        /// Writes in `range` call `hook` with the memory, the address and the byte, instead of storing it
+        pub fn on_write(
+            &mut self,
+            range: RangeInclusive<u16>,
+            hook: impl FnMut(&mut [u8; 0x10000], u16, u8) + 'static,
+        ) -> &mut Self {
+            self.write_hooks.push((range, Box::new(hook)));
+            self
         }
     }
 
@@ -17,26 +55,31 @@ pub(crate) mod bus {
 
     impl BusIO for TestBus {
         fn read_byte(&self, address: u16) -> u8 {
+            match self.read_hooks.iter().rev().find(|(range, _)| range.contains(&address)) {
+                Some((_, hook)) => hook.borrow_mut()(&self.memory, address),
+                None => self.memory[address as usize],
+            }
+        }
+
+        fn read_internal_byte(&self, address: u16) -> u8 {
             self.memory[address as usize]
         }
 
         fn write_byte(&mut self, address: u16, byte: u8) {
-            self.memory[address as usize] = byte;
+            match self
+                .write_hooks
+                .iter_mut()
+                .rev()
+                .find(|(range, _)| range.contains(&address))
+            {
+                Some((_, hook)) => hook(&mut self.memory, address, byte),
+                None => self.memory[address as usize] = byte,
+            }
         }
 
         fn write_internal_byte(&mut self, address: u16, byte: u8) {
             self.memory[address as usize] = byte;
         }
-
-        fn read_word(&self, address: u16) -> u16 {
-            (self.memory[address as usize] as u16)  // LSB first
-                | (self.memory[address as usize + 1] as u16) << 8 // MSB second
-        }
-
-        fn write_word(&mut self, address: u16, word: u16) {
-            self.memory[address as usize] = word as u8;
-            self.memory[address as usize + 1] = (word >> 8) as u8;
-        }
     }
 
     impl CpuBus for TestBus {}
@@ -52,5 +95,541 @@ pub(crate) mod bus {
         // Test word operations
         bus.write_word(0x4321, 0xABCD);
         assert_eq!(bus.read_word(0x4321), 0xABCD);
+
+        // The MSB of a word at $FFFF is at $0000
+        bus.write_word(0xFFFF, 0x1234);
+        assert_eq!((bus.memory[0xFFFF], bus.memory[0x0000]), (0x34, 0x12));
+        assert_eq!(bus.read_word(0xFFFF), 0x1234);
+    }
+
+    #[test]
+    fn test_bus_hooks() {
+        let mut bus = TestBus::default();
+        // LY advancing on each read, serial transfers echoing the byte sent
+        let mut ly = 0u8;
+        bus.on_read(0xFF44..=0xFF44, move |_, _| {
+            ly = (ly + 1) % 154;
+            ly
+        })
+        .on_write(0xFF02..=0xFF02, |memory, _, byte| {
+            if byte & 0x80 != 0 {
+                memory[0xFF0F] |= 0x08;
+            }
+        });
+
+        assert_eq!(bus.read_byte(0xFF44), 1);
+        assert_eq!(bus.read_byte(0xFF44), 2);
+        bus.write_byte(0xFF02, 0x81);
+        assert_eq!(bus.memory[0xFF02], 0x00);
+        assert_eq!(bus.read_byte(0xFF0F), 0x08);
+        // Internal accesses skip the hooks
+        bus.write_internal_byte(0xFF44, 0x90);
+        assert_eq!(bus.read_internal_byte(0xFF44), 0x90);
+        assert_eq!(bus.read_byte(0xFF44), 3);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod temp {
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static NEXT_ID: AtomicU32 = AtomicU32::new(0);
+
+    /// Directory of a test, unique to the process and the call so tests run in parallel, removed with its
+    /// content on drop
+    pub struct TempDir(PathBuf);
+
+    impl TempDir {
+        pub fn new(name: &str) -> std::io::Result<Self> {
+            let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+            let path = std::env::temp_dir().join(format!("gbemu-{name}-{}-{id}", std::process::id()));
+            std::fs::create_dir_all(&path)?;
+            Ok(Self(path))
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.0
+        }
+
+        pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+            self.0.join(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-roms"))]
+pub(crate) mod rom {
+    const LOGO: [u8; 48] = [
+        0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D, //
+        0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99, //
+        0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E, //
+    ];
+    const BANK_SIZE: usize = 0x4000;
+
+    /// Assemble a tiny rom with a valid header, to test the emulator without external roms.
+    ///
+    /// The entry point jumps to [`TestRom::START`], where [`TestRom::code`] appends instructions.
+    pub struct TestRom {
+        rom: Vec<u8>,
+        cursor: usize,
+    }
+
+    impl Default for TestRom {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl TestRom {
+        /// Address of the first instruction after the header
+        pub const START: usize = 0x0150;
+
+        /// 32KiB rom only cartridge titled `TEST`
+        pub fn new() -> Self {
+            let mut rom = vec![0u8; 2 * BANK_SIZE];
+            rom[0x0100..0x0104].copy_from_slice(&[0x00, 0xC3, 0x50, 0x01]); // NOP, JP $0150
+            rom[0x0104..0x0134].copy_from_slice(&LOGO);
+            Self {
+                rom,
+                cursor: Self::START,
+            }
+            .title("TEST")
+        }
+
+        /// Title of the header, truncated to 16 characters
+        pub fn title(mut self, title: &str) -> Self {
+            let mut bytes = [0u8; 16];
+            let len = title.len().min(bytes.len());
+            bytes[..len].copy_from_slice(&title.as_bytes()[..len]);
+            self.rom[0x0134..0x0144].copy_from_slice(&bytes);
+            self
+        }
+
+        /// CGB flag ($0143), `0x80` for a game also running on DMG, `0xC0` for CGB only.
+        /// Set after [`TestRom::title`], which covers it.
+        pub fn cgb_flag(mut self, flag: u8) -> Self {
+            self.rom[0x0143] = flag;
+            self
+        }
+
+        /// Cartridge type byte ($0147), e.g. `0x01` for MBC1
+        pub fn cartridge_type(mut self, cartridge_type: u8) -> Self {
+            self.rom[0x0147] = cartridge_type;
+            self
+        }
+
+        /// Number of 16KiB rom banks, a power of two from 2 to 512
+        pub fn rom_banks(mut self, count: usize) -> Self {
+            assert!(
+                count.is_power_of_two() && (2..=512).contains(&count),
+                "invalid rom bank count {count}"
+            );
+            self.rom.resize(count * BANK_SIZE, 0);
+            self.rom[0x0148] = count.trailing_zeros() as u8 - 1;
+            self
+        }
+
+        /// Ram size byte ($0149), e.g. `0x02` for 8KiB
+        pub fn ram_size(mut self, ram_size: u8) -> Self {
+            self.rom[0x0149] = ram_size;
+            self
+        }
+
+        /// Move the cursor to an offset of the rom image, bank `n` starts at `n * $4000`.
+        /// Used to place interrupt handlers (`$0040`...) or data in switchable banks.
+        pub fn org(mut self, offset: usize) -> Self {
+            self.cursor = offset;
+            self
+        }
+
+        /// Append bytes at the cursor
+        pub fn code(mut self, bytes: &[u8]) -> Self {
+            self.rom[self.cursor..self.cursor + bytes.len()].copy_from_slice(bytes);
+            self.cursor += bytes.len();
+            self
+        }
+
+        /// Rom image with its header and global checksums
+        pub fn build(mut self) -> Vec<u8> {
+            let header = self.rom[0x0134..0x014D]
+                .iter()
+                .fold(0u8, |checksum, byte| checksum.wrapping_sub(*byte).wrapping_sub(1));
+            self.rom[0x014D] = header;
+
+            self.rom[0x014E..0x0150].fill(0);
+            let global = self
+                .rom
+                .iter()
+                .fold(0u16, |checksum, byte| checksum.wrapping_add(*byte as u16));
+            self.rom[0x014E..0x0150].copy_from_slice(&global.to_be_bytes());
+            self.rom
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::Machine;
+        use std::error::Error;
+
+        #[test]
+        fn test_header() -> Result<(), Box<dyn Error>> {
+            let rom = TestRom::new().title("MICRO").cartridge_type(0x01).rom_banks(8).build();
+
+            assert_eq!(rom.len(), 8 * BANK_SIZE);
+            assert_eq!(rom[0x0148], 0x02);
+            assert_eq!(rom[0x014D], 0x6A);
+            let machine = Machine::builder().cartridge_bytes(rom).build()?;
+            assert_eq!(machine.cartridge().title(), "MICRO");
+            Ok(())
+        }
+
+        #[test]
+        fn test_vblank_interrupt() -> Result<(), Box<dyn Error>> {
+            let rom = TestRom::new()
+                .code(&[0x3E, 0x01]) // LD A,$01
+                .code(&[0xE0, 0xFF]) // LDH ($FF),A ; IE = VBlank
+                .code(&[0xFB]) // EI
+                .code(&[0x76]) // HALT
+                .code(&[0x18, 0xFD]) // JR -3
+                .org(0x0040)
+                .code(&[0x21, 0x00, 0xC0]) // LD HL,$C000
+                .code(&[0x34]) // INC (HL)
+                .code(&[0xD9]) // RETI
+                .build();
+            let mut machine = Machine::builder()
+                .ram_init(crate::RamInit::Zero)
+                .cartridge_bytes(rom)
+                .build()?;
+
+            for _ in 0..3 {
+                machine.step_frame()?;
+            }
+            assert!((2..=3).contains(&machine.peek(0xC000)));
+            Ok(())
+        }
+
+        #[test]
+        fn test_oam_dma() -> Result<(), Box<dyn Error>> {
+            let rom = TestRom::new()
+                .code(&[0x3E, 0x42]) // LD A,$42
+                .code(&[0xEA, 0x9F, 0xC1]) // LD ($C19F),A
+                .code(&[0x3E, 0xC1]) // LD A,$C1
+                .code(&[0xE0, 0x46]) // LDH ($46),A ; DMA from $C100
+                .code(&[0x18, 0xFE]) // JR -2
+                .build();
+            let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+
+            machine.step_frame()?;
+            assert_eq!(machine.peek(0xFE9F), 0x42);
+            Ok(())
+        }
+
+        #[test]
+        fn test_mbc1_bank_switch() -> Result<(), Box<dyn Error>> {
+            let rom = TestRom::new()
+                .cartridge_type(0x01)
+                .rom_banks(4)
+                .code(&[0x3E, 0x02]) // LD A,$02
+                .code(&[0xEA, 0x00, 0x20]) // LD ($2000),A ; select bank 2
+                .code(&[0xFA, 0x00, 0x40]) // LD A,($4000)
+                .code(&[0xEA, 0x00, 0xC0]) // LD ($C000),A
+                .code(&[0x18, 0xFE]) // JR -2
+                .org(2 * BANK_SIZE)
+                .code(&[0xB2])
+                .build();
+            let mut machine = Machine::builder().cartridge_bytes(rom).build()?;
+
+            machine.step_frame()?;
+            assert_eq!(machine.peek(0xC000), 0xB2);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-roms"))]
+pub(crate) mod regress {
+    use crate::Machine;
+    use std::error::Error;
+    use std::fmt::{Display, Formatter};
+    use std::path::Path;
+
+    /// Expected outcome of a rom run headless, a line of `doctor/regress.txt`:
+    /// `<rom> <frames> <frame hash> <address>=<byte>...`.
+    ///
+    /// `-` as frame hash and an address without `=<byte>` are values not recorded yet, see [`RegressCase::run`].
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct RegressCase {
+        /// Relative to the directory of the file
+        pub rom: String,
+        pub frames: u32,
+        /// See [`Machine::frame_hash`]
+        pub frame_hash: Option<u32>,
+        pub memory: Vec<(u16, Option<u8>)>,
+    }
+
+    impl RegressCase {
+        /// Cases of a file, skipping blank and `#` comment lines
+        pub fn load(path: impl AsRef<Path>) -> Result<Vec<Self>, Box<dyn Error>> {
+            std::fs::read_to_string(path)?
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| line.parse())
+                .collect()
+        }
+
+        pub fn is_recorded(&self) -> bool {
+            self.frame_hash.is_some() && self.memory.iter().all(|(_, value)| value.is_some())
+        }
+
+        /// Run the rom in `directory` in deterministic mode, the case with the values observed
+        pub fn run(&self, directory: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+            let mut machine = Machine::builder()
+                .cartridge_path(directory.as_ref().join(&self.rom))
+                .deterministic(true)
+                .build()?;
+            for _ in 0..self.frames {
+                machine.step_frame()?;
+            }
+
+            let memory = self
+                .memory
+                .iter()
+                .map(|(address, _)| (*address, Some(machine.bus().read_byte(*address))))
+                .collect();
+            Ok(Self {
+                frame_hash: Some(machine.frame_hash()),
+                memory,
+                ..self.clone()
+            })
+        }
+    }
+
+    impl std::str::FromStr for RegressCase {
+        type Err = Box<dyn Error>;
+
+        fn from_str(line: &str) -> Result<Self, Self::Err> {
+            let mut fields = line.split_whitespace();
+            let mut next = |name| fields.next().ok_or_else(|| format!("missing {name} in \"{line}\""));
+            let rom = next("rom")?.to_string();
+            let frames = next("frames")?.parse()?;
+            let frame_hash = match next("frame hash")? {
+                "-" => None,
+                hash => Some(u32::from_str_radix(hash, 16)?),
+            };
+            let memory = fields
+                .map(|field| {
+                    let (address, value) = field.split_once('=').unwrap_or((field, ""));
+                    let address = u16::from_str_radix(address, 16)?;
+                    let value = match value {
+                        "" => None,
+                        value => Some(u8::from_str_radix(value, 16)?),
+                    };
+                    Ok((address, value))
+                })
+                .collect::<Result<_, std::num::ParseIntError>>()?;
+            Ok(Self {
+                rom,
+                frames,
+                frame_hash,
+                memory,
+            })
+        }
+    }
+
+    impl Display for RegressCase {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{} {}", self.rom, self.frames)?;
+            match self.frame_hash {
+                Some(hash) => write!(f, " {hash:08X}")?,
+                None => write!(f, " -")?,
+            }
+            for (address, value) in &self.memory {
+                match value {
+                    Some(value) => write!(f, " {address:04X}={value:02X}")?,
+                    None => write!(f, " {address:04X}")?,
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::TestRom;
+        use crate::tests::temp::TempDir;
+
+        #[test]
+        fn test_parse_case() -> Result<(), Box<dyn Error>> {
+            let case: RegressCase = "roms/game.gb 600 0A1B2C3D FFE1=07 C000".parse()?;
+            assert_eq!(case.frames, 600);
+            assert_eq!(case.frame_hash, Some(0x0A1B_2C3D));
+            assert_eq!(case.memory, [(0xFFE1, Some(0x07)), (0xC000, None)]);
+            assert!(!case.is_recorded());
+            assert_eq!(case.to_string(), "roms/game.gb 600 0A1B2C3D FFE1=07 C000");
+
+            assert!("roms/game.gb".parse::<RegressCase>().is_err());
+            assert!("roms/game.gb 600 - C0ZZ".parse::<RegressCase>().is_err());
+            Ok(())
+        }
+
+        #[test]
+        fn test_run_records_values() -> Result<(), Box<dyn Error>> {
+            let directory = TempDir::new("regress")?;
+            let rom = TestRom::new()
+                .code(&[0x3E, 0x42, 0xEA, 0x00, 0xC0]) // LD A,$42; LD ($C000),A
+                .code(&[0x18, 0xFE]) // JR -2
+                .build();
+            std::fs::write(directory.join("test.gb"), rom)?;
+
+            let case: RegressCase = "test.gb 2 - C000".parse()?;
+            let recorded = case.run(directory.path())?;
+            assert!(recorded.is_recorded());
+            assert_eq!(recorded.memory, [(0xC000, Some(0x42))]);
+            assert_eq!(recorded.run(directory.path())?, recorded);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(any(test, feature = "test-roms"))]
+pub(crate) mod verdict {
+    use crate::{CYCLES_PER_FRAME, Machine};
+    use std::error::Error;
+    use std::path::PathBuf;
+
+    /// `LD B,B`, the magic breakpoint mooneye roms stop on once they have a result
+    const LD_B_B: u8 = 0x40;
+    /// B, C, D, E, H and L of a passing mooneye rom, a failing one loads $42 in each
+    const MOONEYE_PASSED: [u8; 6] = [3, 5, 8, 13, 21, 34];
+    const MOONEYE_FAILED: [u8; 6] = [0x42; 6];
+
+    /// Result reported by a test rom: the mooneye register signature or the text blargg roms print on the
+    /// serial port
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum TestVerdict {
+        Passed,
+        Failed(String),
+        /// No result within the frame limit
+        Timeout,
+    }
+
+    impl TestVerdict {
+        /// Verdict of a mooneye rom about to execute `LD B,B`, `None` anywhere else
+        pub fn mooneye(machine: &Machine) -> Option<Self> {
+            let cpu = machine.cpu();
+            if machine.peek(cpu.pc()) != LD_B_B {
+                return None;
+            }
+            let registers = [cpu.b(), cpu.c(), cpu.d(), cpu.e(), cpu.h(), cpu.l()];
+            Some(match registers {
+                MOONEYE_PASSED => Self::Passed,
+                MOONEYE_FAILED => Self::Failed("mooneye failure signature".to_string()),
+                _ => Self::Failed(format!("unexpected registers at LD B,B: {registers:02X?}")),
+            })
+        }
+
+        /// Verdict of the text printed so far by a blargg rom, `None` until it prints `Passed` or `Failed`
+        pub fn blargg(output: &[u8]) -> Option<Self> {
+            let text = String::from_utf8_lossy(output);
+            if text.lines().any(|line| line.trim() == "Passed") {
+                Some(Self::Passed)
+            } else if text.contains("Failed") {
+                Some(Self::Failed(text.trim().replace('\n', " | ")))
+            } else {
+                None
+            }
+        }
+
+        /// Run a mooneye or blargg rom from the end of the boot rom until it reports, at most `max_frames`
+        pub fn run(path: impl Into<PathBuf>, max_frames: u64) -> Result<Self, Box<dyn Error>> {
+            let mut machine = Machine::builder().cartridge_path(path).fast_boot(true).build()?;
+            let mut serial = vec![];
+            // `step` does not complete the frames, count their cycles instead
+            let budget = max_frames * CYCLES_PER_FRAME as u64;
+            while machine.cycles() < budget {
+                if let Some(verdict) = Self::mooneye(&machine) {
+                    return Ok(verdict);
+                }
+                if let Err(e) = machine.step() {
+                    return Ok(Self::Failed(e.to_string()));
+                }
+                // Blargg roms print their verdict one line at a time
+                let output = machine.take_serial_output();
+                let line_ended = output.contains(&b'\n');
+                serial.extend(output);
+                if line_ended && let Some(verdict) = Self::blargg(&serial) {
+                    return Ok(verdict);
+                }
+            }
+            Ok(Self::Timeout)
+        }
+    }
+
+    impl std::fmt::Display for TestVerdict {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                Self::Passed => write!(f, "passed"),
+                Self::Failed(reason) => write!(f, "failed: {reason}"),
+                Self::Timeout => write!(f, "timeout"),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::TestRom;
+        use crate::tests::temp::TempDir;
+
+        #[test]
+        fn test_blargg_output() {
+            assert_eq!(TestVerdict::blargg(b"01-special\n\n"), None);
+            assert_eq!(
+                TestVerdict::blargg(b"01-special\n\n\nPassed\n"),
+                Some(TestVerdict::Passed)
+            );
+            assert_eq!(
+                TestVerdict::blargg(b"02-interrupts\n\nEI\nFailed #2\n"),
+                Some(TestVerdict::Failed("02-interrupts |  | EI | Failed #2".to_string()))
+            );
+        }
+
+        #[test]
+        fn test_mooneye_signature() -> Result<(), Box<dyn Error>> {
+            let directory = TempDir::new("verdict")?;
+            for (registers, expected) in [
+                (MOONEYE_PASSED, TestVerdict::Passed),
+                (
+                    MOONEYE_FAILED,
+                    TestVerdict::Failed("mooneye failure signature".to_string()),
+                ),
+            ] {
+                let [b, c, d, e, h, l] = registers;
+                let rom = TestRom::new()
+                    .code(&[0x06, b, 0x0E, c, 0x16, d, 0x1E, e, 0x26, h, 0x2E, l]) // LD B,b ... LD L,l
+                    .code(&[LD_B_B])
+                    .code(&[0x18, 0xFE]) // JR -2
+                    .build();
+                let path = directory.join("mooneye.gb");
+                std::fs::write(&path, rom)?;
+                assert_eq!(TestVerdict::run(&path, 10)?, expected);
+            }
+
+            let rom = TestRom::new().code(&[0x18, 0xFE]).build(); // JR -2
+            let path = directory.join("silent.gb");
+            std::fs::write(&path, rom)?;
+            assert_eq!(TestVerdict::run(&path, 2)?, TestVerdict::Timeout);
+            Ok(())
+        }
     }
 }
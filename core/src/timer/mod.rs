@@ -1,71 +1,156 @@
 pub(crate) mod timer_bus;
 
 use crate::bus::Interrupt;
+use crate::state::{Savable, StateReader, StateWriter};
 use crate::timer::timer_bus::TAC;
 use timer_bus::TimerBus;
 
 pub(crate) const DMG_DIV_INITIAL_VALUE: u8 = 0xD3;
+/// System counter when the DMG boot rom jumps to $0100, DIV reads $AB
+pub(crate) const DMG_POST_BOOT_COUNTER: u16 = 0xABCC;
 
+/// Cycles between a TIMA overflow and the reload from TMA (one M-cycle)
+const RELOAD_DELAY: u8 = 4;
+
+/// Timer built on the 16-bit system counter, DIV being its upper byte.
+///
+/// TIMA is incremented on the falling edge of the counter bit selected by TAC, ANDed with the enable bit.
+/// Resetting the counter (DIV write) or changing TAC can therefore produce an extra increment.
 #[derive(Default)]
 pub struct Timer {
-    div_cycles: u16,
-    timer_cycles: u16,
+    counter: u16,
+    /// Last TAC bits seen, to detect writes
+    tac: u8,
+    /// Cycles left before TMA is loaded into TIMA, 0 when no overflow is pending
+    reload_delay: u8,
+    /// No extra increment on DIV and TAC writes and an immediate reload, see
+    /// [`AccuracyProfile::timer_edge_cases`](crate::AccuracyProfile::timer_edge_cases)
+    ignore_edge_cases: bool,
 }
 
 impl Timer {
     pub fn reset(&mut self, bus: &mut impl TimerBus) {
-        bus.set_div(DMG_DIV_INITIAL_VALUE);
         bus.set_tima(0x00);
         bus.set_tma(0x00);
         bus.set_tac_u8(0xF8);
-        self.div_cycles = 0;
-        self.timer_cycles = 0;
+        self.counter = (DMG_DIV_INITIAL_VALUE as u16) << 8;
+        self.tac = bus.tac().bits();
+        self.reload_delay = 0;
+        bus.set_div(DMG_DIV_INITIAL_VALUE);
+        bus.take_div_write();
+        bus.take_tima_write();
+    }
+
+    /// Set the system counter without the side effects of a DIV write
+    pub(crate) fn set_counter(&mut self, bus: &mut impl TimerBus, counter: u16) {
+        self.counter = counter;
+        bus.set_div((counter >> 8) as u8);
+    }
+
+    pub(crate) fn set_ignore_edge_cases(&mut self, ignore: bool) {
+        self.ignore_edge_cases = ignore;
     }
 
     pub fn step(&mut self, bus: &mut impl TimerBus, cycles: u8) {
-        // Update DIV register (increments every 256 CPU cycles)
-        self.div_cycles = self.div_cycles.wrapping_add(cycles as u16);
-        if self.div_cycles >= 256 {
-            self.div_cycles -= 256;
-            bus.set_div(bus.div().wrapping_add(1));
+        // Writes done by the CPU since the last step
+        if bus.take_tima_write() {
+            // Writing TIMA during the reload delay cancels the reload and the interrupt
+            self.reload_delay = 0;
+        }
+        if bus.take_div_write() {
+            self.reset_counter(bus);
+        }
+        let tac = bus.tac().bits();
+        if tac != self.tac {
+            self.write_tac(bus, tac);
         }
 
-        // Check if timer is enabled (TAC)
-        let tac = bus.tac();
-        if !tac.contains(TAC::Enable) {
-            return;
+        for _ in 0..cycles {
+            self.tick(bus);
         }
+    }
 
-        let timer_freq = match (tac.contains(TAC::ClockSelect1), tac.contains(TAC::ClockSelect0)) {
-            (false, false) => 256, // 4096 Hz   (00)
-            (false, true) => 4,    // 262144 Hz (01)
-            (true, false) => 16,   // 65536 Hz  (10)
-            (true, true) => 64,    // 16384 Hz  (11)
-        };
+    fn tick(&mut self, bus: &mut impl TimerBus) {
+        if self.reload_delay > 0 {
+            self.reload_delay -= 1;
+            if self.reload_delay == 0 {
+                bus.set_tima(bus.tma());
+                bus.set_interrupt_flag(Interrupt::TIMER);
+            }
+        }
 
-        // Update TIMA according to selected frequency
-        self.timer_cycles = self.timer_cycles.wrapping_add(cycles as u16);
+        let before = self.signal(self.tac);
+        self.counter = self.counter.wrapping_add(1);
+        if before && !self.signal(self.tac) {
+            self.increment_tima(bus);
+        }
 
-        if self.timer_cycles >= timer_freq {
-            self.timer_cycles -= timer_freq;
+        bus.set_div((self.counter >> 8) as u8);
+    }
 
-            let tima = bus.tima();
-            if tima == 0xFF {
-                // Overflow
-                bus.set_tima(bus.tma()); // put TMA into TIMA
-                bus.set_interrupt_flag(Interrupt::TIMER); // Trigger TIMER interrupt
-            } else {
-                // Increment TIMA
-                bus.set_tima(tima.wrapping_add(1));
-            }
+    /// DIV write, a falling edge of the selected bit increments TIMA
+    fn reset_counter(&mut self, bus: &mut impl TimerBus) {
+        if self.signal(self.tac) && !self.ignore_edge_cases {
+            self.increment_tima(bus);
+        }
+        self.counter = 0;
+        bus.set_div(0);
+    }
+
+    /// TAC write, disabling the timer or selecting a low bit while the old one is high increments TIMA (DMG)
+    fn write_tac(&mut self, bus: &mut impl TimerBus, tac: u8) {
+        if self.signal(self.tac) && !self.signal(tac) && !self.ignore_edge_cases {
+            self.increment_tima(bus);
         }
+        self.tac = tac;
+    }
+
+    fn increment_tima(&mut self, bus: &mut impl TimerBus) {
+        let (tima, overflow) = bus.tima().overflowing_add(1);
+        if overflow && self.ignore_edge_cases {
+            bus.set_tima(bus.tma());
+            bus.set_interrupt_flag(Interrupt::TIMER);
+            return;
+        }
+        // TIMA reads 0x00 until the reload
+        bus.set_tima(tima);
+        if overflow {
+            self.reload_delay = RELOAD_DELAY;
+        }
+    }
+
+    /// Selected counter bit ANDed with the enable bit
+    fn signal(&self, tac: u8) -> bool {
+        let tac = TAC::from_bits_truncate(tac);
+        let bit = match (tac.contains(TAC::ClockSelect1), tac.contains(TAC::ClockSelect0)) {
+            (false, false) => 9, // 4096 Hz   (00)
+            (false, true) => 3,  // 262144 Hz (01)
+            (true, false) => 5,  // 65536 Hz  (10)
+            (true, true) => 7,   // 16384 Hz  (11)
+        };
+        tac.contains(TAC::Enable) && self.counter & (1 << bit) != 0
+    }
+}
+
+impl Savable for Timer {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.counter);
+        writer.write_u8(self.tac);
+        writer.write_u8(self.reload_delay);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> Result<(), std::io::Error> {
+        self.counter = reader.read_u16()?;
+        self.tac = reader.read_u8()?;
+        self.reload_delay = reader.read_u8()?;
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::bus::InterruptBus;
+    use crate::bus::{InterruptBus, MemorySystem};
 
     use crate::tests::bus::TestBus;
 
@@ -83,22 +168,28 @@ mod tests {
         assert_eq!(bus.div(), 1);
     }
 
+    fn run(timer: &mut Timer, bus: &mut impl TimerBus, cycles: usize) {
+        for _ in 0..cycles {
+            timer.step(bus, 1);
+        }
+    }
+
     #[test]
     fn test_tima_frequencies() {
         let run_test = |tac: TAC, cycles: usize| {
             let mut timer = Timer::default();
             let mut bus = TestBus::default();
             bus.set_tac(tac);
-            timer.step(&mut bus, (cycles - 1) as u8);
+            run(&mut timer, &mut bus, cycles - 1);
             assert_eq!(bus.tima(), 0);
             timer.step(&mut bus, 1);
             assert_eq!(bus.tima(), 1);
         };
 
-        run_test(TAC::Enable, 256); // Test 4096 Hz (256 cycles)
-        run_test(TAC::Enable | TAC::ClockSelect0, 4); // Test 262144 Hz (4 cycles)
-        run_test(TAC::Enable | TAC::ClockSelect1, 16); // Test 65536 Hz (16 cycles)
-        run_test(TAC::Enable | TAC::ClockSelect1 | TAC::ClockSelect0, 64); // Test 16384 Hz (64 cycles)
+        run_test(TAC::Enable, 1024); // Test 4096 Hz (1024 cycles)
+        run_test(TAC::Enable | TAC::ClockSelect0, 16); // Test 262144 Hz (16 cycles)
+        run_test(TAC::Enable | TAC::ClockSelect1, 64); // Test 65536 Hz (64 cycles)
+        run_test(TAC::Enable | TAC::ClockSelect1 | TAC::ClockSelect0, 256); // Test 16384 Hz (256 cycles)
     }
 
     #[test]
@@ -110,9 +201,110 @@ mod tests {
         bus.set_tima(0xFF);
         bus.set_tma(0x42);
 
-        timer.step(&mut bus, 255);
+        run(&mut timer, &mut bus, 1023);
         assert_eq!(bus.tima(), 0xFF);
         timer.step(&mut bus, 1);
+        // TIMA reads 0x00 for one M-cycle before the reload
+        assert_eq!(bus.tima(), 0x00);
+        assert!(!bus.interrupt_flag().contains(Interrupt::TIMER));
+        timer.step(&mut bus, 4);
+        assert_eq!(bus.tima(), 0x42);
+        assert!(bus.interrupt_flag().contains(Interrupt::TIMER));
+    }
+
+    #[test]
+    fn test_tima_write_cancels_reload() {
+        let mut timer = Timer::default();
+        let mut bus = MemorySystem::default();
+
+        bus.set_tac(TAC::Enable | TAC::ClockSelect0);
+        bus.set_tima(0xFF);
+        bus.set_tma(0x42);
+        timer.step(&mut bus, 16);
+        assert_eq!(bus.tima(), 0x00);
+
+        bus.write_byte(0xFF05, 0x10);
+        timer.step(&mut bus, 4);
+        assert_eq!(bus.tima(), 0x10);
+        assert!(!bus.interrupt_flag().contains(Interrupt::TIMER));
+    }
+
+    #[test]
+    fn test_div_write_increments_tima() {
+        let mut timer = Timer::default();
+        let mut bus = MemorySystem::default();
+
+        // Selected bit 3 is high after 8 cycles, resetting the counter makes it fall
+        bus.set_tac(TAC::Enable | TAC::ClockSelect0);
+        timer.step(&mut bus, 8);
+        assert_eq!(bus.tima(), 0);
+        bus.write_byte(0xFF04, 0x00);
+        timer.step(&mut bus, 0);
+        assert_eq!(bus.tima(), 1);
+
+        // Bit 3 is low, no extra increment
+        timer.step(&mut bus, 4);
+        bus.write_byte(0xFF04, 0x00);
+        timer.step(&mut bus, 0);
+        assert_eq!(bus.tima(), 1);
+
+        // The counter restarts from 0
+        timer.step(&mut bus, 15);
+        assert_eq!(bus.tima(), 1);
+        timer.step(&mut bus, 1);
+        assert_eq!(bus.tima(), 2);
+    }
+
+    #[test]
+    fn test_tac_write_glitch() {
+        let mut timer = Timer::default();
+        let mut bus = TestBus::default();
+
+        // Bit 9 is high after 512 cycles, disabling the timer makes the signal fall
+        bus.set_tac(TAC::Enable);
+        run(&mut timer, &mut bus, 512);
+        assert_eq!(bus.tima(), 0);
+        bus.clear_tac(TAC::Enable);
+        timer.step(&mut bus, 0);
+        assert_eq!(bus.tima(), 1);
+
+        // Switching from a high bit (9) to a low one (3) increments too
+        bus.set_tac(TAC::Enable);
+        timer.step(&mut bus, 0);
+        bus.set_tac(TAC::ClockSelect0);
+        timer.step(&mut bus, 0);
+        assert_eq!(bus.tima(), 2);
+
+        // Switching while the signal is low does not
+        bus.set_tac(TAC::ClockSelect1 | TAC::ClockSelect0);
+        timer.step(&mut bus, 0);
+        assert_eq!(bus.tima(), 2);
+    }
+
+    #[test]
+    fn test_ignore_edge_cases() {
+        let mut timer = Timer::default();
+        timer.set_ignore_edge_cases(true);
+        let mut bus = MemorySystem::default();
+
+        // Bit 3 is high, resetting the counter does not increment TIMA
+        bus.set_tac(TAC::Enable | TAC::ClockSelect0);
+        timer.step(&mut bus, 8);
+        bus.write_byte(0xFF04, 0x00);
+        timer.step(&mut bus, 0);
+        assert_eq!(bus.tima(), 0);
+
+        // Nor does disabling the timer
+        timer.step(&mut bus, 8);
+        bus.clear_tac(TAC::Enable);
+        timer.step(&mut bus, 0);
+        assert_eq!(bus.tima(), 0);
+
+        // The overflow reloads TMA at once
+        bus.set_tac(TAC::Enable | TAC::ClockSelect0);
+        bus.set_tima(0xFF);
+        bus.set_tma(0x42);
+        timer.step(&mut bus, 16);
         assert_eq!(bus.tima(), 0x42);
         assert!(bus.interrupt_flag().contains(Interrupt::TIMER));
     }
@@ -20,7 +20,21 @@ pub trait TimerBus: InterruptBus {
     fn set_div(&mut self, byte: u8) {
         self.write_internal_byte(0xFF04, byte);
     }
-    define_u8_accessors!(tima, 0xFF05);
+    fn tima(&self) -> u8 {
+        self.read_byte(0xFF05)
+    }
+    fn set_tima(&mut self, byte: u8) {
+        self.write_internal_byte(0xFF05, byte);
+    }
     define_u8_accessors!(tma, 0xFF06);
     define_flags_accessors!(tac, 0xFF07, TAC);
+
+    /// DIV was written by the CPU since the last call
+    fn take_div_write(&mut self) -> bool {
+        false
+    }
+    /// TIMA was written by the CPU since the last call
+    fn take_tima_write(&mut self) -> bool {
+        false
+    }
 }
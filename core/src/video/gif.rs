@@ -0,0 +1,306 @@
+use crate::FRAME_RATE;
+use crate::video::sink::{FrameRef, VideoSink};
+use crate::video::{SCREEN_HEIGHT, SCREEN_WIDTH, rgb555};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::path::Path;
+
+/// Bits of the color indexes, the 4 shades
+const MIN_CODE_SIZE: u8 = 2;
+const MAX_CODE: u16 = 4095;
+
+/// Sink recording the frames into a looping animated GIF.
+///
+/// Every other frame is kept: GIF delays count in hundredths of a second and most viewers slow
+/// down delays under 2/100 s. The colors are those of the palette of the first frame, or in CGB
+/// mode a color table of each frame.
+pub struct GifRecorder {
+    writer: BufWriter<File>,
+    /// Frames written, the header is written with the first one
+    frames: u64,
+    skip_next: bool,
+}
+
+impl GifRecorder {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            frames: 0,
+            skip_next: false,
+        })
+    }
+
+    fn write_header(&mut self, frame: &FrameRef) -> Result<(), Error> {
+        self.writer.write_all(b"GIF89a")?;
+        self.writer.write_all(&(SCREEN_WIDTH as u16).to_le_bytes())?;
+        self.writer.write_all(&(SCREEN_HEIGHT as u16).to_le_bytes())?;
+        // Global color table of 4 entries
+        self.writer.write_all(&[0xF1, 0, 0])?;
+        for color in frame.palette.colors() {
+            self.writer.write_all(color)?;
+        }
+        // Loop forever
+        self.writer.write_all(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00")
+    }
+
+    /// Delay after the frame `index`, in hundredths of a second
+    fn delay(index: u64) -> u16 {
+        let time = |index: u64| (index as f64 * 2.0 * 100.0 / FRAME_RATE).round() as u64;
+        (time(index + 1) - time(index)) as u16
+    }
+}
+
+impl VideoSink for GifRecorder {
+    fn on_frame(&mut self, frame: &FrameRef) -> Result<(), Error> {
+        self.skip_next = !self.skip_next;
+        if !self.skip_next {
+            return Ok(());
+        }
+        if self.frames == 0 {
+            self.write_header(frame)?;
+        }
+
+        let [delay_low, delay_high] = Self::delay(self.frames).to_le_bytes();
+        self.writer
+            .write_all(&[0x21, 0xF9, 0x04, 0x00, delay_low, delay_high, 0x00, 0x00])?;
+        self.writer.write_all(&[0x2C, 0, 0, 0, 0])?;
+        self.writer.write_all(&(SCREEN_WIDTH as u16).to_le_bytes())?;
+        self.writer.write_all(&(SCREEN_HEIGHT as u16).to_le_bytes())?;
+
+        let (indexes, code_size) = match frame.colors {
+            Some(colors) => {
+                let (table, indexes) = index_colors(colors);
+                let code_size = (table.len().next_power_of_two().trailing_zeros() as u8).max(MIN_CODE_SIZE);
+                // Local color table, padded to a power of two
+                self.writer.write_all(&[0x80 | (code_size - 1)])?;
+                for entry in 0..1 << code_size {
+                    self.writer.write_all(&table.get(entry).copied().unwrap_or_default())?;
+                }
+                (indexes, code_size)
+            }
+            None => {
+                self.writer.write_all(&[0x00])?;
+                let indexes = frame.shades.iter().map(|shade| (*shade).min(3)).collect();
+                (indexes, MIN_CODE_SIZE)
+            }
+        };
+        self.writer.write_all(&[code_size])?;
+        for block in lzw_encode(&indexes, code_size).chunks(255) {
+            self.writer.write_all(&[block.len() as u8])?;
+            self.writer.write_all(block)?;
+        }
+        self.writer.write_all(&[0x00])?;
+
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.writer.write_all(&[0x3B])?;
+        self.writer.flush()
+    }
+}
+
+/// Variable length code stream, least significant bits first
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+/// Color table and color indexes of a CGB frame. The low bits of the channels are dropped until
+/// the colors fit in the 256 entries of a GIF color table.
+fn index_colors(colors: &[u16]) -> (Vec<[u8; 3]>, Vec<u8>) {
+    for dropped_bits in 0..5 {
+        let low = (1 << dropped_bits) - 1;
+        let mask = !(low | low << 5 | low << 10) & 0x7FFF;
+        let mut table: Vec<u16> = colors.iter().map(|color| color & mask).collect();
+        table.sort_unstable();
+        table.dedup();
+        if table.len() <= 256 {
+            let indexes = colors
+                .iter()
+                .map(|color| table.binary_search(&(color & mask)).unwrap_or_else(|index| index) as u8)
+                .collect();
+            return (table.into_iter().map(rgb555).collect(), indexes);
+        }
+    }
+    unreachable!("2 bits per channel fit in 64 colors")
+}
+
+/// GIF flavoured LZW compression of the color indexes, of `code_size` bits
+fn lzw_encode(indexes: &[u8], code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << code_size;
+    let end = clear + 1;
+
+    let mut output = BitWriter::default();
+    let mut codes: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = end + 1;
+    let mut size = code_size + 1;
+    output.write(clear, size);
+
+    let Some((&first, rest)) = indexes.split_first() else {
+        output.write(end, size);
+        return output.finish();
+    };
+    let mut current = first as u16;
+    for &index in rest {
+        if let Some(&code) = codes.get(&(current, index)) {
+            current = code;
+            continue;
+        }
+
+        output.write(current, size);
+        if next_code >= 1 << size && size < 12 {
+            size += 1;
+        }
+        if next_code < MAX_CODE {
+            codes.insert((current, index), next_code);
+            next_code += 1;
+        } else {
+            output.write(clear, size);
+            codes.clear();
+            next_code = end + 1;
+            size = code_size + 1;
+        }
+        current = index as u16;
+    }
+    output.write(current, size);
+    if next_code >= 1 << size && size < 12 {
+        size += 1;
+    }
+    output.write(end, size);
+    output.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::ColorPalette;
+    use crate::tests::temp::TempDir;
+
+    /// Decoder following the GIF specification, to check the encoder against
+    fn lzw_decode(data: &[u8], code_size: u8) -> Vec<u8> {
+        let clear = 1usize << code_size;
+        let mut output = vec![];
+        let mut table: Vec<Vec<u8>> = vec![];
+        let mut size = code_size + 1;
+        let mut previous: Option<Vec<u8>> = None;
+        let (mut buffer, mut bits, mut bytes) = (0u32, 0u8, data.iter());
+
+        loop {
+            while bits < size {
+                buffer |= (*bytes.next().expect("missing end code") as u32) << bits;
+                bits += 8;
+            }
+            let code = (buffer & ((1 << size) - 1)) as usize;
+            buffer >>= size;
+            bits -= size;
+
+            if code == clear {
+                table = (0..clear).map(|index| vec![index as u8]).collect();
+                table.extend([vec![], vec![]]);
+                size = code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == clear + 1 {
+                return output;
+            }
+
+            let entry = match (table.get(code), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) => [previous.as_slice(), &previous[..1]].concat(),
+                (None, None) => panic!("unknown code {code}"),
+            };
+            output.extend_from_slice(&entry);
+            if let Some(previous) = previous
+                && table.len() < 4096
+            {
+                table.push([previous.as_slice(), &entry[..1]].concat());
+            }
+            if table.len() >= 1 << size && size < 12 {
+                size += 1;
+            }
+            previous = Some(entry);
+        }
+    }
+
+    #[test]
+    fn test_lzw_round_trip() {
+        let mut state = 7;
+        let noise: Vec<u8> = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+            .map(|_| (crate::rng::splitmix64(&mut state) & 3) as u8)
+            .collect();
+        let stripes: Vec<u8> = (0..SCREEN_WIDTH * SCREEN_HEIGHT).map(|i| (i / 7 % 4) as u8).collect();
+
+        // Noise fills the table and goes through clear codes
+        for indexes in [noise, stripes, vec![2; 1000], vec![1], vec![]] {
+            assert_eq!(lzw_decode(&lzw_encode(&indexes, MIN_CODE_SIZE), MIN_CODE_SIZE), indexes);
+        }
+        let colors: Vec<u8> = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+            .map(|i| (i * 31 % 251) as u8)
+            .collect();
+        assert_eq!(lzw_decode(&lzw_encode(&colors, 8), 8), colors);
+    }
+
+    #[test]
+    fn test_index_colors() {
+        let (table, indexes) = index_colors(&[0x7FFF, 0x001F, 0x7FFF]);
+        assert_eq!(table, [[0xFF, 0, 0], [0xFF, 0xFF, 0xFF]]);
+        assert_eq!(indexes, [1, 0, 1]);
+
+        // 1024 colors keep 4 bits per channel
+        let colors: Vec<u16> = (0..1024).map(|i| i << 5).collect();
+        let (table, indexes) = index_colors(&colors);
+        assert_eq!(table.len(), 256);
+        assert_eq!((indexes[0], indexes[1023]), (0, 255));
+    }
+
+    #[test]
+    fn test_gif_recorder() -> Result<(), Error> {
+        let dir = TempDir::new("recorder")?;
+        let path = dir.join("recorder.gif");
+        let mut recorder = GifRecorder::create(&path)?;
+        let shades = [1u8; SCREEN_WIDTH * SCREEN_HEIGHT];
+        for number in 0..4 {
+            recorder.on_frame(&FrameRef {
+                number,
+                shades: &shades,
+                colors: None,
+                palette: &ColorPalette::GRAYSCALE,
+            })?;
+        }
+        recorder.finish()?;
+
+        let gif = std::fs::read(&path)?;
+        assert!(gif.starts_with(b"GIF89a\xA0\x00\x90\x00\xF1"));
+        assert_eq!(&gif[13..16], ColorPalette::GRAYSCALE.colors()[0]);
+        assert_eq!(gif.last(), Some(&0x3B));
+        // Frames 0 and 2
+        assert_eq!(gif.windows(3).filter(|window| window == &[0x21, 0xF9, 0x04]).count(), 2);
+        assert_eq!((GifRecorder::delay(0), GifRecorder::delay(1)), (3, 4));
+        Ok(())
+    }
+}
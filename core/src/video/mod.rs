@@ -0,0 +1,269 @@
+//! Pixel conversion shared by the frontends: palette application, scaling and filters.
+//!
+//! Images are slices of pixels in row order, the frame buffer being one shade id per pixel
+//! (see [`Machine::frame`](crate::Machine::frame)).
+
+use crate::ppu::ColorPalette;
+
+mod gif;
+mod sink;
+
+pub use gif::GifRecorder;
+pub use sink::{ChannelSink, Frame, FrameRef, NullSink, PngBurst, VideoSink};
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// RGB colors of the shade ids
+pub fn to_rgb<'a>(shades: &'a [u8], palette: &'a ColorPalette) -> impl Iterator<Item = [u8; 3]> + 'a {
+    shades.iter().map(|shade| palette.color(*shade))
+}
+
+/// Opaque RGBA colors of the shade ids, use `as_flattened` to get the bytes
+pub fn to_rgba(shades: &[u8], palette: &ColorPalette) -> Vec<[u8; 4]> {
+    to_rgb(shades, palette).map(|[r, g, b]| [r, g, b, 0xFF]).collect()
+}
+
+/// RGB colors of a CGB frame, see [`Machine::frame_rgb555`](crate::Machine::frame_rgb555)
+pub fn rgb555_to_rgb(colors: &[u16]) -> impl Iterator<Item = [u8; 3]> + '_ {
+    colors.iter().map(|color| rgb555(*color))
+}
+
+/// RGB color of a CGB color, red in the low bits, each 5 bit channel spread over 8 bits
+pub fn rgb555(color: u16) -> [u8; 3] {
+    [0, 5, 10].map(|shift| {
+        let channel = (color >> shift) as u8 & 0x1F;
+        channel << 3 | channel >> 2
+    })
+}
+
+/// Nearest neighbour scaling by an integer factor
+pub fn scale<T: Copy>(pixels: &[T], width: usize, factor: usize) -> Vec<T> {
+    pixels
+        .chunks(width)
+        .flat_map(|line| {
+            let line: Vec<T> = line
+                .iter()
+                .flat_map(|pixel| std::iter::repeat_n(*pixel, factor))
+                .collect();
+            std::iter::repeat_n(line, factor).flatten()
+        })
+        .collect()
+}
+
+/// Keep the top left pixel of each `factor` x `factor` block
+pub fn downscale<T: Copy>(pixels: &[T], width: usize, factor: usize) -> Vec<T> {
+    pixels
+        .chunks(width)
+        .step_by(factor)
+        .flat_map(|line| line.iter().step_by(factor).copied())
+        .collect()
+}
+
+/// Darken the last row of each line of an image scaled by `factor`, like the gaps between the
+/// lines of a CRT. `intensity` goes from 0 (no effect) to 255 (black).
+pub fn apply_scanlines(rgba: &mut [[u8; 4]], width: usize, factor: usize, intensity: u8) {
+    if factor < 2 {
+        return;
+    }
+    let keep = 255 - intensity as u16;
+    for line in rgba.chunks_mut(width).skip(factor - 1).step_by(factor) {
+        for pixel in line {
+            for channel in &mut pixel[..3] {
+                *channel = (*channel as u16 * keep / 255) as u8;
+            }
+        }
+    }
+}
+
+/// Post-processing of the RGB frames, applied in order to each displayed frame
+pub trait FrameFilter {
+    fn apply(&mut self, rgb: &mut [[u8; 3]]);
+}
+
+/// Mix each frame with the previous one, so sprites flickered at 30 Hz to fake transparency look
+/// transparent. `factor` is the weight of the previous frame, from 0 (no effect) to 255.
+#[derive(Debug, Default, Clone)]
+pub struct FrameBlend {
+    factor: u8,
+    previous: Vec<[u8; 3]>,
+}
+
+impl FrameBlend {
+    /// Equal mix of the two frames
+    pub const DEFAULT_FACTOR: u8 = 128;
+
+    pub fn new(factor: u8) -> Self {
+        Self {
+            factor,
+            previous: vec![],
+        }
+    }
+
+    pub fn factor(&self) -> u8 {
+        self.factor
+    }
+}
+
+impl FrameFilter for FrameBlend {
+    fn apply(&mut self, rgb: &mut [[u8; 3]]) {
+        // The unblended frame is kept, blending the output again would leave trails
+        let current = rgb.to_vec();
+        if self.previous.len() == rgb.len() {
+            let previous = self.factor as u16;
+            let keep = 255 - previous;
+            for (pixel, old) in rgb.iter_mut().zip(&self.previous) {
+                for (channel, old) in pixel.iter_mut().zip(old) {
+                    *channel = ((*channel as u16 * keep + *old as u16 * previous + 127) / 255) as u8;
+                }
+            }
+        }
+        self.previous = current;
+    }
+}
+
+/// Encode an RGB image as PNG. The image data is stored without compression, a 160x144 frame
+/// takes about 70 KiB.
+pub fn encode_png(rgb: &[[u8; 3]], width: usize) -> Vec<u8> {
+    let height = rgb.len() / width;
+
+    // Each line starts with filter type 0 (none)
+    let raw: Vec<u8> = rgb
+        .chunks(width)
+        .flat_map(|line| std::iter::once(0).chain(line.as_flattened().iter().copied()))
+        .collect();
+
+    // zlib stream made of stored deflate blocks
+    let mut data = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        data.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        data.push(blocks.peek().is_none() as u8);
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(&(!len).to_le_bytes());
+        data.extend_from_slice(block);
+    }
+    data.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]); // 8 bits per channel, RGB
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_png_chunk(&mut png, b"IHDR", &header);
+    write_png_chunk(&mut png, b"IDAT", &data);
+    write_png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    png.extend_from_slice(&crc32(&[kind, data]).to_be_bytes());
+}
+
+pub(crate) fn crc32(parts: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for byte in parts.iter().flat_map(|part| part.iter()) {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), byte| {
+        let a = (a + *byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_rgba() {
+        let rgba = to_rgba(&[0, 3, 7], &ColorPalette::GRAYSCALE);
+        assert_eq!(rgba, [[255, 255, 255, 255], [0, 0, 0, 255], [0, 0, 0, 255]]);
+        assert_eq!(rgba.as_flattened().len(), 12);
+    }
+
+    #[test]
+    fn test_rgb555() {
+        let rgb: Vec<_> = rgb555_to_rgb(&[0x0000, 0x7FFF, 0x001F, 0x03E0, 0x7C00, 0x0010]).collect();
+        assert_eq!(
+            rgb,
+            [
+                [0, 0, 0],
+                [255, 255, 255],
+                [255, 0, 0],
+                [0, 255, 0],
+                [0, 0, 255],
+                [132, 0, 0]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scale_and_downscale() {
+        let pixels = [1, 2, 3, 4];
+        let scaled = scale(&pixels, 2, 2);
+        assert_eq!(scaled, [1, 1, 2, 2, 1, 1, 2, 2, 3, 3, 4, 4, 3, 3, 4, 4]);
+        assert_eq!(downscale(&scaled, 4, 2), pixels);
+    }
+
+    #[test]
+    fn test_scanlines() {
+        let mut rgba = scale(&to_rgba(&[0, 0], &ColorPalette::GRAYSCALE), 1, 2);
+        apply_scanlines(&mut rgba, 2, 2, 128);
+
+        assert_eq!(rgba[0], [255, 255, 255, 255]);
+        assert_eq!(rgba[2], [127, 127, 127, 255]);
+        assert_eq!(rgba[4], [255, 255, 255, 255]);
+        assert_eq!(rgba[7], [127, 127, 127, 255]);
+    }
+
+    #[test]
+    fn test_frame_blend() {
+        let mut blend = FrameBlend::new(FrameBlend::DEFAULT_FACTOR);
+
+        let mut first = [[255, 255, 255], [0, 0, 0]];
+        blend.apply(&mut first);
+        assert_eq!(first, [[255, 255, 255], [0, 0, 0]]);
+
+        let mut second = [[0, 0, 0], [0, 0, 0]];
+        blend.apply(&mut second);
+        assert_eq!(second, [[128, 128, 128], [0, 0, 0]]);
+
+        // Blended with the previous frame as rendered, not as displayed
+        let mut third = [[0, 0, 0], [0, 0, 0]];
+        blend.apply(&mut third);
+        assert_eq!(third, [[0, 0, 0], [0, 0, 0]]);
+    }
+
+    #[test]
+    fn test_encode_png() {
+        let rgb: Vec<[u8; 3]> = to_rgb(&[0, 1, 2, 3, 0, 0], &ColorPalette::GRAYSCALE).collect();
+        let png = encode_png(&rgb, 3);
+
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..24], &[0, 0, 0, 3, 0, 0, 0, 2]);
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]));
+        assert_eq!(crc32(&[b"123456789"]), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+}
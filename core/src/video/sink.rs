@@ -0,0 +1,195 @@
+use crate::ppu::ColorPalette;
+use crate::video::{SCREEN_WIDTH, encode_png, rgb555_to_rgb, to_rgb};
+use std::fs;
+use std::io::Error;
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError, sync_channel};
+
+/// Completed frame handed to the [`VideoSink`]s
+pub struct FrameRef<'a> {
+    /// Frames completed since the last reset, see [`Machine::frame_count`](crate::Machine::frame_count)
+    pub number: u64,
+    /// Shade id of each pixel, see [`Machine::frame`](crate::Machine::frame)
+    pub shades: &'a [u8],
+    /// RGB555 color of each pixel in CGB mode, see [`Machine::frame_rgb555`](crate::Machine::frame_rgb555)
+    pub colors: Option<&'a [u16]>,
+    pub palette: &'a ColorPalette,
+}
+
+impl FrameRef<'_> {
+    /// Colors of the pixels, see [`Machine::frame_rgb`](crate::Machine::frame_rgb)
+    pub fn to_rgb(&self) -> Vec<[u8; 3]> {
+        match self.colors {
+            Some(colors) => rgb555_to_rgb(colors).collect(),
+            None => to_rgb(self.shades, self.palette).collect(),
+        }
+    }
+}
+
+/// Receiver of the frames completed by the machine, added with
+/// [`Machine::add_video_sink`](crate::Machine::add_video_sink).
+pub trait VideoSink: Send {
+    /// Called at the end of each frame, an error removes the sink
+    fn on_frame(&mut self, frame: &FrameRef) -> Result<(), Error>;
+
+    /// No more frames are wanted, the sink is finished and removed
+    fn is_done(&self) -> bool {
+        false
+    }
+
+    /// Frames received but not kept, see [`Machine::dropped_frames`](crate::Machine::dropped_frames)
+    fn dropped_frames(&self) -> u64 {
+        0
+    }
+
+    /// Complete the output, called once when the sink is removed
+    fn finish(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Sink dropping every frame
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullSink;
+
+impl VideoSink for NullSink {
+    fn on_frame(&mut self, _frame: &FrameRef) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Owned copy of a completed frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub number: u64,
+    pub shades: Vec<u8>,
+    pub colors: Option<Vec<u16>>,
+}
+
+impl Frame {
+    /// Colors of the pixels, the shades are drawn with `palette` out of CGB mode
+    pub fn to_rgb(&self, palette: &ColorPalette) -> Vec<[u8; 3]> {
+        match &self.colors {
+            Some(colors) => rgb555_to_rgb(colors).collect(),
+            None => to_rgb(&self.shades, palette).collect(),
+        }
+    }
+}
+
+/// Sink sending the frames to another thread, e.g. a frontend drawing at its own pace.
+/// Frames are dropped while the channel is full, the sink is done once the receiver is gone.
+pub struct ChannelSink {
+    sender: SyncSender<Frame>,
+    dropped: u64,
+    disconnected: bool,
+}
+
+impl ChannelSink {
+    /// Sink and receiver of a channel holding up to `capacity` frames
+    pub fn new(capacity: usize) -> (Self, Receiver<Frame>) {
+        let (sender, receiver) = sync_channel(capacity);
+        let sink = Self {
+            sender,
+            dropped: 0,
+            disconnected: false,
+        };
+        (sink, receiver)
+    }
+
+    /// Frames dropped because the channel was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
+
+impl VideoSink for ChannelSink {
+    fn on_frame(&mut self, frame: &FrameRef) -> Result<(), Error> {
+        let frame = Frame {
+            number: frame.number,
+            shades: frame.shades.to_vec(),
+            colors: frame.colors.map(<[u16]>::to_vec),
+        };
+        match self.sender.try_send(frame) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => self.dropped += 1,
+            Err(TrySendError::Disconnected(_)) => self.disconnected = true,
+        }
+        Ok(())
+    }
+
+    fn is_done(&self) -> bool {
+        self.disconnected
+    }
+
+    fn dropped_frames(&self) -> u64 {
+        self.dropped
+    }
+}
+
+/// Sink writing the next frames as `frame_<number>.png` files
+pub struct PngBurst {
+    directory: PathBuf,
+    remaining: usize,
+}
+
+impl PngBurst {
+    pub fn new(directory: impl Into<PathBuf>, count: usize) -> Result<Self, Error> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+        Ok(Self {
+            directory,
+            remaining: count,
+        })
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+}
+
+impl VideoSink for PngBurst {
+    fn on_frame(&mut self, frame: &FrameRef) -> Result<(), Error> {
+        if self.remaining == 0 {
+            return Ok(());
+        }
+        let file = self.directory.join(format!("frame_{:06}.png", frame.number));
+        fs::write(file, encode_png(&frame.to_rgb(), SCREEN_WIDTH))?;
+        self.remaining -= 1;
+        Ok(())
+    }
+
+    fn is_done(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_sink_drops_when_full() -> Result<(), Error> {
+        let (mut sink, receiver) = ChannelSink::new(1);
+        let shades = [0u8; 4];
+        for number in 0..3 {
+            sink.on_frame(&FrameRef {
+                number,
+                shades: &shades,
+                colors: None,
+                palette: &ColorPalette::GRAYSCALE,
+            })?;
+        }
+        assert_eq!(sink.dropped(), 2);
+        assert_eq!(receiver.try_recv().map(|frame| frame.number), Ok(0));
+
+        drop(receiver);
+        sink.on_frame(&FrameRef {
+            number: 3,
+            shades: &shades,
+            colors: None,
+            palette: &ColorPalette::GRAYSCALE,
+        })?;
+        assert!(sink.is_done());
+        Ok(())
+    }
+}
@@ -1,19 +1,68 @@
+use crate::audio::AudioDevice;
+use crate::bindings::{self, Bindings, MACRO_KEYS};
+use crate::config::Config;
+use crate::library::Library;
+use crate::stats::PlayStats;
+use crate::style::container::{panel_content, panel_title};
+use crate::theme::color::red;
 use crate::views::*;
+use crate::widgets::screen;
 use crate::widgets::screen::Screen;
-use crate::widgets::{screen, title_panel};
-use gbemu_core::{JoypadButton, Machine};
+use crate::workspace::{self, Panel, Workspace};
+use gbemu_core::state::{DEFAULT_SNAPSHOT_INTERVAL, SaveSlots, Session};
+use gbemu_core::video::{FrameBlend, GifRecorder};
+use gbemu_core::{
+    Accuracy, AudioSettings, ColorPalette, EmulationStatus, Expression, FrameResult, InputMacro, Interrupt,
+    JoypadButton, Layers, Machine, MachineSnapshot, Palette, StateDiff,
+};
 use iced::alignment::{Horizontal, Vertical};
-use iced::keyboard::key::Named;
+use iced::keyboard::Modifiers;
+use iced::keyboard::key::{Code, Physical};
+use iced::widget::pane_grid::DragEvent;
 use iced::widget::scrollable::{Direction, Scrollbar};
-use iced::widget::{button, column, container, row, scrollable, text, text_input};
-use iced::{Element, Subscription, Task, keyboard, time, window};
+use iced::widget::{
+    Space, button, checkbox, column, container, opaque, pane_grid, pick_list, row, scrollable, slider, stack, text,
+    text_input,
+};
+use iced::{Element, Fill, Subscription, Task, keyboard, time, window};
 use iced_core::keyboard::{Event, Key};
 use log::error;
-use std::time::{Duration, Instant};
+use semver::Version;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
 // Application constants
 const DEFAULT_BREAKPOINT: &str = "00e9";
+const LAYOUT_KEY: &str = "layout";
+const AUDIO_SAMPLE_RATE_KEY: &str = "audio_sample_rate";
+const AUDIO_QUALITY_KEY: &str = "audio_quality";
+const AUDIO_LATENCY_KEY: &str = "audio_latency_ms";
+const PAUSE_ON_FOCUS_LOSS_KEY: &str = "pause_on_focus_loss";
+const BACKGROUND_THROTTLE_KEY: &str = "background_throttle";
+const INPUT_DISPLAY_KEY: &str = "input_display";
+/// Opt-in check of the latest GitHub release, off by default
+const UPDATE_CHECK_KEY: &str = "update_check";
+/// Accuracy preset, overridden by the `--accuracy` option
+pub(crate) const ACCURACY_KEY: &str = "accuracy";
+/// Folders of the rom library, separated like the `PATH` variable
+const ROM_DIRS_KEY: &str = "rom_dirs";
+/// Index of the rom library, next to the config file
+const LIBRARY_FILE: &str = "gbemu-library.idx";
+/// Per game, followed by the header hash
+const FRAME_BLEND_KEY: &str = "frame_blend";
+/// Per game, followed by the slot and the header hash
+const MACRO_KEY: &str = "macro";
+const FRAME_DUMP_DIR: &str = "frames";
 const GB_FRAME_DURATION: Duration = Duration::from_nanos(16_742_706); // 1/59.7275 s
+/// Frame pace while the window is minimized, with background throttling enabled
+const BACKGROUND_FRAME_DURATION: Duration = Duration::from_millis(100);
+/// Longest pause between two ticks counted as play time, longer ones are stalls of the window
+const MAX_TICK_GAP: Duration = Duration::from_millis(250);
+/// Pause of a pane resizing after which the layout is saved, the pane grid does not report the end of a drag
+const LAYOUT_SAVE_DELAY: Duration = Duration::from_millis(500);
+/// Pause of the audio settings changes after which they are saved, the latency slider sends each step
+const AUDIO_SAVE_DELAY: Duration = Duration::from_millis(500);
 const BUTTON_SPACING: f32 = 8.0;
 const COLUMN_SPACING: f32 = 10.0;
 const CONTENT_PADDING: f32 = 10.0;
@@ -21,11 +70,51 @@ const CONTENT_PADDING: f32 = 10.0;
 pub(crate) struct App {
     pub machine: Machine,
     last_update: Option<Instant>,
-    is_running: bool,
     breakpoint_at: String,
+    break_after_cycles: String,
+    run_until: String,
     view_memory_state: view_memory::State,
+    view_tiles_state: view_tiles::State,
+    opcode_sort: view_opcodes::Sort,
+    view_save_slots_state: view_save_slots::State,
+    rom_browser: view_rom_browser::State,
+    command_palette: view_command_palette::State,
+    bindings: Bindings,
+    view_keybindings_state: view_keybindings::State,
+    save_dir: PathBuf,
+    crash_dir: PathBuf,
+    config: Config,
+    workspace: Workspace,
     screen: Screen,
     total_cycles: u64,
+    /// Recorded session scrubbed by the player, with the shown frame
+    session: Option<(Session, u32)>,
+    /// Macro slot being recorded
+    macro_recording: Option<usize>,
+    /// Reference of the diff panel, with its comparison to a later state
+    state_snapshot: Option<MachineSnapshot>,
+    state_diff: Option<StateDiff>,
+    recording_gif: bool,
+    pause_on_focus_loss: bool,
+    background_throttle: bool,
+    /// Pressed buttons drawn over the screen
+    input_display: bool,
+    update_check: bool,
+    /// Version of the latest release, `None` until checked
+    latest_release: Option<Result<Version, String>>,
+    /// Paused when the window lost the focus, resumed when it gets it back
+    paused_by_focus_loss: bool,
+    minimized: bool,
+    /// Emulated time of the machine when the play statistics were last recorded
+    emulated_time_mark: Duration,
+    /// Wall time played since the play statistics were last recorded
+    unsaved_wall_time: Duration,
+    /// Last pane resize of a layout not saved yet
+    layout_resized: Option<Instant>,
+    /// Last change of audio settings not saved yet
+    audio_changed: Option<Instant>,
+    /// Output device playing the sound, `None` when none could be opened
+    audio: Option<AudioDevice>,
 }
 
 #[derive(Debug, Clone)]
@@ -40,19 +129,80 @@ pub enum Message {
     // User interface
     CloseWindow,
     OpenFile,
+    OpenPatch,
+    CommandPalette(view_command_palette::Message),
+    Keybindings(view_keybindings::Message),
+    SetColorPalette(ColorPalette),
+    SetAudioSettings(AudioSettings),
+    SetPalette(view_palettes::PaletteRegister, Palette),
+    ToggleFrameBlend,
+    ToggleLayer(Layers),
+    SetFrameBlend(Option<u8>),
+    DumpFrames(usize),
+    ToggleGifRecording,
+    TogglePauseOnFocusLoss,
+    ToggleBackgroundThrottle,
+    ToggleInputDisplay,
+    SetAccuracy(Accuracy),
+    CopyBuildInfo,
+    ToggleUpdateCheck,
+    /// Check the latest release when opted in
+    CheckForUpdate,
+    UpdateChecked(Result<Version, String>),
+    WindowEvent(window::Id, window::Event),
+    WindowMinimized(bool),
+
+    // Save states
+    SaveSlot(usize),
+    LoadSlot(usize),
+
+    // Recorded sessions
+    ToggleSessionRecording,
+    OpenSession,
+    SessionSeek(u32),
+    CloseSession,
+    TimelineToggle(u32, JoypadButton),
+    TimelineInsert(u32),
+    TimelineRemove(u32),
+    TimelineRecord,
 
     // Breakpoint management
     BreakpointRemove,
     BreakpointSet(u16),
+    BreakpointToggle(u16),
     BreakpointInputChanged(String),
+    BreakOnInterruptToggle(Interrupt),
+    BreakOnRstToggle(u8),
+    BreakOutsideCodeToggle,
+    FreezeToggle(u16),
+    ReadOnlyToggle(u16, u16),
+    ClearMemoryProtection,
+    BreakAfterCycles(u64),
+    BreakAfterInputChanged(String),
+    RunUntil,
+    RunUntilInputChanged(String),
+    TakeSnapshot,
+    CompareWithSnapshot,
 
     // Visual components
     ScreenView(screen::Message),
     MemoryView(view_memory::Message),
+    TilesView(view_tiles::Message),
+    RomBrowser(view_rom_browser::Message),
+    SortOpcodes(view_opcodes::SortColumn),
+    ResetProfile,
+    Workspace(workspace::Message),
+    SaveResizedLayout(Instant),
+    SaveAudioSettings(Instant),
 
     // Machine inputs
+    RequestInterrupt(Interrupt),
+    KeyPressed(Key, Modifiers, Physical),
+    KeyReleased(Key),
     ButtonsPressed(JoypadButton),
     ButtonsReleased(JoypadButton),
+    MacroRecordToggle(usize),
+    MacroPlay(usize),
 }
 
 impl Default for App {
@@ -60,77 +210,120 @@ impl Default for App {
         Self {
             machine: Machine::default(),
             last_update: None,
-            is_running: false,
             breakpoint_at: DEFAULT_BREAKPOINT.into(),
+            break_after_cycles: String::new(),
+            run_until: String::new(),
             view_memory_state: view_memory::State::default(),
+            view_tiles_state: view_tiles::State::default(),
+            opcode_sort: view_opcodes::Sort::default(),
+            view_save_slots_state: view_save_slots::State::default(),
+            rom_browser: view_rom_browser::State::default(),
+            command_palette: view_command_palette::State::default(),
+            bindings: Bindings::default(),
+            view_keybindings_state: view_keybindings::State::default(),
+            save_dir: PathBuf::from("saves"),
+            crash_dir: PathBuf::from("crashes"),
+            config: Config::default(),
+            workspace: Workspace::debug(),
             screen: Screen::default(),
             total_cycles: 0,
+            session: None,
+            macro_recording: None,
+            state_snapshot: None,
+            state_diff: None,
+            recording_gif: false,
+            pause_on_focus_loss: false,
+            background_throttle: false,
+            input_display: false,
+            update_check: false,
+            latest_release: None,
+            paused_by_focus_loss: false,
+            minimized: false,
+            emulated_time_mark: Duration::ZERO,
+            unsaved_wall_time: Duration::ZERO,
+            layout_resized: None,
+            audio_changed: None,
+            audio: None,
         }
     }
 }
 
 impl App {
+    pub fn new(machine: Machine, save_dir: PathBuf, crash_dir: PathBuf, config: Config) -> Self {
+        let workspace = config
+            .get(LAYOUT_KEY)
+            .and_then(Workspace::from_layout)
+            .unwrap_or_else(Workspace::debug);
+        let mut app = Self {
+            machine,
+            save_dir,
+            crash_dir,
+            config,
+            workspace,
+            ..Self::default()
+        };
+        app.view_save_slots_state.refresh(&app.save_slots());
+        app.open_audio(audio_settings(&app.config));
+        app.pause_on_focus_loss = app.config.get(PAUSE_ON_FOCUS_LOSS_KEY) == Some("true");
+        app.background_throttle = app.config.get(BACKGROUND_THROTTLE_KEY) == Some("true");
+        app.input_display = app.config.get(INPUT_DISPLAY_KEY) == Some("true");
+        app.update_check = app.config.get(UPDATE_CHECK_KEY) == Some("true");
+        app.bindings = Bindings::load(&app.config);
+        *app.machine.turbo_mut() = bindings::load_turbo(&app.config);
+        let folders = app
+            .config
+            .get(ROM_DIRS_KEY)
+            .map_or(vec![], |folders| std::env::split_paths(folders).collect());
+        let library = Library::load(app.config.directory().join(LIBRARY_FILE));
+        app.rom_browser = view_rom_browser::State::new(folders, library);
+        app.load_game_settings();
+        app.machine.pause();
+        app
+    }
     pub fn title(&self) -> String {
         String::from("Iced GB")
     }
     pub fn subscription(&self) -> Subscription<Message> {
         let mut subscriptions = vec![];
-        if self.is_running {
-            subscriptions.push(time::every(GB_FRAME_DURATION).map(Message::Tick));
+        if self.machine.is_running() {
+            let frame_duration = if self.minimized && self.background_throttle {
+                BACKGROUND_FRAME_DURATION
+            } else {
+                GB_FRAME_DURATION
+            };
+            subscriptions.push(time::every(frame_duration).map(Message::Tick));
         };
+        subscriptions.push(window::events().filter_map(window_event));
+        if self.layout_resized.is_some() {
+            subscriptions.push(time::every(LAYOUT_SAVE_DELAY).map(Message::SaveResizedLayout));
+        }
+        if self.audio_changed.is_some() {
+            subscriptions.push(time::every(AUDIO_SAVE_DELAY).map(Message::SaveAudioSettings));
+        }
 
-        subscriptions.push(keyboard::listen().filter_map(|event| {
-            if let Event::KeyPressed {
-                key,
-                modifiers: _modifiers,
-                ..
-            } = event
-            {
-                match key.as_ref() {
-                    Key::Named(Named::F7) => Some(Message::Step),
-                    Key::Character("r") => Some(Message::Reset),
-                    Key::Named(Named::F10) => Some(Message::StepFrame),
-                    Key::Named(Named::Space) => Some(Message::TogglePlayback),
-                    Key::Named(Named::Escape) => Some(Message::CloseWindow),
-                    Key::Character("l") => Some(Message::OpenFile),
-
-                    Key::Named(Named::ArrowUp) => Some(Message::ButtonsPressed(JoypadButton::Up)),
-                    Key::Named(Named::ArrowDown) => Some(Message::ButtonsPressed(JoypadButton::Down)),
-                    Key::Named(Named::ArrowLeft) => Some(Message::ButtonsPressed(JoypadButton::Left)),
-                    Key::Named(Named::ArrowRight) => Some(Message::ButtonsPressed(JoypadButton::Right)),
-                    Key::Character("d") => Some(Message::ButtonsPressed(JoypadButton::A)),
-                    Key::Character("f") => Some(Message::ButtonsPressed(JoypadButton::B)),
-                    Key::Character("c") => Some(Message::ButtonsPressed(JoypadButton::Start)),
-                    Key::Character("v") => Some(Message::ButtonsPressed(JoypadButton::Select)),
-
-                    _ => None,
-                }
-            } else if let Event::KeyReleased {
-                key,
-                modifiers: _modifiers,
-                ..
-            } = event
-            {
-                match key.as_ref() {
-                    Key::Named(Named::ArrowUp) => Some(Message::ButtonsReleased(JoypadButton::Up)),
-                    Key::Named(Named::ArrowDown) => Some(Message::ButtonsReleased(JoypadButton::Down)),
-                    Key::Named(Named::ArrowLeft) => Some(Message::ButtonsReleased(JoypadButton::Left)),
-                    Key::Named(Named::ArrowRight) => Some(Message::ButtonsReleased(JoypadButton::Right)),
-                    Key::Character("d") => Some(Message::ButtonsReleased(JoypadButton::A)),
-                    Key::Character("f") => Some(Message::ButtonsReleased(JoypadButton::B)),
-                    Key::Character("c") => Some(Message::ButtonsReleased(JoypadButton::Start)),
-                    Key::Character("v") => Some(Message::ButtonsReleased(JoypadButton::Select)),
-
-                    _ => None,
-                }
-            } else {
-                None
-            }
-        }));
+        if self.command_palette.is_open() {
+            subscriptions.push(self.command_palette.subscription().map(Message::CommandPalette));
+        } else {
+            subscriptions.push(keyboard::listen().filter_map(key_pressed));
+        }
+        subscriptions.push(keyboard::listen().filter_map(key_released));
 
         Subscription::batch(subscriptions)
     }
     pub fn update(&mut self, message: Message) -> Task<Message> {
+        let mut task = self.handle(message);
+        if self.workspace.is_visible(Panel::Memory) {
+            self.view_memory_state.refresh(&self.machine);
+        }
+        if self.workspace.is_visible(Panel::Tiles) {
+            self.view_tiles_state.refresh(&self.machine);
+        }
+        if self.workspace.is_visible(Panel::RomBrowser) && self.rom_browser.needs_scan() {
+            task = Task::batch([task, self.rom_browser.scan().map(Message::RomBrowser)]);
+        }
+        task
+    }
+    fn handle(&mut self, message: Message) -> Task<Message> {
         match message {
             // Execution control
             Message::Tick(_now) => self.do_tick(),
@@ -140,19 +333,222 @@ impl App {
             Message::Reset => self.do_reset(),
 
             // User interface
-            Message::CloseWindow => window::latest().and_then(window::close),
+            Message::CloseWindow => {
+                if self.layout_resized.is_some() {
+                    self.save_layout();
+                }
+                if self.audio_changed.is_some() {
+                    self.save_audio_settings();
+                }
+                self.record_play_stats();
+                if let Err(e) = self.machine.flush_sram() {
+                    error!("Failed to write battery save: {e}");
+                }
+                window::latest().and_then(window::close)
+            }
             Message::OpenFile => self.open_file(),
+            Message::OpenPatch => self.open_patch(),
+            Message::CommandPalette(msg) => {
+                let (task, command) = self.command_palette.update(msg);
+                let task = task.map(Message::CommandPalette);
+                match command {
+                    Some(command) => Task::batch([task, self.update(command)]),
+                    None => task,
+                }
+            }
+            Message::Keybindings(view_keybindings::Message::SetTurbo(button, rate)) => {
+                self.machine.turbo_mut().set_rate(button, rate.0);
+                bindings::save_turbo(&mut self.config, self.machine.turbo());
+                self.save_config();
+                Task::none()
+            }
+            Message::Keybindings(msg) => {
+                if self.view_keybindings_state.update(&mut self.bindings, msg) {
+                    self.save_bindings();
+                }
+                Task::none()
+            }
+            Message::RequestInterrupt(interrupt) => {
+                self.machine.request_interrupt(interrupt);
+                Task::none()
+            }
+            Message::SetColorPalette(palette) => {
+                self.machine.set_color_palette(palette);
+                self.view_save_slots_state.refresh(&self.save_slots());
+                self.update_screen()
+            }
+            Message::SetAudioSettings(settings) => self.set_audio_settings(settings),
+            Message::SetPalette(register, palette) => {
+                register.write(&mut self.machine, palette);
+                Task::none()
+            }
+            Message::ToggleLayer(layer) => {
+                let hidden = self.machine.hidden_layers();
+                self.machine.set_hidden_layers(hidden.symmetric_difference(layer));
+                Task::none()
+            }
+            Message::ToggleFrameBlend => {
+                let factor = match self.screen.frame_blend() {
+                    Some(_) => None,
+                    None => Some(FrameBlend::DEFAULT_FACTOR),
+                };
+                self.set_frame_blend(factor)
+            }
+            Message::SetFrameBlend(factor) => self.set_frame_blend(factor),
+            Message::TogglePauseOnFocusLoss => {
+                self.pause_on_focus_loss = !self.pause_on_focus_loss;
+                self.config
+                    .set(PAUSE_ON_FOCUS_LOSS_KEY, self.pause_on_focus_loss.to_string());
+                self.save_config();
+                Task::none()
+            }
+            Message::ToggleBackgroundThrottle => {
+                self.background_throttle = !self.background_throttle;
+                self.config
+                    .set(BACKGROUND_THROTTLE_KEY, self.background_throttle.to_string());
+                self.save_config();
+                Task::none()
+            }
+            Message::ToggleInputDisplay => {
+                self.input_display = !self.input_display;
+                self.config.set(INPUT_DISPLAY_KEY, self.input_display.to_string());
+                self.save_config();
+                Task::none()
+            }
+            Message::SetAccuracy(accuracy) => {
+                self.machine.set_accuracy(accuracy);
+                self.config.set(ACCURACY_KEY, accuracy.to_string());
+                self.save_config();
+                Task::none()
+            }
+            Message::CopyBuildInfo => iced::clipboard::write(view_about::report(&self.machine)),
+            Message::ToggleUpdateCheck => {
+                self.update_check = !self.update_check;
+                self.latest_release = None;
+                self.config.set(UPDATE_CHECK_KEY, self.update_check.to_string());
+                self.save_config();
+                Task::done(Message::CheckForUpdate)
+            }
+            Message::CheckForUpdate if self.update_check => view_about::check_latest_release(),
+            Message::CheckForUpdate => Task::none(),
+            Message::UpdateChecked(result) => {
+                self.latest_release = Some(result);
+                Task::none()
+            }
+            Message::WindowEvent(id, event) => self.window_event(id, event),
+            Message::WindowMinimized(minimized) => {
+                self.minimized = minimized;
+                Task::none()
+            }
+
+            // Save states
+            Message::SaveSlot(slot) => self.save_slot(slot),
+            Message::LoadSlot(slot) => self.load_slot(slot),
+
+            // Recorded sessions
+            Message::ToggleSessionRecording => self.toggle_session_recording(),
+            Message::OpenSession => self.open_session(),
+            Message::SessionSeek(frame) => self.session_seek(frame),
+            Message::CloseSession => {
+                self.session = None;
+                Task::none()
+            }
+            Message::TimelineToggle(frame, joypad_button) => self.timeline_edit(|session| {
+                if let Some(&pressed) = session.inputs().get(frame as usize) {
+                    session.set_input(frame, pressed ^ 1 << joypad_button.bit());
+                }
+            }),
+            Message::TimelineInsert(frame) => self.timeline_edit(|session| session.insert_frame(frame, 0)),
+            Message::TimelineRemove(frame) => self.timeline_edit(|session| session.remove_frame(frame)),
+            Message::TimelineRecord => self.timeline_record(),
 
             // Breakpoint management
             Message::BreakpointRemove => self.breakpoint_clear(),
             Message::BreakpointSet(addr) => self.breakpoint_set(addr),
+            Message::BreakpointToggle(addr) => self.breakpoint_toggle(addr),
             Message::BreakpointInputChanged(content) => self.breakpoint_update_input(content),
+            Message::BreakOnInterruptToggle(interrupt) => self.break_on_interrupt_toggle(interrupt),
+            Message::BreakOnRstToggle(vector) => self.break_on_rst_toggle(vector),
+            Message::BreakOutsideCodeToggle => {
+                let breakpoints = self.machine.breakpoint_manager_mut();
+                breakpoints.break_on_outside_code(!breakpoints.has_outside_code_break());
+                Task::none()
+            }
+            Message::FreezeToggle(address) => self.freeze_toggle(address),
+            Message::ReadOnlyToggle(start, end) => self.read_only_toggle(start..=end),
+            Message::ClearMemoryProtection => {
+                self.machine.memory_protection_mut().clear();
+                Task::none()
+            }
+            Message::BreakAfterCycles(cycles) => self.break_after(cycles),
+            Message::BreakAfterInputChanged(content) => self.break_after_update_input(content),
+            Message::DumpFrames(count) => self.dump_frames(count),
+            Message::ToggleGifRecording => self.toggle_gif_recording(),
+            Message::RunUntil => self.run_until(),
+            Message::RunUntilInputChanged(content) => self.run_until_update_input(content),
+            Message::TakeSnapshot => {
+                self.state_snapshot = Some(self.machine.snapshot());
+                self.state_diff = None;
+                Task::none()
+            }
+            Message::CompareWithSnapshot => {
+                self.state_diff = self
+                    .state_snapshot
+                    .as_ref()
+                    .map(|snapshot| self.machine.diff_since(snapshot));
+                Task::none()
+            }
 
             // Visual components
             Message::ScreenView(msg) => self.screen.update(msg).map(Message::ScreenView),
             Message::MemoryView(msg) => self.view_memory_state.update(msg).map(Message::MemoryView),
+            Message::TilesView(msg) => {
+                self.view_tiles_state.update(msg);
+                Task::none()
+            }
+            Message::RomBrowser(view_rom_browser::Message::AddFolder) => self.add_rom_folder(),
+            Message::RomBrowser(view_rom_browser::Message::RemoveFolder(index)) => {
+                self.rom_browser.remove_folder(index);
+                self.save_rom_folders();
+                Task::none()
+            }
+            Message::RomBrowser(view_rom_browser::Message::Open(path)) => self.load_rom(&path),
+            Message::RomBrowser(msg) => self.rom_browser.update(msg).map(Message::RomBrowser),
+            Message::SortOpcodes(column) => {
+                self.opcode_sort = self.opcode_sort.by(column);
+                Task::none()
+            }
+            Message::ResetProfile => {
+                #[cfg(feature = "profiling")]
+                self.machine.reset_profile();
+                Task::none()
+            }
+            Message::Workspace(msg) => self.update_workspace(msg),
+            Message::SaveResizedLayout(now) => {
+                if self
+                    .layout_resized
+                    .is_some_and(|resized| now.saturating_duration_since(resized) >= LAYOUT_SAVE_DELAY)
+                {
+                    self.save_layout();
+                }
+                Task::none()
+            }
+            Message::SaveAudioSettings(now) => {
+                if self
+                    .audio_changed
+                    .is_some_and(|changed| now.saturating_duration_since(changed) >= AUDIO_SAVE_DELAY)
+                {
+                    self.save_audio_settings();
+                }
+                Task::none()
+            }
 
             // Machine inputs
+            Message::KeyPressed(key, modifiers, physical_key) => self.key_pressed(&key, modifiers, physical_key),
+            Message::KeyReleased(key) => match self.bindings.released(&key) {
+                Some(message) => self.handle(message),
+                None => Task::none(),
+            },
             Message::ButtonsPressed(button) => {
                 self.machine.button_pressed(button);
                 Task::none()
@@ -161,85 +557,344 @@ impl App {
                 self.machine.button_released(button);
                 Task::none()
             }
+            Message::MacroRecordToggle(slot) => self.macro_record_toggle(slot),
+            Message::MacroPlay(slot) => self.macro_play(slot),
         }
     }
     pub fn view(&self) -> Element<'_, Message> {
-        let controls = view_control_panel(self.is_running, self);
+        let controls = view_control_panel(self.machine.is_running(), self);
+        let panels = view_panel_toggles(&self.workspace);
 
-        let cpu_state = title_panel("CPU", view_cpu::view(self.machine.cpu())).center_x(200);
+        let panes = pane_grid(self.workspace.panes(), |pane, &panel, is_maximized| {
+            let title = text(panel.title()).center().width(Fill);
 
-        let io_registers = title_panel("IO REGISTERS", view_registers::view(&self.machine)).center_x(500);
+            let maximize = if is_maximized {
+                button(text("Restore").size(12)).on_press(workspace::Message::Restore)
+            } else {
+                button(text("Max").size(12)).on_press(workspace::Message::Maximize(pane))
+            };
+            let hide = button(text("Hide").size(12)).on_press(workspace::Message::Toggle(panel));
+            let pane_controls = row![maximize.style(button::secondary), hide.style(button::secondary)].spacing(4);
 
-        let screen = title_panel(
-            "SCREEN",
-            container(self.screen.view(self.machine.frame()).map(Message::ScreenView))
-                .padding(4)
-                .into(),
-        )
-        .center_x(170);
-
-        let memory = title_panel(
-            "MEMORY",
-            view_memory::view(&self.view_memory_state, &self.machine).map(Message::MemoryView),
-        )
-        .center_x(550)
-        .height(370);
-
-        let content = column![
-            controls,
-            row![cpu_state, io_registers, screen].spacing(COLUMN_SPACING),
-            memory
-        ]
+            let body = scrollable(container(self.view_panel(panel)).padding(4)).direction(Direction::Both {
+                vertical: Scrollbar::default(),
+                horizontal: Scrollbar::default(),
+            });
+
+            pane_grid::Content::new(body)
+                .title_bar(
+                    pane_grid::TitleBar::new(title)
+                        .controls(pane_grid::Controls::new(
+                            Element::from(pane_controls).map(Message::Workspace),
+                        ))
+                        .padding(2)
+                        .style(panel_title),
+                )
+                .style(panel_content)
+        })
         .spacing(COLUMN_SPACING)
-        .padding(CONTENT_PADDING);
+        .on_drag(|event| Message::Workspace(workspace::Message::Dragged(event)))
+        .on_resize(10, |event| Message::Workspace(workspace::Message::Resized(event)));
 
-        Element::from(scrollable(content).direction(Direction::Both {
-            vertical: Scrollbar::default(),
-            horizontal: Scrollbar::default(),
-        }))
+        let content = column![controls]
+            .push(view_stopped_banner(self.machine.status()))
+            .push(view_session_player(self.session.as_ref()))
+            .push(panels)
+            .push(panes)
+            .spacing(COLUMN_SPACING)
+            .padding(CONTENT_PADDING);
+
+        if self.command_palette.is_open() {
+            let palette =
+                view_command_palette::view(&self.command_palette, &self.bindings).map(Message::CommandPalette);
+            stack![content, opaque(container(palette).center_x(Fill).padding(60))].into()
+        } else {
+            content.into()
+        }
     }
 
-    fn do_tick(&mut self) -> Task<Message> {
-        let (cycles, break_flag) = self.machine.step_frame().unwrap_or_else(|e| {
-            error!("{}", e);
-            self.is_running = false;
-            (0, false)
-        });
-        self.total_cycles += cycles as u64;
+    fn view_panel(&self, panel: Panel) -> Element<'_, Message> {
+        match panel {
+            Panel::Screen => {
+                let screen = self
+                    .screen
+                    .view(
+                        self.machine.frame(),
+                        self.machine.frame_rgb555(),
+                        self.machine.color_palette(),
+                    )
+                    .map(Message::ScreenView);
+                match self.input_display {
+                    true => stack![screen, view_input_display::view(self.machine.input_state())].into(),
+                    false => screen,
+                }
+            }
+            Panel::Cpu => view_cpu::view(self.machine.cpu()),
+            Panel::IoRegisters => view_registers::view(&self.machine),
+            Panel::Memory => container(view_memory::view(&self.view_memory_state).map(Message::MemoryView))
+                .width(550)
+                .into(),
+            Panel::SaveStates => view_save_slots::view(&self.view_save_slots_state, self.machine.color_palette()),
+            Panel::Mapper => view_mapper::view(&self.machine),
+            Panel::Audio => view_audio::view(
+                audio_settings(&self.config),
+                self.audio.as_ref().map(AudioDevice::stream),
+            ),
+            Panel::Palettes => view_palettes::view(&self.machine),
+            Panel::Tiles => view_tiles::view(&self.view_tiles_state, &self.machine),
+            Panel::Opcodes => view_opcodes::view(&self.machine, self.opcode_sort),
+            Panel::RomBrowser => view_rom_browser::view(&self.rom_browser, self.machine.color_palette(), &self.config)
+                .map(Message::RomBrowser),
+            Panel::Stats => view_stats::view(&self.machine, self.play_stats()),
+            Panel::Keybindings => {
+                view_keybindings::view(&self.view_keybindings_state, &self.bindings, self.machine.turbo())
+                    .map(Message::Keybindings)
+            }
+            Panel::Diff => view_diff::view(self.state_snapshot.as_ref(), self.state_diff.as_ref()),
+            Panel::Timeline => view_timeline::view(self.session.as_ref()),
+            Panel::About => view_about::view(&self.machine, self.update_check, self.latest_release.as_ref()),
+        }
+    }
+
+    fn update_workspace(&mut self, message: workspace::Message) -> Task<Message> {
+        self.workspace.update(message);
 
-        if break_flag {
-            self.is_running = false;
+        match message {
+            workspace::Message::Dragged(DragEvent::Picked { .. } | DragEvent::Canceled { .. }) => {}
+            // Saved once the resizing pauses, not for each step of the drag
+            workspace::Message::Resized(_) => self.layout_resized = Some(Instant::now()),
+            _ => self.save_layout(),
         }
+        Task::none()
+    }
 
-        self.update(Message::ScreenView(screen::Message::UpdateFrameBuffer))
+    fn save_layout(&mut self) {
+        self.layout_resized = None;
+        self.config.set(LAYOUT_KEY, self.workspace.layout());
+        self.save_config();
+    }
+
+    /// Play the sound on the default output device, replacing the device opened before
+    fn open_audio(&mut self, settings: AudioSettings) {
+        // The sink of the previous device is removed from the machine once its stream is dropped
+        self.audio = None;
+        self.audio = AudioDevice::open(&mut self.machine, settings)
+            .inspect_err(|e| error!("Failed to open the audio output: {e}"))
+            .ok();
+    }
+
+    fn set_audio_settings(&mut self, settings: AudioSettings) -> Task<Message> {
+        self.open_audio(settings);
+        self.config.set(AUDIO_SAMPLE_RATE_KEY, settings.sample_rate.to_string());
+        self.config.set(AUDIO_QUALITY_KEY, settings.quality.to_string());
+        self.config
+            .set(AUDIO_LATENCY_KEY, settings.latency.as_millis().to_string());
+        // Saved once the changes pause, not for each step of the latency slider
+        self.audio_changed = Some(Instant::now());
+        Task::none()
+    }
+
+    fn save_audio_settings(&mut self) {
+        self.audio_changed = None;
+        self.save_config();
+    }
+
+    /// Config key of a setting of the current game, keyed on the header hash like the play statistics so games
+    /// sharing a title, or without one, keep their own settings
+    fn game_key(&self, name: &str) -> String {
+        format!("{name}.{:08x}", self.machine.cartridge().header_hash())
+    }
+
+    /// Settings stored for the game in the cartridge
+    fn load_game_settings(&mut self) {
+        let frame_blend = self
+            .config
+            .get(&self.game_key(FRAME_BLEND_KEY))
+            .and_then(|factor| factor.parse().ok());
+        self.screen.set_frame_blend(frame_blend);
+    }
+
+    fn set_frame_blend(&mut self, factor: Option<u8>) -> Task<Message> {
+        self.screen.set_frame_blend(factor);
+        let value = factor.map_or("off".to_string(), |factor| factor.to_string());
+        self.config.set(&self.game_key(FRAME_BLEND_KEY), value);
+        self.save_config();
+        self.update_screen()
+    }
+
+    /// Reset the machine, the play time of the game until then is added to its statistics
+    fn reset_machine(&mut self) {
+        self.record_play_stats();
+        self.machine.reset();
+        self.emulated_time_mark = Duration::ZERO;
     }
-    fn toggle_playback(&mut self) -> Task<Message> {
-        self.is_running = !self.is_running;
 
-        if !self.is_running {
+    /// Statistics of the current game, with the play time not recorded yet
+    fn play_stats(&self) -> PlayStats {
+        let mut stats = PlayStats::load(&self.config, self.machine.cartridge().header_hash());
+        stats.emulated += self.machine.emulated_time().saturating_sub(self.emulated_time_mark);
+        stats.wall += self.unsaved_wall_time;
+        stats
+    }
+
+    /// Add the play time since the last call to the statistics of the current game
+    fn record_play_stats(&mut self) {
+        let emulated = self.machine.emulated_time().saturating_sub(self.emulated_time_mark);
+        let wall = std::mem::take(&mut self.unsaved_wall_time);
+        self.emulated_time_mark = self.machine.emulated_time();
+        if emulated.is_zero() && wall.is_zero() {
+            return;
+        }
+
+        let header_hash = self.machine.cartridge().header_hash();
+        let mut stats = PlayStats::load(&self.config, header_hash);
+        stats.emulated += emulated;
+        stats.wall += wall;
+        stats.last_played = Some(SystemTime::now());
+        stats.save(&mut self.config, header_hash);
+        self.save_config();
+    }
+
+    /// Key press outside the command palette: captured by the key editor, else a save slot, a macro or a binding
+    fn key_pressed(&mut self, key: &Key, modifiers: Modifiers, physical_key: Physical) -> Task<Message> {
+        if self.view_keybindings_state.is_capturing() {
+            if self.view_keybindings_state.capture(&mut self.bindings, key, modifiers) {
+                self.save_bindings();
+            }
+            return Task::none();
+        }
+
+        if let Some(slot) = slot_key(physical_key) {
+            return self.handle(if modifiers.shift() {
+                Message::SaveSlot(slot)
+            } else {
+                Message::LoadSlot(slot)
+            });
+        }
+
+        if let Key::Named(named) = key.as_ref()
+            && let Some(slot) = MACRO_KEYS.iter().position(|&macro_key| macro_key == named)
+        {
+            return self.handle(if modifiers.shift() {
+                Message::MacroRecordToggle(slot + 1)
+            } else {
+                Message::MacroPlay(slot + 1)
+            });
+        }
+
+        match self.bindings.pressed(key, modifiers) {
+            Some(message) => self.handle(message),
+            None => Task::none(),
+        }
+    }
+
+    fn save_bindings(&mut self) {
+        self.bindings.save(&mut self.config);
+        self.save_config();
+    }
+
+    fn save_config(&self) {
+        if let Err(e) = self.config.save() {
+            error!("Failed to write config: {e}");
+        }
+    }
+
+    fn do_tick(&mut self) -> Task<Message> {
+        // Paced on the sound buffer with an audio device, only on the ticks without
+        let frames = self.audio.as_ref().map_or(1, AudioDevice::frames_to_run);
+        for _ in 0..frames {
+            let result = self.machine.step_frame().unwrap_or_else(|e| {
+                self.report_crash(e.as_ref());
+                FrameResult::default()
+            });
+            self.total_cycles += result.cycles as u64;
+            if !result.frame_completed {
+                break;
+            }
+        }
+        let now = Instant::now();
+        if let Some(last) = self.last_update.replace(now) {
+            self.unsaved_wall_time += (now - last).min(MAX_TICK_GAP);
+        }
+
+        self.update_screen()
+    }
+    fn toggle_playback(&mut self) -> Task<Message> {
+        self.paused_by_focus_loss = false;
+        if self.machine.is_running() {
+            self.machine.pause();
             self.last_update = None;
+            self.record_play_stats();
+        } else {
+            self.machine.resume();
         }
 
         Task::none()
     }
+    /// Pause while the window is unfocused and track its minimized state, for the background options
+    fn window_event(&mut self, id: window::Id, event: window::Event) -> Task<Message> {
+        match event {
+            window::Event::Focused if self.paused_by_focus_loss => {
+                self.paused_by_focus_loss = false;
+                self.machine.resume();
+            }
+            window::Event::Unfocused if self.pause_on_focus_loss && self.machine.is_running() => {
+                self.paused_by_focus_loss = true;
+                self.machine.pause();
+                self.last_update = None;
+            }
+            _ => {}
+        }
+        // Minimizing is only reported through the focus and size changes
+        window::is_minimized(id).map(|minimized| Message::WindowMinimized(minimized.unwrap_or(false)))
+    }
     fn do_step(&mut self) -> Task<Message> {
-        self.is_running = false;
-        self.total_cycles += self.machine.step().expect("Failed to step") as u64;
+        self.machine.pause();
+        match self.machine.step() {
+            Ok(cycles) => self.total_cycles += cycles as u64,
+            Err(e) => self.report_crash(e.as_ref()),
+        }
         Task::none()
     }
     fn do_step_frame(&mut self) -> Task<Message> {
-        self.is_running = false;
+        self.machine.pause();
 
-        let (cycles, _) = self.machine.step_frame().unwrap_or_else(|e| {
-            error!("{}", e);
-            (0, false)
+        let result = self.machine.step_frame().unwrap_or_else(|e| {
+            self.report_crash(e.as_ref());
+            FrameResult::default()
         });
 
-        self.total_cycles += cycles as u64;
-        self.update(Message::ScreenView(screen::Message::UpdateFrameBuffer))
+        self.total_cycles += result.cycles as u64;
+        self.update_screen()
+    }
+    /// Stop the emulation, write the post-mortem report and show it
+    fn report_crash(&mut self, error: &dyn std::error::Error) {
+        let report = self.machine.crash_report(&error.to_string());
+        let location = match report.write(&self.crash_dir) {
+            Ok(path) => format!("Report written to {}", path.display()),
+            Err(e) => format!("Failed to write the report: {e}"),
+        };
+        error!("{error}, {location}");
+
+        rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Error)
+            .set_title("Emulation error")
+            .set_description(format!("{}\n\n{location}", report.summary()))
+            .set_buttons(rfd::MessageButtons::Ok)
+            .show();
+    }
+    /// Redraw the lines of the screen changed since the last update
+    fn update_screen(&mut self) -> Task<Message> {
+        self.screen.filter(
+            self.machine.frame(),
+            self.machine.frame_rgb555(),
+            self.machine.color_palette(),
+        );
+        let changed_lines = self.machine.take_changed_lines();
+        self.update(Message::ScreenView(screen::Message::UpdateFrameBuffer(changed_lines)))
     }
     fn do_reset(&mut self) -> Task<Message> {
-        self.machine.reset();
+        self.reset_machine();
         self.screen.clear();
         self.total_cycles = 0;
         Task::none()
@@ -250,12 +905,231 @@ impl App {
             .add_filter("Rom", &["gb", "zip"])
             .add_filter("All files", &["*"]);
 
+        match dialog.pick_file() {
+            Some(path) => self.load_rom(&path),
+            None => Task::none(),
+        }
+    }
+    fn load_rom(&mut self, path: &Path) -> Task<Message> {
+        self.reset_machine();
+        self.state_snapshot = None;
+        self.state_diff = None;
+        if let Err(e) = self.machine.load_cartridge(path) {
+            error!("Failed to load {}: {e}", path.display());
+            rfd::MessageDialog::new()
+                .set_level(rfd::MessageLevel::Error)
+                .set_title("Unable to load the rom")
+                .set_description(format!("{}\n\n{e}", path.display()))
+                .set_buttons(rfd::MessageButtons::Ok)
+                .show();
+            return Task::none();
+        }
+        self.view_save_slots_state.refresh(&self.save_slots());
+        self.load_game_settings();
+        self.machine.resume();
+        Task::none()
+    }
+    fn add_rom_folder(&mut self) -> Task<Message> {
+        if let Some(folder) = rfd::FileDialog::new().set_title("Add ROM folder").pick_folder() {
+            self.rom_browser.add_folder(folder);
+            self.save_rom_folders();
+        }
+        Task::none()
+    }
+    fn save_rom_folders(&mut self) {
+        match std::env::join_paths(self.rom_browser.folders()) {
+            Ok(folders) => {
+                self.config.set(ROM_DIRS_KEY, folders.to_string_lossy().into_owned());
+                self.save_config();
+            }
+            Err(e) => error!("Failed to save the ROM folders: {e}"),
+        }
+    }
+    /// Restart the game from its rom patched with an IPS or BPS file, e.g. a translation
+    fn open_patch(&mut self) -> Task<Message> {
+        let dialog = rfd::FileDialog::new()
+            .set_title("Apply patch")
+            .add_filter("Patch", &["ips", "bps"])
+            .add_filter("All files", &["*"]);
+
         if let Some(path) = dialog.pick_file() {
-            self.machine.reset();
-            self.machine.load_cartridge(path).expect("Failed to load rom");
-            self.is_running = true;
+            self.reset_machine();
+            if let Err(e) = std::fs::read(&path).and_then(|patch| self.machine.apply_patch(&patch)) {
+                error!("Failed to apply {}: {e}", path.display());
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Unable to apply the patch")
+                    .set_description(format!("{}\n\n{e}", path.display()))
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .show();
+                return Task::none();
+            }
+            self.view_save_slots_state.refresh(&self.save_slots());
+            self.load_game_settings();
+        }
+
+        Task::none()
+    }
+    /// Write the next frames to `frames/`, e.g. to inspect an animation frame by frame
+    fn dump_frames(&mut self, count: usize) -> Task<Message> {
+        if let Err(e) = self.machine.dump_frames(count, FRAME_DUMP_DIR) {
+            error!("Failed to start the frame dump: {e}");
+        }
+        Task::none()
+    }
+    /// Record the screen into an animated GIF, until toggled again
+    fn toggle_gif_recording(&mut self) -> Task<Message> {
+        if self.recording_gif {
+            self.machine.clear_video_sinks();
+            self.recording_gif = false;
+            return Task::none();
+        }
+
+        let dialog = rfd::FileDialog::new()
+            .set_title("Record GIF")
+            .add_filter("GIF", &["gif"])
+            .set_file_name(format!("{}.gif", self.machine.cartridge().title().trim()));
+        let Some(path) = dialog.save_file() else {
+            return Task::none();
+        };
+        match GifRecorder::create(&path) {
+            Ok(recorder) => {
+                self.machine.add_video_sink(recorder);
+                self.recording_gif = true;
+            }
+            Err(e) => error!("Failed to create {}: {e}", path.display()),
+        }
+        Task::none()
+    }
+    fn save_slots(&self) -> SaveSlots {
+        SaveSlots::for_machine(&self.save_dir, &self.machine)
+    }
+    fn save_slot(&mut self, slot: usize) -> Task<Message> {
+        let save_slots = self.save_slots();
+        if let Err(e) = save_slots.save(slot, &self.machine) {
+            error!("Failed to save slot {slot}: {e}");
+        }
+        self.view_save_slots_state.refresh(&save_slots);
+        Task::none()
+    }
+    fn load_slot(&mut self, slot: usize) -> Task<Message> {
+        if let Err(e) = self.save_slots().load(slot, &mut self.machine) {
+            error!("Failed to load slot {slot}: {e}");
         }
+        self.update_screen()
+    }
+    fn toggle_session_recording(&mut self) -> Task<Message> {
+        let Some(session) = self.machine.stop_session_recording() else {
+            self.machine.start_session_recording(DEFAULT_SNAPSHOT_INTERVAL);
+            return Task::none();
+        };
 
+        let dialog = rfd::FileDialog::new()
+            .set_title("Save session")
+            .add_filter("Session", &["gbsn"])
+            .set_file_name(format!("{}.gbsn", session.title().trim()));
+        if let Some(path) = dialog.save_file()
+            && let Err(e) = session.save(&path)
+        {
+            error!("Failed to write session {}: {e}", path.display());
+        }
+        Task::none()
+    }
+    /// Open a session of the current game in the player, paused on its first frame
+    fn open_session(&mut self) -> Task<Message> {
+        let dialog = rfd::FileDialog::new()
+            .set_title("Open session")
+            .add_filter("Session", &["gbsn"])
+            .add_filter("All files", &["*"]);
+        let Some(path) = dialog.pick_file() else {
+            return Task::none();
+        };
+
+        let session = Session::load(&path).and_then(|session| {
+            if session.title() != self.machine.cartridge().title() {
+                return Err(std::io::Error::other(format!(
+                    "recorded with \"{}\", load this rom first",
+                    session.title()
+                )));
+            }
+            Ok(session)
+        });
+        match session {
+            Ok(session) => {
+                self.machine.pause();
+                self.session = Some((session, 0));
+                self.session_seek(0)
+            }
+            Err(e) => {
+                error!("Failed to open session {}: {e}", path.display());
+                rfd::MessageDialog::new()
+                    .set_level(rfd::MessageLevel::Error)
+                    .set_title("Unable to open the session")
+                    .set_description(format!("{}\n\n{e}", path.display()))
+                    .set_buttons(rfd::MessageButtons::Ok)
+                    .show();
+                Task::none()
+            }
+        }
+    }
+    fn session_seek(&mut self, frame: u32) -> Task<Message> {
+        let Some((session, position)) = self.session.as_mut() else {
+            return Task::none();
+        };
+        *position = frame.min(session.frame_count().saturating_sub(1));
+        if let Err(e) = session.seek(&mut self.machine, *position) {
+            error!("Failed to seek session frame {frame}: {e}");
+        }
+        self.machine.pause();
+        self.update_screen()
+    }
+    /// Apply an edit of the TIMELINE panel to the opened session and replay it up to the shown frame
+    fn timeline_edit(&mut self, edit: impl FnOnce(&mut Session)) -> Task<Message> {
+        let Some((session, position)) = self.session.as_mut() else {
+            return Task::none();
+        };
+        edit(session);
+        let position = *position;
+        self.session_seek(position)
+    }
+    /// Record over the opened session from the shown frame, the frames after it are replaced
+    fn timeline_record(&mut self) -> Task<Message> {
+        let Some((session, frame)) = self.session.take() else {
+            return Task::none();
+        };
+        if let Err(e) = self.machine.resume_session_recording(session, frame) {
+            error!("Failed to resume the session recording at frame {frame}: {e}");
+            return Task::none();
+        }
+        self.machine.resume();
+        self.update_screen()
+    }
+    /// Start recording the macro slot, or stop the recording and store it in the settings of the game
+    fn macro_record_toggle(&mut self, slot: usize) -> Task<Message> {
+        let Some(recording) = self.macro_recording.take() else {
+            self.machine.start_macro_recording();
+            self.macro_recording = Some(slot);
+            return Task::none();
+        };
+
+        let input_macro = self.machine.stop_macro_recording().unwrap_or_default();
+        self.config.set(
+            &self.game_key(&format!("{MACRO_KEY}{recording}")),
+            input_macro.to_string(),
+        );
+        self.save_config();
+        Task::none()
+    }
+    fn macro_play(&mut self, slot: usize) -> Task<Message> {
+        let input_macro = self
+            .config
+            .get(&self.game_key(&format!("{MACRO_KEY}{slot}")))
+            .map(str::parse::<InputMacro>);
+        match input_macro {
+            Some(Ok(input_macro)) => self.machine.play_macro(input_macro),
+            Some(Err(e)) => error!("Invalid macro {slot}: {e}"),
+            None => {}
+        }
         Task::none()
     }
     fn breakpoint_clear(&mut self) -> Task<Message> {
@@ -263,14 +1137,143 @@ impl App {
         Task::none()
     }
     fn breakpoint_set(&mut self, addr: u16) -> Task<Message> {
-        self.is_running = true;
+        self.machine.resume();
         self.machine.breakpoint_manager_mut().add_breakpoint(addr);
         Task::none()
     }
+    fn breakpoint_toggle(&mut self, addr: u16) -> Task<Message> {
+        let breakpoints = self.machine.breakpoint_manager_mut();
+        if breakpoints.has_breakpoint(addr) {
+            breakpoints.remove_breakpoint(addr);
+        } else {
+            breakpoints.add_breakpoint(addr);
+        }
+        Task::none()
+    }
+    fn break_on_interrupt_toggle(&mut self, interrupt: Interrupt) -> Task<Message> {
+        let breakpoints = self.machine.breakpoint_manager_mut();
+        breakpoints.break_on_interrupts(breakpoints.interrupt_breaks() ^ interrupt);
+        Task::none()
+    }
+    fn break_on_rst_toggle(&mut self, vector: u8) -> Task<Message> {
+        let breakpoints = self.machine.breakpoint_manager_mut();
+        breakpoints.break_on_rst(vector, !breakpoints.has_rst_break(vector));
+        Task::none()
+    }
+    /// Freeze the address at its current value
+    fn freeze_toggle(&mut self, address: u16) -> Task<Message> {
+        let value = self.machine.peek(address);
+        let protection = self.machine.memory_protection_mut();
+        if protection.is_frozen(address) {
+            protection.unfreeze(address);
+        } else {
+            protection.freeze(address, value);
+        }
+        Task::none()
+    }
+    fn read_only_toggle(&mut self, region: RangeInclusive<u16>) -> Task<Message> {
+        let protection = self.machine.memory_protection_mut();
+        if protection.read_only_regions().contains(&region) {
+            protection.unprotect(&region);
+        } else {
+            protection.protect(region);
+        }
+        Task::none()
+    }
     fn breakpoint_update_input(&mut self, content: String) -> Task<Message> {
         self.breakpoint_at = content;
         Task::none()
     }
+    fn break_after(&mut self, cycles: u64) -> Task<Message> {
+        self.machine.resume();
+        self.machine.breakpoint_manager_mut().break_after_cycles(cycles);
+        Task::none()
+    }
+    fn break_after_update_input(&mut self, content: String) -> Task<Message> {
+        self.break_after_cycles = content;
+        Task::none()
+    }
+    fn run_until(&mut self) -> Task<Message> {
+        match self.machine.run_until_expr(&self.run_until) {
+            Ok(()) => self.machine.resume(),
+            Err(e) => error!("Invalid run until expression: {e}"),
+        }
+        Task::none()
+    }
+    fn run_until_update_input(&mut self, content: String) -> Task<Message> {
+        self.run_until = content;
+        Task::none()
+    }
+}
+
+/// Audio settings of the config, the defaults for missing or invalid values
+fn audio_settings(config: &Config) -> AudioSettings {
+    let default = AudioSettings::default();
+    AudioSettings {
+        sample_rate: config
+            .get(AUDIO_SAMPLE_RATE_KEY)
+            .and_then(|value| value.parse().ok())
+            .filter(|sample_rate| AudioSettings::SAMPLE_RATE_RANGE.contains(sample_rate))
+            .unwrap_or(default.sample_rate),
+        quality: config
+            .get(AUDIO_QUALITY_KEY)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default.quality),
+        latency: config
+            .get(AUDIO_LATENCY_KEY)
+            .and_then(|value| value.parse().ok())
+            .map(Duration::from_millis)
+            .unwrap_or(default.latency),
+    }
+}
+
+fn key_pressed(event: Event) -> Option<Message> {
+    match event {
+        Event::KeyPressed {
+            key,
+            modifiers,
+            physical_key,
+            ..
+        } => Some(Message::KeyPressed(key, modifiers, physical_key)),
+        _ => None,
+    }
+}
+
+fn window_event((id, event): (window::Id, window::Event)) -> Option<Message> {
+    match event {
+        window::Event::Focused | window::Event::Unfocused | window::Event::Resized(_) => {
+            Some(Message::WindowEvent(id, event))
+        }
+        _ => None,
+    }
+}
+
+fn key_released(event: Event) -> Option<Message> {
+    match event {
+        Event::KeyReleased { key, .. } => Some(Message::KeyReleased(key)),
+        _ => None,
+    }
+}
+
+/// Digit keys select a save state slot, whatever the keyboard layout
+fn slot_key(physical_key: Physical) -> Option<usize> {
+    const DIGITS: [Code; 10] = [
+        Code::Digit0,
+        Code::Digit1,
+        Code::Digit2,
+        Code::Digit3,
+        Code::Digit4,
+        Code::Digit5,
+        Code::Digit6,
+        Code::Digit7,
+        Code::Digit8,
+        Code::Digit9,
+    ];
+
+    match physical_key {
+        Physical::Code(code) => DIGITS.iter().position(|&digit| digit == code),
+        _ => None,
+    }
 }
 
 fn view_control_panel<'a>(is_running: bool, app: &App) -> Element<'a, Message> {
@@ -279,11 +1282,21 @@ fn view_control_panel<'a>(is_running: bool, app: &App) -> Element<'a, Message> {
         .on_press(Message::TogglePlayback)
         .style(button::primary);
 
-    let step_button = button("Step(F7)").on_press(Message::Step).style(button::secondary);
+    // Name of the button followed by the key bound to its command
+    let label = |name: &str, command: &str| match app.bindings.command_hotkey(command) {
+        Some(hotkey) => format!("{name}({})", hotkey.label()),
+        None => name.to_string(),
+    };
 
-    let reset_button = button("Reset(R)").on_press(Message::Reset).style(button::secondary);
+    let step_button = button(text(label("Step", "Step")))
+        .on_press(Message::Step)
+        .style(button::secondary);
 
-    let step_frame_button = button("Frame(F10)")
+    let reset_button = button(text(label("Reset", "Reset")))
+        .on_press(Message::Reset)
+        .style(button::secondary);
+
+    let step_frame_button = button(text(label("Frame", "Step frame")))
         .on_press(Message::StepFrame)
         .style(button::secondary);
 
@@ -292,6 +1305,54 @@ fn view_control_panel<'a>(is_running: bool, app: &App) -> Element<'a, Message> {
     let breakpoint_controls = view_breakpoint_controls(app);
 
     let load_rom = button("Load ROM").style(button::secondary).on_press(Message::OpenFile);
+    let patch_rom = button("Patch").style(button::secondary).on_press(Message::OpenPatch);
+
+    let macro_status = match app.macro_recording {
+        Some(slot) => text(format!("REC macro F{slot}")).color(red()),
+        None => text(""),
+    };
+
+    let record = button(if app.machine.is_recording_session() {
+        "Stop rec"
+    } else {
+        "Record"
+    })
+    .style(button::secondary)
+    .on_press(Message::ToggleSessionRecording);
+
+    let background = column![
+        checkbox(app.pause_on_focus_loss)
+            .label("Pause unfocused")
+            .text_size(12)
+            .on_toggle(|_| Message::TogglePauseOnFocusLoss),
+        checkbox(app.background_throttle)
+            .label("Throttle minimized")
+            .text_size(12)
+            .on_toggle(|_| Message::ToggleBackgroundThrottle),
+        checkbox(app.input_display)
+            .label("Input display")
+            .text_size(12)
+            .on_toggle(|_| Message::ToggleInputDisplay),
+    ]
+    .spacing(2);
+
+    let layer = |name, layer| {
+        checkbox(!app.machine.hidden_layers().contains(layer))
+            .label(name)
+            .text_size(12)
+            .on_toggle(move |_| Message::ToggleLayer(layer))
+    };
+    let layers = column![
+        layer("Background", Layers::BACKGROUND),
+        layer("Window", Layers::WINDOW),
+        layer("Sprites", Layers::SPRITES),
+    ]
+    .spacing(2);
+
+    // No preset is selected when --oam-bug changed the profile
+    let accuracy = pick_list(Accuracy::ALL, app.machine.accuracy().preset(), Message::SetAccuracy)
+        .placeholder("Custom")
+        .text_size(12);
 
     row![
         run_button,
@@ -300,6 +1361,12 @@ fn view_control_panel<'a>(is_running: bool, app: &App) -> Element<'a, Message> {
         reset_button,
         breakpoint_controls,
         load_rom,
+        patch_rom,
+        record,
+        background,
+        layers,
+        accuracy,
+        macro_status,
         total_cycles,
     ]
     .spacing(BUTTON_SPACING)
@@ -307,6 +1374,80 @@ fn view_control_panel<'a>(is_running: bool, app: &App) -> Element<'a, Message> {
     .into()
 }
 
+/// Error that stopped the emulation, with the ways out
+fn view_stopped_banner<'a>(status: &EmulationStatus) -> Option<Element<'a, Message>> {
+    let EmulationStatus::Stopped(error) = status else {
+        return None;
+    };
+
+    let banner = row![
+        text(format!("Emulation stopped: {error}")).color(red()).width(Fill),
+        button("Reset").on_press(Message::Reset).style(button::secondary),
+        button("Debug")
+            .on_press(Message::Workspace(workspace::Message::Debug))
+            .style(button::secondary),
+    ]
+    .spacing(BUTTON_SPACING)
+    .align_y(Vertical::Center);
+
+    Some(banner.into())
+}
+
+/// Frame scrubbing of the opened session
+fn view_session_player<'a>(session: Option<&(Session, u32)>) -> Option<Element<'a, Message>> {
+    let &(ref session, frame) = session?;
+    let last = session.frame_count().saturating_sub(1);
+
+    let player = row![
+        text(format!("Session \"{}\"", session.title())),
+        button("<")
+            .on_press_maybe(frame.checked_sub(1).map(Message::SessionSeek))
+            .style(button::secondary),
+        slider(0..=last, frame, Message::SessionSeek).width(Fill),
+        button(">")
+            .on_press_maybe((frame < last).then_some(Message::SessionSeek(frame + 1)))
+            .style(button::secondary),
+        text(format!("{frame} / {last}")).width(110),
+        button("Close").on_press(Message::CloseSession).style(button::secondary),
+    ]
+    .spacing(BUTTON_SPACING)
+    .align_y(Vertical::Center);
+
+    Some(player.into())
+}
+
+fn view_panel_toggles<'a>(workspace: &Workspace) -> Element<'a, Message> {
+    let toggles = Panel::ALL.into_iter().map(|panel| {
+        button(text(panel.title()).size(12))
+            .style(if workspace.is_visible(panel) {
+                button::primary
+            } else {
+                button::secondary
+            })
+            .on_press(workspace::Message::Toggle(panel))
+            .into()
+    });
+
+    let presets = [
+        button(text("Play layout").size(12))
+            .style(button::secondary)
+            .on_press(workspace::Message::Play)
+            .into(),
+        button(text("Debug layout").size(12))
+            .style(button::secondary)
+            .on_press(workspace::Message::Debug)
+            .into(),
+    ];
+
+    Element::from(
+        iced::widget::Row::with_children(toggles)
+            .push(Space::new().width(20.0))
+            .extend(presets)
+            .spacing(4),
+    )
+    .map(Message::Workspace)
+}
+
 fn view_breakpoint_controls<'a>(app: &App) -> iced::widget::Row<'a, Message> {
     let breakpoint_empty = app.machine.breakpoint_manager().len() == 0;
 
@@ -320,6 +1461,16 @@ fn view_breakpoint_controls<'a>(app: &App) -> iced::widget::Row<'a, Message> {
         }
     };
 
+    // Accept `3000000` as well as `3_000_000`
+    let break_after_action = app
+        .break_after_cycles
+        .replace('_', "")
+        .parse()
+        .ok()
+        .map(Message::BreakAfterCycles);
+
+    let run_until_action = Expression::parse(&app.run_until).ok().map(|_| Message::RunUntil);
+
     row![
         text("Breakpoint at: $"),
         text_input("Breakpoint", &app.breakpoint_at)
@@ -329,6 +1480,27 @@ fn view_breakpoint_controls<'a>(app: &App) -> iced::widget::Row<'a, Message> {
         button(if breakpoint_empty { "Go" } else { "Del" })
             .on_press_maybe(breakpoint_action())
             .style(button::secondary),
+        Space::new().width(20.0),
+        text("Break after cycles:"),
+        text_input("Cycles", &app.break_after_cycles)
+            .width(100)
+            .on_input(Message::BreakAfterInputChanged)
+            .on_submit_maybe(break_after_action.clone()),
+        button("Go").on_press_maybe(break_after_action).style(button::secondary),
+        Space::new().width(20.0),
+        text("Run until:"),
+        text_input("PC == $4000 && A != 0", &app.run_until)
+            .width(200)
+            .on_input(Message::RunUntilInputChanged)
+            .on_submit_maybe(run_until_action.clone()),
+        button("Go").on_press_maybe(run_until_action).style(button::secondary),
+        Space::new().width(20.0),
+        checkbox(app.machine.breakpoint_manager().has_rst_break(0x38))
+            .label("Break on RST $38")
+            .on_toggle(|_| Message::BreakOnRstToggle(0x38)),
+        checkbox(app.machine.breakpoint_manager().has_outside_code_break())
+            .label("Break outside ROM/RAM")
+            .on_toggle(|_| Message::BreakOutsideCodeToggle),
     ]
     .align_y(Vertical::Center)
 }
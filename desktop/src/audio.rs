@@ -0,0 +1,86 @@
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{BufferSize, FromSample, SampleFormat, SizedSample, StreamConfig};
+use gbemu_core::{AudioSettings, AudioStream, Machine, StreamSink};
+use log::error;
+use std::error::Error;
+
+/// Sound of the machine played on the default output device of the host.
+///
+/// The cpal callback drains an [`AudioStream`] from its own thread, the machine fills it at the end of each frame.
+pub struct AudioDevice {
+    stream: AudioStream,
+    /// Playing until dropped, dropping it also removes the sink from the machine
+    _output: cpal::Stream,
+}
+
+impl AudioDevice {
+    /// Open the default output device at the rate of `settings` and attach its sink to `machine`
+    pub fn open(machine: &mut Machine, settings: AudioSettings) -> Result<Self, Box<dyn Error>> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or("no audio output device")?;
+        let config = StreamConfig {
+            channels: 2,
+            sample_rate: settings.sample_rate,
+            buffer_size: BufferSize::Default,
+        };
+        let (sink, stream) = StreamSink::new(settings);
+        let output = match device.default_output_config()?.sample_format() {
+            SampleFormat::I16 => build_output::<i16>(&device, &config, stream.clone())?,
+            SampleFormat::U16 => build_output::<u16>(&device, &config, stream.clone())?,
+            SampleFormat::F32 => build_output::<f32>(&device, &config, stream.clone())?,
+            format => return Err(format!("unsupported sample format {format}").into()),
+        };
+        output.play()?;
+        machine.add_audio_sink(sink);
+
+        Ok(Self {
+            stream,
+            _output: output,
+        })
+    }
+
+    pub fn stream(&self) -> &AudioStream {
+        &self.stream
+    }
+
+    /// Frames to run on this tick so the buffer stays around half the latency: none when the sound is ahead of
+    /// the device, two when it runs low. The ticks and the device clocks drift apart, this keeps the sound
+    /// from skipping or lagging in long sessions.
+    pub fn frames_to_run(&self) -> usize {
+        let latency = self.stream.settings().latency;
+        let buffered = self.stream.buffered();
+        if buffered > latency.mul_f64(0.75) {
+            0
+        } else if buffered < latency / 4 {
+            2
+        } else {
+            1
+        }
+    }
+}
+
+fn build_output<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    stream: AudioStream,
+) -> Result<cpal::Stream, Box<dyn Error>>
+where
+    T: SizedSample + FromSample<i16>,
+{
+    let mut samples = Vec::new();
+    let output = device.build_output_stream(
+        config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            samples.resize(data.len() / 2, [0, 0]);
+            stream.read(&mut samples);
+            for (frame, sample) in data.chunks_exact_mut(2).zip(&samples) {
+                frame[0] = T::from_sample(sample[0]);
+                frame[1] = T::from_sample(sample[1]);
+            }
+        },
+        |e| error!("Audio output failed: {e}"),
+        None,
+    )?;
+    Ok(output)
+}
@@ -0,0 +1,238 @@
+use crate::app::Message;
+use crate::commands::{COMMANDS, Hotkey};
+use crate::config::Config;
+use gbemu_core::{JoypadButton, Turbo};
+use iced::keyboard::key::Named;
+use iced::keyboard::{Key, Modifiers};
+use log::warn;
+
+/// Per action, followed by the config name of the action. An empty value leaves the action unbound.
+const BINDING_KEY: &str = "key";
+/// Per joypad button, followed by the lowercase button name. Presses per second, see [`Turbo`].
+const TURBO_KEY: &str = "turbo";
+/// Turbo rates offered by the key editor, 0 being off
+pub const TURBO_RATES: [u8; 6] = [0, 5, 10, 15, 20, 30];
+/// Input macro slots, played with F1-F4 and recorded with Shift+F1-F4
+pub const MACRO_KEYS: [Named; 4] = [Named::F1, Named::F2, Named::F3, Named::F4];
+const JOYPAD_BUTTONS: [JoypadButton; 8] = [
+    JoypadButton::Up,
+    JoypadButton::Down,
+    JoypadButton::Left,
+    JoypadButton::Right,
+    JoypadButton::A,
+    JoypadButton::B,
+    JoypadButton::Start,
+    JoypadButton::Select,
+];
+
+/// Something a key can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Joypad(JoypadButton),
+    /// Command without argument, by index in [`COMMANDS`]
+    Command(usize),
+}
+
+impl Action {
+    pub fn name(&self) -> String {
+        match self {
+            Action::Joypad(button) => format!("Joypad {button:?}"),
+            Action::Command(index) => COMMANDS[*index].name.to_string(),
+        }
+    }
+
+    /// Name used in the config file, e.g. `joypad_a` or `play_pause`
+    fn config_name(&self) -> String {
+        self.name()
+            .to_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join("_")
+    }
+
+    pub fn default_hotkey(&self) -> Option<Hotkey> {
+        match self {
+            Action::Joypad(button) => Some(match button {
+                JoypadButton::Up => Hotkey::named(Named::ArrowUp),
+                JoypadButton::Down => Hotkey::named(Named::ArrowDown),
+                JoypadButton::Left => Hotkey::named(Named::ArrowLeft),
+                JoypadButton::Right => Hotkey::named(Named::ArrowRight),
+                JoypadButton::A => Hotkey::char("d"),
+                JoypadButton::B => Hotkey::char("f"),
+                JoypadButton::Start => Hotkey::char("c"),
+                JoypadButton::Select => Hotkey::char("v"),
+            }),
+            Action::Command(index) => COMMANDS[*index].hotkey.clone(),
+        }
+    }
+}
+
+/// Keys bound to the joypad buttons and to the commands without argument.
+///
+/// A command is looked up before the joypad, a joypad button is pressed whatever the modifiers.
+pub struct Bindings {
+    hotkeys: Vec<(Action, Option<Hotkey>)>,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let joypad = JOYPAD_BUTTONS.into_iter().map(Action::Joypad);
+        let commands = COMMANDS
+            .iter()
+            .enumerate()
+            .filter(|(_, command)| command.argument.is_none())
+            .map(|(index, _)| Action::Command(index));
+        let hotkeys = joypad
+            .chain(commands)
+            .map(|action| (action, action.default_hotkey()))
+            .collect();
+        Self { hotkeys }
+    }
+}
+
+impl Bindings {
+    /// Bindings of the config, the default ones for missing or invalid values
+    pub fn load(config: &Config) -> Self {
+        let mut bindings = Self::default();
+        for (action, hotkey) in &mut bindings.hotkeys {
+            let Some(value) = config.get(&key(action)) else {
+                continue;
+            };
+            match value {
+                "" => *hotkey = None,
+                value => match Hotkey::parse(value) {
+                    Some(parsed) => *hotkey = Some(parsed),
+                    None => warn!("Invalid key {value} for {}", action.name()),
+                },
+            }
+        }
+        bindings
+    }
+
+    /// Store the bindings that differ from the default ones
+    pub fn save(&self, config: &mut Config) {
+        for (action, hotkey) in &self.hotkeys {
+            match self.is_default(*action) {
+                true => config.remove(&key(action)),
+                false => config.set(&key(action), hotkey.as_ref().map(Hotkey::label).unwrap_or_default()),
+            }
+        }
+    }
+
+    pub fn actions(&self) -> impl Iterator<Item = Action> + '_ {
+        self.hotkeys.iter().map(|(action, _)| *action)
+    }
+
+    pub fn get(&self, action: Action) -> Option<&Hotkey> {
+        self.hotkeys
+            .iter()
+            .find(|(bound, _)| *bound == action)
+            .and_then(|(_, hotkey)| hotkey.as_ref())
+    }
+
+    /// Key bound to the command with this name
+    pub fn command_hotkey(&self, name: &str) -> Option<&Hotkey> {
+        self.hotkeys.iter().find_map(|(action, hotkey)| match action {
+            Action::Command(index) if COMMANDS[*index].name == name => hotkey.as_ref(),
+            _ => None,
+        })
+    }
+
+    pub fn set(&mut self, action: Action, hotkey: Option<Hotkey>) {
+        if let Some((_, bound)) = self.hotkeys.iter_mut().find(|(bound, _)| *bound == action) {
+            // Modifiers are ignored by the joypad
+            *bound = match action {
+                Action::Joypad(_) => hotkey.map(Hotkey::without_ctrl),
+                Action::Command(_) => hotkey,
+            };
+        }
+    }
+
+    pub fn reset(&mut self, action: Action) {
+        self.set(action, action.default_hotkey());
+    }
+
+    pub fn reset_all(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn is_default(&self, action: Action) -> bool {
+        self.get(action) == action.default_hotkey().as_ref()
+    }
+
+    /// Other actions bound to the same key as `action`
+    pub fn conflicts(&self, action: Action) -> Vec<Action> {
+        let Some(hotkey) = self.get(action) else {
+            return vec![];
+        };
+        self.hotkeys
+            .iter()
+            .filter(|(other, bound)| *other != action && bound.as_ref() == Some(hotkey))
+            .map(|(other, _)| *other)
+            .collect()
+    }
+
+    /// Message of the action bound to the pressed key
+    pub fn pressed(&self, key: &Key, modifiers: Modifiers) -> Option<Message> {
+        let command = self.hotkeys.iter().find_map(|(action, hotkey)| match action {
+            Action::Command(index) if hotkey.as_ref().is_some_and(|hotkey| hotkey.matches(key, modifiers)) => {
+                Some(&COMMANDS[*index])
+            }
+            _ => None,
+        });
+        if let Some(command) = command {
+            return command.message("");
+        }
+        self.joypad_button(key).map(Message::ButtonsPressed)
+    }
+
+    pub fn released(&self, key: &Key) -> Option<Message> {
+        self.joypad_button(key).map(Message::ButtonsReleased)
+    }
+
+    fn joypad_button(&self, key: &Key) -> Option<JoypadButton> {
+        self.hotkeys.iter().find_map(|(action, hotkey)| match action {
+            Action::Joypad(button) if hotkey.as_ref().is_some_and(|hotkey| hotkey.matches_key(key)) => Some(*button),
+            _ => None,
+        })
+    }
+}
+
+/// Keys kept by the save state slots and the input macros, they cannot be bound
+pub fn reserved(hotkey: &Hotkey) -> Option<&'static str> {
+    if hotkey.is_digit() {
+        Some("digits select the save state slots")
+    } else if MACRO_KEYS.iter().any(|&named| hotkey.is_named(named)) {
+        Some("F1 to F4 play the input macros")
+    } else {
+        None
+    }
+}
+
+/// Turbo rates of the config, none for missing or invalid values
+pub fn load_turbo(config: &Config) -> Turbo {
+    let mut turbo = Turbo::default();
+    for button in JOYPAD_BUTTONS {
+        let rate = config.get(&turbo_key(button)).and_then(|rate| rate.parse().ok());
+        turbo.set_rate(button, rate.unwrap_or(0));
+    }
+    turbo
+}
+
+pub fn save_turbo(config: &mut Config, turbo: &Turbo) {
+    for button in JOYPAD_BUTTONS {
+        match turbo.rate(button) {
+            0 => config.remove(&turbo_key(button)),
+            rate => config.set(&turbo_key(button), rate.to_string()),
+        }
+    }
+}
+
+fn turbo_key(button: JoypadButton) -> String {
+    format!("{TURBO_KEY}.{}", format!("{button:?}").to_lowercase())
+}
+
+fn key(action: &Action) -> String {
+    format!("{BINDING_KEY}.{}", action.config_name())
+}
@@ -0,0 +1,451 @@
+use crate::app::Message;
+use crate::views::{view_command_palette, view_memory};
+use crate::workspace;
+use gbemu_core::{ColorPalette, Interrupt};
+use iced::keyboard::key::Named;
+use iced::keyboard::{Key, Modifiers};
+use std::borrow::Cow;
+
+/// Key combination triggering a command, written `Ctrl+P` or `ArrowUp` in the config
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hotkey {
+    key: HotkeyKey,
+    ctrl: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HotkeyKey {
+    Named(Named),
+    /// Lowercase character
+    Char(Cow<'static, str>),
+}
+
+/// Named keys that can be bound, modifiers alone cannot
+const NAMED_KEYS: [Named; 27] = [
+    Named::ArrowUp,
+    Named::ArrowDown,
+    Named::ArrowLeft,
+    Named::ArrowRight,
+    Named::Space,
+    Named::Enter,
+    Named::Tab,
+    Named::Backspace,
+    Named::Delete,
+    Named::Insert,
+    Named::Home,
+    Named::End,
+    Named::PageUp,
+    Named::PageDown,
+    Named::Escape,
+    Named::F1,
+    Named::F2,
+    Named::F3,
+    Named::F4,
+    Named::F5,
+    Named::F6,
+    Named::F7,
+    Named::F8,
+    Named::F9,
+    Named::F10,
+    Named::F11,
+    Named::F12,
+];
+
+impl Hotkey {
+    pub const fn named(named: Named) -> Self {
+        Self {
+            key: HotkeyKey::Named(named),
+            ctrl: false,
+        }
+    }
+    pub const fn char(char: &'static str) -> Self {
+        Self {
+            key: HotkeyKey::Char(Cow::Borrowed(char)),
+            ctrl: false,
+        }
+    }
+    /// Character with Ctrl, or Cmd on macOS
+    pub const fn ctrl(char: &'static str) -> Self {
+        Self {
+            key: HotkeyKey::Char(Cow::Borrowed(char)),
+            ctrl: true,
+        }
+    }
+
+    /// Hotkey of a pressed key, `None` for a key that cannot be bound
+    pub fn from_key(key: &Key, modifiers: Modifiers) -> Option<Self> {
+        let key = match key.as_ref() {
+            Key::Named(named) => HotkeyKey::Named(*NAMED_KEYS.iter().find(|&&bindable| bindable == named)?),
+            Key::Character(char) if !char.trim().is_empty() => HotkeyKey::Char(Cow::Owned(char.to_lowercase())),
+            _ => return None,
+        };
+        Some(Self {
+            key,
+            ctrl: modifiers.command(),
+        })
+    }
+
+    /// Inverse of [`Hotkey::label`]
+    pub fn parse(label: &str) -> Option<Self> {
+        let (ctrl, key) = match label.strip_prefix("Ctrl+") {
+            Some(key) => (true, key),
+            None => (false, label),
+        };
+        let key = match NAMED_KEYS.iter().find(|named| format!("{named:?}") == key) {
+            Some(named) => HotkeyKey::Named(*named),
+            None if key.chars().count() == 1 => HotkeyKey::Char(Cow::Owned(key.to_lowercase())),
+            None => return None,
+        };
+        Some(Self { key, ctrl })
+    }
+
+    pub fn is_named(&self, named: Named) -> bool {
+        self.key == HotkeyKey::Named(named)
+    }
+
+    pub fn is_digit(&self) -> bool {
+        matches!(&self.key, HotkeyKey::Char(char) if char.chars().all(|c| c.is_ascii_digit()))
+    }
+
+    pub fn without_ctrl(self) -> Self {
+        Self { ctrl: false, ..self }
+    }
+
+    pub fn matches(&self, key: &Key, modifiers: Modifiers) -> bool {
+        self.ctrl == modifiers.command() && self.matches_key(key)
+    }
+
+    /// Same key whatever the modifiers
+    pub fn matches_key(&self, key: &Key) -> bool {
+        match (&self.key, key.as_ref()) {
+            (HotkeyKey::Named(named), Key::Named(pressed)) => *named == pressed,
+            (HotkeyKey::Char(char), Key::Character(pressed)) => pressed.eq_ignore_ascii_case(char),
+            _ => false,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        let key = match &self.key {
+            HotkeyKey::Named(named) => format!("{named:?}"),
+            HotkeyKey::Char(char) => char.to_uppercase(),
+        };
+        if self.ctrl { format!("Ctrl+{key}") } else { key }
+    }
+}
+
+/// Debugger action, reachable from the command palette and, without argument, from a hotkey
+pub struct Command {
+    pub name: &'static str,
+    /// Name of the argument typed after the command name
+    pub argument: Option<&'static str>,
+    /// Default hotkey, see [`Bindings`](crate::bindings::Bindings) for the bound one
+    pub hotkey: Option<Hotkey>,
+    action: fn(&str) -> Option<Message>,
+}
+
+impl Command {
+    /// Message running the command, `None` when the argument is invalid
+    pub fn message(&self, argument: &str) -> Option<Message> {
+        (self.action)(argument)
+    }
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "Command palette",
+        argument: None,
+        hotkey: Some(Hotkey::ctrl("p")),
+        action: |_| Some(Message::CommandPalette(view_command_palette::Message::Open)),
+    },
+    Command {
+        name: "Play / Pause",
+        argument: None,
+        hotkey: Some(Hotkey::named(Named::Space)),
+        action: |_| Some(Message::TogglePlayback),
+    },
+    Command {
+        name: "Step",
+        argument: None,
+        hotkey: Some(Hotkey::named(Named::F7)),
+        action: |_| Some(Message::Step),
+    },
+    Command {
+        name: "Step frame",
+        argument: None,
+        hotkey: Some(Hotkey::named(Named::F10)),
+        action: |_| Some(Message::StepFrame),
+    },
+    Command {
+        name: "Reset",
+        argument: None,
+        hotkey: Some(Hotkey::char("r")),
+        action: |_| Some(Message::Reset),
+    },
+    Command {
+        name: "Load ROM",
+        argument: None,
+        hotkey: Some(Hotkey::char("l")),
+        action: |_| Some(Message::OpenFile),
+    },
+    Command {
+        name: "Apply patch",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::OpenPatch),
+    },
+    Command {
+        name: "Quit",
+        argument: None,
+        hotkey: Some(Hotkey::named(Named::Escape)),
+        action: |_| Some(Message::CloseWindow),
+    },
+    Command {
+        name: "Toggle breakpoint",
+        argument: Some("address"),
+        hotkey: None,
+        action: |argument| parse_address(argument).map(Message::BreakpointToggle),
+    },
+    Command {
+        name: "Toggle break on interrupt",
+        argument: Some("vblank|stat|timer|serial|joypad|any"),
+        hotkey: None,
+        action: |argument| {
+            let interrupt = match argument.to_ascii_uppercase().as_str() {
+                "ANY" => Interrupt::all(),
+                name => *Interrupt::SOURCES.iter().find(|interrupt| interrupt.name() == name)?,
+            };
+            Some(Message::BreakOnInterruptToggle(interrupt))
+        },
+    },
+    Command {
+        name: "Toggle break on RST",
+        argument: Some("vector"),
+        hotkey: None,
+        action: |argument| {
+            let vector = parse_address(argument).filter(|vector| *vector <= 0x38 && vector & 7 == 0)?;
+            Some(Message::BreakOnRstToggle(vector as u8))
+        },
+    },
+    Command {
+        name: "Toggle break on execution outside ROM/WRAM/HRAM",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::BreakOutsideCodeToggle),
+    },
+    Command {
+        name: "Toggle freeze",
+        argument: Some("address"),
+        hotkey: None,
+        action: |argument| parse_address(argument).map(Message::FreezeToggle),
+    },
+    Command {
+        name: "Toggle read-only region",
+        argument: Some("start-end"),
+        hotkey: None,
+        action: |argument| {
+            let (start, end) = argument.split_once('-').unwrap_or((argument, argument));
+            let (start, end) = (parse_address(start.trim())?, parse_address(end.trim())?);
+            (start <= end).then_some(Message::ReadOnlyToggle(start, end))
+        },
+    },
+    Command {
+        name: "Clear frozen addresses and read-only regions",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::ClearMemoryProtection),
+    },
+    Command {
+        name: "Take state snapshot",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::TakeSnapshot),
+    },
+    Command {
+        name: "Compare with snapshot",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::CompareWithSnapshot),
+    },
+    Command {
+        name: "Go to address in memory view",
+        argument: Some("address"),
+        hotkey: None,
+        action: |argument| {
+            let address = parse_address(argument)?;
+            let row = format!("{:03X}", address >> 4);
+            Some(Message::MemoryView(view_memory::Message::InputChanged(row)))
+        },
+    },
+    Command {
+        name: "Save state",
+        argument: Some("slot"),
+        hotkey: None,
+        action: |argument| argument.parse().ok().map(Message::SaveSlot),
+    },
+    Command {
+        name: "Load state",
+        argument: Some("slot"),
+        hotkey: None,
+        action: |argument| argument.parse().ok().map(Message::LoadSlot),
+    },
+    Command {
+        name: "Start / stop session recording",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::ToggleSessionRecording),
+    },
+    Command {
+        name: "Open session",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::OpenSession),
+    },
+    Command {
+        name: "Seek session frame",
+        argument: Some("frame"),
+        hotkey: None,
+        action: |argument| argument.parse().ok().map(Message::SessionSeek),
+    },
+    Command {
+        name: "Close session",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::CloseSession),
+    },
+    Command {
+        name: "Record input macro",
+        argument: Some("slot 1-4"),
+        hotkey: None,
+        action: |argument| parse_macro_slot(argument).map(Message::MacroRecordToggle),
+    },
+    Command {
+        name: "Play input macro",
+        argument: Some("slot 1-4"),
+        hotkey: None,
+        action: |argument| parse_macro_slot(argument).map(Message::MacroPlay),
+    },
+    Command {
+        name: "Start / stop GIF recording",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::ToggleGifRecording),
+    },
+    Command {
+        name: "Dump frames",
+        argument: Some("count"),
+        hotkey: None,
+        action: |argument| argument.parse().ok().map(Message::DumpFrames),
+    },
+    Command {
+        name: "Palette DMG green",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::SetColorPalette(ColorPalette::DMG_GREEN)),
+    },
+    Command {
+        name: "Palette grayscale",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::SetColorPalette(ColorPalette::GRAYSCALE)),
+    },
+    Command {
+        name: "Toggle frame blending",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::ToggleFrameBlend),
+    },
+    Command {
+        name: "Frame blending factor",
+        argument: Some("0-255"),
+        hotkey: None,
+        action: |argument| argument.parse().ok().map(|factor| Message::SetFrameBlend(Some(factor))),
+    },
+    Command {
+        name: "Toggle pause on focus loss",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::TogglePauseOnFocusLoss),
+    },
+    Command {
+        name: "Toggle background throttling",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::ToggleBackgroundThrottle),
+    },
+    Command {
+        name: "Toggle input display",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::ToggleInputDisplay),
+    },
+    Command {
+        name: "Play layout",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::Workspace(workspace::Message::Play)),
+    },
+    Command {
+        name: "Debug layout",
+        argument: None,
+        hotkey: None,
+        action: |_| Some(Message::Workspace(workspace::Message::Debug)),
+    },
+];
+
+/// Commands matching `query`, best match first, with the argument typed after the command name.
+///
+/// Matching is fuzzy: the characters of the query must appear in order in the command name.
+pub fn search(query: &str) -> Vec<(&'static Command, &str)> {
+    let query = query.trim();
+    let (name_query, argument) = query.rsplit_once(' ').unwrap_or((query, ""));
+
+    let mut matches: Vec<_> = COMMANDS
+        .iter()
+        .filter_map(|command| {
+            // The last word is an argument only for commands taking one
+            let (name_query, argument) = match command.argument {
+                Some(_) => (name_query, argument),
+                None => (query, ""),
+            };
+            fuzzy_score(name_query, command.name).map(|score| (score, command, argument))
+        })
+        .collect();
+
+    matches.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+    matches
+        .into_iter()
+        .map(|(_, command, argument)| (command, argument))
+        .collect()
+}
+
+/// Score of `pattern` as a subsequence of `text` (case insensitive), consecutive and word start
+/// matches score higher. `None` when `pattern` does not match.
+fn fuzzy_score(pattern: &str, text: &str) -> Option<i32> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match = None;
+
+    for char in pattern.to_lowercase().chars().filter(|c| !c.is_whitespace()) {
+        let found = position + text[position..].iter().position(|&c| c == char)?;
+        score += 1;
+        if previous_match.is_some_and(|previous| previous + 1 == found) {
+            score += 3;
+        }
+        if found == 0 || text[found - 1] == ' ' {
+            score += 2;
+        }
+        previous_match = Some(found);
+        position = found + 1;
+    }
+
+    Some(score)
+}
+
+fn parse_macro_slot(argument: &str) -> Option<usize> {
+    argument.parse().ok().filter(|slot| (1..=4).contains(slot))
+}
+
+fn parse_address(argument: &str) -> Option<u16> {
+    u16::from_str_radix(argument.trim_start_matches('$'), 16).ok()
+}
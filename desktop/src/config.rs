@@ -0,0 +1,65 @@
+use log::warn;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Settings of the frontend, stored as `key = value` lines.
+#[derive(Default)]
+pub struct Config {
+    path: PathBuf,
+    values: BTreeMap<String, String>,
+}
+
+impl Config {
+    /// Read the config file, a missing or unreadable file gives an empty config.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                if e.kind() != ErrorKind::NotFound {
+                    warn!("Failed to read config {}: {e}", path.display());
+                }
+                String::new()
+            }
+        };
+
+        let values = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .collect();
+
+        Self { path, values }
+    }
+
+    /// Directory of the config file, where the other frontend files are kept
+    pub fn directory(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new(""))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+
+    pub fn set(&mut self, key: &str, value: String) {
+        self.values.insert(key.to_string(), value);
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.values.remove(key);
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let content: String = self
+            .values
+            .iter()
+            .map(|(key, value)| format!("{key} = {value}\n"))
+            .collect();
+
+        fs::write(&self.path, content)
+    }
+}
@@ -0,0 +1,139 @@
+use crate::stats::unix_seconds;
+use gbemu_core::RomPreview;
+use log::warn;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Rom known to the library, with what is kept of it between runs
+#[derive(Debug, Clone, Default)]
+pub struct LibraryEntry {
+    pub title: String,
+    pub mapper: String,
+    /// Key of the play statistics of the game
+    pub header_hash: u32,
+    /// Modification time of the file when its preview was made, the preview is made again when it changes
+    pub modified: u64,
+    /// Thumbnail of the preview, one shade id per pixel
+    pub thumbnail: Vec<u8>,
+}
+
+/// Index of the roms of the library folders, stored as one tab separated line per rom:
+/// path, title, mapper, header hash, modification time and the packed thumbnail.
+#[derive(Default)]
+pub struct Library {
+    path: PathBuf,
+    entries: BTreeMap<PathBuf, LibraryEntry>,
+}
+
+impl Library {
+    /// Read the index, a missing or unreadable file gives an empty library.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) => {
+                if e.kind() != ErrorKind::NotFound {
+                    warn!("Failed to read library index {}: {e}", path.display());
+                }
+                String::new()
+            }
+        };
+
+        let entries = content.lines().filter_map(parse_line).collect();
+        Self { path, entries }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let content: String = self
+            .entries
+            .iter()
+            .map(|(path, entry)| {
+                format!(
+                    "{}\t{}\t{}\t{:08x}\t{}\t{}\n",
+                    path.display(),
+                    entry.title,
+                    entry.mapper,
+                    entry.header_hash,
+                    entry.modified,
+                    pack_thumbnail(&entry.thumbnail)
+                )
+            })
+            .collect();
+
+        fs::write(&self.path, content)
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&LibraryEntry> {
+        self.entries.get(path)
+    }
+
+    /// Keep only the roms of `paths`, the index of a rom removed from the folders is lost
+    pub fn retain(&mut self, paths: &[PathBuf]) {
+        self.entries.retain(|path, _| paths.contains(path));
+    }
+
+    /// The preview of the file is missing or older than the file
+    pub fn is_outdated(&self, path: &Path) -> bool {
+        self.entries
+            .get(path)
+            .is_none_or(|entry| entry.thumbnail.is_empty() || entry.modified != modified(path))
+    }
+
+    pub fn set_preview(&mut self, path: &Path, preview: &RomPreview) {
+        let entry = self.entries.entry(path.to_path_buf()).or_default();
+        entry.title = preview.title.replace('\t', " ");
+        entry.mapper = preview.mapper.to_string();
+        entry.header_hash = preview.header_hash;
+        entry.modified = modified(path);
+        entry.thumbnail = preview.pixels.clone();
+    }
+}
+
+fn parse_line(line: &str) -> Option<(PathBuf, LibraryEntry)> {
+    let mut fields = line.split('\t');
+    let path = PathBuf::from(fields.next()?);
+    let title = fields.next()?.to_string();
+    let mapper = fields.next()?.to_string();
+    let header_hash = u32::from_str_radix(fields.next()?, 16).ok()?;
+    let modified = fields.next()?.parse().ok()?;
+    let thumbnail = unpack_thumbnail(fields.next().unwrap_or_default());
+
+    let entry = LibraryEntry {
+        title,
+        mapper,
+        header_hash,
+        modified,
+        thumbnail,
+    };
+    Some((path, entry))
+}
+
+/// Modification time of a file in seconds, 0 when unknown
+fn modified(path: &Path) -> u64 {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_or(0, unix_seconds)
+}
+
+/// Four 2-bit shade ids per byte, in hexadecimal
+fn pack_thumbnail(pixels: &[u8]) -> String {
+    pixels
+        .chunks(4)
+        .map(|shades| {
+            let byte = shades
+                .iter()
+                .enumerate()
+                .fold(0, |byte, (index, shade)| byte | (shade & 3) << (index * 2));
+            format!("{byte:02x}")
+        })
+        .collect()
+}
+
+fn unpack_thumbnail(hex: &str) -> Vec<u8> {
+    (0..hex.len() / 2)
+        .filter_map(|index| u8::from_str_radix(hex.get(index * 2..index * 2 + 2)?, 16).ok())
+        .flat_map(|byte| (0..4).map(move |index| byte >> (index * 2) & 3))
+        .collect()
+}
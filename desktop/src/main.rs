@@ -1,25 +1,65 @@
-use crate::app::{App, Message};
+use crate::app::{ACCURACY_KEY, App, Message};
+use crate::config::Config;
 use iced::{Font, Point, Settings, Size, Task, Theme, application, window};
 
 mod app;
+mod audio;
+mod bindings;
+mod commands;
+mod config;
+mod library;
+mod stats;
 pub(crate) mod style;
 pub(crate) mod theme;
 pub(crate) mod views;
 pub(crate) mod widgets;
+mod workspace;
 
 use clap::Parser;
 use font_kit::source::SystemSource;
+use gbemu_core::{Accuracy, Machine};
 use log::debug;
+use std::io;
+use std::io::Read;
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 #[derive(Debug)]
 struct Args {
+    /// Rom file, `-` reads it from stdin
     rom_path: Option<String>,
+    /// Read the rom from stdin, same as passing `-` as rom path
+    #[arg(long, default_value = "false")]
+    stdin: bool,
+    /// IPS or BPS patch applied to the rom, repeat it to apply several patches in order
+    #[arg(long = "patch")]
+    patches: Vec<PathBuf>,
     #[arg(short = 'b', long, default_value = "false")]
     use_boot_rom: bool,
+    /// Start at $0100 with the exact state left by the boot rom, without running it
+    #[arg(long, default_value = "false")]
+    fast_boot: bool,
     #[arg(long = "run", default_value = "false")]
     auto_run: bool,
+    /// Emulate the DMG OAM corruption bug, relied on by a few demos and test roms
+    #[arg(long, default_value = "false")]
+    oam_bug: bool,
+    /// Answer the Super Game Boy detection of the games that hang waiting for it
+    #[arg(long, default_value = "false")]
+    sgb_stub: bool,
+    /// Accuracy preset: fast, balanced or accurate, the one chosen in the window by default
+    #[arg(long)]
+    accuracy: Option<Accuracy>,
+    /// Directory of battery saves and save states
+    #[arg(long, default_value = "saves")]
+    save_dir: PathBuf,
+    /// Directory of the crash reports written on emulation errors
+    #[arg(long, default_value = "crashes")]
+    crash_dir: PathBuf,
+    /// Settings file, the window layout is kept there
+    #[arg(long, default_value = "gbemu-iced.cfg")]
+    config: PathBuf,
 }
 
 fn main() -> iced::Result {
@@ -34,24 +74,57 @@ fn main() -> iced::Result {
         Err(_) => Font::MONOSPACE,
     };
 
+    // Read before the window opens, the boot closure may run more than once
+    let stdin_rom = (args.stdin || args.rom_path.as_deref() == Some("-")).then(|| {
+        let mut rom = Vec::new();
+        io::stdin()
+            .read_to_end(&mut rom)
+            .expect("Failed to read rom from stdin");
+        rom
+    });
+
     application(move ||{
-        let mut app = App::default();
+        let config = Config::load(&args.config);
+        let accuracy = args
+            .accuracy
+            .or_else(|| config.get(ACCURACY_KEY)?.parse().ok())
+            .unwrap_or_default();
+        let mut builder = Machine::builder()
+            .battery_save_dir(&args.save_dir)
+            .accuracy(accuracy)
+            .fast_boot(args.fast_boot)
+            .sgb_stub(args.sgb_stub)
+            // For the crash reports
+            .instruction_trace(true);
+        if args.oam_bug {
+            builder = builder.oam_bug(true);
+        }
         if args.use_boot_rom {
-            app.machine.use_boot_rom().expect("Failed to load boot rom");
+            builder = builder.boot_rom_path("roms/dmg.bin");
         }
-        app.machine.reset();
-
-        if let Some(rom_path) = &args.rom_path {
-            app.machine
-                .load_cartridge(rom_path.as_str())
-                .expect("Failed to load cartridge");
+        if let Some(rom) = &stdin_rom {
+            builder = builder.cartridge_bytes(rom.clone());
+        } else if let Some(rom_path) = &args.rom_path {
+            builder = builder.cartridge_path(rom_path);
         }
+        for patch in &args.patches {
+            builder = builder.patch_path(patch);
+        }
+
+        let app = App::new(
+            builder.build().expect("Failed to create machine"),
+            args.save_dir.clone(),
+            args.crash_dir.clone(),
+            config,
+        );
 
         let task = if args.auto_run {
             Task::done(Message::TogglePlayback)
         } else {
             Task::none()
         };
+        // Only runs when opted in from the about panel
+        let task = task.chain(Task::done(Message::CheckForUpdate));
 
         (app, task)
     }, App::update, App::view)
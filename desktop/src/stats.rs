@@ -0,0 +1,67 @@
+use crate::config::Config;
+use std::fmt::{Display, Formatter};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Per game, followed by the header hash of the game
+const PLAY_STATS_KEY: &str = "play_stats";
+
+/// Play time of a game, stored in the config as `<emulated seconds> <wall seconds> <last played>`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PlayStats {
+    /// Time elapsed on the console, faster than the wall time when fast forwarding
+    pub emulated: Duration,
+    /// Time the game was running in the window
+    pub wall: Duration,
+    pub last_played: Option<SystemTime>,
+}
+
+impl PlayStats {
+    /// Statistics of the game with this header hash, empty for an unknown game
+    pub fn load(config: &Config, header_hash: u32) -> Self {
+        config.get(&key(header_hash)).and_then(Self::parse).unwrap_or_default()
+    }
+
+    pub fn save(&self, config: &mut Config, header_hash: u32) {
+        config.set(&key(header_hash), self.to_string());
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        let mut fields = value.split_whitespace().map(str::parse::<u64>);
+        let emulated = Duration::from_secs(fields.next()?.ok()?);
+        let wall = Duration::from_secs(fields.next()?.ok()?);
+        let last_played = match fields.next()?.ok()? {
+            0 => None,
+            seconds => Some(UNIX_EPOCH + Duration::from_secs(seconds)),
+        };
+        Some(Self {
+            emulated,
+            wall,
+            last_played,
+        })
+    }
+}
+
+impl Display for PlayStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let last_played = self.last_played.map_or(0, unix_seconds);
+        write!(f, "{} {} {last_played}", self.emulated.as_secs(), self.wall.as_secs())
+    }
+}
+
+fn key(header_hash: u32) -> String {
+    format!("{PLAY_STATS_KEY}.{header_hash:08x}")
+}
+
+pub fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// Play time rounded to the minute, e.g. `2 h 05 min`
+pub fn format_play_time(play_time: Duration) -> String {
+    let minutes = play_time.as_secs() / 60;
+    match minutes {
+        0 => "< 1 min".to_string(),
+        1..60 => format!("{minutes} min"),
+        _ => format!("{} h {:02} min", minutes / 60, minutes % 60),
+    }
+}
@@ -1,3 +1,17 @@
+pub mod view_about;
+pub mod view_audio;
+pub mod view_command_palette;
 pub mod view_cpu;
+pub mod view_diff;
+pub mod view_input_display;
+pub mod view_keybindings;
+pub mod view_mapper;
 pub mod view_memory;
+pub mod view_opcodes;
+pub mod view_palettes;
 pub mod view_registers;
+pub mod view_rom_browser;
+pub mod view_save_slots;
+pub mod view_stats;
+pub mod view_tiles;
+pub mod view_timeline;
@@ -0,0 +1,145 @@
+use crate::app::Message;
+use crate::theme::color::*;
+use gbemu_core::{Machine, build_info};
+use iced::futures::channel::oneshot;
+use iced::widget::{Space, button, checkbox, column, row, text};
+use iced::{Element, Task};
+use semver::Version;
+use serde::Deserialize;
+use std::time::Duration;
+
+const SIZE: u32 = 12;
+const RELEASES_URL: &str = "https://github.com/gbredz1/gbemu/releases";
+const LATEST_RELEASE_API: &str = "https://api.github.com/repos/gbredz1/gbemu/releases/latest";
+const UPDATE_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fields of a GitHub release used by the update check
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+}
+
+/// Features enabled on this frontend
+fn features() -> Vec<&'static str> {
+    [
+        ("profiling", cfg!(feature = "profiling")),
+        ("trace", cfg!(feature = "trace")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+fn list(features: &[&str]) -> String {
+    match features.is_empty() {
+        true => "none".to_string(),
+        false => features.join(", "),
+    }
+}
+
+fn accuracy(machine: &Machine) -> String {
+    machine
+        .accuracy()
+        .preset()
+        .map_or("Custom".to_string(), |preset| preset.to_string())
+}
+
+/// Build and settings to paste in a bug report
+pub fn report(machine: &Machine) -> String {
+    format!(
+        "gbemu-iced {} features: {}\n{}\naccuracy: {}, model: {:?}",
+        env!("CARGO_PKG_VERSION"),
+        list(&features()),
+        build_info(),
+        accuracy(machine),
+        machine.model(),
+    )
+}
+
+/// Version of the latest GitHub release, fetched on another thread
+pub fn check_latest_release() -> Task<Message> {
+    let (sender, receiver) = oneshot::channel();
+    std::thread::spawn(move || {
+        // The receiver is gone once the window is closed
+        let _ = sender.send(fetch_latest_release());
+    });
+    Task::perform(receiver, |result| {
+        Message::UpdateChecked(result.unwrap_or_else(|_| Err("check interrupted".to_string())))
+    })
+}
+
+fn fetch_latest_release() -> Result<Version, String> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(UPDATE_CHECK_TIMEOUT))
+        .build()
+        .into();
+    let release: Release = agent
+        .get(LATEST_RELEASE_API)
+        .header("User-Agent", concat!("gbemu-iced/", env!("CARGO_PKG_VERSION")))
+        .call()
+        .and_then(|mut response| response.body_mut().read_json())
+        .map_err(|e| e.to_string())?;
+    release_version(&release.tag_name)
+}
+
+/// Version of a release tag, with or without the `v` prefix
+fn release_version(tag: &str) -> Result<Version, String> {
+    Version::parse(tag.strip_prefix('v').unwrap_or(tag)).map_err(|e| format!("tag {tag}: {e}"))
+}
+
+/// Result of the update check, `None` while it runs. Only a newer release is reported as available.
+fn update_status(enabled: bool, latest_release: Option<&Result<Version, String>>) -> String {
+    let current = Version::parse(env!("CARGO_PKG_VERSION")).expect("Package versions are semver");
+    match latest_release {
+        _ if !enabled => "Off".to_string(),
+        None => "Checking...".to_string(),
+        Some(Ok(latest)) if *latest > current => format!("{latest} available"),
+        Some(Ok(_)) => "Up to date".to_string(),
+        Some(Err(e)) => format!("Failed: {e}"),
+    }
+}
+
+/// Versions of the core and the frontend, copied with the button for bug reports. The latest release is
+/// only checked once opted in.
+pub fn view<'a>(
+    machine: &Machine,
+    update_check: bool,
+    latest_release: Option<&Result<Version, String>>,
+) -> Element<'a, Message> {
+    let core = build_info();
+    let line = |name: &'a str, value: String| -> Element<'a, Message> {
+        row![
+            Space::new().width(10.0),
+            text(name).color(green()).width(90).size(SIZE),
+            text(value).size(SIZE),
+        ]
+        .into()
+    };
+
+    column![
+        text("gbemu-iced:").color(purple()).size(SIZE),
+        line("VERSION", env!("CARGO_PKG_VERSION").to_string()),
+        line("FEATURES", list(&features())),
+        text("gbemu-core:").color(purple()).size(SIZE),
+        line("VERSION", core.version.to_string()),
+        line("COMMIT", core.git_hash.unwrap_or("-").to_string()),
+        line("BUILD", format!("{} {} {}", core.profile, core.os, core.arch)),
+        line("RUSTC", core.rustc.unwrap_or("-").to_string()),
+        line("FEATURES", list(&core.features)),
+        text("Machine:").color(purple()).size(SIZE),
+        line("ACCURACY", accuracy(machine)),
+        line("MODEL", format!("{:?}", machine.model())),
+        line("RELEASES", RELEASES_URL.to_string()),
+        line("UPDATE", update_status(update_check, latest_release)),
+        checkbox(update_check)
+            .label("Check for updates at startup")
+            .text_size(SIZE)
+            .on_toggle(|_| Message::ToggleUpdateCheck),
+        button(text("Copy for a bug report").size(SIZE))
+            .on_press(Message::CopyBuildInfo)
+            .style(button::secondary),
+    ]
+    .spacing(2)
+    .padding(4)
+    .into()
+}
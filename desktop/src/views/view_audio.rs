@@ -0,0 +1,74 @@
+use crate::app::Message;
+use crate::theme::color::*;
+use gbemu_core::{AudioSettings, AudioStream, ResamplerQuality};
+use iced::Element;
+use iced::widget::{Space, column, pick_list, row, slider, text};
+
+const SAMPLE_RATES: [u32; 4] = [22_050, 44_100, 48_000, 96_000];
+
+/// Settings of the output, with the state of the `stream` of the device playing it
+pub fn view<'a>(settings: AudioSettings, stream: Option<&AudioStream>) -> Element<'a, Message> {
+    const SIZE: u32 = 12;
+
+    let line = |name: &'a str, value: Element<'a, Message>| -> Element<'a, Message> {
+        row![
+            Space::new().width(10.0),
+            text(name).color(green()).width(90).size(SIZE),
+            value,
+        ]
+        .align_y(iced::alignment::Vertical::Center)
+        .into()
+    };
+
+    let sample_rate = pick_list(SAMPLE_RATES, Some(settings.sample_rate), move |sample_rate| {
+        Message::SetAudioSettings(AudioSettings {
+            sample_rate,
+            ..settings
+        })
+    })
+    .text_size(SIZE);
+    let quality = pick_list(ResamplerQuality::ALL, Some(settings.quality), move |quality| {
+        Message::SetAudioSettings(AudioSettings { quality, ..settings })
+    })
+    .text_size(SIZE);
+    let latency_ms = settings.latency.as_millis() as u32;
+    let latency = row![
+        slider(20..=250, latency_ms, move |ms| {
+            Message::SetAudioSettings(AudioSettings {
+                latency: std::time::Duration::from_millis(ms as u64),
+                ..settings
+            })
+        })
+        .width(120),
+        text(format!("{latency_ms} ms")).size(SIZE),
+    ]
+    .spacing(6);
+
+    let device: Element<'a, Message> = match stream {
+        Some(stream) => {
+            let (overruns, underruns) = stream.xruns();
+            column![
+                line(
+                    "BUFFERED",
+                    text(format!("{} ms", stream.buffered().as_millis())).size(SIZE).into()
+                ),
+                line("OVERRUNS", text(overruns).size(SIZE).into()),
+                line("UNDERRUNS", text(underruns).size(SIZE).into()),
+            ]
+            .spacing(2)
+            .into()
+        }
+        None => line("DEVICE", text("none, see the log").color(red()).size(SIZE).into()),
+    };
+
+    column![
+        text("OUTPUT:").color(purple()).size(SIZE),
+        line("SAMPLE RATE", sample_rate.into()),
+        line("RESAMPLER", quality.into()),
+        line("LATENCY", latency.into()),
+        device,
+    ]
+    .spacing(2)
+    .padding(4)
+    .into()
+}
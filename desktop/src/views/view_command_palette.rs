@@ -0,0 +1,130 @@
+use crate::app;
+use crate::bindings::Bindings;
+use crate::commands::{self, Hotkey};
+use crate::style::container::panel_content;
+use crate::theme::color::{green, orange, purple};
+use iced::keyboard::key::Named;
+use iced::keyboard::{Event, Key};
+use iced::widget::{Column, button, column, container, operation, row, text, text_input};
+use iced::{Element, Fill, Subscription, Task, keyboard};
+
+const INPUT_ID: &str = "command-palette";
+const MAX_RESULTS: usize = 10;
+
+#[derive(Default)]
+pub struct State {
+    open: bool,
+    query: String,
+    selected: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Open,
+    Close,
+    QueryChanged(String),
+    Previous,
+    Next,
+    Run(usize),
+}
+
+impl State {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Returns the message of the command to run, if any
+    pub fn update(&mut self, msg: Message) -> (Task<Message>, Option<app::Message>) {
+        match msg {
+            Message::Open => {
+                self.open = true;
+                self.query.clear();
+                self.selected = 0;
+                return (operation::focus(INPUT_ID), None);
+            }
+            Message::Close => self.open = false,
+            Message::QueryChanged(query) => {
+                self.query = query;
+                self.selected = 0;
+            }
+            Message::Previous => self.selected = self.selected.saturating_sub(1),
+            Message::Next => {
+                let count = commands::search(&self.query).len().min(MAX_RESULTS);
+                self.selected = (self.selected + 1).min(count.saturating_sub(1));
+            }
+            Message::Run(index) => {
+                let message = commands::search(&self.query)
+                    .get(index)
+                    .and_then(|(command, argument)| command.message(argument));
+                if message.is_some() {
+                    self.open = false;
+                }
+                return (Task::none(), message);
+            }
+        }
+        (Task::none(), None)
+    }
+
+    /// Keys handled while the palette is open, instead of the hotkeys
+    pub fn subscription(&self) -> Subscription<Message> {
+        keyboard::listen().filter_map(|event| match event {
+            Event::KeyPressed { key, .. } => match key.as_ref() {
+                Key::Named(Named::Escape) => Some(Message::Close),
+                Key::Named(Named::ArrowUp) => Some(Message::Previous),
+                Key::Named(Named::ArrowDown) => Some(Message::Next),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+}
+
+pub fn view<'a>(state: &'a State, bindings: &Bindings) -> Element<'a, Message> {
+    const SIZE: u32 = 12;
+
+    let input = text_input("Type a command, e.g. \"save state 3\"", &state.query)
+        .id(INPUT_ID)
+        .on_input(Message::QueryChanged)
+        .on_submit(Message::Run(state.selected));
+
+    let results = commands::search(&state.query)
+        .into_iter()
+        .take(MAX_RESULTS)
+        .enumerate()
+        .map(|(index, (command, _))| {
+            let name = match command.argument {
+                Some(argument) => format!("{} <{argument}>", command.name),
+                None => command.name.to_string(),
+            };
+            let hotkey = bindings
+                .command_hotkey(command.name)
+                .map(Hotkey::label)
+                .unwrap_or_default();
+
+            button(row![
+                text(name).size(SIZE).color(green()).width(Fill),
+                text(hotkey).size(SIZE).color(orange()),
+            ])
+            .width(Fill)
+            .style(if index == state.selected {
+                button::primary
+            } else {
+                button::text
+            })
+            .on_press(Message::Run(index))
+            .into()
+        });
+
+    container(
+        column![
+            text("COMMANDS").size(SIZE).color(purple()),
+            input,
+            Column::with_children(results).spacing(2)
+        ]
+        .spacing(6),
+    )
+    .width(420)
+    .padding(8)
+    .style(panel_content)
+    .into()
+}
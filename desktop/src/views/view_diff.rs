@@ -0,0 +1,61 @@
+use crate::app::Message;
+use crate::theme::color::*;
+use gbemu_core::{MachineSnapshot, StateDiff};
+use iced::Element;
+use iced::widget::{Column, button, row, scrollable, text};
+
+const SIZE: u32 = 12;
+
+/// Changes since the snapshot, taken and compared with the buttons of the panel
+pub fn view<'a>(snapshot: Option<&MachineSnapshot>, diff: Option<&StateDiff>) -> Element<'a, Message> {
+    let buttons = row![
+        button(text("Snapshot").size(SIZE))
+            .on_press(Message::TakeSnapshot)
+            .style(button::secondary),
+        button(text("Compare").size(SIZE))
+            .on_press_maybe(snapshot.map(|_| Message::CompareWithSnapshot))
+            .style(button::secondary),
+    ]
+    .spacing(4);
+
+    let mut lines = Column::new().push(buttons).spacing(2).padding(4);
+    let header = |title: &'a str| text(title).color(purple()).size(SIZE);
+    let line =
+        |name: String, value: String| row![text(name).color(green()).width(140).size(SIZE), text(value).size(SIZE)];
+
+    match (snapshot, diff) {
+        (None, _) => lines = lines.push(text("No snapshot").size(SIZE)),
+        (Some(snapshot), None) => {
+            lines = lines.push(text(format!("Snapshot at cycle {}", snapshot.cycles())).size(SIZE));
+        }
+        (Some(_), Some(diff)) => {
+            lines = lines.push(text(format!("{} cycles, {} frames later", diff.cycles, diff.frames)).size(SIZE));
+            if diff.is_empty() {
+                lines = lines.push(text("No change").size(SIZE));
+            }
+            if !diff.registers.is_empty() {
+                lines = lines.push(header("Registers:"));
+            }
+            for change in &diff.registers {
+                let value = format!("${:04X} -> ${:04X}", change.before, change.after);
+                lines = lines.push(line(change.name.to_string(), value));
+            }
+            if !diff.io.is_empty() {
+                lines = lines.push(header("IO:"));
+            }
+            for change in &diff.io {
+                let name = format!("${:04X} {}", change.address, change.name.unwrap_or_default());
+                lines = lines.push(line(name, format!("${:02X} -> ${:02X}", change.before, change.after)));
+            }
+            if !diff.memory.is_empty() {
+                lines = lines.push(header("Memory:"));
+            }
+            for range in &diff.memory {
+                let name = format!("{} ${:04X}-${:04X}", range.region, range.start, range.end);
+                lines = lines.push(line(name, format!("{} bytes changed", range.changed)));
+            }
+        }
+    }
+
+    scrollable(lines).into()
+}
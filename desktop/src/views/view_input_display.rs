@@ -0,0 +1,40 @@
+use crate::app::Message;
+use crate::style::container::panel_content;
+use crate::theme::color::{orange, purple};
+use gbemu_core::{InputState, JoypadButton};
+use iced::widget::{Row, container, text};
+use iced::{Element, Fill};
+
+const BUTTONS: [(JoypadButton, &str); 8] = [
+    (JoypadButton::Left, "<"),
+    (JoypadButton::Up, "^"),
+    (JoypadButton::Down, "v"),
+    (JoypadButton::Right, ">"),
+    (JoypadButton::Select, "SELECT"),
+    (JoypadButton::Start, "START"),
+    (JoypadButton::B, "B"),
+    (JoypadButton::A, "A"),
+];
+
+/// Buttons of the frame drawn over the screen, a turbo button is marked with `*`
+pub fn view<'a>(state: InputState) -> Element<'a, Message> {
+    let buttons = BUTTONS.iter().map(|&(button, name)| {
+        let name = match state.has_turbo(button) {
+            true => format!("{name}*"),
+            false => name.to_string(),
+        };
+        let color = if state.is_pressed(button) { orange() } else { purple() };
+        text(name).size(12).color(color).into()
+    });
+
+    container(
+        container(Row::with_children(buttons).spacing(8))
+            .padding(2)
+            .style(panel_content),
+    )
+    .width(Fill)
+    .height(Fill)
+    .center_x(Fill)
+    .align_bottom(Fill)
+    .into()
+}
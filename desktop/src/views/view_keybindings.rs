@@ -0,0 +1,164 @@
+use crate::bindings::{self, Action, Bindings, TURBO_RATES};
+use crate::commands::Hotkey;
+use crate::theme::color::{green, orange, purple, red};
+use gbemu_core::{JoypadButton, Turbo};
+use iced::alignment::Vertical;
+use iced::keyboard::key::Named;
+use iced::keyboard::{Key, Modifiers};
+use iced::widget::{Column, Space, button, column, pick_list, row, scrollable, text};
+use iced::{Element, Fill};
+use std::fmt::{Display, Formatter};
+
+const SIZE: u32 = 12;
+
+/// Key editor, waiting for a key press while capturing
+#[derive(Default)]
+pub struct State {
+    capturing: Option<Action>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Capture(Action),
+    Cancel,
+    Clear(Action),
+    Reset(Action),
+    ResetAll,
+    /// Handled by the app, the turbo belongs to the machine
+    SetTurbo(JoypadButton, TurboRate),
+}
+
+/// Presses per second of a turbo button, 0 being off
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TurboRate(pub u8);
+
+impl Display for TurboRate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            0 => write!(f, "No turbo"),
+            rate => write!(f, "Turbo {rate}/s"),
+        }
+    }
+}
+
+impl State {
+    pub fn is_capturing(&self) -> bool {
+        self.capturing.is_some()
+    }
+
+    /// Returns whether the bindings changed
+    pub fn update(&mut self, bindings: &mut Bindings, msg: Message) -> bool {
+        self.error = None;
+        match msg {
+            Message::Capture(action) => {
+                self.capturing = Some(action);
+                return false;
+            }
+            Message::Cancel => {
+                self.capturing = None;
+                return false;
+            }
+            Message::Clear(action) => bindings.set(action, None),
+            Message::Reset(action) => bindings.reset(action),
+            Message::ResetAll => bindings.reset_all(),
+            Message::SetTurbo(..) => return false,
+        }
+        self.capturing = None;
+        true
+    }
+
+    /// Bind the pressed key to the action being captured, Escape cancels. Returns whether the bindings changed.
+    pub fn capture(&mut self, bindings: &mut Bindings, key: &Key, modifiers: Modifiers) -> bool {
+        let Some(action) = self.capturing else {
+            return false;
+        };
+        if key.as_ref() == Key::Named(Named::Escape) {
+            return self.update(bindings, Message::Cancel);
+        }
+        // Wait for a key that can be bound, e.g. past a lone modifier
+        let Some(hotkey) = Hotkey::from_key(key, modifiers) else {
+            return false;
+        };
+        self.capturing = None;
+        if let Some(reason) = bindings::reserved(&hotkey) {
+            self.error = Some(format!("{} cannot be bound, {reason}", hotkey.label()));
+            return false;
+        }
+        bindings.set(action, Some(hotkey));
+        true
+    }
+}
+
+pub fn view<'a>(state: &State, bindings: &Bindings, turbo: &Turbo) -> Element<'a, Message> {
+    let rows = bindings.actions().map(|action| {
+        let label = match (state.capturing == Some(action), bindings.get(action)) {
+            (true, _) => "press a key...".to_string(),
+            (false, Some(hotkey)) => hotkey.label(),
+            (false, None) => "-".to_string(),
+        };
+        let turbo: Element<'a, Message> = match action {
+            Action::Joypad(button) => pick_list(
+                TURBO_RATES.map(TurboRate),
+                Some(TurboRate(turbo.rate(button))),
+                move |rate| Message::SetTurbo(button, rate),
+            )
+            .text_size(SIZE)
+            .width(110)
+            .into(),
+            Action::Command(_) => Space::new().width(110).into(),
+        };
+        let conflicts = bindings.conflicts(action);
+        let conflict = match conflicts.is_empty() {
+            true => String::new(),
+            false => {
+                let names: Vec<_> = conflicts.iter().map(Action::name).collect();
+                format!("also {}", names.join(", "))
+            }
+        };
+
+        row![
+            text(action.name()).size(SIZE).color(green()).width(220),
+            button(text(label).size(SIZE).color(orange()))
+                .width(110)
+                .style(if state.capturing == Some(action) {
+                    button::primary
+                } else {
+                    button::secondary
+                })
+                .on_press(Message::Capture(action)),
+            button(text("Clear").size(SIZE))
+                .style(button::text)
+                .on_press_maybe(bindings.get(action).is_some().then_some(Message::Clear(action))),
+            button(text("Default").size(SIZE))
+                .style(button::text)
+                .on_press_maybe((!bindings.is_default(action)).then_some(Message::Reset(action))),
+            turbo,
+            text(conflict).size(SIZE).color(red()),
+        ]
+        .spacing(6)
+        .align_y(Vertical::Center)
+        .into()
+    });
+
+    let status = match (&state.error, state.capturing) {
+        (Some(error), _) => text(error.clone()).color(red()),
+        (None, Some(_)) => text("Press a key, Escape to cancel").color(purple()),
+        (None, None) => text("Click a key to change it").color(purple()),
+    };
+    let controls = row![
+        status.size(SIZE).width(Fill),
+        button(text("Reset all").size(SIZE))
+            .style(button::secondary)
+            .on_press(Message::ResetAll),
+    ]
+    .align_y(Vertical::Center);
+
+    column![
+        controls,
+        scrollable(Column::with_children(rows).spacing(2)).height(Fill)
+    ]
+    .spacing(4)
+    .padding(4)
+    .into()
+}
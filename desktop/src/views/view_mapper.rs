@@ -0,0 +1,46 @@
+use crate::app::Message;
+use crate::theme::color::*;
+use gbemu_core::Machine;
+use iced::Element;
+use iced::widget::{Space, column, row, text};
+
+pub fn view<'a>(machine: &Machine) -> Element<'a, Message> {
+    const SIZE: u32 = 12;
+
+    let state = machine.mapper_state();
+    let line = |name: &'a str, value: String| -> Element<'a, Message> {
+        row![
+            Space::new().width(10.0),
+            text(name).color(green()).width(90).size(SIZE),
+            text(value).size(SIZE),
+        ]
+        .into()
+    };
+    let bank = |bank: usize, count: usize| format!("${bank:02X} / {count}");
+
+    column![
+        text(format!("{}:", machine.cartridge().title()))
+            .color(purple())
+            .size(SIZE),
+        line("ROM $0000", bank(state.rom_bank_low, state.rom_bank_count)),
+        line("ROM $4000", bank(state.rom_bank_high, state.rom_bank_count)),
+        if state.ram_bank_count == 0 {
+            line("RAM $A000", "-".to_string())
+        } else {
+            line("RAM $A000", bank(state.ram_bank, state.ram_bank_count))
+        },
+        row![
+            Space::new().width(10.0),
+            text("RAM").color(green()).width(90).size(SIZE),
+            if state.ram_enabled {
+                text("On").color(red()).size(SIZE)
+            } else {
+                text("Off").color(blue()).size(SIZE)
+            },
+        ],
+        line("MODE", state.mode.to_string()),
+    ]
+    .spacing(2)
+    .padding(4)
+    .into()
+}
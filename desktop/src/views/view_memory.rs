@@ -1,14 +1,24 @@
-use gbemu_core::Machine;
+use gbemu_core::{InterestingAddress, Machine};
 
-use crate::theme::color::{green, orange, pink, purple, yellow};
+use crate::theme::color::{green, orange, pink, purple, red, yellow};
 use iced::alignment::{Horizontal, Vertical};
-use iced::widget::{Row, Space, button, column, container, row, text, text_input};
+use iced::widget::{Column, Row, Space, button, column, container, pick_list, row, text, text_input};
 use iced::{Element, Fill, Task};
 use iced_widget::space::horizontal;
+use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
 
 pub struct State {
     input_string: String,
     addr_start: u16,
+    follow: Follow,
+    bookmark_input: String,
+    bookmarks: Vec<RangeInclusive<u16>>,
+    /// Copy of the address space taken by [`State::refresh`]
+    memory: Vec<u8>,
+    addresses: Vec<InterestingAddress>,
+    refreshed_at: Option<Instant>,
 }
 
 #[derive(Debug, Clone)]
@@ -17,23 +27,136 @@ pub enum Message {
     Update(u16),
     Increment(u8),
     Decrement(u8),
+    Follow(Follow),
+    BookmarkInputChanged(String),
+    AddBookmark,
+    RemoveBookmark(usize),
+}
+
+/// Register the view scrolls to on each refresh
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Follow {
+    #[default]
+    Off,
+    Pc,
+    Sp,
+    Hl,
+}
+
+impl Follow {
+    const ALL: [Follow; 4] = [Follow::Off, Follow::Pc, Follow::Sp, Follow::Hl];
+
+    /// Name of the followed [`InterestingAddress`]
+    fn name(self) -> Option<&'static str> {
+        match self {
+            Follow::Off => None,
+            Follow::Pc => Some("PC"),
+            Follow::Sp => Some("SP"),
+            Follow::Hl => Some("HL"),
+        }
+    }
+}
+
+impl Display for Follow {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name().unwrap_or("OFF"))
+    }
 }
 
 const MAX_ADDR: u16 = 0xFF0;
+/// Refresh period of the shown memory while the machine runs
+const REFRESH_PERIOD: Duration = Duration::from_millis(100);
 
 impl Default for State {
     fn default() -> Self {
         Self {
             input_string: "000".to_string(),
             addr_start: 0,
+            follow: Follow::Off,
+            bookmark_input: String::new(),
+            bookmarks: vec![],
+            memory: vec![0; 0x10000],
+            addresses: vec![],
+            refreshed_at: None,
         }
     }
 }
 
 impl State {
+    /// Copy the memory shown and scroll to the followed register, at most every [`REFRESH_PERIOD`]
+    /// while the machine runs
+    pub fn refresh(&mut self, machine: &Machine) {
+        if machine.is_running() && self.refreshed_at.is_some_and(|at| at.elapsed() < REFRESH_PERIOD) {
+            return;
+        }
+        self.refreshed_at = Some(Instant::now());
+
+        // VRAM and OAM as the PPU sees them, the CPU may be locked out
+        for (address, byte) in self.memory.iter_mut().enumerate() {
+            *byte = match address {
+                0x8000..0xA000 => machine.vram()[address - 0x8000],
+                0xFE00..0xFEA0 => machine.oam()[address - 0xFE00],
+                _ => machine.bus().read_byte(address as u16),
+            };
+        }
+        self.addresses = machine.interesting_addresses();
+        if let Some(address) = self.follow.name().and_then(|name| self.address(name)) {
+            self.show(address);
+        }
+    }
+
+    fn address(&self, name: &str) -> Option<u16> {
+        self.addresses
+            .iter()
+            .find(|address| address.name == name)
+            .map(|address| address.address)
+    }
+
+    /// Scroll to the row of `address`
+    fn show(&mut self, address: u16) {
+        self.addr_start = (address / 0x10).min(MAX_ADDR);
+        self.input_string = format!("{:03X}", self.addr_start);
+    }
+
     pub fn update(&mut self, msg: Message) -> Task<Message> {
         match msg {
+            Message::Follow(follow) => {
+                self.follow = follow;
+                if let Some(address) = follow.name().and_then(|name| self.address(name)) {
+                    self.show(address);
+                }
+                Task::none()
+            }
+            Message::BookmarkInputChanged(input) => {
+                self.bookmark_input = input.chars().filter(|c| c.is_ascii_hexdigit() || *c == '-').collect();
+                Task::none()
+            }
+            Message::AddBookmark => {
+                // The shown rows without a range
+                let range = match self.bookmark_input.split_once('-') {
+                    Some((start, end)) => u16::from_str_radix(start, 16)
+                        .and_then(|start| Ok(start..=u16::from_str_radix(end, 16)?))
+                        .ok(),
+                    None if self.bookmark_input.is_empty() => {
+                        let start = self.addr_start * 0x10;
+                        Some(start..=start.saturating_add(0xFF))
+                    }
+                    None => None,
+                };
+                if let Some(range) = range.filter(|range| !range.is_empty() && !self.bookmarks.contains(range)) {
+                    self.bookmarks.push(range);
+                    self.bookmark_input.clear();
+                }
+                Task::none()
+            }
+            Message::RemoveBookmark(index) => {
+                if index < self.bookmarks.len() {
+                    self.bookmarks.remove(index);
+                }
+                Task::none()
+            }
             Message::InputChanged(addr) => {
+                self.follow = Follow::Off;
                 let addr = addr.chars().filter(|c| c.is_ascii_hexdigit()).collect();
                 self.input_string = addr;
 
@@ -49,6 +172,7 @@ impl State {
                 }
             }
             Message::Increment(val) => {
+                self.follow = Follow::Off;
                 let res = match self.addr_start.wrapping_add(val as u16) {
                     res if res < self.addr_start => MAX_ADDR,
                     res if res > MAX_ADDR => MAX_ADDR,
@@ -63,6 +187,7 @@ impl State {
             }
 
             Message::Decrement(val) => {
+                self.follow = Follow::Off;
                 let res = match self.addr_start.wrapping_sub(val as u16) {
                     res if res > self.addr_start => 0x0,
                     res => res,
@@ -121,7 +246,7 @@ macro_rules! memory_row_addr {
     };
 }
 
-pub fn view<'a>(state: &State, machine: &Machine) -> Element<'a, Message> {
+pub fn view<'a>(state: &'a State) -> Element<'a, Message> {
     const SIZE: u32 = 12;
     const SPACE_BYTE: f32 = 4.0; // macro
     const SPACE_BYTE_4: f32 = 7.0; // macro
@@ -170,18 +295,26 @@ pub fn view<'a>(state: &State, machine: &Machine) -> Element<'a, Message> {
         button_increment10,
         horizontal(),
         row![
-            button(text("SP").size(SIZE).color(pink()))
-                .padding(2)
-                .style(button::text)
-                .on_press(Message::InputChanged(format!("{:03X}", machine.cpu().sp() / 0x10))),
-            button(text("PC").size(SIZE).color(purple()))
-                .padding(2)
-                .style(button::text)
-                .on_press(Message::InputChanged(format!("{:03X}", machine.cpu().pc() / 0x10))),
-            button(text("HL").size(SIZE).color(yellow()))
-                .padding(2)
-                .style(button::text)
-                .on_press(Message::InputChanged(format!("{:03X}", machine.cpu().hl() / 0x10))),
+            Row::from_vec(
+                state
+                    .addresses
+                    .iter()
+                    .map(|address| {
+                        let label = text(address.name).size(SIZE);
+                        let label = match address.name {
+                            "SP" => label.color(pink()),
+                            "PC" => label.color(purple()),
+                            "HL" => label.color(yellow()),
+                            _ => label,
+                        };
+                        button(label)
+                            .padding(2)
+                            .style(button::text)
+                            .on_press(Message::InputChanged(format!("{:03X}", address.address / 0x10)))
+                            .into()
+                    })
+                    .collect()
+            ),
             Row::from_vec(
                 [
                     MemorySectors::RomBank0,
@@ -209,6 +342,45 @@ pub fn view<'a>(state: &State, machine: &Machine) -> Element<'a, Message> {
     ]
     .align_y(Vertical::Center);
 
+    let follow = row![
+        text("Follow: ").size(SIZE),
+        pick_list(Follow::ALL, Some(state.follow), Message::Follow).text_size(SIZE),
+        horizontal(),
+        text("Bookmark: ").size(SIZE),
+        text_input("start-end", &state.bookmark_input)
+            .size(SIZE)
+            .width(90)
+            .on_input(Message::BookmarkInputChanged)
+            .on_submit(Message::AddBookmark),
+        button(text("+").size(SIZE))
+            .style(button::secondary)
+            .on_press(Message::AddBookmark),
+    ]
+    .spacing(4)
+    .align_y(Vertical::Center);
+
+    let bookmarks = Column::from_vec(
+        state
+            .bookmarks
+            .iter()
+            .enumerate()
+            .map(|(index, range)| {
+                row![
+                    button(text(format!("${:04X}-${:04X}", range.start(), range.end())).size(SIZE))
+                        .padding(2)
+                        .style(button::text)
+                        .on_press(Message::InputChanged(format!("{:03X}", range.start() / 0x10))),
+                    button(text("x").size(SIZE).color(red()))
+                        .padding(2)
+                        .style(button::text)
+                        .on_press(Message::RemoveBookmark(index)),
+                ]
+                .align_y(Vertical::Center)
+                .into()
+            })
+            .collect(),
+    );
+
     let mem_header = |value: &'a str| text(value).size(SIZE).color(green());
 
     let header = row![
@@ -225,20 +397,21 @@ pub fn view<'a>(state: &State, machine: &Machine) -> Element<'a, Message> {
         .take(ADDR_COUNT)
         .collect();
 
+    let (sp, pc, hl) = (state.address("SP"), state.address("PC"), state.address("HL"));
     let mem_byte = |addr: u16| {
-        let value = machine.bus().read_byte(addr);
+        let value = state.memory[addr as usize];
 
         let t = text(format!("{value:02x}")).size(SIZE);
-        match addr {
-            addr if addr == machine.cpu().sp() => t.color(pink()),
-            addr if addr == machine.cpu().pc() => t.color(purple()),
-            addr if addr == machine.cpu().hl() => t.color(yellow()),
+        match Some(addr) {
+            addr if addr == sp => t.color(pink()),
+            addr if addr == pc => t.color(purple()),
+            addr if addr == hl => t.color(yellow()),
             _ => t,
         }
     };
 
     let mem_ascii = |addr: u16| -> Element<'a, Message> {
-        let value = match machine.bus().read_byte(addr) {
+        let value = match state.memory[addr as usize] {
             val if (0x20..=0xFE).contains(&val) => val as char,
             _ => '.',
         };
@@ -261,7 +434,10 @@ pub fn view<'a>(state: &State, machine: &Machine) -> Element<'a, Message> {
     }
 
     let content = container(column![header, grid]).width(Fill);
-    column![controls, content].spacing(10).padding(8).into()
+    column![controls, follow, bookmarks, content]
+        .spacing(10)
+        .padding(8)
+        .into()
 }
 
 #[allow(dead_code)]
@@ -0,0 +1,151 @@
+use crate::app::Message;
+use crate::theme::color::*;
+use gbemu_core::{Machine, mnemonic};
+use iced::alignment::{Horizontal, Vertical};
+use iced::widget::{Column, button, column, row, scrollable, text};
+use iced::{Element, Fill};
+
+/// Sortable column of the opcode table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortColumn {
+    Opcode,
+    Count,
+    Cycles,
+    /// Cycles per execution
+    Average,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    column: SortColumn,
+    descending: bool,
+}
+
+impl Default for Sort {
+    fn default() -> Self {
+        Self {
+            column: SortColumn::Count,
+            descending: true,
+        }
+    }
+}
+
+impl Sort {
+    /// Sort by `column`, reversing the order when it is already sorted by it
+    pub fn by(self, column: SortColumn) -> Self {
+        Self {
+            column,
+            descending: if column == self.column {
+                !self.descending
+            } else {
+                column != SortColumn::Opcode
+            },
+        }
+    }
+}
+
+/// Opcode (`$CBxx` when prefixed), executions and T-cycles
+#[cfg(feature = "profiling")]
+fn opcode_stats(machine: &Machine) -> Vec<(u16, u64, u64)> {
+    let report = machine.profile_report();
+    report
+        .opcodes
+        .iter()
+        .map(|stats| (stats.opcode, stats.count, stats.cycles))
+        .collect()
+}
+
+#[cfg(not(feature = "profiling"))]
+fn opcode_stats(_machine: &Machine) -> Vec<(u16, u64, u64)> {
+    vec![]
+}
+
+pub fn view<'a>(machine: &Machine, sort: Sort) -> Element<'a, Message> {
+    const SIZE: u32 = 12;
+
+    let mut opcodes = opcode_stats(machine);
+    match sort.column {
+        SortColumn::Opcode => opcodes.sort_by_key(|(opcode, _, _)| *opcode),
+        SortColumn::Count => opcodes.sort_by_key(|(_, count, _)| *count),
+        SortColumn::Cycles => opcodes.sort_by_key(|(_, _, cycles)| *cycles),
+        SortColumn::Average => opcodes.sort_by_key(|(_, count, cycles)| cycles / count),
+    }
+    if sort.descending {
+        opcodes.reverse();
+    }
+    let total_count: u64 = opcodes.iter().map(|(_, count, _)| count).sum();
+    let total_cycles: u64 = opcodes.iter().map(|(_, _, cycles)| cycles).sum();
+    let share = |value: u64, total: u64| format!("{:.2}%", value as f64 * 100.0 / total.max(1) as f64);
+
+    let header = |name: &'a str, column: SortColumn, width: f32| -> Element<'a, Message> {
+        let name = match sort {
+            Sort {
+                column: sorted,
+                descending,
+            } if sorted == column => {
+                format!("{name} {}", if descending { "v" } else { "^" })
+            }
+            _ => name.to_string(),
+        };
+        button(text(name).size(SIZE).color(green()))
+            .padding(0)
+            .width(width)
+            .style(button::text)
+            .on_press(Message::SortOpcodes(column))
+            .into()
+    };
+    let cell = |value: String, width: f32| text(value).size(SIZE).width(width).align_x(Horizontal::Right);
+
+    let headers = row![
+        header("OPCODE", SortColumn::Opcode, 60.0),
+        text("INSTRUCTION").size(SIZE).color(green()).width(100),
+        header("COUNT", SortColumn::Count, 90.0),
+        text("%").size(SIZE).color(green()).width(60).align_x(Horizontal::Right),
+        header("CYCLES", SortColumn::Cycles, 100.0),
+        text("%").size(SIZE).color(green()).width(60).align_x(Horizontal::Right),
+        header("AVG", SortColumn::Average, 40.0),
+    ]
+    .spacing(6);
+
+    let rows = Column::from_vec(
+        opcodes
+            .iter()
+            .map(|&(opcode, count, cycles)| {
+                let name = if opcode > 0xFF {
+                    format!("${opcode:04X}")
+                } else {
+                    format!("${opcode:02X}")
+                };
+                row![
+                    text(name).size(SIZE).color(orange()).width(60),
+                    text(mnemonic(opcode).unwrap_or_default()).size(SIZE).width(100),
+                    cell(count.to_string(), 90.0),
+                    cell(share(count, total_count), 60.0),
+                    cell(cycles.to_string(), 100.0),
+                    cell(share(cycles, total_cycles), 60.0),
+                    cell((cycles / count).to_string(), 40.0),
+                ]
+                .spacing(6)
+                .into()
+            })
+            .collect(),
+    );
+
+    let summary = if cfg!(feature = "profiling") {
+        format!("{total_count} instructions, {total_cycles} cycles")
+    } else {
+        "Built without the profiling feature".to_string()
+    };
+    let controls = row![
+        text(summary).size(SIZE).color(purple()).width(Fill),
+        button(text("Reset").size(SIZE))
+            .style(button::secondary)
+            .on_press_maybe(cfg!(feature = "profiling").then_some(Message::ResetProfile)),
+    ]
+    .align_y(Vertical::Center);
+
+    column![controls, headers, scrollable(rows).height(Fill)]
+        .spacing(4)
+        .padding(4)
+        .into()
+}
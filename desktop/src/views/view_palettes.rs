@@ -0,0 +1,96 @@
+use crate::app::Message;
+use crate::theme::color::*;
+use gbemu_core::{Machine, Palette};
+use iced::widget::{Space, button, column, row, text};
+use iced::{Color, Element};
+
+/// DMG palette registers editable in the panel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteRegister {
+    Bgp,
+    Obp0,
+    Obp1,
+}
+
+impl PaletteRegister {
+    const ALL: [PaletteRegister; 3] = [PaletteRegister::Bgp, PaletteRegister::Obp0, PaletteRegister::Obp1];
+
+    fn name(&self) -> &'static str {
+        match self {
+            PaletteRegister::Bgp => "BGP",
+            PaletteRegister::Obp0 => "OBP0",
+            PaletteRegister::Obp1 => "OBP1",
+        }
+    }
+
+    fn address(&self) -> u16 {
+        match self {
+            PaletteRegister::Bgp => 0xFF47,
+            PaletteRegister::Obp0 => 0xFF48,
+            PaletteRegister::Obp1 => 0xFF49,
+        }
+    }
+
+    fn read(&self, machine: &Machine) -> Palette {
+        match self {
+            PaletteRegister::Bgp => machine.bgp(),
+            PaletteRegister::Obp0 => machine.obp0(),
+            PaletteRegister::Obp1 => machine.obp1(),
+        }
+    }
+
+    pub fn write(&self, machine: &mut Machine, palette: Palette) {
+        match self {
+            PaletteRegister::Bgp => machine.set_bgp(palette),
+            PaletteRegister::Obp0 => machine.set_obp0(palette),
+            PaletteRegister::Obp1 => machine.set_obp1(palette),
+        }
+    }
+}
+
+/// Each color id shows its shade, a click selects the next shade
+pub fn view<'a>(machine: &Machine) -> Element<'a, Message> {
+    const SIZE: u32 = 12;
+
+    let colors = *machine.color_palette();
+    let register_row = |register: PaletteRegister| -> Element<'a, Message> {
+        let palette = register.read(machine);
+        let entries = (0..4u8).map(|color_id| {
+            let shade = palette.shade(color_id);
+            let [r, g, b] = colors.color(shade);
+            let mut next = palette;
+            next.0[color_id as usize] = (shade + 1) & 0x03;
+
+            button(
+                text(shade)
+                    .size(SIZE)
+                    .color(if shade >= 2 { Color::WHITE } else { Color::BLACK }),
+            )
+            .width(28)
+            .padding([0, 8])
+            .style(move |theme, status| button::Style {
+                background: Some(Color::from_rgb8(r, g, b).into()),
+                ..button::secondary(theme, status)
+            })
+            .on_press(Message::SetPalette(register, next))
+            .into()
+        });
+
+        row![
+            Space::new().width(10.0),
+            text(format!("${:04X}", register.address())).color(orange()).size(SIZE),
+            Space::new().width(10.0),
+            text(register.name()).color(green()).width(40).size(SIZE),
+            text(format!("${:02X}", u8::from(palette))).width(40).size(SIZE),
+        ]
+        .extend(entries)
+        .spacing(2)
+        .into()
+    };
+
+    column![text("DMG PALETTES:").color(purple()).size(SIZE)]
+        .extend(PaletteRegister::ALL.map(register_row))
+        .spacing(4)
+        .padding(4)
+        .into()
+}
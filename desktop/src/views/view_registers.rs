@@ -1,9 +1,9 @@
 use crate::app::Message;
 use crate::theme::color::*;
-use gbemu_core::Machine;
+use gbemu_core::{Interrupt, Machine};
 use iced::Element;
 use iced::alignment::Horizontal;
-use iced::widget::{Space, column, row, text};
+use iced::widget::{Column, Space, button, column, row, text};
 
 pub fn view<'a>(machine: &Machine) -> Element<'a, Message> {
     const SIZE: u32 = 12;
@@ -33,10 +33,11 @@ pub fn view<'a>(machine: &Machine) -> Element<'a, Message> {
         ]
         .into()
     };
-    let io_reg_flag = |name: &'a str, val_if, val_ie: bool| -> Element<'a, Message> {
+    let break_on = machine.breakpoint_manager().interrupt_breaks();
+    let io_reg_flag = |interrupt: Interrupt, val_ie: bool, val_if: bool| -> Element<'a, Message> {
         row![
             Space::new().width(15.0),
-            text(name).color(orange()).width(60).size(SIZE),
+            text(interrupt.name()).color(orange()).width(60).size(SIZE),
             if val_if {
                 text("On").width(40).color(red()).size(SIZE)
             } else {
@@ -49,22 +50,37 @@ pub fn view<'a>(machine: &Machine) -> Element<'a, Message> {
             text("IE").color(blue()).size(SIZE),
             Space::new().width(8.0),
             text(if val_ie { "1" } else { "0" }).size(SIZE),
+            Space::new().width(10.0),
+            button(text("IRQ").size(SIZE - 2))
+                .padding([0, 4])
+                .style(button::secondary)
+                .on_press(Message::RequestInterrupt(interrupt)),
+            Space::new().width(4.0),
+            button(text("BRK").size(SIZE - 2))
+                .padding([0, 4])
+                .style(if break_on.contains(interrupt) {
+                    button::danger
+                } else {
+                    button::secondary
+                })
+                .on_press(Message::BreakOnInterruptToggle(interrupt)),
         ]
         .into()
     };
 
-    let ie_val = machine.bus().read_byte(0xFFFF);
-    let if_val = machine.bus().read_byte(0xFF0F);
+    let interrupts = machine.interrupt_state();
+    let interrupt_flags = Column::with_children(
+        interrupts
+            .sources()
+            .map(|(interrupt, enabled, requested)| io_reg_flag(interrupt, enabled, requested)),
+    );
+    let ppu = machine.ppu_state();
     row![
         column![
             title("INTERRUPTS"),
-            io_reg8("IE", 0xFFFF, ie_val),
-            io_reg8("IF", 0xFF0F, if_val),
-            io_reg_flag("VBLNK", if_val & 0b0000_0001 != 0, ie_val & 0b0000_0001 != 0),
-            io_reg_flag("STAT", if_val & 0b0000_0010 != 0, ie_val & 0b0000_0010 != 0),
-            io_reg_flag("TIMER", if_val & 0b0000_0100 != 0, ie_val & 0b0000_0100 != 0),
-            io_reg_flag("SERIAL", if_val & 0b0000_1000 != 0, ie_val & 0b0000_1000 != 0),
-            io_reg_flag("JOYPAD", if_val & 0b0001_0000 != 0, ie_val & 0b0001_0000 != 0),
+            io_reg8("IE", 0xFFFF, interrupts.enable),
+            io_reg8("IF", 0xFF0F, interrupts.flag),
+            interrupt_flags,
             title("GBC"),
             io_reg8("KEY1", 0xFF4D, machine.bus().read_byte(0xFF4D)),
             io_reg8("SVBK", 0xFF70, machine.bus().read_byte(0xFF70)),
@@ -84,18 +100,19 @@ pub fn view<'a>(machine: &Machine) -> Element<'a, Message> {
         Space::new().width(10.0),
         column![
             title("LCD"),
-            io_reg8("LCDC", 0xFF40, machine.bus().read_byte(0xFF40)),
-            io_reg8("STAT", 0xFF41, machine.bus().read_byte(0xFF41)),
-            io_reg8("SCY", 0xFF42, machine.bus().read_byte(0xFF42)),
-            io_reg8("SCX", 0xFF43, machine.bus().read_byte(0xFF43)),
-            io_reg8("LY", 0xFF44, machine.bus().read_byte(0xFF44)),
-            io_reg8("LYC", 0xFF45, machine.bus().read_byte(0xFF45)),
+            io_reg8("LCDC", 0xFF40, ppu.lcdc),
+            io_reg8("STAT", 0xFF41, ppu.stat),
+            io_reg8("SCY", 0xFF42, ppu.scy),
+            io_reg8("SCX", 0xFF43, ppu.scx),
+            io_reg8("LY", 0xFF44, ppu.ly),
+            io_reg8("LYC", 0xFF45, ppu.lyc),
             io_reg8("DMA", 0xFF46, machine.bus().read_byte(0xFF46)),
-            io_reg8("BGP", 0xFF47, machine.bus().read_byte(0xFF47)),
-            io_reg8("OBP0", 0xFF48, machine.bus().read_byte(0xFF48)),
-            io_reg8("OBP1", 0xFF49, machine.bus().read_byte(0xFF49)),
-            io_reg8("WY", 0xFF4A, machine.bus().read_byte(0xFF4A)),
-            io_reg8("WX", 0xFF4B, machine.bus().read_byte(0xFF4B)),
+            io_reg8("BGP", 0xFF47, ppu.bgp),
+            io_reg8("OBP0", 0xFF48, ppu.obp0),
+            io_reg8("OBP1", 0xFF49, ppu.obp1),
+            io_reg8("WY", 0xFF4A, ppu.wy),
+            io_reg8("WX", 0xFF4B, ppu.wx),
+            text(format!("{:?}", ppu.mode)).color(blue()).size(SIZE),
             title("TIMER"),
             io_reg8("DIV", 0xFF04, machine.bus().read_byte(0xFF04)),
             io_reg8("TIMA", 0xFF05, machine.bus().read_byte(0xFF05)),
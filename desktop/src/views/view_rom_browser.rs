@@ -0,0 +1,338 @@
+use crate::config::Config;
+use crate::library::{Library, LibraryEntry};
+use crate::stats::{PlayStats, format_play_time};
+use crate::theme::color::{green, purple, red};
+use crate::views::view_save_slots::format_age;
+use crate::widgets::thumbnail::Thumbnail;
+use gbemu_core::state::{THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
+use gbemu_core::video::SCREEN_WIDTH;
+use gbemu_core::{ColorPalette, RomPreview, rom_preview};
+use iced::alignment::Horizontal;
+use iced::futures::channel::mpsc;
+use iced::task::Handle;
+use iced::widget::{Column, Row, button, canvas, column, container, mouse_area, pick_list, row, text, text_input};
+use iced::{Element, Task};
+use log::{error, warn};
+use std::cmp::Reverse;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const ROMS_PER_ROW: usize = 4;
+const SPACING: f32 = 8.0;
+const SIZE: u32 = 12;
+/// Frames run before the screen is kept, about 4 seconds past the fast boot
+const PREVIEW_FRAMES: usize = 240;
+const ROM_EXTENSIONS: [&str; 2] = ["gb", "zip"];
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    AddFolder,
+    RemoveFolder(usize),
+    Rescan,
+    Search(String),
+    Sort(SortOrder),
+    Select(PathBuf),
+    Open(PathBuf),
+    Previewed(PathBuf, Result<RomPreview, String>),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    #[default]
+    Title,
+    LastPlayed,
+    PlayTime,
+    Mapper,
+}
+
+impl SortOrder {
+    const ALL: [SortOrder; 4] = [
+        SortOrder::Title,
+        SortOrder::LastPlayed,
+        SortOrder::PlayTime,
+        SortOrder::Mapper,
+    ];
+}
+
+impl Display for SortOrder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            SortOrder::Title => "Title",
+            SortOrder::LastPlayed => "Last played",
+            SortOrder::PlayTime => "Play time",
+            SortOrder::Mapper => "Mapper",
+        };
+        write!(f, "{name}")
+    }
+}
+
+struct Rom {
+    path: PathBuf,
+    /// Waiting for the background thread to boot it
+    pending: bool,
+    error: Option<String>,
+    cache: canvas::Cache,
+}
+
+#[derive(Default)]
+pub struct State {
+    folders: Vec<PathBuf>,
+    library: Library,
+    /// Roms of the folders, listed when the panel is first shown
+    roms: Option<Vec<Rom>>,
+    /// Aborts the previews of the previous listing when dropped
+    previews: Option<Handle>,
+    search: String,
+    sort: SortOrder,
+    selected: Option<PathBuf>,
+}
+
+impl State {
+    pub fn new(folders: Vec<PathBuf>, library: Library) -> Self {
+        Self {
+            folders,
+            library,
+            ..Self::default()
+        }
+    }
+
+    pub fn folders(&self) -> &[PathBuf] {
+        &self.folders
+    }
+
+    pub fn add_folder(&mut self, folder: PathBuf) {
+        if !self.folders.contains(&folder) {
+            self.folders.push(folder);
+            self.roms = None;
+        }
+    }
+
+    pub fn remove_folder(&mut self, index: usize) {
+        if index < self.folders.len() {
+            self.folders.remove(index);
+            self.roms = None;
+        }
+    }
+
+    pub fn needs_scan(&self) -> bool {
+        self.roms.is_none()
+    }
+
+    /// List the roms of the folders, and boot the new or modified ones in a background thread for their preview
+    pub fn scan(&mut self) -> Task<Message> {
+        let paths: Vec<PathBuf> = self.folders.iter().flat_map(|folder| list_roms(folder)).collect();
+        self.library.retain(&paths);
+
+        let outdated: Vec<PathBuf> = paths
+            .iter()
+            .filter(|path| self.library.is_outdated(path))
+            .cloned()
+            .collect();
+        self.roms = Some(
+            paths
+                .into_iter()
+                .map(|path| Rom {
+                    pending: outdated.contains(&path),
+                    path,
+                    error: None,
+                    cache: canvas::Cache::new(),
+                })
+                .collect(),
+        );
+
+        let (sender, receiver) = mpsc::unbounded();
+        std::thread::spawn(move || {
+            for path in outdated {
+                let preview = rom_preview(&path, PREVIEW_FRAMES, SCREEN_WIDTH / THUMBNAIL_WIDTH);
+                // The receiver is gone once the listing is replaced
+                if sender
+                    .unbounded_send((path, preview.map_err(|e| e.to_string())))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+        let (task, handle) = Task::run(receiver, |(path, preview)| Message::Previewed(path, preview)).abortable();
+        self.previews = Some(handle.abort_on_drop());
+        task
+    }
+
+    pub fn update(&mut self, msg: Message) -> Task<Message> {
+        match msg {
+            Message::Rescan => return self.scan(),
+            Message::Search(search) => self.search = search,
+            Message::Sort(sort) => self.sort = sort,
+            Message::Select(path) => self.selected = Some(path),
+            Message::Previewed(path, preview) => {
+                let Some(rom) = self.roms.iter_mut().flatten().find(|rom| rom.path == path) else {
+                    return Task::none();
+                };
+                rom.pending = false;
+                rom.cache.clear();
+                match preview {
+                    Ok(preview) => {
+                        self.library.set_preview(&path, &preview);
+                        self.save_library();
+                    }
+                    Err(e) => {
+                        warn!("No preview of {}: {e}", path.display());
+                        rom.error = Some(e);
+                    }
+                }
+            }
+            // Handled by the app
+            Message::AddFolder | Message::RemoveFolder(_) | Message::Open(_) => {}
+        }
+        Task::none()
+    }
+
+    fn save_library(&self) {
+        if let Err(e) = self.library.save() {
+            error!("Failed to write library index: {e}");
+        }
+    }
+
+    /// Roms matching the search, in the sort order
+    fn visible_roms(&self, config: &Config) -> Vec<(&Rom, Option<&LibraryEntry>)> {
+        let search = self.search.to_lowercase();
+        let mut roms: Vec<_> = self
+            .roms
+            .iter()
+            .flatten()
+            .map(|rom| (rom, self.library.get(&rom.path)))
+            .filter(|(rom, entry)| {
+                let title = entry.map_or("", |entry| entry.title.as_str());
+                title.to_lowercase().contains(&search) || file_name(&rom.path).to_lowercase().contains(&search)
+            })
+            .collect();
+
+        match self.sort {
+            SortOrder::Title => roms.sort_by_key(|(rom, entry)| title(rom, *entry).to_lowercase()),
+            SortOrder::LastPlayed => roms.sort_by_key(|(_, entry)| Reverse(play_stats(config, *entry).last_played)),
+            SortOrder::PlayTime => roms.sort_by_key(|(_, entry)| Reverse(play_stats(config, *entry).wall)),
+            SortOrder::Mapper => roms.sort_by_key(|(_, entry)| entry.map(|e| e.mapper.clone())),
+        }
+        roms
+    }
+}
+
+/// Roms of the folder sorted by name, subfolders are not visited
+fn list_roms(folder: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Failed to list {}: {e}", folder.display());
+            return vec![];
+        }
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| ROM_EXTENSIONS.contains(&extension))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
+
+fn title(rom: &Rom, entry: Option<&LibraryEntry>) -> String {
+    entry.map_or_else(|| file_name(&rom.path), |entry| entry.title.clone())
+}
+
+fn play_stats(config: &Config, entry: Option<&LibraryEntry>) -> PlayStats {
+    entry.map_or_else(PlayStats::default, |entry| PlayStats::load(config, entry.header_hash))
+}
+
+pub fn view<'a>(state: &'a State, palette: &'a ColorPalette, config: &Config) -> Element<'a, Message> {
+    let controls = row![
+        button(text("Add folder").size(SIZE))
+            .style(button::secondary)
+            .on_press(Message::AddFolder),
+        button(text("Rescan").size(SIZE))
+            .style(button::secondary)
+            .on_press(Message::Rescan),
+        text_input("search", &state.search)
+            .on_input(Message::Search)
+            .size(SIZE)
+            .width(160),
+        pick_list(SortOrder::ALL, Some(state.sort), Message::Sort).text_size(SIZE),
+    ]
+    .spacing(4);
+
+    let folders = state.folders.iter().enumerate().map(|(index, folder)| {
+        row![
+            button(text("x").size(SIZE))
+                .style(button::secondary)
+                .on_press(Message::RemoveFolder(index)),
+            text(folder.display().to_string()).color(purple()).size(SIZE),
+        ]
+        .spacing(4)
+        .into()
+    });
+    let folders = match state.folders.is_empty() {
+        true => Column::new().push(text("No ROM folder").color(purple()).size(SIZE)),
+        false => Column::with_children(folders).spacing(2),
+    };
+
+    let rom_view = |(rom, entry): (&'a Rom, Option<&'a LibraryEntry>)| -> Element<'a, Message> {
+        let thumbnail = canvas(Thumbnail {
+            cache: &rom.cache,
+            pixels: entry.map_or(&[][..], |entry| &entry.thumbnail),
+            palette,
+        })
+        .width(THUMBNAIL_WIDTH as f32)
+        .height(THUMBNAIL_HEIGHT as f32);
+
+        let status = match (entry, &rom.error) {
+            (_, Some(_)) => text("unreadable").color(red()),
+            _ if rom.pending => text("...").color(green()),
+            (Some(entry), None) => text(entry.mapper.as_str()).color(green()),
+            (None, None) => text(""),
+        };
+        let stats = play_stats(config, entry);
+        let played = match stats.last_played {
+            Some(last_played) => format!("{}, {}", format_play_time(stats.wall), format_age(last_played)),
+            None => "never played".to_string(),
+        };
+
+        let card = column![
+            thumbnail,
+            text(title(rom, entry)).size(SIZE),
+            status.size(SIZE),
+            text(played).size(SIZE),
+        ]
+        .spacing(2)
+        .width(THUMBNAIL_WIDTH as f32 + 8.0)
+        .align_x(Horizontal::Center);
+        let card = match state.selected.as_ref() == Some(&rom.path) {
+            true => container(card).style(container::bordered_box),
+            false => container(card),
+        };
+
+        mouse_area(card.padding(4))
+            .on_press(Message::Select(rom.path.clone()))
+            .on_double_click(Message::Open(rom.path.clone()))
+            .into()
+    };
+    let roms = state.visible_roms(config);
+    let rows = roms.chunks(ROMS_PER_ROW).map(|roms| {
+        Row::with_children(roms.iter().copied().map(rom_view))
+            .spacing(SPACING)
+            .into()
+    });
+
+    column![controls, folders]
+        .push(Column::with_children(rows).spacing(SPACING))
+        .push(text("Double click to play").size(SIZE))
+        .spacing(SPACING)
+        .padding(4)
+        .into()
+}
@@ -0,0 +1,96 @@
+use crate::app::Message;
+use crate::theme::color::{green, purple};
+use crate::widgets::thumbnail::Thumbnail;
+use gbemu_core::ColorPalette;
+use gbemu_core::state::{SLOT_COUNT, SaveSlots, SlotInfo, THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
+use iced::Element;
+use iced::alignment::Horizontal;
+use iced::widget::{Column, button, canvas, column, row, text};
+use std::time::SystemTime;
+
+const SLOTS_PER_ROW: usize = 5;
+const SPACING: f32 = 8.0;
+
+pub struct State {
+    slots: Vec<Option<SlotInfo>>,
+    caches: Vec<canvas::Cache>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            slots: vec![None; SLOT_COUNT],
+            caches: (0..SLOT_COUNT).map(|_| canvas::Cache::new()).collect(),
+        }
+    }
+}
+
+impl State {
+    /// Read the slot headers again, after a save or a cartridge change
+    pub fn refresh(&mut self, save_slots: &SaveSlots) {
+        self.slots = save_slots.list();
+        self.caches.iter().for_each(canvas::Cache::clear);
+    }
+}
+
+pub fn view<'a>(state: &'a State, palette: &'a ColorPalette) -> Element<'a, Message> {
+    const SIZE: u32 = 12;
+
+    let slot_view = |slot: usize| -> Element<'a, Message> {
+        let info = state.slots[slot].as_ref();
+
+        let thumbnail = canvas(Thumbnail {
+            cache: &state.caches[slot],
+            pixels: info.map_or(&[][..], |info| &info.thumbnail),
+            palette,
+        })
+        .width(THUMBNAIL_WIDTH as f32)
+        .height(THUMBNAIL_HEIGHT as f32);
+
+        let age = info.map_or("empty".to_string(), |info| format_age(info.timestamp));
+
+        column![
+            text(format!("Slot {slot}")).color(purple()).size(SIZE),
+            thumbnail,
+            text(age).color(green()).size(SIZE),
+            row![
+                button(text("Save").size(SIZE))
+                    .style(button::secondary)
+                    .on_press(Message::SaveSlot(slot)),
+                button(text("Load").size(SIZE))
+                    .style(button::secondary)
+                    .on_press_maybe(info.map(|_| Message::LoadSlot(slot))),
+            ]
+            .spacing(4)
+        ]
+        .spacing(4)
+        .align_x(Horizontal::Center)
+        .into()
+    };
+
+    let rows = (0..SLOT_COUNT).step_by(SLOTS_PER_ROW).map(|first| {
+        let slots = (first..(first + SLOTS_PER_ROW).min(SLOT_COUNT)).map(slot_view);
+        iced::widget::Row::with_children(slots).spacing(SPACING).into()
+    });
+
+    Column::with_children(rows)
+        .push(text("Load: 0-9  Save: Shift+0-9").size(SIZE))
+        .spacing(SPACING)
+        .padding(4)
+        .align_x(Horizontal::Center)
+        .into()
+}
+
+pub fn format_age(timestamp: SystemTime) -> String {
+    let seconds = SystemTime::now()
+        .duration_since(timestamp)
+        .unwrap_or_default()
+        .as_secs();
+
+    match seconds {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", seconds / 60),
+        3600..86400 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} d ago", seconds / 86400),
+    }
+}
@@ -0,0 +1,42 @@
+use crate::app::Message;
+use crate::stats::{PlayStats, format_play_time};
+use crate::theme::color::*;
+use crate::views::view_save_slots::format_age;
+use gbemu_core::Machine;
+use iced::Element;
+use iced::widget::{Space, column, row, text};
+
+/// Play statistics of the game in the machine, `stats` including the current session
+pub fn view<'a>(machine: &Machine, stats: PlayStats) -> Element<'a, Message> {
+    const SIZE: u32 = 12;
+
+    let line = |name: &'a str, value: String| -> Element<'a, Message> {
+        row![
+            Space::new().width(10.0),
+            text(name).color(green()).width(110).size(SIZE),
+            text(value).size(SIZE),
+        ]
+        .into()
+    };
+    let session = machine.emulated_time();
+
+    column![
+        text(format!("{}:", machine.cartridge().title()))
+            .color(purple())
+            .size(SIZE),
+        line("HEADER HASH", format!("{:08X}", machine.cartridge().header_hash())),
+        line(
+            "SINCE RESET",
+            format!("{}.{:03} s", session.as_secs(), session.subsec_millis())
+        ),
+        line("CYCLES", machine.cycles().to_string()),
+        line("FRAMES", machine.frame_count().to_string()),
+        text("Total:").color(purple()).size(SIZE),
+        line("EMULATED", format_play_time(stats.emulated)),
+        line("PLAYED", format_play_time(stats.wall)),
+        line("LAST PLAYED", stats.last_played.map_or("never".to_string(), format_age)),
+    ]
+    .spacing(2)
+    .padding(4)
+    .into()
+}
@@ -0,0 +1,157 @@
+use crate::app::Message as AppMessage;
+use crate::theme::color::*;
+use gbemu_core::{ColorPalette, Machine, TILE_COUNT};
+use iced::mouse::Cursor;
+use iced::widget::canvas::Geometry;
+use iced::widget::{button, canvas, column, text};
+use iced::{Color, Element, Point, Rectangle, Renderer, Size, Theme};
+
+const TILES_PER_ROW: usize = 16;
+const SCALE: f32 = 2.0;
+/// Frames a changed tile stays highlighted, fading out
+const HEAT_FRAMES: u8 = 30;
+
+/// Tile data of VRAM bank 0, and of bank 1 on its right in CGB mode, with the tiles changed during the last
+/// frames highlighted
+pub struct State {
+    cache: canvas::Cache,
+    highlight: bool,
+    /// Frames left of the highlight of each tile, those of bank 1 from [`TILE_COUNT`]
+    heat: [u8; 2 * TILE_COUNT as usize],
+    /// Frame of the machine at the last refresh
+    frame: u64,
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    ToggleHighlight,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            cache: canvas::Cache::new(),
+            highlight: true,
+            heat: [0; 2 * TILE_COUNT as usize],
+            frame: 0,
+        }
+    }
+}
+
+impl State {
+    /// Redraw the tiles, the changed ones get hot when the machine completed a frame
+    pub fn refresh(&mut self, machine: &Machine) {
+        self.cache.clear();
+        let frame = machine.frame_count();
+        if frame == self.frame {
+            return;
+        }
+
+        // The frame count goes back on resets
+        let elapsed = frame.checked_sub(self.frame).unwrap_or(u64::MAX);
+        let cooling = elapsed.min(HEAT_FRAMES as u64) as u8;
+        self.heat
+            .iter_mut()
+            .for_each(|heat| *heat = heat.saturating_sub(cooling));
+        for tile in machine.changed_tiles().iter() {
+            self.heat[tile as usize] = HEAT_FRAMES;
+        }
+        self.frame = frame;
+    }
+
+    pub fn update(&mut self, msg: Message) {
+        match msg {
+            Message::ToggleHighlight => {
+                self.highlight = !self.highlight;
+                self.cache.clear();
+            }
+        }
+    }
+}
+
+pub fn view<'a>(state: &'a State, machine: &'a Machine) -> Element<'a, AppMessage> {
+    const SIZE: u32 = 12;
+
+    let banks = match machine.is_cgb_mode() {
+        true => vec![machine.vram(), machine.bus().vram_bank1()],
+        false => vec![machine.vram()],
+    };
+    let rows = (TILE_COUNT as usize).div_ceil(TILES_PER_ROW);
+    let width = banks.len() * TILES_PER_ROW * 8;
+    let tiles = canvas(TileCanvas {
+        state,
+        banks,
+        palette: machine.color_palette(),
+    })
+    .width(width as f32 * SCALE)
+    .height((rows * 8) as f32 * SCALE);
+
+    let label = match state.highlight {
+        true => text("Highlight changes: on").color(red()),
+        false => text("Highlight changes: off").color(blue()),
+    };
+    column![
+        button(label.size(SIZE))
+            .style(button::text)
+            .on_press(AppMessage::TilesView(Message::ToggleHighlight)),
+        tiles,
+    ]
+    .spacing(4)
+    .padding(4)
+    .into()
+}
+
+struct TileCanvas<'a> {
+    state: &'a State,
+    /// VRAM banks drawn side by side
+    banks: Vec<&'a [u8]>,
+    palette: &'a ColorPalette,
+}
+
+impl<Message> canvas::Program<Message> for TileCanvas<'_> {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        renderer: &Renderer,
+        _theme: &Theme,
+        bounds: Rectangle,
+        _cursor: Cursor,
+    ) -> Vec<Geometry<Renderer>> {
+        let draw = self.state.cache.draw(renderer, bounds.size(), |frame| {
+            frame.scale(SCALE);
+            let [r, g, b] = self.palette.color(0);
+            frame.fill_rectangle(Point::ORIGIN, bounds.size() * (1.0 / SCALE), Color::from_rgb8(r, g, b));
+
+            let tiles = self
+                .banks
+                .iter()
+                .flat_map(|vram| vram.chunks_exact(16).take(TILE_COUNT as usize));
+            for (tile, data) in tiles.enumerate() {
+                let (bank, index) = (tile / TILE_COUNT as usize, tile % TILE_COUNT as usize);
+                let column = bank * TILES_PER_ROW + index % TILES_PER_ROW;
+                let origin = Point::new((column * 8) as f32, (index / TILES_PER_ROW * 8) as f32);
+                for (y, row) in data.chunks_exact(2).enumerate() {
+                    for x in 0..8 {
+                        let bit = 7 - x;
+                        let color_id = (row[0] >> bit) & 1 | ((row[1] >> bit) & 1) << 1;
+                        if color_id == 0 {
+                            continue;
+                        }
+                        let [r, g, b] = self.palette.color(color_id);
+                        let point = Point::new(origin.x + x as f32, origin.y + y as f32);
+                        frame.fill_rectangle(point, Size::new(1.0, 1.0), Color::from_rgb8(r, g, b));
+                    }
+                }
+
+                let heat = self.state.heat[tile];
+                if self.state.highlight && heat > 0 {
+                    let alpha = 0.6 * heat as f32 / HEAT_FRAMES as f32;
+                    frame.fill_rectangle(origin, Size::new(8.0, 8.0), Color { a: alpha, ..red() });
+                }
+            }
+        });
+        vec![draw]
+    }
+}
@@ -0,0 +1,84 @@
+use crate::app::Message;
+use crate::theme::color::{green, purple};
+use gbemu_core::JoypadButton;
+use gbemu_core::state::Session;
+use iced::Element;
+use iced::widget::{Column, Row, button, column, row, text};
+
+const SIZE: u32 = 12;
+/// Frames listed around the shown one
+const ROWS: u32 = 16;
+
+const BUTTONS: [(JoypadButton, &str); 8] = [
+    (JoypadButton::Left, "<"),
+    (JoypadButton::Up, "^"),
+    (JoypadButton::Down, "v"),
+    (JoypadButton::Right, ">"),
+    (JoypadButton::Select, "SEL"),
+    (JoypadButton::Start, "STA"),
+    (JoypadButton::B, "B"),
+    (JoypadButton::A, "A"),
+];
+
+/// Inputs of the frames around the shown one of the opened session. The frame numbers of the greenzone are
+/// green, a click on a button toggles it and the session is replayed up to the shown frame.
+pub fn view<'a>(session: Option<&(Session, u32)>) -> Element<'a, Message> {
+    let Some(&(ref session, position)) = session else {
+        return text("Open a session to edit its inputs").size(SIZE).into();
+    };
+    let first = position.saturating_sub(ROWS / 2);
+    let last = (first + ROWS).min(session.frame_count());
+    let greenzone = session.greenzone();
+
+    let frames = (first..last).map(|frame| {
+        let pressed = session.inputs()[frame as usize];
+        let number = text(format!("{frame:>6}"))
+            .size(SIZE)
+            .color(if frame <= greenzone { green() } else { purple() });
+        let seek = button(number)
+            .on_press(Message::SessionSeek(frame))
+            .style(if frame == position {
+                button::primary
+            } else {
+                button::text
+            });
+
+        let buttons = BUTTONS.iter().map(|&(joypad_button, name)| {
+            button(text(name).size(SIZE))
+                .on_press(Message::TimelineToggle(frame, joypad_button))
+                .style(match pressed & 1 << joypad_button.bit() != 0 {
+                    true => button::primary,
+                    false => button::secondary,
+                })
+                .into()
+        });
+
+        row![seek]
+            .extend(buttons)
+            .push(
+                button(text("+").size(SIZE))
+                    .on_press(Message::TimelineInsert(frame))
+                    .style(button::text),
+            )
+            .push(
+                button(text("-").size(SIZE))
+                    .on_press(Message::TimelineRemove(frame))
+                    .style(button::text),
+            )
+            .spacing(2)
+            .into()
+    });
+
+    column![
+        text(format!("{} frames, greenzone up to {greenzone}", session.frame_count())).size(SIZE),
+        Column::with_children(frames).spacing(2),
+        Row::new().push(
+            button(text(format!("Record from frame {position}")).size(SIZE))
+                .on_press(Message::TimelineRecord)
+                .style(button::secondary),
+        ),
+    ]
+    .spacing(4)
+    .padding(4)
+    .into()
+}
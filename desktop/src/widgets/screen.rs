@@ -1,34 +1,86 @@
+use gbemu_core::video::{FrameBlend, FrameFilter, SCREEN_HEIGHT, SCREEN_WIDTH, rgb555_to_rgb, to_rgb};
+use gbemu_core::{ChangedLines, ColorPalette};
 use iced::mouse::Cursor;
 use iced::widget::canvas;
 use iced::widget::canvas::Geometry;
 use iced::{Color, Element, Point, Size, Task};
 use iced::{Rectangle, Renderer, Theme};
 
-#[derive(Default)]
+/// Lines of the screen drawn by each canvas cache, only the bands with changed lines are redrawn
+const BAND_HEIGHT: usize = 16;
+const BAND_COUNT: usize = Screen::HEIGHT / BAND_HEIGHT;
+
 pub struct Screen {
-    cache: canvas::Cache,
+    bands: [canvas::Cache; BAND_COUNT],
+    frame_blend: Option<FrameBlend>,
+    /// Frame after the filters, empty without filter
+    filtered: Vec<[u8; 3]>,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self {
+            bands: std::array::from_fn(|_| canvas::Cache::new()),
+            frame_blend: None,
+            filtered: vec![],
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
-    UpdateFrameBuffer,
+    UpdateFrameBuffer(ChangedLines),
 }
 
 impl Screen {
-    pub const WIDTH: usize = 160;
-    pub const HEIGHT: usize = 144;
+    pub const WIDTH: usize = SCREEN_WIDTH;
+    pub const HEIGHT: usize = SCREEN_HEIGHT;
 
     pub fn update(&mut self, message: Message) -> Task<Message> {
         match message {
-            Message::UpdateFrameBuffer => self.clear(),
+            Message::UpdateFrameBuffer(changed_lines) => {
+                for line in changed_lines.iter() {
+                    self.bands[line as usize / BAND_HEIGHT].clear();
+                }
+            }
         }
 
         Task::none()
     }
-    pub fn view<'a>(&'a self, frame_buffer: &'a [u8]) -> Element<'a, Message> {
+    /// Blend each frame with the previous one with this factor, see [`FrameBlend`]
+    pub fn set_frame_blend(&mut self, factor: Option<u8>) {
+        self.frame_blend = factor.map(FrameBlend::new);
+        self.filtered.clear();
+        self.clear();
+    }
+    pub fn frame_blend(&self) -> Option<u8> {
+        self.frame_blend.as_ref().map(FrameBlend::factor)
+    }
+    /// Run the filters on a new frame. The result depends on the previous frames, the whole screen is redrawn.
+    /// `colors` is the RGB555 frame of the CGB mode, the palette then does not apply.
+    pub fn filter(&mut self, frame_buffer: &[u8], colors: Option<&[u16]>, palette: &ColorPalette) {
+        let Some(frame_blend) = self.frame_blend.as_mut() else {
+            return;
+        };
+        self.filtered = match colors {
+            Some(colors) => rgb555_to_rgb(colors).collect(),
+            None => to_rgb(frame_buffer, palette).collect(),
+        };
+        frame_blend.apply(&mut self.filtered);
+        self.clear();
+    }
+    pub fn view<'a>(
+        &'a self,
+        frame_buffer: &'a [u8],
+        colors: Option<&'a [u16]>,
+        palette: &'a ColorPalette,
+    ) -> Element<'a, Message> {
         canvas(ScreenCanvas {
-            cache: &self.cache,
+            bands: &self.bands,
             frame_buffer,
+            colors,
+            filtered: &self.filtered,
+            palette,
         })
         .width(Self::WIDTH as f32)
         .height(Self::HEIGHT as f32 + 1.0)
@@ -36,13 +88,17 @@ impl Screen {
     }
 
     pub fn clear(&mut self) {
-        self.cache.clear();
+        self.bands.iter().for_each(canvas::Cache::clear);
     }
 }
 
 struct ScreenCanvas<'a> {
-    cache: &'a canvas::Cache,
+    bands: &'a [canvas::Cache],
     frame_buffer: &'a [u8],
+    /// RGB555 frame of the CGB mode
+    colors: Option<&'a [u16]>,
+    filtered: &'a [[u8; 3]],
+    palette: &'a ColorPalette,
 }
 impl<'a> canvas::Program<Message> for ScreenCanvas<'a> {
     type State = ();
@@ -55,33 +111,42 @@ impl<'a> canvas::Program<Message> for ScreenCanvas<'a> {
         bounds: Rectangle,
         _cursor: Cursor,
     ) -> Vec<Geometry<Renderer>> {
-        let draw = self.cache.draw(renderer, bounds.size(), |frame| {
-            let background = canvas::Path::rectangle(
-                Point::from([0f32, 0f32]),
-                Size::new(Screen::WIDTH as f32, Screen::HEIGHT as f32),
-            );
-            frame.fill(&background, Color::from_rgb8(15, 56, 15));
-
-            for x in 0..Screen::WIDTH {
-                for y in 0..Screen::HEIGHT {
-                    let point = Point::from([x as f32, y as f32]);
-                    let index = x + (Screen::WIDTH * y);
+        self.bands
+            .iter()
+            .enumerate()
+            .map(|(band, cache)| {
+                let lines = band * BAND_HEIGHT..(band + 1) * BAND_HEIGHT;
+                cache.draw(renderer, bounds.size(), |frame| {
+                    let background = canvas::Path::rectangle(
+                        Point::from([0f32, lines.start as f32]),
+                        Size::new(Screen::WIDTH as f32, BAND_HEIGHT as f32),
+                    );
+                    let darkest = match self.colors {
+                        Some(_) => [0, 0, 0],
+                        None => self.palette.color(3),
+                    };
+                    let [r, g, b] = darkest;
+                    frame.fill(&background, Color::from_rgb8(r, g, b));
 
-                    let color = self.frame_buffer[index];
-                    if color > 2 {
-                        continue;
-                    }
-                    let color = match color {
-                        0 => Color::from_rgb8(155, 188, 15),
-                        1 => Color::from_rgb8(139, 172, 15),
-                        2 => Color::from_rgb8(48, 98, 48),
-                        _ => Color::from_rgb8(15, 56, 15), // background color
+                    let pixels = lines.start * Screen::WIDTH..lines.end * Screen::WIDTH;
+                    let rgb: Vec<[u8; 3]> = match (self.filtered.get(pixels.clone()), self.colors) {
+                        (Some(filtered), _) => filtered.to_vec(),
+                        (None, Some(colors)) => rgb555_to_rgb(&colors[pixels]).collect(),
+                        (None, None) => to_rgb(&self.frame_buffer[pixels], self.palette).collect(),
                     };
-                    let size = Size::new(1.0, 1.0);
-                    frame.fill_rectangle(point, size, color)
-                }
-            }
-        });
-        vec![draw]
+                    for (index, rgb) in rgb.into_iter().enumerate() {
+                        if rgb == darkest {
+                            continue;
+                        }
+                        let point = Point::from([
+                            (index % Screen::WIDTH) as f32,
+                            (lines.start + index / Screen::WIDTH) as f32,
+                        ]);
+                        let [r, g, b] = rgb;
+                        frame.fill_rectangle(point, Size::new(1.0, 1.0), Color::from_rgb8(r, g, b))
+                    }
+                })
+            })
+            .collect()
     }
 }
@@ -0,0 +1,49 @@
+use gbemu_core::ColorPalette;
+use gbemu_core::state::{THUMBNAIL_HEIGHT, THUMBNAIL_WIDTH};
+use gbemu_core::video::to_rgb;
+use iced::mouse::Cursor;
+use iced::widget::canvas;
+use iced::widget::canvas::Geometry;
+use iced::{Color, Point, Rectangle, Renderer, Size, Theme};
+
+/// Downscaled frame of [`THUMBNAIL_WIDTH`] x [`THUMBNAIL_HEIGHT`] shade ids, a gray box when empty
+pub struct Thumbnail<'a> {
+    pub cache: &'a canvas::Cache,
+    pub pixels: &'a [u8],
+    pub palette: &'a ColorPalette,
+}
+
+impl<Message> canvas::Program<Message> for Thumbnail<'_> {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        renderer: &Renderer,
+        _theme: &Theme,
+        bounds: Rectangle,
+        _cursor: Cursor,
+    ) -> Vec<Geometry<Renderer>> {
+        let draw = self.cache.draw(renderer, bounds.size(), |frame| {
+            let size = Size::new(THUMBNAIL_WIDTH as f32, THUMBNAIL_HEIGHT as f32);
+            if self.pixels.is_empty() {
+                frame.fill_rectangle(Point::ORIGIN, size, Color::from_rgb8(40, 40, 40));
+                return;
+            }
+
+            let darkest = self.palette.color(3);
+            let [r, g, b] = darkest;
+            frame.fill_rectangle(Point::ORIGIN, size, Color::from_rgb8(r, g, b));
+
+            for (index, rgb) in to_rgb(self.pixels, self.palette).enumerate() {
+                if rgb == darkest {
+                    continue;
+                }
+                let point = Point::new((index % THUMBNAIL_WIDTH) as f32, (index / THUMBNAIL_WIDTH) as f32);
+                let [r, g, b] = rgb;
+                frame.fill_rectangle(point, Size::new(1.0, 1.0), Color::from_rgb8(r, g, b));
+            }
+        });
+        vec![draw]
+    }
+}
@@ -0,0 +1,277 @@
+use iced::widget::pane_grid::{self, Axis, Configuration, DragEvent, Node, Pane, ResizeEvent};
+
+/// Dockable panels of the main window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Panel {
+    Screen,
+    Cpu,
+    IoRegisters,
+    Memory,
+    SaveStates,
+    Mapper,
+    Audio,
+    Palettes,
+    Tiles,
+    Opcodes,
+    RomBrowser,
+    Stats,
+    Keybindings,
+    Diff,
+    Timeline,
+    About,
+}
+
+impl Panel {
+    pub const ALL: [Panel; 16] = [
+        Panel::Screen,
+        Panel::Cpu,
+        Panel::IoRegisters,
+        Panel::Memory,
+        Panel::SaveStates,
+        Panel::Mapper,
+        Panel::Audio,
+        Panel::Palettes,
+        Panel::Tiles,
+        Panel::Opcodes,
+        Panel::RomBrowser,
+        Panel::Stats,
+        Panel::Keybindings,
+        Panel::Diff,
+        Panel::Timeline,
+        Panel::About,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Panel::Screen => "SCREEN",
+            Panel::Cpu => "CPU",
+            Panel::IoRegisters => "IO REGISTERS",
+            Panel::Memory => "MEMORY",
+            Panel::SaveStates => "SAVE STATES",
+            Panel::Mapper => "MAPPER",
+            Panel::Audio => "AUDIO",
+            Panel::Palettes => "PALETTES",
+            Panel::Tiles => "TILES",
+            Panel::Opcodes => "OPCODES",
+            Panel::RomBrowser => "ROMS",
+            Panel::Stats => "STATS",
+            Panel::Keybindings => "KEYS",
+            Panel::Diff => "DIFF",
+            Panel::Timeline => "TIMELINE",
+            Panel::About => "ABOUT",
+        }
+    }
+
+    /// Name used in the config file
+    fn key(&self) -> &'static str {
+        match self {
+            Panel::Screen => "screen",
+            Panel::Cpu => "cpu",
+            Panel::IoRegisters => "io",
+            Panel::Memory => "memory",
+            Panel::SaveStates => "save_states",
+            Panel::Mapper => "mapper",
+            Panel::Audio => "audio",
+            Panel::Palettes => "palettes",
+            Panel::Tiles => "tiles",
+            Panel::Opcodes => "opcodes",
+            Panel::RomBrowser => "roms",
+            Panel::Stats => "stats",
+            Panel::Keybindings => "keys",
+            Panel::Diff => "diff",
+            Panel::Timeline => "timeline",
+            Panel::About => "about",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Panel> {
+        Panel::ALL.into_iter().find(|panel| panel.key() == key)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Message {
+    Dragged(DragEvent),
+    Resized(ResizeEvent),
+    Toggle(Panel),
+    Maximize(Pane),
+    Restore,
+    Play,
+    Debug,
+}
+
+/// Arrangement of the panels, hidden panels are not part of the pane grid.
+pub struct Workspace {
+    panes: pane_grid::State<Panel>,
+}
+
+impl Workspace {
+    /// Every panel visible
+    pub fn debug() -> Self {
+        Self::new(split(
+            Axis::Horizontal,
+            0.45,
+            split(
+                Axis::Vertical,
+                0.22,
+                Configuration::Pane(Panel::Cpu),
+                split(
+                    Axis::Vertical,
+                    0.72,
+                    Configuration::Pane(Panel::IoRegisters),
+                    Configuration::Pane(Panel::Screen),
+                ),
+            ),
+            split(
+                Axis::Horizontal,
+                0.6,
+                Configuration::Pane(Panel::Memory),
+                split(
+                    Axis::Vertical,
+                    0.7,
+                    Configuration::Pane(Panel::SaveStates),
+                    split(
+                        Axis::Horizontal,
+                        0.5,
+                        Configuration::Pane(Panel::Mapper),
+                        split(
+                            Axis::Vertical,
+                            0.5,
+                            Configuration::Pane(Panel::Audio),
+                            split(
+                                Axis::Horizontal,
+                                0.5,
+                                Configuration::Pane(Panel::Palettes),
+                                Configuration::Pane(Panel::Opcodes),
+                            ),
+                        ),
+                    ),
+                ),
+            ),
+        ))
+    }
+
+    /// Screen and save states only
+    pub fn play() -> Self {
+        Self::new(split(
+            Axis::Horizontal,
+            0.5,
+            Configuration::Pane(Panel::Screen),
+            Configuration::Pane(Panel::SaveStates),
+        ))
+    }
+
+    fn new(configuration: Configuration<Panel>) -> Self {
+        Self {
+            panes: pane_grid::State::with_configuration(configuration),
+        }
+    }
+
+    /// Restore a layout written by [`Workspace::layout`].
+    pub fn from_layout(layout: &str) -> Option<Self> {
+        let mut parser = LayoutParser { input: layout.trim() };
+        let configuration = parser.node()?;
+        if !parser.input.is_empty() {
+            return None;
+        }
+
+        let workspace = Self::new(configuration);
+        let unique = Panel::ALL
+            .iter()
+            .all(|panel| workspace.panes.iter().filter(|(_, p)| *p == panel).count() <= 1);
+        unique.then_some(workspace)
+    }
+
+    /// Compact description of the layout, e.g. `h0.50(screen,save_states)`
+    pub fn layout(&self) -> String {
+        self.write_node(self.panes.layout())
+    }
+
+    fn write_node(&self, node: &Node) -> String {
+        match node {
+            Node::Split { axis, ratio, a, b, .. } => {
+                let axis = match axis {
+                    Axis::Horizontal => 'h',
+                    Axis::Vertical => 'v',
+                };
+                format!("{axis}{ratio:.2}({},{})", self.write_node(a), self.write_node(b))
+            }
+            Node::Pane(pane) => self.panes.get(*pane).map_or("", |panel| panel.key()).to_string(),
+        }
+    }
+
+    pub fn panes(&self) -> &pane_grid::State<Panel> {
+        &self.panes
+    }
+
+    pub fn is_visible(&self, panel: Panel) -> bool {
+        self.find(panel).is_some()
+    }
+
+    fn find(&self, panel: Panel) -> Option<Pane> {
+        self.panes.iter().find(|(_, p)| **p == panel).map(|(pane, _)| *pane)
+    }
+
+    pub fn update(&mut self, message: Message) {
+        match message {
+            Message::Dragged(DragEvent::Dropped { pane, target }) => self.panes.drop(pane, target),
+            Message::Dragged(_) => {}
+            Message::Resized(ResizeEvent { split, ratio }) => self.panes.resize(split, ratio),
+            Message::Toggle(panel) => self.toggle(panel),
+            Message::Maximize(pane) => self.panes.maximize(pane),
+            Message::Restore => self.panes.restore(),
+            Message::Play => *self = Self::play(),
+            Message::Debug => *self = Self::debug(),
+        }
+    }
+
+    /// Hide a visible panel, or dock a hidden one next to the last pane.
+    /// The last visible panel cannot be hidden.
+    fn toggle(&mut self, panel: Panel) {
+        if let Some(pane) = self.find(panel) {
+            self.panes.close(pane);
+        } else if let Some(&target) = self.panes.iter().map(|(pane, _)| pane).last() {
+            self.panes.restore();
+            self.panes.split(Axis::Vertical, target, panel);
+        }
+    }
+}
+
+fn split(axis: Axis, ratio: f32, a: Configuration<Panel>, b: Configuration<Panel>) -> Configuration<Panel> {
+    Configuration::Split {
+        axis,
+        ratio,
+        a: Box::new(a),
+        b: Box::new(b),
+    }
+}
+
+/// `node := panel | ('h' | 'v') ratio '(' node ',' node ')'`
+struct LayoutParser<'a> {
+    input: &'a str,
+}
+
+impl LayoutParser<'_> {
+    fn node(&mut self) -> Option<Configuration<Panel>> {
+        let axis = match self.input.chars().next()? {
+            'h' if self.input[1..].starts_with(|c: char| c.is_ascii_digit()) => Axis::Horizontal,
+            'v' if self.input[1..].starts_with(|c: char| c.is_ascii_digit()) => Axis::Vertical,
+            _ => {
+                let end = self.input.find([',', ')']).unwrap_or(self.input.len());
+                let panel = Panel::from_key(&self.input[..end])?;
+                self.input = &self.input[end..];
+                return Some(Configuration::Pane(panel));
+            }
+        };
+
+        let end = self.input.find('(')?;
+        let ratio: f32 = self.input[1..end].parse().ok()?;
+        self.input = &self.input[end + 1..];
+        let a = self.node()?;
+        self.input = self.input.strip_prefix(',')?;
+        let b = self.node()?;
+        self.input = self.input.strip_prefix(')')?;
+
+        Some(split(axis, ratio.clamp(0.0, 1.0), a, b))
+    }
+}
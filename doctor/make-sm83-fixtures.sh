@@ -0,0 +1,27 @@
+#!/usr/bin/env bash
+# Bundle the first cases of each sm83 vector file into core/fixtures/sm83, run by
+# `cargo test -p gbemu-core --features sm83-vectors`. Needs jq and zip, and the vectors cloned by setup.sh.
+source settings.inc
+
+CASES=${CASES:-50}
+SM83_JSON="${GAMEBOY_SM83_DIR}/v1"
+FIXTURES=$(realpath "${CURRENT_DIR}/../core")/fixtures/sm83
+WORK=$(mktemp -d)
+
+mkdir -p "${WORK}/main" "${WORK}/cb" "${FIXTURES}"
+for file in "${SM83_JSON}"/*.json; do
+  name=$(basename "$file")
+  case "$name" in
+    cb*) table=cb ;;
+    *) table=main ;;
+  esac
+  jq -c ".[:${CASES}]" "$file" > "${WORK}/${table}/${name}"
+done
+
+for table in main cb; do
+  rm -f "${FIXTURES}/${table}.zip"
+  (cd "${WORK}/${table}" && zip -q -9 "${FIXTURES}/${table}.zip" *.json)
+done
+rm -rf "${WORK}"
+
+echo "Fixtures written to ${FIXTURES}"
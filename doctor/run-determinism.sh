@@ -0,0 +1,16 @@
+#!/usr/bin/env bash
+source settings.inc
+
+FRAMES=600
+
+cargo run --release --bin determinism-doctor -- --frames ${FRAMES} \
+  ${BLARGG_ROM}/cpu_instrs/individual/*.gb \
+  ${DEMOS}/*.gb
+
+if [ $? -ne 0 ]; then
+  echo "FAILED"
+  exit 1
+fi
+
+echo "SUCCESS!!"
+exit 0
@@ -9,7 +9,7 @@ for i in $( seq 1 11 ); do
   file_gb="${BLARGG_ROM}/cpu_instrs/individual/${index}-*.gb"
   file_log="${LOGS}/doctor_${index}.log"
 
-  cargo run --release --bin gameboy-doctor -- ${file_gb} > ${file_log}
+  cargo run --release --bin gameboy-doctor -- --stub-ly ${file_gb} > ${file_log}
   ${GAMEBOY_DOCTOR} ${file_log} cpu_instrs ${index}
 
   if [ $? -ne 0 ]; then
@@ -0,0 +1,13 @@
+#!/usr/bin/env bash
+source settings.inc
+
+# Pass --record to write the observed values to regress.txt
+cargo run --release --bin regress-doctor -- "${CURRENT_DIR}/regress.txt" "$@"
+
+if [ $? -ne 0 ]; then
+  echo "FAILED"
+  exit 1
+fi
+
+echo "SUCCESS!!"
+exit 0
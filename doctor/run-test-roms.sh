@@ -0,0 +1,20 @@
+#!/usr/bin/env bash
+source settings.inc
+
+MOONEYE_ROM="${ROMS}/mooneye-test-suite"
+
+cargo run --release --bin test-rom-doctor -- \
+  ${BLARGG_ROM}/cpu_instrs/individual/*.gb \
+  ${BLARGG_ROM}/instr_timing/instr_timing.gb \
+  ${MOONEYE_ROM}/acceptance/boot_regs-dmgABC.gb \
+  ${MOONEYE_ROM}/acceptance/boot_hwio-dmgABCmgb.gb \
+  ${MOONEYE_ROM}/acceptance/boot_div-dmgABCmgb.gb \
+  ${MOONEYE_ROM}/acceptance/timer/*.gb
+
+if [ $? -ne 0 ]; then
+  echo "FAILED"
+  exit 1
+fi
+
+echo "SUCCESS!!"
+exit 0
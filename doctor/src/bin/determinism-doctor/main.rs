@@ -0,0 +1,56 @@
+use clap::Parser;
+use colored::Colorize;
+use gbemu_core::Machine;
+use log::debug;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Run each rom twice in deterministic mode and compare the state hashes
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+#[derive(Debug)]
+struct Args {
+    rom_paths: Vec<PathBuf>,
+    /// Frames run before hashing the state
+    #[arg(short, long, default_value_t = 600)]
+    frames: u32,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    dotenv::dotenv().ok();
+    env_logger::builder().init();
+
+    let args = Args::parse();
+    debug!("{:?}", args);
+
+    let mut failures = vec![];
+    for path in &args.rom_paths {
+        let first = run(path, args.frames)?;
+        let second = run(path, args.frames)?;
+        let success = first == second;
+
+        println!(
+            "{} {} : {:016X} {:016X}",
+            if success { "passed".green() } else { "failed".red() },
+            path.display(),
+            first,
+            second
+        );
+        if !success {
+            failures.push(path.display().to_string());
+        }
+    }
+
+    if !failures.is_empty() {
+        return Err(format!("non deterministic: {}", failures.join(", ")).into());
+    }
+    Ok(())
+}
+
+fn run(path: &Path, frames: u32) -> Result<u64, Box<dyn Error>> {
+    let mut machine = Machine::builder().cartridge_path(path).deterministic(true).build()?;
+    for _ in 0..frames {
+        machine.step_frame()?;
+    }
+    Ok(machine.state_hash())
+}
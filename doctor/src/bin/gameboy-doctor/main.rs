@@ -1,13 +1,28 @@
 use clap::Parser;
-use gbemu_core::{MemorySystem, Timer};
+use gbemu_core::{MemorySystem, Timer, TraceFormat, TraceLogger, TraceVerbosity};
 use log::debug;
 use std::error::Error;
+use std::io;
+use std::io::{BufWriter, Read};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 #[derive(Debug)]
 struct Args {
+    /// Rom file, `-` reads it from stdin (e.g. generated micro roms)
     rom_path: String,
+
+    /// Log line format: gbdoctor, binjgb or custom
+    #[arg(long, default_value_t = TraceFormat::GbDoctor)]
+    format: TraceFormat,
+
+    /// Appended to the registers: registers (nothing), disassembly or annotated (with the operand values)
+    #[arg(long, default_value_t = TraceVerbosity::Registers)]
+    verbosity: TraceVerbosity,
+
+    /// Force LY to $90, as expected by gameboy-doctor which runs without a PPU
+    #[arg(long)]
+    stub_ly: bool,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -21,33 +36,28 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut bus = MemorySystem::default();
     let mut timer = Timer::default();
 
-    bus.load_cartridge(args.rom_path)?;
+    if args.rom_path == "-" {
+        let mut rom = Vec::new();
+        io::stdin().read_to_end(&mut rom)?;
+        bus.load_cartridge_bytes(&rom)?;
+    } else {
+        bus.load_cartridge(args.rom_path)?;
+    }
     cpu.reset();
 
-    bus.write_byte(0xFF44, 0x90); // LY = 90
+    if args.stub_ly {
+        bus.write_byte(0xFF44, 0x90); // LY = 90
+    }
 
+    let mut logger = TraceLogger::new(BufWriter::new(io::stdout().lock()), args.format);
+    logger.set_verbosity(args.verbosity);
     let mut serial_buffer = String::new();
 
     loop {
-        println!(
-            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
-            cpu.a(),
-            cpu.f(),
-            cpu.b(),
-            cpu.c(),
-            cpu.d(),
-            cpu.e(),
-            cpu.h(),
-            cpu.l(),
-            cpu.sp(),
-            cpu.pc(),
-            bus.read_byte(cpu.pc()),
-            bus.read_byte(cpu.pc().wrapping_add(1)),
-            bus.read_byte(cpu.pc().wrapping_add(2)),
-            bus.read_byte(cpu.pc().wrapping_add(3)),
-        );
+        logger.log(&cpu, &bus)?;
 
         let cycles = cpu.step(&mut bus)?;
+        logger.advance(cycles);
         timer.step(&mut bus, cycles);
 
         if simple_serial(&mut bus, &mut serial_buffer) {
@@ -55,6 +65,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
+    logger.flush()?;
     Ok(())
 }
 
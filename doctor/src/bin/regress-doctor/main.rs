@@ -0,0 +1,61 @@
+use clap::Parser;
+use colored::Colorize;
+use gbemu_core::RegressCase;
+use log::debug;
+use std::error::Error;
+use std::path::PathBuf;
+
+/// Run the roms of a regression file headless and compare the frame hash and memory with the expected ones
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+#[derive(Debug)]
+struct Args {
+    /// Cases file, the rom paths are relative to its directory
+    #[arg(default_value = "regress.txt")]
+    path: PathBuf,
+    /// Write the observed values to the file instead of comparing them
+    #[arg(long)]
+    record: bool,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    dotenv::dotenv().ok();
+    env_logger::builder().init();
+
+    let args = Args::parse();
+    debug!("{:?}", args);
+
+    let directory = args.path.parent().map(PathBuf::from).unwrap_or_default();
+    let content = std::fs::read_to_string(&args.path)?;
+    let mut lines = vec![];
+    let mut failures = vec![];
+    for line in content.lines() {
+        if line.trim().is_empty() || line.trim().starts_with('#') {
+            lines.push(line.to_string());
+            continue;
+        }
+        let case: RegressCase = line.parse()?;
+        let result = case.run(&directory)?;
+
+        if args.record {
+            println!("{} {result}", "recorded".green());
+            lines.push(result.to_string());
+            continue;
+        }
+        let success = result == case;
+        println!("{} {case}", if success { "passed".green() } else { "failed".red() });
+        if !success {
+            println!("    got {result}");
+            failures.push(case.rom.clone());
+        }
+        lines.push(line.to_string());
+    }
+
+    if args.record {
+        std::fs::write(&args.path, lines.join("\n") + "\n")?;
+    }
+    if !failures.is_empty() {
+        return Err(format!("regressions: {}", failures.join(", ")).into());
+    }
+    Ok(())
+}
@@ -1,6 +1,6 @@
 use clap::Parser;
 use colored::Colorize;
-use gbemu_core::{BusIO, Cpu, InterruptBus, TestBus};
+use gbemu_core::{BusIO, Cpu, CpuState, InterruptBus, TestBus};
 use log::{debug, error, info};
 use serde::Deserialize;
 use std::error::Error;
@@ -35,17 +35,21 @@ fn main() -> Result<(), Box<dyn Error>> {
         bus.set_interrupt_flag_u8(0x00);
         bus.set_interrupt_flag_u8(0x00);
 
-        cpu.load_state(&test.initial);
-        bus.load_state(&test.initial);
+        cpu.set_state(&test.initial.cpu_state());
+        for ram in test.initial.ram.iter() {
+            bus.write_internal_byte(ram.addr, ram.val);
+        }
 
         cpu.fetch_instruction(&mut bus)?;
         for (pc, sp, msg) in test.cycles.iter() {
             debug!("  @cycle: {:04X} {:04X} {}", pc, sp, msg);
         }
 
-        let mut state = State::default_with_ram(&test.r#final.ram);
-        cpu.write_state(&mut state);
-        bus.write_state(&mut state);
+        let ram = test.r#final.ram.iter().map(|ram| RamState {
+            addr: ram.addr,
+            val: bus.read_byte(ram.addr),
+        });
+        let state = State::new(&cpu.state(), ram.collect());
 
         let success = State::assert_eq(&state, &test.r#final, "Final state not equal to expected");
         all_success &= success;
@@ -72,56 +76,6 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
 }
 
-trait JsonState {
-    fn load_state(&mut self, state: &State);
-
-    fn write_state(&self, state: &mut State);
-}
-
-impl JsonState for Cpu {
-    fn load_state(&mut self, state: &State) {
-        self.set_pc(state.pc);
-        self.set_sp(state.sp);
-        self.set_a(state.a);
-        self.set_b(state.b);
-        self.set_c(state.c);
-        self.set_d(state.d);
-        self.set_e(state.e);
-        self.set_f(state.f);
-        self.set_h(state.h);
-        self.set_l(state.l);
-        self.set_ime(state.ime == 1);
-    }
-
-    fn write_state(&self, state: &mut State) {
-        state.pc = self.pc();
-        state.sp = self.sp();
-        state.a = self.a();
-        state.b = self.b();
-        state.c = self.c();
-        state.d = self.d();
-        state.e = self.e();
-        state.f = self.f();
-        state.h = self.h();
-        state.l = self.l();
-        state.ime = self.ime() as u8;
-    }
-}
-
-impl JsonState for TestBus {
-    fn load_state(&mut self, state: &State) {
-        for ram in state.ram.iter() {
-            self.write_internal_byte(ram.addr, ram.val);
-        }
-    }
-
-    fn write_state(&self, state: &mut State) {
-        for ram in state.ram.iter_mut() {
-            ram.val = self.read_byte(ram.addr);
-        }
-    }
-}
-
 #[derive(Debug, Deserialize)]
 struct JsonTest {
     name: String,
@@ -148,16 +102,37 @@ struct State {
 }
 
 impl State {
-    fn default_with_ram(ram: &[RamState]) -> Self {
+    fn new(cpu: &CpuState, ram: Vec<RamState>) -> Self {
         State {
-            ram: ram
-                .iter()
-                .map(move |x| RamState {
-                    addr: x.addr,
-                    val: 0xFF,
-                })
-                .collect(),
-            ..Default::default()
+            pc: cpu.pc,
+            sp: cpu.sp,
+            a: cpu.a,
+            b: cpu.b,
+            c: cpu.c,
+            d: cpu.d,
+            e: cpu.e,
+            f: cpu.f,
+            h: cpu.h,
+            l: cpu.l,
+            ime: cpu.ime as u8,
+            ram,
+        }
+    }
+
+    fn cpu_state(&self) -> CpuState {
+        CpuState {
+            a: self.a,
+            f: self.f,
+            b: self.b,
+            c: self.c,
+            d: self.d,
+            e: self.e,
+            h: self.h,
+            l: self.l,
+            sp: self.sp,
+            pc: self.pc,
+            ime: self.ime == 1,
+            halted: false,
         }
     }
 
@@ -177,17 +152,8 @@ impl Display for State {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "pc: {:04X}, sp: {:04X}, a: {:02X}, b: {:02X}, c: {:02X}, d: {:02X}, e: {:02X}, f: {:02X}, h: {:02X}, l: {:02X}, ime: {:02X}, ram:[{}]",
-            self.pc,
-            self.sp,
-            self.a,
-            self.b,
-            self.c,
-            self.d,
-            self.e,
-            self.f,
-            self.h,
-            self.l,
+            "{} IME:{:02X} ram:[{}]",
+            self.cpu_state(),
             self.ime,
             self.ram.iter().map(|x| x.to_string()).collect::<Vec<_>>().join(", ")
         )
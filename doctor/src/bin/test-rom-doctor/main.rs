@@ -0,0 +1,43 @@
+use clap::Parser;
+use colored::Colorize;
+use gbemu_core::TestVerdict;
+use log::debug;
+use std::error::Error;
+use std::path::PathBuf;
+
+/// Run mooneye and blargg test roms headless, the verdict comes from the mooneye register signature at
+/// `LD B,B` or from the blargg text on the serial port
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+#[derive(Debug)]
+struct Args {
+    rom_paths: Vec<PathBuf>,
+    /// Frames run before giving up on a rom
+    #[arg(short, long, default_value_t = 3600)]
+    frames: u64,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    dotenv::dotenv().ok();
+    env_logger::builder().init();
+
+    let args = Args::parse();
+    debug!("{:?}", args);
+
+    let mut failures = vec![];
+    for path in &args.rom_paths {
+        let verdict = TestVerdict::run(path, args.frames)?;
+        match &verdict {
+            TestVerdict::Passed => println!("{} {}", "passed".green(), path.display()),
+            verdict => {
+                println!("{} {} : {verdict}", "failed".red(), path.display());
+                failures.push(path.display().to_string());
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        return Err(format!("failed: {}", failures.join(", ")).into());
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use gbemu_core::{Cpu, CpuFlags, Machine, disassemble_range};
+use ratatui::prelude::*;
+
+const DISASSEMBLY_LINES: usize = 6;
+
+/// Command typed in step mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// `s [count]`: execute `count` instructions, 1 by default
+    Step(usize),
+    /// `c`: resume the emulation until the next breakpoint
+    Continue,
+    /// `b <address>`: toggle a breakpoint, the address is hexadecimal
+    Breakpoint(u16),
+    /// `r`: reset the machine, also leaves the stopped state after an error
+    Reset,
+    /// `q`: quit
+    Quit,
+}
+
+impl Command {
+    pub fn parse(input: &str) -> Result<Command, String> {
+        let mut words = input.split_whitespace();
+        let command = match (words.next(), words.next()) {
+            (Some("s" | "step"), None) => Command::Step(1),
+            (Some("s" | "step"), Some(count)) => {
+                Command::Step(count.parse().map_err(|_| format!("invalid count `{count}`"))?)
+            }
+            (Some("c" | "continue"), None) => Command::Continue,
+            (Some("b" | "break"), Some(address)) => Command::Breakpoint(
+                u16::from_str_radix(address.trim_start_matches('$'), 16)
+                    .map_err(|_| format!("invalid address `{address}`"))?,
+            ),
+            (Some("r" | "reset"), None) => Command::Reset,
+            (Some("q" | "quit"), None) => Command::Quit,
+            _ => return Err(format!("unknown command `{}`", input.trim())),
+        };
+        match words.next() {
+            Some(extra) => Err(format!("unexpected `{extra}`")),
+            None => Ok(command),
+        }
+    }
+}
+
+/// Register values, in display order
+type Registers = [(&'static str, u16, usize); 14];
+
+fn registers(cpu: &Cpu) -> Registers {
+    [
+        ("A", cpu.a() as u16, 2),
+        ("F", cpu.f() as u16, 2),
+        ("B", cpu.b() as u16, 2),
+        ("C", cpu.c() as u16, 2),
+        ("D", cpu.d() as u16, 2),
+        ("E", cpu.e() as u16, 2),
+        ("H", cpu.h() as u16, 2),
+        ("L", cpu.l() as u16, 2),
+        ("SP", cpu.sp(), 4),
+        ("PC", cpu.pc(), 4),
+        ("Z", cpu.flag(CpuFlags::Z) as u16, 1),
+        ("N", cpu.flag(CpuFlags::N) as u16, 1),
+        ("H", cpu.flag(CpuFlags::H) as u16, 1),
+        ("C", cpu.flag(CpuFlags::C) as u16, 1),
+    ]
+}
+
+/// gdb-like command line of the step mode, highlighting the registers changed by the last command
+pub struct Debugger {
+    input: String,
+    last_command: Command,
+    previous: Registers,
+    message: String,
+}
+
+impl Debugger {
+    pub fn new(cpu: &Cpu, message: String) -> Self {
+        Self {
+            input: String::new(),
+            last_command: Command::Step(1),
+            previous: registers(cpu),
+            message,
+        }
+    }
+
+    /// Edit the command line, return the command submitted with Enter.
+    /// An empty line repeats the last command.
+    pub fn handle_key(&mut self, key_event: KeyEvent) -> Option<Command> {
+        if key_event.is_release() {
+            return None;
+        }
+        match key_event.code {
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Enter => {
+                let input = std::mem::take(&mut self.input);
+                if input.trim().is_empty() {
+                    return Some(self.last_command);
+                }
+                match Command::parse(&input) {
+                    Ok(command) => {
+                        self.last_command = command;
+                        return Some(command);
+                    }
+                    Err(e) => self.message = e,
+                }
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Remember the registers before running a command
+    pub fn snapshot(&mut self, cpu: &Cpu) {
+        self.previous = registers(cpu);
+    }
+
+    pub fn set_message(&mut self, message: String) {
+        self.message = message;
+    }
+
+    pub fn lines(&self, machine: &Machine) -> Vec<Line<'static>> {
+        let label = Style::new().fg(Color::Magenta);
+        let changed = Style::new().fg(Color::Black).bg(Color::Yellow);
+
+        let current = registers(machine.cpu());
+        let register_spans = |range: std::ops::Range<usize>| {
+            let spans = current[range.clone()].iter().zip(&self.previous[range]);
+            Line::from_iter(spans.flat_map(|(&(name, value, digits), &(_, previous, _))| {
+                let style = if value != previous { changed } else { Style::new() };
+                [
+                    Span::styled(format!("{name} "), label),
+                    Span::styled(format!("{value:0digits$X}"), style),
+                    Span::raw("  "),
+                ]
+            }))
+        };
+
+        let mut lines = vec![
+            register_spans(0..4),
+            register_spans(4..8),
+            register_spans(8..10),
+            register_spans(10..14),
+            Line::raw(""),
+        ];
+
+        let read = |address| machine.peek(address);
+        for (index, line) in disassemble_range(machine.cpu().pc(), DISASSEMBLY_LINES, read)
+            .into_iter()
+            .enumerate()
+        {
+            let marker = if index == 0 { "> " } else { "  " };
+            let breakpoint = if machine.breakpoint_manager().has_breakpoint(line.address) {
+                "*"
+            } else {
+                " "
+            };
+            lines.push(Line::from(vec![
+                Span::styled(breakpoint, Style::new().fg(Color::Red)),
+                Span::raw(marker),
+                Span::styled(format!("{:04X} ", line.address), label),
+                Span::raw(line.text),
+            ]));
+        }
+
+        lines.push(Line::raw(""));
+        lines.push(Line::styled(self.message.clone(), Style::new().fg(Color::Cyan)));
+        lines.push(Line::from(vec![
+            Span::styled("> ", label),
+            Span::raw(self.input.clone()),
+        ]));
+        lines
+    }
+}
@@ -1,17 +1,22 @@
+mod debugger;
 mod screen_view;
 
+use crate::debugger::{Command, Debugger};
 use crate::screen_view::{SCREEN_HEIGHT, SCREEN_WIDTH, ScreenView};
 use clap::Parser;
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyboardEnhancementFlags, PushKeyboardEnhancementFlags};
 use crossterm::terminal::supports_keyboard_enhancement;
 use crossterm::{event, execute};
-use gbemu_core::{JoypadButton, Machine};
+use gbemu_core::{Accuracy, AudioChannels, EmulationStatus, JoypadButton, Machine};
 use log::{debug, error};
 use ratatui::DefaultTerminal;
 use ratatui::prelude::*;
 use ratatui::symbols::Marker;
 use ratatui::widgets::canvas::Canvas;
+use ratatui::widgets::{Block, Paragraph};
 use std::io;
+use std::io::Read;
+use std::path::PathBuf;
 use std::thread::sleep;
 use std::time::{Duration, Instant};
 
@@ -19,9 +24,43 @@ use std::time::{Duration, Instant};
 #[command(version, about, long_about = None)]
 #[derive(Debug)]
 struct Args {
+    /// Rom file, `-` reads it from stdin
     rom_path: Option<String>,
+    /// Read the rom from stdin, same as passing `-` as rom path
+    #[arg(long, default_value = "false")]
+    stdin: bool,
+    /// IPS or BPS patch applied to the rom, repeat it to apply several patches in order
+    #[arg(long = "patch")]
+    patches: Vec<PathBuf>,
     #[arg(short = 'b', long, default_value = "false")]
     use_boot_rom: bool,
+    /// Start at $0100 with the exact state left by the boot rom, without running it
+    #[arg(long, default_value = "false")]
+    fast_boot: bool,
+    /// Emulate the DMG OAM corruption bug, relied on by a few demos and test roms
+    #[arg(long, default_value = "false")]
+    oam_bug: bool,
+    /// Answer the Super Game Boy detection of the games that hang waiting for it
+    #[arg(long, default_value = "false")]
+    sgb_stub: bool,
+    /// Accuracy preset: fast, balanced or accurate
+    #[arg(long, default_value = "balanced")]
+    accuracy: Accuracy,
+    /// Directory of battery saves
+    #[arg(long, default_value = "saves")]
+    save_dir: PathBuf,
+    /// Directory of the crash reports written on emulation errors
+    #[arg(long, default_value = "crashes")]
+    crash_dir: PathBuf,
+    /// Record the audio to this wav file, each channel is also written to `<name>_ch<n>.wav`
+    #[arg(long)]
+    capture_audio: Option<PathBuf>,
+    /// Length of the audio capture in seconds of emulated time
+    #[arg(long, default_value = "30")]
+    capture_seconds: u64,
+    /// Log the sound register writes to this VGM file
+    #[arg(long)]
+    sound_log: Option<PathBuf>,
 }
 
 fn main() -> io::Result<()> {
@@ -35,44 +74,73 @@ fn main() -> io::Result<()> {
     let args = Args::parse();
     debug!("{:?}", args);
 
-    let mut result = Ok(());
-    let mut app = App::default();
+    let mut builder = Machine::builder()
+        .battery_save_dir(&args.save_dir)
+        .accuracy(args.accuracy)
+        .fast_boot(args.fast_boot)
+        .sgb_stub(args.sgb_stub)
+        // For the crash reports
+        .instruction_trace(true);
+    if args.oam_bug {
+        builder = builder.oam_bug(true);
+    }
     if args.use_boot_rom {
-        result = app.machine.use_boot_rom();
+        builder = builder.boot_rom_path("roms/dmg.bin");
+    }
+    if args.stdin || args.rom_path.as_deref() == Some("-") {
+        let mut rom = Vec::new();
+        io::stdin().read_to_end(&mut rom)?;
+        builder = builder.cartridge_bytes(rom);
+    } else if let Some(rom_path) = &args.rom_path {
+        builder = builder.cartridge_path(rom_path);
     }
-    if let Some(rom_path) = &args.rom_path {
-        result = app.load(rom_path.as_str());
+    for patch in &args.patches {
+        builder = builder.patch_path(patch);
     }
 
-    if result.is_ok() {
-        let mut terminal = ratatui::init();
+    let mut app = App {
+        machine: builder.build()?,
+        crash_dir: args.crash_dir,
+        ..App::default()
+    };
+    if let Some(path) = &args.capture_audio {
+        let duration = Duration::from_secs(args.capture_seconds);
+        app.machine.capture_audio(path, AudioChannels::all(), duration)?;
+    }
+    if let Some(path) = &args.sound_log {
+        app.machine.start_sound_log(path)?;
+    }
 
-        let mut stdout = io::stdout();
-        execute!(stdout, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::all()))?;
+    let mut terminal = ratatui::init();
 
-        result = app.run(&mut terminal);
-    }
+    let mut stdout = io::stdout();
+    execute!(stdout, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::all()))?;
+
+    let result = app.run(&mut terminal);
 
     ratatui::restore();
 
+    #[cfg(feature = "profiling")]
+    println!("{}", app.machine.profile_report());
+
     result
 }
 
 #[derive(Default)]
 struct App {
     machine: Machine,
+    crash_dir: PathBuf,
+    /// Show the debug status line (Tab)
+    show_debug: bool,
+    /// Step mode (F8), the emulation is paused and driven by typed commands
+    debugger: Option<Debugger>,
+    /// Draw even if no line of the screen changed (resize, status line toggled)
+    redraw: bool,
     exit: bool,
 }
 
 const GB_FRAME_DURATION: Duration = Duration::from_nanos(16_742_706); // 1/59.7275 s
 impl App {
-    pub fn load(&mut self, path: &str) -> io::Result<()> {
-        self.machine.load_cartridge(path)?;
-        self.machine.reset();
-
-        Ok(())
-    }
-
     pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
         let mut delta = Duration::from_nanos(0);
 
@@ -81,7 +149,12 @@ impl App {
 
             self.handle_events()?;
             self.update(&delta);
-            terminal.draw(|frame| self.draw(frame))?;
+            // Unchanged frames are not drawn, to save bandwidth on remote sessions
+            let changed_lines = self.machine.take_changed_lines();
+            let always = self.show_debug || self.debugger.is_some();
+            if !changed_lines.is_empty() || always || std::mem::take(&mut self.redraw) {
+                terminal.draw(|frame| self.draw(frame))?;
+            }
 
             delta = frame_start.elapsed();
 
@@ -93,21 +166,124 @@ impl App {
     }
 
     fn update(&mut self, _delta: &Duration) {
-        self.machine.step_frame().unwrap_or_else(|e| {
-            error!("{}", e);
-            (0, false)
-        });
+        if !self.machine.is_running() {
+            return;
+        }
+        match self.machine.step_frame() {
+            Ok(result) if result.hit_breakpoint => {
+                let message = format!("Break at ${:04X}", self.machine.cpu().pc());
+                self.debugger = Some(Debugger::new(self.machine.cpu(), message));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                let message = self.crash(e.as_ref());
+                self.debugger = Some(Debugger::new(self.machine.cpu(), message));
+            }
+        }
+    }
+
+    /// Write the crash report, the machine is stopped until a reset
+    fn crash(&mut self, e: &dyn std::error::Error) -> String {
+        let report = self.machine.crash_report(&e.to_string());
+        match report.write(&self.crash_dir) {
+            Ok(path) => error!("{}, crash report written to {}", e, path.display()),
+            Err(write_error) => error!("{}, failed to write crash report: {write_error}", e),
+        }
+        format!("{e}, reset with r")
+    }
+
+    fn run_command(&mut self, command: Command) {
+        let Some(mut debugger) = self.debugger.take() else {
+            return;
+        };
+        debugger.snapshot(self.machine.cpu());
+
+        let stopped = matches!(self.machine.status(), EmulationStatus::Stopped(_));
+        let message = match command {
+            Command::Step(_) | Command::Continue if stopped => "Emulation stopped, reset with r".to_string(),
+            Command::Step(count) => match self.step(count) {
+                Ok(message) => message,
+                Err(e) => self.crash(e.as_ref()),
+            },
+            Command::Continue => return self.machine.resume(),
+            Command::Reset => {
+                self.machine.reset();
+                "Reset".to_string()
+            }
+            Command::Breakpoint(address) => {
+                let breakpoints = self.machine.breakpoint_manager_mut();
+                if breakpoints.has_breakpoint(address) {
+                    breakpoints.remove_breakpoint(address);
+                    format!("Breakpoint ${address:04X} removed")
+                } else {
+                    breakpoints.add_breakpoint(address);
+                    format!("Breakpoint ${address:04X} set")
+                }
+            }
+            Command::Quit => return self.exit(),
+        };
+        debugger.set_message(message);
+        self.debugger = Some(debugger);
+    }
+
+    /// Execute `count` instructions, stopping early on a breakpoint
+    fn step(&mut self, count: usize) -> Result<String, Box<dyn std::error::Error>> {
+        for executed in 1..=count {
+            self.machine.step()?;
+            let pc = self.machine.cpu().pc();
+            if executed < count && self.machine.breakpoint_manager().has_breakpoint(pc) {
+                return Ok(format!("Break at ${pc:04X} after {executed} steps"));
+            }
+        }
+        Ok(format!("Stepped {count}"))
     }
 
     fn draw(&self, frame: &mut Frame) {
+        let mut area = frame.area();
+        if let Some(debugger) = &self.debugger {
+            let [screen, panel] = Layout::horizontal([Constraint::Fill(1), Constraint::Length(32)]).areas(area);
+            let block = Block::bordered().title(" STEP (s [n], c, b <addr>, r, q) ");
+            frame.render_widget(Paragraph::new(debugger.lines(&self.machine)).block(block), panel);
+            area = screen;
+        }
+        if self.show_debug {
+            let [screen, status] = Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(area);
+            frame.render_widget(Paragraph::new(self.debug_status()), status);
+            area = screen;
+        }
+
         let screen_block = Canvas::default()
             .x_bounds([0., SCREEN_WIDTH as f64])
             .y_bounds([0., SCREEN_HEIGHT as f64])
             .marker(Marker::HalfBlock)
             .paint(|ctx| {
-                ctx.draw(&ScreenView::from(self.machine.frame()));
+                ctx.draw(&ScreenView::new(self.machine.frame_rgb()));
             });
-        frame.render_widget(screen_block, frame.area());
+        frame.render_widget(screen_block, area);
+    }
+
+    fn debug_status(&self) -> Line<'_> {
+        let mapper = self.machine.mapper_state();
+        let ram = if mapper.ram_bank_count == 0 {
+            "-".to_string()
+        } else {
+            let enabled = if mapper.ram_enabled { "on" } else { "off" };
+            format!("${:02X}/{} ({enabled})", mapper.ram_bank, mapper.ram_bank_count)
+        };
+
+        Line::from(vec![
+            Span::styled("PC ", Style::new().fg(Color::Magenta)),
+            Span::raw(format!("${:04X}  ", self.machine.cpu().pc())),
+            Span::styled("ROM ", Style::new().fg(Color::Magenta)),
+            Span::raw(format!(
+                "${:02X}:${:02X}/{}  ",
+                mapper.rom_bank_low, mapper.rom_bank_high, mapper.rom_bank_count
+            )),
+            Span::styled("RAM ", Style::new().fg(Color::Magenta)),
+            Span::raw(format!("{ram}  ")),
+            Span::styled("MODE ", Style::new().fg(Color::Magenta)),
+            Span::raw(mapper.mode.to_string()),
+        ])
     }
 
     fn handle_events(&mut self) -> io::Result<()> {
@@ -115,17 +291,41 @@ impl App {
             return Ok(());
         }
 
-        if let Event::Key(key_event) = event::read()? {
-            self.handle_key_event(key_event);
+        match event::read()? {
+            Event::Key(key_event) => self.handle_key_event(key_event),
+            Event::Resize(..) => self.redraw = true,
+            _ => {}
         }
 
         Ok(())
     }
 
     fn handle_key_event(&mut self, key_event: KeyEvent) {
+        if let Some(debugger) = self.debugger.as_mut() {
+            match key_event.code {
+                KeyCode::Esc => self.exit(),
+                KeyCode::F(8) if !key_event.is_release() => self.run_command(Command::Continue),
+                _ => {
+                    if let Some(command) = debugger.handle_key(key_event) {
+                        self.run_command(command);
+                    }
+                }
+            }
+            self.redraw = true;
+            return;
+        }
+
         match (key_event.code, !key_event.is_release()) {
             (KeyCode::Esc, _) => self.exit(),
+            (KeyCode::F(8), true) => {
+                self.machine.pause();
+                self.debugger = Some(Debugger::new(self.machine.cpu(), "Paused".to_string()));
+            }
             (KeyCode::Char('*'), _) => self.machine.reset(),
+            (KeyCode::Tab, true) => {
+                self.show_debug = !self.show_debug;
+                self.redraw = true;
+            }
             (KeyCode::Up, pressed) => self.machine.button_changed(JoypadButton::Up, pressed),
             (KeyCode::Down, pressed) => self.machine.button_changed(JoypadButton::Down, pressed),
             (KeyCode::Left, pressed) => self.machine.button_changed(JoypadButton::Left, pressed),
@@ -1,22 +1,21 @@
+pub use gbemu_core::video::{SCREEN_HEIGHT, SCREEN_WIDTH};
 use ratatui::style::Color;
 use ratatui::widgets::canvas::{Painter, Shape};
 
-pub const SCREEN_WIDTH: usize = 160;
-pub const SCREEN_HEIGHT: usize = 144;
-
-pub struct ScreenView<'a> {
-    image: &'a [u8],
+pub struct ScreenView {
+    /// Colors of the frame, see `Machine::frame_rgb`
+    image: Vec<[u8; 3]>,
 }
 
-impl<'a> From<&'a [u8]> for ScreenView<'a> {
-    fn from(image: &'a [u8]) -> Self {
+impl ScreenView {
+    pub fn new(image: Vec<[u8; 3]>) -> Self {
         Self { image }
     }
 }
 
-impl Shape for ScreenView<'_> {
+impl Shape for ScreenView {
     fn draw(&self, painter: &mut Painter) {
-        self.image.iter().enumerate().for_each(|(index, &v)| {
+        self.image.iter().enumerate().for_each(|(index, &[r, g, b])| {
             let x = index % SCREEN_WIDTH;
             let y = index / SCREEN_WIDTH;
 
@@ -24,14 +23,7 @@ impl Shape for ScreenView<'_> {
                 return;
             };
 
-            let color = match &v {
-                0 => Color::Rgb(155, 188, 15),
-                1 => Color::Rgb(139, 172, 15),
-                2 => Color::Rgb(48, 98, 48),
-                _ => Color::Rgb(15, 56, 15), // background
-            };
-
-            painter.paint(x, y, color);
+            painter.paint(x, y, Color::Rgb(r, g, b));
         });
     }
 }